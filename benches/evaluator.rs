@@ -0,0 +1,42 @@
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use interpreter::environment::Environment;
+use interpreter::evaluator::eval;
+use interpreter::lexer::Lexer;
+use interpreter::parser::Parser;
+
+mod generator;
+
+fn bench_evaluator(c: &mut Criterion) {
+    let mut group = c.benchmark_group("evaluator");
+    let cases = [
+        ("fibonacci_25", generator::fibonacci(25)),
+        ("arithmetic_loop", generator::arithmetic_loop(1000)),
+        ("hash_literal_20k", generator::hash_literal(20_000)),
+    ];
+    for (name, input) in cases.iter() {
+        let l = Lexer::new(input);
+        let mut p = Parser::new(l);
+        let program = p.parse();
+        group.bench_function(*name, |b| {
+            b.iter(|| {
+                let mut env = Environment::new();
+                eval(black_box(&program), &mut env)
+            })
+        });
+    }
+    // a program evaluated again, like a REPL line or a script run on each
+    // request, reuses the literals its first run made
+    let program = Parser::new(Lexer::new(&generator::literal_array(10_000))).parse();
+    group.bench_function("literal_array_10k", |b| {
+        b.iter(|| {
+            for _ in 0..100 {
+                let mut env = Environment::new();
+                black_box(eval(black_box(&program), &mut env));
+            }
+        })
+    });
+    group.finish();
+}
+
+criterion_group!(benches, bench_evaluator);
+criterion_main!(benches);
@@ -0,0 +1,156 @@
+//! Deterministic generators for the synthetic programs used by the benchmarks.
+//!
+//! Everything here is derived from a fixed seed so the same input is produced
+//! on every machine and run, which keeps the numbers comparable.
+
+#![allow(dead_code)]
+
+const SEED: u64 = 0x2545_f491_4f6c_dd1d;
+
+/// A tiny xorshift generator. We only need "random looking" programs, not
+/// good randomness, and pulling in a crate for this would be overkill.
+struct Rng(u64);
+
+impl Rng {
+    fn new() -> Self {
+        Rng(SEED)
+    }
+
+    fn next(&mut self) -> u64 {
+        let mut x = self.0;
+        x ^= x << 13;
+        x ^= x >> 7;
+        x ^= x << 17;
+        self.0 = x;
+        x
+    }
+
+    fn below(&mut self, n: u64) -> u64 {
+        self.next() % n
+    }
+}
+
+/// Identifiers may only contain letters and underscores, so numbers are
+/// spelled in base 26: 0 => `a`, 25 => `z`, 26 => `ba`, ...
+fn name(prefix: &str, mut n: usize) -> String {
+    let mut digits = Vec::new();
+    loop {
+        digits.push((b'a' + (n % 26) as u8) as char);
+        n /= 26;
+        if n == 0 {
+            break;
+        }
+    }
+    let mut res = prefix.to_owned();
+    res.extend(digits.iter().rev());
+    res
+}
+
+const OPERATORS: [&str; 4] = ["+", "-", "*", "/"];
+
+/// A single expression nested `depth` levels deep, e.g. `(1 + (2 * (3 - 4)))`.
+pub fn deep_expression(depth: usize) -> String {
+    let mut rng = Rng::new();
+    let mut res = String::new();
+    for i in 0..depth {
+        res.push('(');
+        res.push_str(&(i + 1).to_string());
+        res.push(' ');
+        res.push_str(OPERATORS[rng.below(4) as usize]);
+        res.push(' ');
+    }
+    res.push_str(&(depth + 1).to_string());
+    for _ in 0..depth {
+        res.push(')');
+    }
+    res.push(';');
+    res
+}
+
+/// `count` let bindings, each referring to a few of the previously bound names.
+pub fn many_identifiers(count: usize) -> String {
+    let mut rng = Rng::new();
+    let mut res = format!("let {} = 0;\n", name("ident_", 0));
+    for i in 1..count {
+        let prev = rng.below(i as u64) as usize;
+        res.push_str(&format!(
+            "let {} = {}",
+            name("ident_", i),
+            name("ident_", prev)
+        ));
+        for _ in 0..3 {
+            let prev = rng.below(i as u64) as usize;
+            res.push_str(&format!(" + {}", name("ident_", prev)));
+        }
+        res.push_str(";\n");
+    }
+    res
+}
+
+/// `count` small, independent statements of every kind the parser knows about.
+pub fn many_statements(count: usize) -> String {
+    let mut rng = Rng::new();
+    let mut res = String::new();
+    for i in 0..count {
+        let stmt = match rng.below(6) {
+            0 => format!("let {} = {};\n", name("x_", i), rng.below(1000)),
+            1 => format!("{} * {} + {};\n", rng.below(100), rng.below(100), i),
+            2 => format!("\"string number {}\";\n", i),
+            3 => format!("[{}, {}, {}];\n", i, rng.below(10), rng.below(10)),
+            4 => format!("{{\"k{}\": {}}};\n", i, rng.below(10)),
+            _ => format!(
+                "if ({} < {}) {{ true }} else {{ false }};\n",
+                i,
+                rng.below(100)
+            ),
+        };
+        res.push_str(&stmt);
+    }
+    res
+}
+
+/// `count` one-statement programs, the statements of `many_statements`.
+pub fn snippets(count: usize) -> Vec<String> {
+    many_statements(count).lines().map(str::to_owned).collect()
+}
+
+/// A recursive fibonacci evaluating `fib(n)`.
+///
+/// Functions capture their environment when they are created, so the
+/// function is passed itself to be able to recurse.
+pub fn fibonacci(n: usize) -> String {
+    format!(
+        "let fib = fn(f, n) {{
+    if (n < 2) {{ n }} else {{ f(f, n - 1) + f(f, n - 2) }}
+}};
+fib(fib, {});",
+        n
+    )
+}
+
+/// A tight arithmetic loop running `iterations` times.
+///
+/// Monkey has no loop construct, so the loop is a self-recursive function.
+pub fn arithmetic_loop(iterations: usize) -> String {
+    format!(
+        "let loop = fn(f, n, acc) {{
+    if (n == 0) {{ acc }} else {{ f(f, n - 1, acc + n * 2 - n / 2) }}
+}};
+loop(loop, {}, 0);",
+        iterations
+    )
+}
+
+/// The length of a literal array of `len` integers, which only the first
+/// evaluation of the program makes.
+pub fn literal_array(len: usize) -> String {
+    let elements: Vec<String> = (0..len).map(|i| i.to_string()).collect();
+    format!("len([{}])", elements.join(", "))
+}
+
+/// The length of a hash literal of `len` pairs. The keys are worked out
+/// from a binding, so every evaluation makes the hash again.
+pub fn hash_literal(len: usize) -> String {
+    let pairs: Vec<String> = (0..len).map(|i| format!("k + {}: {}", i, i)).collect();
+    format!("let k = 0; len({{{}}})", pairs.join(", "))
+}
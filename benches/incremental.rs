@@ -0,0 +1,41 @@
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use interpreter::incremental::Document;
+use interpreter::lexer::Lexer;
+use interpreter::parser::Parser;
+
+mod generator;
+
+/* the start of a line in the middle of `source` */
+fn middle(source: &str) -> usize {
+    source[source.len() / 2..].find('\n').unwrap() + source.len() / 2 + 1
+}
+
+/// Types a character into the middle of a large file and deletes it
+/// again, once through a `Document` and once parsing the whole file after
+/// each edit.
+fn bench_single_char_edit(c: &mut Criterion) {
+    let mut group = c.benchmark_group("single_char_edit");
+    let source = generator::many_statements(5_000);
+    let at = middle(&source);
+    group.bench_function("incremental", |b| {
+        let mut doc = Document::new(&source);
+        b.iter(|| {
+            doc.apply_edit(black_box(at..at), "1");
+            doc.apply_edit(black_box(at..at + 1), "");
+            doc.program().statements.len()
+        })
+    });
+    group.bench_function("full_reparse", |b| {
+        let mut source = source.clone();
+        b.iter(|| {
+            source.insert(black_box(at), '1');
+            black_box(Parser::new(Lexer::new(&source)).parse());
+            source.remove(black_box(at));
+            Parser::new(Lexer::new(&source)).parse().statements.len()
+        })
+    });
+    group.finish();
+}
+
+criterion_group!(benches, bench_single_char_edit);
+criterion_main!(benches);
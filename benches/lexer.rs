@@ -0,0 +1,30 @@
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use interpreter::lexer::Lexer;
+use interpreter::token::Token;
+
+mod generator;
+
+fn lex(input: &str) -> usize {
+    let mut l = Lexer::new(input);
+    let mut count = 0;
+    while l.next_token() != Token::Eof {
+        count += 1;
+    }
+    count
+}
+
+fn bench_lexer(c: &mut Criterion) {
+    let mut group = c.benchmark_group("lexer");
+    let cases = [
+        ("deep_expression", generator::deep_expression(200)),
+        ("many_identifiers", generator::many_identifiers(500)),
+        ("many_statements", generator::many_statements(500)),
+    ];
+    for (name, input) in cases.iter() {
+        group.bench_function(*name, |b| b.iter(|| lex(black_box(input))));
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_lexer);
+criterion_main!(benches);
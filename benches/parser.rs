@@ -0,0 +1,81 @@
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use interpreter::lexer::Lexer;
+use interpreter::parser::Parser;
+
+mod generator;
+
+fn bench_parser(c: &mut Criterion) {
+    let mut group = c.benchmark_group("parser");
+    let cases = [
+        ("deep_expression", generator::deep_expression(100)),
+        ("many_identifiers", generator::many_identifiers(500)),
+        ("many_statements", generator::many_statements(500)),
+    ];
+    for (name, input) in cases.iter() {
+        group.bench_function(*name, |b| {
+            b.iter(|| {
+                let l = Lexer::new(black_box(input));
+                let mut p = Parser::new(l);
+                p.parse()
+            })
+        });
+    }
+    group.finish();
+}
+
+/// Parses many tiny programs, once with a parser per snippet and once with
+/// a single parser that is reset between snippets.
+fn bench_parser_reuse(c: &mut Criterion) {
+    let mut group = c.benchmark_group("parser_reuse");
+    let snippets = generator::snippets(10_000);
+    group.bench_function("fresh", |b| {
+        b.iter(|| {
+            for snippet in snippets.iter() {
+                let l = Lexer::new(black_box(snippet));
+                let mut p = Parser::new(l);
+                black_box(p.parse());
+            }
+        })
+    });
+    group.bench_function("reset", |b| {
+        let mut p = Parser::new(Lexer::new(""));
+        b.iter(|| {
+            for snippet in snippets.iter() {
+                p.reset(black_box(snippet));
+                black_box(p.parse());
+            }
+        })
+    });
+    group.finish();
+}
+
+/// Same inputs as `bench_parser`, allocating the tree in a bump arena. The
+/// arena is reset instead of recreated so its chunks are reused like a
+/// long running embedding would.
+#[cfg(feature = "bump")]
+fn bench_parser_bump(c: &mut Criterion) {
+    let mut group = c.benchmark_group("parser_bump");
+    let cases = [
+        ("deep_expression", generator::deep_expression(100)),
+        ("many_identifiers", generator::many_identifiers(500)),
+        ("many_statements", generator::many_statements(500)),
+    ];
+    let mut bump = bumpalo::Bump::new();
+    for (name, input) in cases.iter() {
+        group.bench_function(*name, |b| {
+            b.iter(|| {
+                bump.reset();
+                let l = Lexer::new(black_box(input));
+                let mut p = Parser::new(l);
+                p.parse_in(&bump).statements.len()
+            })
+        });
+    }
+    group.finish();
+}
+
+#[cfg(feature = "bump")]
+criterion_group!(benches, bench_parser, bench_parser_reuse, bench_parser_bump);
+#[cfg(not(feature = "bump"))]
+criterion_group!(benches, bench_parser, bench_parser_reuse);
+criterion_main!(benches);
@@ -0,0 +1,30 @@
+use criterion::{criterion_group, criterion_main, Criterion};
+use interpreter::project::{parse_file, parse_files};
+
+mod generator;
+
+/// Parses 8 files serially and with `parse_files` to show how well the
+/// thread pool scales on the cores available.
+fn bench_parse_files(c: &mut Criterion) {
+    let dir = std::env::temp_dir().join(format!("monkey-bench-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let paths: Vec<_> = (0..8)
+        .map(|i| {
+            let path = dir.join(format!("file{}.mk", i));
+            std::fs::write(&path, generator::many_statements(5_000)).unwrap();
+            path
+        })
+        .collect();
+
+    let mut group = c.benchmark_group("parse_files");
+    group.bench_function("serial", |b| {
+        b.iter(|| paths.iter().map(|p| parse_file(p)).collect::<Vec<_>>())
+    });
+    group.bench_function("parallel", |b| b.iter(|| parse_files(&paths)));
+    group.finish();
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+criterion_group!(benches, bench_parse_files);
+criterion_main!(benches);
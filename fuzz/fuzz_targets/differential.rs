@@ -0,0 +1,15 @@
+#![no_main]
+
+use interpreter::builtins::SeededRng;
+use interpreter::differential::{check, generate};
+use libfuzzer_sys::fuzz_target;
+
+// the input only picks which program gets generated, so every one it
+// tries is inside what both engines run
+fuzz_target!(|input: (u64, u8)| {
+    let (seed, size) = input;
+    let program = generate(&mut SeededRng::new(seed), 1 + size as usize % 8);
+    if let Err(divergence) = check(&program) {
+        panic!("{}", divergence);
+    }
+});
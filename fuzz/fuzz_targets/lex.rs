@@ -0,0 +1,16 @@
+#![no_main]
+
+use interpreter::lexer::Lexer;
+use interpreter::token::Token;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|src: &str| {
+    let mut l = Lexer::new(src);
+    // every token consumes at least one char, so Eof must show up by then
+    for _ in 0..=src.chars().count() {
+        if l.next_token() == Token::Eof {
+            return;
+        }
+    }
+    panic!("lexer did not reach Eof");
+});
@@ -0,0 +1,19 @@
+#![no_main]
+
+use interpreter::lexer::Lexer;
+use interpreter::parser::{Parser, MAX_NESTING_DEPTH};
+use interpreter::token::Token;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|src: &str| {
+    let mut l = Lexer::new(src);
+    let mut tokens = 1;
+    while l.next_token() != Token::Eof {
+        tokens += 1;
+    }
+    // every token is read once, and the Eof again about once for each
+    // level of nesting that ends there, so a parser that reads more is
+    // stuck and panics
+    let mut p = Parser::new(Lexer::new(src)).with_max_reads(tokens + 2 * MAX_NESTING_DEPTH);
+    let _ = p.parse();
+});
@@ -0,0 +1,6 @@
+#![no_main]
+
+use interpreter::parser::parse_dont_panic;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| parse_dont_panic(data));
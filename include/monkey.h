@@ -0,0 +1,51 @@
+/*
+ * C interface to the monkey interpreter, built with
+ * `cargo build --release --features capi`. Keep in sync with src/ffi.rs.
+ *
+ * Every string returned by these functions belongs to the caller and must
+ * be released with monkey_string_free, never with free().
+ */
+#ifndef MONKEY_H
+#define MONKEY_H
+
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+#define MONKEY_OK 0
+#define MONKEY_INVALID_ARGUMENT 1
+#define MONKEY_INVALID_UTF8 2
+#define MONKEY_PARSE_ERROR 3
+#define MONKEY_RUNTIME_ERROR 4
+#define MONKEY_PANIC 5
+
+/* an interpreter, bindings made by one monkey_run are seen by the next */
+typedef struct Monkey Monkey;
+
+Monkey *monkey_new(void);
+
+/* null is ignored */
+void monkey_free(Monkey *handle);
+
+/*
+ * Runs `len` bytes of UTF-8 source and returns the inspected value of the
+ * program, an empty string if it has none. Returns null on failure, see
+ * monkey_last_errors for why. `out_err`, unless null, is set to MONKEY_OK
+ * or to one of the error codes above.
+ */
+char *monkey_run(Monkey *handle, const uint8_t *source, size_t len, int *out_err);
+
+/* the errors of the last monkey_run, one per line, empty if it succeeded */
+char *monkey_last_errors(const Monkey *handle);
+
+/* null is ignored */
+void monkey_string_free(char *s);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif
@@ -0,0 +1,1469 @@
+//! Static checks over a parsed program that don't stop it from running.
+
+use std::collections::{HashMap, HashSet};
+use std::ops::Range;
+use std::rc::Rc;
+
+use crate::ast::{
+    BlockStatement, Expression, ForExpression, FunctionLiteral, Identifier, IfExpression,
+    InfixExpression, InfixOperator, Node, PrefixOperator, Program, Statement,
+};
+use crate::parser::SourceMap;
+
+#[non_exhaustive]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DiagnosticKind {
+    /// A `let` binding that nothing reads.
+    UnusedBinding,
+    /// A function parameter its body never reads.
+    UnusedParameter,
+    /// A binding with the name of one in an enclosing function or the
+    /// top level, which it hides.
+    Shadowed,
+    /// A name that nothing declares, which fails at runtime.
+    Undefined,
+    /// Statements after one that always returns.
+    Unreachable,
+    /// The branch of an `if` that its literal condition never takes.
+    NeverTaken,
+    /// An operator or condition on a value of the wrong type, which fails
+    /// or misbehaves at runtime.
+    TypeError,
+    /// An `if` whose branches give values of different types.
+    BranchTypes,
+    /// An expression statement that does nothing with its value or
+    /// otherwise.
+    UnusedResult,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic {
+    pub kind: DiagnosticKind,
+    /// The name the diagnostic is about, for dead code the code itself as
+    /// `string()` prints it, and for type errors the whole message.
+    pub subject: Rc<str>,
+    /// The path, as in [`Program::replace_expr_at`], of the expression the
+    /// diagnostic is at, for the checks that know it.
+    pub path: Option<Vec<usize>>,
+    /// The bytes of the source the expression at `path` was parsed from,
+    /// once [`locate`](Diagnostic::locate) found them.
+    pub span: Option<Range<usize>>,
+}
+
+impl Diagnostic {
+    fn new(kind: DiagnosticKind, subject: Rc<str>) -> Self {
+        Diagnostic {
+            kind,
+            subject,
+            path: None,
+            span: None,
+        }
+    }
+
+    /// Fills in `span` from the source map of the parser made
+    /// [`with_source_map`](crate::parser::Parser::with_source_map) that
+    /// parsed the program, and the [`token_spans`](crate::lexer::token_spans)
+    /// of its source. Without a path the span stays unknown.
+    pub fn locate(&mut self, source_map: &[SourceMap], token_spans: &[Range<usize>]) {
+        let Some((first, rest)) = self.path.as_deref().and_then(<[usize]>::split_first) else {
+            return;
+        };
+        let range = source_map.get(*first).and_then(|map| map.token_range(rest));
+        self.span = range.and_then(|(start, end)| {
+            Some(token_spans.get(start)?.start..token_spans.get(end.checked_sub(1)?)?.end)
+        });
+    }
+}
+
+/* the diagnostics with the paths of the expressions they were reported at,
+ * found in one walk over `program`. The pointers are only compared, never
+ * followed. */
+fn with_paths(
+    program: &Program,
+    reported: Vec<(Diagnostic, Option<*const Expression>)>,
+) -> Vec<Diagnostic> {
+    let wanted: HashSet<*const Expression> = reported.iter().filter_map(|(_, at)| *at).collect();
+    let mut paths = HashMap::new();
+    // each node is its parent's node and its index below it; a stack
+    // instead of recursion for the long `1 + 1 + ...` chains
+    let mut nodes: Vec<(Option<usize>, usize)> = Vec::new();
+    let mut stack = Vec::new();
+    for (i, stmt) in program.statements.iter().enumerate() {
+        if let Some(exp) = stmt.expression() {
+            nodes.push((None, i));
+            stack.push((nodes.len() - 1, exp));
+        }
+    }
+    while let Some((node, exp)) = stack.pop() {
+        if paths.len() == wanted.len() {
+            break;
+        }
+        let ptr: *const Expression = exp;
+        if wanted.contains(&ptr) {
+            let mut path = Vec::new();
+            let mut at = Some(node);
+            while let Some(node) = at {
+                path.push(nodes[node].1);
+                at = nodes[node].0;
+            }
+            path.reverse();
+            paths.insert(ptr, path);
+        }
+        for (i, child) in exp.children().into_iter().enumerate() {
+            nodes.push((Some(node), i));
+            stack.push((nodes.len() - 1, child));
+        }
+    }
+    reported
+        .into_iter()
+        .map(|(mut diagnostic, at)| {
+            diagnostic.path = at.and_then(|at| paths.get(&at).cloned());
+            diagnostic
+        })
+        .collect()
+}
+
+impl std::fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.kind {
+            DiagnosticKind::UnusedBinding => write!(f, "unused binding '{}'", self.subject),
+            DiagnosticKind::UnusedParameter => write!(f, "unused parameter '{}'", self.subject),
+            DiagnosticKind::Shadowed => {
+                write!(f, "'{}' shadows a binding in an outer scope", self.subject)
+            }
+            DiagnosticKind::Undefined => write!(f, "identifier not found: {}", self.subject),
+            DiagnosticKind::Unreachable => write!(f, "unreachable code: {}", self.subject),
+            DiagnosticKind::NeverTaken => write!(f, "branch never taken: {}", self.subject),
+            DiagnosticKind::TypeError => f.write_str(&self.subject),
+            DiagnosticKind::BranchTypes => {
+                write!(f, "branches of if have different types: {}", self.subject)
+            }
+            DiagnosticKind::UnusedResult => {
+                write!(f, "expression result unused: {}", self.subject)
+            }
+        }
+    }
+}
+
+/// Reports unused `let` bindings and parameters, and bindings that shadow
+/// one in an outer scope. Names starting with `_` are never reported.
+///
+/// Scopes work like in the evaluator: only function and loop bodies open
+/// one, and a function sees the bindings made before it, so a use inside a closure
+/// counts for the binding it captures. A function bound with `let` calling
+/// itself doesn't count as a use.
+pub fn lint(program: &Program) -> Vec<Diagnostic> {
+    let mut linter = Linter {
+        scopes: vec![Vec::new()],
+        diagnostics: Vec::new(),
+    };
+    linter.statements(&program.statements);
+    linter.pop_scope();
+    linter.diagnostics
+}
+
+/// Reports every name that is read without being declared, in the order
+/// they appear. A name is declared by a `let` or `const` before it, by a
+/// parameter of an enclosing function, by the name of the let-bound
+/// function it's in, by an enclosing `for`, or by being one of
+/// `builtins`.
+///
+/// A function body may also use a top-level binding made after the
+/// function, so `let f = fn() { g() }; let g = fn() { 1 };` resolves. The
+/// call usually happens once everything is bound, and this keeps mutually
+/// recursive functions clean. The evaluator allows it when the later
+/// binding is a function, see [`eval`](crate::evaluator::eval). A later
+/// binding of any other value still fails at runtime.
+///
+/// `import "path" as name;` declares `name`. Modules aren't loaded here, so
+/// after an `import "path";` that binds whatever the module does, nothing
+/// is reported.
+///
+/// Each diagnostic has the path of the identifier, or the assignment, with
+/// the name.
+pub fn resolve(program: &Program, builtins: &[&str]) -> Vec<Diagnostic> {
+    let mut resolver = Resolver {
+        scopes: vec![builtins.iter().map(|&name| name.into()).collect()],
+        functions: 0,
+        unresolved: Vec::new(),
+        imported: false,
+    };
+    resolver.scopes.push(HashSet::new());
+    resolver.statements(&program.statements);
+    let top_level = &resolver.scopes[1];
+    let unresolved = resolver
+        .unresolved
+        .into_iter()
+        .filter(|(name, in_function, _)| !(*in_function && top_level.contains(name)))
+        .map(|(name, _, at)| (Diagnostic::new(DiagnosticKind::Undefined, name), Some(at)))
+        .collect();
+    with_paths(program, unresolved)
+}
+
+struct Resolver {
+    /* the builtins, the top level, then one per enclosing function or loop */
+    scopes: Vec<HashSet<Rc<str>>>,
+    /* how many functions the current node is inside */
+    functions: usize,
+    /* with whether the use is inside a function, and the expression it is */
+    unresolved: Vec<(Rc<str>, bool, *const Expression)>,
+    /* whether an `import` without `as` came before, which could have bound
+     * any name */
+    imported: bool,
+}
+
+impl Resolver {
+    fn declare(&mut self, name: &Identifier) {
+        let scope = self.scopes.last_mut().expect("there is always a scope");
+        scope.insert(name.value.clone());
+    }
+
+    fn use_name(&mut self, name: &Rc<str>, at: &Expression) {
+        if !self.imported && !self.scopes.iter().any(|scope| scope.contains(name)) {
+            let in_function = self.functions > 0;
+            self.unresolved.push((name.clone(), in_function, at));
+        }
+    }
+
+    fn statements(&mut self, statements: &[Statement]) {
+        for stmt in statements {
+            match stmt {
+                Statement::LetStatement(ls) => {
+                    match &ls.value {
+                        Expression::FunctionLiteral(fl) => self.function(fl, Some(&ls.name)),
+                        value => self.expression(value),
+                    }
+                    self.declare(&ls.name);
+                }
+                Statement::LetTupleStatement(lts) => {
+                    self.expression(&lts.value);
+                    for name in lts.names.iter() {
+                        self.declare(name);
+                    }
+                }
+                Statement::ReturnStatement(rs) => self.expression(&rs.value),
+                Statement::ExpressionStatement(es) => self.expression(&es.expression),
+                Statement::ImportStatement(is) => match &is.alias {
+                    Some(alias) => self.declare(alias),
+                    None => self.imported = true,
+                },
+                Statement::BreakStatement(_) | Statement::ContinueStatement(_) => {}
+            }
+        }
+    }
+
+    fn function(&mut self, fl: &FunctionLiteral, name: Option<&Identifier>) {
+        self.scopes.push(HashSet::new());
+        self.functions += 1;
+        if let Some(name) = name {
+            self.declare(name);
+        }
+        for param in fl.parameters.iter() {
+            self.declare(param);
+        }
+        self.statements(&fl.body.statements);
+        self.functions -= 1;
+        self.scopes.pop();
+    }
+
+    fn for_expression(&mut self, fe: &ForExpression) {
+        self.expression(&fe.iterable);
+        self.scopes.push(HashSet::new());
+        for name in fe.names.iter() {
+            self.declare(name);
+        }
+        self.statements(&fe.body.statements);
+        self.scopes.pop();
+    }
+
+    fn expression(&mut self, exp: &Expression) {
+        match exp {
+            Expression::Identifier(ident) => self.use_name(&ident.value, exp),
+            Expression::Integer(_)
+            | Expression::Float(_)
+            | Expression::String(_)
+            | Expression::Boolean(_) => {}
+            Expression::Array(al) => self.expressions(&al.elements),
+            Expression::Tuple(tl) => self.expressions(&tl.elements),
+            Expression::Hash(hl) => {
+                for (key, value) in hl.pairs.iter() {
+                    self.expression(key);
+                    self.expression(value);
+                }
+            }
+            Expression::PrefixExpression(pe) => self.expression(&pe.right),
+            Expression::Assign(ae) => {
+                self.expression(&ae.value);
+                self.use_name(&ae.name.value, exp);
+            }
+            Expression::InfixExpression(ie) => {
+                // walk the left spine in a loop, see `Linter::expression`;
+                // the left operand is pushed first to keep the reports in
+                // source order
+                let mut rights = Vec::new();
+                let mut ie = ie;
+                let leftmost = loop {
+                    rights.push(&ie.right);
+                    match &*ie.left {
+                        Expression::InfixExpression(left) => ie = left,
+                        left => break left,
+                    }
+                };
+                self.expression(leftmost);
+                for right in rights.into_iter().rev() {
+                    self.expression(right);
+                }
+            }
+            Expression::IfExpression(ie) => {
+                self.expression(&ie.condition);
+                self.statements(&ie.consequence.statements);
+                if let Some(alt) = &ie.alternative {
+                    self.statements(&alt.statements);
+                }
+            }
+            Expression::ForExpression(fe) => self.for_expression(fe),
+            Expression::FunctionLiteral(fl) => self.function(fl, None),
+            // what a macro builds is checked once it's expanded
+            Expression::MacroLiteral(_) => {}
+            Expression::CallExpression(ce) => {
+                self.expression(&ce.function);
+                self.expressions(&ce.arguments);
+            }
+            Expression::IndexExpression(ie) => {
+                self.expression(&ie.left);
+                self.expression(&ie.index);
+            }
+        }
+    }
+
+    fn expressions(&mut self, exps: &[Expression]) {
+        for exp in exps {
+            self.expression(exp);
+        }
+    }
+}
+
+/// Reports the code that never runs: the statements after a `return` in
+/// the same block, and the branch an `if` with a literal condition doesn't
+/// take. An `if` whose branches both return counts as a return, one with
+/// a return in only one of them doesn't. Nothing inside code that's
+/// already reported is reported again.
+///
+/// Each diagnostic has the path of the first expression of the dead code,
+/// or of the `if` when the branch it never takes is empty.
+pub fn dead_code(program: &Program) -> Vec<Diagnostic> {
+    let mut finder = DeadCode {
+        diagnostics: Vec::new(),
+    };
+    finder.statements(&program.statements);
+    with_paths(program, finder.diagnostics)
+}
+
+/// Whether an `if` on `condition` always takes its consequence, or never
+/// does, when that is known without running anything.
+pub(crate) fn literal_truthiness(condition: &Expression) -> Option<bool> {
+    match condition {
+        Expression::Boolean(b) => Some(b.value),
+        Expression::Integer(_) | Expression::Float(_) | Expression::String(_) => Some(true),
+        _ => None,
+    }
+}
+
+/// Whether running `stmt` always ends in a `return`.
+pub(crate) fn always_returns(stmt: &Statement) -> bool {
+    let Statement::ExpressionStatement(es) = stmt else {
+        return matches!(stmt, Statement::ReturnStatement(_));
+    };
+    let Expression::IfExpression(ife) = &es.expression else {
+        return false;
+    };
+    let alternative_returns = ife.alternative.as_ref().is_some_and(block_returns);
+    match literal_truthiness(&ife.condition) {
+        Some(true) => block_returns(&ife.consequence),
+        Some(false) => alternative_returns,
+        None => alternative_returns && block_returns(&ife.consequence),
+    }
+}
+
+fn block_returns(block: &BlockStatement) -> bool {
+    block.statements.iter().any(always_returns)
+}
+
+struct DeadCode {
+    /* with the expression each is at */
+    diagnostics: Vec<(Diagnostic, Option<*const Expression>)>,
+}
+
+impl DeadCode {
+    fn report(&mut self, kind: DiagnosticKind, subject: String, at: Option<&Expression>) {
+        let diagnostic = Diagnostic::new(kind, subject.into());
+        self.diagnostics
+            .push((diagnostic, at.map(|at| at as *const Expression)));
+    }
+
+    fn statements(&mut self, statements: &[Statement]) {
+        for (i, stmt) in statements.iter().enumerate() {
+            match stmt {
+                Statement::LetStatement(ls) => self.expression(&ls.value),
+                Statement::LetTupleStatement(lts) => self.expression(&lts.value),
+                Statement::ReturnStatement(rs) => self.expression(&rs.value),
+                Statement::ExpressionStatement(es) => self.expression(&es.expression),
+                Statement::BreakStatement(_)
+                | Statement::ContinueStatement(_)
+                | Statement::ImportStatement(_) => {}
+            }
+            let rest = &statements[i + 1..];
+            if always_returns(stmt) && !rest.is_empty() {
+                let dead: Vec<String> = rest.iter().map(|stmt| stmt.string()).collect();
+                // nothing to point at if it's all `break`s and `continue`s
+                let at = rest.iter().find_map(Statement::expression);
+                return self.report(DiagnosticKind::Unreachable, dead.join(" "), at);
+            }
+        }
+    }
+
+    /* `exp` is the `if` expression `ife` is */
+    fn if_expression(&mut self, ife: &IfExpression, exp: &Expression) {
+        self.expression(&ife.condition);
+        let taken = literal_truthiness(&ife.condition);
+        if taken == Some(false) {
+            let consequence = format!("{{ {} }}", ife.consequence.string());
+            let at = ife.consequence.expressions().next().unwrap_or(exp);
+            self.report(DiagnosticKind::NeverTaken, consequence, Some(at));
+        } else {
+            self.statements(&ife.consequence.statements);
+        }
+        if let Some(alt) = &ife.alternative {
+            if taken == Some(true) {
+                let alternative = format!("else {{ {} }}", alt.string());
+                let at = alt.expressions().next().unwrap_or(exp);
+                self.report(DiagnosticKind::NeverTaken, alternative, Some(at));
+            } else {
+                self.statements(&alt.statements);
+            }
+        }
+    }
+
+    fn expression(&mut self, exp: &Expression) {
+        match exp {
+            Expression::Identifier(_)
+            | Expression::Integer(_)
+            | Expression::Float(_)
+            | Expression::String(_)
+            | Expression::Boolean(_) => {}
+            Expression::Array(al) => self.expressions(&al.elements),
+            Expression::Tuple(tl) => self.expressions(&tl.elements),
+            Expression::Hash(hl) => {
+                for (key, value) in hl.pairs.iter() {
+                    self.expression(key);
+                    self.expression(value);
+                }
+            }
+            Expression::PrefixExpression(pe) => self.expression(&pe.right),
+            Expression::Assign(ae) => self.expression(&ae.value),
+            Expression::InfixExpression(ie) => {
+                // walk the left spine in a loop, see `Resolver::expression`
+                let mut rights = Vec::new();
+                let mut ie = ie;
+                let leftmost = loop {
+                    rights.push(&ie.right);
+                    match &*ie.left {
+                        Expression::InfixExpression(left) => ie = left,
+                        left => break left,
+                    }
+                };
+                self.expression(leftmost);
+                for right in rights.into_iter().rev() {
+                    self.expression(right);
+                }
+            }
+            Expression::IfExpression(ife) => self.if_expression(ife, exp),
+            Expression::ForExpression(fe) => {
+                self.expression(&fe.iterable);
+                self.statements(&fe.body.statements);
+            }
+            Expression::FunctionLiteral(fl) => self.statements(&fl.body.statements),
+            Expression::MacroLiteral(_) => {}
+            Expression::CallExpression(ce) => {
+                self.expression(&ce.function);
+                self.expressions(&ce.arguments);
+            }
+            Expression::IndexExpression(ie) => {
+                self.expression(&ie.left);
+                self.expression(&ie.index);
+            }
+        }
+    }
+
+    fn expressions(&mut self, exps: &[Expression]) {
+        for exp in exps {
+            self.expression(exp);
+        }
+    }
+}
+
+/// Warns about expression statements whose value is thrown away when
+/// working it out can't do anything else either, like `1 + 2;` on a line
+/// of its own. Anything with a call, an assignment or a loop in it may
+/// do something, so it's never reported, and neither is the last
+/// statement of a block or the program, which is its value. Each
+/// diagnostic has the path of the unused expression.
+pub fn unused_results(program: &Program) -> Vec<Diagnostic> {
+    let mut finder = UnusedResults {
+        diagnostics: Vec::new(),
+    };
+    finder.statements(&program.statements);
+    with_paths(program, finder.diagnostics)
+}
+
+/* whether evaluating `exp` can do nothing but give a value or fail */
+fn is_pure(exp: &Expression) -> bool {
+    let pure_block = |block: &BlockStatement| {
+        block.statements.iter().all(|stmt| match stmt {
+            Statement::ExpressionStatement(es) => is_pure(&es.expression),
+            // a `let` in a branch binds in the scope around the `if`
+            _ => false,
+        })
+    };
+    match exp {
+        Expression::Identifier(_)
+        | Expression::Integer(_)
+        | Expression::Float(_)
+        | Expression::String(_)
+        | Expression::Boolean(_)
+        | Expression::FunctionLiteral(_)
+        | Expression::MacroLiteral(_) => true,
+        Expression::Array(al) => al.elements.iter().all(is_pure),
+        Expression::Tuple(tl) => tl.elements.iter().all(is_pure),
+        Expression::Hash(hl) => hl
+            .pairs
+            .iter()
+            .all(|(key, value)| is_pure(key) && is_pure(value)),
+        Expression::PrefixExpression(pe) => is_pure(&pe.right),
+        Expression::InfixExpression(ie) => {
+            // walk the left spine in a loop, see `Resolver::expression`
+            let mut ie = ie;
+            loop {
+                if !is_pure(&ie.right) {
+                    return false;
+                }
+                match &*ie.left {
+                    Expression::InfixExpression(left) => ie = left,
+                    left => return is_pure(left),
+                }
+            }
+        }
+        Expression::IndexExpression(ie) => is_pure(&ie.left) && is_pure(&ie.index),
+        Expression::IfExpression(ife) => {
+            is_pure(&ife.condition)
+                && pure_block(&ife.consequence)
+                && ife.alternative.as_ref().is_none_or(pure_block)
+        }
+        Expression::CallExpression(_) | Expression::Assign(_) | Expression::ForExpression(_) => {
+            false
+        }
+    }
+}
+
+struct UnusedResults {
+    /* with the expression each is at */
+    diagnostics: Vec<(Diagnostic, Option<*const Expression>)>,
+}
+
+impl UnusedResults {
+    fn statements(&mut self, statements: &[Statement]) {
+        for (i, stmt) in statements.iter().enumerate() {
+            match stmt {
+                Statement::ExpressionStatement(es)
+                    if i + 1 < statements.len() && is_pure(&es.expression) =>
+                {
+                    let subject = es.expression.string().into();
+                    let diagnostic = Diagnostic::new(DiagnosticKind::UnusedResult, subject);
+                    self.diagnostics.push((diagnostic, Some(&es.expression)));
+                }
+                Statement::ExpressionStatement(es) => self.expression(&es.expression),
+                Statement::LetStatement(ls) => self.expression(&ls.value),
+                Statement::LetTupleStatement(lts) => self.expression(&lts.value),
+                Statement::ReturnStatement(rs) => self.expression(&rs.value),
+                Statement::BreakStatement(_)
+                | Statement::ContinueStatement(_)
+                | Statement::ImportStatement(_) => {}
+            }
+        }
+    }
+
+    fn expression(&mut self, exp: &Expression) {
+        match exp {
+            Expression::Identifier(_)
+            | Expression::Integer(_)
+            | Expression::Float(_)
+            | Expression::String(_)
+            | Expression::Boolean(_) => {}
+            Expression::Array(al) => self.expressions(&al.elements),
+            Expression::Tuple(tl) => self.expressions(&tl.elements),
+            Expression::Hash(hl) => {
+                for (key, value) in hl.pairs.iter() {
+                    self.expression(key);
+                    self.expression(value);
+                }
+            }
+            Expression::PrefixExpression(pe) => self.expression(&pe.right),
+            Expression::Assign(ae) => self.expression(&ae.value),
+            Expression::InfixExpression(ie) => {
+                // walk the left spine in a loop, see `Resolver::expression`
+                let mut rights = Vec::new();
+                let mut ie = ie;
+                let leftmost = loop {
+                    rights.push(&ie.right);
+                    match &*ie.left {
+                        Expression::InfixExpression(left) => ie = left,
+                        left => break left,
+                    }
+                };
+                self.expression(leftmost);
+                for right in rights.into_iter().rev() {
+                    self.expression(right);
+                }
+            }
+            Expression::IfExpression(ife) => {
+                self.expression(&ife.condition);
+                self.statements(&ife.consequence.statements);
+                if let Some(alt) = &ife.alternative {
+                    self.statements(&alt.statements);
+                }
+            }
+            Expression::ForExpression(fe) => {
+                self.expression(&fe.iterable);
+                self.statements(&fe.body.statements);
+            }
+            Expression::FunctionLiteral(fl) => self.statements(&fl.body.statements),
+            Expression::MacroLiteral(ml) => self.statements(&ml.body.statements),
+            Expression::CallExpression(ce) => {
+                self.expression(&ce.function);
+                self.expressions(&ce.arguments);
+            }
+            Expression::IndexExpression(ie) => {
+                self.expression(&ie.left);
+                self.expression(&ie.index);
+            }
+        }
+    }
+
+    fn expressions(&mut self, exps: &[Expression]) {
+        for exp in exps {
+            self.expression(exp);
+        }
+    }
+}
+
+#[derive(PartialEq)]
+enum BindingKind {
+    Let,
+    Parameter,
+    /* the name of a let-bound function inside its own body */
+    Itself,
+}
+
+struct Binding {
+    name: Rc<str>,
+    kind: BindingKind,
+    used: bool,
+}
+
+struct Linter {
+    scopes: Vec<Vec<Binding>>,
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl Linter {
+    fn report(&mut self, kind: DiagnosticKind, name: &Rc<str>) {
+        if !name.starts_with('_') {
+            self.diagnostics.push(Diagnostic::new(kind, name.clone()));
+        }
+    }
+
+    fn declare(&mut self, name: &Identifier, kind: BindingKind) {
+        let (scope, outer) = self.scopes.split_last().expect("there is always a scope");
+        let in_scope = |scope: &Vec<Binding>| scope.iter().any(|b| b.name == name.value);
+        // a redeclaration in the same scope is the parser's no_shadowing check
+        if kind != BindingKind::Itself && !in_scope(scope) && outer.iter().any(in_scope) {
+            self.report(DiagnosticKind::Shadowed, &name.value);
+        }
+        self.scopes
+            .last_mut()
+            .expect("there is always a scope")
+            .push(Binding {
+                name: name.value.clone(),
+                kind,
+                used: false,
+            });
+    }
+
+    fn use_name(&mut self, name: &Rc<str>) {
+        let binding = self
+            .scopes
+            .iter_mut()
+            .rev()
+            .find_map(|scope| scope.iter_mut().rev().find(|b| b.name == *name));
+        // anything else is a builtin or not bound at all
+        if let Some(binding) = binding {
+            binding.used = true;
+        }
+    }
+
+    fn pop_scope(&mut self) {
+        let scope = self.scopes.pop().expect("there is always a scope");
+        for binding in scope.iter().filter(|b| !b.used) {
+            match binding.kind {
+                BindingKind::Let => self.report(DiagnosticKind::UnusedBinding, &binding.name),
+                BindingKind::Parameter => {
+                    self.report(DiagnosticKind::UnusedParameter, &binding.name)
+                }
+                BindingKind::Itself => {}
+            }
+        }
+    }
+
+    fn statements(&mut self, statements: &[Statement]) {
+        for stmt in statements {
+            match stmt {
+                Statement::LetStatement(ls) => {
+                    // the value comes first, `let x = x + 1` reads the old `x`
+                    match &ls.value {
+                        Expression::FunctionLiteral(fl) => self.function(fl, Some(&ls.name)),
+                        value => self.expression(value),
+                    }
+                    self.declare(&ls.name, BindingKind::Let);
+                }
+                Statement::LetTupleStatement(lts) => {
+                    self.expression(&lts.value);
+                    for name in lts.names.iter() {
+                        self.declare(name, BindingKind::Let);
+                    }
+                }
+                Statement::ReturnStatement(rs) => self.expression(&rs.value),
+                Statement::ExpressionStatement(es) => self.expression(&es.expression),
+                Statement::ImportStatement(is) => {
+                    if let Some(alias) = &is.alias {
+                        self.declare(alias, BindingKind::Let);
+                    }
+                }
+                Statement::BreakStatement(_) | Statement::ContinueStatement(_) => {}
+            }
+        }
+    }
+
+    fn function(&mut self, fl: &FunctionLiteral, name: Option<&Identifier>) {
+        self.scopes.push(Vec::new());
+        if let Some(name) = name {
+            self.declare(name, BindingKind::Itself);
+        }
+        for param in fl.parameters.iter() {
+            self.declare(param, BindingKind::Parameter);
+        }
+        self.statements(&fl.body.statements);
+        self.pop_scope();
+    }
+
+    fn expression(&mut self, exp: &Expression) {
+        match exp {
+            Expression::Identifier(ident) => self.use_name(&ident.value),
+            Expression::Integer(_)
+            | Expression::Float(_)
+            | Expression::String(_)
+            | Expression::Boolean(_) => {}
+            Expression::Array(al) => self.expressions(&al.elements),
+            Expression::Tuple(tl) => self.expressions(&tl.elements),
+            Expression::Hash(hl) => {
+                for (key, value) in hl.pairs.iter() {
+                    self.expression(key);
+                    self.expression(value);
+                }
+            }
+            Expression::PrefixExpression(pe) => self.expression(&pe.right),
+            // setting a binding counts as using it
+            Expression::Assign(ae) => {
+                self.expression(&ae.value);
+                self.use_name(&ae.name.value);
+            }
+            Expression::InfixExpression(ie) => {
+                // a long `1 + 1 + ...` nests down the left, so walk that spine
+                // in a loop rather than recursing
+                let mut ie = ie;
+                loop {
+                    self.expression(&ie.right);
+                    match &*ie.left {
+                        Expression::InfixExpression(left) => ie = left,
+                        left => break self.expression(left),
+                    }
+                }
+            }
+            Expression::IfExpression(ie) => {
+                self.expression(&ie.condition);
+                self.statements(&ie.consequence.statements);
+                if let Some(alt) = &ie.alternative {
+                    self.statements(&alt.statements);
+                }
+            }
+            Expression::ForExpression(fe) => {
+                self.expression(&fe.iterable);
+                self.scopes.push(Vec::new());
+                for name in fe.names.iter() {
+                    self.declare(name, BindingKind::Let);
+                }
+                self.statements(&fe.body.statements);
+                self.pop_scope();
+            }
+            Expression::FunctionLiteral(fl) => self.function(fl, None),
+            // what a macro builds is checked once it's expanded
+            Expression::MacroLiteral(_) => {}
+            Expression::CallExpression(ce) => {
+                self.expression(&ce.function);
+                self.expressions(&ce.arguments);
+            }
+            Expression::IndexExpression(ie) => {
+                self.expression(&ie.left);
+                self.expression(&ie.index);
+            }
+        }
+    }
+
+    fn expressions(&mut self, exps: &[Expression]) {
+        for exp in exps {
+            self.expression(exp);
+        }
+    }
+}
+
+/// Infers the type of every expression from its literals and operators,
+/// before anything runs, and reports the operators whose operands the
+/// evaluator would reject, like `1 + true`, and `if` conditions that
+/// aren't booleans. An `if` whose branches give values of two different
+/// types gets a [`DiagnosticKind::BranchTypes`] warning.
+///
+/// Names bound with `let` keep the type of their value. Everything that
+/// can't be known without running the program is unknown and never
+/// reported: parameters, what calls and index expressions give, and names
+/// `let` doesn't bind, like the builtins. Scopes work like in [`lint`].
+pub fn typecheck(program: &Program) -> Vec<Diagnostic> {
+    let mut checker = TypeChecker {
+        scopes: vec![HashMap::new()],
+        diagnostics: Vec::new(),
+    };
+    checker.statements(&program.statements);
+    checker.diagnostics
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Type {
+    Int,
+    Bool,
+    String,
+    Array,
+    Hash,
+    Function,
+    Unknown,
+}
+
+impl std::fmt::Display for Type {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Type::Int => "INT",
+            Type::Bool => "BOOL",
+            Type::String => "STRING",
+            Type::Array => "ARRAY",
+            Type::Hash => "HASH",
+            Type::Function => "FUNCTION",
+            Type::Unknown => "UNKNOWN",
+        })
+    }
+}
+
+struct TypeChecker {
+    /* the top level, then one per enclosing function or loop */
+    scopes: Vec<HashMap<Rc<str>, Type>>,
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl TypeChecker {
+    fn report(&mut self, kind: DiagnosticKind, subject: String) {
+        self.diagnostics.push(Diagnostic::new(kind, subject.into()));
+    }
+
+    fn bind(&mut self, name: &Identifier, ty: Type) {
+        let scope = self.scopes.last_mut().expect("there is always a scope");
+        scope.insert(name.value.clone(), ty);
+    }
+
+    /* the type of the last statement's value, unknown without one */
+    fn statements(&mut self, statements: &[Statement]) -> Type {
+        let mut res = Type::Unknown;
+        for stmt in statements {
+            res = Type::Unknown;
+            match stmt {
+                Statement::LetStatement(ls) => {
+                    let ty = self.expression(&ls.value);
+                    self.bind(&ls.name, ty);
+                }
+                Statement::LetTupleStatement(lts) => {
+                    self.expression(&lts.value);
+                    for name in lts.names.iter() {
+                        self.bind(name, Type::Unknown);
+                    }
+                }
+                Statement::ReturnStatement(rs) => {
+                    self.expression(&rs.value);
+                }
+                Statement::ExpressionStatement(es) => res = self.expression(&es.expression),
+                Statement::ImportStatement(is) => match &is.alias {
+                    Some(alias) => self.bind(alias, Type::Hash),
+                    None => {
+                        // the module may rebind any of them
+                        let scope = self.scopes.last_mut().expect("there is always a scope");
+                        scope.values_mut().for_each(|ty| *ty = Type::Unknown);
+                    }
+                },
+                Statement::BreakStatement(_) | Statement::ContinueStatement(_) => {}
+            }
+        }
+        res
+    }
+
+    fn function(&mut self, fl: &FunctionLiteral) -> Type {
+        self.scopes.push(HashMap::new());
+        for param in fl.parameters.iter() {
+            self.bind(param, Type::Unknown);
+        }
+        self.statements(&fl.body.statements);
+        self.scopes.pop();
+        Type::Function
+    }
+
+    fn expression(&mut self, exp: &Expression) -> Type {
+        match exp {
+            Expression::Identifier(ident) => self
+                .scopes
+                .iter()
+                .rev()
+                .find_map(|scope| scope.get(&ident.value))
+                .copied()
+                .unwrap_or(Type::Unknown),
+            Expression::Integer(_) => Type::Int,
+            // the checks know nothing of floats yet, so mixing them in
+            // isn't reported
+            Expression::Float(_) => Type::Unknown,
+            Expression::Boolean(_) => Type::Bool,
+            Expression::String(_) => Type::String,
+            Expression::Array(al) => {
+                self.expressions(&al.elements);
+                Type::Array
+            }
+            Expression::Tuple(tl) => {
+                self.expressions(&tl.elements);
+                Type::Unknown
+            }
+            Expression::Hash(hl) => {
+                for (key, value) in hl.pairs.iter() {
+                    self.expression(key);
+                    self.expression(value);
+                }
+                Type::Hash
+            }
+            Expression::PrefixExpression(pe) => {
+                let right = self.expression(&pe.right);
+                match pe.operator {
+                    PrefixOperator::Bang => Type::Bool,
+                    PrefixOperator::Minus => match right {
+                        Type::Int | Type::Unknown => right,
+                        _ => {
+                            let msg = format!("cannot negate {}", right);
+                            self.report(DiagnosticKind::TypeError, msg);
+                            Type::Unknown
+                        }
+                    },
+                }
+            }
+            Expression::InfixExpression(ie) => self.infix(ie),
+            Expression::Assign(ae) => {
+                let ty = self.expression(&ae.value);
+                // the binding may or may not have been set by the time it's
+                // read, so its type is no longer known
+                let binding = self
+                    .scopes
+                    .iter_mut()
+                    .rev()
+                    .find_map(|scope| scope.get_mut(&ae.name.value));
+                if let Some(binding) = binding {
+                    *binding = Type::Unknown;
+                }
+                ty
+            }
+            Expression::IfExpression(ife) => self.if_expression(ife),
+            Expression::ForExpression(fe) => self.for_expression(fe),
+            Expression::FunctionLiteral(fl) => self.function(fl),
+            Expression::MacroLiteral(_) => Type::Unknown,
+            Expression::CallExpression(ce) => {
+                self.expression(&ce.function);
+                self.expressions(&ce.arguments);
+                Type::Unknown
+            }
+            Expression::IndexExpression(ie) => {
+                self.expression(&ie.left);
+                self.expression(&ie.index);
+                Type::Unknown
+            }
+        }
+    }
+
+    fn expressions(&mut self, exps: &[Expression]) {
+        for exp in exps {
+            self.expression(exp);
+        }
+    }
+
+    fn infix(&mut self, ie: &InfixExpression) -> Type {
+        // walk the left spine in a loop, see `Resolver::expression`
+        let mut spine = Vec::new();
+        let mut ie = ie;
+        let leftmost = loop {
+            spine.push(ie);
+            match &*ie.left {
+                Expression::InfixExpression(left) => ie = left,
+                left => break left,
+            }
+        };
+        let mut left = self.expression(leftmost);
+        for ie in spine.into_iter().rev() {
+            let right = self.expression(&ie.right);
+            left = self.operator(left, &ie.operator, right);
+        }
+        left
+    }
+
+    /* the type `left operator right` gives, the same signatures as the
+     * evaluator's operators */
+    fn operator(&mut self, left: Type, operator: &InfixOperator, right: Type) -> Type {
+        let comparison = matches!(
+            operator,
+            InfixOperator::Lt | InfixOperator::Gt | InfixOperator::Eq | InfixOperator::NotEq
+        );
+        let equality = matches!(operator, InfixOperator::Eq | InfixOperator::NotEq);
+        let res = match (left, right) {
+            (Type::Unknown, _) | (_, Type::Unknown) if comparison => Some(Type::Bool),
+            (Type::Unknown, _) | (_, Type::Unknown) => Some(Type::Unknown),
+            (Type::Int, Type::Int) if comparison => Some(Type::Bool),
+            (Type::Int, Type::Int) => Some(Type::Int),
+            (Type::String, Type::String) if *operator == InfixOperator::Plus => Some(Type::String),
+            (Type::String, Type::Int) if *operator == InfixOperator::Asterisk => Some(Type::String),
+            // values of different types are never equal, but it's no error
+            _ if equality => Some(Type::Bool),
+            _ => None,
+        };
+        res.unwrap_or_else(|| {
+            let verb = match operator {
+                InfixOperator::Plus => "add",
+                InfixOperator::Minus => "subtract",
+                InfixOperator::Asterisk => "multiply",
+                InfixOperator::Slash => "divide",
+                InfixOperator::Lt
+                | InfixOperator::Gt
+                | InfixOperator::Eq
+                | InfixOperator::NotEq => "compare",
+            };
+            let msg = format!("cannot {} {} and {}", verb, left, right);
+            self.report(DiagnosticKind::TypeError, msg);
+            Type::Unknown
+        })
+    }
+
+    fn for_expression(&mut self, fe: &ForExpression) -> Type {
+        let iterable = self.expression(&fe.iterable);
+        if !matches!(
+            iterable,
+            Type::Array | Type::Hash | Type::String | Type::Unknown
+        ) {
+            let msg = format!("cannot iterate over {}", iterable);
+            self.report(DiagnosticKind::TypeError, msg);
+        }
+        self.scopes.push(HashMap::new());
+        for name in fe.names.iter() {
+            self.bind(name, Type::Unknown);
+        }
+        self.statements(&fe.body.statements);
+        self.scopes.pop();
+        Type::Unknown
+    }
+
+    fn if_expression(&mut self, ife: &IfExpression) -> Type {
+        let condition = self.expression(&ife.condition);
+        if !matches!(condition, Type::Bool | Type::Unknown) {
+            let msg = format!("condition of if must be BOOL, found {}", condition);
+            self.report(DiagnosticKind::TypeError, msg);
+        }
+        let consequence = self.statements(&ife.consequence.statements);
+        let Some(alt) = &ife.alternative else {
+            // without an else the value may be null
+            return Type::Unknown;
+        };
+        let alternative = self.statements(&alt.statements);
+        if consequence == alternative {
+            return consequence;
+        }
+        if consequence != Type::Unknown && alternative != Type::Unknown {
+            let subject = format!("{} and {}", consequence, alternative);
+            self.report(DiagnosticKind::BranchTypes, subject);
+        }
+        Type::Unknown
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::analysis::{dead_code, lint, resolve, typecheck, unused_results, Diagnostic};
+    use crate::ast::Program;
+    use crate::builtins::BUILTIN_NAMES;
+    use crate::lexer::{token_spans, Lexer};
+    use crate::parser::Parser;
+
+    /* the source each diagnostic of `pass` is at */
+    fn located(input: &str, pass: impl Fn(&Program) -> Vec<Diagnostic>) -> Vec<&str> {
+        let mut p = Parser::new(Lexer::new(input)).with_source_map();
+        let program = p.parse();
+        assert!(p.get_errors().is_empty(), "{:?}", p.get_errors());
+        let spans = token_spans(input);
+        let mut diagnostics = pass(&program);
+        diagnostics
+            .iter_mut()
+            .map(|d| {
+                d.locate(p.source_map(), &spans);
+                &input[d.span.clone().expect("every diagnostic is located")]
+            })
+            .collect()
+    }
+
+    fn check(input: &str, pass: impl Fn(&Program) -> Vec<Diagnostic>) -> Vec<String> {
+        let mut p = Parser::new(Lexer::new(input));
+        let program = p.parse();
+        assert!(p.get_errors().is_empty(), "{:?}", p.get_errors());
+        pass(&program).iter().map(|d| d.to_string()).collect()
+    }
+
+    fn lint_source(input: &str) -> Vec<String> {
+        check(input, lint)
+    }
+
+    fn resolve_source(input: &str) -> Vec<String> {
+        check(input, |program| resolve(program, &BUILTIN_NAMES))
+    }
+
+    #[test]
+    fn test_resolve() {
+        let tests: [(&str, &[&str]); 19] = [
+            ("let x = 1; x + len(\"a\")", &[]),
+            ("x", &["identifier not found: x"]),
+            ("y + 1; let y = 2;", &["identifier not found: y"]),
+            (
+                "a + b * a",
+                &[
+                    "identifier not found: a",
+                    "identifier not found: b",
+                    "identifier not found: a",
+                ],
+            ),
+            ("const c = 1; fn(p) { p + c }", &[]),
+            (
+                "let f = fn() { let z = 1; z }; z",
+                &["identifier not found: z"],
+            ),
+            // closures see the enclosing function's bindings, at any depth
+            ("fn(a) { let b = a; fn(c) { fn() { a + b + c } } }", &[]),
+            ("fn(a) { fn() { b } }", &["identifier not found: b"]),
+            // a parameter shadowing a top-level binding is still declared
+            ("let x = 1; fn(x) { x }; x", &[]),
+            // functions may use top-level bindings made after them
+            ("let f = fn() { g() }; let g = fn() { f() };", &[]),
+            ("fn() { fn() { later } }; let later = 1;", &[]),
+            // but not ones inside a later function
+            (
+                "let f = fn() { g() }; let h = fn() { let g = 1; g };",
+                &["identifier not found: g"],
+            ),
+            // and the rule is for functions only, not for the top level
+            (
+                "if (true) { later } let later = 1;",
+                &["identifier not found: later"],
+            ),
+            // loop variables are only declared in the body
+            ("for (k, v in {}) { k + v }", &[]),
+            ("for (x in []) { x }; x", &["identifier not found: x"]),
+            // an alias is declared, and a plain import could declare anything
+            (
+                "m; import \"m\" as m; m[\"f\"]; f",
+                &["identifier not found: m", "identifier not found: f"],
+            ),
+            ("import \"m\"; f(g)", &[]),
+            // a let declares only its own name
+            (
+                "let x = (y = 5); x + y",
+                &["identifier not found: y", "identifier not found: y"],
+            ),
+            (
+                "y = 5; y",
+                &["identifier not found: y", "identifier not found: y"],
+            ),
+        ];
+        for (input, exp) in tests {
+            assert_eq!(resolve_source(input), exp, "resolving {:?}", input);
+        }
+        let input = "let f = fn() {\n  g(x)\n};\ny = 1 + 1 + z;";
+        assert_eq!(
+            located(input, |program| resolve(program, &BUILTIN_NAMES)),
+            ["g", "x", "z", "y = 1 + 1 + z"]
+        );
+    }
+
+    #[test]
+    fn test_resolve_self_reference() {
+        let tests: [(&str, &[&str]); 4] = [
+            (
+                "let fact = fn(n) { if (n < 2) { 1 } else { n * fact(n - 1) } };",
+                &[],
+            ),
+            ("let x = x + 1;", &["identifier not found: x"]),
+            // only a let-bound function knows its own name
+            (
+                "fn() { let h = [fn() { h }]; h }",
+                &["identifier not found: h"],
+            ),
+            ("let a, b = (1, a);", &["identifier not found: a"]),
+        ];
+        for (input, exp) in tests {
+            assert_eq!(resolve_source(input), exp, "resolving {:?}", input);
+        }
+        assert_eq!(
+            check("len(x)", |program| resolve(program, &["x"])),
+            ["identifier not found: len"]
+        );
+    }
+
+    #[test]
+    fn test_dead_code() {
+        let tests: [(&str, &[&str]); 9] = [
+            (
+                "let x = 1; return x; x + 1; let y = 2;",
+                &["unreachable code: (x + 1) let y = 2;"],
+            ),
+            ("return 1;", &[]),
+            ("fn() { return 1; 2 }; fn() { 3 }", &["unreachable code: 2"]),
+            // a return in one branch leaves the code after the if reachable
+            ("fn(x) { if (x) { return 1; } 2 }", &[]),
+            ("fn(x) { if (x) { 1 } else { return 2; } 3 }", &[]),
+            (
+                "fn(x) { if (x) { return 1; } else { return 2; } 3 }",
+                &["unreachable code: 3"],
+            ),
+            // the return ends its own block, not the one around it
+            (
+                "fn(x) { if (x) { if (x) { return 1; 2 } 3 } 4 }",
+                &["unreachable code: 2"],
+            ),
+            (
+                "fn(x) { if (x) { return 1; } else { if (x) { return 2; } else { return 3; } } 4 }",
+                &["unreachable code: 4"],
+            ),
+            // code inside unreachable code isn't reported again
+            (
+                "return 1; fn() { return 2; 3 }",
+                &["unreachable code: fn() { return 2; 3 }"],
+            ),
+        ];
+        for (input, exp) in tests {
+            assert_eq!(check(input, dead_code), exp, "checking {:?}", input);
+        }
+    }
+
+    #[test]
+    fn test_dead_code_literal_conditions() {
+        let tests: [(&str, &[&str]); 6] = [
+            ("if (false) { 1 }", &["branch never taken: { 1 }"]),
+            (
+                "if (true) { 1 } else { 2 }",
+                &["branch never taken: else { 2 }"],
+            ),
+            (
+                "if (0) { 1 } else { 2 }",
+                &["branch never taken: else { 2 }"],
+            ),
+            ("if (x) { 1 } else { 2 }", &[]),
+            // the branch that is taken decides whether the if returns
+            (
+                "fn() { if (true) { return 1; } 2 }",
+                &["unreachable code: 2"],
+            ),
+            (
+                "fn() { if (false) { return 1; return 2; } 3 }",
+                &["branch never taken: { return 1; return 2; }"],
+            ),
+        ];
+        for (input, exp) in tests {
+            assert_eq!(check(input, dead_code), exp, "checking {:?}", input);
+        }
+        let input = "fn() { return 1;\n  x * 2; 3 };\nif (false) { f() } else { g() };\nif (true) { 1 } else {}";
+        assert_eq!(
+            located(input, dead_code),
+            ["x * 2", "f()", "if (true) { 1 } else {}"]
+        );
+    }
+
+    #[test]
+    fn test_unused_results() {
+        let tests: [(&str, &[&str]); 12] = [
+            ("1 + 2; 3", &["expression result unused: (1 + 2)"]),
+            // the last statement is the value of the program or block
+            ("1 + 2", &[]),
+            ("let f = fn(x) { x * 2 }; f(1)", &[]),
+            (
+                "let f = fn(x) { x; x * 2 }; f(1)",
+                &["expression result unused: x"],
+            ),
+            ("if (c) { 1 } else { 2 }", &[]),
+            (
+                "if (c) { a; b } else { 2 }",
+                &["expression result unused: a"],
+            ),
+            // a call may do anything
+            ("print(1); len(\"a\") + 1; 2", &[]),
+            ("[1, f(2)]; 3", &[]),
+            ("let x = 1; x = 2; x", &[]),
+            ("for (x in xs) { x }; 1", &[]),
+            ("if (c) { print(1) }; 2", &[]),
+            (
+                "[1, -x, {\"a\": y[0]}]; if (c) { 1 }; fn() { 2 }; 3",
+                &[
+                    "expression result unused: [1, (-x), {\"a\":(y[0])}]",
+                    "expression result unused: if (c) { 1 }",
+                    "expression result unused: fn() { 2 }",
+                ],
+            ),
+        ];
+        for (input, exp) in tests {
+            assert_eq!(check(input, unused_results), exp, "checking {:?}", input);
+        }
+        let input = "let x = 1;\n(x + 2);\nif (c) { a; f(-b) }; 1 + 1 + 1 + x; 3";
+        assert_eq!(
+            located(input, unused_results),
+            ["(x + 2)", "a", "1 + 1 + 1 + x"]
+        );
+    }
+
+    #[test]
+    fn test_lint() {
+        let tests: [(&str, &[&str]); 12] = [
+            ("let x = 1; x", &[]),
+            ("let x = 1;", &["unused binding 'x'"]),
+            ("let _x = 1; let f = fn(_y) { 1 }; f()", &[]),
+            ("let f = fn(a, b) { a }; f(1, 2)", &["unused parameter 'b'"]),
+            // captured by a closure that is itself used
+            ("let x = 1; let f = fn() { x }; f()", &[]),
+            ("let x = 1; let f = fn() { fn() { x } }; f()()", &[]),
+            // a closure that is never called still reads `x`
+            ("let x = 1; let f = fn() { x };", &["unused binding 'f'"]),
+            // closures only see bindings made before them
+            (
+                "let f = fn() { y }; let y = 1; f()",
+                &["unused binding 'y'"],
+            ),
+            (
+                "let x = 1; let f = fn(x) { let y = x; y }; f(x)",
+                &["'x' shadows a binding in an outer scope"],
+            ),
+            (
+                "let x = 1; let f = fn() { let x = 2; x }; f()",
+                &[
+                    "'x' shadows a binding in an outer scope",
+                    "unused binding 'x'",
+                ],
+            ),
+            // the value is read before the new `x` is bound
+            ("let x = 1; let x = x + 1; x", &[]),
+            (
+                "let fact = fn(n) { if (n < 2) { 1 } else { n * fact(n - 1) } };",
+                &["unused binding 'fact'"],
+            ),
+        ];
+        for (input, exp) in tests {
+            assert_eq!(lint_source(input), exp, "linting {:?}", input);
+        }
+    }
+
+    #[test]
+    fn test_lint_tuples_and_blocks() {
+        let tests: [(&str, &[&str]); 3] = [
+            ("let a, b = (1, 2); a", &["unused binding 'b'"]),
+            // an if block binds into the scope around it
+            ("if (true) { let x = 1; } x", &[]),
+            (
+                "let g = fn(x) { if (x) { let x = 2; } }; g(1)",
+                &["unused binding 'x'"],
+            ),
+        ];
+        for (input, exp) in tests {
+            assert_eq!(lint_source(input), exp, "linting {:?}", input);
+        }
+    }
+
+    #[test]
+    fn test_typecheck_well_typed() {
+        let tests = [
+            "1 + 2 * 3 - 4 / 5",
+            "let a = \"x\" + \"y\"; a * 3",
+            "let b = 1 < 2; if (b == true) { 1 } else { 2 }",
+            "!5; -(-5); [1, true] == [2]; {1: 2} != {}; 1 == \"1\"",
+            "let f = fn(x) { x + 1 }; f(true) + 1",
+            "if (len(\"a\")) { 1 }",
+            "let x = if (true) { 1 }; x + \"s\"",
+            "if (true) { 1 } else { let y = 2; }",
+            "let a, b = (1, 2); a + true",
+            "let n = 1; let g = fn(n) { n + \"s\" };",
+            "first([1]) - 1; [1][0] * \"s\"",
+        ];
+        for input in tests {
+            assert_eq!(check(input, typecheck), [] as [&str; 0], "{:?}", input);
+        }
+    }
+
+    #[test]
+    fn test_typecheck_ill_typed() {
+        let tests: [(&str, &[&str]); 13] = [
+            ("1 + true", &["cannot add INT and BOOL"]),
+            ("\"a\" - \"b\"", &["cannot subtract STRING and STRING"]),
+            ("3 * \"a\"", &["cannot multiply INT and STRING"]),
+            ("true / false", &["cannot divide BOOL and BOOL"]),
+            ("1 < true", &["cannot compare INT and BOOL"]),
+            ("\"a\" < \"b\"", &["cannot compare STRING and STRING"]),
+            ("-true", &["cannot negate BOOL"]),
+            (
+                "let x = 5; let y = x == 5; y + x",
+                &["cannot add BOOL and INT"],
+            ),
+            ("if (1) { 2 }", &["condition of if must be BOOL, found INT"]),
+            (
+                "if (1 > 2) { 1 } else { \"one\" }",
+                &["branches of if have different types: INT and STRING"],
+            ),
+            // an error makes the result unknown, so it's reported once
+            ("(1 + true) * 2 + [1]", &["cannot add INT and BOOL"]),
+            (
+                "let f = fn() { if (\"s\") { [1] + 1 } };",
+                &[
+                    "condition of if must be BOOL, found STRING",
+                    "cannot add ARRAY and INT",
+                ],
+            ),
+            ("for (x in 1) { x }", &["cannot iterate over INT"]),
+        ];
+        for (input, exp) in tests {
+            assert_eq!(check(input, typecheck), exp, "typechecking {:?}", input);
+        }
+    }
+}
@@ -1,107 +1,457 @@
+use crate::object::Object;
 use crate::token::Token;
 
+#[cfg(feature = "bump")]
+pub mod arena;
+
 pub trait Node {
     fn token_literal(&self) -> String;
     fn string(&self) -> String;
 }
 
 #[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub struct Program {
     pub statements: Vec<Statement>,
 }
 
+impl Program {
+    /// The names the top-level `let` and `const` statements bind, in order,
+    /// including every name of a destructuring `let`. Bindings inside
+    /// functions and blocks are not included.
+    pub fn let_bindings(&self) -> Vec<&Identifier> {
+        let mut res = Vec::new();
+        for stmt in self.statements.iter() {
+            match stmt {
+                Statement::LetStatement(ls) => res.push(&ls.name),
+                Statement::LetTupleStatement(lts) => res.extend(lts.names.iter()),
+                Statement::ReturnStatement(_)
+                | Statement::ExpressionStatement(_)
+                | Statement::BreakStatement(_)
+                | Statement::ContinueStatement(_)
+                | Statement::ImportStatement(_) => {}
+            }
+        }
+        res
+    }
+
+    /// Replaces the expression at `path` with `exp` and returns the one
+    /// that was there, or `None` if nothing is at `path`.
+    ///
+    /// The first index picks a top-level statement, which leads to its
+    /// expression, so there is nothing at a path through a `break`. Every index after that picks one of the expressions
+    /// right below the current one, in the order they're written; a block
+    /// counts as the expressions of its statements. So in `1 + 5;` the
+    /// path to the `5` is `[0, 1]`, and in `if (c) { a; b }` the path to
+    /// `b` is `[0, 2]`.
+    pub fn replace_expr_at(&mut self, path: &[usize], exp: Expression) -> Option<Expression> {
+        let (first, rest) = path.split_first()?;
+        let mut target = self.statements.get_mut(*first)?.expression_mut()?;
+        for i in rest {
+            target = target.children_mut().into_iter().nth(*i)?;
+        }
+        Some(std::mem::replace(target, exp))
+    }
+}
+
+impl Statement {
+    pub(crate) fn expression(&self) -> Option<&Expression> {
+        match self {
+            Statement::LetStatement(ls) => Some(&ls.value),
+            Statement::LetTupleStatement(lts) => Some(&lts.value),
+            Statement::ReturnStatement(rs) => Some(&rs.value),
+            Statement::ExpressionStatement(es) => Some(&es.expression),
+            Statement::ImportStatement(is) => Some(&is.path),
+            Statement::BreakStatement(_) | Statement::ContinueStatement(_) => None,
+        }
+    }
+
+    pub(crate) fn expression_mut(&mut self) -> Option<&mut Expression> {
+        match self {
+            Statement::LetStatement(ls) => Some(&mut ls.value),
+            Statement::LetTupleStatement(lts) => Some(&mut lts.value),
+            Statement::ReturnStatement(rs) => Some(&mut rs.value),
+            Statement::ExpressionStatement(es) => Some(&mut es.expression),
+            Statement::ImportStatement(is) => Some(&mut is.path),
+            Statement::BreakStatement(_) | Statement::ContinueStatement(_) => None,
+        }
+    }
+}
+
+impl BlockStatement {
+    pub(crate) fn expressions(&self) -> impl Iterator<Item = &Expression> {
+        self.statements.iter().filter_map(Statement::expression)
+    }
+
+    fn expressions_mut(&mut self) -> impl Iterator<Item = &mut Expression> {
+        self.statements
+            .iter_mut()
+            .filter_map(Statement::expression_mut)
+    }
+}
+
+impl Expression {
+    /* the expressions right below this one, in source order */
+    pub(crate) fn children(&self) -> Vec<&Expression> {
+        match self {
+            Expression::Identifier(_)
+            | Expression::Integer(_)
+            | Expression::Float(_)
+            | Expression::String(_)
+            | Expression::Boolean(_) => Vec::new(),
+            Expression::Array(al) => al.elements.iter().collect(),
+            Expression::Tuple(tl) => tl.elements.iter().collect(),
+            Expression::Hash(hl) => hl
+                .pairs
+                .iter()
+                .flat_map(|(key, value)| [key, value])
+                .collect(),
+            Expression::PrefixExpression(pe) => vec![&*pe.right],
+            Expression::InfixExpression(ie) => vec![&*ie.left, &*ie.right],
+            Expression::IfExpression(ife) => {
+                let mut res = vec![&*ife.condition];
+                res.extend(ife.consequence.expressions());
+                if let Some(alt) = &ife.alternative {
+                    res.extend(alt.expressions());
+                }
+                res
+            }
+            Expression::ForExpression(fe) => {
+                let mut res = vec![&*fe.iterable];
+                res.extend(fe.body.expressions());
+                res
+            }
+            Expression::FunctionLiteral(fl) => fl.body.expressions().collect(),
+            Expression::MacroLiteral(ml) => ml.body.expressions().collect(),
+            Expression::CallExpression(ce) => {
+                let mut res = vec![&*ce.function];
+                res.extend(ce.arguments.iter());
+                res
+            }
+            Expression::IndexExpression(ie) => vec![&*ie.left, &*ie.index],
+            Expression::Assign(ae) => vec![&*ae.value],
+        }
+    }
+
+    /* whether this is a literal, or an array, hash, tuple or prefix
+     * expression of nothing but literals, which evaluates to the same
+     * value every time without doing anything else */
+    fn is_literal(&self) -> bool {
+        match self {
+            Expression::Integer(_)
+            | Expression::Float(_)
+            | Expression::String(_)
+            | Expression::Boolean(_) => true,
+            Expression::Array(al) => al.constant.0.is_some(),
+            Expression::Tuple(tl) => tl.constant.0.is_some(),
+            Expression::Hash(hl) => hl.constant.0.is_some(),
+            Expression::PrefixExpression(pe) => pe.right.is_literal(),
+            _ => false,
+        }
+    }
+
+    /* `children`, to change them */
+    pub(crate) fn children_mut(&mut self) -> Vec<&mut Expression> {
+        match self {
+            Expression::Identifier(_)
+            | Expression::Integer(_)
+            | Expression::Float(_)
+            | Expression::String(_)
+            | Expression::Boolean(_) => Vec::new(),
+            // the value kept may not be the value once they are changed
+            Expression::Array(al) => {
+                al.constant = Constant::default();
+                al.elements.iter_mut().collect()
+            }
+            Expression::Tuple(tl) => {
+                tl.constant = Constant::default();
+                tl.elements.iter_mut().collect()
+            }
+            Expression::Hash(hl) => {
+                hl.constant = Constant::default();
+                hl.pairs
+                    .iter_mut()
+                    .flat_map(|(key, value)| [key, value])
+                    .collect()
+            }
+            Expression::PrefixExpression(pe) => vec![std::rc::Rc::make_mut(&mut pe.right)],
+            Expression::InfixExpression(ie) => vec![
+                std::rc::Rc::make_mut(&mut ie.left),
+                std::rc::Rc::make_mut(&mut ie.right),
+            ],
+            Expression::IfExpression(ife) => {
+                let mut res = vec![std::rc::Rc::make_mut(&mut ife.condition)];
+                res.extend(ife.consequence.expressions_mut());
+                if let Some(alt) = &mut ife.alternative {
+                    res.extend(alt.expressions_mut());
+                }
+                res
+            }
+            Expression::ForExpression(fe) => {
+                let mut res = vec![std::rc::Rc::make_mut(&mut fe.iterable)];
+                res.extend(fe.body.expressions_mut());
+                res
+            }
+            Expression::FunctionLiteral(fl) => fl.body.expressions_mut().collect(),
+            Expression::MacroLiteral(ml) => ml.body.expressions_mut().collect(),
+            Expression::CallExpression(ce) => {
+                let mut res = vec![std::rc::Rc::make_mut(&mut ce.function)];
+                res.extend(ce.arguments.iter_mut());
+                res
+            }
+            Expression::IndexExpression(ie) => vec![
+                std::rc::Rc::make_mut(&mut ie.left),
+                std::rc::Rc::make_mut(&mut ie.index),
+            ],
+            Expression::Assign(ae) => vec![std::rc::Rc::make_mut(&mut ae.value)],
+        }
+    }
+}
+
+#[non_exhaustive]
 #[derive(PartialEq, Eq, Debug, Clone, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub enum Statement {
     LetStatement(LetStatement),
+    LetTupleStatement(LetTupleStatement),
     ReturnStatement(ReturnStatement),
     ExpressionStatement(ExpressionStatement),
+    BreakStatement(BreakStatement),
+    ContinueStatement(ContinueStatement),
+    ImportStatement(ImportStatement),
 }
 
 #[derive(PartialEq, Eq, Debug, Clone, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub struct LetStatement {
-    pub tok: Token, /* the Let token */
+    /// `Token::Let` or `Token::Const`, or `Token::Function` for a
+    /// function declaration, `fn name(x) { ... }`, which is bound like
+    /// `let name = fn(x) { ... };`.
+    pub tok: Token,
     pub name: Identifier,
     pub value: Expression,
+    pub mutable: bool, /* false for `const` */
 }
 
 #[derive(PartialEq, Eq, Debug, Clone, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct LetTupleStatement {
+    pub tok: Token, /* the Let or Const token */
+    pub names: Vec<Identifier>,
+    pub value: Expression,
+    pub mutable: bool, /* false for `const` */
+}
+
+#[derive(PartialEq, Eq, Debug, Clone, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub struct Identifier {
     pub tok: Token, /* the Ident token */
     pub value: std::rc::Rc<str>,
 }
 
 #[derive(PartialEq, Eq, Debug, Clone, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub struct ReturnStatement {
     pub tok: Token, /* the Return token */
     pub value: Expression,
 }
 
 #[derive(PartialEq, Eq, Debug, Clone, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub struct ExpressionStatement {
     pub tok: Token,
     pub expression: Expression,
 }
 
+/// `break;`, which ends the innermost loop, or `break label;`, which ends
+/// the `for` loop labeled `label:` around it, `for` being the only loop.
+/// The parser only allows it inside the body of a loop with that label,
+/// and not in a function in there.
+#[derive(PartialEq, Eq, Debug, Clone, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct BreakStatement {
+    pub tok: Token, /* the Break token */
+    pub label: Option<Identifier>,
+}
+
+/// `continue;`, which goes on with the next turn of the innermost loop,
+/// allowed where `break` is.
+#[derive(PartialEq, Eq, Debug, Clone, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct ContinueStatement {
+    pub tok: Token, /* the Continue token */
+}
+
+/// `import "path";`, which binds the top-level names of the module at
+/// `path` where it is, or `import "path" as name;`, which binds `name` to a
+/// hash of them by name instead.
+#[derive(PartialEq, Eq, Debug, Clone, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct ImportStatement {
+    pub tok: Token, /* the Import token */
+    /// Always a string literal.
+    pub path: Expression,
+    pub alias: Option<Identifier>,
+}
+
+#[non_exhaustive]
 #[derive(PartialEq, Eq, Debug, Clone, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub enum Expression {
     Identifier(Identifier),
     Integer(IntegerLiteral),
+    Float(FloatLiteral),
     String(StringLiteral),
     Array(ArrayLiteral),
+    Tuple(TupleLiteral),
     Boolean(BooleanLiteral),
     PrefixExpression(PrefixExpression),
     InfixExpression(InfixExpression),
     IfExpression(IfExpression),
+    ForExpression(ForExpression),
     FunctionLiteral(FunctionLiteral),
+    MacroLiteral(MacroLiteral),
     CallExpression(CallExpression),
     IndexExpression(IndexExpression),
     Hash(HashLiteral),
+    Assign(AssignExpression),
 }
 
 #[derive(PartialEq, Eq, Debug, Clone, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub struct IntegerLiteral {
     pub tok: Token,
     pub value: i64,
 }
 
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct FloatLiteral {
+    pub tok: Token,
+    pub value: f64,
+}
+
+/* by the bits of the value, which a literal never makes NaN */
+impl PartialEq for FloatLiteral {
+    fn eq(&self, other: &Self) -> bool {
+        self.tok == other.tok && self.value.to_bits() == other.value.to_bits()
+    }
+}
+
+impl Eq for FloatLiteral {}
+
+impl std::hash::Hash for FloatLiteral {
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        self.tok.hash(state);
+        self.value.to_bits().hash(state);
+    }
+}
+
 #[derive(PartialEq, Eq, Debug, Clone, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub struct BooleanLiteral {
     pub tok: Token,
     pub value: bool,
 }
 
 #[derive(PartialEq, Eq, Debug, Clone, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub struct StringLiteral {
     pub tok: Token,
     pub value: std::rc::Rc<str>,
 }
 
 #[derive(PartialEq, Eq, Debug, Clone, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub struct ArrayLiteral {
     pub tok: Token, /* the LBracket token */
     pub elements: Vec<Expression>,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub constant: Constant,
+}
+
+#[derive(PartialEq, Eq, Debug, Clone, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct TupleLiteral {
+    pub tok: Token, /* the LParen token, or the first Comma of `return a, b` */
+    pub elements: Vec<Expression>,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub constant: Constant,
 }
 
 #[derive(PartialEq, Eq, Debug, Clone, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub struct HashLiteral {
     pub tok: Token, /* the LSquirly token */
     pub pairs: Vec<(Expression, Expression)>,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub constant: Constant,
+}
+
+/// Where an array, hash or tuple literal of nothing but literals, like
+/// `[1, -2, {"a": 3}]`, keeps its value once it has been evaluated. Every
+/// copy of the expression shares it, so the literals of a function body or
+/// of a program evaluated again are only made once. Values can't be
+/// changed in place, which makes sharing them safe.
+///
+/// The parser makes them, see [`Constant::of`], and the others are
+/// `Constant::default()`, which keeps nothing. An expression changed in
+/// place loses it. It takes no part in comparing, hashing or printing
+/// expressions.
+#[derive(Clone, Default)]
+pub struct Constant(Option<std::rc::Rc<std::cell::OnceCell<Object>>>);
+
+impl Constant {
+    /// One that keeps the value when all of the `children` of the literal
+    /// are literals, and one that doesn't otherwise.
+    pub fn of<'a>(children: impl IntoIterator<Item = &'a Expression>) -> Self {
+        let constant = children.into_iter().all(Expression::is_literal);
+        Constant(constant.then(Default::default))
+    }
+
+    /* where the value goes, if it's kept */
+    pub(crate) fn value(&self) -> Option<&std::cell::OnceCell<Object>> {
+        self.0.as_deref()
+    }
+}
+
+impl PartialEq for Constant {
+    fn eq(&self, _: &Self) -> bool {
+        true
+    }
+}
+
+impl Eq for Constant {}
+
+impl std::hash::Hash for Constant {
+    fn hash<H: std::hash::Hasher>(&self, _: &mut H) {}
+}
+
+impl std::fmt::Debug for Constant {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("Constant")
+    }
 }
 
+#[non_exhaustive]
 #[derive(PartialEq, Eq, Debug, Clone, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub enum PrefixOperator {
     Bang,
     Minus,
 }
 
 #[derive(PartialEq, Eq, Debug, Clone, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub struct PrefixExpression {
     pub tok: Token,
     pub operator: PrefixOperator,
     pub right: std::rc::Rc<Expression>,
 }
 
+#[non_exhaustive]
 #[derive(PartialEq, Eq, Debug, Clone, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub enum InfixOperator {
     Plus,
     Minus,
@@ -113,7 +463,18 @@ pub enum InfixOperator {
     NotEq,
 }
 
+/// `name = value`, which gives `name`'s binding the value and evaluates to
+/// it.
+#[derive(PartialEq, Eq, Debug, Clone, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct AssignExpression {
+    pub tok: Token, /* the Assign token */
+    pub name: Identifier,
+    pub value: std::rc::Rc<Expression>,
+}
+
 #[derive(PartialEq, Eq, Debug, Clone, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub struct InfixExpression {
     pub tok: Token,
     pub left: std::rc::Rc<Expression>,
@@ -122,6 +483,7 @@ pub struct InfixExpression {
 }
 
 #[derive(PartialEq, Eq, Debug, Clone, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub struct IfExpression {
     pub tok: Token, /* the If token */
     pub condition: std::rc::Rc<Expression>,
@@ -129,13 +491,31 @@ pub struct IfExpression {
     pub alternative: Option<BlockStatement>,
 }
 
+/// `for (x in iterable) { ... }`, or `for (k, v in iterable) { ... }`,
+/// which runs its body once for each element of an array, key of a hash,
+/// character of a string or integer of a range. With two names the first
+/// is the key of a hash and the index of anything else, and the second
+/// the value. A loop evaluates to null.
 #[derive(PartialEq, Eq, Debug, Clone, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct ForExpression {
+    pub tok: Token, /* the For token */
+    /* from `label: for (...)`, for a `break label;` in the body */
+    pub label: Option<Identifier>,
+    pub names: Vec<Identifier>, /* one or two */
+    pub iterable: std::rc::Rc<Expression>,
+    pub body: BlockStatement,
+}
+
+#[derive(PartialEq, Eq, Debug, Clone, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub struct BlockStatement {
     pub tok: Token, /* the { token */
     pub statements: Vec<Statement>,
 }
 
 #[derive(PartialEq, Eq, Debug, Clone, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub struct FunctionLiteral {
     pub tok: Token, /* the Fn token */
     pub parameters: Vec<Identifier>,
@@ -143,6 +523,15 @@ pub struct FunctionLiteral {
 }
 
 #[derive(PartialEq, Eq, Debug, Clone, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct MacroLiteral {
+    pub tok: Token, /* the Macro token */
+    pub parameters: Vec<Identifier>,
+    pub body: BlockStatement,
+}
+
+#[derive(PartialEq, Eq, Debug, Clone, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub struct CallExpression {
     pub tok: Token, /* the LParen token */
     pub function: std::rc::Rc<Expression>,
@@ -150,8 +539,9 @@ pub struct CallExpression {
 }
 
 #[derive(PartialEq, Eq, Debug, Clone, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub struct IndexExpression {
-    pub tok: Token, /* the LBracket token */
+    pub tok: Token, /* the LBracket token, or Dot for `left.name` */
     pub left: std::rc::Rc<Expression>,
     pub index: std::rc::Rc<Expression>,
 }
@@ -161,40 +551,72 @@ impl Node for Program {
         todo!()
     }
 
+    /// One statement per line. Expression statements get the `;` they
+    /// need to not run into the next line, so the output parses back into
+    /// the same statements.
     fn string(&self) -> String {
         let mut res = String::new();
-        for stmt in self.statements.iter() {
-            let s = stmt.string();
-            res.push_str(&s);
+        for (i, stmt) in self.statements.iter().enumerate() {
+            if i != 0 {
+                res.push('\n');
+            }
+            res.push_str(&stmt.string());
+            let last = i == self.statements.len() - 1;
+            if !last && matches!(stmt, Statement::ExpressionStatement(_)) {
+                res.push(';');
+            }
         }
         res
     }
 }
 
+impl std::fmt::Display for Program {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.string())
+    }
+}
+
 impl Node for Statement {
     fn token_literal(&self) -> String {
         match self {
             Statement::LetStatement(ls) => ls.token_literal(),
+            Statement::LetTupleStatement(lts) => lts.token_literal(),
             Statement::ReturnStatement(rs) => rs.token_literal(),
             Statement::ExpressionStatement(es) => es.token_literal(),
+            Statement::BreakStatement(bs) => bs.token_literal(),
+            Statement::ContinueStatement(cs) => cs.token_literal(),
+            Statement::ImportStatement(is) => is.token_literal(),
         }
     }
 
     fn string(&self) -> String {
         match self {
             Statement::LetStatement(ls) => ls.string(),
+            Statement::LetTupleStatement(lts) => lts.string(),
             Statement::ReturnStatement(rs) => rs.string(),
             Statement::ExpressionStatement(es) => es.string(),
+            Statement::BreakStatement(bs) => bs.string(),
+            Statement::ContinueStatement(cs) => cs.string(),
+            Statement::ImportStatement(is) => is.string(),
         }
     }
 }
 
 impl Node for LetStatement {
     fn token_literal(&self) -> String {
-        "let".to_owned()
+        match self.tok {
+            Token::Function => "fn",
+            _ if self.mutable => "let",
+            _ => "const",
+        }
+        .to_owned()
     }
 
     fn string(&self) -> String {
+        if let (Token::Function, Expression::FunctionLiteral(func)) = (&self.tok, &self.value) {
+            // the literal's `fn(x) {...}` with the name after the `fn`
+            return format!("fn {}{}", self.name.string(), &func.string()[2..]);
+        }
         let mut res = String::new();
         res.push_str(&self.token_literal());
         res.push(' ');
@@ -206,6 +628,28 @@ impl Node for LetStatement {
     }
 }
 
+impl Node for LetTupleStatement {
+    fn token_literal(&self) -> String {
+        if self.mutable { "let" } else { "const" }.to_owned()
+    }
+
+    fn string(&self) -> String {
+        let mut res = String::new();
+        res.push_str(&self.token_literal());
+        res.push(' ');
+        for (i, name) in self.names.iter().enumerate() {
+            res.push_str(&name.string());
+            if i != self.names.len() - 1 {
+                res.push_str(", ");
+            }
+        }
+        res.push_str(" = ");
+        res.push_str(&self.value.string());
+        res.push(';');
+        res
+    }
+}
+
 impl Node for Identifier {
     fn token_literal(&self) -> String {
         if let Token::Ident(v) = &self.tok {
@@ -235,6 +679,45 @@ impl Node for ReturnStatement {
     }
 }
 
+impl Node for BreakStatement {
+    fn token_literal(&self) -> String {
+        "break".to_owned()
+    }
+
+    fn string(&self) -> String {
+        match &self.label {
+            Some(label) => format!("break {};", label.value),
+            None => "break;".to_owned(),
+        }
+    }
+}
+
+impl Node for ContinueStatement {
+    fn token_literal(&self) -> String {
+        "continue".to_owned()
+    }
+
+    fn string(&self) -> String {
+        "continue;".to_owned()
+    }
+}
+
+impl Node for ImportStatement {
+    fn token_literal(&self) -> String {
+        "import".to_owned()
+    }
+
+    fn string(&self) -> String {
+        let mut res = format!("import {}", self.path.string());
+        if let Some(alias) = &self.alias {
+            res.push_str(" as ");
+            res.push_str(&alias.value);
+        }
+        res.push(';');
+        res
+    }
+}
+
 impl Node for ExpressionStatement {
     fn token_literal(&self) -> String {
         todo!()
@@ -246,11 +729,28 @@ impl Node for ExpressionStatement {
 }
 
 impl Node for IntegerLiteral {
+    /// The literal as written in the source, so `0xFF` stays `0xFF`.
     fn token_literal(&self) -> String {
-        todo!()
+        match &self.tok {
+            Token::Int(lit) => lit.to_string(),
+            _ => self.value.to_string(),
+        }
+    }
+    fn string(&self) -> String {
+        self.token_literal()
+    }
+}
+
+impl Node for FloatLiteral {
+    /// The literal as written in the source, so `5f` stays `5f`.
+    fn token_literal(&self) -> String {
+        match &self.tok {
+            Token::Float(lit) => lit.to_string(),
+            _ => format!("{:?}", self.value),
+        }
     }
     fn string(&self) -> String {
-        self.value.to_string()
+        self.token_literal()
     }
 }
 
@@ -276,7 +776,7 @@ impl Node for StringLiteral {
     }
 
     fn string(&self) -> String {
-        self.value.to_string()
+        crate::util::quote_string(&self.value)
     }
 }
 
@@ -298,6 +798,24 @@ impl Node for ArrayLiteral {
     }
 }
 
+impl Node for TupleLiteral {
+    fn token_literal(&self) -> String {
+        "(".to_owned()
+    }
+    fn string(&self) -> String {
+        let mut res = String::new();
+        res.push('(');
+        for (i, exp) in self.elements.iter().enumerate() {
+            res.push_str(&exp.string());
+            if i != self.elements.len() - 1 {
+                res.push_str(", ");
+            }
+        }
+        res.push(')');
+        res
+    }
+}
+
 impl Node for PrefixExpression {
     fn token_literal(&self) -> String {
         todo!()
@@ -347,34 +865,68 @@ impl Node for IfExpression {
     }
     fn string(&self) -> String {
         let mut res = String::new();
-        res.push_str("if");
+        res.push_str("if (");
         res.push_str(&self.condition.string());
-        res.push(' ');
-        res.push_str(&self.consequence.string());
-        match &self.alternative {
-            Some(alt) => {
-                res.push_str("else ");
-                res.push_str(&alt.string());
-            }
-            None => {}
+        res.push_str(") ");
+        res.push_str(&braced(&self.consequence));
+        if let Some(alt) = &self.alternative {
+            res.push_str(" else ");
+            res.push_str(&braced(alt));
         };
         res
     }
 }
 
+impl Node for ForExpression {
+    fn token_literal(&self) -> String {
+        "for".to_owned()
+    }
+    fn string(&self) -> String {
+        let names: Vec<String> = self.names.iter().map(|name| name.string()).collect();
+        let label = match &self.label {
+            Some(label) => format!("{}: ", label.value),
+            None => String::new(),
+        };
+        format!(
+            "{}for ({} in {}) {}",
+            label,
+            names.join(", "),
+            self.iterable.string(),
+            braced(&self.body)
+        )
+    }
+}
+
 impl Node for BlockStatement {
     fn token_literal(&self) -> String {
         "{".to_owned()
     }
+    /// The statements without the surrounding braces. Expression
+    /// statements are separated with a `;` so the output parses back into
+    /// the same statements.
     fn string(&self) -> String {
         let mut res = String::new();
-        for stmt in self.statements.iter() {
-            res.push_str(&stmt.string())
+        for (i, stmt) in self.statements.iter().enumerate() {
+            if i != 0 {
+                res.push(' ');
+            }
+            res.push_str(&stmt.string());
+            let last = i == self.statements.len() - 1;
+            if !last && matches!(stmt, Statement::ExpressionStatement(_)) {
+                res.push(';');
+            }
         }
         res
     }
 }
 
+fn braced(block: &BlockStatement) -> String {
+    if block.statements.is_empty() {
+        return "{}".to_owned();
+    }
+    format!("{{ {} }}", block.string())
+}
+
 impl Node for Expression {
     fn token_literal(&self) -> String {
         todo!()
@@ -384,20 +936,35 @@ impl Node for Expression {
         match self {
             Expression::Identifier(i) => i.string(),
             Expression::Integer(i) => i.string(),
+            Expression::Float(f) => f.string(),
             Expression::Boolean(b) => b.string(),
             Expression::String(s) => s.string(),
             Expression::Array(a) => a.string(),
+            Expression::Tuple(t) => t.string(),
             Expression::PrefixExpression(pe) => pe.string(),
             Expression::InfixExpression(ie) => ie.string(),
             Expression::IfExpression(ife) => ife.string(),
+            Expression::ForExpression(fe) => fe.string(),
             Expression::FunctionLiteral(fne) => fne.string(),
+            Expression::MacroLiteral(ml) => ml.string(),
             Expression::CallExpression(call) => call.string(),
             Expression::IndexExpression(idx) => idx.string(),
             Expression::Hash(hash) => hash.string(),
+            Expression::Assign(ae) => ae.string(),
         }
     }
 }
 
+impl Node for AssignExpression {
+    fn token_literal(&self) -> String {
+        "=".to_owned()
+    }
+
+    fn string(&self) -> String {
+        format!("({} = {})", self.name.string(), self.value.string())
+    }
+}
+
 impl Node for FunctionLiteral {
     fn token_literal(&self) -> String {
         "fn".to_owned()
@@ -409,12 +976,33 @@ impl Node for FunctionLiteral {
         res.push('(');
         for (i, ident) in self.parameters.iter().enumerate() {
             res.push_str(&ident.string());
-            if i != self.parameters.len() {
+            if i != self.parameters.len() - 1 {
                 res.push_str(", ");
             }
         }
         res.push_str(") ");
-        res.push_str(&self.body.string());
+        res.push_str(&braced(&self.body));
+        res
+    }
+}
+
+impl Node for MacroLiteral {
+    fn token_literal(&self) -> String {
+        "macro".to_owned()
+    }
+
+    fn string(&self) -> String {
+        let mut res = String::new();
+        res.push_str(&self.token_literal());
+        res.push('(');
+        for (i, ident) in self.parameters.iter().enumerate() {
+            res.push_str(&ident.string());
+            if i != self.parameters.len() - 1 {
+                res.push_str(", ");
+            }
+        }
+        res.push_str(") ");
+        res.push_str(&braced(&self.body));
         res
     }
 }
@@ -448,9 +1036,18 @@ impl Node for IndexExpression {
         let mut res = String::new();
         res.push('(');
         res.push_str(&self.left.string());
-        res.push('[');
-        res.push_str(&self.index.string());
-        res.push_str("])");
+        match (&self.tok, self.index.as_ref()) {
+            (Token::Dot, Expression::String(name)) => {
+                res.push('.');
+                res.push_str(&name.value);
+            }
+            _ => {
+                res.push('[');
+                res.push_str(&self.index.string());
+                res.push(']');
+            }
+        }
+        res.push(')');
         res
     }
 }
@@ -476,17 +1073,128 @@ impl Node for HashLiteral {
     }
 }
 
-impl ToString for InfixOperator {
-    fn to_string(&self) -> String {
-        match self {
-            InfixOperator::Plus => "+".to_owned(),
-            InfixOperator::Minus => "-".to_owned(),
-            InfixOperator::Asterisk => "*".to_owned(),
-            InfixOperator::Slash => "/".to_owned(),
-            InfixOperator::Lt => "<".to_owned(),
-            InfixOperator::Gt => ">".to_owned(),
-            InfixOperator::Eq => "==".to_owned(),
-            InfixOperator::NotEq => "!=".to_owned(),
+impl std::fmt::Display for InfixOperator {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let s = match self {
+            InfixOperator::Plus => "+",
+            InfixOperator::Minus => "-",
+            InfixOperator::Asterisk => "*",
+            InfixOperator::Slash => "/",
+            InfixOperator::Lt => "<",
+            InfixOperator::Gt => ">",
+            InfixOperator::Eq => "==",
+            InfixOperator::NotEq => "!=",
+        };
+        f.write_str(s)
+    }
+}
+
+impl std::fmt::Display for PrefixOperator {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let s = match self {
+            PrefixOperator::Bang => "!",
+            PrefixOperator::Minus => "-",
+        };
+        f.write_str(s)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::ast::{Expression, Node, Program};
+    use crate::lexer::Lexer;
+    use crate::parser::Parser;
+
+    fn parse(input: &str) -> Program {
+        let mut p = Parser::new(Lexer::new(input));
+        let program = p.parse();
+        assert!(p.get_errors().is_empty(), "{:?}", p.get_errors());
+        program
+    }
+
+    #[test]
+    fn test_program_string_parses_back() {
+        let tests = [
+            ("3 + 4; -5 * 5", "(3 + 4);\n((-5) * 5)"),
+            ("a; b", "a;\nb"),
+            ("f\n(1)", "f(1)"),
+            ("let x = 1; x", "let x = 1;\nx"),
+            ("x; return x", "x;\nreturn x;"),
+            ("[1] [0]", "([1][0])"),
+            ("[1]; [0]", "[1];\n[0]"),
+            ("fn() { 1; 2 }; (3, 4)", "fn() { 1; 2 };\n(3, 4)"),
+        ];
+        for (input, exp) in tests {
+            assert_eq!(parse(input).to_string(), exp, "printing {:?}", input);
+            assert_eq!(parse(exp).to_string(), exp, "reparsing {:?}", exp);
+        }
+    }
+
+    #[test]
+    fn test_number_token_literals() {
+        let tests = ["5", "0xFF", "5i", "2.5", "5f"];
+        for input in tests {
+            let mut program = parse(input);
+            let literal = match program.statements[0].expression_mut() {
+                Some(Expression::Integer(int)) => int.token_literal(),
+                Some(Expression::Float(float)) => float.token_literal(),
+                exp => panic!("not a number literal: {:?}", exp),
+            };
+            assert_eq!(literal, input);
         }
     }
+
+    #[test]
+    fn test_replace_expr_at() {
+        let x = || parse("x").statements[0].expression_mut().unwrap().clone();
+
+        let mut program = parse("1 + 5");
+        let old = program.replace_expr_at(&[0, 1], x());
+        assert_eq!(old.map(|exp| exp.string()).as_deref(), Some("5"));
+        assert_eq!(program.string(), "(1 + x)");
+
+        let tests = [
+            ("let a = f(1, 2);", vec![0, 2], "let a = f(1, x);"),
+            (
+                "if (c) { a; b } else { d }",
+                vec![0, 3],
+                "if (c) { a; b } else { x }",
+            ),
+            ("a; [1, {2: 3}]", vec![1, 1, 1], "a;\n[1, {2:x}]"),
+            ("fn(y) { -y }", vec![0, 0, 0], "fn(y) { (-x) }"),
+            ("return m[0];", vec![0, 1], "return (m[x]);"),
+            ("1 + 5", vec![0], "x"),
+        ];
+        for (input, path, exp) in tests {
+            let mut program = parse(input);
+            assert!(program.replace_expr_at(&path, x()).is_some(), "{}", input);
+            assert_eq!(program.string(), exp, "{}", input);
+        }
+
+        for path in [&[][..], &[1], &[0, 2], &[0, 0, 0]] {
+            let mut program = parse("1 + 5");
+            assert_eq!(program.replace_expr_at(path, x()), None, "{:?}", path);
+            assert_eq!(program.string(), "(1 + 5)");
+        }
+    }
+
+    #[test]
+    fn test_let_bindings() {
+        let input = "let x = 1;
+const PI = 3;
+let add = fn(a, b) { let sum = a + b; sum };
+if (x) { let inner = 2; }
+let q, r = (7 / 2, 1);
+add(x, PI);
+let x = 2;";
+        let mut p = Parser::new(Lexer::new(input));
+        let program = p.parse();
+        assert!(p.get_errors().is_empty(), "{:?}", p.get_errors());
+        let names: Vec<&str> = program
+            .let_bindings()
+            .iter()
+            .map(|ident| ident.value.as_ref())
+            .collect();
+        assert_eq!(names, ["x", "PI", "add", "q", "r", "x"]);
+    }
 }
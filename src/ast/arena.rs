@@ -0,0 +1,339 @@
+//! A borrowed variant of the AST whose nodes live in a [`bumpalo::Bump`].
+//!
+//! Built by [`Parser::parse_in`](crate::parser::Parser::parse_in). Every node
+//! borrows from the arena, so the borrow checker stops the tree from
+//! outliving it. Nodes only hold plain data (`&'a str` instead of
+//! `Rc<str>`, no [`Token`]s), which matters because the arena frees its
+//! memory without running destructors: nothing allocated in it can leak.
+//!
+//! Use [`Program::to_owned_program`] to get an owned [`ast::Program`] that
+//! is independent of the arena and can be kept after it is dropped.
+
+use crate::ast::{self, InfixOperator, PrefixOperator};
+use crate::token::Token;
+
+#[derive(Debug, Clone)]
+pub struct Program<'a> {
+    pub statements: &'a [Statement<'a>],
+}
+
+#[derive(Debug, Clone)]
+pub enum Statement<'a> {
+    /* the bool is false for `const` */
+    Let(&'a str, Expression<'a>, bool),
+    LetTuple(&'a [&'a str], Expression<'a>, bool),
+    /* `fn name(parameters) { body }` */
+    Function(&'a str, &'a [&'a str], &'a [Statement<'a>]),
+    Return(Expression<'a>),
+    /* `break label;` */
+    Break(Option<&'a str>),
+    Continue,
+    /* `import "path" as alias;` */
+    Import(&'a str, Option<&'a str>),
+    Expression(Expression<'a>),
+}
+
+#[derive(Debug, Clone)]
+pub enum Expression<'a> {
+    Identifier(&'a str),
+    /* the literal as written, and its value */
+    Integer(&'a str, i64),
+    Float(&'a str, f64),
+    String(&'a str),
+    Boolean(bool),
+    Array(&'a [Expression<'a>]),
+    Tuple(&'a [Expression<'a>]),
+    Hash(&'a [(Expression<'a>, Expression<'a>)]),
+    Prefix(PrefixOperator, &'a Expression<'a>),
+    Infix(&'a Expression<'a>, InfixOperator, &'a Expression<'a>),
+    /* `name = value` */
+    Assign(&'a str, &'a Expression<'a>),
+    If(
+        &'a Expression<'a>,
+        &'a [Statement<'a>],
+        Option<&'a [Statement<'a>]>,
+    ),
+    /* `label: for (names in iterable) { body }` */
+    For(
+        Option<&'a str>,
+        &'a [&'a str],
+        &'a Expression<'a>,
+        &'a [Statement<'a>],
+    ),
+    Function(&'a [&'a str], &'a [Statement<'a>]),
+    Macro(&'a [&'a str], &'a [Statement<'a>]),
+    Call(&'a Expression<'a>, &'a [Expression<'a>]),
+    Index(&'a Expression<'a>, &'a Expression<'a>),
+    /* `left.name` */
+    Member(&'a Expression<'a>, &'a str),
+}
+
+impl Program<'_> {
+    /// Copies the tree out of the arena into the regular owned AST.
+    ///
+    /// The result is equal to what [`Parser::parse`](crate::parser::Parser::parse)
+    /// produces for the same source, except that the `tok` of an
+    /// [`ast::ExpressionStatement`] is rebuilt from its leftmost operand, so a
+    /// statement starting with `(` gets the token after the parenthesis, and
+    /// one starting with a `|>` pipe the token of the function piped into.
+    pub fn to_owned_program(&self) -> ast::Program {
+        ast::Program {
+            statements: statements_to_owned(self.statements),
+        }
+    }
+}
+
+fn statements_to_owned(statements: &[Statement]) -> Vec<ast::Statement> {
+    statements.iter().map(|s| s.to_owned_statement()).collect()
+}
+
+fn identifier(name: &str) -> ast::Identifier {
+    let value: std::rc::Rc<str> = name.into();
+    ast::Identifier {
+        tok: Token::Ident(value.clone()),
+        value,
+    }
+}
+
+fn let_token(mutable: bool) -> Token {
+    if mutable {
+        Token::Let
+    } else {
+        Token::Const
+    }
+}
+
+fn block(statements: &[Statement]) -> ast::BlockStatement {
+    ast::BlockStatement {
+        tok: Token::LSquirly,
+        statements: statements_to_owned(statements),
+    }
+}
+
+impl Statement<'_> {
+    pub fn to_owned_statement(&self) -> ast::Statement {
+        match self {
+            Statement::Let(name, value, mutable) => {
+                ast::Statement::LetStatement(ast::LetStatement {
+                    tok: let_token(*mutable),
+                    name: identifier(name),
+                    value: value.to_owned_expression(),
+                    mutable: *mutable,
+                })
+            }
+            Statement::LetTuple(names, value, mutable) => {
+                ast::Statement::LetTupleStatement(ast::LetTupleStatement {
+                    tok: let_token(*mutable),
+                    names: names.iter().map(|n| identifier(n)).collect(),
+                    value: value.to_owned_expression(),
+                    mutable: *mutable,
+                })
+            }
+            Statement::Function(name, parameters, body) => {
+                ast::Statement::LetStatement(ast::LetStatement {
+                    tok: Token::Function,
+                    name: identifier(name),
+                    value: ast::Expression::FunctionLiteral(ast::FunctionLiteral {
+                        tok: Token::Function,
+                        parameters: parameters.iter().map(|p| identifier(p)).collect(),
+                        body: block(body),
+                    }),
+                    mutable: true,
+                })
+            }
+            Statement::Return(value) => ast::Statement::ReturnStatement(ast::ReturnStatement {
+                tok: Token::Return,
+                value: value.to_owned_expression(),
+            }),
+            Statement::Break(label) => ast::Statement::BreakStatement(ast::BreakStatement {
+                tok: Token::Break,
+                label: label.map(identifier),
+            }),
+            Statement::Continue => ast::Statement::ContinueStatement(ast::ContinueStatement {
+                tok: Token::Continue,
+            }),
+            Statement::Import(path, alias) => {
+                ast::Statement::ImportStatement(ast::ImportStatement {
+                    tok: Token::Import,
+                    path: Expression::String(path).to_owned_expression(),
+                    alias: alias.map(identifier),
+                })
+            }
+            Statement::Expression(exp) => {
+                ast::Statement::ExpressionStatement(ast::ExpressionStatement {
+                    tok: exp.leftmost_token(),
+                    expression: exp.to_owned_expression(),
+                })
+            }
+        }
+    }
+}
+
+impl Expression<'_> {
+    fn token(&self) -> Token {
+        match self {
+            Expression::Identifier(name) => Token::Ident((*name).into()),
+            Expression::Integer(lit, _) => Token::Int((*lit).into()),
+            Expression::Float(lit, _) => Token::Float((*lit).into()),
+            Expression::String(s) => Token::String((*s).into()),
+            Expression::Boolean(true) => Token::True,
+            Expression::Boolean(false) => Token::False,
+            Expression::Array(_) | Expression::Index(..) => Token::LBracket,
+            Expression::Tuple(_) | Expression::Call(..) => Token::LParen,
+            Expression::Member(..) => Token::Dot,
+            Expression::Hash(_) => Token::LSquirly,
+            Expression::Prefix(PrefixOperator::Bang, _) => Token::Bang,
+            Expression::Prefix(PrefixOperator::Minus, _) => Token::Minus,
+            Expression::Infix(_, op, _) => match op {
+                InfixOperator::Plus => Token::Plus,
+                InfixOperator::Minus => Token::Minus,
+                InfixOperator::Asterisk => Token::Asterisk,
+                InfixOperator::Slash => Token::Slash,
+                InfixOperator::Lt => Token::Lt,
+                InfixOperator::Gt => Token::Gt,
+                InfixOperator::Eq => Token::Eq,
+                InfixOperator::NotEq => Token::NotEq,
+            },
+            Expression::Assign(..) => Token::Assign,
+            Expression::If(..) => Token::If,
+            Expression::For(..) => Token::For,
+            Expression::Function(..) => Token::Function,
+            Expression::Macro(..) => Token::Macro,
+        }
+    }
+
+    fn leftmost_token(&self) -> Token {
+        match self {
+            Expression::Infix(left, _, _)
+            | Expression::Call(left, _)
+            | Expression::Index(left, _)
+            | Expression::Member(left, _) => left.leftmost_token(),
+            Expression::Assign(name, _) => Token::Ident((*name).into()),
+            _ => self.token(),
+        }
+    }
+
+    pub fn to_owned_expression(&self) -> ast::Expression {
+        let tok = self.token();
+        let rc = |e: &Expression| std::rc::Rc::new(e.to_owned_expression());
+        let list = |l: &[Expression]| l.iter().map(|e| e.to_owned_expression()).collect();
+        match self {
+            Expression::Identifier(name) => ast::Expression::Identifier(identifier(name)),
+            Expression::Integer(_, value) => {
+                ast::Expression::Integer(ast::IntegerLiteral { tok, value: *value })
+            }
+            Expression::Float(_, value) => {
+                ast::Expression::Float(ast::FloatLiteral { tok, value: *value })
+            }
+            Expression::String(s) => ast::Expression::String(ast::StringLiteral {
+                tok,
+                value: (*s).into(),
+            }),
+            Expression::Boolean(value) => {
+                ast::Expression::Boolean(ast::BooleanLiteral { tok, value: *value })
+            }
+            Expression::Array(elements) => {
+                let elements = list(elements);
+                ast::Expression::Array(ast::ArrayLiteral {
+                    tok,
+                    constant: ast::Constant::of(&elements),
+                    elements,
+                })
+            }
+            Expression::Tuple(elements) => {
+                let elements = list(elements);
+                ast::Expression::Tuple(ast::TupleLiteral {
+                    tok,
+                    constant: ast::Constant::of(&elements),
+                    elements,
+                })
+            }
+            Expression::Hash(pairs) => {
+                let pairs: Vec<_> = pairs
+                    .iter()
+                    .map(|(k, v)| (k.to_owned_expression(), v.to_owned_expression()))
+                    .collect();
+                ast::Expression::Hash(ast::HashLiteral {
+                    tok,
+                    constant: ast::Constant::of(pairs.iter().flat_map(|(k, v)| [k, v])),
+                    pairs,
+                })
+            }
+            Expression::Prefix(operator, right) => {
+                ast::Expression::PrefixExpression(ast::PrefixExpression {
+                    tok,
+                    operator: operator.clone(),
+                    right: rc(right),
+                })
+            }
+            Expression::Infix(left, operator, right) => {
+                ast::Expression::InfixExpression(ast::InfixExpression {
+                    tok,
+                    left: rc(left),
+                    operator: operator.clone(),
+                    right: rc(right),
+                })
+            }
+            Expression::Assign(name, value) => ast::Expression::Assign(ast::AssignExpression {
+                tok,
+                name: identifier(name),
+                value: rc(value),
+            }),
+            Expression::If(condition, consequence, alternative) => {
+                ast::Expression::IfExpression(ast::IfExpression {
+                    tok,
+                    condition: rc(condition),
+                    consequence: block(consequence),
+                    alternative: alternative.map(block),
+                })
+            }
+            Expression::For(label, names, iterable, body) => {
+                ast::Expression::ForExpression(ast::ForExpression {
+                    tok,
+                    label: label.map(identifier),
+                    names: names.iter().map(|n| identifier(n)).collect(),
+                    iterable: rc(iterable),
+                    body: block(body),
+                })
+            }
+            Expression::Function(parameters, body) => {
+                ast::Expression::FunctionLiteral(ast::FunctionLiteral {
+                    tok,
+                    parameters: parameters.iter().map(|p| identifier(p)).collect(),
+                    body: block(body),
+                })
+            }
+            Expression::Macro(parameters, body) => {
+                ast::Expression::MacroLiteral(ast::MacroLiteral {
+                    tok,
+                    parameters: parameters.iter().map(|p| identifier(p)).collect(),
+                    body: block(body),
+                })
+            }
+            Expression::Call(function, arguments) => {
+                ast::Expression::CallExpression(ast::CallExpression {
+                    tok,
+                    function: rc(function),
+                    arguments: list(arguments),
+                })
+            }
+            Expression::Index(left, index) => {
+                ast::Expression::IndexExpression(ast::IndexExpression {
+                    tok,
+                    left: rc(left),
+                    index: rc(index),
+                })
+            }
+            Expression::Member(left, name) => {
+                ast::Expression::IndexExpression(ast::IndexExpression {
+                    tok,
+                    left: rc(left),
+                    index: std::rc::Rc::new(ast::Expression::String(ast::StringLiteral {
+                        tok: Token::String((*name).into()),
+                        value: (*name).into(),
+                    })),
+                })
+            }
+        }
+    }
+}
@@ -1,128 +1,1316 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+
 use crate::{
+    environment::Environment,
     evaluator,
-    object::{Array, Object, ObjectTrait},
+    iter::{self, Filtered, Iter, Mapped, Taken},
+    modules::{FsResolver, ModuleResolver, Modules},
+    object::{
+        Array, EvalError, Function, Memo, Object, ObjectTrait, ObjectType, Range, Tuple, TypeSet,
+    },
+    stdlib::{self, StdlibConfig},
 };
 
-pub fn len(args: &Vec<Object>) -> Object {
-    if args.len() != 1 {
-        return Object::Error(format!(
-            "wrong number of arguments. got={}, want=1",
-            args.len()
-        ));
+thread_local! {
+    /* where `print` writes while `capture_output` runs */
+    static OUTPUT: RefCell<Option<String>> = const { RefCell::new(None) };
+    /* what `args`, `env`, the file, time and random builtins see while
+     * `with_host` runs */
+    static HOST: RefCell<Option<Host>> = const { RefCell::new(None) };
+}
+
+/// Runs `f` and returns what `print` printed meanwhile, instead of writing
+/// it to stdout. For embedders that have no stdout, like the browser.
+pub fn capture_output<R>(f: impl FnOnce() -> R) -> (R, String) {
+    let outer = OUTPUT.with(|out| out.replace(Some(String::new())));
+    let res = f();
+    let captured = OUTPUT.with(|out| out.replace(outer));
+    (res, captured.unwrap_or_default())
+}
+
+/// What a script can learn about how it was started, through the `args()`
+/// and `env(name)` builtins while [`with_host`] runs it, what else it may
+/// do, and where its time and random numbers come from. Without a host a
+/// script has no arguments, sees no environment variables, is granted no
+/// capabilities and uses the system clock and a randomly seeded
+/// [`SeededRng`].
+pub struct Host {
+    args: Vec<String>,
+    env_var: EnvLookup,
+    capabilities: Capabilities,
+    clock: Box<dyn Clock>,
+    rng: Box<dyn Rng>,
+    int_overflow: IntOverflow,
+    resolver: Rc<dyn ModuleResolver>,
+    modules: Rc<RefCell<Modules>>,
+    stdlib: StdlibConfig,
+    /* the bindings of the prelude, if scripts see them */
+    prelude: Option<Environment>,
+    max_call_depth: usize,
+    fuel: Option<u64>,
+}
+
+/// What a script is allowed to do beyond computing, all off by default
+/// so untrusted code can be run with [`Host::new`]. A builtin that needs a
+/// capability that isn't granted fails with
+/// [`EvalError::CapabilityNotGranted`].
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Capabilities {
+    /// `read_file(path)`, `read_lines(path)` and `write_file(path, contents)`.
+    pub fs: bool,
+    /// `sleep(ms)`, which blocks the thread running the script.
+    pub sleep: bool,
+    /// `eval(code)`, which runs code the script made up as it went.
+    pub eval: bool,
+}
+
+/// How deeply calls nest without a [`Host::with_max_call_depth`]. That
+/// many fit in the 8 MiB stack of the main thread in a release build. A
+/// debug build takes up to 32 KiB per call, so it needs a thread with a
+/// stack of 32 MiB or more, which is what the command line runs on.
+pub const DEFAULT_MAX_CALL_DEPTH: usize = 1000;
+
+/// What integer arithmetic gives when its result doesn't fit an integer,
+/// like `9223372036854775807 + 1`. Negating the smallest integer and
+/// dividing it by `-1` overflow too. Floats are never affected.
+#[non_exhaustive]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum IntOverflow {
+    /// Fails with [`EvalError::IntegerOverflow`].
+    #[default]
+    Checked,
+    /// Wraps around, so the largest integer plus one is the smallest.
+    Wrapping,
+    /// Clamps to the largest or smallest integer.
+    Saturating,
+}
+
+/// Where `clock()` gets the time, and how `sleep(ms)` waits.
+pub trait Clock {
+    /// Milliseconds since the Unix epoch.
+    fn now_millis(&self) -> i64;
+
+    fn sleep(&self, millis: u64);
+}
+
+/// The time of the system, sleeping the thread.
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now_millis(&self) -> i64 {
+        let since_epoch = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap_or_default();
+        since_epoch.as_millis() as i64
     }
-    let arg = &args[0];
-    match arg {
-        Object::String(v) => Object::Integer(v.len() as i64),
-        Object::Array(v) => Object::Integer(v.elements.len() as i64),
-        _ => Object::Error(format!(
-            "argument to `len` not supported, got {}",
-            arg.type_string()
-        )),
-    }
-}
-
-pub fn first(args: &Vec<Object>) -> Object {
-    if args.len() != 1 {
-        return Object::Error(format!(
-            "wrong number of arguments. got={}, want=1",
-            args.len()
-        ));
-    }
-    let arg = &args[0];
-    match arg {
-        Object::Array(v) => {
-            if v.elements.len() > 0 {
-                return v.elements[0].clone();
-            } else {
-                return evaluator::NULL;
-            }
+
+    fn sleep(&self, millis: u64) {
+        std::thread::sleep(std::time::Duration::from_millis(millis));
+    }
+}
+
+/// Where `rand()` and `rand_int(lo, hi)` get their random numbers.
+pub trait Rng {
+    /// The next of a sequence of uniformly distributed numbers.
+    fn next_u64(&mut self) -> u64;
+}
+
+/// A small generator (SplitMix64) that gives the same numbers for the
+/// same seed, for reproducible runs. Not for anything that needs to be
+/// unpredictable.
+pub struct SeededRng {
+    state: u64,
+}
+
+impl SeededRng {
+    pub fn new(seed: u64) -> Self {
+        SeededRng { state: seed }
+    }
+
+    /// Seeded differently every time.
+    pub fn from_entropy() -> Self {
+        use std::hash::{BuildHasher, Hasher};
+        // the standard library seeds its hashers randomly, without a
+        // clock, so this works wherever they do
+        let seed = std::collections::hash_map::RandomState::new()
+            .build_hasher()
+            .finish();
+        Self::new(seed)
+    }
+}
+
+impl Rng for SeededRng {
+    fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+}
+
+type EnvLookup = Box<dyn Fn(&str) -> Option<String>>;
+
+impl Default for Host {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Host {
+    /// No arguments, no environment variables and no capabilities, with
+    /// the system clock and a randomly seeded [`SeededRng`].
+    pub fn new() -> Self {
+        Host {
+            args: Vec::new(),
+            env_var: Box::new(|_| None),
+            capabilities: Capabilities::default(),
+            clock: Box::new(SystemClock),
+            rng: Box::new(SeededRng::from_entropy()),
+            int_overflow: IntOverflow::default(),
+            resolver: Rc::new(FsResolver::new(".")),
+            modules: Rc::default(),
+            stdlib: StdlibConfig::new(),
+            prelude: None,
+            max_call_depth: DEFAULT_MAX_CALL_DEPTH,
+            fuel: None,
         }
-        _ => Object::Error(format!(
-            "argument to `len` not supported, got {}",
-            arg.type_string()
-        )),
-    }
-}
-
-pub fn last(args: &Vec<Object>) -> Object {
-    if args.len() != 1 {
-        return Object::Error(format!(
-            "wrong number of arguments. got={}, want=1",
-            args.len()
-        ));
-    }
-    let arg = &args[0];
-    match arg {
-        Object::Array(v) => {
-            if v.elements.len() > 0 {
-                return v.elements[v.elements.len() - 1].clone();
-            } else {
-                return evaluator::NULL;
+    }
+
+    /// The environment variables of this process, for the command line.
+    pub fn from_process() -> Self {
+        Self::new().with_env(|name| std::env::var(name).ok())
+    }
+
+    /// The strings `args()` returns.
+    pub fn with_args(mut self, args: Vec<String>) -> Self {
+        self.args = args;
+        self
+    }
+
+    /// Looks up the environment variables for `env(name)` with `lookup`,
+    /// which gives `None` for one that isn't set.
+    pub fn with_env(mut self, lookup: impl Fn(&str) -> Option<String> + 'static) -> Self {
+        self.env_var = Box::new(lookup);
+        self
+    }
+
+    /// Grants the script `capabilities`.
+    pub fn with_capabilities(mut self, capabilities: Capabilities) -> Self {
+        self.capabilities = capabilities;
+        self
+    }
+
+    /// The clock `clock()` reads and `sleep(ms)` waits on.
+    pub fn with_clock(mut self, clock: impl Clock + 'static) -> Self {
+        self.clock = Box::new(clock);
+        self
+    }
+
+    /// The generator `rand()` and `rand_int(lo, hi)` draw from.
+    pub fn with_rng(mut self, rng: impl Rng + 'static) -> Self {
+        self.rng = Box::new(rng);
+        self
+    }
+
+    /// What overflowing integer arithmetic gives, [`IntOverflow::Checked`]
+    /// by default.
+    pub fn with_int_overflow(mut self, int_overflow: IntOverflow) -> Self {
+        self.int_overflow = int_overflow;
+        self
+    }
+
+    /// Where `import` finds modules, a [`FsResolver`] in the working
+    /// directory by default, which needs the `fs` capability.
+    pub fn with_module_resolver(mut self, resolver: impl ModuleResolver + 'static) -> Self {
+        self.resolver = Rc::new(resolver);
+        self
+    }
+
+    /// The path of the program that is run, as the module resolver gives
+    /// paths. The program's imports are then relative to it, and a module
+    /// that imports it back is a cycle that starts at the program, instead
+    /// of running it again as a module.
+    pub fn with_main_module(self, path: impl Into<String>) -> Self {
+        self.modules.borrow_mut().main = Some(path.into());
+        self
+    }
+
+    /// How deeply calls of functions may nest before they fail with
+    /// [`EvalError::CallTooDeep`], [`DEFAULT_MAX_CALL_DEPTH`] by default.
+    /// A call in tail position, whose result is the result of the function
+    /// making it, takes the place of that function and doesn't count.
+    pub fn with_max_call_depth(mut self, max_call_depth: usize) -> Self {
+        self.max_call_depth = max_call_depth;
+        self
+    }
+
+    /// How many steps the programs the host runs may take between them
+    /// before they fail with [`EvalError::OutOfFuel`], for running code that
+    /// might never end. Evaluating an expression is a step, and so is a turn
+    /// of a loop. Without a limit they take as many as they need.
+    pub fn with_fuel(mut self, steps: u64) -> Self {
+        self.fuel = Some(steps);
+        self
+    }
+
+    /// Which groups of builtins scripts see, all of them by default.
+    pub fn with_stdlib(mut self, stdlib: StdlibConfig) -> Self {
+        self.stdlib = stdlib;
+        self
+    }
+
+    /// Whether scripts see the functions of the
+    /// [`PRELUDE`](stdlib::PRELUDE), off by default. A script's own
+    /// bindings come first, and the prelude's before the builtins, so its
+    /// `map` is the one a script calls.
+    pub fn with_prelude(mut self, prelude: bool) -> Self {
+        self.prelude = prelude.then(stdlib::prelude);
+        self
+    }
+}
+
+/// Runs `f` with `host` answering the `args()` and `env(name)` of the
+/// programs it evaluates, and granting them its capabilities.
+pub fn with_host<R>(host: Host, f: impl FnOnce() -> R) -> R {
+    let outer_fuel = evaluator::set_fuel(host.fuel.map(|fuel| (fuel, fuel)));
+    let outer = HOST.with(|h| h.replace(Some(host)));
+    let res = f();
+    HOST.with(|h| h.replace(outer));
+    evaluator::set_fuel(outer_fuel);
+    res
+}
+
+/* the `IntOverflow` of the host of `with_host`, or the default */
+pub(crate) fn int_overflow() -> IntOverflow {
+    HOST.with(|h| {
+        h.borrow()
+            .as_ref()
+            .map_or_else(IntOverflow::default, |host| host.int_overflow)
+    })
+}
+
+/* the `max_call_depth` of the host of `with_host`, or the default */
+pub(crate) fn max_call_depth() -> usize {
+    HOST.with(|h| {
+        h.borrow()
+            .as_ref()
+            .map_or(DEFAULT_MAX_CALL_DEPTH, |host| host.max_call_depth)
+    })
+}
+
+/* the `StdlibConfig` of the host of `with_host`, or the default */
+pub(crate) fn stdlib() -> StdlibConfig {
+    HOST.with(|h| {
+        h.borrow()
+            .as_ref()
+            .map_or_else(StdlibConfig::new, |host| host.stdlib)
+    })
+}
+
+/* what the prelude of the host of `with_host` binds `name` to, if it has
+ * one */
+pub(crate) fn prelude(name: &Rc<str>) -> Option<Object> {
+    HOST.with(|h| h.borrow().as_ref()?.prelude.as_ref()?.get(name))
+}
+
+/* whether the host of `with_host` granted `fs` */
+pub(crate) fn fs_granted() -> bool {
+    HOST.with(|h| h.borrow().as_ref().is_some_and(|host| host.capabilities.fs))
+}
+
+/* the resolver and the imported modules of the host. They're shared so
+ * a module can import others while it's evaluated, outside the borrow. */
+pub(crate) fn module_host() -> (Rc<dyn ModuleResolver>, Rc<RefCell<Modules>>) {
+    current_host(|host| (host.resolver.clone(), host.modules.clone()))
+}
+
+/* runs `f` on the host of `with_host`, or without one on a default host
+ * made the first time it's needed */
+fn current_host<R>(f: impl FnOnce(&mut Host) -> R) -> R {
+    HOST.with(|h| f(h.borrow_mut().get_or_insert_with(Host::new)))
+}
+
+/// What a builtin is: a function from its arguments to its result.
+pub type BuiltinFunction = fn(args: &[Object]) -> Object;
+
+/// A builtin, with the number and types of arguments it takes, which
+/// [`call`](BuiltinDef::call) checks before running it. The functions
+/// never see arguments their definition doesn't allow.
+#[derive(Debug, Clone, Copy)]
+pub struct BuiltinDef {
+    pub name: &'static str,
+    pub min_args: usize,
+    /// The most arguments it takes, or `None` for any number.
+    pub max_args: Option<usize>,
+    /// The types each argument may have, in order. The arguments after
+    /// these may be anything.
+    pub param_types: &'static [TypeSet],
+    pub f: BuiltinFunction,
+}
+
+impl BuiltinDef {
+    /// Runs the builtin on `args`, or gives the error for the first thing
+    /// wrong with them: their number, then the type of each in order.
+    pub fn call(&self, args: &[Object]) -> Object {
+        match self.check(args) {
+            Ok(()) => (self.f)(args),
+            Err(err) => Object::Error(err),
+        }
+    }
+
+    pub fn check(&self, args: &[Object]) -> Result<(), EvalError> {
+        if args.len() < self.min_args || self.max_args.is_some_and(|max| args.len() > max) {
+            return Err(EvalError::WrongArgumentCount {
+                builtin: self.name,
+                got: args.len(),
+                min: self.min_args,
+                max: self.max_args,
+            });
+        }
+        for (i, (arg, want)) in args.iter().zip(self.param_types).enumerate() {
+            if !want.contains(arg.type_val()) {
+                return Err(EvalError::WrongArgumentType {
+                    builtin: self.name,
+                    position: i + 1,
+                    want: *want,
+                    got: arg.type_val(),
+                });
             }
         }
-        _ => Object::Error(format!(
-            "argument to `len` not supported, got {}",
-            arg.type_string()
-        )),
-    }
-}
-
-pub fn rest(args: &Vec<Object>) -> Object {
-    if args.len() != 1 {
-        return Object::Error(format!(
-            "wrong number of arguments. got={}, want=1",
-            args.len()
-        ));
-    }
-    let arg = &args[0];
-    match arg {
-        Object::Array(v) => {
-            let len = v.elements.len();
-            if len > 0 {
-                let mut r = Vec::new();
-                for i in 1..len {
-                    r.push(v.elements[i].clone());
+        Ok(())
+    }
+
+    /// How it's called, like `push(ARRAY, ANY)`. The arguments it can do
+    /// without are in brackets, and `...` stands for any number more.
+    pub fn signature(&self) -> String {
+        let shown = self
+            .max_args
+            .unwrap_or(self.min_args.max(self.param_types.len()));
+        let mut params: Vec<String> = (0..shown)
+            .map(|i| {
+                let t = self.param_types.get(i).copied().unwrap_or(TypeSet::ANY);
+                match i < self.min_args {
+                    true => t.to_string(),
+                    false => format!("[{}]", t),
                 }
-                Object::Array(Array { elements: r })
-            } else {
-                return evaluator::NULL;
+            })
+            .collect();
+        if self.max_args.is_none() {
+            params.push("...".to_owned());
+        }
+        format!("{}({})", self.name, params.join(", "))
+    }
+}
+
+const ANY: TypeSet = TypeSet::ANY;
+const INTEGER: TypeSet = TypeSet::of(&[ObjectType::Integer]);
+const NUMBER: TypeSet = TypeSet::of(&[ObjectType::Integer, ObjectType::Float]);
+const STRING: TypeSet = TypeSet::of(&[ObjectType::String]);
+const ARRAY: TypeSet = TypeSet::of(&[ObjectType::Array]);
+const HASH: TypeSet = TypeSet::of(&[ObjectType::Hash]);
+const FUNCTION: TypeSet = TypeSet::of(&[ObjectType::Function]);
+const CALLABLE: TypeSet = TypeSet::of(&[ObjectType::Function, ObjectType::Builtin]);
+const CAUGHT: TypeSet = TypeSet::of(&[ObjectType::Caught]);
+/* what `iter` makes an iterator of */
+const ITERABLE: TypeSet = TypeSet::of(&[
+    ObjectType::Array,
+    ObjectType::Hash,
+    ObjectType::Range,
+    ObjectType::Iterator,
+]);
+
+/* a builtin that takes exactly as many arguments as it has types */
+const fn def(
+    name: &'static str,
+    param_types: &'static [TypeSet],
+    f: BuiltinFunction,
+) -> BuiltinDef {
+    BuiltinDef {
+        name,
+        min_args: param_types.len(),
+        max_args: Some(param_types.len()),
+        param_types,
+        f,
+    }
+}
+
+/// Every builtin, which the evaluator resolves a name to when nothing
+/// else binds it.
+pub const BUILTINS: [BuiltinDef; 50] = [
+    def(
+        "len",
+        &[TypeSet::of(&[
+            ObjectType::String,
+            ObjectType::Array,
+            ObjectType::Hash,
+            ObjectType::Iterator,
+        ])],
+        len,
+    ),
+    def("first", &[ARRAY], first),
+    def("last", &[ARRAY], last),
+    def("rest", &[ARRAY], rest),
+    def("push", &[ARRAY, ANY], push),
+    def("keys", &[HASH], keys),
+    def("range", &[INTEGER, INTEGER], range),
+    BuiltinDef {
+        name: "zip",
+        min_args: 2,
+        max_args: None,
+        param_types: &[ARRAY, ARRAY],
+        f: zip,
+    },
+    def("enumerate", &[ARRAY], enumerate),
+    def("freeze", &[ARRAY], freeze),
+    def("iter", &[ITERABLE], to_iter),
+    def("take", &[ITERABLE, INTEGER], take),
+    def("collect", &[ITERABLE], collect),
+    def("map_iter", &[ITERABLE, CALLABLE], map_iter),
+    def("filter_iter", &[ITERABLE, CALLABLE], filter_iter),
+    BuiltinDef {
+        name: "print",
+        min_args: 0,
+        max_args: None,
+        param_types: &[],
+        f: print,
+    },
+    def("type", &[ANY], type_name),
+    def("abs", &[NUMBER], abs),
+    def("min", &[NUMBER, NUMBER], min),
+    def("max", &[NUMBER, NUMBER], max),
+    def("pow", &[INTEGER, INTEGER], pow),
+    def("sqrt", &[NUMBER], sqrt),
+    def("mod", &[INTEGER, INTEGER], modulo),
+    def("map", &[ARRAY, CALLABLE], map),
+    def("memoize", &[FUNCTION], memoize),
+    def("typeassert", &[ANY, STRING], typeassert),
+    def("deepEqual", &[ANY, ANY], deep_equal),
+    def("diff", &[ANY, ANY], diff),
+    def("assert_eq", &[ANY, ANY], assert_eq),
+    def("throw", &[ANY], throw),
+    def("try", &[CALLABLE], try_call),
+    def("kind", &[CAUGHT], kind),
+    def("thrown", &[CAUGHT], thrown),
+    def("chars", &[STRING], chars),
+    def("bytes", &[STRING], bytes),
+    def(
+        "slice",
+        &[
+            TypeSet::of(&[ObjectType::String, ObjectType::Array]),
+            INTEGER,
+            INTEGER,
+        ],
+        slice,
+    ),
+    def("split", &[STRING, STRING], split),
+    def("upper", &[STRING], upper),
+    def("contains", &[STRING, STRING], contains),
+    def("args", &[], args),
+    def("env", &[STRING], env),
+    def("exit", &[INTEGER], exit),
+    def("read_file", &[STRING], read_file),
+    def("read_lines", &[STRING], read_lines),
+    def("write_file", &[STRING, STRING], write_file),
+    def("clock", &[], clock),
+    def("sleep", &[INTEGER], sleep),
+    def("rand", &[], rand),
+    def("rand_int", &[INTEGER, INTEGER], rand_int),
+    def("parse", &[STRING], parse),
+];
+
+/// The names of the [`BUILTINS`], for passes like `analysis::resolve`
+/// that need to know.
+pub const BUILTIN_NAMES: [&str; 50] = {
+    let mut names = [""; 50];
+    let mut i = 0;
+    while i < names.len() {
+        names[i] = BUILTINS[i].name;
+        i += 1;
+    }
+    names
+};
+
+/// The builtin called `name`.
+pub fn lookup(name: &str) -> Option<&'static BuiltinDef> {
+    let builtins: &'static [BuiltinDef] = &BUILTINS;
+    builtins.iter().find(|def| def.name == name)
+}
+
+/* The functions below only run through `BuiltinDef::call`, so the
+ * arguments they get are as many and of the types their definition says,
+ * and a pattern that needs more can't fail. */
+
+/// `len(x)` is the number of elements of an array, of pairs of a hash, or
+/// of chars of a string. Strings are indexed, sliced and split by chars
+/// too, which are Unicode scalar values rather than what a reader sees as
+/// one character: `"e\u{301}"` shows as `é` but is two chars, the `e` and
+/// the combining accent.
+fn len(args: &[Object]) -> Object {
+    match &args[0] {
+        Object::String(v) => Object::Integer(v.chars().count() as i64),
+        Object::Array(v) => Object::Integer(v.elements.len() as i64),
+        Object::Hash(h) => Object::Integer(h.len() as i64),
+        Object::Iterator(_) => Object::Error(EvalError::InvalidArgument {
+            builtin: "len",
+            reason: "an iterator has no length, `collect` it into an array first",
+        }),
+        _ => unreachable!("checked to be a string, array, hash or iterator"),
+    }
+}
+
+fn first(args: &[Object]) -> Object {
+    let [Object::Array(v)] = args else {
+        unreachable!("checked to be an array")
+    };
+    v.elements.first().cloned().unwrap_or(evaluator::NULL)
+}
+
+fn last(args: &[Object]) -> Object {
+    let [Object::Array(v)] = args else {
+        unreachable!("checked to be an array")
+    };
+    v.elements.last().cloned().unwrap_or(evaluator::NULL)
+}
+
+fn rest(args: &[Object]) -> Object {
+    let [Object::Array(v)] = args else {
+        unreachable!("checked to be an array")
+    };
+    if v.elements.is_empty() {
+        return evaluator::NULL;
+    }
+    Object::Array(Rc::new(Array {
+        elements: v.elements[1..].to_vec(),
+        frozen: false,
+    }))
+}
+
+fn push(args: &[Object]) -> Object {
+    let [Object::Array(v), val] = args else {
+        unreachable!("checked to be an array and a value")
+    };
+    if v.frozen {
+        return Object::Error(EvalError::FrozenArray);
+    }
+    let mut r = Vec::with_capacity(v.elements.len() + 1);
+    r.extend(v.elements.iter().cloned());
+    r.push(val.clone());
+    Object::Array(Rc::new(Array {
+        elements: r,
+        frozen: false,
+    }))
+}
+
+/// `keys(h)` is an array of the keys of a hash, in the order they were
+/// first inserted.
+fn keys(args: &[Object]) -> Object {
+    let [Object::Hash(h)] = args else {
+        unreachable!("checked to be a hash")
+    };
+    let elements = h.pairs().iter().map(|(k, _)| k.clone()).collect();
+    Object::Array(Rc::new(Array {
+        elements,
+        frozen: false,
+    }))
+}
+
+/// `zip(a, b, ...)` pairs up the elements of arrays by index, so
+/// `zip([1, 2], ["a", "b"])` is `[[1, "a"], [2, "b"]]`. With more than two
+/// arrays each element has one from every array, and there are as many as
+/// the shortest array has.
+fn zip(args: &[Object]) -> Object {
+    let mut arrays = Vec::with_capacity(args.len());
+    for (i, arg) in args.iter().enumerate() {
+        match arg {
+            Object::Array(arr) => arrays.push(arr),
+            // the first two are checked already
+            _ => {
+                return Object::Error(EvalError::WrongArgumentType {
+                    builtin: "zip",
+                    position: i + 1,
+                    want: ARRAY,
+                    got: arg.type_val(),
+                })
             }
         }
-        _ => Object::Error(format!(
-            "argument to `len` not supported, got {}",
-            arg.type_string()
-        )),
-    }
-}
-
-pub fn push(args: &Vec<Object>) -> Object {
-    if args.len() != 2 {
-        return Object::Error(format!(
-            "wrong number of arguments. got={}, want=1",
-            args.len()
-        ));
-    }
-    let arg = &args[0];
-    let val = &args[1];
-    match arg {
-        Object::Array(v) => {
-            let mut r = Vec::new();
-            for o in v.elements.iter() {
-                r.push(o.clone());
+    }
+    let len = arrays
+        .iter()
+        .map(|arr| arr.elements.len())
+        .min()
+        .unwrap_or(0);
+    let elements = (0..len)
+        .map(|i| {
+            let elements = arrays.iter().map(|arr| arr.elements[i].clone()).collect();
+            Object::Array(Rc::new(Array {
+                elements,
+                frozen: false,
+            }))
+        })
+        .collect();
+    Object::Array(Rc::new(Array {
+        elements,
+        frozen: false,
+    }))
+}
+
+/// `freeze(xs)` is `xs` with `push` failing on it, and on every array
+/// passed on that is it. `xs` itself stays as it was.
+fn freeze(args: &[Object]) -> Object {
+    let [Object::Array(arr)] = args else {
+        unreachable!("checked to be an array")
+    };
+    if arr.frozen {
+        return args[0].clone();
+    }
+    Object::Array(Rc::new(Array {
+        elements: arr.elements.clone(),
+        frozen: true,
+    }))
+}
+
+/* `iter(x)`, see `iter::iter` */
+fn to_iter(args: &[Object]) -> Object {
+    let iter = iter::iter(&args[0]).expect("checked to be iterable");
+    Object::Iterator(iter)
+}
+
+/// `take(xs, n)` is an iterator over the first `n` elements of `xs`.
+fn take(args: &[Object]) -> Object {
+    let [xs, Object::Integer(n)] = args else {
+        unreachable!("checked to be iterable and an integer")
+    };
+    let Ok(left) = usize::try_from(*n) else {
+        return Object::Error(EvalError::InvalidArgument {
+            builtin: "take",
+            reason: "negative count",
+        });
+    };
+    let inner = iter::iter(xs).expect("checked to be iterable");
+    Object::Iterator(Rc::new(Iter::new(Taken { inner, left })))
+}
+
+/// `collect(xs)` is an array of the elements of `xs` an iterator has
+/// left, or of the error working one out made.
+fn collect(args: &[Object]) -> Object {
+    let iter = iter::iter(&args[0]).expect("checked to be iterable");
+    match iter.collect() {
+        Ok(elements) => Object::Array(Rc::new(Array {
+            elements,
+            frozen: false,
+        })),
+        Err(err) => err,
+    }
+}
+
+/// `map_iter(xs, f)` is an iterator over what `f` gives for each element
+/// of `xs`, calling it only once the element is asked for.
+fn map_iter(args: &[Object]) -> Object {
+    let [xs, f] = args else {
+        unreachable!("checked to be iterable and a function")
+    };
+    let inner = iter::iter(xs).expect("checked to be iterable");
+    let f = f.clone();
+    Object::Iterator(Rc::new(Iter::new(Mapped { inner, f })))
+}
+
+/// `filter_iter(xs, f)` is an iterator over the elements of `xs` that `f`
+/// gives something truthy for.
+fn filter_iter(args: &[Object]) -> Object {
+    let [xs, f] = args else {
+        unreachable!("checked to be iterable and a function")
+    };
+    let inner = iter::iter(xs).expect("checked to be iterable");
+    let f = f.clone();
+    Object::Iterator(Rc::new(Iter::new(Filtered { inner, f })))
+}
+
+/// `enumerate(xs)` pairs each element of an array with its index, so
+/// `enumerate(["a", "b"])` is `[[0, "a"], [1, "b"]]`.
+fn enumerate(args: &[Object]) -> Object {
+    let [Object::Array(arr)] = args else {
+        unreachable!("checked to be an array")
+    };
+    let elements = arr
+        .elements
+        .iter()
+        .enumerate()
+        .map(|(i, elem)| {
+            let elements = vec![Object::Integer(i as i64), elem.clone()];
+            Object::Array(Rc::new(Array {
+                elements,
+                frozen: false,
+            }))
+        })
+        .collect();
+    Object::Array(Rc::new(Array {
+        elements,
+        frozen: false,
+    }))
+}
+
+/// `range(start, end)` is the integers from `start` up to but not
+/// including `end`, which `for` goes through one at a time. It's empty if
+/// `end` isn't greater than `start`.
+fn range(args: &[Object]) -> Object {
+    let [Object::Integer(start), Object::Integer(end)] = args else {
+        unreachable!("checked to be two integers")
+    };
+    Object::Range(Range {
+        start: *start,
+        end: *end,
+    })
+}
+
+fn abs(args: &[Object]) -> Object {
+    match &args[0] {
+        Object::Integer(v) => match v.checked_abs() {
+            Some(v) => Object::Integer(v),
+            None => Object::Error(EvalError::InvalidArgument {
+                builtin: "abs",
+                reason: "result overflows an integer",
+            }),
+        },
+        Object::Float(v) => Object::Float(v.abs()),
+        _ => unreachable!("checked to be a number"),
+    }
+}
+
+fn min(args: &[Object]) -> Object {
+    min_max(args, std::cmp::Ordering::Less)
+}
+
+fn max(args: &[Object]) -> Object {
+    min_max(args, std::cmp::Ordering::Greater)
+}
+
+/* two integers give an integer. With a float among them both are taken
+ * as floats, the way `<` compares them, so `min(1, 2.5)` is `1.0`. */
+fn min_max(args: &[Object], keep: std::cmp::Ordering) -> Object {
+    let as_float = |obj: &Object| match obj {
+        Object::Integer(v) => *v as f64,
+        Object::Float(v) => *v,
+        _ => unreachable!("checked to be numbers"),
+    };
+    match (&args[0], &args[1]) {
+        (Object::Integer(x), Object::Integer(y)) if x.cmp(y) == keep => Object::Integer(*x),
+        (Object::Integer(_), Object::Integer(y)) => Object::Integer(*y),
+        (a, b) => {
+            let (x, y) = (as_float(a), as_float(b));
+            Object::Float(if x.total_cmp(&y) == keep { x } else { y })
+        }
+    }
+}
+
+fn pow(args: &[Object]) -> Object {
+    let [Object::Integer(b), Object::Integer(e)] = args else {
+        unreachable!("checked to be two integers")
+    };
+    let Ok(e) = u32::try_from(*e) else {
+        return Object::Error(EvalError::InvalidArgument {
+            builtin: "pow",
+            reason: "negative exponent",
+        });
+    };
+    match b.checked_pow(e) {
+        Some(v) => Object::Integer(v),
+        None => Object::Error(EvalError::InvalidArgument {
+            builtin: "pow",
+            reason: "result overflows an integer",
+        }),
+    }
+}
+
+fn sqrt(args: &[Object]) -> Object {
+    let v = match &args[0] {
+        Object::Integer(v) => *v as f64,
+        Object::Float(v) => *v,
+        _ => unreachable!("checked to be a number"),
+    };
+    if v < 0.0 {
+        return Object::Error(EvalError::InvalidArgument {
+            builtin: "sqrt",
+            reason: "negative number",
+        });
+    }
+    Object::Float(v.sqrt())
+}
+
+/// `mod(a, b)` is the euclidean remainder, so it is never negative:
+/// `mod(-7, 3)` is `2`.
+fn modulo(args: &[Object]) -> Object {
+    let [Object::Integer(x), Object::Integer(y)] = args else {
+        unreachable!("checked to be two integers")
+    };
+    if *y == 0 {
+        return Object::Error(EvalError::DivisionByZero);
+    }
+    match x.checked_rem_euclid(*y) {
+        Some(v) => Object::Integer(v),
+        None => Object::Error(EvalError::InvalidArgument {
+            builtin: "mod",
+            reason: "result overflows an integer",
+        }),
+    }
+}
+
+/// `map(array, f)` is the array of `f` applied to each element, where `f`
+/// is a function or a builtin.
+fn map(args: &[Object]) -> Object {
+    let [Object::Array(arr), f] = args else {
+        unreachable!("checked to be an array and a function")
+    };
+    let mut elements = Vec::with_capacity(arr.elements.len());
+    for elem in arr.elements.iter() {
+        match evaluator::apply_function(f, std::slice::from_ref(elem)) {
+            Some(err @ Object::Error(_)) => return err,
+            Some(obj) => elements.push(obj),
+            None => elements.push(evaluator::NULL),
+        }
+    }
+    Object::Array(Rc::new(Array {
+        elements,
+        frozen: false,
+    }))
+}
+
+/// `chars(s)` splits a string into its Unicode scalar values, each a
+/// string of its own, so `chars("héllo")` has 5 elements.
+fn chars(args: &[Object]) -> Object {
+    let [Object::String(s)] = args else {
+        unreachable!("checked to be a string")
+    };
+    let elements = s
+        .chars()
+        .map(|ch| Object::String(ch.to_string().into()))
+        .collect();
+    Object::Array(Rc::new(Array {
+        elements,
+        frozen: false,
+    }))
+}
+
+/// `bytes(s)` is the UTF-8 bytes of a string as integers, so
+/// `bytes("héllo")` has 6 elements, the `é` taking two.
+fn bytes(args: &[Object]) -> Object {
+    let [Object::String(s)] = args else {
+        unreachable!("checked to be a string")
+    };
+    let elements = s.bytes().map(|b| Object::Integer(b as i64)).collect();
+    Object::Array(Rc::new(Array {
+        elements,
+        frozen: false,
+    }))
+}
+
+/// `slice(x, start, end)` is the elements of an array, or the chars of a
+/// string, from index `start` up to but not including `end`. Both have to
+/// be from 0 to `len(x)`, and `start` can't be past `end`.
+fn slice(args: &[Object]) -> Object {
+    let [x, Object::Integer(start), Object::Integer(end)] = args else {
+        unreachable!("checked to be a string or array and two integers")
+    };
+    let range = |len: usize| {
+        let index = |index: i64| {
+            usize::try_from(index)
+                .ok()
+                .filter(|&i| i <= len)
+                .ok_or(EvalError::IndexOutOfRange {
+                    builtin: "slice",
+                    index,
+                    len,
+                })
+        };
+        let (start, end) = (index(*start)?, index(*end)?);
+        if start > end {
+            return Err(EvalError::InvalidArgument {
+                builtin: "slice",
+                reason: "start is past end",
+            });
+        }
+        Ok(start..end)
+    };
+    match x {
+        Object::Array(arr) => match range(arr.elements.len()) {
+            Ok(range) => Object::Array(Rc::new(Array {
+                elements: arr.elements[range].to_vec(),
+                frozen: false,
+            })),
+            Err(e) => Object::Error(e),
+        },
+        Object::String(s) => match range(s.chars().count()) {
+            Ok(range) => {
+                let sliced: String = s.chars().skip(range.start).take(range.len()).collect();
+                Object::String(sliced.into())
             }
-            r.push(val.clone());
-            Object::Array(Array { elements: r })
+            Err(e) => Object::Error(e),
+        },
+        _ => unreachable!("checked to be a string or array"),
+    }
+}
+
+/// `split(s, sep)` is the array of the parts of `s` between each `sep`.
+/// An empty `sep` splits `s` into its chars, the same as `chars(s)`.
+fn split(args: &[Object]) -> Object {
+    let [Object::String(s), Object::String(sep)] = args else {
+        unreachable!("checked to be two strings")
+    };
+    if sep.is_empty() {
+        return chars(&args[..1]);
+    }
+    let elements = s
+        .split(&**sep)
+        .map(|part| Object::String(part.into()))
+        .collect();
+    Object::Array(Rc::new(Array {
+        elements,
+        frozen: false,
+    }))
+}
+
+/// `memoize(f)` is a function that runs `f` the first time it is called
+/// with some arguments, and after that gives the same result again without
+/// running it, see [`Memo`] for which arguments it remembers. The calls
+/// `f` makes to itself by the name it was `let` bound to are to the new
+/// function, so `memoize(fib)` only works out each `fib(n)` once. An
+/// error is never remembered.
+fn memoize(args: &[Object]) -> Object {
+    let [Object::Function(func)] = args else {
+        unreachable!("checked to be a function")
+    };
+    Object::Function(Rc::new(Function {
+        memo: Some(Memo::default()),
+        ..Function::clone(func)
+    }))
+}
+
+/// `typeassert(x, type)` is `x` if its type is named `type`, exactly as
+/// errors name it, like `"INTEGER"` or `"ARRAY"`, and an error otherwise.
+/// It checks the arguments a function gets where it starts, instead of
+/// wherever they are first used the wrong way.
+fn typeassert(args: &[Object]) -> Object {
+    let [x, Object::String(want)] = args else {
+        unreachable!("checked to be a value and a string")
+    };
+    if x.type_string() != &**want {
+        return Object::Error(EvalError::TypeAssertion {
+            want: want.to_string(),
+            got: x.type_val(),
+        });
+    }
+    x.clone()
+}
+
+/// `type(x)` is the name of the type of `x`, like `"INTEGER"`, the one
+/// `typeassert` takes.
+fn type_name(args: &[Object]) -> Object {
+    Object::String(args[0].type_string().into())
+}
+
+/// `deepEqual(a, b)` compares like `==` through nested arrays, tuples and
+/// hashes, but functions by their parameters and bodies rather than by
+/// identity, see [`Object::deep_eq`].
+fn deep_equal(args: &[Object]) -> Object {
+    Object::Boolean(args[0].deep_eq(&args[1]))
+}
+
+/// `diff(expected, got)` is an array of where `got` differs from
+/// `expected`, see [`Object::diff`], which is empty when they are equal.
+fn diff(args: &[Object]) -> Object {
+    let elements = args[0]
+        .diff(&args[1])
+        .into_iter()
+        .map(|d| Object::String(d.into()))
+        .collect();
+    Object::Array(Rc::new(Array {
+        elements,
+        frozen: false,
+    }))
+}
+
+/// `assert_eq(expected, got)` is null if `got` is equal to `expected`,
+/// and otherwise an error listing where they differ, as `diff` does.
+fn assert_eq(args: &[Object]) -> Object {
+    let diffs = args[0].diff(&args[1]);
+    if diffs.is_empty() {
+        return evaluator::NULL;
+    }
+    Object::Error(EvalError::AssertionFailed(diffs))
+}
+
+/// `throw(value)` fails with `value`, an error of the kind `Custom` that
+/// `try` catches like any other. Throwing an error `try` caught fails with
+/// that error again.
+fn throw(args: &[Object]) -> Object {
+    match &args[0] {
+        Object::Caught(err) => Object::Error(EvalError::clone(err)),
+        value => Object::Error(EvalError::Thrown(Box::new(value.clone()))),
+    }
+}
+
+/// `try(f)` calls `f` and is `(value, null)` with what it gave, or
+/// `(null, err)` if it failed, where `err` is the error as a value. An
+/// `exit` and running out of fuel aren't failures it can catch, they end
+/// the program all the same.
+fn try_call(args: &[Object]) -> Object {
+    let pair = |value, err| {
+        Object::Tuple(Rc::new(Tuple {
+            elements: vec![value, err],
+        }))
+    };
+    match evaluator::apply_function(&args[0], &[]) {
+        Some(res @ Object::Error(EvalError::Exit(_) | EvalError::OutOfFuel(_))) => res,
+        Some(Object::Error(err)) => {
+            evaluator::caught();
+            pair(evaluator::NULL, Object::Caught(Rc::new(err)))
+        }
+        res => pair(res.unwrap_or(evaluator::NULL), evaluator::NULL),
+    }
+}
+
+/// `kind(err)` is the name of the kind of an error `try` caught, like
+/// `"DivisionByZero"`, or `"Custom"` for one `throw` made.
+fn kind(args: &[Object]) -> Object {
+    let [Object::Caught(err)] = args else {
+        unreachable!("checked to be a caught error")
+    };
+    Object::String(err.kind().as_str().into())
+}
+
+/// `thrown(err)` is the value `throw` threw to make an error `try` caught,
+/// and null for an error that wasn't thrown.
+fn thrown(args: &[Object]) -> Object {
+    let [Object::Caught(err)] = args else {
+        unreachable!("checked to be a caught error")
+    };
+    match &**err {
+        EvalError::Thrown(value) => Object::clone(value),
+        _ => evaluator::NULL,
+    }
+}
+
+/// `upper(s)` is `s` in upper case. That can change its length, since
+/// some chars have no upper case char of their own: `upper("ß")` is
+/// `"SS"`.
+fn upper(args: &[Object]) -> Object {
+    let [Object::String(s)] = args else {
+        unreachable!("checked to be a string")
+    };
+    Object::String(s.to_uppercase().into())
+}
+
+/// `contains(s, part)` is whether `part` appears in `s`, which it does
+/// whenever `split(s, part)` has more than one part. Every string
+/// contains `""`.
+fn contains(args: &[Object]) -> Object {
+    let [Object::String(s), Object::String(part)] = args else {
+        unreachable!("checked to be two strings")
+    };
+    Object::Boolean(s.contains(&**part))
+}
+
+/// `args()` is the array of the arguments the host passed the script, as
+/// strings.
+fn args(_: &[Object]) -> Object {
+    let elements = HOST.with(|h| match h.borrow().as_ref() {
+        Some(host) => host
+            .args
+            .iter()
+            .map(|arg| Object::String(arg.as_str().into()))
+            .collect(),
+        None => Vec::new(),
+    });
+    Object::Array(Rc::new(Array {
+        elements,
+        frozen: false,
+    }))
+}
+
+/// `env(name)` is the value of the environment variable `name` as a
+/// string, or null if it isn't set.
+fn env(args: &[Object]) -> Object {
+    let [Object::String(name)] = args else {
+        unreachable!("checked to be a string")
+    };
+    let value = HOST.with(|h| h.borrow().as_ref().and_then(|host| (host.env_var)(name)));
+    match value {
+        Some(value) => Object::String(value.into()),
+        None => evaluator::NULL,
+    }
+}
+
+/// `exit(code)` stops the program with `code`, from 0 to 255, as its exit
+/// status. It travels out of every call like an error does, as
+/// `EvalError::Exit`, so an embedder gets it back from `eval` instead of
+/// the process ending.
+fn exit(args: &[Object]) -> Object {
+    match &args[0] {
+        Object::Integer(code @ 0..=255) => Object::Error(EvalError::Exit(*code as u8)),
+        _ => Object::Error(EvalError::InvalidArgument {
+            builtin: "exit",
+            reason: "exit status must be from 0 to 255",
+        }),
+    }
+}
+
+/* the path a file builtin was called with, once the host has granted
+ * `fs` */
+fn fs_path(args: &[Object]) -> Result<&str, Object> {
+    if !fs_granted() {
+        return Err(Object::Error(EvalError::CapabilityNotGranted("fs")));
+    }
+    match &args[0] {
+        Object::String(path) => Ok(path),
+        _ => unreachable!("checked to be a string"),
+    }
+}
+
+fn io_error(builtin: &'static str, path: &str, err: std::io::Error) -> Object {
+    Object::Error(EvalError::Io {
+        builtin,
+        path: path.to_owned(),
+        reason: err.to_string(),
+    })
+}
+
+/// `read_file(path)` is the contents of a UTF-8 file as a string. Needs
+/// the `fs` capability.
+fn read_file(args: &[Object]) -> Object {
+    let path = match fs_path(args) {
+        Ok(path) => path,
+        Err(err) => return err,
+    };
+    match std::fs::read_to_string(path) {
+        Ok(contents) => Object::String(contents.into()),
+        Err(err) => io_error("read_file", path, err),
+    }
+}
+
+/// `read_lines(path)` is the lines of a UTF-8 file as an array of strings,
+/// without their line endings. Needs the `fs` capability.
+fn read_lines(args: &[Object]) -> Object {
+    let path = match fs_path(args) {
+        Ok(path) => path,
+        Err(err) => return err,
+    };
+    match std::fs::read_to_string(path) {
+        Ok(contents) => {
+            let elements = contents
+                .lines()
+                .map(|line| Object::String(line.into()))
+                .collect();
+            Object::Array(Rc::new(Array {
+                elements,
+                frozen: false,
+            }))
         }
-        _ => Object::Error(format!(
-            "argument to `len` not supported, got {}",
-            arg.type_string()
-        )),
+        Err(err) => io_error("read_lines", path, err),
     }
 }
 
-pub fn print(args: &Vec<Object>) -> Object {
-    for arg in args.iter() {
-        println!("{}", arg.inspect());
+/// `write_file(path, contents)` writes a string to a file, replacing
+/// whatever was in it. Needs the `fs` capability.
+fn write_file(args: &[Object]) -> Object {
+    let path = match fs_path(args) {
+        Ok(path) => path,
+        Err(err) => return err,
+    };
+    let Object::String(contents) = &args[1] else {
+        unreachable!("checked to be a string")
+    };
+    match std::fs::write(path, contents.as_bytes()) {
+        Ok(()) => evaluator::NULL,
+        Err(err) => io_error("write_file", path, err),
     }
-    return evaluator::NULL;
+}
+
+/* whether the host lets scripts `eval`, which the evaluator implements
+ * since it needs the caller's environment */
+pub(crate) fn eval_granted() -> bool {
+    current_host(|host| host.capabilities.eval)
+}
+
+/// `parse(code)` is `code` as the formatter prints it, with every operator
+/// in parentheses to show how it parses, like `(1 + (2 * 3))` for
+/// `1 + 2 * 3`.
+fn parse(args: &[Object]) -> Object {
+    let [Object::String(code)] = args else {
+        unreachable!("checked to be a string")
+    };
+    match crate::format::format_grouped(code) {
+        Ok(formatted) => Object::String(formatted.into()),
+        Err(errors) => Object::Error(EvalError::ParseFailed(errors)),
+    }
+}
+
+/// `clock()` is the time in milliseconds since the Unix epoch, from the
+/// host's [`Clock`].
+fn clock(_: &[Object]) -> Object {
+    Object::Integer(current_host(|host| host.clock.now_millis()))
+}
+
+/// `sleep(ms)` waits for `ms` milliseconds on the host's [`Clock`]. Needs
+/// the `sleep` capability.
+fn sleep(args: &[Object]) -> Object {
+    if !current_host(|host| host.capabilities.sleep) {
+        return Object::Error(EvalError::CapabilityNotGranted("sleep"));
+    }
+    match &args[0] {
+        Object::Integer(ms @ 0..) => {
+            current_host(|host| host.clock.sleep(*ms as u64));
+            evaluator::NULL
+        }
+        _ => Object::Error(EvalError::InvalidArgument {
+            builtin: "sleep",
+            reason: "can't sleep for a negative time",
+        }),
+    }
+}
+
+/// `rand()` is a float from 0 up to but not including 1, from the host's
+/// [`Rng`].
+fn rand(_: &[Object]) -> Object {
+    // the top 53 bits, as many as a float has
+    let bits = current_host(|host| host.rng.next_u64()) >> 11;
+    Object::Float(bits as f64 / (1u64 << 53) as f64)
+}
+
+/// `rand_int(lo, hi)` is an integer from `lo` to `hi`, both included, from
+/// the host's [`Rng`].
+fn rand_int(args: &[Object]) -> Object {
+    let [Object::Integer(lo), Object::Integer(hi)] = args else {
+        unreachable!("checked to be two integers")
+    };
+    let (lo, hi) = (*lo, *hi);
+    if lo > hi {
+        return Object::Error(EvalError::InvalidArgument {
+            builtin: "rand_int",
+            reason: "lo must not be greater than hi",
+        });
+    }
+    let n = current_host(|host| host.rng.next_u64());
+    // scaling rather than taking a remainder keeps it uniform enough
+    // without a loop, and `i64::MIN..=i64::MAX` is every u64
+    let span = (hi as i128 - lo as i128 + 1) as u128;
+    let offset = (n as u128 * span) >> 64;
+    Object::Integer((lo as i128 + offset as i128) as i64)
+}
+
+fn print(args: &[Object]) -> Object {
+    OUTPUT.with(|out| match out.borrow_mut().as_mut() {
+        Some(buf) => {
+            for arg in args.iter() {
+                buf.push_str(&arg.inspect());
+                buf.push('\n');
+            }
+        }
+        None => {
+            for arg in args.iter() {
+                println!("{}", arg.inspect());
+            }
+        }
+    });
+    evaluator::NULL
 }
@@ -0,0 +1,295 @@
+//! Saves [`Bytecode`] to bytes and loads it back, so a program can be
+//! compiled once and run many times. All numbers are big-endian:
+//!
+//! ```text
+//! magic         b"MKBC"
+//! version       u16
+//! constants     u32 count, then per constant a u8 tag and its payload:
+//!                 0 integer  i64
+//!                 1 boolean  u8, 0 or 1
+//!                 2 string   u32 length, then that many bytes of UTF-8
+//! instructions  u32 length, then the bytes
+//! ```
+//!
+//! Tag 3 is kept for compiled functions once the compiler has them.
+
+use crate::compiler::Bytecode;
+use crate::object::{Object, ObjectTrait, ObjectType};
+
+const MAGIC: &[u8; 4] = b"MKBC";
+pub const VERSION: u16 = 1;
+
+const TAG_INTEGER: u8 = 0;
+const TAG_BOOLEAN: u8 = 1;
+const TAG_STRING: u8 = 2;
+
+#[non_exhaustive]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FormatError {
+    /// A constant of a type the format can't hold.
+    UnsupportedConstant(ObjectType),
+    /// More constants or instruction bytes than a u32 can count.
+    TooLarge,
+    /// The file doesn't start with the magic bytes.
+    NotBytecode,
+    /// The file is from a version of the format this one can't read.
+    UnsupportedVersion(u16),
+    /// The file ends in the middle of something.
+    Truncated,
+    /// A constant with a tag that isn't one of the format's.
+    UnknownTag(u8),
+    /// A boolean that isn't 0 or 1.
+    InvalidBoolean(u8),
+    /// A string constant that isn't UTF-8.
+    InvalidString,
+    /// Bytes after the instructions.
+    TrailingBytes(usize),
+}
+
+impl std::fmt::Display for FormatError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::UnsupportedConstant(got) => write!(f, "can't save a {} constant", got),
+            Self::TooLarge => f.write_str("too large to save"),
+            Self::NotBytecode => f.write_str("not a bytecode file"),
+            Self::UnsupportedVersion(version) => write!(
+                f,
+                "unsupported bytecode version {}, expected {}",
+                version, VERSION
+            ),
+            Self::Truncated => f.write_str("truncated bytecode file"),
+            Self::UnknownTag(tag) => write!(f, "unknown constant tag {}", tag),
+            Self::InvalidBoolean(byte) => write!(f, "invalid boolean constant {}", byte),
+            Self::InvalidString => f.write_str("string constant isn't valid UTF-8"),
+            Self::TrailingBytes(n) => write!(f, "{} bytes after the instructions", n),
+        }
+    }
+}
+
+impl std::error::Error for FormatError {}
+
+impl Bytecode {
+    pub fn serialize(&self) -> Result<Vec<u8>, FormatError> {
+        let mut res = Vec::with_capacity(14 + self.instructions.len());
+        res.extend_from_slice(MAGIC);
+        res.extend_from_slice(&VERSION.to_be_bytes());
+        push_len(&mut res, self.constants.len())?;
+        for constant in self.constants.iter() {
+            match constant {
+                Object::Integer(v) => {
+                    res.push(TAG_INTEGER);
+                    res.extend_from_slice(&v.to_be_bytes());
+                }
+                Object::Boolean(v) => {
+                    res.push(TAG_BOOLEAN);
+                    res.push(*v as u8);
+                }
+                Object::String(s) => {
+                    res.push(TAG_STRING);
+                    push_len(&mut res, s.len())?;
+                    res.extend_from_slice(s.as_bytes());
+                }
+                _ => return Err(FormatError::UnsupportedConstant(constant.type_val())),
+            }
+        }
+        push_len(&mut res, self.instructions.len())?;
+        res.extend_from_slice(&self.instructions);
+        Ok(res)
+    }
+
+    /// Whether `bytes` start like what [`serialize`](Bytecode::serialize)
+    /// saves, rather than like source, which never does.
+    pub fn is_serialized(bytes: &[u8]) -> bool {
+        bytes.starts_with(MAGIC)
+    }
+
+    /// Loads what [`serialize`](Bytecode::serialize) saved. The
+    /// instructions themselves aren't checked, the VM reports the ones
+    /// that are invalid when it gets to them.
+    pub fn deserialize(bytes: &[u8]) -> Result<Bytecode, FormatError> {
+        let mut r = Reader { bytes };
+        if r.take(MAGIC.len()).ok() != Some(MAGIC.as_slice()) {
+            return Err(FormatError::NotBytecode);
+        }
+        let version = u16::from_be_bytes(r.array()?);
+        if version != VERSION {
+            return Err(FormatError::UnsupportedVersion(version));
+        }
+        let count = r.len()?;
+        // the count isn't trusted to size the allocation, every constant
+        // takes at least two bytes
+        let mut constants = Vec::with_capacity(count.min(r.bytes.len() / 2));
+        for _ in 0..count {
+            let constant = match r.array::<1>()?[0] {
+                TAG_INTEGER => Object::Integer(i64::from_be_bytes(r.array()?)),
+                TAG_BOOLEAN => match r.array::<1>()?[0] {
+                    byte @ (0 | 1) => Object::Boolean(byte == 1),
+                    byte => return Err(FormatError::InvalidBoolean(byte)),
+                },
+                TAG_STRING => {
+                    let len = r.len()?;
+                    let s = std::str::from_utf8(r.take(len)?)
+                        .map_err(|_| FormatError::InvalidString)?;
+                    Object::String(s.into())
+                }
+                tag => return Err(FormatError::UnknownTag(tag)),
+            };
+            constants.push(constant);
+        }
+        let len = r.len()?;
+        let instructions = r.take(len)?.to_vec();
+        if !r.bytes.is_empty() {
+            return Err(FormatError::TrailingBytes(r.bytes.len()));
+        }
+        Ok(Bytecode {
+            instructions,
+            constants,
+        })
+    }
+}
+
+fn push_len(res: &mut Vec<u8>, len: usize) -> Result<(), FormatError> {
+    let len = u32::try_from(len).map_err(|_| FormatError::TooLarge)?;
+    res.extend_from_slice(&len.to_be_bytes());
+    Ok(())
+}
+
+/* the bytes not read yet */
+struct Reader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, n: usize) -> Result<&'a [u8], FormatError> {
+        if self.bytes.len() < n {
+            return Err(FormatError::Truncated);
+        }
+        let (taken, rest) = self.bytes.split_at(n);
+        self.bytes = rest;
+        Ok(taken)
+    }
+
+    fn array<const N: usize>(&mut self) -> Result<[u8; N], FormatError> {
+        Ok(self.take(N)?.try_into().unwrap())
+    }
+
+    fn len(&mut self) -> Result<usize, FormatError> {
+        Ok(u32::from_be_bytes(self.array()?) as usize)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::bytecode_file::{FormatError, VERSION};
+    use crate::code::{make, Opcode};
+    use crate::compiler::{Bytecode, Compiler};
+    use crate::lexer::Lexer;
+    use crate::object::{Object, ObjectType};
+    use crate::parser::Parser;
+    use crate::vm::Vm;
+
+    fn compile(input: &str) -> Bytecode {
+        let mut p = Parser::new(Lexer::new(input));
+        let program = p.parse();
+        assert!(p.get_errors().is_empty(), "{:?}", p.get_errors());
+        Compiler::new().compile(&program).unwrap()
+    }
+
+    fn run(bytecode: Bytecode) -> Option<Object> {
+        let mut vm = Vm::new(bytecode);
+        vm.run().unwrap();
+        vm.last_popped().cloned()
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let tests = [
+            "",
+            "1 + 2 * 3",
+            "let a = 5; let b = -a; if (a > b) { a - b } else { 0 }",
+            "let x = true; !x == false",
+            "if (1 > 2) { 10 }",
+        ];
+        for input in tests {
+            let bytecode = compile(input);
+            let bytes = bytecode.serialize().unwrap();
+            let loaded = Bytecode::deserialize(&bytes).unwrap();
+            assert_eq!(loaded, bytecode, "{}", input);
+            assert_eq!(run(loaded), run(bytecode), "{}", input);
+        }
+    }
+
+    #[test]
+    fn test_constants_round_trip() {
+        let bytecode = Bytecode {
+            instructions: make(Opcode::Constant, &[2]),
+            constants: vec![
+                Object::Integer(i64::MIN),
+                Object::Boolean(true),
+                Object::String("héllo".into()),
+                Object::String("".into()),
+                Object::Boolean(false),
+                Object::Integer(-1),
+            ],
+        };
+        let bytes = bytecode.serialize().unwrap();
+        assert_eq!(Bytecode::deserialize(&bytes).as_ref(), Ok(&bytecode));
+        assert_eq!(run(bytecode), None);
+
+        let unsupported = Bytecode {
+            instructions: vec![],
+            constants: vec![Object::Null],
+        };
+        assert_eq!(
+            unsupported.serialize(),
+            Err(FormatError::UnsupportedConstant(ObjectType::Null))
+        );
+    }
+
+    #[test]
+    fn test_corrupt_files() {
+        let bytecode = Bytecode {
+            instructions: compile("let a = 1; a + 2").instructions,
+            constants: vec![Object::Integer(5 << 56), Object::String("ab".into())],
+        };
+        let bytes = bytecode.serialize().unwrap();
+        for len in 0..bytes.len() {
+            let res = Bytecode::deserialize(&bytes[..len]);
+            let exp = if len < 4 {
+                FormatError::NotBytecode
+            } else {
+                FormatError::Truncated
+            };
+            assert_eq!(res, Err(exp), "cut to {} bytes", len);
+        }
+
+        let corrupt = |at: usize, byte: u8| {
+            let mut bytes = bytes.clone();
+            bytes[at] = byte;
+            Bytecode::deserialize(&bytes)
+        };
+        assert_eq!(corrupt(0, b'X'), Err(FormatError::NotBytecode));
+        assert_eq!(
+            corrupt(5, VERSION as u8 + 1),
+            Err(FormatError::UnsupportedVersion(VERSION + 1))
+        );
+        // the first constant's tag comes after the magic, version and count
+        assert_eq!(corrupt(10, 7), Err(FormatError::UnknownTag(7)));
+        assert_eq!(corrupt(10, 1), Err(FormatError::InvalidBoolean(5)));
+        // the string's bytes come after its tag and length
+        assert_eq!(corrupt(19 + 5, 0xff), Err(FormatError::InvalidString));
+        // a count larger than the file
+        assert_eq!(corrupt(6, 0xff), Err(FormatError::Truncated));
+
+        let mut trailing = bytes.clone();
+        trailing.push(0);
+        assert_eq!(
+            Bytecode::deserialize(&trailing),
+            Err(FormatError::TrailingBytes(1))
+        );
+        assert_eq!(
+            FormatError::UnsupportedVersion(9).to_string(),
+            "unsupported bytecode version 9, expected 1"
+        );
+    }
+}
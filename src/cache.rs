@@ -0,0 +1,169 @@
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+use std::rc::Rc;
+
+use crate::ast::Program;
+use crate::lexer::Lexer;
+use crate::parser::Parser;
+
+struct Entry {
+    source: Box<str>,
+    program: Rc<Program>,
+    last_used: u64,
+}
+
+/// Parsed programs keyed by a hash of their source, so loading the same
+/// source twice only parses it once. Holds at most `capacity` programs and
+/// evicts the least recently used one when full.
+pub struct ProgramCache {
+    entries: HashMap<u64, Entry>,
+    capacity: usize,
+    clock: u64,
+    hits: usize,
+    misses: usize,
+}
+
+impl ProgramCache {
+    pub fn new(capacity: usize) -> Self {
+        ProgramCache {
+            entries: HashMap::new(),
+            capacity,
+            clock: 0,
+            hits: 0,
+            misses: 0,
+        }
+    }
+
+    /// Returns the cached program for `source`, parsing it on a miss.
+    /// Sources that fail to parse are not cached.
+    pub fn get_or_parse(&mut self, source: &str) -> Result<Rc<Program>, Vec<String>> {
+        self.clock += 1;
+        let key = hash_source(source);
+        if let Some(entry) = self.entries.get_mut(&key) {
+            // a hash collision is treated like a changed source
+            if *entry.source == *source {
+                entry.last_used = self.clock;
+                self.hits += 1;
+                return Ok(entry.program.clone());
+            }
+        }
+
+        self.misses += 1;
+        let mut p = Parser::new(Lexer::new(source));
+        let program = p.parse();
+        if p.errors_len() != 0 {
+            return Err(p.get_errors().clone());
+        }
+        let program = Rc::new(program);
+        if self.capacity == 0 {
+            return Ok(program);
+        }
+        if self.entries.len() >= self.capacity && !self.entries.contains_key(&key) {
+            self.evict();
+        }
+        self.entries.insert(
+            key,
+            Entry {
+                source: source.into(),
+                program: program.clone(),
+                last_used: self.clock,
+            },
+        );
+        Ok(program)
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn hits(&self) -> usize {
+        self.hits
+    }
+
+    /// Every miss is one parse.
+    pub fn misses(&self) -> usize {
+        self.misses
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+    }
+
+    fn evict(&mut self) {
+        let oldest = self
+            .entries
+            .iter()
+            .min_by_key(|(_, entry)| entry.last_used)
+            .map(|(key, _)| *key);
+        if let Some(key) = oldest {
+            self.entries.remove(&key);
+        }
+    }
+}
+
+fn hash_source(source: &str) -> u64 {
+    let mut hasher = std::collections::hash_map::DefaultHasher::new();
+    source.hash(&mut hasher);
+    hasher.finish()
+}
+
+#[cfg(test)]
+mod test {
+    use std::rc::Rc;
+
+    use crate::{cache::ProgramCache, environment::Environment, evaluator::eval, object::Object};
+
+    #[test]
+    fn test_identical_source_parses_once() {
+        let mut cache = ProgramCache::new(8);
+        let source = "let add = fn(a, b) { a + b }; add(1, 2);";
+        let first = cache.get_or_parse(source).unwrap();
+        let second = cache.get_or_parse(source).unwrap();
+        assert!(Rc::ptr_eq(&first, &second));
+        assert_eq!(cache.misses(), 1);
+        assert_eq!(cache.hits(), 1);
+
+        let mut env = Environment::new();
+        assert_eq!(eval(&second, &mut env), Some(Object::Integer(3)));
+    }
+
+    #[test]
+    fn test_modified_source_is_reparsed() {
+        let mut cache = ProgramCache::new(8);
+        let first = cache.get_or_parse("let x = 1; x").unwrap();
+        let second = cache.get_or_parse("let x = 2; x").unwrap();
+        assert!(!Rc::ptr_eq(&first, &second));
+        assert_eq!(cache.misses(), 2);
+        assert_eq!(cache.hits(), 0);
+        assert_eq!(cache.len(), 2);
+    }
+
+    #[test]
+    fn test_errors_are_not_cached() {
+        let mut cache = ProgramCache::new(8);
+        assert!(cache.get_or_parse("let = 1;").is_err());
+        assert!(cache.get_or_parse("let = 1;").is_err());
+        assert_eq!(cache.misses(), 2);
+        assert!(cache.is_empty());
+    }
+
+    #[test]
+    fn test_evicts_least_recently_used() {
+        let mut cache = ProgramCache::new(2);
+        cache.get_or_parse("1").unwrap();
+        cache.get_or_parse("2").unwrap();
+        cache.get_or_parse("1").unwrap();
+        cache.get_or_parse("3").unwrap();
+        assert_eq!(cache.len(), 2);
+
+        // "2" was evicted, "1" was used more recently
+        cache.get_or_parse("1").unwrap();
+        assert_eq!(cache.hits(), 2);
+        cache.get_or_parse("2").unwrap();
+        assert_eq!(cache.misses(), 4);
+    }
+}
@@ -0,0 +1,264 @@
+//! The bytecode the [`compiler`](crate::compiler) produces, laid out as in
+//! "Writing a Compiler in Go": each instruction is a one-byte [`Opcode`]
+//! followed by its operands, big-endian and as wide as its [`Definition`]
+//! says.
+
+use std::fmt::Write;
+
+use crate::object::{Object, ObjectTrait};
+
+pub type Instructions = Vec<u8>;
+
+#[non_exhaustive]
+#[repr(u8)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Opcode {
+    /// Pushes the constant at the index of its operand.
+    Constant,
+    Add,
+    Sub,
+    Mul,
+    Div,
+    True,
+    False,
+    Equal,
+    NotEqual,
+    GreaterThan,
+    Minus,
+    Bang,
+    /// Drops the value of an expression statement.
+    Pop,
+    /// Continues at the offset of its operand.
+    Jump,
+    /// Pops the condition and jumps to the offset of its operand if it
+    /// isn't truthy.
+    JumpNotTruthy,
+    Null,
+    /// Pops a value into the global at the index of its operand.
+    SetGlobal,
+    /// Pushes the global at the index of its operand.
+    GetGlobal,
+    /// After the others so bytecode from before it keeps its opcodes.
+    LessThan,
+}
+
+/// An opcode's name as listings print it, and the width in bytes of each
+/// of its operands.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Definition {
+    pub name: &'static str,
+    pub operand_widths: &'static [usize],
+}
+
+const OPCODES: [Opcode; 19] = [
+    Opcode::Constant,
+    Opcode::Add,
+    Opcode::Sub,
+    Opcode::Mul,
+    Opcode::Div,
+    Opcode::True,
+    Opcode::False,
+    Opcode::Equal,
+    Opcode::NotEqual,
+    Opcode::GreaterThan,
+    Opcode::Minus,
+    Opcode::Bang,
+    Opcode::Pop,
+    Opcode::Jump,
+    Opcode::JumpNotTruthy,
+    Opcode::Null,
+    Opcode::SetGlobal,
+    Opcode::GetGlobal,
+    Opcode::LessThan,
+];
+
+impl Opcode {
+    pub fn definition(self) -> Definition {
+        let (name, operand_widths): (_, &[usize]) = match self {
+            Opcode::Constant => ("OpConstant", &[2]),
+            Opcode::Add => ("OpAdd", &[]),
+            Opcode::Sub => ("OpSub", &[]),
+            Opcode::Mul => ("OpMul", &[]),
+            Opcode::Div => ("OpDiv", &[]),
+            Opcode::True => ("OpTrue", &[]),
+            Opcode::False => ("OpFalse", &[]),
+            Opcode::Equal => ("OpEqual", &[]),
+            Opcode::NotEqual => ("OpNotEqual", &[]),
+            Opcode::GreaterThan => ("OpGreaterThan", &[]),
+            Opcode::Minus => ("OpMinus", &[]),
+            Opcode::Bang => ("OpBang", &[]),
+            Opcode::Pop => ("OpPop", &[]),
+            Opcode::Jump => ("OpJump", &[2]),
+            Opcode::JumpNotTruthy => ("OpJumpNotTruthy", &[2]),
+            Opcode::Null => ("OpNull", &[]),
+            Opcode::SetGlobal => ("OpSetGlobal", &[2]),
+            Opcode::GetGlobal => ("OpGetGlobal", &[2]),
+            Opcode::LessThan => ("OpLessThan", &[]),
+        };
+        Definition {
+            name,
+            operand_widths,
+        }
+    }
+
+    /// The opcode a byte stands for, if any.
+    pub fn lookup(byte: u8) -> Option<Opcode> {
+        OPCODES.get(byte as usize).copied()
+    }
+}
+
+/// Encodes one instruction. Operands are truncated to their width, and
+/// missing ones are zero.
+pub fn make(op: Opcode, operands: &[usize]) -> Instructions {
+    let widths = op.definition().operand_widths;
+    let mut res = Vec::with_capacity(1 + widths.iter().sum::<usize>());
+    res.push(op as u8);
+    for (i, width) in widths.iter().enumerate() {
+        let operand = operands.get(i).copied().unwrap_or(0);
+        match width {
+            2 => res.extend_from_slice(&(operand as u16).to_be_bytes()),
+            _ => unreachable!("no operand is {} bytes wide", width),
+        }
+    }
+    res
+}
+
+/// Decodes the operands of an instruction from the bytes after its
+/// opcode, with how many bytes they took.
+pub fn read_operands(def: &Definition, ins: &[u8]) -> (Vec<usize>, usize) {
+    let mut operands = Vec::with_capacity(def.operand_widths.len());
+    let mut offset = 0;
+    for width in def.operand_widths {
+        match width {
+            2 => operands.push(u16::from_be_bytes([ins[offset], ins[offset + 1]]) as usize),
+            _ => unreachable!("no operand is {} bytes wide", width),
+        }
+        offset += width;
+    }
+    (operands, offset)
+}
+
+/// Lists the instructions one per line, with the offset each starts at and
+/// the value of each constant an `OpConstant` pushes. Bytes that aren't an
+/// opcode, and an instruction cut short, are reported at their offset.
+pub fn disassemble(ins: &[u8], constants: &[Object]) -> String {
+    let mut res = String::new();
+    let mut offset = 0;
+    while offset < ins.len() {
+        let Some(op) = Opcode::lookup(ins[offset]) else {
+            writeln!(res, "{:04} unknown opcode {}", offset, ins[offset]).unwrap();
+            offset += 1;
+            continue;
+        };
+        let def = op.definition();
+        let width: usize = def.operand_widths.iter().sum();
+        if ins.len() - offset - 1 < width {
+            writeln!(
+                res,
+                "{:04} {} truncated, {} of {} operand bytes",
+                offset,
+                def.name,
+                ins.len() - offset - 1,
+                width
+            )
+            .unwrap();
+            break;
+        }
+        let (operands, read) = read_operands(&def, &ins[offset + 1..]);
+        write!(res, "{:04} {}", offset, def.name).unwrap();
+        for operand in operands.iter() {
+            write!(res, " {}", operand).unwrap();
+        }
+        if op == Opcode::Constant {
+            match constants.get(operands[0]) {
+                Some(constant) => write!(res, " (= {})", constant.inspect()).unwrap(),
+                None => res.push_str(" (no such constant)"),
+            }
+        }
+        res.push('\n');
+        offset += 1 + read;
+    }
+    res
+}
+
+#[cfg(test)]
+mod test {
+    use crate::code::{disassemble, make, read_operands, Opcode, OPCODES};
+    use crate::object::Object;
+
+    #[test]
+    fn test_make() {
+        let tests: [(Opcode, &[usize], &[u8]); 3] = [
+            (
+                Opcode::Constant,
+                &[65534],
+                &[Opcode::Constant as u8, 255, 254],
+            ),
+            (Opcode::Add, &[], &[Opcode::Add as u8]),
+            (Opcode::Pop, &[], &[Opcode::Pop as u8]),
+        ];
+        for (op, operands, exp) in tests {
+            assert_eq!(make(op, operands), exp, "making {:?}", op);
+        }
+    }
+
+    #[test]
+    fn test_read_operands() {
+        let ins = make(Opcode::Constant, &[65535]);
+        let def = Opcode::Constant.definition();
+        assert_eq!(read_operands(&def, &ins[1..]), (vec![65535], 2));
+    }
+
+    #[test]
+    fn test_lookup() {
+        for (i, op) in OPCODES.iter().enumerate() {
+            assert_eq!(*op as u8 as usize, i);
+            assert_eq!(Opcode::lookup(i as u8), Some(*op));
+        }
+        assert_eq!(Opcode::lookup(OPCODES.len() as u8), None);
+    }
+
+    #[test]
+    fn test_disassemble() {
+        let ins = [
+            make(Opcode::Add, &[]),
+            make(Opcode::Constant, &[1]),
+            make(Opcode::Constant, &[65535]),
+        ]
+        .concat();
+        let constants = [Object::Integer(1), Object::Integer(2)];
+        assert_eq!(
+            disassemble(&ins, &constants),
+            "0000 OpAdd\n0001 OpConstant 1 (= 2)\n0004 OpConstant 65535 (no such constant)\n"
+        );
+    }
+
+    #[test]
+    fn test_disassemble_corrupt() {
+        let tests: [(&[u8], &str); 4] = [
+            (&[], ""),
+            (
+                &[255, Opcode::Pop as u8],
+                "0000 unknown opcode 255\n0001 OpPop\n",
+            ),
+            (
+                &[Opcode::Pop as u8, Opcode::Constant as u8, 0],
+                "0000 OpPop\n0001 OpConstant truncated, 1 of 2 operand bytes\n",
+            ),
+            (
+                &[Opcode::Constant as u8],
+                "0000 OpConstant truncated, 0 of 2 operand bytes\n",
+            ),
+        ];
+        for (ins, exp) in tests {
+            assert_eq!(disassemble(ins, &[]), exp, "disassembling {:?}", ins);
+        }
+        // every byte string disassembles to something
+        for a in 0..=u8::MAX {
+            for b in [0, 1, a, u8::MAX] {
+                disassemble(&[a, b, a], &[]);
+                disassemble(&[b, a], &[]);
+            }
+        }
+    }
+}
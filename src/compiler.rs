@@ -0,0 +1,645 @@
+//! Compiles a [`Program`] to [`code`](crate::code) instructions, which the
+//! [`vm`](crate::vm) runs. Only the expression subset is supported so far:
+//! integer and boolean literals, the prefix and infix operators on them,
+//! `if`, and `let` and `const` at the top level.
+
+use std::rc::Rc;
+
+use crate::ast::{
+    BlockStatement, Expression, IfExpression, InfixExpression, InfixOperator, LetStatement,
+    PrefixOperator, Program, Statement,
+};
+use crate::code::{make, Instructions, Opcode};
+use crate::object::Object;
+use crate::peephole;
+use crate::symbol_table::{SymbolScope, SymbolTable};
+
+/// What the compiler produces: the instructions of the whole program, and
+/// the constants their `OpConstant`s refer to by index.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Bytecode {
+    pub instructions: Instructions,
+    pub constants: Vec<Object>,
+}
+
+#[non_exhaustive]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CompileError {
+    /// A statement or expression the compiler doesn't handle yet.
+    Unsupported(&'static str),
+    /// More constants than an `OpConstant` operand can index.
+    TooManyConstants,
+    /// More instructions than a jump operand can reach.
+    TooLong,
+    /// More globals than an `OpSetGlobal` operand can index.
+    TooManyGlobals,
+    /// A name that no `let` or `const` before it defines.
+    UndefinedVariable(Rc<str>),
+    /// A `let` or `const` of a name a `const` already defines.
+    ConstReassignment(Rc<str>),
+}
+
+impl std::fmt::Display for CompileError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Unsupported(what) => write!(f, "can't compile {} yet", what),
+            Self::TooManyConstants => {
+                write!(f, "too many constants, the limit is {}", MAX_CONSTANTS)
+            }
+            Self::TooLong => write!(f, "too long, jumps reach {} bytes at most", u16::MAX),
+            Self::TooManyGlobals => write!(f, "too many globals, the limit is {}", MAX_GLOBALS),
+            Self::UndefinedVariable(name) => write!(f, "undefined variable: {}", name),
+            Self::ConstReassignment(name) => write!(f, "cannot reassign const '{}'", name),
+        }
+    }
+}
+
+impl std::error::Error for CompileError {}
+
+pub(crate) const MAX_CONSTANTS: usize = u16::MAX as usize + 1;
+pub(crate) const MAX_GLOBALS: usize = u16::MAX as usize + 1;
+
+#[derive(Default)]
+pub struct Compiler {
+    instructions: Instructions,
+    constants: Vec<Object>,
+    symbols: SymbolTable,
+    /* the opcodes and offsets of the last two instructions emitted */
+    last: Option<(Opcode, usize)>,
+    previous: Option<(Opcode, usize)>,
+    optimize: bool,
+}
+
+impl Compiler {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Runs the [`peephole`](crate::peephole) optimizer over what
+    /// [`compile`](Compiler::compile) produces.
+    pub fn with_optimizations(mut self) -> Self {
+        self.optimize = true;
+        self
+    }
+
+    /// Compiles `program`, each expression statement leaving nothing on
+    /// the stack once it's done.
+    pub fn compile(mut self, program: &Program) -> Result<Bytecode, CompileError> {
+        for stmt in program.statements.iter() {
+            self.statement(stmt)?;
+        }
+        let bytecode = Bytecode {
+            instructions: self.instructions,
+            constants: self.constants,
+        };
+        Ok(if self.optimize {
+            peephole::optimize(bytecode)
+        } else {
+            bytecode
+        })
+    }
+
+    /* returns the offset of the instruction */
+    fn emit(&mut self, op: Opcode, operands: &[usize]) -> usize {
+        let position = self.instructions.len();
+        self.instructions.extend(make(op, operands));
+        self.previous = self.last.replace((op, position));
+        position
+    }
+
+    fn remove_last_pop(&mut self) {
+        if let Some((Opcode::Pop, position)) = self.last {
+            self.instructions.truncate(position);
+            self.last = self.previous.take();
+        }
+    }
+
+    /* replaces the operand of the jump emitted at `position` */
+    fn patch_jump(&mut self, position: usize, target: usize) -> Result<(), CompileError> {
+        let target = u16::try_from(target).map_err(|_| CompileError::TooLong)?;
+        self.instructions[position + 1..position + 3].copy_from_slice(&target.to_be_bytes());
+        Ok(())
+    }
+
+    fn add_constant(&mut self, obj: Object) -> Result<usize, CompileError> {
+        if self.constants.len() == MAX_CONSTANTS {
+            return Err(CompileError::TooManyConstants);
+        }
+        self.constants.push(obj);
+        Ok(self.constants.len() - 1)
+    }
+
+    fn statement(&mut self, stmt: &Statement) -> Result<(), CompileError> {
+        match stmt {
+            Statement::ExpressionStatement(es) => {
+                self.expression(&es.expression)?;
+                self.emit(Opcode::Pop, &[]);
+                Ok(())
+            }
+            Statement::LetStatement(ls) => self.let_statement(ls),
+            Statement::LetTupleStatement(_) => {
+                Err(CompileError::Unsupported("tuple destructuring"))
+            }
+            Statement::ReturnStatement(_) => Err(CompileError::Unsupported("return statements")),
+            Statement::BreakStatement(_) | Statement::ContinueStatement(_) => {
+                Err(CompileError::Unsupported("loops"))
+            }
+            Statement::ImportStatement(_) => Err(CompileError::Unsupported("imports")),
+        }
+    }
+
+    fn let_statement(&mut self, ls: &LetStatement) -> Result<(), CompileError> {
+        self.expression(&ls.value)?;
+        let name = &ls.name.value;
+        if self.symbols.resolve(name).is_some_and(|sym| !sym.mutable) {
+            return Err(CompileError::ConstReassignment(name.clone()));
+        }
+        let sym = self.symbols.define(name.clone(), ls.mutable);
+        if sym.index == MAX_GLOBALS {
+            return Err(CompileError::TooManyGlobals);
+        }
+        self.emit(Opcode::SetGlobal, &[sym.index]);
+        Ok(())
+    }
+
+    fn expression(&mut self, exp: &Expression) -> Result<(), CompileError> {
+        match exp {
+            Expression::Integer(int) => {
+                let index = self.add_constant(Object::Integer(int.value))?;
+                self.emit(Opcode::Constant, &[index]);
+            }
+            Expression::Float(float) => {
+                let index = self.add_constant(Object::Float(float.value))?;
+                self.emit(Opcode::Constant, &[index]);
+            }
+            Expression::Boolean(b) => {
+                self.emit(if b.value { Opcode::True } else { Opcode::False }, &[]);
+            }
+            Expression::PrefixExpression(pe) => {
+                self.expression(&pe.right)?;
+                let op = match pe.operator {
+                    PrefixOperator::Bang => Opcode::Bang,
+                    PrefixOperator::Minus => Opcode::Minus,
+                };
+                self.emit(op, &[]);
+            }
+            Expression::InfixExpression(ie) => self.infix(ie)?,
+            Expression::IfExpression(ife) => self.if_expression(ife)?,
+            Expression::Identifier(ident) => {
+                let sym = self
+                    .symbols
+                    .resolve(&ident.value)
+                    .ok_or_else(|| CompileError::UndefinedVariable(ident.value.clone()))?;
+                let op = match sym.scope {
+                    SymbolScope::Global => Opcode::GetGlobal,
+                    SymbolScope::Local => return Err(CompileError::Unsupported("locals")),
+                };
+                let index = sym.index;
+                self.emit(op, &[index]);
+            }
+            Expression::String(_) => return Err(CompileError::Unsupported("strings")),
+            Expression::Array(_) => return Err(CompileError::Unsupported("arrays")),
+            Expression::Tuple(_) => return Err(CompileError::Unsupported("tuples")),
+            Expression::Hash(_) => return Err(CompileError::Unsupported("hashes")),
+            Expression::FunctionLiteral(_) => return Err(CompileError::Unsupported("functions")),
+            Expression::Assign(_) => return Err(CompileError::Unsupported("assignments")),
+            Expression::ForExpression(_) => return Err(CompileError::Unsupported("loops")),
+            Expression::MacroLiteral(_) => return Err(CompileError::Unsupported("macros")),
+            Expression::CallExpression(_) => return Err(CompileError::Unsupported("calls")),
+            Expression::IndexExpression(_) => {
+                return Err(CompileError::Unsupported("index expressions"))
+            }
+        }
+        Ok(())
+    }
+
+    fn if_expression(&mut self, ife: &IfExpression) -> Result<(), CompileError> {
+        self.expression(&ife.condition)?;
+        // where the jumps go is known once the branches are compiled
+        let jump_not_truthy = self.emit(Opcode::JumpNotTruthy, &[0]);
+        self.block(&ife.consequence)?;
+        let jump = self.emit(Opcode::Jump, &[0]);
+        self.patch_jump(jump_not_truthy, self.instructions.len())?;
+        match &ife.alternative {
+            Some(alt) => self.block(alt)?,
+            None => {
+                self.emit(Opcode::Null, &[]);
+            }
+        }
+        self.patch_jump(jump, self.instructions.len())
+    }
+
+    /* leaves the value of the block on the stack, null if it doesn't end
+     * in an expression statement */
+    fn block(&mut self, block: &BlockStatement) -> Result<(), CompileError> {
+        for stmt in block.statements.iter() {
+            self.statement(stmt)?;
+        }
+        if matches!(
+            block.statements.last(),
+            Some(Statement::ExpressionStatement(_))
+        ) {
+            self.remove_last_pop();
+        } else {
+            self.emit(Opcode::Null, &[]);
+        }
+        Ok(())
+    }
+
+    fn infix(&mut self, ie: &InfixExpression) -> Result<(), CompileError> {
+        // a long `1 + 1 + ...` nests down the left, so walk that spine in a
+        // loop
+        let mut ops = Vec::new();
+        let mut ie = ie;
+        loop {
+            ops.push(ie);
+            match &*ie.left {
+                Expression::InfixExpression(left) => ie = left,
+                left => break self.expression(left)?,
+            }
+        }
+        for ie in ops.into_iter().rev() {
+            self.expression(&ie.right)?;
+            let op = match ie.operator {
+                InfixOperator::Plus => Opcode::Add,
+                InfixOperator::Minus => Opcode::Sub,
+                InfixOperator::Asterisk => Opcode::Mul,
+                InfixOperator::Slash => Opcode::Div,
+                InfixOperator::Gt => Opcode::GreaterThan,
+                InfixOperator::Eq => Opcode::Equal,
+                InfixOperator::NotEq => Opcode::NotEqual,
+                InfixOperator::Lt => Opcode::LessThan,
+            };
+            self.emit(op, &[]);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::rc::Rc;
+
+    use crate::ast::{Expression, Program, Statement};
+    use crate::code::{disassemble, make, Instructions, Opcode};
+    use crate::compiler::{CompileError, Compiler};
+    use crate::lexer::Lexer;
+    use crate::object::Object;
+    use crate::parser::Parser;
+
+    fn parse(input: &str) -> Program {
+        let mut p = Parser::new(Lexer::new(input));
+        let program = p.parse();
+        assert!(p.get_errors().is_empty(), "{:?}", p.get_errors());
+        program
+    }
+
+    fn check(tests: &[(&str, Vec<Object>, Vec<Instructions>)]) {
+        for (input, constants, instructions) in tests {
+            let bytecode = Compiler::new()
+                .compile(&parse(input))
+                .unwrap_or_else(|e| panic!("compiling {:?}: {}", input, e));
+            // compared as listings, which say where they differ
+            assert_eq!(
+                disassemble(&bytecode.instructions, &bytecode.constants),
+                disassemble(&instructions.concat(), constants),
+                "compiling {:?}",
+                input
+            );
+            assert_eq!(bytecode.instructions, instructions.concat());
+            assert_eq!(&bytecode.constants, constants, "compiling {:?}", input);
+        }
+    }
+
+    #[test]
+    fn test_integer_arithmetic() {
+        let ints = |values: &[i64]| values.iter().map(|&v| Object::Integer(v)).collect();
+        let tests = [
+            (
+                "1 + 2",
+                ints(&[1, 2]),
+                vec![
+                    make(Opcode::Constant, &[0]),
+                    make(Opcode::Constant, &[1]),
+                    make(Opcode::Add, &[]),
+                    make(Opcode::Pop, &[]),
+                ],
+            ),
+            (
+                "1; 2",
+                ints(&[1, 2]),
+                vec![
+                    make(Opcode::Constant, &[0]),
+                    make(Opcode::Pop, &[]),
+                    make(Opcode::Constant, &[1]),
+                    make(Opcode::Pop, &[]),
+                ],
+            ),
+            (
+                "1 - 2 * 3 / 4",
+                ints(&[1, 2, 3, 4]),
+                vec![
+                    make(Opcode::Constant, &[0]),
+                    make(Opcode::Constant, &[1]),
+                    make(Opcode::Constant, &[2]),
+                    make(Opcode::Mul, &[]),
+                    make(Opcode::Constant, &[3]),
+                    make(Opcode::Div, &[]),
+                    make(Opcode::Sub, &[]),
+                    make(Opcode::Pop, &[]),
+                ],
+            ),
+            (
+                "-(1 + 2)",
+                ints(&[1, 2]),
+                vec![
+                    make(Opcode::Constant, &[0]),
+                    make(Opcode::Constant, &[1]),
+                    make(Opcode::Add, &[]),
+                    make(Opcode::Minus, &[]),
+                    make(Opcode::Pop, &[]),
+                ],
+            ),
+        ];
+        check(&tests);
+    }
+
+    #[test]
+    fn test_boolean_expressions() {
+        let tests = [
+            (
+                "true != false",
+                vec![],
+                vec![
+                    make(Opcode::True, &[]),
+                    make(Opcode::False, &[]),
+                    make(Opcode::NotEqual, &[]),
+                    make(Opcode::Pop, &[]),
+                ],
+            ),
+            (
+                "1 > 2",
+                vec![Object::Integer(1), Object::Integer(2)],
+                vec![
+                    make(Opcode::Constant, &[0]),
+                    make(Opcode::Constant, &[1]),
+                    make(Opcode::GreaterThan, &[]),
+                    make(Opcode::Pop, &[]),
+                ],
+            ),
+            // the operands of `<` are evaluated in order, like the
+            // evaluator does, so the first to fail is the same
+            (
+                "1 < 2",
+                vec![Object::Integer(1), Object::Integer(2)],
+                vec![
+                    make(Opcode::Constant, &[0]),
+                    make(Opcode::Constant, &[1]),
+                    make(Opcode::LessThan, &[]),
+                    make(Opcode::Pop, &[]),
+                ],
+            ),
+            (
+                "1 + 2 < 3 == !true",
+                vec![Object::Integer(1), Object::Integer(2), Object::Integer(3)],
+                vec![
+                    make(Opcode::Constant, &[0]),
+                    make(Opcode::Constant, &[1]),
+                    make(Opcode::Add, &[]),
+                    make(Opcode::Constant, &[2]),
+                    make(Opcode::LessThan, &[]),
+                    make(Opcode::True, &[]),
+                    make(Opcode::Bang, &[]),
+                    make(Opcode::Equal, &[]),
+                    make(Opcode::Pop, &[]),
+                ],
+            ),
+        ];
+        check(&tests);
+    }
+
+    #[test]
+    fn test_listings() {
+        let tests = [
+            ("", ""),
+            ("true", "0000 OpTrue\n0001 OpPop\n"),
+            (
+                "1 + 2; -3",
+                "0000 OpConstant 0 (= 1)\n\
+                 0003 OpConstant 1 (= 2)\n\
+                 0006 OpAdd\n\
+                 0007 OpPop\n\
+                 0008 OpConstant 2 (= 3)\n\
+                 0011 OpMinus\n\
+                 0012 OpPop\n",
+            ),
+            (
+                "4 < 5 != !false",
+                "0000 OpConstant 0 (= 4)\n\
+                 0003 OpConstant 1 (= 5)\n\
+                 0006 OpLessThan\n\
+                 0007 OpFalse\n\
+                 0008 OpBang\n\
+                 0009 OpNotEqual\n\
+                 0010 OpPop\n",
+            ),
+        ];
+        for (input, exp) in tests {
+            let bytecode = Compiler::new().compile(&parse(input)).unwrap();
+            assert_eq!(
+                disassemble(&bytecode.instructions, &bytecode.constants),
+                exp,
+                "compiling {:?}",
+                input
+            );
+        }
+    }
+
+    #[test]
+    fn test_conditionals() {
+        let tests = [
+            (
+                "if (true) { 10 } else { 20 }; 3333;",
+                vec![
+                    Object::Integer(10),
+                    Object::Integer(20),
+                    Object::Integer(3333),
+                ],
+                vec![
+                    make(Opcode::True, &[]),
+                    make(Opcode::JumpNotTruthy, &[10]),
+                    make(Opcode::Constant, &[0]),
+                    make(Opcode::Jump, &[13]),
+                    make(Opcode::Constant, &[1]),
+                    make(Opcode::Pop, &[]),
+                    make(Opcode::Constant, &[2]),
+                    make(Opcode::Pop, &[]),
+                ],
+            ),
+            (
+                "if (true) { 10 }; 3333;",
+                vec![Object::Integer(10), Object::Integer(3333)],
+                vec![
+                    make(Opcode::True, &[]),
+                    make(Opcode::JumpNotTruthy, &[10]),
+                    make(Opcode::Constant, &[0]),
+                    make(Opcode::Jump, &[11]),
+                    make(Opcode::Null, &[]),
+                    make(Opcode::Pop, &[]),
+                    make(Opcode::Constant, &[1]),
+                    make(Opcode::Pop, &[]),
+                ],
+            ),
+            // a block keeps the value of its last statement only
+            (
+                "if (1 > 2) { 3; 4 } else {}",
+                vec![
+                    Object::Integer(1),
+                    Object::Integer(2),
+                    Object::Integer(3),
+                    Object::Integer(4),
+                ],
+                vec![
+                    make(Opcode::Constant, &[0]),
+                    make(Opcode::Constant, &[1]),
+                    make(Opcode::GreaterThan, &[]),
+                    make(Opcode::JumpNotTruthy, &[20]),
+                    make(Opcode::Constant, &[2]),
+                    make(Opcode::Pop, &[]),
+                    make(Opcode::Constant, &[3]),
+                    make(Opcode::Jump, &[21]),
+                    make(Opcode::Null, &[]),
+                    make(Opcode::Pop, &[]),
+                ],
+            ),
+        ];
+        check(&tests);
+    }
+
+    /* takes the left spine apart in a loop, dropping a long chain
+     * recursively would overflow the stack */
+    fn drop_spine(mut program: Program) {
+        let Some(Statement::ExpressionStatement(es)) = program.statements.pop() else {
+            return;
+        };
+        let mut exp = es.expression;
+        while let Expression::InfixExpression(ie) = exp {
+            exp = Rc::try_unwrap(ie.left).expect("left operand is shared");
+        }
+    }
+
+    #[test]
+    fn test_long_sum() {
+        let program = parse(&vec!["1"; 60_000].join(" + "));
+        let bytecode = Compiler::new().compile(&program).unwrap();
+        assert_eq!(bytecode.constants.len(), 60_000);
+        drop_spine(program);
+
+        let program = parse(&vec!["1"; 65537].join(" + "));
+        let res = Compiler::new().compile(&program);
+        assert_eq!(res, Err(CompileError::TooManyConstants));
+        drop_spine(program);
+    }
+
+    #[test]
+    fn test_jump_too_far() {
+        let fits = format!("if (true) {{ {} }}", "true; ".repeat(32_000));
+        assert!(Compiler::new().compile(&parse(&fits)).is_ok());
+        let too_far = format!("if (true) {{ {} }}", "true; ".repeat(33_000));
+        let res = Compiler::new().compile(&parse(&too_far));
+        assert_eq!(res, Err(CompileError::TooLong));
+    }
+
+    #[test]
+    fn test_global_let_statements() {
+        let tests = [
+            (
+                "let one = 1; let two = one + one; two;",
+                vec![Object::Integer(1)],
+                vec![
+                    make(Opcode::Constant, &[0]),
+                    make(Opcode::SetGlobal, &[0]),
+                    make(Opcode::GetGlobal, &[0]),
+                    make(Opcode::GetGlobal, &[0]),
+                    make(Opcode::Add, &[]),
+                    make(Opcode::SetGlobal, &[1]),
+                    make(Opcode::GetGlobal, &[1]),
+                    make(Opcode::Pop, &[]),
+                ],
+            ),
+            // a name defined again keeps its global
+            (
+                "let x = true; const y = x; let x = !x;",
+                vec![],
+                vec![
+                    make(Opcode::True, &[]),
+                    make(Opcode::SetGlobal, &[0]),
+                    make(Opcode::GetGlobal, &[0]),
+                    make(Opcode::SetGlobal, &[1]),
+                    make(Opcode::GetGlobal, &[0]),
+                    make(Opcode::Bang, &[]),
+                    make(Opcode::SetGlobal, &[0]),
+                ],
+            ),
+        ];
+        check(&tests);
+    }
+
+    #[test]
+    fn test_name_errors() {
+        let tests = [
+            ("x", CompileError::UndefinedVariable("x".into())),
+            ("let x = x;", CompileError::UndefinedVariable("x".into())),
+            (
+                "let a = 1; if (a) { b }",
+                CompileError::UndefinedVariable("b".into()),
+            ),
+            (
+                "const a = 1; let a = 2;",
+                CompileError::ConstReassignment("a".into()),
+            ),
+        ];
+        for (input, exp) in tests {
+            let res = Compiler::new().compile(&parse(input));
+            assert_eq!(res, Err(exp), "compiling {:?}", input);
+        }
+        let err = Compiler::new().compile(&parse("1 + y")).unwrap_err();
+        assert_eq!(err.to_string(), "undefined variable: y");
+    }
+
+    #[test]
+    fn test_too_many_globals() {
+        // names can't have digits, so each spells its index in base 26
+        let name = |mut i: usize| {
+            let mut name = String::from("a");
+            while i > 0 {
+                name.push((b'a' + (i % 26) as u8) as char);
+                i /= 26;
+            }
+            name
+        };
+        let lets = |n| {
+            (0..n)
+                .map(|i| format!("let {} = true;", name(i)))
+                .collect::<String>()
+        };
+        assert!(Compiler::new().compile(&parse(&lets(65536))).is_ok());
+        let res = Compiler::new().compile(&parse(&lets(65537)));
+        assert_eq!(res, Err(CompileError::TooManyGlobals));
+    }
+
+    #[test]
+    fn test_unsupported() {
+        let tests = [
+            (
+                "let a, b = (1, 2);",
+                "can't compile tuple destructuring yet",
+            ),
+            ("[1, 2]", "can't compile arrays yet"),
+        ];
+        for (input, exp) in tests {
+            let err = Compiler::new().compile(&parse(input)).unwrap_err();
+            assert_eq!(err.to_string(), exp, "compiling {:?}", input);
+        }
+    }
+}
@@ -0,0 +1,271 @@
+//! Which lines of a script ran, which `run --coverage script.mk` reports.
+//!
+//! A line counts if a statement starts on it, and it ran if any of those
+//! statements did. The statements in the blocks of an `if` count on the
+//! lines of the blocks, so an `else` that never ran shows up:
+//!
+//! ```
+//! use interpreter::coverage;
+//! use interpreter::prelude::*;
+//!
+//! let source = "let x = 1;\nif (x > 0) {\n  x\n} else {\n  -x\n}\n";
+//! let mut p = Parser::new(Lexer::new(source)).with_source_map();
+//! let program = p.parse();
+//! let source_map = p.source_map().to_vec();
+//! let (_, coverage) = coverage::run(source, program, source_map);
+//! assert_eq!(
+//!     coverage.lines().collect::<Vec<_>>(),
+//!     [(1, true), (2, true), (3, true), (5, false)]
+//! );
+//! ```
+
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::rc::Rc;
+
+use crate::ast::{Expression, Program};
+use crate::debugger::Source;
+use crate::environment::Environment;
+use crate::evaluator::eval_observed;
+use crate::object::Object;
+use crate::parser::SourceMap;
+
+/// The lines statements start on, and whether one of those ran.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Coverage {
+    lines: BTreeMap<usize, bool>,
+}
+
+impl Coverage {
+    /// The lines a statement starts on, counted from 1 and in order, with
+    /// whether one that starts there ran.
+    pub fn lines(&self) -> impl Iterator<Item = (usize, bool)> + '_ {
+        self.lines.iter().map(|(&line, &ran)| (line, ran))
+    }
+
+    /// The number of lines that ran.
+    pub fn executed(&self) -> usize {
+        self.lines.values().filter(|&&ran| ran).count()
+    }
+
+    /// The number of lines a statement starts on.
+    pub fn total(&self) -> usize {
+        self.lines.len()
+    }
+
+    /// The lines that ran out of all of them, in percent. All of nothing
+    /// ran, so a program without statements is at 100.
+    pub fn percent(&self) -> f64 {
+        match self.total() {
+            0 => 100.0,
+            total => self.executed() as f64 * 100.0 / total as f64,
+        }
+    }
+
+    /// `source`, the text the coverage is of, with `✓ ` before each line
+    /// that ran and `✗ ` before each one that didn't, followed by a summary.
+    /// The other lines are indented to match, and blank lines stay blank.
+    pub fn annotate(&self, source: &str) -> String {
+        let mut res = String::new();
+        for (i, line) in source.lines().enumerate() {
+            if !line.trim().is_empty() {
+                res.push_str(match self.lines.get(&(i + 1)) {
+                    Some(true) => "✓ ",
+                    Some(false) => "✗ ",
+                    None => "  ",
+                });
+                res.push_str(line);
+            }
+            res.push('\n');
+        }
+        res.push_str(&self.summary());
+        res.push('\n');
+        res
+    }
+
+    /// Like `3 of 4 lines ran (75.0%)`.
+    pub fn summary(&self) -> String {
+        format!(
+            "{} of {} lines ran ({:.1}%)",
+            self.executed(),
+            self.total(),
+            self.percent()
+        )
+    }
+
+    /// The coverage as a JSON object, with each line and whether it ran
+    /// under `lines` and the totals next to it.
+    pub fn to_json(&self) -> String {
+        let lines: Vec<String> = self
+            .lines()
+            .map(|(line, ran)| format!("{{\"line\":{},\"executed\":{}}}", line, ran))
+            .collect();
+        format!(
+            "{{\"lines\":[{}],\"executed\":{},\"total\":{},\"percent\":{:.1}}}",
+            lines.join(","),
+            self.executed(),
+            self.total(),
+            self.percent()
+        )
+    }
+}
+
+/// Runs `program`, parsed from `source` by a parser
+/// [`with_source_map`](crate::parser::Parser::with_source_map) that gave
+/// `source_map`, and records which of its lines ran. Gives what the
+/// program does and the coverage.
+pub fn run(
+    source: &str,
+    program: Program,
+    source_map: Vec<SourceMap>,
+) -> (Option<Object>, Coverage) {
+    let program = Rc::new(program);
+    let source = Rc::new(Source::new(source, program.clone(), source_map));
+    let mut lines = BTreeMap::new();
+    for path in statement_paths(&program) {
+        lines.extend(source.line_at(&path).map(|line| (line, false)));
+    }
+    let lines = Rc::new(RefCell::new(lines));
+    let observed = lines.clone();
+    let mut env = Environment::new();
+    let res = eval_observed(&program, &mut env, move |pause| {
+        // a `break` or `continue` has no line of its own
+        if pause.statement.expression().is_none() {
+            return;
+        }
+        if let Some(line) = source.line(&pause) {
+            observed.borrow_mut().insert(line, true);
+        }
+    });
+    let lines = lines.take();
+    (res, Coverage { lines })
+}
+
+/* the paths of the expressions of the statements in `program` that could
+ * run, which leaves out the bodies of macros and `break` and `continue` */
+fn statement_paths(program: &Program) -> Vec<Vec<usize>> {
+    let mut res = Vec::new();
+    let mut path = Vec::new();
+    for (i, stmt) in program.statements.iter().enumerate() {
+        if let Some(exp) = stmt.expression() {
+            path.push(i);
+            res.push(path.clone());
+            block_paths(exp, &mut path, &mut res);
+            path.pop();
+        }
+    }
+    res
+}
+
+/* adds the paths of the statements in blocks at or below `exp`, which is
+ * at `path` */
+fn block_paths(exp: &Expression, path: &mut Vec<usize>, res: &mut Vec<Vec<usize>>) {
+    // the expressions right below that are statements of a block
+    let blocks_from = match exp {
+        Expression::IfExpression(_) | Expression::ForExpression(_) => 1,
+        Expression::FunctionLiteral(_) => 0,
+        Expression::MacroLiteral(_) => return,
+        _ => usize::MAX,
+    };
+    for (i, child) in exp.children().into_iter().enumerate() {
+        path.push(i);
+        if i >= blocks_from {
+            res.push(path.clone());
+        }
+        block_paths(child, path, res);
+        path.pop();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::coverage::{run, Coverage};
+    use crate::lexer::Lexer;
+    use crate::object::Object;
+    use crate::parser::Parser;
+
+    fn coverage(source: &str) -> Coverage {
+        let mut p = Parser::new(Lexer::new(source)).with_source_map();
+        let program = p.parse();
+        assert!(p.get_errors().is_empty(), "{:?}", p.get_errors());
+        let source_map = p.source_map().to_vec();
+        let (res, coverage) = run(source, program, source_map);
+        assert!(!matches!(res, Some(Object::Error(_))), "{:?}", res);
+        coverage
+    }
+
+    #[test]
+    fn test_coverage() {
+        let source = "let sign = fn(x) {
+  if (x < 0) {
+    return -1;
+  } else {
+    // zero counts as positive
+    1
+  }
+};
+
+let unused = fn() {
+  let a = 1;
+  a
+};
+for (x in [1, 2]) {
+  if (x > 5) { break; }
+  sign(x);
+}
+";
+        let coverage = coverage(source);
+        let exp = [
+            (1, true),
+            (2, true),
+            (3, false),
+            (6, true),
+            (10, true),
+            (11, false),
+            (12, false),
+            (14, true),
+            (15, true),
+            (16, true),
+        ];
+        assert_eq!(coverage.lines().collect::<Vec<_>>(), exp);
+        assert_eq!((coverage.executed(), coverage.total()), (7, 10));
+        assert_eq!(coverage.percent(), 70.0);
+        assert_eq!(coverage.summary(), "7 of 10 lines ran (70.0%)");
+
+        let exp = "✓ let sign = fn(x) {
+✓   if (x < 0) {
+✗     return -1;
+    } else {
+      // zero counts as positive
+✓     1
+    }
+  };
+
+✓ let unused = fn() {
+✗   let a = 1;
+✗   a
+  };
+✓ for (x in [1, 2]) {
+✓   if (x > 5) { break; }
+✓   sign(x);
+  }
+7 of 10 lines ran (70.0%)
+";
+        assert_eq!(coverage.annotate(source), exp);
+    }
+
+    #[test]
+    fn test_coverage_json() {
+        let branch = coverage("let x = 1;\nif (x > 1) {\n  x\n}\n");
+        assert_eq!(
+            branch.to_json(),
+            "{\"lines\":[{\"line\":1,\"executed\":true},{\"line\":2,\"executed\":true},\
+             {\"line\":3,\"executed\":false}],\"executed\":2,\"total\":3,\"percent\":66.7}"
+        );
+
+        assert_eq!(
+            coverage("").to_json(),
+            "{\"lines\":[],\"executed\":0,\"total\":0,\"percent\":100.0}"
+        );
+    }
+}
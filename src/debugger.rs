@@ -0,0 +1,334 @@
+//! A debugger for scripts, which `debug script.mk` runs them under.
+//!
+//! It stops before the first statement, says which line it is on, and
+//! takes commands, one per line:
+//!
+//! - `step`, or `s`, runs to the next statement, which may be one in a
+//!   block or in a function the statement calls
+//! - `next`, or `n`, runs to the next top-level statement
+//! - `continue`, or `c`, runs to the next breakpoint, or to the end
+//! - `break line`, or `b line`, stops at every statement that starts on
+//!   `line` from then on
+//! - `print expr`, or `p expr`, evaluates `expr` where the program stopped
+//! - `locals` lists the bindings of the scope it stopped in
+//!
+//! Without more input the program runs to the end.
+
+use std::collections::BTreeSet;
+use std::io::{BufRead, Write};
+use std::ops::Range;
+use std::rc::Rc;
+
+use crate::ast::{Node, Program};
+use crate::environment::Environment;
+use crate::evaluator::{eval, eval_observed, Pause};
+use crate::lexer::{line_col, token_spans, Lexer};
+use crate::object::{Object, ObjectTrait};
+use crate::parser::{Parser, SourceMap};
+use crate::repl::bindings;
+
+const PROMPT: &str = "(debug) ";
+
+const USAGE: &str = "commands: step, next, continue, break <line>, print <expr>, locals";
+
+/// A line of input to the debugger.
+#[non_exhaustive]
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum Command {
+    Step,
+    Next,
+    Continue,
+    Break(usize),
+    Print(String),
+    Locals,
+    Help,
+}
+
+impl Command {
+    /// The command `line` is, or what is wrong with it.
+    pub fn parse(line: &str) -> Result<Self, String> {
+        let line = line.trim();
+        let (name, rest) = line
+            .split_once(char::is_whitespace)
+            .map_or((line, ""), |(name, rest)| (name, rest.trim()));
+        let command = match name {
+            "step" | "s" => Command::Step,
+            "next" | "n" => Command::Next,
+            "continue" | "c" => Command::Continue,
+            "locals" => Command::Locals,
+            "help" | "h" => Command::Help,
+            "break" | "b" => match rest.parse() {
+                Ok(line) if line > 0 => return Ok(Command::Break(line)),
+                _ => return Err("usage: break <line>, with a line from 1 on".to_owned()),
+            },
+            "print" | "p" if rest.is_empty() => return Err("usage: print <expr>".to_owned()),
+            "print" | "p" => return Ok(Command::Print(rest.to_owned())),
+            "" => return Err(USAGE.to_owned()),
+            _ => return Err(format!("unknown command `{}`, {}", name, USAGE)),
+        };
+        if !rest.is_empty() {
+            return Err(format!("`{}` takes no arguments", name));
+        }
+        Ok(command)
+    }
+}
+
+/// How far the program runs before the debugger stops it again.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Resume {
+    /// To the next statement, wherever it is.
+    Step,
+    /// To the next top-level statement or breakpoint.
+    Next,
+    /// To the next breakpoint.
+    Continue,
+}
+
+/// Where the debugger stops: its breakpoints and how it was last told to
+/// go on.
+#[derive(Debug)]
+pub struct Debugger {
+    resume: Resume,
+    breakpoints: BTreeSet<usize>,
+}
+
+impl Default for Debugger {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Debugger {
+    /// A debugger that stops at the first statement.
+    pub fn new() -> Self {
+        Debugger {
+            resume: Resume::Step,
+            breakpoints: BTreeSet::new(),
+        }
+    }
+
+    pub fn resume(&mut self, resume: Resume) {
+        self.resume = resume;
+    }
+
+    /// Stops at the statements that start on `line`, counted from 1.
+    /// Returns whether there wasn't a breakpoint there yet.
+    pub fn add_breakpoint(&mut self, line: usize) -> bool {
+        self.breakpoints.insert(line)
+    }
+
+    pub fn breakpoints(&self) -> impl Iterator<Item = usize> + '_ {
+        self.breakpoints.iter().copied()
+    }
+
+    /// Whether to stop before a statement that starts on `line`, if that
+    /// is known, and is a top-level one or not.
+    pub fn should_stop(&self, line: Option<usize>, top_level: bool) -> bool {
+        let on_breakpoint = line.is_some_and(|line| self.breakpoints.contains(&line));
+        match self.resume {
+            Resume::Step => true,
+            Resume::Next => top_level || on_breakpoint,
+            Resume::Continue => on_breakpoint,
+        }
+    }
+
+    /* once the input ends, nothing can tell the program to go on again */
+    fn finish(&mut self) {
+        self.resume = Resume::Continue;
+        self.breakpoints.clear();
+    }
+}
+
+/* what it takes to say where a statement is. The program is the one
+ * that runs, not a copy, since `Pause::path` finds the statement by where
+ * it is in memory. */
+pub(crate) struct Source {
+    pub(crate) text: String,
+    pub(crate) program: Rc<Program>,
+    source_map: Vec<SourceMap>,
+    spans: Vec<Range<usize>>,
+}
+
+impl Source {
+    pub(crate) fn new(text: &str, program: Rc<Program>, source_map: Vec<SourceMap>) -> Self {
+        Source {
+            text: text.to_owned(),
+            program,
+            source_map,
+            spans: token_spans(text),
+        }
+    }
+
+    /* the line the statement `pause` stopped at starts on */
+    pub(crate) fn line(&self, pause: &Pause) -> Option<usize> {
+        self.line_at(&pause.path(&self.program)?)
+    }
+
+    /* the line the expression at `path` starts on */
+    pub(crate) fn line_at(&self, path: &[usize]) -> Option<usize> {
+        let (first, rest) = path.split_first()?;
+        let (start, _) = self.source_map.get(*first)?.token_range(rest)?;
+        Some(line_col(&self.text, self.spans[start].start).0)
+    }
+}
+
+/// Runs `program`, parsed from `source` by a parser
+/// [`with_source_map`](Parser::with_source_map) that gave `source_map`,
+/// under the debugger. It reads commands from `input` and writes what
+/// they show, and where the program stopped, to `output`. Gives what the
+/// program does.
+pub fn debug(
+    source: &str,
+    program: Program,
+    source_map: Vec<SourceMap>,
+    mut input: impl BufRead + 'static,
+    mut output: impl Write + 'static,
+) -> Option<Object> {
+    let program = Rc::new(program);
+    let source = Source::new(source, program.clone(), source_map);
+    let mut debugger = Debugger::new();
+    let mut env = Environment::new();
+    // there is nowhere to report that the output is gone, and the program
+    // may as well go on without it
+    eval_observed(&program, &mut env, move |pause| {
+        let line = source.line(&pause);
+        if !debugger.should_stop(line, pause.is_top_level()) {
+            return;
+        }
+        let _ = match line {
+            Some(line) => {
+                let text = source.text.lines().nth(line - 1).unwrap_or("");
+                writeln!(output, "line {}: {}", line, text.trim())
+            }
+            None => writeln!(output, "at {}", pause.statement.string()),
+        };
+        loop {
+            let _ = write!(output, "{}", PROMPT).and_then(|_| output.flush());
+            let mut line = String::new();
+            if !matches!(input.read_line(&mut line), Ok(read) if read > 0) {
+                debugger.finish();
+                return;
+            }
+            let shown = match Command::parse(&line) {
+                Ok(Command::Step) => return debugger.resume(Resume::Step),
+                Ok(Command::Next) => return debugger.resume(Resume::Next),
+                Ok(Command::Continue) => return debugger.resume(Resume::Continue),
+                Ok(Command::Break(line)) => {
+                    debugger.add_breakpoint(line);
+                    format!("breakpoint at line {}", line)
+                }
+                Ok(Command::Print(code)) => evaluate(&code, pause.env),
+                Ok(Command::Locals) => locals(pause.env),
+                Ok(Command::Help) => USAGE.to_owned(),
+                Err(msg) => msg,
+            };
+            let _ = writeln!(output, "{}", shown);
+        }
+    })
+}
+
+/* the value of `code` in `env`, or why it has none */
+fn evaluate(code: &str, env: &mut Environment) -> String {
+    let mut p = Parser::new(Lexer::new(code));
+    let program = p.parse();
+    if p.errors_len() != 0 {
+        return p.get_errors().join("\n");
+    }
+    eval(&program, env).unwrap_or(Object::Null).inspect()
+}
+
+/* the bindings of `env`'s scope, like the REPL's `:env` */
+fn locals(env: &Environment) -> String {
+    let lines = bindings(env);
+    if lines.is_empty() {
+        return "no locals".to_owned();
+    }
+    lines.join("\n")
+}
+
+#[cfg(test)]
+mod test {
+    use crate::debugger::{Command, Debugger, Resume};
+
+    #[test]
+    fn test_parse_commands() {
+        let tests = [
+            ("step", Ok(Command::Step)),
+            ("  s\n", Ok(Command::Step)),
+            ("next", Ok(Command::Next)),
+            ("n", Ok(Command::Next)),
+            ("continue", Ok(Command::Continue)),
+            ("c", Ok(Command::Continue)),
+            ("locals", Ok(Command::Locals)),
+            ("help", Ok(Command::Help)),
+            ("break 12", Ok(Command::Break(12))),
+            ("b   3 ", Ok(Command::Break(3))),
+            ("print x + 1", Ok(Command::Print("x + 1".to_owned()))),
+            ("p  f(1, 2) ", Ok(Command::Print("f(1, 2)".to_owned()))),
+            (
+                "break",
+                Err("usage: break <line>, with a line from 1 on".to_owned()),
+            ),
+            (
+                "break 0",
+                Err("usage: break <line>, with a line from 1 on".to_owned()),
+            ),
+            (
+                "break two",
+                Err("usage: break <line>, with a line from 1 on".to_owned()),
+            ),
+            ("print", Err("usage: print <expr>".to_owned())),
+            ("step 2", Err("`step` takes no arguments".to_owned())),
+            (
+                "run",
+                Err("unknown command `run`, commands: step, next, continue, break <line>, print <expr>, locals".to_owned()),
+            ),
+            (
+                "",
+                Err("commands: step, next, continue, break <line>, print <expr>, locals".to_owned()),
+            ),
+        ];
+        for (input, exp) in tests {
+            assert_eq!(Command::parse(input), exp, "{:?}", input);
+        }
+    }
+
+    #[test]
+    fn test_should_stop() {
+        let mut debugger = Debugger::new();
+        assert!(debugger.should_stop(None, false));
+        assert!(debugger.add_breakpoint(4));
+        assert!(!debugger.add_breakpoint(4));
+        debugger.add_breakpoint(9);
+        assert_eq!(debugger.breakpoints().collect::<Vec<_>>(), [4, 9]);
+
+        let tests = [
+            // (resume, line, top level, stops)
+            (Resume::Step, Some(1), false, true),
+            (Resume::Step, None, true, true),
+            (Resume::Next, Some(1), true, true),
+            (Resume::Next, Some(2), false, false),
+            (Resume::Next, Some(4), false, true),
+            (Resume::Next, None, false, false),
+            (Resume::Continue, Some(1), true, false),
+            (Resume::Continue, Some(4), false, true),
+            (Resume::Continue, Some(9), true, true),
+            (Resume::Continue, Some(5), false, false),
+            (Resume::Continue, None, true, false),
+        ];
+        for (resume, line, top_level, exp) in tests {
+            debugger.resume(resume);
+            assert_eq!(
+                debugger.should_stop(line, top_level),
+                exp,
+                "{:?} at {:?}, top level {}",
+                resume,
+                line,
+                top_level
+            );
+        }
+
+        debugger.finish();
+        assert!(!debugger.should_stop(Some(4), true));
+    }
+}
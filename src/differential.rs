@@ -0,0 +1,292 @@
+//! Runs programs on both the evaluator and the [`Vm`] and compares what
+//! they give, so the two don't drift apart. [`generate`] makes up random
+//! programs in the part of the language the compiler handles, which
+//! `tests/differential.rs` and the `differential` fuzz target feed to
+//! [`check`]:
+//!
+//! ```
+//! use interpreter::builtins::SeededRng;
+//! use interpreter::differential::{check, generate};
+//!
+//! let mut rng = SeededRng::new(7);
+//! for _ in 0..10 {
+//!     let program = generate(&mut rng, 4);
+//!     assert_eq!(check(&program), Ok(()), "{}", program);
+//! }
+//! ```
+//!
+//! A generated program only binds globals, reads names it bound before
+//! and never rebinds a `const`, since the compiler rejects those mistakes
+//! before anything runs where the evaluator fails on them as it goes. It
+//! ends in an expression, whose value both give. It can't loop or call,
+//! so it always ends.
+
+use crate::ast::Statement;
+use crate::builtins::Rng;
+use crate::compiler::Compiler;
+use crate::environment::Environment;
+use crate::evaluator::eval;
+use crate::lexer::Lexer;
+use crate::object::{ErrorKind, Object, ObjectTrait};
+use crate::parser::Parser;
+use crate::vm::{Vm, VmError};
+
+/// What running a program gave, in terms both engines can give.
+#[non_exhaustive]
+#[derive(Debug, Clone, PartialEq)]
+pub enum Outcome {
+    /// The value of the last expression statement, if there was one.
+    Value(Option<Object>),
+    /// A runtime error, by its kind.
+    Error(ErrorKind),
+    /// The compiler refused the program, with why.
+    NotCompiled(String),
+    /// The VM failed in a way the evaluator can't, like overflowing its
+    /// stack.
+    VmFailed(String),
+}
+
+impl std::fmt::Display for Outcome {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Value(Some(obj)) => write!(f, "{}", obj.inspect()),
+            Self::Value(None) => write!(f, "no value"),
+            Self::Error(kind) => write!(f, "a {:?} error", kind),
+            Self::NotCompiled(why) => write!(f, "no bytecode: {}", why),
+            Self::VmFailed(why) => write!(f, "a VM failure: {}", why),
+        }
+    }
+}
+
+/// A program the engines disagree on.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Divergence {
+    pub source: String,
+    pub evaluator: Outcome,
+    pub vm: Outcome,
+}
+
+impl std::fmt::Display for Divergence {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "the evaluator gives {} and the VM {} for\n{}",
+            self.evaluator, self.vm, self.source
+        )
+    }
+}
+
+impl std::error::Error for Divergence {}
+
+/// Runs `source` on both engines. Panics if it doesn't parse, which no
+/// generated program fails to.
+pub fn run_both(source: &str) -> (Outcome, Outcome) {
+    let mut p = Parser::new(Lexer::new(source));
+    let program = p.parse();
+    assert!(p.get_errors().is_empty(), "{:?}", p.get_errors());
+
+    let evaluated = match eval(&program, &mut Environment::new()) {
+        Some(Object::Error(err)) => Outcome::Error(err.kind()),
+        // a block without statements has no value, which the VM pushes
+        // as null
+        None if matches!(
+            program.statements.last(),
+            Some(Statement::ExpressionStatement(_))
+        ) =>
+        {
+            Outcome::Value(Some(Object::Null))
+        }
+        res => Outcome::Value(res),
+    };
+
+    let run = match Compiler::new().compile(&program) {
+        Ok(bytecode) => {
+            let mut vm = Vm::new(bytecode);
+            match vm.run() {
+                Ok(()) => Outcome::Value(vm.last_popped().cloned()),
+                Err(VmError::Runtime(err)) => Outcome::Error(err.kind()),
+                Err(err) => Outcome::VmFailed(err.to_string()),
+            }
+        }
+        Err(err) => Outcome::NotCompiled(err.to_string()),
+    };
+    (evaluated, run)
+}
+
+/// Whether both engines give the same for `source`, which has to end in
+/// an expression statement: after a `let` the evaluator gives nothing and
+/// the VM still has the value of the expression before it.
+pub fn check(source: &str) -> Result<(), Box<Divergence>> {
+    match run_both(source) {
+        (evaluator, vm) if evaluator == vm => Ok(()),
+        (evaluator, vm) => Err(Box::new(Divergence {
+            source: source.to_owned(),
+            evaluator,
+            vm,
+        })),
+    }
+}
+
+/// A random program that both engines should agree on, drawing from
+/// `rng`. `size` bounds how many statements it has and how deeply its
+/// expressions nest.
+pub fn generate(rng: &mut impl Rng, size: usize) -> String {
+    let mut gen = Generator {
+        rng,
+        names: Vec::new(),
+        consts: 0,
+    };
+    let size = size.max(1);
+    let mut res = String::new();
+    for _ in 0..gen.below(size) {
+        res.push_str(&gen.binding(size));
+        res.push('\n');
+    }
+    res.push_str(&gen.expression(size));
+    res.push('\n');
+    res
+}
+
+struct Generator<'r, R> {
+    rng: &'r mut R,
+    /* the names bound so far, the first `consts` of them with `const` */
+    names: Vec<String>,
+    consts: usize,
+}
+
+impl<R: Rng> Generator<'_, R> {
+    /* a number from 0 to `n - 1` */
+    fn below(&mut self, n: usize) -> usize {
+        (self.rng.next_u64() % n as u64) as usize
+    }
+
+    fn pick<'a>(&mut self, choices: &[&'a str]) -> &'a str {
+        choices[self.below(choices.len())]
+    }
+
+    /* a `let` or `const` of a new name, or a `let` of a name bound with
+     * `let` before. A `const` goes in front of the names, which keeps all
+     * of them from `consts` on rebindable. */
+    fn binding(&mut self, depth: usize) -> String {
+        let value = self.expression(depth);
+        let rebindable = self.names.len() - self.consts;
+        if rebindable > 0 && self.below(3) == 0 {
+            let i = self.consts + self.below(rebindable);
+            let name = self.names[i].clone();
+            return format!("let {} = {};", name, value);
+        }
+        let name = format!("v{}", self.names.len());
+        if self.below(3) == 0 {
+            self.names.insert(0, name.clone());
+            self.consts += 1;
+            format!("const {} = {};", name, value)
+        } else {
+            self.names.push(name.clone());
+            format!("let {} = {};", name, value)
+        }
+    }
+
+    fn expression(&mut self, depth: usize) -> String {
+        if depth == 0 || self.below(3) == 0 {
+            return self.leaf();
+        }
+        let depth = depth - 1;
+        match self.below(4) {
+            0 => {
+                let op = self.pick(&["-", "!"]);
+                format!("{}{}", op, self.expression(depth))
+            }
+            1 => {
+                let cond = self.expression(depth);
+                let consequence = self.block(depth);
+                match self.below(2) {
+                    0 => format!("if ({}) {}", cond, consequence),
+                    _ => format!("if ({}) {} else {}", cond, consequence, self.block(depth)),
+                }
+            }
+            _ => {
+                let left = self.expression(depth);
+                let op = self.pick(&["+", "-", "*", "/", "<", ">", "==", "!="]);
+                format!("({} {} {})", left, op, self.expression(depth))
+            }
+        }
+    }
+
+    /* one or two expression statements in braces. A `let` in a block
+     * would bind a global the compiler knows of even where the block
+     * doesn't run. */
+    fn block(&mut self, depth: usize) -> String {
+        let mut statements = vec![self.expression(depth)];
+        if self.below(3) == 0 {
+            statements.push(self.expression(depth));
+        }
+        format!("{{ {} }}", statements.join("; "))
+    }
+
+    fn leaf(&mut self) -> String {
+        match self.below(6) {
+            0 if !self.names.is_empty() => {
+                let i = self.below(self.names.len());
+                self.names[i].clone()
+            }
+            0 | 1 => self.pick(&["true", "false"]).to_owned(),
+            // near the ends of the integers, to overflow
+            2 => self
+                .pick(&["9223372036854775807", "4611686018427387904"])
+                .to_owned(),
+            _ => self.below(10).to_string(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::builtins::SeededRng;
+    use crate::differential::{check, generate, run_both, Divergence, Outcome};
+    use crate::object::{ErrorKind, Object};
+
+    #[test]
+    fn test_run_both() {
+        let tests = [
+            ("1 + 2", Outcome::Value(Some(Object::Integer(3)))),
+            ("let a = 1; a", Outcome::Value(Some(Object::Integer(1)))),
+            ("if (false) { 1 }", Outcome::Value(Some(Object::Null))),
+            ("1 / 0", Outcome::Error(ErrorKind::DivisionByZero)),
+            ("-true", Outcome::Error(ErrorKind::TypeMismatch)),
+        ];
+        for (input, exp) in tests {
+            assert_eq!(run_both(input), (exp.clone(), exp), "{}", input);
+        }
+    }
+
+    #[test]
+    fn test_divergence() {
+        // the compiler knows about `b` before the evaluator runs into it
+        let err = check("b").unwrap_err();
+        assert_eq!(
+            *err,
+            Divergence {
+                source: "b".to_owned(),
+                evaluator: Outcome::Error(ErrorKind::UnknownIdentifier),
+                vm: Outcome::NotCompiled("undefined variable: b".to_owned()),
+            }
+        );
+        assert_eq!(
+            err.to_string(),
+            "the evaluator gives a UnknownIdentifier error and the VM no bytecode: \
+             undefined variable: b for\nb"
+        );
+    }
+
+    #[test]
+    fn test_generated_programs() {
+        let mut rng = SeededRng::new(0);
+        let programs: Vec<String> = (0..50).map(|_| generate(&mut rng, 4)).collect();
+        for program in programs.iter() {
+            assert_eq!(check(program), Ok(()), "{}", program);
+        }
+        // the same seed gives the same programs
+        let mut rng = SeededRng::new(0);
+        assert_eq!(generate(&mut rng, 4), programs[0]);
+    }
+}
@@ -1,31 +1,129 @@
-use crate::object::Object;
+use crate::object::{EvalError, Object};
 
-#[derive(Debug, PartialEq, Eq, Clone)]
+/// A scope of bindings and the scopes around it.
+///
+/// Cloning an environment shares the scope rather than copying it: what is
+/// bound or assigned through one clone is seen through every other. A
+/// function keeps a clone of the scope it was made in, so it sees the
+/// bindings made there after it, and assigning to one of them in its body
+/// changes it for everyone.
+#[derive(Clone)]
 pub struct Environment {
+    scope: std::rc::Rc<std::cell::RefCell<Scope>>,
+}
+
+#[derive(Default)]
+struct Scope {
     store: std::collections::HashMap<std::rc::Rc<str>, Object>,
-    outer: Option<std::boxed::Box<Environment>>,
+    /* the names in `store` bound with `const` */
+    consts: std::collections::HashSet<std::rc::Rc<str>>,
+    outer: Option<Environment>,
+}
+
+/* two environments are equal when they are the same scope. Comparing the
+ * bindings could go on forever, since a function bound in a scope
+ * captures that scope. */
+impl PartialEq for Environment {
+    fn eq(&self, other: &Self) -> bool {
+        std::rc::Rc::ptr_eq(&self.scope, &other.scope)
+    }
+}
+
+/* the names only, for the same reason */
+impl std::fmt::Debug for Environment {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let scope = self.scope.borrow();
+        let mut names: Vec<&str> = scope.store.keys().map(|name| &**name).collect();
+        names.sort_unstable();
+        f.debug_struct("Environment")
+            .field("names", &names)
+            .field("outer", &scope.outer)
+            .finish()
+    }
+}
+
+impl Default for Environment {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Environment {
     pub fn new() -> Self {
-        let store = std::collections::HashMap::new();
-        Self { store, outer: None }
+        Self {
+            scope: Default::default(),
+        }
     }
 
+    /// A new scope inside `outer`, which it shares.
     pub fn new_enclosed_env(outer: &Environment) -> Self {
-        let mut env = Environment::new();
-        env.outer = Some(std::boxed::Box::new(outer.clone()));
+        let env = Environment::new();
+        env.scope.borrow_mut().outer = Some(outer.clone());
         env
     }
 
     pub fn set(&mut self, name: std::rc::Rc<str>, val: Object) {
-        self.store.insert(name, val);
+        self.scope.borrow_mut().store.insert(name, val);
+    }
+
+    /// Binds `name` like `set`, and marks it as a `const` unless `mutable`.
+    pub fn define(&mut self, name: std::rc::Rc<str>, val: Object, mutable: bool) {
+        if !mutable {
+            self.scope.borrow_mut().consts.insert(name.clone());
+        }
+        self.set(name, val);
+    }
+
+    /// Whether `name` is a `const` of this scope. A `const` of an outer
+    /// scope can be shadowed.
+    pub fn is_const(&self, name: &str) -> bool {
+        self.scope.borrow().consts.contains(name)
+    }
+
+    /// Sets the binding of `name` in the innermost scope that has one. It
+    /// fails if there is none, or if it's a `const`.
+    pub fn assign(&mut self, name: &std::rc::Rc<str>, val: Object) -> Result<(), EvalError> {
+        let mut scope = self.scope.borrow_mut();
+        if scope.store.contains_key(name) {
+            if scope.consts.contains(name) {
+                return Err(EvalError::ConstReassignment(name.to_string()));
+            }
+            scope.store.insert(name.clone(), val);
+            return Ok(());
+        }
+        match scope.outer.clone() {
+            Some(mut env) => {
+                drop(scope);
+                env.assign(name, val)
+            }
+            None => Err(EvalError::IdentifierNotFound(name.to_string())),
+        }
+    }
+
+    /// Unbinds `name` from this scope, `const` or not, and returns what
+    /// it was bound to. Outer scopes are left alone.
+    pub fn remove(&mut self, name: &str) -> Option<Object> {
+        let mut scope = self.scope.borrow_mut();
+        scope.consts.remove(name);
+        scope.store.remove(name)
+    }
+
+    /// The bindings of this scope, without those of the scopes around it,
+    /// in no particular order.
+    pub fn locals(&self) -> Vec<(std::rc::Rc<str>, Object)> {
+        let scope = self.scope.borrow();
+        scope
+            .store
+            .iter()
+            .map(|(name, obj)| (name.clone(), obj.clone()))
+            .collect()
     }
 
-    pub fn get(&self, name: &std::rc::Rc<str>) -> Option<&Object> {
-        match self.store.get(name) {
-            Some(obj) => Some(obj),
-            None => match &self.outer {
+    pub fn get(&self, name: &std::rc::Rc<str>) -> Option<Object> {
+        let scope = self.scope.borrow();
+        match scope.store.get(name) {
+            Some(obj) => Some(obj.clone()),
+            None => match &scope.outer {
                 Some(env) => env.get(name),
                 None => None,
             },
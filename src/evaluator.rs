@@ -1,30 +1,634 @@
+#[cfg(feature = "trace")]
+use std::cell::{Cell, RefCell};
 use std::ops::Deref;
+use std::rc::Rc;
 
 use crate::ast::{
-    Expression, ExpressionStatement, HashLiteral, IfExpression, InfixOperator, PrefixExpression,
-    PrefixOperator, Program, Statement,
+    BlockStatement, CallExpression, Constant, Expression, ExpressionStatement, ForExpression,
+    FunctionLiteral, HashLiteral, IfExpression, ImportStatement, IndexExpression, InfixExpression,
+    InfixOperator, LetTupleStatement, PrefixExpression, PrefixOperator, Program, Statement,
+    StringLiteral, TupleLiteral,
 };
-use crate::builtins::{first, last, len, print, push, rest};
+use crate::builtins::{self, eval_granted, int_overflow, IntOverflow};
 use crate::environment::Environment;
-use crate::object::{Array, Builtin, Function, Hash, Object, ObjectTrait, ObjectType};
+use crate::lexer::{token_spans, Lexer};
+use crate::modules;
+use crate::object::{
+    Array, ErrorKind, EvalError, Function, Hash, Macro, Object, ObjectTrait, ObjectType, Tuple,
+    TypeSet,
+};
+use crate::optimize::{boolean, integer, map_children, map_statement};
+use crate::parser::{Parser, SourceMap};
+use crate::token::Token;
+
+pub(crate) const TRUE: Object = Object::Boolean(true);
+pub(crate) const FALSE: Object = Object::Boolean(false);
+pub(crate) const NULL: Object = Object::Null;
+
+/* how deeply code run by `eval` may call `eval` again */
+const MAX_EVAL_DEPTH: usize = 32;
+
+thread_local! {
+    /* how many `eval` calls are running */
+    static EVAL_DEPTH: std::cell::Cell<usize> = const { std::cell::Cell::new(0) };
+    /* how many calls of functions are nested, not counting tail calls, the
+     * function whose body is running and the tail call it made, if it
+     * just did */
+    static CALL_DEPTH: std::cell::Cell<usize> = const { std::cell::Cell::new(0) };
+    /* how many more steps the host of `with_host` lets programs take, and
+     * how many it let them take to begin with */
+    static FUEL: std::cell::Cell<Option<(u64, u64)>> = const { std::cell::Cell::new(None) };
+    static CALLING: std::cell::RefCell<Option<Rc<Function>>> = const { std::cell::RefCell::new(None) };
+    static TAIL_CALL: std::cell::RefCell<Option<(Rc<Function>, Vec<Object>)>> = const { std::cell::RefCell::new(None) };
+    /* whether `eval_located` is running, and where the error being passed
+     * up was made */
+    static LOCATING: std::cell::Cell<bool> = const { std::cell::Cell::new(false) };
+    static FAILURE: std::cell::RefCell<Option<Failure>> = const { std::cell::RefCell::new(None) };
+    /* what `eval_observed` calls before each statement, taken out while it
+     * runs so the code that runs meanwhile isn't observed. While there is
+     * one, `OBSERVED` has the expressions being evaluated, outermost
+     * first, and `FRAMES` the functions being called, each with how many
+     * of the expressions were there before the call. */
+    static OBSERVER: std::cell::RefCell<Option<Box<Observer>>> = const { std::cell::RefCell::new(None) };
+    static OBSERVING: std::cell::Cell<bool> = const { std::cell::Cell::new(false) };
+    static OBSERVED: std::cell::RefCell<Vec<*const Expression>> = const { std::cell::RefCell::new(Vec::new()) };
+    static FRAMES: std::cell::RefCell<Vec<(Rc<Function>, usize)>> = const { std::cell::RefCell::new(Vec::new()) };
+}
 
-pub const TRUE: Object = Object::Boolean(true);
-pub const FALSE: Object = Object::Boolean(false);
-pub const NULL: Object = Object::Null;
+type Observer = dyn FnMut(Pause<'_>);
 
-const LEN: Object = Object::Builtin(Builtin { func: len });
-const FIRST: Object = Object::Builtin(Builtin { func: first });
-const LAST: Object = Object::Builtin(Builtin { func: last });
-const REST: Object = Object::Builtin(Builtin { func: rest });
-const PUSH: Object = Object::Builtin(Builtin { func: push });
-const PRINT: Object = Object::Builtin(Builtin { func: print });
+/* the expressions an error has been passed up through so far. `frames`
+ * has a function for each one it was passed out of, innermost first, with
+ * the path below the function's literal of where in it the error was
+ * made. `chain` has the expressions, innermost first, of the function or
+ * program it is in now. The pointers are only compared, never followed. */
+struct Failure {
+    error: EvalError,
+    frames: Vec<(Rc<Function>, Vec<usize>)>,
+    chain: Vec<*const Expression>,
+    /* whether binding the innermost expression to names made it */
+    binding: bool,
+}
 
+/// Runs `program` in `env` and gives the value of its last statement.
+///
+/// The program's top-level function declarations, `fn name(x) { ... }`,
+/// are hoisted: they are bound before the first statement runs, so they
+/// can be called before they are declared. A declaration inside a function
+/// or block isn't hoisted.
+///
+/// A function shares the scope it was made in, see [`Environment`]. The
+/// names it uses are looked up when it runs, so functions bound with `let`
+/// can call each other whatever their order, and assigning to a name of
+/// an outer scope, like `n = n + 1`, changes that binding.
+///
+/// An array, hash or tuple of nothing but literals, like `[1, 2, 3]`, is
+/// made the first time it's evaluated and evaluates to the same value from
+/// then on, in this call of `eval` and the later ones on the same program,
+/// see [`Constant`].
 pub fn eval(program: &Program, env: &mut Environment) -> Option<Object> {
     eval_statements(&program.statements, env)
 }
 
-fn eval_statements(statements: &Vec<Statement>, env: &mut Environment) -> Option<Object> {
+/* the value of the array, hash or tuple with `constant`, which `f`
+ * evaluates, made only the first time if the parser found it constant */
+fn literal(constant: &Constant, f: impl FnOnce() -> Option<Object>) -> Option<Object> {
+    #[cfg(feature = "trace")]
+    if TRACING.get() {
+        return f();
+    }
+    let Some(value) = constant.value() else {
+        return f();
+    };
+    if let Some(value) = value.get() {
+        return Some(value.clone());
+    }
+    let res = f();
+    // an error, like the one of `[-true]`, is made again each time
+    if let Some(made) = res.as_ref().filter(|res| !matches!(res, Object::Error(_))) {
+        let _ = value.set(made.clone());
+    }
+    res
+}
+
+/// Like `eval`, but writes a line to `out` for each statement and
+/// expression as it is evaluated, indented by how deeply it's nested, and
+/// one with its value once it has one:
+///
+/// ```text
+/// eval ExpressionStatement
+///   eval InfixExpression(+)
+///     eval IntegerLiteral(1)
+///     => 1
+///     eval IntegerLiteral(2)
+///     => 2
+///   => 3
+/// => 3
+/// ```
+///
+/// The trace is written once the program has finished. Only with the
+/// `trace` feature, so that without it `eval` doesn't check whether it is
+/// tracing at every step.
+#[cfg(feature = "trace")]
+pub fn eval_traced(
+    program: &Program,
+    env: &mut Environment,
+    out: &mut impl std::io::Write,
+) -> std::io::Result<Option<Object>> {
+    let outer = TRACE.with(|t| t.replace(Some(Trace::default())));
+    let tracing = TRACING.replace(true);
+    let res = eval(program, env);
+    TRACING.set(tracing);
+    let trace = TRACE.with(|t| t.replace(outer)).unwrap_or_default();
+    out.write_all(trace.out.as_bytes())?;
+    Ok(res)
+}
+
+/// Like `eval`, but when the program fails also gives where: the path, as
+/// in [`Program::replace_expr_at`](crate::ast::Program::replace_expr_at),
+/// of the innermost expression that failed, so in `1 + 2 / 0` the `2 / 0`.
+/// For a function the error was passed out of, that is inside the
+/// function's literal, or if the literal isn't in `program`, like one
+/// `eval` made, where the function was called. Two literals written the
+/// same can't be told apart, an error in either is put in the first.
+///
+/// An error of an `import`, even one made in the module, is put at the
+/// path string of it. One of binding names, like binding a `const` again
+/// or destructuring a tuple into as many names as it doesn't have, is put
+/// at the names.
+pub fn eval_located(
+    program: &Program,
+    env: &mut Environment,
+) -> (Option<Object>, Option<Location>) {
+    let (res, failure) = eval_failing(program, env);
+    (res, failure.and_then(|failure| failure.locate(program)))
+}
+
+/// Where in a program [`eval_located`] found an error was made.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Location {
+    /// The path of the expression it was made at, as in
+    /// [`Program::replace_expr_at`](crate::ast::Program::replace_expr_at).
+    pub path: Vec<usize>,
+    /// Whether the error was made binding the value at `path` to names,
+    /// so it is at the names rather than the value.
+    pub binding: bool,
+}
+
+impl Location {
+    /// The range of the tokens the error is at, from the `source_map` of
+    /// the parser that parsed the program.
+    pub fn token_range(&self, source_map: &[SourceMap]) -> Option<(usize, usize)> {
+        let (first, rest) = self.path.split_first()?;
+        let map = source_map.get(*first)?;
+        match self.binding {
+            true => map.names(rest),
+            false => map.token_range(rest),
+        }
+    }
+}
+
+/* `eval`, and what is known of where the error the program failed with
+ * was made, if it did */
+fn eval_failing(program: &Program, env: &mut Environment) -> (Option<Object>, Option<Failure>) {
+    let locating = LOCATING.replace(true);
+    let outer = FAILURE.take();
+    let res = eval(program, env);
+    LOCATING.set(locating);
+    let failure = match (&res, FAILURE.replace(outer)) {
+        (Some(Object::Error(err)), Some(failure)) if failure.error == *err => Some(failure),
+        _ => None,
+    };
+    (res, failure)
+}
+
+/// An error of a program [`eval_source`] ran, with where in the source it
+/// was made and the calls it was passed out of.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RuntimeError {
+    pub error: EvalError,
+    /// The bytes of the source the innermost expression that failed was
+    /// parsed from, as [`eval_located`] finds it, or of the token a parse
+    /// error is at.
+    pub span: Option<std::ops::Range<usize>>,
+    /// The calls of functions the error was passed out of, innermost
+    /// first. A tail call takes the place of the call it is in, so only
+    /// the last of a chain of them is there.
+    pub trace: Vec<Frame>,
+}
+
+/// A call of a function that an error was passed out of.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Frame {
+    /// The name the function was bound to, if it was.
+    pub function: Option<Rc<str>>,
+    /// The bytes of the source of the call, if it is in the program, which
+    /// it isn't for a call of a function in code `eval` ran.
+    pub call: Option<std::ops::Range<usize>>,
+}
+
+impl RuntimeError {
+    pub fn kind(&self) -> ErrorKind {
+        self.error.kind()
+    }
+}
+
+impl std::fmt::Display for RuntimeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.error.fmt(f)
+    }
+}
+
+impl std::error::Error for RuntimeError {}
+
+/// Parses `source`, block comments and all, and runs it in `env` like
+/// `eval`, for an embedder that has the program as a string. A program
+/// that fails, or that doesn't parse, gives a [`RuntimeError`] with where
+/// in `source` it did, so it can be shown the way the command line does:
+///
+/// ```
+/// use interpreter::evaluator::eval_source;
+/// use interpreter::prelude::*;
+/// use interpreter::object::ErrorKind;
+///
+/// let source = "let f = fn(x) { 10 / x };\nf(0)";
+/// let err = eval_source(source, &mut Environment::new()).unwrap_err();
+/// assert_eq!(err.kind(), ErrorKind::DivisionByZero);
+/// assert_eq!(&source[err.span.unwrap()], "10 / x");
+/// assert_eq!(err.trace[0].function.as_deref(), Some("f"));
+/// assert_eq!(&source[err.trace[0].call.clone().unwrap()], "f(0)");
+/// ```
+///
+/// An `exit(code)` is an error of the kind [`ErrorKind::Exit`] too. The
+/// program's macros aren't expanded.
+pub fn eval_source(source: &str, env: &mut Environment) -> Result<Option<Object>, RuntimeError> {
+    let mut p = Parser::new(Lexer::new(source).with_block_comments()).with_source_map();
+    let program = p.parse();
+    let spans = token_spans(source);
+    if p.errors_len() != 0 {
+        let span = p.error_tokens().iter().flatten().next().map(|&i| {
+            spans
+                .get(i)
+                .map_or(source.len()..source.len(), Clone::clone)
+        });
+        return Err(RuntimeError {
+            error: EvalError::ParseFailed(p.get_errors().clone()),
+            span,
+            trace: Vec::new(),
+        });
+    }
+    let span = |at: Option<Location>| {
+        let (start, end) = at?.token_range(p.source_map())?;
+        Some(spans[start].start..spans[end - 1].end)
+    };
+    let (res, failure) = eval_failing(&program, env);
+    let Some(Object::Error(error)) = res else {
+        return Ok(res);
+    };
+    let (span, trace) = match failure {
+        Some(failure) => (
+            span(failure.locate(&program)),
+            failure.trace(&program, span),
+        ),
+        None => (None, Vec::new()),
+    };
+    Err(RuntimeError { error, span, trace })
+}
+
+impl Failure {
+    fn locate(&self, program: &Program) -> Option<Location> {
+        // the names are only where the error was made, not where the
+        // functions it was passed out of were called
+        for (i, (func, path)) in self.frames.iter().enumerate() {
+            if let Some(path) = frame_path(program, func, path) {
+                let binding = self.binding && i == 0;
+                return Some(Location { path, binding });
+            }
+        }
+        let path = self.top_level_path(program)?;
+        let binding = self.binding && self.frames.is_empty();
+        Some(Location { path, binding })
+    }
+
+    /* the path of where the innermost expression of `chain` is at the top
+     * level of `program` */
+    fn top_level_path(&self, program: &Program) -> Option<Vec<usize>> {
+        let (path, _) = chain_path(top_level(program), self.chain.iter().rev().copied());
+        (!path.is_empty()).then_some(path)
+    }
+
+    /* each function the error was passed out of, innermost first, called
+     * where `span` says the path is: in the function of the next frame, or
+     * at the top level for the last one */
+    fn trace(
+        &self,
+        program: &Program,
+        span: impl Fn(Option<Location>) -> Option<std::ops::Range<usize>>,
+    ) -> Vec<Frame> {
+        let frames = self.frames.iter().enumerate();
+        frames
+            .map(|(i, (func, _))| {
+                let call = match self.frames.get(i + 1) {
+                    Some((caller, path)) => frame_path(program, caller, path),
+                    None => self.top_level_path(program),
+                };
+                Frame {
+                    function: func.name.clone(),
+                    call: span(call.map(|path| Location {
+                        path,
+                        binding: false,
+                    })),
+                }
+            })
+            .collect()
+    }
+}
+
+/// Like `eval`, but calls `observer` before each statement runs, those in
+/// blocks and function bodies too, for a debugger that stops the program
+/// there until it is told to go on. What the observer evaluates itself
+/// isn't observed.
+pub fn eval_observed(
+    program: &Program,
+    env: &mut Environment,
+    observer: impl FnMut(Pause<'_>) + 'static,
+) -> Option<Object> {
+    let outer = OBSERVER.replace(Some(Box::new(observer)));
+    let observing = OBSERVING.replace(true);
+    let observed = OBSERVED.take();
+    let frames = FRAMES.take();
+    let res = eval(program, env);
+    OBSERVING.set(observing);
+    OBSERVER.set(outer);
+    OBSERVED.set(observed);
+    FRAMES.set(frames);
+    res
+}
+
+/// A statement [`eval_observed`] is about to run.
+pub struct Pause<'a> {
+    pub statement: &'a Statement,
+    /// The scope the statement runs in.
+    pub env: &'a mut Environment,
+    /* the function the statement is in, none at the top level, and the
+     * expressions of it being evaluated, outermost first */
+    frame: Option<Rc<Function>>,
+    chain: Vec<*const Expression>,
+}
+
+impl Pause<'_> {
+    /// Whether the statement is one of the program's own, rather than one
+    /// in a block or a function it called.
+    pub fn is_top_level(&self) -> bool {
+        self.frame.is_none() && self.chain.is_empty()
+    }
+
+    /// Where in `program`, the one being run, the statement's expression
+    /// is, as a path for [`Program::replace_expr_at`](crate::ast::Program::replace_expr_at).
+    /// For a `break` or `continue` that is the expression it is in. Like
+    /// [`eval_located`], a statement in a function whose literal isn't in
+    /// `program` has no path, and one in a function written the same way
+    /// as one before it gets the path in that one.
+    pub fn path(&self, program: &Program) -> Option<Vec<usize>> {
+        let (mut path, roots) = match &self.frame {
+            None => (Vec::new(), top_level(program)),
+            Some(func) => (
+                literal_path(program, func)?,
+                indexed(func.body.expressions().collect()),
+            ),
+        };
+        let (below, level) = chain_path(roots, self.chain.iter().copied());
+        if below.len() != self.chain.len() {
+            return None;
+        }
+        path.extend(below);
+        match self.statement.expression() {
+            Some(exp) => {
+                let &(i, _) = level.iter().find(|(_, e)| std::ptr::eq(*e, exp))?;
+                path.push(i);
+                Some(path)
+            }
+            None => (!path.is_empty()).then_some(path),
+        }
+    }
+}
+
+/* the expressions of the program's statements, by statement index */
+fn top_level(program: &Program) -> Vec<(usize, &Expression)> {
+    let statements = program.statements.iter().enumerate();
+    statements
+        .filter_map(|(i, stmt)| Some((i, stmt.expression()?)))
+        .collect()
+}
+
+fn indexed(exps: Vec<&Expression>) -> Vec<(usize, &Expression)> {
+    exps.into_iter().enumerate().collect()
+}
+
+/* the path from `level` down through `chain`, outermost first, for as
+ * far as each expression is right below the one before it, and the
+ * expressions right below the last one it got to */
+fn chain_path(
+    mut level: Vec<(usize, &Expression)>,
+    chain: impl Iterator<Item = *const Expression>,
+) -> (Vec<usize>, Vec<(usize, &Expression)>) {
+    let mut path = Vec::new();
+    for exp in chain {
+        let Some(&(i, e)) = level.iter().find(|(_, e)| std::ptr::eq(*e, exp)) else {
+            break;
+        };
+        path.push(i);
+        level = indexed(e.children());
+    }
+    (path, level)
+}
+
+/* the path to the first function literal in `program` written like the
+ * one `func` was made from */
+fn literal_path(program: &Program, func: &Function) -> Option<Vec<usize>> {
+    let mut path = Vec::new();
+    find_literal(top_level(program), &func.body, &mut path).then_some(path)
+}
+
+/* the path in `program` of `path` below the literal of `func`, if the
+ * literal is there */
+fn frame_path(program: &Program, func: &Function, path: &[usize]) -> Option<Vec<usize>> {
+    let mut at = literal_path(program, func)?;
+    at.extend(path);
+    Some(at)
+}
+
+/* adds the path to the first function literal with `body` at or below
+ * `level` to `path` */
+fn find_literal(
+    level: Vec<(usize, &Expression)>,
+    body: &BlockStatement,
+    path: &mut Vec<usize>,
+) -> bool {
+    for (i, e) in level {
+        path.push(i);
+        if matches!(e, Expression::FunctionLiteral(fl) if fl.body == *body)
+            || find_literal(indexed(e.children()), body, path)
+        {
+            return true;
+        }
+        path.pop();
+    }
+    false
+}
+
+/* stops for the observer of `eval_observed`, if there is one, before
+ * `stmt` runs */
+fn observe(stmt: &Statement, env: &mut Environment) {
+    if !OBSERVING.get() {
+        return;
+    }
+    let Some(mut observer) = OBSERVER.take() else {
+        return;
+    };
+    let (frame, start) = FRAMES.with_borrow(|frames| match frames.last() {
+        Some((func, start)) => (Some(func.clone()), *start),
+        None => (None, 0),
+    });
+    let chain = OBSERVED.with_borrow(|observed| observed[start..].to_vec());
+    OBSERVING.set(false);
+    observer(Pause {
+        statement: stmt,
+        env,
+        frame,
+        chain,
+    });
+    OBSERVING.set(true);
+    OBSERVER.set(Some(observer));
+}
+
+/* `err` was passed up out of `exp` */
+#[cold]
+fn failed(exp: &Expression, err: &EvalError) {
+    if !LOCATING.get() {
+        return;
+    }
+    FAILURE.with_borrow_mut(|failure| match failure {
+        Some(failure) if failure.error == *err => failure.chain.push(exp),
+        _ => {
+            *failure = Some(Failure {
+                error: err.clone(),
+                frames: Vec::new(),
+                chain: vec![exp],
+                binding: false,
+            })
+        }
+    })
+}
+
+/* binding `value` to the names of its statement made `err` */
+#[cold]
+fn binding_failed(value: &Expression, err: EvalError) -> Option<Object> {
+    if LOCATING.get() {
+        FAILURE.set(Some(Failure {
+            error: err.clone(),
+            frames: Vec::new(),
+            chain: vec![value],
+            binding: true,
+        }));
+    }
+    Some(Object::Error(err))
+}
+
+/* `err` was passed up out of a call to `func`, so the expressions so far
+ * were in its body */
+#[cold]
+fn failed_in(func: &Rc<Function>, err: &EvalError) {
+    if !LOCATING.get() {
+        return;
+    }
+    FAILURE.with_borrow_mut(|failure| match failure {
+        Some(failure) if failure.error == *err => {
+            let body = indexed(func.body.expressions().collect());
+            let (path, _) = chain_path(body, failure.chain.iter().rev().copied());
+            failure.chain.clear();
+            if !path.is_empty() {
+                failure.frames.push((func.clone(), path));
+            }
+        }
+        // made by a statement of the body, not an expression
+        _ => {
+            *failure = Some(Failure {
+                error: err.clone(),
+                frames: Vec::new(),
+                chain: Vec::new(),
+                binding: false,
+            })
+        }
+    })
+}
+
+/* code `eval` ran is gone once it has, and where in it an error was made
+ * with it */
+fn failed_in_code() {
+    FAILURE.with_borrow_mut(|failure| {
+        if let Some(failure) = failure {
+            failure.chain.clear();
+            failure.frames.clear();
+        }
+    })
+}
+
+/* `try` caught the error being passed up, so it goes no further */
+pub(crate) fn caught() {
+    FAILURE.set(None);
+}
+
+/// Like `eval`, but keeps the value of every top-level statement instead of
+/// only the last one. A statement without a value, like `let`, gives
+/// `Null`. Evaluation stops after a top-level `return` or an error, which
+/// is the last value.
+pub fn eval_each(program: &Program, env: &mut Environment) -> Vec<Object> {
+    let mut res = Vec::with_capacity(program.statements.len());
+    hoist_functions(&program.statements, env);
+    for stmt in program.statements.iter() {
+        match eval_statement(stmt, env) {
+            Some(Object::Return(ret)) => {
+                res.push(*ret);
+                break;
+            }
+            Some(err @ Object::Error(_)) => {
+                res.push(err);
+                break;
+            }
+            Some(obj) => res.push(obj),
+            None => res.push(NULL),
+        }
+    }
+    res
+}
+
+/* binds the top-level function declarations of `statements` before any
+ * of them runs */
+fn hoist_functions(statements: &[Statement], env: &mut Environment) {
+    for (name, func) in statements.iter().filter_map(declaration) {
+        // the declaration reports that a `const` can't be bound again
+        if !env.is_const(name) {
+            let obj = eval_function_literal(func, Some(name.clone()), env);
+            env.set(name.clone(), obj);
+        }
+    }
+}
+
+/* the name and function of `fn name(x) { ... }` */
+fn declaration(stmt: &Statement) -> Option<(&Rc<str>, &FunctionLiteral)> {
+    match stmt {
+        Statement::LetStatement(ls) if ls.tok == Token::Function => match &ls.value {
+            Expression::FunctionLiteral(func) => Some((&ls.name.value, func)),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+fn eval_statements(statements: &[Statement], env: &mut Environment) -> Option<Object> {
     let mut obj: Option<Object> = None;
+    hoist_functions(statements, env);
     for stmt in statements {
         obj = eval_statement(stmt, env);
         if let Some(o) = obj.clone() {
@@ -41,33 +645,238 @@ fn eval_statements(statements: &Vec<Statement>, env: &mut Environment) -> Option
     obj
 }
 
+#[cfg(feature = "trace")]
+thread_local! {
+    /* what `eval_traced` has traced so far, while it runs. `TRACING` says
+     * whether it is. */
+    static TRACE: RefCell<Option<Trace>> = const { RefCell::new(None) };
+    static TRACING: Cell<bool> = const { Cell::new(false) };
+}
+
+#[cfg(feature = "trace")]
+#[derive(Default)]
+struct Trace {
+    depth: usize,
+    out: String,
+}
+
+#[cfg(feature = "trace")]
+impl Trace {
+    fn line(&mut self, line: &str) {
+        for _ in 0..self.depth {
+            self.out.push_str("  ");
+        }
+        self.out.push_str(line);
+        self.out.push('\n');
+    }
+}
+
+/* runs `f` between the trace's lines for the node `label` when tracing,
+ * and just runs it otherwise */
+#[cfg(feature = "trace")]
+#[inline(always)]
+fn traced(label: impl FnOnce() -> String, f: impl FnOnce() -> Option<Object>) -> Option<Object> {
+    if !TRACING.get() {
+        return f();
+    }
+    trace_node(label(), f)
+}
+
+/* without the feature there is nothing to check */
+#[cfg(not(feature = "trace"))]
+#[inline(always)]
+fn traced(_: impl FnOnce() -> String, f: impl FnOnce() -> Option<Object>) -> Option<Object> {
+    f()
+}
+
+#[cfg(feature = "trace")]
+#[cold]
+#[inline(never)]
+fn trace_node(label: String, f: impl FnOnce() -> Option<Object>) -> Option<Object> {
+    TRACE.with(|t| {
+        if let Some(trace) = t.borrow_mut().as_mut() {
+            trace.line(&format!("eval {}", label));
+            trace.depth += 1;
+        }
+    });
+    let res = f();
+    TRACE.with(|t| {
+        if let Some(trace) = t.borrow_mut().as_mut() {
+            trace.depth -= 1;
+            if let Some(obj) = &res {
+                trace.line(&format!("=> {}", obj.inspect()));
+            }
+        }
+    });
+    res
+}
+
+fn statement_label(statement: &Statement) -> String {
+    match statement {
+        Statement::LetStatement(ls) => format!("LetStatement({})", ls.name.value),
+        Statement::LetTupleStatement(lts) => {
+            let names: Vec<&str> = lts.names.iter().map(|name| &*name.value).collect();
+            format!("LetTupleStatement({})", names.join(", "))
+        }
+        Statement::ReturnStatement(_) => "ReturnStatement".to_owned(),
+        Statement::ExpressionStatement(_) => "ExpressionStatement".to_owned(),
+        Statement::BreakStatement(_) => "BreakStatement".to_owned(),
+        Statement::ContinueStatement(_) => "ContinueStatement".to_owned(),
+        Statement::ImportStatement(_) => "ImportStatement".to_owned(),
+    }
+}
+
+fn expression_label(e: &Expression) -> String {
+    match e {
+        Expression::Identifier(ident) => format!("Identifier({})", ident.value),
+        Expression::Integer(int) => format!("IntegerLiteral({})", int.value),
+        Expression::Float(float) => format!("FloatLiteral({:?})", float.value),
+        Expression::String(s) => format!("StringLiteral({:?})", s.value),
+        Expression::Boolean(b) => format!("BooleanLiteral({})", b.value),
+        Expression::Array(_) => "ArrayLiteral".to_owned(),
+        Expression::Tuple(_) => "TupleLiteral".to_owned(),
+        Expression::Hash(_) => "HashLiteral".to_owned(),
+        Expression::PrefixExpression(pe) => format!("PrefixExpression({})", pe.operator),
+        Expression::InfixExpression(ie) => format!("InfixExpression({})", ie.operator),
+        Expression::Assign(ae) => format!("AssignExpression({})", ae.name.value),
+        Expression::IfExpression(_) => "IfExpression".to_owned(),
+        Expression::ForExpression(_) => "ForExpression".to_owned(),
+        Expression::FunctionLiteral(_) => "FunctionLiteral".to_owned(),
+        Expression::MacroLiteral(_) => "MacroLiteral".to_owned(),
+        Expression::CallExpression(_) => "CallExpression".to_owned(),
+        Expression::IndexExpression(_) => "IndexExpression".to_owned(),
+    }
+}
+
 fn eval_statement(statement: &Statement, env: &mut Environment) -> Option<Object> {
+    observe(statement, env);
+    traced(
+        || statement_label(statement),
+        || eval_statement_node(statement, env),
+    )
+}
+
+/* `eval_statement` apart from tracing */
+#[inline(always)]
+fn eval_statement_node(statement: &Statement, env: &mut Environment) -> Option<Object> {
     match statement {
         Statement::LetStatement(ls) => {
-            let val = eval_expression(&ls.value, env);
+            let val = match &ls.value {
+                // the environment is captured before `let` binds the name,
+                // so the function learns its name to be able to recurse
+                Expression::FunctionLiteral(func) => Some(eval_function_literal(
+                    func,
+                    Some(ls.name.value.clone()),
+                    env,
+                )),
+                value => eval_expression(value, env),
+            };
             if let Some(exp) = val.clone() {
                 if exp.type_val() == ObjectType::Error {
-                    return val;
+                    val
+                } else if env.is_const(&ls.name.value) {
+                    let err = EvalError::ConstReassignment(ls.name.value.to_string());
+                    binding_failed(&ls.value, err)
                 } else {
-                    env.set(ls.name.value.clone(), exp);
-                    return None;
+                    env.define(ls.name.value.clone(), exp, ls.mutable);
+                    None
                 }
             } else {
-                return None;
+                None
             }
         }
+        Statement::LetTupleStatement(lts) => eval_let_tuple_statement(lts, env),
         Statement::ReturnStatement(rs) => {
-            let return_value = match eval_expression(&rs.value, env) {
-                Some(v) => v,
-                None => return None,
-            };
+            let return_value = eval_expression(&rs.value, env)?;
             if return_value.type_val() == ObjectType::Error {
                 return Some(return_value);
             }
             Some(Object::Return(std::boxed::Box::new(return_value)))
         }
         Statement::ExpressionStatement(es) => eval_expression_statement(es, env),
+        Statement::BreakStatement(bs) => Some(Object::Break(
+            bs.label.as_ref().map(|label| label.value.clone()),
+        )),
+        Statement::ContinueStatement(_) => Some(Object::Continue),
+        Statement::ImportStatement(is) => {
+            let res = eval_import_statement(is, env);
+            // the path stands for the statement, which has no expression
+            // of its own
+            if let Some(Object::Error(err)) = &res {
+                failed(&is.path, err);
+            }
+            res
+        }
+    }
+}
+
+/* binds what the module exports, evaluating it the first time it's
+ * imported */
+fn eval_import_statement(is: &ImportStatement, env: &mut Environment) -> Option<Object> {
+    let path = match eval_expression(&is.path, env)? {
+        Object::String(path) => path,
+        obj => return Some(obj),
+    };
+    let exports = match modules::import(&path) {
+        Ok(exports) => exports,
+        Err(err) => return Some(Object::Error(err)),
+    };
+    match &is.alias {
+        Some(alias) => {
+            if env.is_const(&alias.value) {
+                return Some(const_reassignment(&alias.value));
+            }
+            let hash: Hash = exports
+                .iter()
+                .map(|(name, value)| (Object::String(name.clone()), value.clone()))
+                .collect();
+            let module = Object::Hash(Rc::new(hash));
+            env.define(alias.value.clone(), module, true);
+        }
+        None => {
+            if let Some((name, _)) = exports.iter().find(|(name, _)| env.is_const(name)) {
+                return Some(const_reassignment(name));
+            }
+            for (name, value) in exports.iter() {
+                env.define(name.clone(), value.clone(), true);
+            }
+        }
+    }
+    None
+}
+
+fn eval_let_tuple_statement(lts: &LetTupleStatement, env: &mut Environment) -> Option<Object> {
+    let val = eval_expression(&lts.value, env)?;
+    let tuple = match val {
+        Object::Error(_) => return Some(val),
+        Object::Tuple(t) => t,
+        _ => {
+            let err = EvalError::NotDestructurable {
+                got: val.type_val(),
+                names: lts.names.len(),
+            };
+            return binding_failed(&lts.value, err);
+        }
+    };
+    if tuple.elements.len() != lts.names.len() {
+        let err = EvalError::DestructureCountMismatch {
+            got: tuple.elements.len(),
+            want: lts.names.len(),
+        };
+        return binding_failed(&lts.value, err);
+    }
+    if let Some(name) = lts.names.iter().find(|name| env.is_const(&name.value)) {
+        let err = EvalError::ConstReassignment(name.value.to_string());
+        return binding_failed(&lts.value, err);
     }
+    for (name, val) in lts.names.iter().zip(tuple.elements.iter()) {
+        env.define(name.value.clone(), val.clone(), lts.mutable);
+    }
+    None
+}
+
+fn const_reassignment(name: &str) -> Object {
+    Object::Error(EvalError::ConstReassignment(name.to_owned()))
 }
 
 fn eval_expression_statement(es: &ExpressionStatement, env: &mut Environment) -> Option<Object> {
@@ -75,216 +884,623 @@ fn eval_expression_statement(es: &ExpressionStatement, env: &mut Environment) ->
 }
 
 fn eval_expression(e: &Expression, env: &mut Environment) -> Option<Object> {
+    if let Some(err) = burn_fuel() {
+        failed(e, &err);
+        return Some(Object::Error(err));
+    }
+    let observing = OBSERVING.get();
+    if observing {
+        OBSERVED.with_borrow_mut(|observed| observed.push(e));
+    }
+    let res = traced(|| expression_label(e), || eval_expression_node(e, env));
+    if observing {
+        OBSERVED.with_borrow_mut(Vec::pop);
+    }
+    if let Some(Object::Error(err)) = &res {
+        failed(e, err);
+    }
+    res
+}
+
+/* takes a step of the fuel there is, if the host set a limit, or gives
+ * the error of there being none left */
+#[inline(always)]
+fn burn_fuel() -> Option<EvalError> {
+    let (left, limit) = FUEL.get()?;
+    if left == 0 {
+        return Some(EvalError::OutOfFuel(limit));
+    }
+    FUEL.set(Some((left - 1, limit)));
+    None
+}
+
+/* sets how many steps programs have left and had to begin with, any
+ * number without a limit, and gives back what it was before */
+pub(crate) fn set_fuel(fuel: Option<(u64, u64)>) -> Option<(u64, u64)> {
+    FUEL.replace(fuel)
+}
+
+/* `eval_expression` apart from tracing */
+#[inline(always)]
+fn eval_expression_node(e: &Expression, env: &mut Environment) -> Option<Object> {
     match e {
         Expression::Integer(val) => Some(Object::Integer(val.value)),
+        Expression::Float(val) => Some(Object::Float(val.value)),
         Expression::Boolean(val) => Some(native_bool_to_bool_object(val.value)),
         Expression::String(val) => Some(Object::String(val.value.clone())),
         Expression::Identifier(val) => Some(eval_identifier(&val.value, env)),
         Expression::PrefixExpression(pe) => {
-            let right = match eval_expression(&pe.right, env) {
-                Some(val) => val,
-                None => return None,
-            };
+            let right = eval_expression(&pe.right, env)?;
             if let Object::Error(_) = right {
                 return Some(right);
             }
-            Some(eval_prefix_expression(&pe, &right))
+            Some(eval_prefix_expression(pe, &right))
         }
-        Expression::InfixExpression(ie) => {
-            let left = match eval_expression(&ie.left, env) {
-                Some(val) => val,
-                None => return None,
-            };
-            if let Object::Error(_) = left {
-                return Some(left);
+        Expression::InfixExpression(ie) => eval_infix_operands(ie, env),
+        Expression::Assign(ae) => {
+            let val = eval_expression(&ae.value, env)?;
+            if let Object::Error(_) = val {
+                return Some(val);
             }
-            let right = match eval_expression(&ie.right, env) {
-                Some(val) => val,
-                None => return None,
-            };
-            if let Object::Error(_) = right {
-                return Some(right);
+            match env.assign(&ae.name.value, val.clone()) {
+                Ok(()) => Some(val),
+                Err(e) => Some(Object::Error(e)),
             }
-            Some(eval_infix_expression(&left, &right, &ie.operator))
         }
-        Expression::IfExpression(ife) => eval_if_expression(&ife, env),
-        Expression::FunctionLiteral(func) => Some(Object::Function(Function {
-            parameters: func.parameters.clone(),
-            body: func.body.clone(),
+        Expression::IfExpression(ife) => eval_if_expression(ife, env),
+        Expression::ForExpression(fe) => eval_for_expression(fe, env),
+        Expression::FunctionLiteral(func) => Some(eval_function_literal(func, None, env)),
+        Expression::MacroLiteral(ml) => Some(Object::Macro(Rc::new(Macro {
+            parameters: ml.parameters.clone(),
+            body: ml.body.clone(),
             env: env.clone(),
-        })),
-        Expression::CallExpression(call) => {
-            let func_opt = eval_expression(&call.function, env);
-            match func_opt {
-                Some(func_obj) => {
-                    if func_obj.type_val() == ObjectType::Error {
-                        return Some(func_obj);
-                    }
-                    let args = eval_expressions(&call.arguments, env);
-                    if args.len() == 1 && args[0].type_val() == ObjectType::Error {
-                        return Some(args[0].clone());
-                    }
-                    apply_function(&func_obj, &args)
-                }
-                None => return None,
-            }
-        }
-        Expression::Array(arr) => {
+        }))),
+        Expression::CallExpression(call) => eval_call_expression(call, env),
+        Expression::Array(arr) => literal(&arr.constant, || {
             let elements = eval_expressions(&arr.elements, env);
             if elements.len() == 1 && elements[0].type_val() == ObjectType::Error {
                 return Some(elements[0].clone());
             }
-            Some(Object::Array(Array { elements }))
-        }
-        Expression::IndexExpression(idx) => {
-            let left = match eval_expression(&idx.left, env) {
-                Some(l) => l,
-                None => return None,
-            };
-            if left.type_val() == ObjectType::Error {
-                return Some(left);
-            }
-            let index = match eval_expression(&idx.index, env) {
-                Some(l) => l,
-                None => return None,
-            };
-            if index.type_val() == ObjectType::Error {
-                return Some(index);
-            }
-            Some(eval_index_expression(&left, &index))
-        }
-        Expression::Hash(hash) => eval_hash_literal(hash, env),
+            Some(Object::Array(Rc::new(Array {
+                elements,
+                frozen: false,
+            })))
+        }),
+        Expression::Tuple(tuple) => literal(&tuple.constant, || eval_tuple_literal(tuple, env)),
+        Expression::IndexExpression(idx) => eval_index_operands(idx, env),
+        Expression::Hash(hash) => literal(&hash.constant, || eval_hash_literal(hash, env)),
     }
 }
 
-fn eval_prefix_expression(pe: &PrefixExpression, right: &Object) -> Object {
-    match pe.operator {
-        PrefixOperator::Bang => eval_bang_operator(right),
-        PrefixOperator::Minus => eval_minus_operator(right),
+fn eval_infix_operands(ie: &InfixExpression, env: &mut Environment) -> Option<Object> {
+    let left = eval_expression(&ie.left, env)?;
+    if let Object::Error(_) = left {
+        return Some(left);
+    }
+    let right = eval_expression(&ie.right, env)?;
+    if let Object::Error(_) = right {
+        return Some(right);
     }
+    Some(eval_infix_expression(&left, &right, &ie.operator))
 }
 
-fn eval_bang_operator(right: &Object) -> Object {
-    match right {
-        Object::Boolean(v) => native_bool_to_bool_object(!*v),
-        Object::Null => TRUE,
-        _ => FALSE,
+fn eval_index_operands(idx: &IndexExpression, env: &mut Environment) -> Option<Object> {
+    let left = eval_expression(&idx.left, env)?;
+    if left.type_val() == ObjectType::Error {
+        return Some(left);
     }
+    let index = eval_expression(&idx.index, env)?;
+    if index.type_val() == ObjectType::Error {
+        return Some(index);
+    }
+    Some(eval_index_expression(&left, &index))
 }
 
-fn eval_minus_operator(right: &Object) -> Object {
-    match right {
-        Object::Integer(v) => Object::Integer(-v),
-        _ => Object::Error(format!("unknown operator: -{}", right.type_string())),
+fn eval_function_literal(
+    func: &FunctionLiteral,
+    name: Option<Rc<str>>,
+    env: &Environment,
+) -> Object {
+    Object::Function(Rc::new(Function {
+        parameters: func.parameters.clone(),
+        body: func.body.clone(),
+        env: env.clone(),
+        name,
+        memo: None,
+    }))
+}
+
+fn eval_call_expression(call: &CallExpression, env: &mut Environment) -> Option<Object> {
+    if let Expression::Identifier(ident) = &*call.function {
+        if &*ident.value == "quote" {
+            return Some(quote(&call.arguments, env));
+        }
+        if &*ident.value == "eval" && env.get(&ident.value).is_none() {
+            return Some(eval_code(&call.arguments, env));
+        }
+    }
+    let func_obj = eval_expression(&call.function, env)?;
+    if func_obj.type_val() == ObjectType::Error {
+        return Some(func_obj);
+    }
+    let args = eval_expressions(&call.arguments, env);
+    if args.len() == 1 && args[0].type_val() == ObjectType::Error {
+        return Some(args[0].clone());
+    }
+    // made by `apply_tail_calls` of the function this is a tail call in,
+    // without a value for now. A memoized callee has to record what it
+    // gives, so it is called.
+    if let Object::Function(callee) = &func_obj {
+        let tail = callee.memo.is_none()
+            && CALLING.with_borrow(|calling| {
+                calling
+                    .as_ref()
+                    .is_some_and(|f| in_tail_position(&f.body.statements, call))
+            });
+        if tail {
+            TAIL_CALL.set(Some((callee.clone(), args)));
+            return None;
+        }
     }
+    apply_function(&func_obj, &args)
 }
 
-fn eval_infix_expression(left: &Object, right: &Object, operator: &InfixOperator) -> Object {
-    let lval: i64;
-    let rval: i64;
-    if left.type_val() != right.type_val() {
-        return Object::Error(format!(
-            "type mismatch: {} {} {}",
-            left.type_string(),
-            operator.to_string(),
-            right.type_string()
-        ));
+/* `eval(code)` runs the string `code` in the caller's environment, so
+ * what it binds stays bound, and gives the value of its last statement.
+ * Like `quote` it's only there to be called by name, so it isn't a
+ * value or one of the `BUILTIN_NAMES`. */
+fn eval_code(arguments: &[Expression], env: &mut Environment) -> Object {
+    if !eval_granted() {
+        return Object::Error(EvalError::CapabilityNotGranted("eval"));
     }
-    match operator {
-        InfixOperator::Eq => return native_bool_to_bool_object(left == right),
-        InfixOperator::NotEq => return native_bool_to_bool_object(left != right),
-        _ => {}
-    };
-    if left.type_val() == ObjectType::String && right.type_val() == ObjectType::String {
-        let lval = match left {
-            Object::String(s) => s,
-            _ => unreachable!("lval should be a string"),
-        };
-        let rval = match right {
-            Object::String(s) => s,
-            _ => unreachable!("lval should be a string"),
-        };
-        return eval_string_infix_expression(lval, rval, &operator);
+    let args = eval_expressions(arguments, env);
+    if args.len() == 1 && args[0].type_val() == ObjectType::Error {
+        return args[0].clone();
     }
-    match left {
-        Object::Integer(val) => lval = *val,
-        _ => {
-            return Object::Error(format!(
-                "unknown operator: {} {} {}",
-                left.type_string(),
-                operator.to_string(),
-                right.type_string()
-            ))
+    let code = match args.as_slice() {
+        [Object::String(code)] => code,
+        [arg] => {
+            return Object::Error(EvalError::WrongArgumentType {
+                builtin: "eval",
+                position: 1,
+                want: TypeSet::of(&[ObjectType::String]),
+                got: arg.type_val(),
+            })
         }
-    };
-    match right {
-        Object::Integer(val) => rval = *val,
         _ => {
-            return Object::Error(format!(
-                "unknown operator: {} {} {}",
-                left.type_string(),
-                operator.to_string(),
-                right.type_string()
-            ))
+            return Object::Error(EvalError::WrongArgumentCount {
+                builtin: "eval",
+                got: args.len(),
+                min: 1,
+                max: Some(1),
+            })
         }
     };
-    eval_integer_infix_expression(lval, rval, operator)
-}
-
-fn eval_integer_infix_expression(lval: i64, rval: i64, operator: &InfixOperator) -> Object {
-    match operator {
-        InfixOperator::Plus => Object::Integer(lval + rval),
-        InfixOperator::Minus => Object::Integer(lval - rval),
-        InfixOperator::Asterisk => Object::Integer(lval * rval),
-        InfixOperator::Slash => Object::Integer(lval / rval),
-        InfixOperator::Eq => native_bool_to_bool_object(lval == rval),
-        InfixOperator::NotEq => native_bool_to_bool_object(lval != rval),
-        InfixOperator::Lt => native_bool_to_bool_object(lval < rval),
-        InfixOperator::Gt => native_bool_to_bool_object(lval > rval),
+    let depth = EVAL_DEPTH.get();
+    if depth == MAX_EVAL_DEPTH {
+        return Object::Error(EvalError::EvalTooDeep(MAX_EVAL_DEPTH));
+    }
+    let mut p = Parser::new(Lexer::new(code));
+    let program = p.parse();
+    if p.errors_len() != 0 {
+        return Object::Error(EvalError::ParseFailed(p.get_errors().clone()));
     }
+    EVAL_DEPTH.set(depth + 1);
+    let res = eval_statements(&program.statements, env);
+    EVAL_DEPTH.set(depth);
+    if let Some(Object::Error(_)) = res {
+        failed_in_code();
+    }
+    res.unwrap_or(NULL)
 }
 
-fn eval_string_infix_expression(
-    lval: &std::rc::Rc<str>,
-    rval: &std::rc::Rc<str>,
-    operator: &InfixOperator,
-) -> Object {
-    if *operator != InfixOperator::Plus {
-        return Object::Error(format!(
-            "unknown operator: {} {} {}",
-            "STRING",
-            operator.to_string(),
-            "STRING",
-        ));
-    }
-    let val = lval.to_string() + &rval.to_string();
-    Object::String(val.into())
+/* `quote(x)` gives `x` unevaluated, except for the `unquote(y)` calls in
+ * it, which are replaced by the value of `y` */
+fn quote(arguments: &[Expression], env: &Environment) -> Object {
+    match arguments {
+        [exp] => Object::Quote(Rc::new(eval_unquote_calls(exp.clone(), env))),
+        _ => Object::Error(EvalError::WrongArgumentCount {
+            builtin: "quote",
+            got: arguments.len(),
+            min: 1,
+            max: Some(1),
+        }),
+    }
 }
 
-fn eval_if_expression(ife: &IfExpression, env: &mut Environment) -> Option<Object> {
-    let cond = match eval_expression(&ife.condition, env) {
-        Some(v) => v,
-        None => return None,
-    };
-    if is_truthy(&cond) {
-        return eval_block_statments(&ife.consequence.statements, env);
-    } else {
-        match &ife.alternative {
-            Some(alt) => {
-                return eval_block_statments(&alt.statements, env);
-            }
-            None => return Some(NULL),
+fn eval_unquote_calls(exp: Expression, env: &Environment) -> Expression {
+    match exp {
+        Expression::CallExpression(call) if is_unquote_call(&call) => {
+            let mut env = env.clone();
+            let converted = eval_expression(&call.arguments[0], &mut env)
+                .as_ref()
+                .and_then(object_to_expression);
+            converted.unwrap_or(Expression::CallExpression(call))
         }
+        exp => map_children(
+            exp,
+            |exp| eval_unquote_calls(exp, env),
+            |block| BlockStatement {
+                tok: block.tok,
+                statements: block
+                    .statements
+                    .into_iter()
+                    .map(|stmt| map_statement(stmt, |exp| eval_unquote_calls(exp, env)))
+                    .collect(),
+            },
+        ),
     }
 }
 
-fn eval_block_statments(statements: &Vec<Statement>, env: &mut Environment) -> Option<Object> {
-    let mut obj: Option<Object> = None;
-    for stmt in statements {
-        obj = eval_statement(stmt, env);
-        if let Some(o) = obj.clone() {
+fn is_unquote_call(call: &CallExpression) -> bool {
+    matches!(&*call.function, Expression::Identifier(ident) if &*ident.value == "unquote")
+        && call.arguments.len() == 1
+}
+
+/* the code for a value `unquote` splices in, `None` for values that have
+ * no literal */
+fn object_to_expression(obj: &Object) -> Option<Expression> {
+    match obj {
+        Object::Integer(v) => Some(integer(*v)),
+        Object::Boolean(v) => Some(boolean(*v)),
+        Object::String(v) => Some(Expression::String(StringLiteral {
+            tok: Token::String(v.clone()),
+            value: v.clone(),
+        })),
+        Object::Quote(exp) => Some(exp.deref().clone()),
+        _ => None,
+    }
+}
+
+fn eval_tuple_literal(tuple: &TupleLiteral, env: &mut Environment) -> Option<Object> {
+    let elements = eval_expressions(&tuple.elements, env);
+    if elements.len() == 1 && elements[0].type_val() == ObjectType::Error {
+        return Some(elements[0].clone());
+    }
+    Some(Object::Tuple(Rc::new(Tuple { elements })))
+}
+
+fn eval_prefix_expression(pe: &PrefixExpression, right: &Object) -> Object {
+    eval_prefix_operator(&pe.operator, right)
+}
+
+/* what a prefix operator does to an operand of the given type */
+type PrefixHandler = fn(&Object) -> Object;
+
+/// Prefix operators by operand type, `None` matching every type. An
+/// operand without a row is an [`EvalError::UnsupportedOperand`].
+const PREFIX_OPERATORS: &[(PrefixOperator, Option<ObjectType>, PrefixHandler)] = &[
+    (PrefixOperator::Bang, None, eval_bang_operator),
+    (
+        PrefixOperator::Minus,
+        Some(ObjectType::Integer),
+        eval_integer_negation,
+    ),
+    (
+        PrefixOperator::Minus,
+        Some(ObjectType::Float),
+        eval_float_negation,
+    ),
+];
+
+pub(crate) fn eval_prefix_operator(operator: &PrefixOperator, right: &Object) -> Object {
+    let right_type = right.type_val();
+    let handler = PREFIX_OPERATORS
+        .iter()
+        .find(|(op, t, _)| op == operator && t.is_none_or(|t| t == right_type));
+    match handler {
+        Some((_, _, handler)) => handler(right),
+        None => Object::Error(EvalError::UnsupportedOperand {
+            op: operator.clone(),
+            right: right_type,
+        }),
+    }
+}
+
+pub(crate) fn eval_bang_operator(right: &Object) -> Object {
+    match right {
+        Object::Boolean(v) => native_bool_to_bool_object(!*v),
+        Object::Null => TRUE,
+        _ => FALSE,
+    }
+}
+
+pub(crate) fn eval_minus_operator(right: &Object) -> Object {
+    eval_prefix_operator(&PrefixOperator::Minus, right)
+}
+
+fn eval_integer_negation(right: &Object) -> Object {
+    let Object::Integer(v) = right else {
+        unreachable!("dispatched on an integer")
+    };
+    overflowing(v.checked_neg(), v.wrapping_neg(), v.saturating_neg())
+}
+
+/* the `checked` result, or if it overflowed, the one the host's
+ * `IntOverflow` asks for */
+fn overflowing(checked: Option<i64>, wrapping: i64, saturating: i64) -> Object {
+    match (checked, int_overflow()) {
+        (Some(val), _) => Object::Integer(val),
+        (None, IntOverflow::Checked) => Object::Error(EvalError::IntegerOverflow),
+        (None, IntOverflow::Wrapping) => Object::Integer(wrapping),
+        (None, IntOverflow::Saturating) => Object::Integer(saturating),
+    }
+}
+
+fn eval_float_negation(right: &Object) -> Object {
+    let Object::Float(v) = right else {
+        unreachable!("dispatched on a float")
+    };
+    Object::Float(-v)
+}
+
+/* what an infix operator does to operands of the given types */
+type InfixHandler = fn(&Object, &Object, &InfixOperator) -> Object;
+
+/// Infix operators by operand types, the first matching row wins. An
+/// operator of `None` matches every operator. Pairs without a row still
+/// support `==` and `!=`, with [`Object::monkey_eq`], and anything else on
+/// them is an [`EvalError::UnsupportedOperands`].
+const INFIX_OPERATORS: &[(Option<InfixOperator>, ObjectType, ObjectType, InfixHandler)] = &[
+    (
+        None,
+        ObjectType::Integer,
+        ObjectType::Integer,
+        eval_integer_infix_expression,
+    ),
+    (
+        None,
+        ObjectType::Float,
+        ObjectType::Float,
+        eval_number_infix_expression,
+    ),
+    (
+        None,
+        ObjectType::Integer,
+        ObjectType::Float,
+        eval_number_infix_expression,
+    ),
+    (
+        None,
+        ObjectType::Float,
+        ObjectType::Integer,
+        eval_number_infix_expression,
+    ),
+    (
+        Some(InfixOperator::Plus),
+        ObjectType::String,
+        ObjectType::String,
+        eval_string_concatenation,
+    ),
+    (
+        Some(InfixOperator::Asterisk),
+        ObjectType::String,
+        ObjectType::Integer,
+        eval_string_repetition,
+    ),
+];
+
+pub(crate) fn eval_infix_expression(
+    left: &Object,
+    right: &Object,
+    operator: &InfixOperator,
+) -> Object {
+    let (left_type, right_type) = (left.type_val(), right.type_val());
+    let handler = INFIX_OPERATORS.iter().find(|(op, l, r, _)| {
+        op.as_ref().is_none_or(|op| op == operator) && *l == left_type && *r == right_type
+    });
+    if let Some((_, _, _, handler)) = handler {
+        return handler(left, right, operator);
+    }
+    match operator {
+        InfixOperator::Eq => native_bool_to_bool_object(left.monkey_eq(right)),
+        InfixOperator::NotEq => native_bool_to_bool_object(!left.monkey_eq(right)),
+        _ => no_infix_operator(left, right, operator),
+    }
+}
+
+/* the error for operands `operator` isn't defined on */
+fn no_infix_operator(left: &Object, right: &Object, operator: &InfixOperator) -> Object {
+    Object::Error(EvalError::UnsupportedOperands {
+        op: operator.clone(),
+        left: left.type_val(),
+        right: right.type_val(),
+    })
+}
+
+fn eval_integer_infix_expression(
+    left: &Object,
+    right: &Object,
+    operator: &InfixOperator,
+) -> Object {
+    let (Object::Integer(lval), Object::Integer(rval)) = (left, right) else {
+        unreachable!("dispatched on two integers")
+    };
+    let (lval, rval) = (*lval, *rval);
+    match operator {
+        InfixOperator::Plus => overflowing(
+            lval.checked_add(rval),
+            lval.wrapping_add(rval),
+            lval.saturating_add(rval),
+        ),
+        InfixOperator::Minus => overflowing(
+            lval.checked_sub(rval),
+            lval.wrapping_sub(rval),
+            lval.saturating_sub(rval),
+        ),
+        InfixOperator::Asterisk => overflowing(
+            lval.checked_mul(rval),
+            lval.wrapping_mul(rval),
+            lval.saturating_mul(rval),
+        ),
+        InfixOperator::Slash if rval == 0 => Object::Error(EvalError::DivisionByZero),
+        InfixOperator::Slash => overflowing(
+            lval.checked_div(rval),
+            lval.wrapping_div(rval),
+            lval.saturating_div(rval),
+        ),
+        InfixOperator::Eq => native_bool_to_bool_object(lval == rval),
+        InfixOperator::NotEq => native_bool_to_bool_object(lval != rval),
+        InfixOperator::Lt => native_bool_to_bool_object(lval < rval),
+        InfixOperator::Gt => native_bool_to_bool_object(lval > rval),
+    }
+}
+
+/* two numbers with a float among them, the integer taken as the float it
+ * is closest to, so `2 == sqrt(4)`, `1 < sqrt(2)` and `1 + 0.5` is `1.5`.
+ * That an integer can equal a float may surprise, and a large integer can
+ * equal a float that is a little off from it. Two integers never get
+ * here, they compute exactly. Dividing by zero fails like it does for
+ * integers rather than giving an infinity. */
+fn eval_number_infix_expression(left: &Object, right: &Object, operator: &InfixOperator) -> Object {
+    let as_float = |obj: &Object| match obj {
+        Object::Integer(v) => *v as f64,
+        Object::Float(v) => *v,
+        _ => unreachable!("dispatched on two numbers"),
+    };
+    let (lval, rval) = (as_float(left), as_float(right));
+    match operator {
+        InfixOperator::Plus => Object::Float(lval + rval),
+        InfixOperator::Minus => Object::Float(lval - rval),
+        InfixOperator::Asterisk => Object::Float(lval * rval),
+        InfixOperator::Slash if rval == 0.0 => Object::Error(EvalError::DivisionByZero),
+        InfixOperator::Slash => Object::Float(lval / rval),
+        InfixOperator::Eq => native_bool_to_bool_object(lval == rval),
+        InfixOperator::NotEq => native_bool_to_bool_object(lval != rval),
+        InfixOperator::Lt => native_bool_to_bool_object(lval < rval),
+        InfixOperator::Gt => native_bool_to_bool_object(lval > rval),
+    }
+}
+
+fn eval_string_concatenation(left: &Object, right: &Object, _: &InfixOperator) -> Object {
+    let (Object::String(lval), Object::String(rval)) = (left, right) else {
+        unreachable!("dispatched on two strings")
+    };
+    let val = lval.to_string() + rval.as_ref();
+    Object::String(val.into())
+}
+
+/* `"ab" * 3` is `"ababab"` */
+fn eval_string_repetition(left: &Object, right: &Object, _: &InfixOperator) -> Object {
+    let (Object::String(s), Object::Integer(count)) = (left, right) else {
+        unreachable!("dispatched on a string and an integer")
+    };
+    match usize::try_from(*count) {
+        // a result too long to allocate can't be built either
+        Ok(n)
+            if s.len()
+                .checked_mul(n)
+                .is_some_and(|len| len <= isize::MAX as usize) =>
+        {
+            Object::String(s.repeat(n).into())
+        }
+        _ => Object::Error(EvalError::InvalidRepeatCount(*count)),
+    }
+}
+
+fn eval_if_expression(ife: &IfExpression, env: &mut Environment) -> Option<Object> {
+    let cond = eval_expression(&ife.condition, env)?;
+    if let Object::Error(_) = cond {
+        return Some(cond);
+    }
+    if is_truthy(&cond) {
+        eval_block_statments(&ife.consequence.statements, env)
+    } else {
+        match &ife.alternative {
+            Some(alt) => eval_block_statments(&alt.statements, env),
+            None => Some(NULL),
+        }
+    }
+}
+
+fn eval_for_expression(fe: &ForExpression, env: &mut Environment) -> Option<Object> {
+    let iterable = eval_expression(&fe.iterable, env)?;
+    let index = |i: usize| Object::Integer(i as i64);
+    match &iterable {
+        Object::Error(_) => Some(iterable),
+        Object::Array(arr) => {
+            let items = arr.elements.iter().enumerate();
+            eval_loop(fe, env, false, items.map(|(i, el)| (index(i), el.clone())))
+        }
+        Object::Hash(hash) => eval_loop(fe, env, true, hash.pairs().iter().cloned()),
+        Object::String(s) => {
+            let chars = s.chars().map(|ch| Object::String(ch.to_string().into()));
+            eval_loop(
+                fe,
+                env,
+                false,
+                chars.enumerate().map(|(i, ch)| (index(i), ch)),
+            )
+        }
+        Object::Range(range) => {
+            let ints = (range.start..range.end).map(Object::Integer);
+            eval_loop(fe, env, false, ints.enumerate().map(|(i, n)| (index(i), n)))
+        }
+        Object::Iterator(iter) => {
+            let elements = std::iter::from_fn(|| iter.next()).enumerate();
+            eval_loop(fe, env, false, elements.map(|(i, el)| (index(i), el)))
+        }
+        _ => Some(Object::Error(EvalError::NotIterable(iterable.type_val()))),
+    }
+}
+
+/* runs the body of `fe` for each key or index and value of `items`. A
+ * single name is bound to the key if `keys`, and to the value if not. */
+fn eval_loop(
+    fe: &ForExpression,
+    env: &mut Environment,
+    keys: bool,
+    items: impl Iterator<Item = (Object, Object)>,
+) -> Option<Object> {
+    for (key, value) in items {
+        // an iterator gives the error a function it calls made
+        if let Object::Error(_) = value {
+            return Some(value);
+        }
+        // a turn of an empty body evaluates nothing, but still takes a step
+        if let Some(err) = burn_fuel() {
+            return Some(Object::Error(err));
+        }
+        // every turn gets a scope of its own, so a closure made in the body
+        // keeps the values it saw
+        let mut scope = Environment::new_enclosed_env(env);
+        match (fe.names.as_slice(), keys) {
+            ([name], true) => scope.set(name.value.clone(), key),
+            ([name], false) => scope.set(name.value.clone(), value),
+            (names, _) => {
+                for (name, val) in names.iter().zip([key, value]) {
+                    scope.set(name.value.clone(), val);
+                }
+            }
+        }
+        let res = eval_block_statments(&fe.body.statements, &mut scope);
+        match res {
+            Some(Object::Break(None)) => break,
+            Some(Object::Break(Some(label)))
+                if fe.label.as_ref().is_some_and(|l| l.value == label) =>
+            {
+                break
+            }
+            // to the loop with the label, further out
+            Some(obj @ (Object::Return(_) | Object::Error(_) | Object::Break(_))) => {
+                return Some(obj)
+            }
+            _ => {}
+        }
+    }
+    Some(NULL)
+}
+
+pub(crate) fn eval_block_statments(
+    statements: &[Statement],
+    env: &mut Environment,
+) -> Option<Object> {
+    let mut obj: Option<Object> = None;
+    for stmt in statements {
+        obj = eval_statement(stmt, env);
+        if let Some(o) = obj.clone() {
             match o {
-                Object::Return(_) => return Some(o),
+                Object::Return(_) | Object::Break(_) | Object::Continue => return Some(o),
                 Object::Error(_) => return Some(o),
                 _ => {}
             }
@@ -297,34 +1513,21 @@ fn eval_identifier(name: &std::rc::Rc<str>, env: &Environment) -> Object {
     match env.get(name) {
         Some(v) => v.clone(),
         None => {
-            let s = name.to_string();
-            if s == "len".to_owned() {
-                return LEN;
-            }
-            if s == "first".to_owned() {
-                return FIRST;
-            }
-            if s == "last".to_owned() {
-                return LAST;
-            }
-            if s == "rest".to_owned() {
-                return REST;
-            }
-            if s == "push".to_owned() {
-                return PUSH;
+            if let Some(obj) = builtins::prelude(name) {
+                return obj;
             }
-            if s == "print".to_owned() {
-                return PRINT;
+            if let Some(obj) = builtins::stdlib().global(name) {
+                return obj;
             }
-            Object::Error(format!("identifier not found: {}", name))
+            Object::Error(EvalError::IdentifierNotFound(name.to_string()))
         }
     }
 }
 
-fn eval_expressions(exps: &Vec<Expression>, env: &mut Environment) -> Vec<Object> {
+fn eval_expressions(exps: &[Expression], env: &mut Environment) -> Vec<Object> {
     let mut res = Vec::new();
     for exp in exps.iter() {
-        let obj = match eval_expression(&exp, env) {
+        let obj = match eval_expression(exp, env) {
             Some(o) => o,
             None => return Vec::new(),
         };
@@ -338,25 +1541,31 @@ fn eval_expressions(exps: &Vec<Expression>, env: &mut Environment) -> Vec<Object
     res
 }
 
-fn apply_function(func_obj: &Object, args: &Vec<Object>) -> Option<Object> {
+pub(crate) fn apply_function(func_obj: &Object, args: &[Object]) -> Option<Object> {
     match func_obj {
         Object::Function(func) => {
-            let mut extended = extend_function_env(func, args);
-            let evaluated = eval_block_statments(&func.body.statements, &mut extended);
-            match evaluated {
-                Some(e) => Some(unwrap_return_value(e)),
-                None => None,
+            if let Some(res) = func.memo.as_ref().and_then(|memo| memo.get(args)) {
+                return Some(res);
             }
+            let depth = CALL_DEPTH.get();
+            let limit = builtins::max_call_depth();
+            if depth >= limit {
+                return Some(Object::Error(EvalError::CallTooDeep(limit)));
+            }
+            CALL_DEPTH.set(depth + 1);
+            let outer = CALLING.replace(Some(func.clone()));
+            let res = apply_tail_calls(func.clone(), args.to_vec());
+            CALLING.set(outer);
+            CALL_DEPTH.set(depth);
+            // an error may not happen again, like one `exit` made
+            match (&func.memo, &res) {
+                (Some(_), Some(Object::Error(_))) | (None, _) => {}
+                (Some(memo), res) => memo.insert(args, res.clone().unwrap_or(NULL)),
+            }
+            res
         }
-        Object::Builtin(builtin) => {
-            let fun = builtin.func;
-            let r = fun(args);
-            Some(r)
-        }
-        _ => Some(Object::Error(format!(
-            "not a function: {}",
-            func_obj.type_string()
-        ))),
+        Object::Builtin(builtin) => Some(builtin.def.call(args)),
+        _ => Some(Object::Error(EvalError::NotCallable(func_obj.type_val()))),
     }
 }
 
@@ -365,13 +1574,13 @@ fn eval_index_expression(left: &Object, index: &Object) -> Object {
     if lt == ObjectType::Array && index.type_val() == ObjectType::Integer {
         return eval_array_index_expression(left, index);
     }
+    if let (Object::String(s), Object::Integer(idx)) = (left, index) {
+        return eval_string_index_expression(s, *idx);
+    }
     if lt == ObjectType::Hash {
         return eval_hash_index_expression(left, index);
     }
-    Object::Error(format!(
-        "index operator not supported: {}",
-        left.type_string()
-    ))
+    Object::Error(EvalError::IndexNotSupported(left.type_val()))
 }
 
 fn eval_array_index_expression(left: &Object, index: &Object) -> Object {
@@ -388,7 +1597,16 @@ fn eval_array_index_expression(left: &Object, index: &Object) -> Object {
         return NULL;
     }
 
-    return arr.elements[*idx as usize].clone();
+    arr.elements[*idx as usize].clone()
+}
+
+/* the char at `idx`, as `len` counts them, or null like an array index
+ * out of range */
+fn eval_string_index_expression(s: &str, idx: i64) -> Object {
+    usize::try_from(idx)
+        .ok()
+        .and_then(|idx| s.chars().nth(idx))
+        .map_or(NULL, |ch| Object::String(ch.to_string().into()))
 }
 
 fn eval_hash_index_expression(left: &Object, index: &Object) -> Object {
@@ -396,37 +1614,90 @@ fn eval_hash_index_expression(left: &Object, index: &Object) -> Object {
         Object::Hash(h) => h,
         _ => unreachable!("not a hash left in eval_hash_index_expression"),
     };
-    let val = hash.pairs.iter().find(|x| x.0 == *index);
-    match val {
-        Some(v) => v.1.clone(),
-        None => NULL,
-    }
+    hash.get(index).cloned().unwrap_or(NULL)
 }
 
 fn eval_hash_literal(hash: &HashLiteral, env: &mut Environment) -> Option<Object> {
-    let mut pairs = Vec::new();
+    let mut res = Hash::new();
     for pair in hash.pairs.iter() {
-        let key = match eval_expression(&pair.0, env) {
-            Some(v) => v,
-            None => return None,
-        };
+        let key = eval_expression(&pair.0, env)?;
         if key.type_val() == ObjectType::Error {
             return Some(key);
         }
-        let val = match eval_expression(&pair.1, env) {
-            Some(v) => v,
-            None => return None,
-        };
+        let val = eval_expression(&pair.1, env)?;
         if val.type_val() == ObjectType::Error {
             return Some(val);
         }
-        pairs.push((key, val));
+        res.insert(key, val);
+    }
+    Some(Object::Hash(Rc::new(res)))
+}
+
+/* runs the body of `func`, and then of each function it calls in tail
+ * position in its place, in this Rust frame instead of one more. Every
+ * call gets an environment of its own, so a closure made in one still
+ * sees its arguments after the next call. */
+fn apply_tail_calls(mut func: Rc<Function>, mut args: Vec<Object>) -> Option<Object> {
+    let observing = OBSERVING.get();
+    if observing {
+        let depth = OBSERVED.with_borrow(Vec::len);
+        FRAMES.with_borrow_mut(|frames| frames.push((func.clone(), depth)));
+    }
+    let res = loop {
+        let mut extended = extend_function_env(&func, &args);
+        let evaluated = eval_block_statments(&func.body.statements, &mut extended);
+        if let Some((callee, callee_args)) = TAIL_CALL.take() {
+            CALLING.set(Some(callee.clone()));
+            if observing {
+                FRAMES.with_borrow_mut(|frames| {
+                    if let Some(frame) = frames.last_mut() {
+                        frame.0 = callee.clone();
+                    }
+                });
+            }
+            (func, args) = (callee, callee_args);
+            continue;
+        }
+        if let Some(Object::Error(err)) = &evaluated {
+            failed_in(&func, err);
+        }
+        break evaluated.map(unwrap_return_value);
+    };
+    if observing {
+        FRAMES.with_borrow_mut(Vec::pop);
+    }
+    res
+}
+
+/* whether `call` is a tail call in `statements`, the body of a function:
+ * the last statement, returned or not, or the last statement of a branch
+ * of an `if` that is */
+fn in_tail_position(statements: &[Statement], call: &CallExpression) -> bool {
+    let exp = match statements.last() {
+        Some(Statement::ExpressionStatement(es)) => &es.expression,
+        Some(Statement::ReturnStatement(rs)) => &rs.value,
+        _ => return false,
+    };
+    match exp {
+        Expression::CallExpression(last) => std::ptr::eq(last, call),
+        Expression::IfExpression(ife) => {
+            in_tail_position(&ife.consequence.statements, call)
+                || ife
+                    .alternative
+                    .as_ref()
+                    .is_some_and(|alt| in_tail_position(&alt.statements, call))
+        }
+        _ => false,
     }
-    Some(Object::Hash(Hash { pairs }))
 }
 
-fn extend_function_env(func: &Function, args: &Vec<Object>) -> Environment {
+fn extend_function_env(func: &Rc<Function>, args: &[Object]) -> Environment {
     let mut env = Environment::new_enclosed_env(&func.env);
+    // so a function `memoize` made calls itself rather than what it was
+    // made of
+    if let Some(name) = &func.name {
+        env.set(name.clone(), Object::Function(func.clone()));
+    }
 
     for (i, param) in func.parameters.iter().enumerate() {
         let arg = args[i].clone();
@@ -444,7 +1715,7 @@ fn native_bool_to_bool_object(input: bool) -> Object {
     }
 }
 
-fn is_truthy(obj: &Object) -> bool {
+pub(crate) fn is_truthy(obj: &Object) -> bool {
     match obj {
         Object::Null => false,
         Object::Boolean(v) => *v,
@@ -452,7 +1723,7 @@ fn is_truthy(obj: &Object) -> bool {
     }
 }
 
-fn unwrap_return_value(obj: Object) -> Object {
+pub(crate) fn unwrap_return_value(obj: Object) -> Object {
     match obj {
         Object::Return(val) => val.deref().clone(),
         _ => obj,
@@ -461,9 +1732,23 @@ fn unwrap_return_value(obj: Object) -> Object {
 
 #[cfg(test)]
 mod test {
+    use std::rc::Rc;
+
     use crate::{
-        ast::Node, environment::Environment, evaluator::eval, lexer::Lexer, object::Object,
+        ast::{InfixOperator, Node, PrefixOperator},
+        builtins::{
+            capture_output, with_host, Capabilities, Clock, Host, IntOverflow, SeededRng, BUILTINS,
+            BUILTIN_NAMES,
+        },
+        environment::Environment,
+        evaluator::{
+            eval, eval_each, eval_infix_expression, eval_located, eval_observed,
+            eval_prefix_operator, eval_source, Pause,
+        },
+        lexer::{line_col, token_spans, Lexer},
+        object::{Array, ErrorKind, EvalError, Object, ObjectTrait, ObjectType, TypeSet},
         parser::Parser,
+        stdlib::{Group, StdlibConfig},
     };
 
     struct IntTest {
@@ -600,6 +1885,51 @@ mod test {
         }
     }
 
+    #[test]
+    fn test_left_associative_arithmetic() {
+        let tests = [
+            IntTest {
+                input: "10 - 2 - 3",
+                exp: 5,
+            },
+            IntTest {
+                input: "16 / 2 / 2",
+                exp: 4,
+            },
+            IntTest {
+                input: "100 - 50 - 25 - 5",
+                exp: 20,
+            },
+            IntTest {
+                input: "64 / 4 / 2 / 2",
+                exp: 4,
+            },
+            IntTest {
+                input: "10 - 4 + 3 - 2",
+                exp: 7,
+            },
+            IntTest {
+                input: "20 / 5 * 2 / 4",
+                exp: 2,
+            },
+            IntTest {
+                input: "10 - (2 - 3)",
+                exp: 11,
+            },
+            IntTest {
+                input: "16 / (4 / 2)",
+                exp: 8,
+            },
+        ];
+
+        for test in tests.iter() {
+            match test_eval(test.input) {
+                Some(obj) => test_int_object(&obj, test.exp),
+                None => panic!("evaluator returned None"),
+            }
+        }
+    }
+
     #[test]
     fn test_eval_bool_expression() {
         let tests = vec![
@@ -693,7 +2023,7 @@ mod test {
 
     #[test]
     fn test_bang_operator() {
-        let tests = vec![
+        let tests = [
             BoolTest {
                 input: "!true",
                 exp: false,
@@ -732,7 +2062,7 @@ mod test {
 
     #[test]
     fn test_if_else_expressions() {
-        let tests = vec![
+        let tests = [
             IfElseTest {
                 input: "if (true) { 10 }",
                 exp: Some(10),
@@ -779,7 +2109,7 @@ mod test {
 
     #[test]
     fn test_return_statements() {
-        let tests = vec![
+        let tests = [
             IntTest {
                 input: "return 10;",
                 exp: 10,
@@ -823,27 +2153,27 @@ mod test {
         let tests = vec![
             ErrorTest {
                 input: "5 + true",
-                exp: "type mismatch: INTEGER + BOOLEAN",
+                exp: "unsupported operand types for +: INTEGER and BOOLEAN",
             },
             ErrorTest {
                 input: "5 + true; 5;",
-                exp: "type mismatch: INTEGER + BOOLEAN",
+                exp: "unsupported operand types for +: INTEGER and BOOLEAN",
             },
             ErrorTest {
                 input: "-true",
-                exp: "unknown operator: -BOOLEAN",
+                exp: "unsupported operand type for -: BOOLEAN",
             },
             ErrorTest {
                 input: "true + false;",
-                exp: "unknown operator: BOOLEAN + BOOLEAN",
+                exp: "unsupported operand types for +: BOOLEAN and BOOLEAN",
             },
             ErrorTest {
                 input: "5; true + false; 5",
-                exp: "unknown operator: BOOLEAN + BOOLEAN",
+                exp: "unsupported operand types for +: BOOLEAN and BOOLEAN",
             },
             ErrorTest {
                 input: "if (10 > 1) { true + false; }",
-                exp: "unknown operator: BOOLEAN + BOOLEAN",
+                exp: "unsupported operand types for +: BOOLEAN and BOOLEAN",
             },
             ErrorTest {
                 input: "if (10 > 1) {
@@ -852,7 +2182,7 @@ mod test {
                     }
                     return 1;
                 }",
-                exp: "unknown operator: BOOLEAN + BOOLEAN",
+                exp: "unsupported operand types for +: BOOLEAN and BOOLEAN",
             },
             ErrorTest {
                 input: "foobar",
@@ -860,190 +2190,2290 @@ mod test {
             },
             ErrorTest {
                 input: "\"Hello\" - \"World\"",
-                exp: "unknown operator: STRING - STRING",
+                exp: "unsupported operand types for -: STRING and STRING",
             },
             ErrorTest {
                 input: "len(1)",
-                exp: "argument to `len` not supported, got INTEGER",
+                exp: "`len` expects STRING|ARRAY|HASH|ITERATOR for argument 1, got INTEGER",
             },
             ErrorTest {
                 input: "len(\"one\", \"two\")",
-                exp: "wrong number of arguments. got=2, want=1",
+                exp: "`len` expects 1 argument, got 2",
+            },
+        ];
+
+        for test in tests.iter() {
+            let obj = test_eval(test.input);
+            match obj {
+                Some(v) => match v {
+                    Object::Error(v) => assert_eq!(v.to_string(), test.exp),
+                    _ => panic!("{:#?} is not an error object", v),
+                },
+                None => panic!("eval returned none"),
+            }
+        }
+    }
+
+    #[test]
+    fn test_let_statements() {
+        let tests = [
+            IntTest {
+                input: "let a = 5; a;",
+                exp: 5,
+            },
+            IntTest {
+                input: "let a = 5 * 5; a;",
+                exp: 25,
+            },
+            IntTest {
+                input: "let a = 5; let b = a; b;",
+                exp: 5,
+            },
+            IntTest {
+                input: "let a = 5; let b = a; let c = a + b + 5; c;",
+                exp: 15,
+            },
+        ];
+
+        for test in tests.iter() {
+            let obj_opt = test_eval(test.input);
+            if let Some(obj) = obj_opt {
+                test_int_object(&obj, test.exp);
+            } else {
+                panic!("evaluator returned None");
+            }
+        }
+    }
+
+    #[test]
+    fn test_tuple_destructuring() {
+        let tests = [
+            IntTest {
+                input: "let f = fn() { return 1, 2; }; let a, b = f(); a * 10 + b;",
+                exp: 12,
+            },
+            IntTest {
+                input: "let f = fn(x) { (x, x * 2, x * 3) }; let a, b, c = f(2); a + b + c;",
+                exp: 12,
+            },
+            IntTest {
+                input: "let a, b = (1, 2); let b, a = (a, b); a - b;",
+                exp: 1,
+            },
+        ];
+
+        for test in tests.iter() {
+            let obj_opt = test_eval(test.input);
+            if let Some(obj) = obj_opt {
+                test_int_object(&obj, test.exp);
+            } else {
+                panic!("evaluator returned None");
+            }
+        }
+    }
+
+    #[test]
+    fn test_tuple_destructuring_errors() {
+        let tests = [
+            ErrorTest {
+                input: "let f = fn() { return 1, 2, 3; }; let a, b = f();",
+                exp: "wrong number of values to destructure. got=3, want=2",
+            },
+            ErrorTest {
+                input: "let a, b, c = (1, 2);",
+                exp: "wrong number of values to destructure. got=2, want=3",
+            },
+            ErrorTest {
+                input: "let a, b = 5;",
+                exp: "cannot destructure INTEGER into 2 names",
             },
         ];
 
-        for test in tests.iter() {
-            let obj = test_eval(test.input);
-            match obj {
-                Some(v) => match v {
-                    Object::Error(v) => assert_eq!(v, test.exp.to_owned()),
-                    _ => panic!("{:#?} is not an error object", v),
-                },
-                None => panic!("eval returned none"),
-            }
+        for test in tests.iter() {
+            let obj = test_eval(test.input);
+            match obj {
+                Some(Object::Error(v)) => assert_eq!(v.to_string(), test.exp),
+                _ => panic!("{:#?} is not an error object", obj),
+            }
+        }
+    }
+
+    /* a few values of each type a program can hold, so that what depends
+     * on the value shows up too. A new type doesn't compile here until it
+     * has some. */
+    fn samples(t: ObjectType) -> Vec<Object> {
+        match t {
+            ObjectType::Null => vec![Object::Null],
+            ObjectType::Integer => vec![Object::Integer(0), Object::Integer(3)],
+            ObjectType::Float => vec![Object::Float(0.0), Object::Float(1.5)],
+            ObjectType::Boolean => vec![Object::Boolean(true), Object::Boolean(false)],
+            ObjectType::String => vec![Object::String("".into()), Object::String("ab".into())],
+            ObjectType::Array => vec![test_eval("[1, 2]").unwrap()],
+            ObjectType::Tuple => vec![test_eval("(1, 2)").unwrap()],
+            ObjectType::Hash => vec![test_eval("{1: 2}").unwrap()],
+            ObjectType::Range => vec![test_eval("range(0, 2)").unwrap()],
+            ObjectType::Iterator => vec![test_eval("iter([1, 2])").unwrap()],
+            ObjectType::Function => vec![test_eval("fn(x) { x }").unwrap()],
+            ObjectType::Builtin => vec![test_eval("len").unwrap()],
+            ObjectType::Quote => vec![test_eval("quote(1)").unwrap()],
+            ObjectType::Caught => vec![test_eval("let _, err = try(fn() { 1 / 0 }); err").unwrap()],
+            ObjectType::Macro => {
+                let mut program = Parser::new(Lexer::new("let m = macro() { 1 };")).parse();
+                let mut env = Environment::new();
+                crate::macros::define_macros(&mut program, &mut env);
+                vec![env.get(&"m".into()).unwrap().clone()]
+            }
+            // these never get to an operator, the evaluator passes them on
+            // before it applies one
+            ObjectType::Return
+            | ObjectType::Break
+            | ObjectType::Continue
+            | ObjectType::Error
+            | ObjectType::CompiledFunction => vec![],
+        }
+    }
+
+    const TYPES: [ObjectType; 20] = [
+        ObjectType::Null,
+        ObjectType::Integer,
+        ObjectType::Float,
+        ObjectType::Boolean,
+        ObjectType::Return,
+        ObjectType::Break,
+        ObjectType::Continue,
+        ObjectType::Error,
+        ObjectType::Function,
+        ObjectType::String,
+        ObjectType::Builtin,
+        ObjectType::Array,
+        ObjectType::Tuple,
+        ObjectType::Hash,
+        ObjectType::Range,
+        ObjectType::Iterator,
+        ObjectType::CompiledFunction,
+        ObjectType::Quote,
+        ObjectType::Macro,
+        ObjectType::Caught,
+    ];
+
+    #[test]
+    fn test_infix_operator_matrix() {
+        let operators = [
+            InfixOperator::Plus,
+            InfixOperator::Minus,
+            InfixOperator::Asterisk,
+            InfixOperator::Slash,
+            InfixOperator::Lt,
+            InfixOperator::Gt,
+            InfixOperator::Eq,
+            InfixOperator::NotEq,
+        ];
+        // every operator is above, or this doesn't compile
+        for op in operators.iter() {
+            match op {
+                InfixOperator::Plus
+                | InfixOperator::Minus
+                | InfixOperator::Asterisk
+                | InfixOperator::Slash
+                | InfixOperator::Lt
+                | InfixOperator::Gt
+                | InfixOperator::Eq
+                | InfixOperator::NotEq => {}
+            }
+        }
+        let number = |t: ObjectType| matches!(t, ObjectType::Integer | ObjectType::Float);
+        let supported = |op: &InfixOperator, l: ObjectType, r: ObjectType| {
+            matches!(op, InfixOperator::Eq | InfixOperator::NotEq)
+                || (number(l) && number(r))
+                || (*op == InfixOperator::Plus
+                    && l == ObjectType::String
+                    && r == ObjectType::String)
+                || (*op == InfixOperator::Asterisk
+                    && l == ObjectType::String
+                    && r == ObjectType::Integer)
+        };
+        for op in operators.iter() {
+            for (l, r) in TYPES
+                .iter()
+                .flat_map(|l| TYPES.iter().map(move |r| (*l, *r)))
+            {
+                for (left, right) in samples(l).iter().flat_map(|left| {
+                    samples(r)
+                        .into_iter()
+                        .map(move |right| (left.clone(), right))
+                }) {
+                    let res = eval_infix_expression(&left, &right, op);
+                    let case = format!("{} {} {}", left.inspect(), op, right.inspect());
+                    if !supported(op, l, r) {
+                        let exp = format!(
+                            "ERROR: unsupported operand types for {}: {} and {}",
+                            op, l, r
+                        );
+                        assert_eq!(res.inspect(), exp, "{}", case);
+                        continue;
+                    }
+                    match res {
+                        Object::Error(EvalError::DivisionByZero) => {
+                            assert_eq!(*op, InfixOperator::Slash, "{}", case);
+                            assert!(right == Object::Integer(0) || right == Object::Float(0.0));
+                        }
+                        Object::Error(e) => panic!("{}: {}", case, e),
+                        _ => {}
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_prefix_operator_matrix() {
+        for t in TYPES {
+            for right in samples(t) {
+                let res = eval_prefix_operator(&PrefixOperator::Bang, &right);
+                assert_eq!(res.type_val(), ObjectType::Boolean, "!{}", right.inspect());
+
+                let res = eval_prefix_operator(&PrefixOperator::Minus, &right);
+                if matches!(t, ObjectType::Integer | ObjectType::Float) {
+                    assert_eq!(res.type_val(), t, "-{}", right.inspect());
+                } else {
+                    let exp = format!("ERROR: unsupported operand type for -: {}", t);
+                    assert_eq!(res.inspect(), exp);
+                }
+            }
+        }
+        assert_eq!(test_eval("-sqrt(4)"), Some(Object::Float(-2.0)));
+    }
+
+    #[test]
+    fn test_structured_errors() {
+        match test_eval("5 + true;") {
+            Some(Object::Error(EvalError::UnsupportedOperands { op, left, right })) => {
+                assert_eq!(op, InfixOperator::Plus);
+                assert_eq!(left, ObjectType::Integer);
+                assert_eq!(right, ObjectType::Boolean);
+            }
+            obj => panic!("{:#?} is not an unsupported operands error", obj),
+        }
+
+        let tests = [
+            (
+                "-true",
+                EvalError::UnsupportedOperand {
+                    op: PrefixOperator::Minus,
+                    right: ObjectType::Boolean,
+                },
+            ),
+            ("10 / 0", EvalError::DivisionByZero),
+            ("foobar", EvalError::IdentifierNotFound("foobar".to_owned())),
+            ("5(1)", EvalError::NotCallable(ObjectType::Integer)),
+            (
+                "len(1, 2)",
+                EvalError::WrongArgumentCount {
+                    builtin: "len",
+                    got: 2,
+                    min: 1,
+                    max: Some(1),
+                },
+            ),
+            (
+                "first(1)",
+                EvalError::WrongArgumentType {
+                    builtin: "first",
+                    position: 1,
+                    want: TypeSet::of(&[ObjectType::Array]),
+                    got: ObjectType::Integer,
+                },
+            ),
+        ];
+
+        for (input, exp) in tests {
+            match test_eval(input) {
+                Some(Object::Error(err)) => assert_eq!(err, exp),
+                obj => panic!("{:#?} is not an error object", obj),
+            }
+        }
+    }
+
+    #[test]
+    fn test_function_object() {
+        let input = "fn(x) { x + 2; };";
+        let obj = test_eval(input);
+        match obj {
+            Some(o) => {
+                if let Object::Function(func) = o {
+                    assert_eq!(func.parameters.len(), 1);
+                    let param1 = &func.parameters[0];
+                    assert_eq!(param1.value.to_string(), "x".to_owned());
+                    let exp_body = "(x + 2)";
+                    let s = func.body.string();
+                    assert_eq!(exp_body.to_owned(), s);
+                } else {
+                    panic!("{:#?} is not a function", o);
+                }
+            }
+            None => panic!("eval returned none"),
+        }
+    }
+
+    #[test]
+    fn test_function_application() {
+        let tests = [
+            IntTest {
+                input: "let identity = fn(x) { x; }; identity(5);",
+                exp: 5,
+            },
+            IntTest {
+                input: "let identity = fn(x) { return x; }; identity(5);",
+                exp: 5,
+            },
+            IntTest {
+                input: "let double = fn(x) { x * 2; }; double(5);",
+                exp: 10,
+            },
+            IntTest {
+                input: "let add = fn(x, y) { x + y; }; add(5, 5);",
+                exp: 10,
+            },
+            IntTest {
+                input: "let add = fn(x, y) { x + y; }; add(5 + 5, add(5, 5));",
+                exp: 20,
+            },
+            IntTest {
+                input: "fn(x) { x; }(5)",
+                exp: 5,
+            },
+        ];
+
+        for test in tests.iter() {
+            let obj_opt = test_eval(test.input);
+            if let Some(obj) = obj_opt {
+                test_int_object(&obj, test.exp);
+            } else {
+                panic!("evaluator returned None");
+            }
+        }
+    }
+
+    #[test]
+    fn test_closures() {
+        let input = "
+        let newAdder = fn(x) {
+            fn(y) { x + y };
+        };
+        let addTwo = newAdder(2);
+        addTwo(2);";
+        let obj_opt = test_eval(input);
+        if let Some(obj) = obj_opt {
+            test_int_object(&obj, 4);
+        } else {
+            panic!("evaluator returned None");
+        }
+    }
+
+    #[test]
+    fn test_strings() {
+        let input = "\"Hello World!\"";
+        let obj_opt = test_eval(input);
+        if let Some(obj) = obj_opt {
+            if let Object::String(s) = obj {
+                assert_eq!(s.to_string(), "Hello World!");
+            } else {
+                panic!("{:#?} is not a string", obj);
+            }
+        } else {
+            panic!("evaluator returned None");
+        }
+    }
+
+    #[test]
+    fn test_string_concatination() {
+        let input = "\"Hello\" + \" \" + \"World!\"";
+        let obj_opt = test_eval(input);
+        if let Some(obj) = obj_opt {
+            if let Object::String(s) = obj {
+                assert_eq!(s.to_string(), "Hello World!");
+            } else {
+                panic!("{:#?} is not a string", obj);
+            }
+        } else {
+            panic!("evaluator returned None");
+        }
+    }
+
+    #[test]
+    fn test_string_repetition() {
+        let tests = [
+            ("\"ab\" * 3", "ababab"),
+            ("\"ab\" * 0", ""),
+            ("\"\" * 5", ""),
+            ("\"-\" * 2 + \"|\"", "--|"),
+            ("\"ab\" * 3 == \"ababab\"", "true"),
+            ("\"ab\" * -1", "ERROR: invalid repeat count: -1"),
+            (
+                "\"ab\" * 9223372036854775807",
+                "ERROR: invalid repeat count: 9223372036854775807",
+            ),
+            (
+                "3 * \"ab\"",
+                "ERROR: unsupported operand types for *: INTEGER and STRING",
+            ),
+            (
+                "\"ab\" - 1",
+                "ERROR: unsupported operand types for -: STRING and INTEGER",
+            ),
+            (
+                "\"ab\" * \"ab\"",
+                "ERROR: unsupported operand types for *: STRING and STRING",
+            ),
+        ];
+        for (input, exp) in tests {
+            let obj = test_eval(input).expect("evaluator returned None");
+            assert_eq!(obj.inspect(), exp, "evaluating {:?}", input);
+        }
+    }
+
+    #[test]
+    fn test_stdlib_namespaces() {
+        let tests = [
+            ("string.upper(\"a\")", "A"),
+            ("string[\"upper\"](\"a\")", "A"),
+            ("math.abs(-3) + array.len([1])", "4"),
+            ("hash.keys({1: 2})", "[1]"),
+            ("string.upper == upper", "true"),
+            ("type(string)", "HASH"),
+            ("type(1.5)", "FLOAT"),
+            // a local `len` shadows the global one, not the namespace's
+            ("let len = 5; [len, string.len(\"ab\")]", "[5, 2]"),
+            ("let string = {\"upper\": 1}; string.upper", "1"),
+            ("string.lower", "null"),
+            ("1.x", "ERROR: index operator not supported: INTEGER"),
+        ];
+        for (input, exp) in tests {
+            let obj = test_eval(input).expect("evaluator returned None");
+            assert_eq!(obj.inspect(), exp, "evaluating {:?}", input);
+        }
+    }
+
+    #[test]
+    fn test_stdlib_config() {
+        let without_io = || Host::new().with_stdlib(StdlibConfig::new().without(Group::Io));
+        let tests = [
+            (
+                "read_file(\"x\")",
+                Object::Error(EvalError::IdentifierNotFound("read_file".to_owned())),
+            ),
+            (
+                "io",
+                Object::Error(EvalError::IdentifierNotFound("io".to_owned())),
+            ),
+            ("len(\"abc\")", Object::Integer(3)),
+            ("os.exit == exit", Object::Boolean(true)),
+        ];
+        for (input, exp) in tests {
+            let obj = with_host(without_io(), || test_eval(input));
+            assert_eq!(obj, Some(exp), "evaluating {:?}", input);
+        }
+
+        let namespaced = Host::new().with_stdlib(StdlibConfig::new().with_flat_names(false));
+        let obj = with_host(namespaced, || {
+            test_eval("[string.split(\"a b\", \" \"), split]")
+        });
+        assert_eq!(
+            obj,
+            Some(Object::Error(EvalError::IdentifierNotFound(
+                "split".to_owned()
+            )))
+        );
+    }
+
+    #[test]
+    fn test_prelude() {
+        let tests = [
+            ("map([1, 2, 3], fn(x) { x * 2 })", "[2, 4, 6]"),
+            ("map([\"a\", \"bc\"], len)", "[1, 2]"),
+            ("filter([1, 2, 3, 4], fn(x) { x > 2 })", "[3, 4]"),
+            ("reduce([1, 2, 3], 10, fn(acc, x) { acc + x })", "16"),
+            ("map([], fn(x) { x })", "[]"),
+            // the prelude's `map`, which isn't the builtin
+            ("map == array.map", "false"),
+            // a script's own bindings come first
+            ("let map = fn(xs, f) { 0 }; map([1], fn(x) { x })", "0"),
+            // its recursion is in tail position, and doesn't nest
+            (
+                "reduce(collect(range(0, 5000)), 0, fn(a, b) { a + b })",
+                "12497500",
+            ),
+        ];
+        for (input, exp) in tests {
+            let host = Host::new().with_prelude(true);
+            let obj = with_host(host, || test_eval(input)).unwrap();
+            assert_eq!(obj.inspect(), exp, "{}", input);
+        }
+
+        let obj = with_host(Host::new().with_prelude(false), || test_eval("reduce"));
+        assert_eq!(
+            obj,
+            Some(Object::Error(EvalError::IdentifierNotFound(
+                "reduce".to_owned()
+            )))
+        );
+    }
+
+    #[test]
+    fn test_builtin_names() {
+        for name in BUILTIN_NAMES {
+            let obj = test_eval(name).expect("evaluator returned None");
+            assert_eq!(
+                obj.type_val(),
+                ObjectType::Builtin,
+                "{} is not a builtin",
+                name
+            );
+        }
+    }
+
+    /// Every builtin reports the wrong number of arguments the same way,
+    /// before it looks at anything else.
+    #[test]
+    fn test_builtin_arity_errors() {
+        for def in BUILTINS.iter() {
+            let (input, got) = match def.max_args {
+                _ if def.min_args > 0 => (format!("{}()", def.name), 0),
+                Some(max) => (
+                    format!("{}({})", def.name, vec!["1"; max + 1].join(", ")),
+                    max + 1,
+                ),
+                None => continue,
+            };
+            let exp = EvalError::WrongArgumentCount {
+                builtin: def.name,
+                got,
+                min: def.min_args,
+                max: def.max_args,
+            };
+            assert_eq!(test_eval(&input), Some(Object::Error(exp)), "{}", input);
+        }
+        let tests = [
+            ("push([])", "`push` expects 2 arguments, got 1"),
+            ("len()", "`len` expects 1 argument, got 0"),
+            ("rand(1)", "`rand` expects 0 arguments, got 1"),
+        ];
+        for (input, exp) in tests {
+            let obj = test_eval(input).expect("evaluator returned None");
+            assert_eq!(obj.inspect(), format!("ERROR: {}", exp), "{}", input);
+        }
+        let err = EvalError::WrongArgumentCount {
+            builtin: "f",
+            got: 0,
+            min: 1,
+            max: None,
+        };
+        assert_eq!(err.to_string(), "`f` expects at least 1 argument, got 0");
+        let err = EvalError::WrongArgumentCount {
+            builtin: "f",
+            got: 4,
+            min: 1,
+            max: Some(3),
+        };
+        assert_eq!(err.to_string(), "`f` expects 1 to 3 arguments, got 4");
+        assert_eq!(err.kind(), ErrorKind::ArityMismatch);
+    }
+
+    #[test]
+    fn test_builtin_argument_types() {
+        let tests = [
+            (
+                "len(1)",
+                "`len` expects STRING|ARRAY|HASH|ITERATOR for argument 1, got INTEGER",
+            ),
+            (
+                "push(1, 2)",
+                "`push` expects ARRAY for argument 1, got INTEGER",
+            ),
+            // the first argument that's wrong
+            (
+                "slice(1, \"a\", 2)",
+                "`slice` expects STRING|ARRAY for argument 1, got INTEGER",
+            ),
+            (
+                "rand_int(1, 2.5)",
+                "`rand_int` expects INTEGER for argument 2, got FLOAT",
+            ),
+        ];
+        for (input, exp) in tests {
+            let obj = test_eval(input).expect("evaluator returned None");
+            assert_eq!(obj.inspect(), format!("ERROR: {}", exp), "{}", input);
+        }
+        assert_eq!(
+            test_eval("len({\"a\": 1, \"b\": 2})"),
+            Some(Object::Integer(2))
+        );
+        assert_eq!(TypeSet::ANY.to_string(), "ANY");
+        assert!(TypeSet::ANY.contains(ObjectType::Macro));
+    }
+
+    #[test]
+    fn test_builtin_functions() {
+        let tests = vec![
+            IntTest {
+                input: "len(\"\")",
+                exp: 0,
+            },
+            IntTest {
+                input: "len(\"four\")",
+                exp: 4,
+            },
+            IntTest {
+                input: "len(\"hello world\")",
+                exp: 11,
+            },
+        ];
+
+        for test in tests {
+            let obj_opt = test_eval(test.input);
+            if let Some(obj) = obj_opt {
+                test_int_object(&obj, test.exp);
+            } else {
+                panic!("evaluator returned None");
+            }
+        }
+    }
+
+    #[test]
+    fn test_chars_and_bytes() {
+        let tests = [
+            ("chars(\"abc\")", "[a, b, c]"),
+            ("bytes(\"abc\")", "[97, 98, 99]"),
+            ("chars(\"\")", "[]"),
+            ("bytes(\"\")", "[]"),
+            ("chars(\"héllo\")", "[h, é, l, l, o]"),
+            ("bytes(\"é\")", "[195, 169]"),
+            // a scalar value outside the BMP is one char and four bytes
+            ("len(chars(\"a😀\")) + 10 * len(bytes(\"a😀\"))", "52"),
+            ("len(chars(\"héllo\")) == len(\"héllo\")", "true"),
+            ("chars(\"ab\")[0] == \"a\"", "true"),
+            (
+                "chars(1)",
+                "ERROR: `chars` expects STRING for argument 1, got INTEGER",
+            ),
+            (
+                "bytes([\"a\"])",
+                "ERROR: `bytes` expects STRING for argument 1, got ARRAY",
+            ),
+            (
+                "chars(\"a\", \"b\")",
+                "ERROR: `chars` expects 1 argument, got 2",
+            ),
+        ];
+        for (input, exp) in tests {
+            let obj = test_eval(input).expect("evaluator returned None");
+            assert_eq!(obj.inspect(), exp, "evaluating {:?}", input);
+        }
+    }
+
+    #[test]
+    fn test_unicode_strings() {
+        let tests = [
+            ("len(\"héllo\")", "5"),
+            ("\"héllo\"[1]", "é"),
+            ("\"héllo\"[4]", "o"),
+            ("\"a😀b\"[1]", "😀"),
+            ("\"héllo\"[5]", "null"),
+            ("\"héllo\"[-1]", "null"),
+            ("\"\"[0]", "null"),
+            ("slice(\"héllo\", 1, 3)", "él"),
+            ("slice(\"héllo\", 0, 5)", "héllo"),
+            ("len(slice(\"héllo\", 2, 2))", "0"),
+            ("len(slice(\"\", 0, 0))", "0"),
+            (
+                "slice(\"héllo\", 3, 6)",
+                "ERROR: index 6 out of range for `slice`, the length is 5",
+            ),
+            (
+                "slice(\"héllo\", 3, 2)",
+                "ERROR: invalid argument to `slice`: start is past end",
+            ),
+            (
+                "slice(\"héllo\", -1, 2)",
+                "ERROR: index -1 out of range for `slice`, the length is 5",
+            ),
+            ("slice([1, 2, 3], 1, 3)", "[2, 3]"),
+            ("slice([1, 2, 3], 3, 3)", "[]"),
+            (
+                "slice([1, 2, 3], 0, 4)",
+                "ERROR: index 4 out of range for `slice`, the length is 3",
+            ),
+            ("split(\"héllo\", \"\")", "[h, é, l, l, o]"),
+            ("split(\"a,é,,b\", \",\")", "[a, é, , b]"),
+            ("split(\"\", \"\")", "[]"),
+            ("upper(\"héllo\")", "HÉLLO"),
+            ("upper(\"ß\")", "SS"),
+            ("contains(\"héllo\", \"él\")", "true"),
+            ("contains(\"héllo\", \"e\")", "false"),
+            ("contains(\"\", \"\")", "true"),
+            // the same char by char everywhere
+            (
+                "let s = \"añ😀\"; [len(s), len(chars(s)), len(split(s, \"\"))]",
+                "[3, 3, 3]",
+            ),
+            (
+                "let s = \"añ😀\"; s[2] == chars(s)[2] && s[2] == slice(s, 2, 3)",
+                "true",
+            ),
+            (
+                "let s = \"añ😀\"; contains(s, s[1]) && upper(s)[1] == \"Ñ\"",
+                "true",
+            ),
+            // an accent combined with the letter before it is a char of its
+            // own, grapheme clusters aren't handled
+            ("len(\"e\\u{301}\")", "2"),
+            ("\"e\\u{301}\"[0]", "e"),
+            ("slice(\"e\\u{301}x\", 1, 3) == \"\\u{301}x\"", "true"),
+            (
+                "slice(\"a\", \"0\", 1)",
+                "ERROR: `slice` expects INTEGER for argument 2, got STRING",
+            ),
+            (
+                "split(\"a\", 1)",
+                "ERROR: `split` expects STRING for argument 2, got INTEGER",
+            ),
+            (
+                "contains([1], 1)",
+                "ERROR: `contains` expects STRING for argument 1, got ARRAY",
+            ),
+            ("upper()", "ERROR: `upper` expects 1 argument, got 0"),
+        ];
+        for (input, exp) in tests {
+            let obj = test_eval(input).expect("evaluator returned None");
+            assert_eq!(obj.inspect(), exp, "evaluating {:?}", input);
+        }
+    }
+
+    #[test]
+    fn test_pipe() {
+        let tests = [
+            ("[1, 2, 3] |> len", "3"),
+            ("let double = fn(x) { x * 2 }; 5 |> double |> double", "20"),
+            ("let add = fn(a, b) { a + b }; 1 |> add(2) |> add(3)", "6"),
+            ("[1, 2, 3] |> map(fn(x) { x * x }) |> last", "9"),
+            ("1 + 2 |> fn(x) { x * 10 }", "30"),
+            // `len == 5` is what the value is piped into
+            (
+                "\"héllo\" |> upper |> len == 5",
+                "ERROR: not a function: BOOLEAN",
+            ),
+            ("(\"héllo\" |> upper |> len) == 5", "true"),
+            ("let x = 0; x = [1] |> push(2); x", "[1, 2]"),
+            ("1 |> 2", "ERROR: not a function: INTEGER"),
+            (
+                "\"a\" |> len(\"b\")",
+                "ERROR: `len` expects 1 argument, got 2",
+            ),
+        ];
+        for (input, exp) in tests {
+            let obj = test_eval(input).expect("evaluator returned None");
+            assert_eq!(obj.inspect(), exp, "evaluating {:?}", input);
+        }
+    }
+
+    #[test]
+    fn test_memoize() {
+        let fib = "let fib = fn(n) { if (n < 2) { n } else { fib(n - 1) + fib(n - 2) } };";
+        let tests = [
+            (format!("{} memoize(fib)(20)", fib), "6765"),
+            (
+                format!("{} let f = memoize(fib); [f(10), f(10), fib(10)]", fib),
+                "[55, 55, 55]",
+            ),
+            // out of reach without it
+            (format!("{} memoize(fib)(90)", fib), "2880067194370816120"),
+            ("memoize(fn(a, b) { a * b })(6, 7)".to_owned(), "42"),
+            (
+                "let f = memoize(fn(xs) { len(xs) }); [f([1, 2]), f([1, 2]), f([[1], 2])]"
+                    .to_owned(),
+                "[2, 2, 2]",
+            ),
+            (
+                "let f = memoize(fn(x) { 1 / x }); f(0); f(0)".to_owned(),
+                "ERROR: division by zero",
+            ),
+            (
+                "memoize(len)".to_owned(),
+                "ERROR: `memoize` expects FUNCTION for argument 1, got BUILTIN",
+            ),
+            (
+                "memoize()".to_owned(),
+                "ERROR: `memoize` expects 1 argument, got 0",
+            ),
+        ];
+        for (input, exp) in tests {
+            let obj = test_eval(&input).expect("evaluator returned None");
+            assert_eq!(obj.inspect(), exp, "evaluating {:?}", input);
+        }
+
+        // the body runs once for each different argument
+        let input = "let f = memoize(fn(x) { print(x); x * 2 });
+f(1) + f(2) + f(1) + f(2) + f(1)";
+        let (obj, out) = capture_output(|| test_eval(input));
+        assert_eq!(obj, Some(Object::Integer(14)));
+        assert_eq!(out, "1\n2\n");
+        let input = format!(
+            "let calls = fn(n) {{ print(n); n }}; {}",
+            fib.replace("if (n < 2)", "if (calls(n) < 2)")
+        );
+        let (_, out) = capture_output(|| test_eval(&format!("{} memoize(fib)(15)", input)));
+        assert_eq!(out.lines().count(), 16);
+        // unless it can't remember them
+        let input = "let f = memoize(fn(x) { print(\"ran\"); x }); f(sqrt(2)); f(sqrt(2)); f(fn() { 1 }); f(fn() { 1 }); f({1: 2}); f({1: 2})";
+        let (_, out) = capture_output(|| test_eval(input));
+        assert_eq!(out.lines().count(), 6);
+    }
+
+    #[test]
+    fn test_diff_builtin() {
+        let tests = [
+            ("diff([1, {\"a\": 2}], [1, {\"a\": 2}])", "[]"),
+            (
+                "diff([{\"name\": \"bob\", \"id\": 1}], [{\"name\": \"alice\"}])",
+                "[[0].name: expected \"bob\", got \"alice\", [0]: missing key \"id\"]",
+            ),
+            ("len(diff(\"a\", 1))", "1"),
+            ("assert_eq([1, (2, 3)], [1, (2, 3)])", "null"),
+            (
+                "assert_eq({\"a\": [1, 2]}, {\"a\": [1, 3], \"b\": 4})",
+                "ERROR: assertion failed: .a[1]: expected 2, got 3; unexpected key \"b\"",
+            ),
+            (
+                "assert_eq(1, \"1\"); 2",
+                "ERROR: assertion failed: expected INTEGER 1, got STRING \"1\"",
+            ),
+        ];
+        for (input, exp) in tests {
+            let obj = test_eval(input).expect("evaluator returned None");
+            assert_eq!(obj.inspect(), exp, "{}", input);
+        }
+    }
+
+    #[test]
+    fn test_deep_equal() {
+        let tests = [
+            ("deepEqual([[1, 2]], [[1, 2]])", true),
+            ("deepEqual([[1, 2]], [[1, 3]])", false),
+            ("deepEqual([[1, 2]], [[1, 2], []])", false),
+            (
+                "deepEqual({\"a\": [1, {\"b\": (2, 3)}]}, {\"a\": [1, {\"b\": (2, 3)}]})",
+                true,
+            ),
+            (
+                "deepEqual({\"a\": [1, {\"b\": (2, 3)}]}, {\"a\": [1, {\"b\": (2, 4)}]})",
+                false,
+            ),
+            // in any order, like `==`
+            (
+                "deepEqual({1: \"x\", 2: \"y\"}, {2: \"y\", 1: \"x\"})",
+                true,
+            ),
+            ("deepEqual([1, 2.0], [1.0, 2])", true),
+            ("deepEqual(\"1\", 1)", false),
+            // functions by what they are, not by which one they are
+            ("deepEqual(fn(x) { x + 1 }, fn(x) { x + 1 })", true),
+            ("fn(x) { x + 1 } == fn(x) { x + 1 }", false),
+            ("deepEqual([fn(x) { x }], [fn(y) { y }])", false),
+            ("deepEqual(fn(x) { x }, fn(x) { x + 0 })", false),
+            // whatever they captured
+            ("let f = fn(n) { fn() { n } }; deepEqual(f(1), f(2))", true),
+            ("deepEqual(len, len)", true),
+            ("deepEqual(len, first)", false),
+        ];
+        for (input, exp) in tests {
+            assert_eq!(
+                test_eval(input),
+                Some(Object::Boolean(exp)),
+                "evaluating {:?}",
+                input
+            );
+        }
+        assert_eq!(
+            test_eval("deepEqual([1])"),
+            Some(Object::Error(EvalError::WrongArgumentCount {
+                builtin: "deepEqual",
+                got: 1,
+                min: 2,
+                max: Some(2),
+            }))
+        );
+    }
+
+    #[test]
+    fn test_typeassert() {
+        let tests = [
+            ("typeassert(5, \"INTEGER\")", "5"),
+            ("typeassert(\"five\", \"STRING\")", "five"),
+            ("typeassert([1, 2], \"ARRAY\")", "[1, 2]"),
+            ("typeassert(sqrt(2) > 1, \"BOOLEAN\")", "true"),
+            ("typeassert(5f, \"FLOAT\")", "5.0"),
+            ("typeassert(len, \"BUILTIN\")", "builtin function"),
+            (
+                "typeassert(\"5\", \"INTEGER\")",
+                "ERROR: expected INTEGER, got STRING",
+            ),
+            // the name has to be the one errors use
+            (
+                "typeassert(5, \"integer\")",
+                "ERROR: expected integer, got INTEGER",
+            ),
+            ("typeassert(5, \"INT\")", "ERROR: expected INT, got INTEGER"),
+            (
+                "typeassert(5, 5)",
+                "ERROR: `typeassert` expects STRING for argument 2, got INTEGER",
+            ),
+            (
+                "typeassert(5)",
+                "ERROR: `typeassert` expects 2 arguments, got 1",
+            ),
+            // at the start of a function, before the argument is used
+            (
+                "let twice = fn(n) { typeassert(n, \"INTEGER\"); n * 2 }; [twice(4), twice(\"4\")]",
+                "ERROR: expected INTEGER, got STRING",
+            ),
+        ];
+        for (input, exp) in tests {
+            let obj = test_eval(input).expect("evaluator returned None");
+            assert_eq!(obj.inspect(), exp, "evaluating {:?}", input);
+        }
+        assert_eq!(
+            test_eval("typeassert(true, \"STRING\")"),
+            Some(Object::Error(EvalError::TypeAssertion {
+                want: "STRING".to_owned(),
+                got: ObjectType::Boolean,
+            }))
+        );
+    }
+
+    #[test]
+    fn test_zip_and_enumerate() {
+        let tests = [
+            ("zip([1, 2], [\"a\", \"b\"])", "[[1, a], [2, b]]"),
+            // as long as the shorter one
+            ("zip([1, 2, 3], [4])", "[[1, 4]]"),
+            ("zip([], [1, 2])", "[]"),
+            ("zip([1, 2], [3, 4], [5, 6, 7])", "[[1, 3, 5], [2, 4, 6]]"),
+            ("enumerate([\"a\", \"b\"])", "[[0, a], [1, b]]"),
+            ("enumerate([])", "[]"),
+            (
+                "let s = 0; for (pair in zip([1, 2], [10, 20])) { s = s + pair[0] * pair[1]; }; s",
+                "50",
+            ),
+            (
+                "zip([1], 2)",
+                "ERROR: `zip` expects ARRAY for argument 2, got INTEGER",
+            ),
+            (
+                "zip([1], [2], \"c\")",
+                "ERROR: `zip` expects ARRAY for argument 3, got STRING",
+            ),
+            (
+                "zip([1])",
+                "ERROR: `zip` expects at least 2 arguments, got 1",
+            ),
+            (
+                "enumerate({})",
+                "ERROR: `enumerate` expects ARRAY for argument 1, got HASH",
+            ),
+        ];
+        for (input, exp) in tests {
+            let obj = test_eval(input).expect("evaluator returned None");
+            assert_eq!(obj.inspect(), exp, "{}", input);
+        }
+    }
+
+    #[test]
+    fn test_freeze() {
+        let tests = [
+            ("freeze([1, 2])", "[1, 2]"),
+            ("freeze([1, 2]) == [1, 2]", "true"),
+            ("len(freeze([1, 2]))", "2"),
+            ("push(freeze([1]), 2)", "ERROR: cannot modify frozen array"),
+            // the array it was made from isn't frozen, and pushing onto it
+            // gives a copy as before
+            (
+                "let a = [1]; let f = freeze(a); [push(a, 2), a, f]",
+                "[[1, 2], [1], [1]]",
+            ),
+            (
+                "let f = freeze([1]); let id = fn(x) { x }; push(id(f), 2)",
+                "ERROR: cannot modify frozen array",
+            ),
+            (
+                "let h = {\"xs\": freeze([1])}; push(h[\"xs\"], 2)",
+                "ERROR: cannot modify frozen array",
+            ),
+            (
+                "push(freeze(freeze([1])), 2)",
+                "ERROR: cannot modify frozen array",
+            ),
+            // what other builtins make of it is a new array
+            ("push(rest(freeze([1, 2])), 3)", "[2, 3]"),
+            (
+                "freeze(1)",
+                "ERROR: `freeze` expects ARRAY for argument 1, got INTEGER",
+            ),
+        ];
+        for (input, exp) in tests {
+            let obj = test_eval(input).expect("evaluator returned None");
+            assert_eq!(obj.inspect(), exp, "{}", input);
+        }
+        assert_eq!(EvalError::FrozenArray.kind(), ErrorKind::Frozen);
+    }
+
+    #[test]
+    fn test_iterators() {
+        let tests = [
+            ("iter([1, 2])", "iterator"),
+            ("type(iter(range(0, 2)))", "ITERATOR"),
+            ("collect(iter([1, 2]))", "[1, 2]"),
+            ("collect(range(0, 3))", "[0, 1, 2]"),
+            ("collect({\"a\": 1, \"b\": 2})", "[[a, 1], [b, 2]]"),
+            (
+                "collect(take(filter_iter(map_iter(range(0, 1000000000), fn(x) { x * 3 }), fn(x) { mod(x, 2) == 0 }), 4))",
+                "[0, 6, 12, 18]",
+            ),
+            ("collect(map_iter([\"a\", \"bc\"], len))", "[1, 2]"),
+            ("collect(take([1, 2], 5))", "[1, 2]"),
+            ("collect(take(range(0, 5), 0))", "[]"),
+            (
+                "let s = 0; for (x in map_iter(range(0, 100000), fn(x) { x * 2 })) { s = s + x }; s",
+                "9999900000",
+            ),
+            (
+                "let s = 0; for (i, x in iter([10, 20])) { s = s + i * x }; s",
+                "20",
+            ),
+            // an iterator is used up after one pass
+            ("let it = iter([1, 2]); [collect(it), collect(it)]", "[[1, 2], []]"),
+            (
+                "let it = iter(range(0, 3)); let n = 0; for (x in it) { n = n + 1 }; for (x in it) { n = n + 1 }; n",
+                "3",
+            ),
+            // a `take` takes no more than it gives
+            (
+                "let it = iter([1, 2, 3]); [collect(take(it, 1)), collect(it)]",
+                "[[1], [2, 3]]",
+            ),
+            (
+                "let it = iter([1, 2]); first(collect(map_iter(it, fn(x) { collect(it) })))",
+                "[2]",
+            ),
+            (
+                "len(iter([1]))",
+                "ERROR: invalid argument to `len`: an iterator has no length, `collect` it into an array first",
+            ),
+            (
+                "take([1], -1)",
+                "ERROR: invalid argument to `take`: negative count",
+            ),
+            (
+                "collect(map_iter([1, 0], fn(x) { 1 / x }))",
+                "ERROR: division by zero",
+            ),
+            (
+                "for (x in filter_iter([1], fn(x) { -true })) { x }",
+                "ERROR: unsupported operand type for -: BOOLEAN",
+            ),
+            (
+                "iter(1)",
+                "ERROR: `iter` expects ARRAY|HASH|RANGE|ITERATOR for argument 1, got INTEGER",
+            ),
+            (
+                "map_iter([1], 2)",
+                "ERROR: `map_iter` expects FUNCTION|BUILTIN for argument 2, got INTEGER",
+            ),
+        ];
+        for (input, exp) in tests {
+            let obj = test_eval(input).expect("evaluator returned None");
+            assert_eq!(obj.inspect(), exp, "{}", input);
+        }
+    }
+
+    #[test]
+    fn test_builtins_as_values() {
+        let tests = [
+            ("let f = len; f(\"hi\")", "2"),
+            ("let apply = fn(f, x) { f(x) }; apply(len, [1, 2, 3])", "3"),
+            ("let fs = [first, last]; fs[1]([1, 2])", "2"),
+            ("map([\"a\", \"bb\"], len)", "[1, 2]"),
+            ("map([1, -2], abs)", "[1, 2]"),
+            ("map([1, 2], fn(x) { x * 10 })", "[10, 20]"),
+            ("map([], len)", "[]"),
+            ("let len = fn(x) { 0 }; map([\"a\"], len)", "[0]"),
+            ("map([1], fn(x) { if (x > 5) { x } })", "[null]"),
+            (
+                "map([\"a\", 1], len)",
+                "ERROR: `len` expects STRING|ARRAY|HASH|ITERATOR for argument 1, got INTEGER",
+            ),
+            (
+                "map([1], 2)",
+                "ERROR: `map` expects FUNCTION|BUILTIN for argument 2, got INTEGER",
+            ),
+            (
+                "map(len, [1])",
+                "ERROR: `map` expects ARRAY for argument 1, got BUILTIN",
+            ),
+        ];
+        for (input, exp) in tests {
+            let obj = test_eval(input).expect("evaluator returned None");
+            assert_eq!(obj.inspect(), exp, "evaluating {:?}", input);
+        }
+    }
+
+    #[test]
+    fn test_quote_unquote() {
+        let tests = [
+            ("quote(5)", "QUOTE(5)"),
+            ("quote(5 + 8)", "QUOTE((5 + 8))"),
+            ("quote(foobar)", "QUOTE(foobar)"),
+            ("quote(foobar + barfoo)", "QUOTE((foobar + barfoo))"),
+            ("quote(unquote(4))", "QUOTE(4)"),
+            ("quote(unquote(4 + 4))", "QUOTE(8)"),
+            ("quote(8 + unquote(4 + 4))", "QUOTE((8 + 8))"),
+            ("quote(unquote(4 + 4) + 8)", "QUOTE((8 + 8))"),
+            ("let foobar = 8; quote(unquote(foobar))", "QUOTE(8)"),
+            ("quote(unquote(true))", "QUOTE(true)"),
+            ("quote(unquote(true == false))", "QUOTE(false)"),
+            ("quote(unquote(\"a\" + \"b\"))", "QUOTE(\"ab\")"),
+            ("quote(unquote(quote(4 + 4)))", "QUOTE((4 + 4))"),
+            (
+                "let quotedInfixExpression = quote(4 + 4);
+quote(unquote(4 + 4) + unquote(quotedInfixExpression))",
+                "QUOTE((8 + (4 + 4)))",
+            ),
+            ("quote(fn(x) { unquote(1 + 2) })", "QUOTE(fn(x) { 3 })"),
+            // values without a literal leave the call in place
+            ("quote(unquote([1]))", "QUOTE(unquote([1]))"),
+            ("quote(1, 2)", "ERROR: `quote` expects 1 argument, got 2"),
+        ];
+        for (input, exp) in tests {
+            let obj = test_eval(input).expect("evaluator returned None");
+            assert_eq!(obj.inspect(), exp, "evaluating {:?}", input);
+        }
+    }
+
+    #[test]
+    fn test_math_builtins() {
+        let tests = [
+            IntTest {
+                input: "abs(-5)",
+                exp: 5,
+            },
+            IntTest {
+                input: "abs(5)",
+                exp: 5,
+            },
+            IntTest {
+                input: "min(3, -2)",
+                exp: -2,
+            },
+            IntTest {
+                input: "max(3, -2)",
+                exp: 3,
+            },
+            IntTest {
+                input: "pow(2, 10)",
+                exp: 1024,
+            },
+            IntTest {
+                input: "pow(7, 0)",
+                exp: 1,
+            },
+            IntTest {
+                input: "mod(7, 3)",
+                exp: 1,
+            },
+            IntTest {
+                input: "mod(-7, 3)",
+                exp: 2,
+            },
+        ];
+        for test in tests.iter() {
+            match test_eval(test.input) {
+                Some(obj) => test_int_object(&obj, test.exp),
+                None => panic!("evaluator returned None"),
+            }
+        }
+
+        let tests = [
+            ("sqrt(9)", 3.0),
+            ("sqrt(2)", 2f64.sqrt()),
+            ("sqrt(0)", 0.0),
+            ("min(1.5, 2.5)", 1.5),
+            // an integer and a float compare as floats
+            ("min(1, 2.5)", 1.0),
+            ("min(2.5, 1)", 1.0),
+            ("max(2.5, 1)", 2.5),
+            ("max(1, 2.5)", 2.5),
+            ("max(3, 2.5)", 3.0),
+            ("min(-1, -0.5)", -1.0),
+        ];
+        for (input, exp) in tests {
+            match test_eval(input) {
+                Some(Object::Float(v)) => assert_eq!(v, exp),
+                obj => panic!("{:#?} is not a float", obj),
+            }
+        }
+    }
+
+    #[test]
+    fn test_number_literal_suffixes() {
+        let tests = [
+            ("5 + 1", "6"),
+            ("5i + 1", "6"),
+            ("5f + 1", "6.0"),
+            ("5.0 + 1", "6.0"),
+            ("1.5 * 2", "3.0"),
+            ("5f", "5.0"),
+            ("0.1 + 0.2", "0.30000000000000004"),
+            ("-2.5", "-2.5"),
+            ("5i == 5f", "true"),
+            ("7i / 2", "3"),
+            ("7f / 2", "3.5"),
+        ];
+        for (input, exp) in tests {
+            assert_eq!(test_eval(input).unwrap().inspect(), exp, "{}", input);
+        }
+    }
+
+    #[test]
+    fn test_mixed_number_comparison() {
+        let tests = [
+            ("sqrt(4) == 2", true),
+            ("2 == sqrt(4)", true),
+            ("sqrt(4) != 2", false),
+            ("sqrt(2) == 1", false),
+            ("2 != sqrt(2)", true),
+            ("1 < sqrt(2)", true),
+            ("sqrt(2) < 1", false),
+            ("2 > sqrt(2)", true),
+            ("sqrt(2) > 2", false),
+            ("sqrt(2) < sqrt(3)", true),
+            ("sqrt(4) == sqrt(4)", true),
+            ("[1, sqrt(4)] == [sqrt(1), 2]", true),
+            ("{\"a\": 2} == {\"a\": sqrt(4)}", true),
+            // two integers still compare as integers, exactly
+            ("9007199254740993 == 9007199254740992", false),
+            ("1 == 1", true),
+        ];
+        for (input, exp) in tests {
+            assert_eq!(test_eval(input), Some(Object::Boolean(exp)), "{}", input);
+        }
+
+        // and arithmetic with a float in it is on floats
+        let tests = [
+            ("1 + sqrt(4)", "3.0"),
+            ("sqrt(4) * 2", "4.0"),
+            ("sqrt(4) - sqrt(1)", "1.0"),
+            ("3 / sqrt(4)", "1.5"),
+            ("sqrt(4) / 0", "ERROR: division by zero"),
+            (
+                "sqrt(4) + true",
+                "ERROR: unsupported operand types for +: FLOAT and BOOLEAN",
+            ),
+        ];
+        for (input, exp) in tests {
+            assert_eq!(test_eval(input).unwrap().inspect(), exp, "{}", input);
+        }
+    }
+
+    #[test]
+    fn test_const() {
+        let tests = [
+            ErrorTest {
+                input: "const PI = 3; let PI = 4;",
+                exp: "cannot reassign const 'PI'",
+            },
+            ErrorTest {
+                input: "const PI = 3; const PI = 4;",
+                exp: "cannot reassign const 'PI'",
+            },
+            ErrorTest {
+                input: "const a, b = (1, 2); let c, b = (3, 4);",
+                exp: "cannot reassign const 'b'",
+            },
+        ];
+        for test in tests.iter() {
+            match test_eval(test.input) {
+                Some(Object::Error(v)) => assert_eq!(v.to_string(), test.exp),
+                obj => panic!("{:#?} is not an error object", obj),
+            }
+        }
+
+        let tests = [
+            IntTest {
+                input: "let x = 1; let x = x + 1; x",
+                exp: 2,
+            },
+            IntTest {
+                input: "const PI = 3; PI * 2",
+                exp: 6,
+            },
+            IntTest {
+                // a function body is a new scope, the `const` can be shadowed there
+                input: "const PI = 3; let f = fn() { let PI = 4; PI }; f() + PI",
+                exp: 7,
+            },
+            IntTest {
+                input: "let x = 1; const x = 2; x",
+                exp: 2,
+            },
+        ];
+        for test in tests.iter() {
+            match test_eval(test.input) {
+                Some(obj) => test_int_object(&obj, test.exp),
+                None => panic!("evaluator returned None"),
+            }
+        }
+    }
+
+    #[test]
+    fn test_recursive_let() {
+        let tests = [
+            IntTest {
+                input: "let fib = fn(n) { if (n < 2) { n } else { fib(n - 1) + fib(n - 2) } }; fib(10)",
+                exp: 55,
+            },
+            IntTest {
+                input: "let fact = fn(n) { if (n == 0) { 1 } else { n * fact(n - 1) } }; let f = fact; f(5)",
+                exp: 120,
+            },
+            IntTest {
+                // closures made in the body see the name too
+                input: "let count = fn(n) { let step = fn() { count(n - 1) }; if (n == 0) { 0 } else { 1 + step() } }; count(4)",
+                exp: 4,
+            },
+            IntTest {
+                // a parameter of the same name wins
+                input: "let f = fn(f) { f }; f(7)",
+                exp: 7,
+            },
+        ];
+        for test in tests.iter() {
+            match test_eval(test.input) {
+                Some(obj) => test_int_object(&obj, test.exp),
+                None => panic!("evaluator returned None"),
+            }
+        }
+    }
+
+    #[test]
+    fn test_host_builtins() {
+        let host = Host::new()
+            .with_args(vec!["one".to_owned(), "two words".to_owned()])
+            .with_env(|name| (name == "HOME").then(|| "/home/monkey".to_owned()));
+        let tests = [
+            ("args()", "[one, two words]"),
+            ("len(args())", "2"),
+            ("env(\"HOME\")", "/home/monkey"),
+            ("env(\"HOME\") + \"/x\"", "/home/monkey/x"),
+            ("env(\"PATH\")", "null"),
+            (
+                "env(1)",
+                "ERROR: `env` expects STRING for argument 1, got INTEGER",
+            ),
+            ("args(1)", "ERROR: `args` expects 0 arguments, got 1"),
+        ];
+        with_host(host, || {
+            for (input, exp) in tests {
+                let obj = test_eval(input).expect("evaluator returned None");
+                assert_eq!(obj.inspect(), exp, "evaluating {:?}", input);
+            }
+        });
+        // without a host there is nothing to see
+        assert_eq!(test_eval("args()").unwrap().inspect(), "[]");
+        assert_eq!(test_eval("env(\"HOME\")"), Some(Object::Null));
+    }
+
+    #[test]
+    fn test_file_builtins() {
+        let dir = std::env::temp_dir().join(format!("monkey-fs-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("data.txt");
+        let missing = dir.join("missing.txt");
+        let invalid = dir.join("invalid.txt");
+        std::fs::write(&invalid, [0xFF, 0xFE]).unwrap();
+        let (path, missing, invalid) = (
+            path.to_str().unwrap(),
+            missing.to_str().unwrap(),
+            invalid.to_str().unwrap(),
+        );
+
+        let host = Host::new().with_capabilities(Capabilities {
+            fs: true,
+            ..Default::default()
+        });
+        with_host(host, || {
+            let input = format!("write_file({:?}, \"one\")", path);
+            assert_eq!(test_eval(&input), Some(Object::Null));
+            assert_eq!(std::fs::read_to_string(path).unwrap(), "one");
+            std::fs::write(path, "one\ntwo\n").unwrap();
+            let tests = [
+                (format!("read_file({:?})", path), "one\ntwo\n"),
+                (format!("read_lines({:?})", path), "[one, two]"),
+                (format!("len(read_lines({:?}))", path), "2"),
+                (
+                    "read_file(1)".to_owned(),
+                    "ERROR: `read_file` expects STRING for argument 1, got INTEGER",
+                ),
+                (
+                    format!("write_file({:?}, 1)", path),
+                    "ERROR: `write_file` expects STRING for argument 2, got INTEGER",
+                ),
+            ];
+            for (input, exp) in tests {
+                let obj = test_eval(&input).expect("evaluator returned None");
+                assert_eq!(obj.inspect(), exp, "evaluating {:?}", input);
+            }
+            for (file, builtin) in [(missing, "read_file"), (invalid, "read_lines")] {
+                let input = format!("{}({:?})", builtin, file);
+                match test_eval(&input) {
+                    Some(Object::Error(EvalError::Io {
+                        builtin: b,
+                        path: p,
+                        ..
+                    })) => assert_eq!((b, p.as_str()), (builtin, file)),
+                    obj => panic!("{:?} is not an io error", obj),
+                }
+            }
+            match test_eval(&format!("read_file({:?})", missing)) {
+                Some(Object::Error(err)) => assert_eq!(err.kind(), ErrorKind::Io),
+                obj => panic!("{:?} is not an error", obj),
+            }
+        });
+
+        // without the capability, whatever the path, though arguments
+        // a file builtin never takes are wrong first
+        let not_granted = Object::Error(EvalError::CapabilityNotGranted("fs"));
+        for input in [
+            format!("read_file({:?})", path),
+            format!("read_lines({:?})", missing),
+            format!("write_file({:?}, \"x\")", path),
+        ] {
+            assert_eq!(test_eval(&input), Some(not_granted.clone()), "{}", input);
+        }
+        assert_eq!(
+            test_eval("read_file()").unwrap().inspect(),
+            "ERROR: `read_file` expects 1 argument, got 0"
+        );
+        with_host(Host::new(), || {
+            assert_eq!(test_eval("read_file(\"x\")"), Some(not_granted.clone()));
+        });
+        assert_eq!(not_granted.inspect(), "ERROR: capability 'fs' not granted");
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    /* a clock that only moves when a script sleeps */
+    struct FakeClock(Rc<std::cell::Cell<i64>>);
+
+    impl Clock for FakeClock {
+        fn now_millis(&self) -> i64 {
+            self.0.get()
+        }
+
+        fn sleep(&self, millis: u64) {
+            self.0.set(self.0.get() + millis as i64);
+        }
+    }
+
+    #[test]
+    fn test_time_and_random_builtins() {
+        let now = Rc::new(std::cell::Cell::new(1_000));
+        let host = || {
+            Host::new()
+                .with_clock(FakeClock(now.clone()))
+                .with_rng(SeededRng::new(42))
+                .with_capabilities(Capabilities {
+                    sleep: true,
+                    ..Default::default()
+                })
+        };
+        let tests = [
+            ("clock()", "1000"),
+            ("let start = clock(); sleep(250); clock() - start", "250"),
+            (
+                "[rand(), rand()]",
+                "[0.7415648787718233, 0.1599103928769201]",
+            ),
+            (
+                "[rand_int(1, 6), rand_int(1, 6), rand_int(1, 6)]",
+                "[5, 1, 2]",
+            ),
+            ("rand_int(3, 3)", "3"),
+            (
+                "rand_int(5, 1)",
+                "ERROR: invalid argument to `rand_int`: lo must not be greater than hi",
+            ),
+            (
+                "rand_int(1, \"6\")",
+                "ERROR: `rand_int` expects INTEGER for argument 2, got STRING",
+            ),
+            (
+                "sleep(-1)",
+                "ERROR: invalid argument to `sleep`: can't sleep for a negative time",
+            ),
+            ("clock(1)", "ERROR: `clock` expects 0 arguments, got 1"),
+        ];
+        for (input, exp) in tests {
+            let obj = with_host(host(), || test_eval(input)).expect("evaluator returned None");
+            assert_eq!(obj.inspect(), exp, "evaluating {:?}", input);
+        }
+        assert_eq!(now.get(), 1_250);
+
+        // the same seed gives the same numbers, and they are in range
+        let draw = "map([1, 2, 3, 4, 5, 6, 7, 8], fn(_) { rand_int(-2, 2) })";
+        let first = with_host(host(), || test_eval(draw)).unwrap();
+        let second = with_host(host(), || test_eval(draw)).unwrap();
+        assert_eq!(first, second);
+        let Object::Array(arr) = first else {
+            panic!("{:?} is not an array", first);
+        };
+        for n in arr.elements.iter() {
+            assert!(matches!(n, Object::Integer(-2..=2)), "{:?}", n);
+        }
+
+        // sleeping needs the capability
+        assert_eq!(
+            with_host(Host::new(), || test_eval("sleep(1)")),
+            Some(Object::Error(EvalError::CapabilityNotGranted("sleep")))
+        );
+    }
+
+    #[test]
+    fn test_error_kinds() {
+        let tests = [
+            ("1 + true", ErrorKind::TypeMismatch),
+            ("-\"a\"", ErrorKind::TypeMismatch),
+            ("1(2)", ErrorKind::TypeMismatch),
+            ("len(1)", ErrorKind::TypeMismatch),
+            ("for (x in 1) { x }", ErrorKind::TypeMismatch),
+            ("x", ErrorKind::UnknownIdentifier),
+            ("1 / 0", ErrorKind::DivisionByZero),
+            ("9223372036854775807 * 2", ErrorKind::IntegerOverflow),
+            ("len()", ErrorKind::ArityMismatch),
+            ("let a, b = (1, 2, 3);", ErrorKind::ArityMismatch),
+            ("\"a\" * -1", ErrorKind::InvalidArgument),
+            ("const c = 1; c = 2", ErrorKind::ConstReassignment),
+            ("push(freeze([1]), 2)", ErrorKind::Frozen),
+            ("slice([1], 0, 2)", ErrorKind::IndexOutOfRange),
+            ("read_file(\"x\")", ErrorKind::Denied),
+            ("parse(\"let\")", ErrorKind::Eval),
+            ("exit(1)", ErrorKind::Exit),
+            ("assert_eq(1, 2)", ErrorKind::AssertionFailed),
+            ("throw(1)", ErrorKind::Custom),
+        ];
+        for (input, exp) in tests {
+            match test_eval(input) {
+                Some(Object::Error(err)) => assert_eq!(err.kind(), exp, "evaluating {:?}", input),
+                obj => panic!("{:?} gave {:?}, not an error", input, obj),
+            }
+        }
+    }
+
+    #[test]
+    fn test_try() {
+        let tests = [
+            ("let v, err = try(fn() { 1 + 2 }); [v, err]", "[3, null]"),
+            (
+                "let v, err = try(fn() { 1 / 0 }); [v, kind(err)]",
+                "[null, DivisionByZero]",
+            ),
+            ("let _, err = try(fn() { [1][5] + 1 }); kind(err)", "TypeMismatch"),
+            ("let _, err = try(fn() { nope }); err", "CAUGHT: identifier not found: nope"),
+            (
+                "let _, err = try(fn() { throw({\"code\": 7}) }); [kind(err), thrown(err)[\"code\"]]",
+                "[Custom, 7]",
+            ),
+            ("let _, err = try(fn() { 1 / 0 }); thrown(err)", "null"),
+            ("throw(\"bad\")", "ERROR: bad"),
+            // an error thrown again is the one that was caught
+            ("let _, err = try(fn() { nope }); throw(err)", "ERROR: identifier not found: nope"),
+            // a builtin is called the same way
+            ("let _, err = try(args); err", "null"),
+            // an exit isn't a failure
+            ("let _, err = try(fn() { exit(3) }); 1", "ERROR: exit with status 3"),
+            (
+                "kind(1)",
+                "ERROR: `kind` expects CAUGHT for argument 1, got INTEGER",
+            ),
+        ];
+        for (input, exp) in tests {
+            let obj = test_eval(input).expect("eval returned None");
+            assert_eq!(obj.inspect(), exp, "{}", input);
+        }
+        // nor is running out of fuel
+        let obj = with_host(Host::new().with_fuel(100), || {
+            test_eval("try(fn() { for (x in range(0, 1000)) {} })")
+        });
+        assert_eq!(
+            obj.map(|obj| obj.inspect()),
+            Some("ERROR: out of fuel after 100 steps".into())
+        );
+    }
+
+    #[test]
+    fn test_eval_source() {
+        let tests = [
+            ("1 / 0", "1 / 0", vec![]),
+            ("/* a comment */ 1 / 0", "1 / 0", vec![]),
+            (
+                "let inner = fn(x) { x / 0 };\nlet outer = fn(x) { 1 + inner(x) };\nouter(5)",
+                "x / 0",
+                vec![
+                    (Some("inner"), Some("inner(x)")),
+                    (Some("outer"), Some("outer(5)")),
+                ],
+            ),
+            (
+                "map([1], fn(x) { x / 0 })",
+                "x / 0",
+                vec![(None, Some("map([1], fn(x) { x / 0 })"))],
+            ),
+            // the tail calls are one call
+            (
+                "let f = fn(n) { if (n == 0) { 1 / 0 } else { f(n - 1) } };\nf(3)",
+                "1 / 0",
+                vec![(Some("f"), Some("f(3)"))],
+            ),
+            ("let x = 1;\nexit(2)", "exit(2)", vec![]),
+            ("let a, b = (1, 2, 3);", "a, b", vec![]),
+            ("let = 1;", "=", vec![]),
+        ];
+        for (input, exp, trace) in tests {
+            let err = eval_source(input, &mut Environment::new()).expect_err(input);
+            let source =
+                |span: &Option<std::ops::Range<usize>>| span.clone().map(|span| &input[span]);
+            assert_eq!(source(&err.span), Some(exp), "{}", input);
+            let got: Vec<_> = err
+                .trace
+                .iter()
+                .map(|frame| (frame.function.as_deref(), source(&frame.call)))
+                .collect();
+            assert_eq!(got, trace, "{}", input);
+        }
+
+        let err = eval_source("exit(2)", &mut Environment::new()).unwrap_err();
+        assert_eq!(
+            (err.kind(), err.to_string()),
+            (ErrorKind::Exit, "exit with status 2".into())
+        );
+        let err = eval_source("let = 1;", &mut Environment::new()).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::Eval);
+        let mut env = Environment::new();
+        assert_eq!(eval_source("let x = 2;", &mut env), Ok(None));
+        assert_eq!(eval_source("x + 1", &mut env), Ok(Some(Object::Integer(3))));
+    }
+
+    /* the source of the expression `eval_located` says `input` failed at,
+     * and the line and column it starts at */
+    fn failed_at(input: &str) -> Option<(&str, (usize, usize))> {
+        let mut p = Parser::new(Lexer::new(input)).with_source_map();
+        let program = p.parse();
+        assert!(p.get_errors().is_empty(), "{:?}", p.get_errors());
+        let host = Host::new().with_capabilities(Capabilities {
+            eval: true,
+            ..Default::default()
+        });
+        let (res, path) = with_host(host, || eval_located(&program, &mut Environment::new()));
+        assert!(
+            matches!(res, Some(Object::Error(_))),
+            "{:?} gave {:?}",
+            input,
+            res
+        );
+        let (start, end) = path?.token_range(p.source_map())?;
+        let spans = token_spans(input);
+        let (start, end) = (spans[start].start, spans[end - 1].end);
+        Some((&input[start..end], line_col(input, start)))
+    }
+
+    #[test]
+    fn test_error_locations() {
+        let tests = [
+            ("1 / 0", "1 / 0", (1, 1)),
+            ("let x = 1;\nlet y = 2 + (3 * (x / 0));", "(x / 0)", (2, 18)),
+            ("[1, 2][0] + [3][0] * -true", "-true", (1, 22)),
+            ("let a = [1];\n{\"k\": a[0] + nope}", "nope", (2, 14)),
+            ("if (1 < 2) { let t = 3; t + \"s\" }", "t + \"s\"", (1, 25)),
+            ("for (x in [1, 0]) { 10 / x }", "10 / x", (1, 21)),
+            ("let a = len(1, 2);", "len(1, 2)", (1, 9)),
+            // inside the function, not where it was called
+            (
+                "let f = fn(x) {\n  let y = x + 1;\n  y / (x - x)\n};\nlet z = 1;\nf(2) + z;",
+                "y / (x - x)",
+                (3, 3),
+            ),
+            (
+                "fn inner(x) { x + true }\nfn outer(x) { inner(x) * 2 }\nouter(1);",
+                "x + true",
+                (1, 15),
+            ),
+            ("map([1, 0], fn(x) { 10 / x })", "10 / x", (1, 21)),
+            (
+                "let make = fn() { fn(x) { x[0] } };\nlet g = make();\ng(1)",
+                "x[0]",
+                (1, 27),
+            ),
+            // a function `eval` made is only where it was called
+            (
+                "let f = eval(\"fn(x) { -x }\");\nf(true)",
+                "f(true)",
+                (2, 1),
+            ),
+            ("1 + eval(\"1 / 0\")", "eval(\"1 / 0\")", (1, 5)),
+            // the error `try` caught is gone, and one like it made later
+            // is where that one was made
+            ("try(fn() { 1 / 0 });\n2 / 0", "2 / 0", (2, 1)),
+        ];
+        for (input, exp, at) in tests {
+            assert_eq!(failed_at(input), Some((exp, at)), "{}", input);
+        }
+
+        // binding the names made these
+        let tests = [
+            ("const c = 1;\nlet c = 2;", "c", (2, 5)),
+            ("const c = 1;\nfn c() { 1 }", "c", (2, 4)),
+            ("let a, b = (1, 2, 3);", "a, b", (1, 5)),
+            ("let a, b = 1;", "a, b", (1, 5)),
+            ("const c = 1;\nlet a, c = (1, 2);", "a, c", (2, 5)),
+            (
+                "let f = fn() {\n  let x, y = [1];\n};\n1 + f()",
+                "x, y",
+                (2, 7),
+            ),
+        ];
+        for (input, exp, at) in tests {
+            assert_eq!(failed_at(input), Some((exp, at)), "{}", input);
         }
+        // where an error passed out of a function is, is the call
+        assert_eq!(
+            failed_at("let f = eval(\"fn() { let a, b = 1; }\");\nf()"),
+            Some(("f()", (2, 1)))
+        );
+
+        let (res, path) = eval_located(
+            &Parser::new(Lexer::new("1 + 2")).parse(),
+            &mut Environment::new(),
+        );
+        assert_eq!((res, path), (Some(Object::Integer(3)), None));
     }
 
     #[test]
-    fn test_let_statements() {
-        let tests = vec![
-            IntTest {
-                input: "let a = 5; a;",
-                exp: 5,
-            },
-            IntTest {
-                input: "let a = 5 * 5; a;",
-                exp: 25,
-            },
-            IntTest {
-                input: "let a = 5; let b = a; b;",
-                exp: 5,
-            },
-            IntTest {
-                input: "let a = 5; let b = a; let c = a + b + 5; c;",
-                exp: 15,
-            },
+    fn test_eval_observed() {
+        let input = "let f = fn(n) {
+  if (n > 1) { n } else { 0 }
+};
+f(2);
+for (x in [1, 2]) { let y = x; break; }";
+        let program = Rc::new(Parser::new(Lexer::new(input)).parse());
+        let seen = Rc::new(std::cell::RefCell::new(Vec::new()));
+        let observer = {
+            let (program, seen) = (program.clone(), seen.clone());
+            move |pause: Pause| {
+                // what the observer runs isn't observed
+                let code = Parser::new(Lexer::new("let z = 1; z")).parse();
+                assert_eq!(eval(&code, pause.env), Some(Object::Integer(1)));
+                seen.borrow_mut().push((
+                    pause.statement.string(),
+                    pause.path(&program),
+                    pause.is_top_level(),
+                ));
+            }
+        };
+        let res = eval_observed(&program, &mut Environment::new(), observer);
+        assert_eq!(res, Some(Object::Null));
+        let exp = [
+            (
+                "let f = fn(n) { if ((n > 1)) { n } else { 0 } };",
+                Some(vec![0]),
+                true,
+            ),
+            ("f(2)", Some(vec![1]), true),
+            ("if ((n > 1)) { n } else { 0 }", Some(vec![0, 0]), false),
+            ("n", Some(vec![0, 0, 1]), false),
+            (
+                "for (x in [1, 2]) { let y = x; break; }",
+                Some(vec![2]),
+                true,
+            ),
+            ("let y = x;", Some(vec![2, 1]), false),
+            // a `break` is where its loop is
+            ("break;", Some(vec![2]), false),
         ];
+        let seen = seen.borrow();
+        assert_eq!(seen.len(), exp.len(), "{:?}", seen);
+        for (seen, (string, path, top_level)) in seen.iter().zip(exp) {
+            assert_eq!(*seen, (string.to_owned(), path, top_level));
+        }
+    }
 
-        for test in tests.iter() {
-            let obj_opt = test_eval(test.input);
-            if let Some(obj) = obj_opt {
-                test_int_object(&obj, test.exp);
-            } else {
-                panic!("evaluator returned None");
+    #[test]
+    fn test_int_overflow() {
+        let min = "let min = -9223372036854775807 - 1;";
+        let tests = [
+            // (input, checked, wrapping, saturating)
+            (
+                "9223372036854775807 + 1".to_owned(),
+                "ERROR: integer overflow",
+                "-9223372036854775808",
+                "9223372036854775807",
+            ),
+            (
+                format!("{} min - 1", min),
+                "ERROR: integer overflow",
+                "9223372036854775807",
+                "-9223372036854775808",
+            ),
+            (
+                "-3037000500 * 3037000500".to_owned(),
+                "ERROR: integer overflow",
+                "9223372036709301616",
+                "-9223372036854775808",
+            ),
+            (
+                format!("{} -min", min),
+                "ERROR: integer overflow",
+                "-9223372036854775808",
+                "9223372036854775807",
+            ),
+            (
+                format!("{} min / -1", min),
+                "ERROR: integer overflow",
+                "-9223372036854775808",
+                "9223372036854775807",
+            ),
+            // what doesn't overflow is the same under every policy
+            (
+                "9223372036854775806 + 1".to_owned(),
+                "9223372036854775807",
+                "9223372036854775807",
+                "9223372036854775807",
+            ),
+            (
+                "1 / 0".to_owned(),
+                "ERROR: division by zero",
+                "ERROR: division by zero",
+                "ERROR: division by zero",
+            ),
+        ];
+        let policies = [
+            IntOverflow::Checked,
+            IntOverflow::Wrapping,
+            IntOverflow::Saturating,
+        ];
+        for (input, checked, wrapping, saturating) in tests {
+            for (policy, exp) in policies.into_iter().zip([checked, wrapping, saturating]) {
+                let host = Host::new().with_int_overflow(policy);
+                let obj = with_host(host, || test_eval(&input)).expect("evaluator returned None");
+                assert_eq!(
+                    obj.inspect(),
+                    exp,
+                    "evaluating {:?} with {:?}",
+                    input,
+                    policy
+                );
             }
+            // checked without a host too
+            let obj = test_eval(&input).expect("evaluator returned None");
+            assert_eq!(obj.inspect(), checked, "evaluating {:?}", input);
         }
     }
 
     #[test]
-    fn test_function_object() {
-        let input = "fn(x) { x + 2; };";
-        let obj = test_eval(input);
-        match obj {
-            Some(o) => {
-                if let Object::Function(func) = o {
-                    assert_eq!(func.parameters.len(), 1);
-                    let param1 = &func.parameters[0];
-                    assert_eq!(param1.value.to_string(), "x".to_owned());
-                    let exp_body = "(x + 2)";
-                    let s = func.body.string();
-                    assert_eq!(exp_body.to_owned(), s);
-                } else {
-                    panic!("{:#?} is not a function", o);
-                }
-            }
-            None => panic!("eval returned none"),
+    fn test_eval_and_parse_builtins() {
+        let host = || {
+            Host::new().with_capabilities(Capabilities {
+                eval: true,
+                ..Default::default()
+            })
+        };
+        let tests = [
+            ("eval(\"1 + 2\")", "3"),
+            // what it binds is visible afterwards
+            ("eval(\"let x = 5;\"); x * 2", "10"),
+            (
+                "let f = fn(code) { eval(code) }; f(\"let y = 1; y + 1\")",
+                "2",
+            ),
+            ("let n = 4; eval(\"n = n + 1\"); n", "5"),
+            ("eval(\"let z = 1;\")", "null"),
+            ("eval(\"eval(\\\"3\\\")\")", "3"),
+            (
+                "eval(\"let = 1;\")",
+                "ERROR: parse errors: expected next token to be Token::Ident, got Assign instead; \
+                 no prefix parse fn for Assign",
+            ),
+            (
+                "eval(\"1 + true\")",
+                "ERROR: unsupported operand types for +: INTEGER and BOOLEAN",
+            ),
+            (
+                "eval(1)",
+                "ERROR: `eval` expects STRING for argument 1, got INTEGER",
+            ),
+            // calling itself through `eval` stops at the limit
+            (
+                "let f = fn() { eval(\"f()\") }; f()",
+                "ERROR: eval nested more than 32 deep",
+            ),
+            ("parse(\"1 + 2 * 3\")", "(1 + (2 * 3));\n"),
+            ("parse(\"let x=fn(a){a}\")", "let x = fn(a) {\n    a;\n};\n"),
+            (
+                "parse(\"1 +\")",
+                "ERROR: parse errors: no prefix parse fn for Eof",
+            ),
+            // a binding of its own is called like any function
+            ("let eval = fn(x) { x }; eval(1)", "1"),
+        ];
+        for (input, exp) in tests {
+            let obj = with_host(host(), || test_eval(input)).expect("evaluator returned None");
+            assert_eq!(obj.inspect(), exp, "evaluating {:?}", input);
         }
+
+        // an embedder has to grant it, `parse` is always there
+        assert_eq!(
+            test_eval("eval(\"1\")"),
+            Some(Object::Error(EvalError::CapabilityNotGranted("eval")))
+        );
+        assert_eq!(test_eval("parse(\"x\")").unwrap().inspect(), "x;\n");
     }
 
     #[test]
-    fn test_function_application() {
-        let tests = vec![
-            IntTest {
-                input: "let identity = fn(x) { x; }; identity(5);",
-                exp: 5,
-            },
-            IntTest {
-                input: "let identity = fn(x) { return x; }; identity(5);",
-                exp: 5,
-            },
-            IntTest {
-                input: "let double = fn(x) { x * 2; }; double(5);",
-                exp: 10,
-            },
-            IntTest {
-                input: "let add = fn(x, y) { x + y; }; add(5, 5);",
-                exp: 10,
-            },
-            IntTest {
-                input: "let add = fn(x, y) { x + y; }; add(5 + 5, add(5, 5));",
-                exp: 20,
-            },
-            IntTest {
-                input: "fn(x) { x; }(5)",
-                exp: 5,
-            },
+    fn test_exit() {
+        let tests = [
+            ("exit(3); 1", Object::Error(EvalError::Exit(3))),
+            // out of nested calls and builtins, skipping the rest
+            (
+                "let f = fn(x) { if (x > 1) { exit(x) } x }; map([1, 2, 3], f); 10",
+                Object::Error(EvalError::Exit(2)),
+            ),
+            ("let a = [1, exit(0)]; a", Object::Error(EvalError::Exit(0))),
+            (
+                "exit(256)",
+                Object::Error(EvalError::InvalidArgument {
+                    builtin: "exit",
+                    reason: "exit status must be from 0 to 255",
+                }),
+            ),
+            (
+                "exit(\"1\")",
+                Object::Error(EvalError::WrongArgumentType {
+                    builtin: "exit",
+                    position: 1,
+                    want: TypeSet::of(&[ObjectType::Integer]),
+                    got: ObjectType::String,
+                }),
+            ),
         ];
+        for (input, exp) in tests {
+            assert_eq!(test_eval(input), Some(exp), "{}", input);
+        }
+    }
 
-        for test in tests.iter() {
-            let obj_opt = test_eval(test.input);
-            if let Some(obj) = obj_opt {
-                test_int_object(&obj, test.exp);
-            } else {
-                panic!("evaluator returned None");
-            }
+    #[test]
+    fn test_hoisting() {
+        let tests = [
+            (
+                "fn isEven(n){ if(n==0){true}else{isOdd(n-1)} } fn isOdd(n){ if(n==0){false}else{isEven(n-1)} } isEven(10)",
+                Object::Boolean(true),
+            ),
+            (
+                "fn isEven(n){ if(n==0){true}else{isOdd(n-1)} } fn isOdd(n){ if(n==0){false}else{isEven(n-1)} } isOdd(7)",
+                Object::Boolean(true),
+            ),
+            // called before it is declared
+            ("let x = double(4); fn double(n) { n * 2 } x", Object::Integer(8)),
+            // and from then on seeing what was bound before the declaration
+            (
+                "let limit = 3; fn under(n) { n < limit } under(2)",
+                Object::Boolean(true),
+            ),
+            (
+                "let limit = 3; fn a(n) { b(n) } fn b(n) { n < limit } a(2)",
+                Object::Boolean(true),
+            ),
+            // a declaration in a function body isn't hoisted
+            (
+                "fn f() { g(); fn g() { 1 } } f()",
+                Object::Error(EvalError::IdentifierNotFound("g".to_owned())),
+            ),
+            (
+                "fn f() { fn g() { 1 } g() } f()",
+                Object::Integer(1),
+            ),
+            (
+                "const f = 1; fn f() { 2 }",
+                Object::Error(EvalError::ConstReassignment("f".to_owned())),
+            ),
+        ];
+        for (input, exp) in tests {
+            assert_eq!(test_eval(input), Some(exp), "{}", input);
         }
+
+        let input = "let a = f(); fn f() { 1 } let b = f(); [a, b]";
+        let mut p = Parser::new(Lexer::new(input));
+        let values = eval_each(&p.parse(), &mut Environment::new());
+        assert_eq!(values.last().unwrap().inspect(), "[1, 1]");
     }
 
     #[test]
-    fn test_closures() {
-        let input = "
-        let newAdder = fn(x) {
-            fn(y) { x + y };
-        };
-        let addTwo = newAdder(2);
-        addTwo(2);";
-        let obj_opt = test_eval(input);
-        if let Some(obj) = obj_opt {
-            test_int_object(&obj, 4);
-        } else {
-            panic!("evaluator returned None");
+    fn test_let_bound_mutual_recursion() {
+        let even_odd = "let even = fn(n) { if (n == 0) { true } else { odd(n - 1) } };
+let odd = fn(n) { if (n == 0) { false } else { even(n - 1) } };";
+        let tests = [
+            (format!("{} even(10)", even_odd), "true"),
+            (format!("{} odd(7)", even_odd), "true"),
+            (format!("{} [even(3), odd(4)]", even_odd), "[false, false]"),
+            // self-recursion through the let name
+            (
+                "let fact = fn(n) { if (n < 2) { 1 } else { n * fact(n - 1) } }; fact(5)"
+                    .to_owned(),
+                "120",
+            ),
+            // what is bound later is visible to the body once it is
+            (
+                "let f = fn() { g() + 1 }; let g = fn() { 1 }; f()".to_owned(),
+                "2",
+            ),
+            ("let f = fn() { y }; let y = 1; f()".to_owned(), "1"),
+            (
+                "let f = fn() { y }; f(); let y = 1;".to_owned(),
+                "ERROR: identifier not found: y",
+            ),
+            // the name itself is bound only once its let runs
+            (
+                "let x = g(); let g = fn() { 1 };".to_owned(),
+                "ERROR: identifier not found: g",
+            ),
+            (
+                "const f = fn() { 1 }; f = 2".to_owned(),
+                "ERROR: cannot reassign const 'f'",
+            ),
+            // and in the scope of a call
+            (
+                "let f = fn() { let a = fn() { b() }; let b = fn() { 1 }; a() }; f()".to_owned(),
+                "1",
+            ),
+        ];
+        for (input, exp) in tests {
+            let obj = test_eval(&input).expect("eval returned None");
+            assert_eq!(obj.inspect(), exp, "{}", input);
         }
     }
 
     #[test]
-    fn test_strings() {
-        let input = "\"Hello World!\"";
-        let obj_opt = test_eval(input);
-        if let Some(obj) = obj_opt {
-            if let Object::String(s) = obj {
-                assert_eq!(s.to_string(), "Hello World!");
-            } else {
-                panic!("{:#?} is not a string", obj);
-            }
-        } else {
-            panic!("evaluator returned None");
+    fn test_assign() {
+        let tests = [
+            ("let y = 0; let x = (y = 5); [x, y]", "[5, 5]"),
+            (
+                "let y = 0; let z = 0; let x = y = z = 5; [x, y, z]",
+                "[5, 5, 5]",
+            ),
+            // a let declares only its own name
+            ("let x = (y = 5); [x, y]", "ERROR: identifier not found: y"),
+            (
+                "const c = 1; let x = c = 2",
+                "ERROR: cannot reassign const 'c'",
+            ),
+            (
+                "let y = 0; let x = y = missing; y",
+                "ERROR: identifier not found: missing",
+            ),
+            ("let a = 1; let b = 2; a = b = 3; [a, b]", "[3, 3]"),
+            ("let n = 1; n = n + 1; n", "2"),
+            // a function assigns to the binding it closes over
+            ("let n = 1; let f = fn() { n = 2; n }; [f(), n]", "[2, 2]"),
+            (
+                "let counter = fn() { let c = 0; fn() { c = c + 1 } };
+let next = counter(); next(); next(); next()",
+                "3",
+            ),
+            (
+                "let make = fn() { let c = 0; fn() { c = c + 1 } };
+let a = make(); let b = make(); a(); a(); [a(), b()]",
+                "[3, 1]",
+            ),
+            ("let n = 1; let f = fn(n) { n = 5 }; f(0); n", "1"),
+            (
+                "let total = 0; for (x in [1, 2, 3]) { total = total + x }; total",
+                "6",
+            ),
+            ("let f = fn(n) { n = n * 2; n }; f(4)", "8"),
+            ("y = 1", "ERROR: identifier not found: y"),
+            ("const c = 1; c = 2", "ERROR: cannot reassign const 'c'"),
+            (
+                "let y = 0; y = missing",
+                "ERROR: identifier not found: missing",
+            ),
+        ];
+        for (input, exp) in tests {
+            let evaluated = test_eval(input).unwrap();
+            assert_eq!(evaluated.inspect(), exp, "{}", input);
         }
     }
 
+    #[cfg(feature = "trace")]
     #[test]
-    fn test_string_concatination() {
-        let input = "\"Hello\" + \" \" + \"World!\"";
-        let obj_opt = test_eval(input);
-        if let Some(obj) = obj_opt {
-            if let Object::String(s) = obj {
-                assert_eq!(s.to_string(), "Hello World!");
-            } else {
-                panic!("{:#?} is not a string", obj);
-            }
-        } else {
-            panic!("evaluator returned None");
+    fn test_eval_traced() {
+        let mut p = Parser::new(Lexer::new("1 + 2"));
+        let program = p.parse();
+        let mut out = Vec::new();
+        let res = super::eval_traced(&program, &mut Environment::new(), &mut out).unwrap();
+        assert_eq!(res, Some(Object::Integer(3)));
+        let trace = String::from_utf8(out).unwrap();
+        assert_eq!(
+            trace,
+            "eval ExpressionStatement
+  eval InfixExpression(+)
+    eval IntegerLiteral(1)
+    => 1
+    eval IntegerLiteral(2)
+    => 2
+  => 3
+=> 3
+"
+        );
+
+        // a call traces the function's body one level further in, and a
+        // statement without a value has no result line
+        let input = "let f = fn(x) { x * 2 }; f(3)";
+        let program = Parser::new(Lexer::new(input)).parse();
+        let mut out = Vec::new();
+        super::eval_traced(&program, &mut Environment::new(), &mut out).unwrap();
+        let trace = String::from_utf8(out).unwrap();
+        let lines: Vec<&str> = trace.lines().collect();
+        assert_eq!(lines[0], "eval LetStatement(f)");
+        assert_eq!(lines[1], "eval ExpressionStatement");
+        assert!(lines.contains(&"  eval CallExpression"), "{}", trace);
+        assert!(
+            lines.contains(&"      eval InfixExpression(*)"),
+            "{}",
+            trace
+        );
+        assert_eq!(lines.last(), Some(&"=> 6"));
+
+        // and plain `eval` afterwards traces nothing
+        assert_eq!(test_eval("1 + 2"), Some(Object::Integer(3)));
+    }
+
+    #[test]
+    fn test_eval_each() {
+        let tests = [
+            (
+                "let x = 5; x * 2; let y = x + 1; [x, y]; \"done\"",
+                vec![
+                    Object::Null,
+                    Object::Integer(10),
+                    Object::Null,
+                    Object::Array(Rc::new(Array {
+                        elements: vec![Object::Integer(5), Object::Integer(6)],
+                        frozen: false,
+                    })),
+                    Object::String("done".into()),
+                ],
+            ),
+            (
+                "1; let x = 1 + true; 3",
+                vec![
+                    Object::Integer(1),
+                    Object::Error(EvalError::UnsupportedOperands {
+                        op: InfixOperator::Plus,
+                        left: ObjectType::Integer,
+                        right: ObjectType::Boolean,
+                    }),
+                ],
+            ),
+            (
+                "1; return 2; 3",
+                vec![Object::Integer(1), Object::Integer(2)],
+            ),
+            ("", vec![]),
+        ];
+        for (input, exp) in tests {
+            let mut p = Parser::new(Lexer::new(input));
+            let program = p.parse();
+            let mut env = Environment::new();
+            assert_eq!(eval_each(&program, &mut env), exp, "{}", input);
         }
     }
 
     #[test]
-    fn test_builtin_functions() {
-        let tests = vec![
-            IntTest {
-                input: "len(\"\")",
-                exp: 0,
+    fn test_capture_print_output() {
+        let (obj, out) = capture_output(|| test_eval("print(1, \"two\"); print([3]); 4"));
+        assert_eq!(obj, Some(Object::Integer(4)));
+        assert_eq!(out, "1\ntwo\n[3]\n");
+
+        // captures nest, the inner one takes the output while it runs
+        let ((_, inner), outer) = capture_output(|| {
+            test_eval("print(1)");
+            capture_output(|| test_eval("print(2)"))
+        });
+        assert_eq!(inner, "2\n");
+        assert_eq!(outer, "1\n");
+    }
+
+    #[test]
+    fn test_math_builtin_errors() {
+        let tests = [
+            ErrorTest {
+                input: "abs(true)",
+                exp: "`abs` expects INTEGER|FLOAT for argument 1, got BOOLEAN",
             },
-            IntTest {
-                input: "len(\"four\")",
-                exp: 4,
+            ErrorTest {
+                input: "abs(1, 2)",
+                exp: "`abs` expects 1 argument, got 2",
             },
-            IntTest {
-                input: "len(\"hello world\")",
-                exp: 11,
+            ErrorTest {
+                input: "min(1, \"two\")",
+                exp: "`min` expects INTEGER|FLOAT for argument 2, got STRING",
+            },
+            ErrorTest {
+                input: "max([], 1)",
+                exp: "`max` expects INTEGER|FLOAT for argument 1, got ARRAY",
+            },
+            ErrorTest {
+                input: "pow(2)",
+                exp: "`pow` expects 2 arguments, got 1",
+            },
+            ErrorTest {
+                input: "pow(2, -1)",
+                exp: "invalid argument to `pow`: negative exponent",
+            },
+            ErrorTest {
+                input: "pow(10, 100)",
+                exp: "invalid argument to `pow`: result overflows an integer",
+            },
+            ErrorTest {
+                input: "pow(\"2\", 2)",
+                exp: "`pow` expects INTEGER for argument 1, got STRING",
+            },
+            ErrorTest {
+                input: "sqrt(-1)",
+                exp: "invalid argument to `sqrt`: negative number",
+            },
+            ErrorTest {
+                input: "sqrt(\"9\")",
+                exp: "`sqrt` expects INTEGER|FLOAT for argument 1, got STRING",
+            },
+            ErrorTest {
+                input: "mod(1, 0)",
+                exp: "division by zero",
+            },
+            ErrorTest {
+                input: "mod(1, true)",
+                exp: "`mod` expects INTEGER for argument 2, got BOOLEAN",
             },
         ];
-
-        for test in tests {
-            let obj_opt = test_eval(test.input);
-            if let Some(obj) = obj_opt {
-                test_int_object(&obj, test.exp);
-            } else {
-                panic!("evaluator returned None");
+        for test in tests.iter() {
+            match test_eval(test.input) {
+                Some(Object::Error(v)) => assert_eq!(v.to_string(), test.exp),
+                obj => panic!("{:#?} is not an error object", obj),
             }
         }
     }
@@ -1140,23 +4570,23 @@ mod test {
         let obj_opt = test_eval(input);
         if let Some(obj) = obj_opt {
             if let Object::Hash(hash) = obj {
-                assert_eq!(hash.pairs.len(), 6);
-                let p1 = &hash.pairs[0];
+                assert_eq!(hash.len(), 6);
+                let p1 = &hash.pairs()[0];
                 test_string_object(&p1.0, "one");
                 test_int_object(&p1.1, 1);
-                let p2 = &hash.pairs[1];
+                let p2 = &hash.pairs()[1];
                 test_string_object(&p2.0, "two");
                 test_int_object(&p2.1, 2);
-                let p3 = &hash.pairs[2];
+                let p3 = &hash.pairs()[2];
                 test_string_object(&p3.0, "three");
                 test_int_object(&p3.1, 3);
-                let p4 = &hash.pairs[3];
+                let p4 = &hash.pairs()[3];
                 test_int_object(&p4.0, 4);
                 test_int_object(&p4.1, 4);
-                let p5 = &hash.pairs[4];
+                let p5 = &hash.pairs()[4];
                 test_bool_object(&p5.0, true);
                 test_int_object(&p5.1, 5);
-                let p6 = &hash.pairs[5];
+                let p6 = &hash.pairs()[5];
                 test_bool_object(&p6.0, false);
                 test_int_object(&p6.1, 6);
             } else {
@@ -1169,7 +4599,7 @@ mod test {
 
     #[test]
     fn test_hash_index_expression() {
-        let tests = vec![
+        let tests = [
             IndexTest {
                 input: "{\"foo\": 5 }[\"foo\"]",
                 exp: Some(5),
@@ -1213,4 +4643,310 @@ mod test {
             }
         }
     }
+
+    #[test]
+    fn test_hash_order() {
+        let input = "{\"b\": 1, 3: 2, \"a\": 3, true: 4, \"c\": 5}";
+        let exp = "{b: 1, 3: 2, a: 3, true: 4, c: 5}";
+        for _ in 0..100 {
+            let obj = test_eval(input).expect("eval returned None");
+            assert_eq!(obj.inspect(), exp);
+        }
+
+        let tests = [
+            ("keys({})", "[]"),
+            ("keys({\"b\": 1, \"a\": 2})", "[b, a]"),
+            // an overwritten key stays where it was first put
+            ("keys({\"a\": 1, \"b\": 2, \"a\": 3})", "[a, b]"),
+            ("{\"a\": 1, \"b\": 2, \"a\": 3}", "{a: 3, b: 2}"),
+            ("{\"a\": 1, \"b\": 2, \"a\": 3}[\"a\"]", "3"),
+            ("{\"a\": 1, \"b\": 2} == {\"b\": 2, \"a\": 1}", "true"),
+            ("{\"a\": 1, \"b\": 2} != {\"b\": 2, \"a\": 1}", "false"),
+            ("{\"a\": 1, \"b\": 2} == {\"b\": 1, \"a\": 2}", "false"),
+            ("{\"a\": 1, \"a\": 2} == {\"a\": 2}", "true"),
+            (
+                "keys(1)",
+                "ERROR: `keys` expects HASH for argument 1, got INTEGER",
+            ),
+        ];
+        for (input, exp) in tests {
+            let obj = test_eval(input).expect("eval returned None");
+            assert_eq!(obj.inspect(), exp, "{}", input);
+        }
+    }
+
+    #[test]
+    fn test_for_expression() {
+        let tests = [
+            ("let s = 0; for (x in [1, 2, 3]) { s = s + x; }; s", "6"),
+            ("let s = \"\"; for (k, v in {\"b\": 1, \"a\": 2}) { s = s + k; }; s", "ba"),
+            ("let s = 0; for (k in {1: 10, 2: 20}) { s = s + k; }; s", "3"),
+            ("let s = 0; for (i, v in [5, 6]) { s = s + i * v; }; s", "6"),
+            ("let s = []; for (c in \"héllo\") { s = push(s, c); }; s", "[h, é, l, l, o]"),
+            ("let s = []; for (i, x in range(3, 6)) { s = push(s, i + x); }; s", "[3, 5, 7]"),
+            ("let s = 0; for (x in range(5, 0)) { s = s + 1; }; s", "0"),
+            ("for (x in []) { x }", "null"),
+            // a range is never built, so a huge one is fine to break out of
+            ("let s = 0; for (x in range(0, 1000000000)) { if (x == 3) { break; } s = s + x; }; s", "3"),
+            ("let s = 0; for (x in [1, 2, 3, 4]) { if (x == 2) { continue; } s = s + x; }; s", "8"),
+            ("let s = 0; for (x in [1, 2]) { for (y in [1, 2]) { if (y == 2) { break; } s = s + 1; } }; s", "2"),
+            ("let f = fn() { for (x in [1, 2, 3]) { if (x == 2) { return x * 10; } } 0 }; f()", "20"),
+            // `break outer` ends both loops below `outer` at once
+            ("let s = []; outer: for (x in [1, 2, 3]) { for (y in [1, 2]) { for (z in [1, 2]) { if (x == 2) { break outer; } s = push(s, x * 100 + y * 10 + z); } } }; s", "[111, 112, 121, 122]"),
+            ("let s = 0; outer: for (x in [1, 2]) { inner: for (y in [1, 2]) { if (y == 2) { break inner; } s = s + 1; } }; s", "2"),
+            ("let s = 0; a: for (x in [1, 2]) { for (y in [1, 2]) { continue; break a; } s = s + 1; }; s", "2"),
+            ("for (x in 1) { x }", "ERROR: cannot iterate over INTEGER"),
+            ("for (x in [1, y]) { x }", "ERROR: identifier not found: y"),
+            ("for (x in [1, 2]) { x + true }", "ERROR: unsupported operand types for +: INTEGER and BOOLEAN"),
+            ("range(1)", "ERROR: `range` expects 2 arguments, got 1"),
+            ("range(\"a\", 2)", "ERROR: `range` expects INTEGER for argument 1, got STRING"),
+            ("range(1, 4)", "range(1, 4)"),
+        ];
+        for (input, exp) in tests {
+            let obj = test_eval(input).expect("eval returned None");
+            assert_eq!(obj.inspect(), exp, "{}", input);
+        }
+
+        // every turn binds its own variable, so closures see distinct values
+        let input = "let fs = []; for (x in [1, 2, 3]) { fs = push(fs, fn() { x }); }; map(fs, fn(f) { f() })";
+        let obj = test_eval(input).expect("eval returned None");
+        assert_eq!(obj.inspect(), "[1, 2, 3]");
+
+        // and the variables are gone after the loop
+        let obj = test_eval("for (x in [1]) { let y = x; }; x").expect("eval returned None");
+        assert_eq!(obj.inspect(), "ERROR: identifier not found: x");
+        let obj = test_eval("for (x in [1]) { let y = x; }; y").expect("eval returned None");
+        assert_eq!(obj.inspect(), "ERROR: identifier not found: y");
+    }
+
+    #[test]
+    fn test_tail_calls() {
+        let shallow = || Host::new().with_max_call_depth(50);
+        let tests = [
+            (
+                "let loop = fn(n, acc) { if (n == 0) { acc } else { loop(n - 1, acc + n) } }; loop(1000000, 0)",
+                "500000500000",
+            ),
+            // a returned call is in tail position too
+            (
+                "let down = fn(n) { if (n == 0) { return 0; } return down(n - 1); }; down(100000)",
+                "0",
+            ),
+            (
+                "fn even(n) { if (n == 0) { true } else { odd(n - 1) } } fn odd(n) { if (n == 0) { false } else { even(n - 1) } } even(100001)",
+                "false",
+            ),
+            // the call has to give the value of the function as it is
+            (
+                "let sum = fn(n) { if (n == 0) { 0 } else { n + sum(n - 1) } }; sum(100)",
+                "ERROR: calls nested more than 50 deep",
+            ),
+            (
+                "let f = fn(n) { if (n == 0) { 0 } else { let x = f(n - 1); x } }; f(100)",
+                "ERROR: calls nested more than 50 deep",
+            ),
+            (
+                "let f = fn(n) { if (n == 0) { 0 } else { [f(n - 1)][0] } }; f(100)",
+                "ERROR: calls nested more than 50 deep",
+            ),
+            // each call binds its own arguments, which a closure made in
+            // the one before still sees
+            (
+                "let collect = fn(n, fs) { if (n == 0) { fs } else { collect(n - 1, push(fs, fn() { n })) } }; map(collect(3, []), fn(f) { f() })",
+                "[3, 2, 1]",
+            ),
+            // builtins in tail position are called as they are
+            ("let f = fn(n) { len(n) }; f(\"abc\")", "3"),
+            (
+                "let loop = fn(n) { if (n == 0) { 1 / 0 } else { loop(n - 1) } }; loop(1000)",
+                "ERROR: division by zero",
+            ),
+        ];
+        for (input, exp) in tests {
+            let obj = with_host(shallow(), || test_eval(input)).expect("eval returned None");
+            assert_eq!(obj.inspect(), exp, "{}", input);
+        }
+        assert_eq!(EvalError::CallTooDeep(50).kind(), ErrorKind::CallTooDeep);
+    }
+
+    #[test]
+    fn test_fuel() {
+        let tests = [
+            // `1 + 2` and its operands are three steps
+            ("1 + 2", 3, "3"),
+            ("1 + 2", 2, "ERROR: out of fuel after 2 steps"),
+            (
+                "for (x in range(0, 1000000000)) {}",
+                1000,
+                "ERROR: out of fuel after 1000 steps",
+            ),
+            (
+                "let loop = fn() { loop() }; loop()",
+                1000,
+                "ERROR: out of fuel after 1000 steps",
+            ),
+            (
+                "let f = fn(n) { if (n == 0) { 0 } else { 1 + f(n - 1) } }; f(10)",
+                1000,
+                "10",
+            ),
+        ];
+        for (input, fuel, exp) in tests {
+            let host = Host::new().with_fuel(fuel);
+            let obj = with_host(host, || test_eval(input)).expect("eval returned None");
+            assert_eq!(obj.inspect(), exp, "{} with {} fuel", input, fuel);
+        }
+        // the fuel is the host's, and another one runs without a limit
+        assert_eq!(
+            test_eval("1 + 2").map(|obj| obj.inspect()),
+            Some("3".into())
+        );
+        assert_eq!(EvalError::OutOfFuel(1).kind(), ErrorKind::OutOfFuel);
+    }
+
+    #[test]
+    fn test_shared_values_are_immutable() {
+        let input = "let a = [1, 2];
+let b = push(a, 3);
+let h = {\"k\": a};
+let c = push(h[\"k\"], 4);
+[len(a), len(b), len(h[\"k\"]), len(c)]";
+        match test_eval(input) {
+            Some(Object::Array(arr)) => {
+                for (obj, exp) in arr.elements.iter().zip([2, 3, 2, 3]) {
+                    test_int_object(obj, exp);
+                }
+            }
+            obj => panic!("{:#?} is not an array", obj),
+        }
+    }
+
+    #[test]
+    fn test_literals_are_made_once() {
+        match test_eval("let f = fn() { [1, 2] }; (f(), f())") {
+            Some(Object::Tuple(tuple)) => match &tuple.elements[..] {
+                [Object::Array(a), Object::Array(b)] => assert!(Rc::ptr_eq(a, b)),
+                elements => panic!("{:?} are not two arrays", elements),
+            },
+            obj => panic!("{:#?} is not a tuple", obj),
+        }
+
+        // so is the one of a program evaluated again, or of a copy of it,
+        // until the literal is changed
+        let mut program = Parser::new(Lexer::new("[1, [2]]")).parse();
+        let array = |program: &crate::ast::Program| match eval(program, &mut Environment::new()) {
+            Some(Object::Array(array)) => array,
+            obj => panic!("{:?} is not an array", obj),
+        };
+        let first = array(&program);
+        assert!(Rc::ptr_eq(&first, &array(&program)));
+        let copy = crate::ast::Program {
+            statements: program.statements.clone(),
+        };
+        assert!(Rc::ptr_eq(&first, &array(&copy)));
+        let seven = Parser::new(Lexer::new("7")).parse().statements[0]
+            .expression()
+            .cloned()
+            .unwrap();
+        program.replace_expr_at(&[0, 1, 0], seven);
+        assert_eq!(Object::Array(array(&program)).inspect(), "[1, [7]]");
+        assert_eq!(Object::Array(first).inspect(), "[1, [2]]");
+
+        let tests = [
+            // what builtins give is a copy, and the literal stays as it was
+            (
+                "let f = fn() { [1, [2, 3], {\"a\": -1}] };
+let a = f();
+let b = push(a, 4);
+let c = push(a[1], 5);
+[f(), b, c]",
+                "[[1, [2, 3], {a: -1}], [1, [2, 3], {a: -1}, 4], [2, 3, 5]]",
+            ),
+            (
+                "let f = fn(x) { [x, [x]] }; [f(1), f(2)]",
+                "[[1, [1]], [2, [2]]]",
+            ),
+            (
+                "let total = 0; for (i in range(0, 3)) { total = total + len(push([0, 1], i)) }; total",
+                "9",
+            ),
+            (
+                "let f = eval(\"fn() { [1, 2] }\"); let g = eval(\"fn() { [3] }\"); [f(), g(), f()]",
+                "[[1, 2], [3], [1, 2]]",
+            ),
+            (
+                "let f = fn() { [-true] }; [f(), f()]",
+                "ERROR: unsupported operand type for -: BOOLEAN",
+            ),
+        ];
+        let host = || {
+            Host::new().with_capabilities(Capabilities {
+                eval: true,
+                ..Default::default()
+            })
+        };
+        for (input, exp) in tests {
+            let res = with_host(host(), || test_eval(input)).unwrap().inspect();
+            assert_eq!(res, exp, "{}", input);
+        }
+    }
+
+    #[test]
+    fn test_equality_across_types() {
+        let tests = [
+            ("1 == \"1\"", false),
+            ("1 != \"1\"", true),
+            ("true == 1", false),
+            ("\"true\" == true", false),
+            ("[] == {}", false),
+            ("let n = if (false) { 1 }; n == n", true),
+            ("if (false) { 1 } == 0", false),
+            ("\"a\" == \"a\"", true),
+            ("[1, \"a\", [true]] == [1, \"a\", [true]]", true),
+            ("[1] == [true]", false),
+            ("[1, 2] == [1]", false),
+            ("(1, \"a\") == (1, \"a\")", true),
+            ("{1: 2, \"a\": [3]} == {\"a\": [3], 1: 2}", true),
+            ("{1: 2} == {1: \"2\"}", false),
+            ("let f = fn() { 1 }; let g = f; f == g", true),
+            ("fn() { 1 } == fn() { 1 }", false),
+            ("len == len", true),
+            ("len != first", true),
+            ("len == fn(x) { len(x) }", false),
+        ];
+        for (input, exp) in tests {
+            match test_eval(input) {
+                Some(obj) => assert_eq!(obj, Object::Boolean(exp), "evaluating {:?}", input),
+                None => panic!("evaluator returned None for {:?}", input),
+            }
+        }
+    }
+
+    #[test]
+    fn test_shared_booleans_compare() {
+        let tests = [
+            BoolTest {
+                input: "let t = true; let f = false; t == true",
+                exp: true,
+            },
+            BoolTest {
+                input: "let t = 1 < 2; t == (3 > 2)",
+                exp: true,
+            },
+            BoolTest {
+                input: "let f = fn(x) { x == 1 }; f(1) != f(2)",
+                exp: true,
+            },
+            BoolTest {
+                input: "let a = [true, false]; a[0] == a[1]",
+                exp: false,
+            },
+        ];
+        for test in tests.iter() {
+            match test_eval(test.input) {
+                Some(obj) => test_bool_object(&obj, test.exp),
+                None => panic!("evaluator returned None"),
+            }
+        }
+    }
 }
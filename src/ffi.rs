@@ -0,0 +1,236 @@
+//! A C interface for embedding the interpreter, declared in
+//! `include/monkey.h`. Every string handed out is owned by the caller and
+//! must be released with `monkey_string_free`. No panic crosses the
+//! boundary: one is caught and reported as `MONKEY_PANIC`.
+
+use std::ffi::{c_char, c_int, CString};
+use std::panic::{catch_unwind, AssertUnwindSafe};
+
+use crate::environment::Environment;
+use crate::evaluator;
+use crate::lexer::Lexer;
+use crate::object::{Object, ObjectTrait};
+use crate::parser::Parser;
+
+pub const MONKEY_OK: c_int = 0;
+pub const MONKEY_INVALID_ARGUMENT: c_int = 1;
+pub const MONKEY_INVALID_UTF8: c_int = 2;
+pub const MONKEY_PARSE_ERROR: c_int = 3;
+pub const MONKEY_RUNTIME_ERROR: c_int = 4;
+pub const MONKEY_PANIC: c_int = 5;
+
+/// An interpreter handle. Bindings made by one `monkey_run` are visible to
+/// the next, like in the REPL.
+pub struct Monkey {
+    env: Environment,
+    errors: Vec<String>,
+}
+
+#[no_mangle]
+pub extern "C" fn monkey_new() -> *mut Monkey {
+    Box::into_raw(Box::new(Monkey {
+        env: Environment::new(),
+        errors: Vec::new(),
+    }))
+}
+
+/// # Safety
+///
+/// `handle` must come from `monkey_new` and not have been freed. Null is
+/// ignored.
+#[no_mangle]
+pub unsafe extern "C" fn monkey_free(handle: *mut Monkey) {
+    if !handle.is_null() {
+        drop(Box::from_raw(handle));
+    }
+}
+
+/// Runs `len` bytes of UTF-8 source at `source` and returns the inspected
+/// value of the program, an empty string if it has none. On failure this
+/// returns null, the reasons are left in `monkey_last_errors`. `out_err`,
+/// unless null, is set to `MONKEY_OK` or to the kind of failure.
+///
+/// # Safety
+///
+/// `handle` must come from `monkey_new`, and `source` must point at `len`
+/// readable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn monkey_run(
+    handle: *mut Monkey,
+    source: *const u8,
+    len: usize,
+    out_err: *mut c_int,
+) -> *mut c_char {
+    let (code, res) = match handle.as_mut() {
+        None => (MONKEY_INVALID_ARGUMENT, std::ptr::null_mut()),
+        Some(monkey) if source.is_null() && len != 0 => {
+            monkey.errors = vec!["source is null".to_owned()];
+            (MONKEY_INVALID_ARGUMENT, std::ptr::null_mut())
+        }
+        Some(monkey) => {
+            let source = if len == 0 {
+                &[]
+            } else {
+                std::slice::from_raw_parts(source, len)
+            };
+            match catch_unwind(AssertUnwindSafe(|| run(monkey, source))) {
+                Ok(Ok(value)) => (MONKEY_OK, into_c_string(value)),
+                Ok(Err(code)) => (code, std::ptr::null_mut()),
+                Err(_) => {
+                    monkey.errors = vec!["the interpreter panicked".to_owned()];
+                    (MONKEY_PANIC, std::ptr::null_mut())
+                }
+            }
+        }
+    };
+    if let Some(out_err) = out_err.as_mut() {
+        *out_err = code;
+    }
+    res
+}
+
+/// The errors of the last `monkey_run`, one per line, or an empty string if
+/// it succeeded. Null if `handle` is null.
+///
+/// # Safety
+///
+/// `handle` must come from `monkey_new`.
+#[no_mangle]
+pub unsafe extern "C" fn monkey_last_errors(handle: *const Monkey) -> *mut c_char {
+    match handle.as_ref() {
+        Some(monkey) => into_c_string(monkey.errors.join("\n")),
+        None => std::ptr::null_mut(),
+    }
+}
+
+/// # Safety
+///
+/// `s` must be a string returned by this library that hasn't been freed.
+/// Null is ignored.
+#[no_mangle]
+pub unsafe extern "C" fn monkey_string_free(s: *mut c_char) {
+    if !s.is_null() {
+        drop(CString::from_raw(s));
+    }
+}
+
+fn run(monkey: &mut Monkey, source: &[u8]) -> Result<String, c_int> {
+    monkey.errors.clear();
+    let source = match std::str::from_utf8(source) {
+        Ok(source) => source,
+        Err(e) => {
+            monkey
+                .errors
+                .push(format!("source is not valid UTF-8: {}", e));
+            return Err(MONKEY_INVALID_UTF8);
+        }
+    };
+    let mut p = Parser::new(Lexer::new(source));
+    let program = p.parse();
+    if p.errors_len() != 0 {
+        monkey.errors = p.get_errors().clone();
+        return Err(MONKEY_PARSE_ERROR);
+    }
+    match evaluator::eval(&program, &mut monkey.env) {
+        Some(Object::Error(err)) => {
+            monkey.errors.push(err.to_string());
+            Err(MONKEY_RUNTIME_ERROR)
+        }
+        Some(obj) => Ok(obj.inspect()),
+        None => Ok(String::new()),
+    }
+}
+
+fn into_c_string(s: String) -> *mut c_char {
+    // a NUL can't be represented, cut the string there
+    let bytes = match s.find('\0') {
+        Some(i) => s[..i].to_owned(),
+        None => s,
+    };
+    CString::new(bytes)
+        .expect("NUL bytes were removed")
+        .into_raw()
+}
+
+#[cfg(test)]
+mod test {
+    use std::ffi::{c_int, CStr};
+
+    use crate::ffi::{
+        monkey_free, monkey_last_errors, monkey_new, monkey_run, monkey_string_free,
+        MONKEY_INVALID_ARGUMENT, MONKEY_INVALID_UTF8, MONKEY_OK, MONKEY_PARSE_ERROR,
+        MONKEY_RUNTIME_ERROR,
+    };
+
+    /* runs `source` and copies the result and errors out of their buffers */
+    fn run(handle: *mut crate::ffi::Monkey, source: &[u8]) -> (c_int, Option<String>, String) {
+        let mut code = -1;
+        unsafe {
+            let res = monkey_run(handle, source.as_ptr(), source.len(), &mut code);
+            let value = (!res.is_null()).then(|| CStr::from_ptr(res).to_str().unwrap().to_owned());
+            monkey_string_free(res);
+            let errors = monkey_last_errors(handle);
+            let errs = CStr::from_ptr(errors).to_str().unwrap().to_owned();
+            monkey_string_free(errors);
+            (code, value, errs)
+        }
+    }
+
+    #[test]
+    fn test_run() {
+        let handle = monkey_new();
+        let tests: [(&[u8], c_int, Option<&str>, &str); 6] = [
+            (b"let add = fn(a, b) { a + b };", MONKEY_OK, Some(""), ""),
+            (b"add(1, 2)", MONKEY_OK, Some("3"), ""),
+            (b"\"a\" + \"b\"", MONKEY_OK, Some("ab"), ""),
+            (
+                b"let = 1;",
+                MONKEY_PARSE_ERROR,
+                None,
+                "expected next token to be Token::Ident, got Assign instead\nno prefix parse fn for Assign",
+            ),
+            (
+                b"add(1, true)",
+                MONKEY_RUNTIME_ERROR,
+                None,
+                "unsupported operand types for +: INTEGER and BOOLEAN",
+            ),
+            (
+                b"\"\xff\"",
+                MONKEY_INVALID_UTF8,
+                None,
+                "source is not valid UTF-8: invalid utf-8 sequence of 1 bytes from index 1",
+            ),
+        ];
+        for (source, code, value, errors) in tests {
+            let res = run(handle, source);
+            assert_eq!(res, (code, value.map(String::from), errors.to_owned()));
+        }
+        unsafe { monkey_free(handle) };
+    }
+
+    #[test]
+    fn test_null_arguments() {
+        unsafe {
+            let mut code = -1;
+            let res = monkey_run(std::ptr::null_mut(), b"1".as_ptr(), 1, &mut code);
+            assert!(res.is_null());
+            assert_eq!(code, MONKEY_INVALID_ARGUMENT);
+            assert!(monkey_last_errors(std::ptr::null()).is_null());
+
+            let handle = monkey_new();
+            let res = monkey_run(handle, std::ptr::null(), 1, &mut code);
+            assert!(res.is_null());
+            assert_eq!(code, MONKEY_INVALID_ARGUMENT);
+
+            // an empty source may be null, and `out_err` is optional
+            let res = monkey_run(handle, std::ptr::null(), 0, std::ptr::null_mut());
+            assert_eq!(CStr::from_ptr(res).to_str(), Ok(""));
+            monkey_string_free(res);
+
+            monkey_string_free(std::ptr::null_mut());
+            monkey_free(handle);
+            monkey_free(std::ptr::null_mut());
+        }
+    }
+}
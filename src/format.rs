@@ -0,0 +1,598 @@
+//! The source formatter behind `monkey fmt`. It prints the AST one statement
+//! per line with 4-space indentation, with only the parentheses the
+//! precedence of the operators needs, and puts the comments of the source
+//! back in. String literals keep the escapes they were written with.
+//!
+//! A comment is attached to the first token after it that isn't a paren or
+//! a `;`, since those are the tokens the formatter may add or drop. One
+//! that shared a line with code stays at the end of a line, the others get
+//! a line of their own before the line their token ends up on. Block
+//! comments, `/* like this */`, are kept the same way.
+
+use std::collections::{HashMap, VecDeque};
+use std::rc::Rc;
+
+use crate::ast::{BlockStatement, Expression, Identifier, InfixOperator, Node, Statement};
+use crate::lexer::Lexer;
+use crate::parser::{Parser, Precedence};
+use crate::token::Token;
+
+const INDENT: &str = "    ";
+
+/// Formats `source`, or returns the parse errors if it doesn't parse.
+/// Formatting is idempotent: formatting the output again doesn't change it.
+pub fn format(source: &str) -> Result<String, Vec<String>> {
+    format_with(source, false)
+}
+
+/* like `format`, but with every operator and index in parentheses the way
+ * `string()` has them, which shows how the source parses */
+pub(crate) fn format_grouped(source: &str) -> Result<String, Vec<String>> {
+    format_with(source, true)
+}
+
+fn format_with(source: &str, grouped: bool) -> Result<String, Vec<String>> {
+    let mut p = Parser::new(Lexer::new(source).with_block_comments());
+    let program = p.parse();
+    if p.errors_len() != 0 {
+        return Err(p.get_errors().clone());
+    }
+    let mut f = Formatter {
+        out: String::new(),
+        indent: 0,
+        printed: 0,
+        comments: comments(source),
+        strings: strings(source),
+        grouped,
+    };
+    for stmt in program.statements.iter() {
+        f.statement(stmt);
+    }
+    while let Some(comment) = f.comments.pop_front() {
+        f.out.push_str(&comment.text);
+        f.out.push('\n');
+    }
+    Ok(f.out)
+}
+
+struct Comment {
+    text: Rc<str>,
+    /* how many anchoring tokens come before it */
+    anchor: usize,
+    /* on the same line as the token before it */
+    trailing: bool,
+}
+
+/* tokens the formatter prints exactly as often as the source has them */
+fn is_anchor(tok: &Token) -> bool {
+    !matches!(
+        tok,
+        Token::LParen
+            | Token::RParen
+            | Token::Semicolon
+            | Token::Pipe
+            | Token::Comment(_)
+            | Token::Eof
+    )
+}
+
+/* how tightly `exp` holds together, as `Token::precedence` has it for the
+ * operators. Everything that isn't an operator is an operand. */
+fn precedence(exp: &Expression) -> Precedence {
+    match exp {
+        Expression::Assign(_) => Precedence::Assign,
+        Expression::InfixExpression(ie) => match ie.operator {
+            InfixOperator::Eq | InfixOperator::NotEq => Precedence::Equals,
+            InfixOperator::Lt | InfixOperator::Gt => Precedence::LessGreater,
+            InfixOperator::Plus | InfixOperator::Minus => Precedence::Sum,
+            InfixOperator::Asterisk | InfixOperator::Slash => Precedence::Product,
+        },
+        Expression::PrefixExpression(_) => Precedence::Prefix,
+        _ => Precedence::Index,
+    }
+}
+
+fn comments(source: &str) -> VecDeque<Comment> {
+    let mut res = VecDeque::new();
+    let mut l = Lexer::new(source).with_block_comments().with_comments();
+    let mut anchors = 0;
+    let mut prev_end = None;
+    loop {
+        let tok = l.next_token();
+        let span = l.span();
+        match tok {
+            Token::Eof => break,
+            Token::Comment(text) => res.push_back(Comment {
+                text,
+                anchor: anchors,
+                trailing: prev_end.is_some_and(|end| !source[end..span.start].contains('\n')),
+            }),
+            tok if is_anchor(&tok) => anchors += 1,
+            _ => {}
+        }
+        prev_end = Some(span.end);
+    }
+    res
+}
+
+/* the string literals of `source` as they are written, by the string each
+ * is, in the order they come. Literals of the same string are told apart
+ * by their order alone, which only the pipe changes. */
+fn strings(source: &str) -> HashMap<Rc<str>, VecDeque<Rc<str>>> {
+    let mut res: HashMap<Rc<str>, VecDeque<Rc<str>>> = HashMap::new();
+    let mut l = Lexer::new(source).with_block_comments();
+    loop {
+        match l.next_token() {
+            Token::Eof => return res,
+            Token::String(value) => res
+                .entry(value)
+                .or_default()
+                .push_back(source[l.span()].into()),
+            _ => {}
+        }
+    }
+}
+
+struct Formatter {
+    out: String,
+    indent: usize,
+    /* anchoring tokens printed so far, in step with `Comment::anchor` */
+    printed: usize,
+    comments: VecDeque<Comment>,
+    strings: HashMap<Rc<str>, VecDeque<Rc<str>>>,
+    /* see `format_grouped` */
+    grouped: bool,
+}
+
+impl Formatter {
+    /* an anchoring token */
+    fn token(&mut self, s: &str) {
+        self.out.push_str(s);
+        self.printed += 1;
+    }
+
+    fn push(&mut self, s: &str) {
+        self.out.push_str(s);
+    }
+
+    fn start_line(&mut self) {
+        self.own_line_comments();
+        self.push_indent();
+    }
+
+    /* the comments that belong before whatever is printed next */
+    fn own_line_comments(&mut self) {
+        while self
+            .comments
+            .front()
+            .is_some_and(|c| c.anchor <= self.printed)
+        {
+            let comment = self.comments.pop_front().expect("front was checked");
+            self.push_indent();
+            self.out.push_str(&comment.text);
+            self.out.push('\n');
+        }
+    }
+
+    fn end_line(&mut self) {
+        if self
+            .comments
+            .front()
+            .is_some_and(|c| c.trailing && c.anchor <= self.printed)
+        {
+            let comment = self.comments.pop_front().expect("front was checked");
+            self.out.push(' ');
+            self.out.push_str(&comment.text);
+        }
+        self.out.push('\n');
+    }
+
+    fn push_indent(&mut self) {
+        for _ in 0..self.indent {
+            self.out.push_str(INDENT);
+        }
+    }
+
+    /* whether a comment sits right before the next token */
+    fn comment_before_next(&self) -> bool {
+        self.comments
+            .iter()
+            .take_while(|c| c.anchor <= self.printed)
+            .any(|c| c.anchor == self.printed)
+    }
+
+    fn statement(&mut self, stmt: &Statement) {
+        self.start_line();
+        match stmt {
+            Statement::LetStatement(ls) if ls.tok == Token::Function => {
+                if let Expression::FunctionLiteral(func) = &ls.value {
+                    self.token("fn");
+                    self.push(" ");
+                    self.function(&ls.name.value, &func.parameters, &func.body);
+                    self.end_line();
+                    return;
+                }
+            }
+            Statement::LetStatement(ls) => {
+                self.token(if ls.mutable { "let" } else { "const" });
+                self.push(" ");
+                self.token(&ls.name.value);
+                self.push(" ");
+                self.token("=");
+                self.push(" ");
+                self.expression(&ls.value);
+            }
+            Statement::LetTupleStatement(lts) => {
+                self.token(if lts.mutable { "let" } else { "const" });
+                self.push(" ");
+                for (i, name) in lts.names.iter().enumerate() {
+                    if i != 0 {
+                        self.token(",");
+                        self.push(" ");
+                    }
+                    self.token(&name.value);
+                }
+                self.push(" ");
+                self.token("=");
+                self.push(" ");
+                self.expression(&lts.value);
+            }
+            Statement::ReturnStatement(rs) => {
+                self.token("return");
+                self.push(" ");
+                self.expression(&rs.value);
+            }
+            Statement::ExpressionStatement(es) => self.expression(&es.expression),
+            Statement::BreakStatement(bs) => {
+                self.token("break");
+                if let Some(label) = &bs.label {
+                    self.push(" ");
+                    self.token(&label.value);
+                }
+            }
+            Statement::ContinueStatement(_) => self.token("continue"),
+            Statement::ImportStatement(is) => {
+                self.token("import");
+                self.push(" ");
+                self.expression(&is.path);
+                if let Some(alias) = &is.alias {
+                    self.push(" ");
+                    self.token("as");
+                    self.push(" ");
+                    self.token(&alias.value);
+                }
+            }
+        }
+        self.push(";");
+        self.end_line();
+    }
+
+    fn block(&mut self, block: &BlockStatement) {
+        self.token("{");
+        if block.statements.is_empty() && !self.comment_before_next() {
+            self.token("}");
+            return;
+        }
+        self.end_line();
+        self.indent += 1;
+        for stmt in block.statements.iter() {
+            self.statement(stmt);
+        }
+        self.own_line_comments();
+        self.indent -= 1;
+        self.push_indent();
+        self.token("}");
+    }
+
+    fn function(&mut self, keyword: &str, parameters: &[Identifier], body: &BlockStatement) {
+        self.token(keyword);
+        self.push("(");
+        for (i, param) in parameters.iter().enumerate() {
+            if i != 0 {
+                self.token(",");
+                self.push(" ");
+            }
+            self.token(&param.value);
+        }
+        self.push(") ");
+        self.block(body);
+    }
+
+    fn list(&mut self, elements: &[Expression]) {
+        for (i, exp) in elements.iter().enumerate() {
+            if i != 0 {
+                self.token(",");
+                self.push(" ");
+            }
+            self.expression(exp);
+        }
+    }
+
+    /* `exp`, in parentheses if `parens` and it doesn't get them anyway */
+    fn operand(&mut self, exp: &Expression, parens: bool) {
+        let parens = parens && !self.grouped;
+        if parens {
+            self.push("(");
+        }
+        self.expression(exp);
+        if parens {
+            self.push(")");
+        }
+    }
+
+    fn expression(&mut self, exp: &Expression) {
+        let grouped = self.grouped
+            && matches!(
+                exp,
+                Expression::PrefixExpression(_)
+                    | Expression::InfixExpression(_)
+                    | Expression::Assign(_)
+                    | Expression::IndexExpression(_)
+            );
+        if grouped {
+            self.push("(");
+        }
+        self.ungrouped(exp);
+        if grouped {
+            self.push(")");
+        }
+    }
+
+    fn ungrouped(&mut self, exp: &Expression) {
+        match exp {
+            Expression::Identifier(ident) => self.token(&ident.value),
+            Expression::Integer(int) => self.token(&int.string()),
+            Expression::Float(float) => self.token(&float.string()),
+            Expression::String(s) => {
+                let written = self.strings.get_mut(&s.value).and_then(VecDeque::pop_front);
+                self.token(&written.unwrap_or_else(|| s.string().into()));
+            }
+            Expression::Boolean(b) => self.token(if b.value { "true" } else { "false" }),
+            Expression::Array(array) => {
+                self.token("[");
+                self.list(&array.elements);
+                self.token("]");
+            }
+            Expression::Tuple(tuple) => {
+                self.push("(");
+                self.list(&tuple.elements);
+                self.push(")");
+            }
+            Expression::Hash(hash) => {
+                self.token("{");
+                for (i, (key, value)) in hash.pairs.iter().enumerate() {
+                    if i != 0 {
+                        self.token(",");
+                        self.push(" ");
+                    }
+                    self.expression(key);
+                    self.token(":");
+                    self.push(" ");
+                    self.expression(value);
+                }
+                self.token("}");
+            }
+            Expression::PrefixExpression(pe) => {
+                self.token(&pe.operator.to_string());
+                self.operand(&pe.right, precedence(&pe.right) < Precedence::Prefix);
+            }
+            Expression::InfixExpression(ie) => {
+                // operators of the same precedence group to the left
+                let own = precedence(exp);
+                self.operand(&ie.left, precedence(&ie.left) < own);
+                self.push(" ");
+                self.token(&ie.operator.to_string());
+                self.push(" ");
+                self.operand(&ie.right, precedence(&ie.right) <= own);
+            }
+            // the value is everything after the `=`
+            Expression::Assign(ae) => {
+                self.token(&ae.name.value);
+                self.push(" ");
+                self.token("=");
+                self.push(" ");
+                self.expression(&ae.value);
+            }
+            Expression::IfExpression(ife) => {
+                self.token("if");
+                self.push(" (");
+                self.expression(&ife.condition);
+                self.push(") ");
+                self.block(&ife.consequence);
+                if let Some(alt) = &ife.alternative {
+                    self.push(" ");
+                    self.token("else");
+                    self.push(" ");
+                    self.block(alt);
+                }
+            }
+            Expression::ForExpression(fe) => {
+                if let Some(label) = &fe.label {
+                    self.token(&label.value);
+                    self.token(":");
+                    self.push(" ");
+                }
+                self.token("for");
+                self.push(" (");
+                for (i, name) in fe.names.iter().enumerate() {
+                    if i != 0 {
+                        self.token(",");
+                        self.push(" ");
+                    }
+                    self.token(&name.value);
+                }
+                self.push(" ");
+                self.token("in");
+                self.push(" ");
+                self.expression(&fe.iterable);
+                self.push(") ");
+                self.block(&fe.body);
+            }
+            Expression::FunctionLiteral(func) => self.function("fn", &func.parameters, &func.body),
+            Expression::MacroLiteral(mac) => self.function("macro", &mac.parameters, &mac.body),
+            Expression::CallExpression(call) => {
+                self.operand(
+                    &call.function,
+                    precedence(&call.function) < Precedence::Call,
+                );
+                self.push("(");
+                self.list(&call.arguments);
+                self.push(")");
+            }
+            Expression::IndexExpression(idx) => {
+                self.operand(&idx.left, precedence(&idx.left) < Precedence::Call);
+                match (&idx.tok, idx.index.as_ref()) {
+                    (Token::Dot, Expression::String(name)) => {
+                        self.token(".");
+                        self.token(&name.value);
+                    }
+                    _ => {
+                        self.token("[");
+                        self.expression(&idx.index);
+                        self.token("]");
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::format::{format, format_grouped};
+
+    #[test]
+    fn test_format() {
+        let tests = [
+            ("", ""),
+            ("let x=1+2*3", "let x = 1 + 2 * 3;\n"),
+            (
+                "((1+2))*3 == -(a-b) - (c-d)",
+                "(1 + 2) * 3 == -(a - b) - (c - d);\n",
+            ),
+            ("(a < b) == (c > d) - e", "a < b == (c > d) - e;\n"),
+            (
+                "(1 - 2) - 3; 1 - (2 - 3); --a",
+                "1 - 2 - 3;\n1 - (2 - 3);\n--a;\n",
+            ),
+            (
+                "(-f)(x)[0]; (a + b)[i](); (f(x))[0]",
+                "(-f)(x)[0];\n(a + b)[i]();\nf(x)[0];\n",
+            ),
+            (
+                "x = (y = 1) + 1; f((x = 2), [(y)])",
+                "x = (y = 1) + 1;\nf(x = 2, [y]);\n",
+            ),
+            ("5 |> add(1) |> -f", "(-f)(add(5, 1));\n"),
+            ("x;y", "x;\ny;\n"),
+            (
+                "let a, b = (1, 2) return a, b",
+                "let a, b = (1, 2);\nreturn (a, b);\n",
+            ),
+            (
+                "const h = {\"a\":1,0x1F:[1,2]}",
+                "const h = {\"a\": 1, 0x1F: [1, 2]};\n",
+            ),
+            ("-a[0](1)", "-a[0](1);\n"),
+            ("string . upper(s)", "string.upper(s);\n"),
+            (
+                "a:for(x in xs){break a}",
+                "a: for (x in xs) {\n    break a;\n};\n",
+            ),
+            ("fn(){}", "fn() {};\n"),
+            ("let x=y=1", "let x = y = 1;\n"),
+            (
+                "for(k,v in h){if(k){continue}break}",
+                "for (k, v in h) {\n    if (k) {\n        continue;\n    };\n    break;\n};\n",
+            ),
+            // strings stay as they are written
+            (
+                r#"let s="\x41\"\u{e9}\u{7}\n"+"A"+"\x41""#,
+                "let s = \"\\x41\\\"\\u{e9}\\u{7}\\n\" + \"A\" + \"\\x41\";\n",
+            ),
+            (r#""\x41" |> f("A")"#, "f(\"\\x41\", \"A\");\n"),
+            (
+                "fn add(a,b){a+b};add(1,2)",
+                "fn add(a, b) {\n    a + b;\n}\nadd(1, 2);\n",
+            ),
+            (
+                "let f = fn(x, y) { if (x > y) { x } else { let z = y; z } };",
+                "let f = fn(x, y) {
+    if (x > y) {
+        x;
+    } else {
+        let z = y;
+        z;
+    };
+};
+",
+            ),
+        ];
+        for (input, exp) in tests {
+            assert_eq!(format(input).as_deref(), Ok(exp), "formatting {:?}", input);
+            assert_eq!(format(exp).as_deref(), Ok(exp), "reformatting {:?}", exp);
+        }
+    }
+
+    #[test]
+    fn test_format_grouped() {
+        let tests = [
+            ("let x=1+2*3", "let x = (1 + (2 * 3));\n"),
+            ("-a[0](1)", "(-(a[0])(1));\n"),
+            ("let x=y=-1 // one", "let x = (y = (-1)); // one\n"),
+        ];
+        for (input, exp) in tests {
+            assert_eq!(
+                format_grouped(input).as_deref(),
+                Ok(exp),
+                "formatting {:?}",
+                input
+            );
+        }
+    }
+
+    #[test]
+    fn test_format_comments() {
+        let tests = [
+            ("// only a comment", "// only a comment\n"),
+            (
+                "// head\nlet x = 1 // one  \n\n  // two\nx",
+                "// head\nlet x = 1; // one\n// two\nx;\n",
+            ),
+            (
+                "let f = fn() { // opens\n    // inside\n    1\n    // last\n} // closes\n// tail",
+                "let f = fn() { // opens
+    // inside
+    1;
+    // last
+}; // closes
+// tail
+",
+            ),
+            ("if (x) {\n// empty\n}", "if (x) {\n    // empty\n};\n"),
+            (
+                "/* head\n   more */ let s = \"/* not one */\"; /* one */\nfn() {\n/* two */ 2 }",
+                "/* head\n   more */\nlet s = \"/* not one */\"; /* one */\nfn() {\n    /* two */\n    2;\n};\n",
+            ),
+            (
+                "let a = [1, // one\n  2, // two\n  // three\n  3];\nlet b = 2;",
+                "let a = [1, 2, 3]; // one\n// two\n// three\nlet b = 2;\n",
+            ),
+        ];
+        for (input, exp) in tests {
+            assert_eq!(format(input).as_deref(), Ok(exp), "formatting {:?}", input);
+            assert_eq!(format(exp).as_deref(), Ok(exp), "reformatting {:?}", exp);
+        }
+    }
+
+    #[test]
+    fn test_format_errors() {
+        assert_eq!(
+            format("let = 1;"),
+            Err(vec![
+                "expected next token to be Token::Ident, got Assign instead".to_owned(),
+                "no prefix parse fn for Assign".to_owned(),
+            ])
+        );
+    }
+}
@@ -0,0 +1,330 @@
+//! Keeping a parse up to date as its source is edited, for an editor or a
+//! language server that reparses on every keystroke.
+//!
+//! A [`Document`] re-lexes an edit only until the tokens line up with the
+//! old ones again, and reparses only the top-level statements that could
+//! have seen a changed token. The rest of the tree is kept as it was:
+//!
+//! ```
+//! use interpreter::incremental::Document;
+//! use interpreter::prelude::*;
+//!
+//! let mut doc = Document::new("let a = 1;\nlet b = 2;\nlet c = 3;");
+//! doc.apply_edit(19..20, "20");
+//! assert_eq!(doc.program().statements[1].string(), "let b = 20;");
+//! assert_eq!(doc.source(), "let a = 1;\nlet b = 20;\nlet c = 3;");
+//! ```
+//!
+//! Whatever the edits, the program and errors are the same a parse of the
+//! whole source from scratch gives with the default [`Lexer`] and
+//! [`Parser`] options.
+
+use std::ops::Range;
+
+use crate::ast::Program;
+use crate::lexer::Lexer;
+use crate::parser::Parser;
+use crate::token::Token;
+
+/// Source text with its tokens and the program they parse to.
+pub struct Document {
+    source: String,
+    /* every token but the final `Token::Eof`, with its byte range */
+    tokens: Vec<Token>,
+    spans: Vec<Range<usize>>,
+    chunks: Vec<Chunk>,
+    /* the errors of the first two tokens, which the parser reads as it is
+     * made, before any chunk */
+    leading_errors: Vec<String>,
+    program: Program,
+}
+
+/* what one turn of the parser's top-level loop did */
+struct Chunk {
+    /* the index of the token the turn started on */
+    start: usize,
+    /* the number of tokens from the start of the document that had been
+     * read when it ended. Only a change to one of those can change it. */
+    reach: usize,
+    has_statement: bool,
+    errors: Vec<String>,
+}
+
+impl Document {
+    pub fn new(source: &str) -> Self {
+        let mut lexer = Lexer::new(source);
+        let mut tokens = Vec::new();
+        let mut spans = Vec::new();
+        loop {
+            let tok = lexer.next_token();
+            if tok == Token::Eof {
+                break;
+            }
+            tokens.push(tok);
+            spans.push(lexer.span());
+        }
+        let mut doc = Document {
+            source: source.to_owned(),
+            tokens,
+            spans,
+            chunks: Vec::new(),
+            leading_errors: Vec::new(),
+            program: Program {
+                statements: Vec::new(),
+            },
+        };
+        let len = doc.tokens.len();
+        doc.reparse(0, len, len);
+        doc
+    }
+
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+
+    /// The tokens of the source, without the final `Token::Eof`.
+    pub fn tokens(&self) -> &[Token] {
+        &self.tokens
+    }
+
+    /// The byte range of each of [`tokens`](Document::tokens).
+    pub fn spans(&self) -> &[Range<usize>] {
+        &self.spans
+    }
+
+    pub fn program(&self) -> &Program {
+        &self.program
+    }
+
+    /// The parse errors, in the order a parse from scratch reports them.
+    pub fn errors(&self) -> impl Iterator<Item = &str> + '_ {
+        let chunks = self.chunks.iter().flat_map(|chunk| chunk.errors.iter());
+        self.leading_errors.iter().chain(chunks).map(String::as_str)
+    }
+
+    /// Replaces the bytes of `range` with `new_text` and brings the tokens
+    /// and the program up to date.
+    ///
+    /// # Panics
+    ///
+    /// If `range` is out of bounds or doesn't start and end on a char
+    /// boundary, like [`String::replace_range`].
+    pub fn apply_edit(&mut self, range: Range<usize>, new_text: &str) {
+        let (changed, old_from, new_from) = self.relex(range, new_text);
+        self.reparse(changed, old_from, new_from);
+    }
+
+    /* lexes the edited text until a token starts where an old token after
+     * the edit did, since lexing from a token on depends only on the text
+     * after it. Returns the index of the first token that may have
+     * changed, and the indices of the first old token kept before and
+     * after the edit. */
+    fn relex(&mut self, range: Range<usize>, new_text: &str) -> (usize, usize, usize) {
+        // the token before the edit may grow into it, or one before that
+        // if the edit is at its start, like `a` in `a+` with `b` put in
+        // before the `+`
+        let before = self.spans.partition_point(|span| span.start < range.start);
+        let changed = before.saturating_sub(1);
+        let from = match before {
+            0 => 0,
+            _ => self.spans[changed].start,
+        };
+        let mut old = self.spans.partition_point(|span| span.start < range.end);
+        let shift = |at: usize| at + new_text.len() - range.len();
+        self.source.replace_range(range.clone(), new_text);
+
+        let mut lexer = Lexer::new(&self.source[from..]);
+        let mut tokens = Vec::new();
+        let mut spans = Vec::new();
+        loop {
+            let tok = lexer.next_token();
+            if tok == Token::Eof {
+                old = self.tokens.len();
+                break;
+            }
+            let span = lexer.span();
+            let span = from + span.start..from + span.end;
+            while old < self.spans.len() && shift(self.spans[old].start) < span.start {
+                old += 1;
+            }
+            if old < self.spans.len() && shift(self.spans[old].start) == span.start {
+                break;
+            }
+            tokens.push(tok);
+            spans.push(span);
+        }
+
+        for span in self.spans[old..].iter_mut() {
+            *span = shift(span.start)..shift(span.end);
+        }
+        let new_from = changed + tokens.len();
+        self.tokens.splice(changed..old, tokens);
+        self.spans.splice(changed..old, spans);
+        (changed, old, new_from)
+    }
+
+    /* reparses from the first statement that read the token at `changed`,
+     * until the parser gets to where an old statement at `old_from` or
+     * later started, which now is at the same place after `new_from`.
+     * From there on the old statements are kept. */
+    fn reparse(&mut self, changed: usize, old_from: usize, new_from: usize) {
+        let first = self.chunks.partition_point(|chunk| chunk.reach <= changed);
+        let start = self.chunks.get(first).map_or(0, |chunk| chunk.start);
+        let offset = self.spans.get(start).map_or(self.source.len(), |s| s.start);
+
+        let mut p = Parser::new(Lexer::new(&self.source[offset..]));
+        let mut chunks = Vec::new();
+        let mut statements = Vec::new();
+        let mut resume = self.chunks.len();
+        // the chunk before read the tokens the parser reads as it's made
+        // too, and has their errors, if there is one
+        if first == 0 {
+            self.leading_errors = p.get_errors().clone();
+        }
+        let mut seen = p.errors_len();
+        while !p.at_eof() {
+            let at = start + p.position();
+            if at >= new_from {
+                let old_start = at - new_from + old_from;
+                let rest = &self.chunks[first..];
+                if let Ok(i) = rest.binary_search_by_key(&old_start, |chunk| chunk.start) {
+                    resume = first + i;
+                    break;
+                }
+            }
+            let stmt = p.parse_top_level();
+            chunks.push(Chunk {
+                start: at,
+                reach: start + p.tokens_read(),
+                has_statement: stmt.is_some(),
+                errors: p.get_errors()[seen..].to_vec(),
+            });
+            seen = p.errors_len();
+            statements.extend(stmt);
+        }
+
+        let count = |chunks: &[Chunk]| chunks.iter().filter(|c| c.has_statement).count();
+        let kept_before = count(&self.chunks[..first]);
+        let kept_after = count(&self.chunks[resume..]);
+        let end = self.program.statements.len() - kept_after;
+        self.program.statements.splice(kept_before..end, statements);
+        for chunk in self.chunks[resume..].iter_mut() {
+            chunk.start = chunk.start - old_from + new_from;
+            chunk.reach = chunk.reach - old_from + new_from;
+        }
+        self.chunks.splice(first..resume, chunks);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::ast::Node;
+    use crate::incremental::Document;
+    use crate::lexer::Lexer;
+    use crate::parser::Parser;
+    use crate::token::Token;
+
+    fn assert_fresh(doc: &Document) {
+        let fresh = Document::new(doc.source());
+        assert_eq!(doc.tokens(), fresh.tokens(), "{:?}", doc.source());
+        assert_eq!(doc.spans(), fresh.spans(), "{:?}", doc.source());
+        let mut p = Parser::new(Lexer::new(doc.source()));
+        let program = p.parse();
+        assert_eq!(
+            doc.program().statements,
+            program.statements,
+            "{:?}",
+            doc.source()
+        );
+        assert_eq!(
+            doc.errors().collect::<Vec<_>>(),
+            *p.get_errors(),
+            "{:?}",
+            doc.source()
+        );
+    }
+
+    #[test]
+    fn test_new() {
+        let doc = Document::new("let a = 1;\n// one\na + 2");
+        assert_eq!(
+            doc.tokens(),
+            [
+                Token::Let,
+                Token::Ident("a".into()),
+                Token::Assign,
+                Token::Int("1".into()),
+                Token::Semicolon,
+                Token::Ident("a".into()),
+                Token::Plus,
+                Token::Int("2".into()),
+            ]
+        );
+        assert_eq!(doc.spans()[5], 18..19);
+        assert_eq!(doc.program().string(), "let a = 1;\n(a + 2)");
+        assert_eq!(doc.errors().count(), 0);
+        assert_fresh(&Document::new(""));
+    }
+
+    #[test]
+    fn test_apply_edit() {
+        let tests = [
+            // (source, range, new text, result)
+            (
+                "let a = 1;\nlet b = 2;",
+                8..9,
+                "10",
+                "let a = 10;\nlet b = 2;",
+            ),
+            (
+                "let a = 1;\nlet b = 2;",
+                10..10,
+                " a",
+                "let a = 1; a\nlet b = 2;",
+            ),
+            ("a+b", 1..1, "c", "ac+b"),
+            ("x = 1", 2..2, "=", "x == 1"),
+            ("let a = 1;", 0..10, "", ""),
+            ("", 0..0, "fn(x) { x }", "fn(x) { x }"),
+            ("let a = 1;\n1x;", 0..11, "", "1x;"),
+            ("\n1x 2y;", 0..0, "[", "[\n1x 2y;"),
+            ("0a", 0..0, "let x = 1;\n", "let x = 1;\n0a"),
+            // a string or comment swallows what follows
+            ("a; b; c;", 1..1, "\"", "a\"; b; c;"),
+            ("a;\nb;\nc;", 0..0, "// ", "// a;\nb;\nc;"),
+            ("// a;\nb;\nc;", 0..3, "", "a;\nb;\nc;"),
+            // errors come and go with the code that has them
+            ("let a = 1;\nlet b = 2;", 4..5, "", "let  = 1;\nlet b = 2;"),
+            ("if (a { 1 };\nb;", 5..5, ")", "if (a) { 1 };\nb;"),
+            (
+                "let f = fn(x) {\nx + 1\n};\nf(2)",
+                14..15,
+                "",
+                "let f = fn(x) \nx + 1\n};\nf(2)",
+            ),
+        ];
+        for (source, range, new_text, exp) in tests {
+            let mut doc = Document::new(source);
+            doc.apply_edit(range, new_text);
+            assert_eq!(doc.source(), exp);
+            assert_fresh(&doc);
+        }
+    }
+
+    #[test]
+    fn test_edits_keep_unaffected_statements() {
+        let source = "let a = 1;\nlet b = 2;\nlet c = 3;\n";
+        let mut doc = Document::new(source);
+        doc.apply_edit(19..20, "20");
+        assert_fresh(&doc);
+        assert_eq!(doc.chunks.len(), 3);
+        assert_eq!(doc.chunks[2].start, 10);
+        assert_eq!(doc.spans()[10], 23..26);
+
+        let source = doc.source().to_owned();
+        for (at, ch) in source.char_indices().rev() {
+            doc.apply_edit(at..at, if ch == ';' { ";;" } else { " " });
+            assert_fresh(&doc);
+        }
+    }
+}
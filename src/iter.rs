@@ -0,0 +1,202 @@
+//! Iterators, which give the elements of a sequence one at a time without
+//! making an array of them. `iter(x)` makes one of an array, a hash or a
+//! range, and `map_iter`, `filter_iter` and `take` make one of another,
+//! working out each element only when it is asked for. A `for` loop goes
+//! through an iterator, and `collect` makes an array of what is left of
+//! it:
+//!
+//! ```
+//! use interpreter::prelude::*;
+//!
+//! let program = Parser::new(Lexer::new(
+//!     "let odd = filter_iter(range(0, 1000000000), fn(x) { mod(x, 2) == 1 });
+//! collect(take(map_iter(odd, fn(x) { x * x }), 3))",
+//! ))
+//! .parse();
+//! let res = eval(&program, &mut Environment::new()).unwrap();
+//! assert_eq!(res.inspect(), "[1, 9, 25]");
+//! ```
+//!
+//! An iterator is used up as it goes: every element is given once, to
+//! whatever asks for the next one, so going through an iterator a second
+//! time gives nothing, and an iterator made of another takes its elements
+//! away from it.
+
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use crate::evaluator::{self, apply_function, is_truthy};
+use crate::object::{Array, Hash, Object};
+
+/// A sequence an [`Iter`] goes through.
+pub trait ObjectIter {
+    /// The next element, or `None` once there are no more. An error,
+    /// like one a function of `map_iter` made, is given as an element.
+    fn next(&mut self) -> Option<Object>;
+}
+
+/// What `Object::Iterator` has: an iterator shared by every copy of the
+/// object, so each element is only given once.
+pub struct Iter {
+    inner: RefCell<Box<dyn ObjectIter>>,
+}
+
+impl Iter {
+    pub fn new(iter: impl ObjectIter + 'static) -> Self {
+        Iter {
+            inner: RefCell::new(Box::new(iter)),
+        }
+    }
+
+    /// The next element. An iterator that is asked for one while it works
+    /// out another, by a function of `map_iter` that uses it, has none.
+    pub fn next(&self) -> Option<Object> {
+        self.inner.try_borrow_mut().ok()?.next()
+    }
+
+    /* the elements up to the first error, or the error */
+    pub(crate) fn collect(&self) -> Result<Vec<Object>, Object> {
+        let mut res = Vec::new();
+        while let Some(obj) = self.next() {
+            if let Object::Error(_) = obj {
+                return Err(obj);
+            }
+            res.push(obj);
+        }
+        Ok(res)
+    }
+}
+
+impl std::fmt::Debug for Iter {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("Iter")
+    }
+}
+
+/* an iterator is only equal to itself */
+impl PartialEq for Iter {
+    fn eq(&self, other: &Self) -> bool {
+        std::ptr::eq(self, other)
+    }
+}
+
+/// An iterator over `obj`: the elements of an array, the pairs of a hash
+/// as arrays of the key and the value, or the integers of a range. An
+/// iterator is itself. `None` for anything else.
+pub fn iter(obj: &Object) -> Option<Rc<Iter>> {
+    let iter = match obj {
+        Object::Iterator(iter) => return Some(iter.clone()),
+        Object::Array(array) => Iter::new(Elements {
+            array: array.clone(),
+            at: 0,
+        }),
+        Object::Hash(hash) => Iter::new(Entries {
+            hash: hash.clone(),
+            at: 0,
+        }),
+        Object::Range(range) => Iter::new(Ints {
+            next: range.start,
+            end: range.end,
+        }),
+        _ => return None,
+    };
+    Some(Rc::new(iter))
+}
+
+struct Ints {
+    next: i64,
+    end: i64,
+}
+
+impl ObjectIter for Ints {
+    fn next(&mut self) -> Option<Object> {
+        if self.next >= self.end {
+            return None;
+        }
+        self.next += 1;
+        Some(Object::Integer(self.next - 1))
+    }
+}
+
+struct Elements {
+    array: Rc<Array>,
+    at: usize,
+}
+
+impl ObjectIter for Elements {
+    fn next(&mut self) -> Option<Object> {
+        let elem = self.array.elements.get(self.at)?.clone();
+        self.at += 1;
+        Some(elem)
+    }
+}
+
+struct Entries {
+    hash: Rc<Hash>,
+    at: usize,
+}
+
+impl ObjectIter for Entries {
+    fn next(&mut self) -> Option<Object> {
+        let (key, value) = self.hash.pairs().get(self.at)?.clone();
+        self.at += 1;
+        Some(Object::Array(Rc::new(Array {
+            elements: vec![key, value],
+            frozen: false,
+        })))
+    }
+}
+
+/* what `f` gives for the elements of `inner` */
+pub(crate) struct Mapped {
+    pub(crate) inner: Rc<Iter>,
+    pub(crate) f: Object,
+}
+
+impl ObjectIter for Mapped {
+    fn next(&mut self) -> Option<Object> {
+        let elem = self.inner.next()?;
+        if let Object::Error(_) = elem {
+            return Some(elem);
+        }
+        Some(apply_function(&self.f, &[elem]).unwrap_or(evaluator::NULL))
+    }
+}
+
+/* the elements of `inner` that `f` gives something truthy for */
+pub(crate) struct Filtered {
+    pub(crate) inner: Rc<Iter>,
+    pub(crate) f: Object,
+}
+
+impl ObjectIter for Filtered {
+    fn next(&mut self) -> Option<Object> {
+        loop {
+            let elem = self.inner.next()?;
+            if let Object::Error(_) = elem {
+                return Some(elem);
+            }
+            match apply_function(&self.f, std::slice::from_ref(&elem)) {
+                Some(err @ Object::Error(_)) => return Some(err),
+                Some(keep) if is_truthy(&keep) => return Some(elem),
+                _ => {}
+            }
+        }
+    }
+}
+
+/* the first `left` elements of `inner`, taking no more from it */
+pub(crate) struct Taken {
+    pub(crate) inner: Rc<Iter>,
+    pub(crate) left: usize,
+}
+
+impl ObjectIter for Taken {
+    fn next(&mut self) -> Option<Object> {
+        if self.left == 0 {
+            return None;
+        }
+        self.left -= 1;
+        self.inner.next()
+    }
+}
@@ -1,30 +1,162 @@
-use crate::token::Token;
+use crate::token::{Token, TokenKind};
 use crate::util::{is_digit, is_letter, lookup_ident};
 
 pub struct Lexer {
-    input: std::sync::Arc<str>,
+    input: String,
+    /* byte offset of the char after `ch` */
     position: usize,
+    /* byte offset of `ch` */
+    ch_offset: usize,
     ch: char,
+    /* where the last token returned started and ended */
+    span: std::ops::Range<usize>,
+    /* longest identifier or string literal accepted, in chars */
+    max_literal_len: Option<usize>,
+    comment_prefix: String,
+    block_comments: bool,
+    keep_comments: bool,
+    errors: Vec<String>,
 }
 
+/* the characters that begin a token, other than letters and digits */
+const TOKEN_CHARS: &str = "\"=!+-/*<>(){}[],:;|";
+
 impl Lexer {
+    /// Copies `input`, so the lexer doesn't borrow it. Use
+    /// [`from_string`](Lexer::from_string) to hand over a `String` without
+    /// the copy.
     pub fn new(input: &str) -> Self {
+        Self::from_string(input.to_owned())
+    }
+
+    /// Takes ownership of `input`.
+    pub fn from_string(input: String) -> Self {
         let mut l = Lexer {
-            input: input.into(),
+            input,
             position: 0,
+            ch_offset: 0,
             ch: '\0',
+            span: 0..0,
+            max_literal_len: None,
+            comment_prefix: "//".to_owned(),
+            block_comments: false,
+            keep_comments: false,
+            errors: Vec::new(),
         };
         l.read_char();
         l
     }
 
+    /// Caps the length of identifiers and string literals. A longer one is
+    /// skipped without being buffered and lexed as `Token::Illegal`, with
+    /// the reason recorded in the lexer's errors. Unlimited by default.
+    pub fn with_max_literal_len(mut self, max: usize) -> Self {
+        self.max_literal_len = Some(max);
+        self
+    }
+
+    /// Makes `prefix` start a comment that runs to the end of the line,
+    /// instead of `//`, e.g. `#` for embedding in a shell-like language.
+    /// `//` is then a pair of slashes again.
+    ///
+    /// # Panics
+    ///
+    /// If `prefix` is empty or starts with a character that starts a token
+    /// or whitespace, since then it would change how valid code lexes. `//`
+    /// is the one exception: valid code never has a `/` after another.
+    pub fn with_line_comment_prefix(mut self, prefix: &str) -> Self {
+        let first = prefix.chars().next().expect("comment prefix is empty");
+        assert!(
+            prefix == "//"
+                || !(is_letter(first)
+                    || is_digit(first)
+                    || first.is_whitespace()
+                    || TOKEN_CHARS.contains(first)),
+            "comment prefix {:?} clashes with the tokens",
+            prefix
+        );
+        self.comment_prefix = prefix.to_owned();
+        self
+    }
+
+    /// Makes `/*` start a comment that runs to the next `*/`. Monkey has no
+    /// block comments, so `/*` is a `/` and a `*` by default, which no valid
+    /// program has next to each other.
+    pub fn with_block_comments(mut self) -> Self {
+        self.block_comments = true;
+        self
+    }
+
+    /// Returns comments as `Token::Comment` instead of skipping them, for
+    /// tools like the formatter that need to keep them. The parser skips
+    /// them either way.
+    pub fn with_comments(mut self) -> Self {
+        self.keep_comments = true;
+        self
+    }
+
+    /// Starts over on `input`, keeping the input buffer and the configured
+    /// options so a lexer can be reused without reallocating.
+    pub fn reset(&mut self, input: &str) {
+        self.input.clear();
+        self.input.push_str(input);
+        self.position = 0;
+        self.ch_offset = 0;
+        self.ch = '\0';
+        self.span = 0..0;
+        self.errors.clear();
+        self.read_char();
+    }
+
+    /// The byte range of the last token `next_token` returned.
+    pub fn span(&self) -> std::ops::Range<usize> {
+        self.span.clone()
+    }
+
+    /// Length of the input in bytes.
+    pub fn input_len(&self) -> usize {
+        self.input.len()
+    }
+
+    /// Hands over the errors recorded so far. Every error comes with a
+    /// `Token::Illegal` in the token stream.
+    pub fn take_errors(&mut self) -> Vec<String> {
+        std::mem::take(&mut self.errors)
+    }
+
     pub fn next_token(&mut self) -> Token {
+        let tok = self.read_token();
+        self.span.end = self.ch_offset;
+        tok
+    }
+
+    fn read_token(&mut self) -> Token {
         let tok: Token;
-        self.skip_whitespace();
+        loop {
+            self.skip_whitespace();
+            self.span.start = self.ch_offset;
+            let rest = &self.input[self.ch_offset..];
+            if self.block_comments && rest.starts_with("/*") {
+                if !self.skip_block_comment() {
+                    self.errors.push("unterminated block comment".to_owned());
+                    return Token::Illegal;
+                }
+            } else if rest.starts_with(&self.comment_prefix) {
+                self.skip_comment();
+            } else {
+                break;
+            }
+            if self.keep_comments {
+                let comment = self.input[self.span.start..self.ch_offset].trim_end();
+                return Token::Comment(comment.into());
+            }
+        }
         match self.ch {
             '"' => {
-                let str = self.read_string();
-                tok = Token::String(str.into());
+                tok = match self.read_string() {
+                    Some(str) => Token::String(str.into()),
+                    None => Token::Illegal,
+                };
             }
             '=' => {
                 if self.peek_char() == '=' {
@@ -57,15 +189,20 @@ impl Lexer {
             ',' => tok = Token::Comma,
             ':' => tok = Token::Colon,
             ';' => tok = Token::Semicolon,
-            '\0' => tok = Token::Eof,
+            '.' => tok = Token::Dot,
+            '|' if self.peek_char() == '>' => {
+                tok = Token::Pipe;
+                self.read_char();
+            }
+            '\0' if self.at_end() => tok = Token::Eof,
             _ => {
                 if is_letter(self.ch) {
-                    let str = self.read_ident();
-                    return lookup_ident(&str);
+                    return match self.read_ident() {
+                        Some(str) => lookup_ident(&str),
+                        None => Token::Illegal,
+                    };
                 } else if is_digit(self.ch) {
-                    let str = self.read_number();
-                    tok = Token::Int(str.into());
-                    return tok;
+                    return self.read_number();
                 } else {
                     tok = Token::Illegal;
                 }
@@ -76,71 +213,367 @@ impl Lexer {
     }
 
     fn read_char(&mut self) {
-        if self.position >= self.input.len() {
-            self.ch = '\0';
-        } else {
-            match self.input.chars().nth(self.position) {
-                Some(ch) => self.ch = ch,
-                None => self.ch = '\0',
+        self.ch_offset = self.position;
+        match self.input[self.position..].chars().next() {
+            Some(ch) => {
+                self.ch = ch;
+                self.position += ch.len_utf8();
             }
+            None => self.ch = '\0',
         }
-        self.position += 1;
     }
 
-    fn read_ident(&mut self) -> String {
-        let mut res = String::new();
-        while is_letter(self.ch) {
-            res.push(self.ch);
+    /* up to the newline, which is left for skip_whitespace */
+    fn skip_comment(&mut self) {
+        while self.ch != '\n' && !self.at_end() {
             self.read_char();
         }
-        res
     }
 
-    fn read_number(&mut self) -> String {
+    // past the closing `*/`, false if the input ends first; block comments
+    // don't nest
+    fn skip_block_comment(&mut self) -> bool {
+        self.read_char();
+        self.read_char();
+        loop {
+            match self.ch {
+                _ if self.at_end() => return false,
+                '*' if self.peek_char() == '/' => {
+                    self.read_char();
+                    self.read_char();
+                    return true;
+                }
+                _ => self.read_char(),
+            }
+        }
+    }
+
+    fn read_ident(&mut self) -> Option<String> {
         let mut res = String::new();
-        while is_digit(self.ch) {
-            res.push(self.ch);
+        let mut len = 0;
+        // only the first char can't be a digit
+        while is_letter(self.ch) || is_digit(self.ch) {
+            if !self.exceeds_max_literal_len(len) {
+                res.push(self.ch);
+            }
+            len += 1;
             self.read_char();
         }
-        res
+        if self.exceeds_max_literal_len(len) {
+            self.literal_too_long("identifier");
+            return None;
+        }
+        Some(res)
     }
 
-    fn read_string(&mut self) -> String {
+    /* the literal as written, the parser works out its value. A fraction
+     * or an `f` after the digits makes it a float and an `i` an integer.
+     * Any other letters or digits run on are an unknown suffix, so `5q`
+     * isn't a `5` and a `q`. */
+    fn read_number(&mut self) -> Token {
+        let start = self.ch_offset;
+        let hex = self.ch == '0' && matches!(self.peek_char(), 'x' | 'X');
+        let mut fraction = false;
+        if hex {
+            self.read_char();
+            self.read_char();
+            while self.ch.is_ascii_hexdigit() || self.ch == '_' {
+                self.read_char();
+            }
+        } else {
+            self.skip_digits();
+            if self.ch == '.' && is_digit(self.peek_char()) {
+                fraction = true;
+                self.read_char();
+                self.skip_digits();
+            }
+        }
+        let suffix_start = self.ch_offset;
+        while is_letter(self.ch) || is_digit(self.ch) {
+            self.read_char();
+        }
+        let literal = &self.input[start..self.ch_offset];
+        match &self.input[suffix_start..self.ch_offset] {
+            "" | "i" if !fraction => Token::Int(literal.into()),
+            "" | "f" if !hex => Token::Float(literal.into()),
+            suffix => {
+                let want = match (hex, fraction) {
+                    (true, _) => "i",
+                    (_, true) => "f",
+                    _ => "i or f",
+                };
+                let e = format!(
+                    "unknown suffix {} on number {}, want {}",
+                    suffix, literal, want
+                );
+                self.errors.push(e);
+                Token::Illegal
+            }
+        }
+    }
+
+    fn skip_digits(&mut self) {
+        while is_digit(self.ch) || self.ch == '_' {
+            self.read_char();
+        }
+    }
+
+    /* the value of the string literal with its escapes replaced. A bad
+     * escape is reported and the rest of the literal skipped, so what
+     * follows it lexes as usual. */
+    fn read_string(&mut self) -> Option<String> {
         let mut res = String::new();
+        let mut len = 0;
+        let mut error = None;
         self.read_char();
         loop {
-            if self.ch == '"' || self.ch == '\0' {
+            if self.ch == '"' || self.at_end() {
                 break;
             }
-            res.push(self.ch);
+            let ch = match self.ch {
+                '\\' => match self.read_escape() {
+                    Ok(ch) => ch,
+                    Err(e) => {
+                        error.get_or_insert(e);
+                        continue;
+                    }
+                },
+                ch => ch,
+            };
+            if !self.exceeds_max_literal_len(len) {
+                res.push(ch);
+            }
+            len += 1;
             self.read_char();
         }
+        if let Some(e) = error {
+            self.errors.push(e);
+            return None;
+        }
+        if self.exceeds_max_literal_len(len) {
+            self.literal_too_long("string literal");
+            return None;
+        }
+        Some(res)
+    }
+
+    /* from the `\` to the last char of the escape, what it stands for. On
+     * an error `ch` is the first char that isn't part of the escape. */
+    fn read_escape(&mut self) -> Result<char, String> {
+        let start = self.ch_offset;
+        self.read_char();
+        let res = match self.ch {
+            'n' => Ok('\n'),
+            't' => Ok('\t'),
+            'r' => Ok('\r'),
+            '0' => Ok('\0'),
+            '\\' => Ok('\\'),
+            '"' => Ok('"'),
+            'x' => {
+                let digits = self.read_hex_digits(2);
+                match u8::from_str_radix(&digits, 16) {
+                    Ok(byte @ 0..=0x7F) if digits.len() == 2 => Ok(byte as char),
+                    Ok(_) if digits.len() == 2 => Err(format!(
+                        "hex escape {} is out of range, use \\u{{...}} for non-ASCII",
+                        &self.input[start..self.position]
+                    )),
+                    _ => Err(format!(
+                        "invalid hex escape {}, want two hex digits",
+                        &self.input[start..self.position]
+                    )),
+                }
+            }
+            'u' if self.peek_char() == '{' => {
+                self.read_char();
+                let digits = self.read_hex_digits(6);
+                if self.peek_char() != '}' || digits.is_empty() {
+                    self.read_char();
+                    return Err(format!(
+                        "invalid unicode escape {}, want 1 to 6 hex digits in braces",
+                        &self.input[start..self.ch_offset]
+                    ));
+                }
+                self.read_char();
+                let code = u32::from_str_radix(&digits, 16).expect("hex digits");
+                char::from_u32(code).ok_or_else(|| {
+                    format!(
+                        "invalid unicode escape {}, {:X} is not a code point",
+                        &self.input[start..self.position],
+                        code
+                    )
+                })
+            }
+            _ if self.at_end() => return Err("unterminated escape at end of input".to_owned()),
+            _ => Err(format!(
+                "unknown escape {}",
+                &self.input[start..self.position]
+            )),
+        };
+        if res.is_err() {
+            self.read_char();
+        }
+        res
+    }
+
+    /* up to `max` hex digits after `ch`, leaving `ch` on the last */
+    fn read_hex_digits(&mut self, max: usize) -> String {
+        let mut res = String::new();
+        while res.len() < max && self.peek_char().is_ascii_hexdigit() {
+            self.read_char();
+            res.push(self.ch);
+        }
         res
     }
 
+    fn exceeds_max_literal_len(&self, len: usize) -> bool {
+        match self.max_literal_len {
+            Some(max) => len > max,
+            None => false,
+        }
+    }
+
+    fn literal_too_long(&mut self, what: &str) {
+        let max = self.max_literal_len.unwrap_or_default();
+        self.errors
+            .push(format!("{} exceeds max length of {}", what, max));
+    }
+
     fn skip_whitespace(&mut self) {
         while self.ch == ' ' || self.ch == '\t' || self.ch == '\n' || self.ch == '\r' {
             self.read_char();
         }
     }
 
+    /* `ch` is `'\0'` past the end too, so this tells the end from a NUL in
+     * the input, which is an illegal token */
+    fn at_end(&self) -> bool {
+        self.ch_offset == self.input.len()
+    }
+
     fn peek_char(&self) -> char {
-        if self.position >= self.input.len() {
-            '\0'
-        } else {
-            match self.input.chars().nth(self.position) {
-                Some(ch) => ch,
-                None => '\0',
+        self.input[self.position..].chars().next().unwrap_or('\0')
+    }
+}
+
+/// Lexes `src` and counts how often each kind of token occurs. `Eof` is
+/// not counted.
+pub fn token_histogram(src: &str) -> std::collections::HashMap<TokenKind, usize> {
+    let mut res = std::collections::HashMap::new();
+    let mut l = Lexer::new(src);
+    loop {
+        let tok = l.next_token();
+        if tok == Token::Eof {
+            break;
+        }
+        *res.entry(tok.kind()).or_insert(0) += 1;
+    }
+    res
+}
+
+/// The byte range of every token of `src` the way a [`Parser`] reads
+/// them, without comments and with the final `Token::Eof`, so the indices
+/// of [`Parser::error_tokens`] point into it.
+///
+/// Block comments are skipped whether or not the parser's lexer was made
+/// [`with_block_comments`](Lexer::with_block_comments). No code has a `/*`,
+/// so that only changes the spans of a source the parser rejects.
+///
+/// [`Parser`]: crate::parser::Parser
+/// [`Parser::error_tokens`]: crate::parser::Parser::error_tokens
+pub fn token_spans(src: &str) -> Vec<std::ops::Range<usize>> {
+    let mut res = Vec::new();
+    let mut l = Lexer::new(src).with_block_comments();
+    loop {
+        let tok = l.next_token();
+        if !matches!(tok, Token::Comment(_)) {
+            res.push(l.span());
+        }
+        if tok == Token::Eof {
+            return res;
+        }
+    }
+}
+
+/// The line and column of the byte `offset` of `src`, both counted from 1.
+/// Columns count chars, not bytes.
+pub fn line_col(src: &str, offset: usize) -> (usize, usize) {
+    let before = &src[..offset];
+    let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+    let line = before.matches('\n').count() + 1;
+    (line, before[line_start..].chars().count() + 1)
+}
+
+/// Removes the `//` and `/* */` comments from `src`, leaving everything
+/// else where it was. A block comment becomes the newlines it spanned, or a space if it
+/// didn't span any, so the tokens on either side stay apart. Text that only
+/// looks like a comment, inside a string literal, is kept.
+pub fn strip_comments(src: &str) -> String {
+    let mut res = String::with_capacity(src.len());
+    let mut l = Lexer::new(src).with_block_comments().with_comments();
+    let mut copied = 0;
+    loop {
+        let tok = l.next_token();
+        if tok == Token::Eof {
+            break;
+        }
+        let Token::Comment(_) = tok else {
+            continue;
+        };
+        let span = l.span();
+        res.push_str(&src[copied..span.start]);
+        let comment = &src[span.clone()];
+        if comment.starts_with("/*") {
+            match comment.matches('\n').count() {
+                0 => res.push(' '),
+                lines => res.extend(std::iter::repeat_n('\n', lines)),
             }
         }
+        copied = span.end;
     }
+    res.push_str(&src[copied..]);
+    res
+}
+
+/// Whether `src` could be a whole program: every `(`, `[` and `{` is
+/// closed and every string literal is terminated. This only scans the
+/// characters, so it's much cheaper than parsing. Stray closing brackets
+/// count as complete, the parser reports those.
+pub fn is_complete(src: &str) -> bool {
+    let mut depth: i64 = 0;
+    let mut in_string = false;
+    let mut chars = src.chars().peekable();
+    while let Some(ch) = chars.next() {
+        if in_string {
+            match ch {
+                '"' => in_string = false,
+                '\\' => {
+                    chars.next();
+                }
+                _ => {}
+            }
+            continue;
+        }
+        match ch {
+            '"' => in_string = true,
+            '/' if chars.peek() == Some(&'/') => {
+                chars.by_ref().take_while(|&ch| ch != '\n').for_each(drop);
+            }
+
+            '(' | '[' | '{' => depth += 1,
+            ')' | ']' | '}' => depth -= 1,
+            _ => {}
+        }
+    }
+    !in_string && depth <= 0
 }
 
 #[cfg(test)]
 mod test {
 
-    use crate::lexer::Lexer;
-    use crate::token::Token;
+    use crate::lexer::{
+        is_complete, line_col, strip_comments, token_histogram, token_spans, Lexer,
+    };
+    use crate::token::{Token, TokenKind};
 
     #[test]
     fn test_next_token() {
@@ -163,8 +596,9 @@ if (5 < 10) {
 \"foo bar\"
 [1, 2];
 {\"foo\": \"bar\"}
+macro(x, y) { x + y; };
 ";
-        let mut l = Lexer::new(&input);
+        let mut l = Lexer::new(input);
         let exps = vec![
             Token::Let,
             Token::Ident("five".into()),
@@ -252,6 +686,19 @@ if (5 < 10) {
             Token::Colon,
             Token::String("bar".into()),
             Token::RSquirly,
+            Token::Macro,
+            Token::LParen,
+            Token::Ident("x".into()),
+            Token::Comma,
+            Token::Ident("y".into()),
+            Token::RParen,
+            Token::LSquirly,
+            Token::Ident("x".into()),
+            Token::Plus,
+            Token::Ident("y".into()),
+            Token::Semicolon,
+            Token::RSquirly,
+            Token::Semicolon,
             Token::Eof,
         ];
         for exp in exps.iter() {
@@ -259,4 +706,417 @@ if (5 < 10) {
             assert_eq!(tok, *exp);
         }
     }
+
+    #[test]
+    fn test_identifiers_with_digits() {
+        let mut l = Lexer::new("_1 x2y 3z");
+        let exps = [
+            Token::Ident("_1".into()),
+            Token::Ident("x2y".into()),
+            // a number can't run into a name
+            Token::Illegal,
+            Token::Eof,
+        ];
+        for exp in exps.iter() {
+            assert_eq!(l.next_token(), *exp);
+        }
+        assert_eq!(
+            l.take_errors(),
+            ["unknown suffix z on number 3z, want i or f"]
+        );
+    }
+
+    #[test]
+    fn test_from_string_outlives_source() {
+        let mut l = {
+            let input = format!("let {} = {};", "x", 1);
+            Lexer::from_string(input)
+        };
+        let exps = [
+            Token::Let,
+            Token::Ident("x".into()),
+            Token::Assign,
+            Token::Int("1".into()),
+            Token::Semicolon,
+            Token::Eof,
+        ];
+        for exp in exps.iter() {
+            assert_eq!(l.next_token(), *exp);
+        }
+    }
+
+    #[test]
+    fn test_max_literal_len() {
+        let long = "a".repeat(100_000);
+        let input = format!("let {} = \"{}\"; x", long, long);
+
+        let mut l = Lexer::new(&input).with_max_literal_len(255);
+        let exps = [
+            Token::Let,
+            Token::Illegal,
+            Token::Assign,
+            Token::Illegal,
+            Token::Semicolon,
+            Token::Ident("x".into()),
+            Token::Eof,
+        ];
+        for exp in exps.iter() {
+            assert_eq!(l.next_token(), *exp);
+        }
+        assert_eq!(
+            l.take_errors(),
+            vec![
+                "identifier exceeds max length of 255",
+                "string literal exceeds max length of 255"
+            ]
+        );
+
+        let mut l = Lexer::new(&input);
+        assert_eq!(l.next_token(), Token::Let);
+        assert_eq!(l.next_token(), Token::Ident(long.as_str().into()));
+        assert!(l.take_errors().is_empty());
+    }
+
+    #[test]
+    fn test_max_literal_len_boundary() {
+        let mut l = Lexer::new("abc abcd \"abc\"").with_max_literal_len(3);
+        assert_eq!(l.next_token(), Token::Ident("abc".into()));
+        assert_eq!(l.next_token(), Token::Illegal);
+        assert_eq!(l.next_token(), Token::String("abc".into()));
+        assert_eq!(l.next_token(), Token::Eof);
+    }
+
+    #[test]
+    fn test_string_escapes() {
+        let tests = [
+            (r#""\x41""#, "A"),
+            (r#""\u{1F600}""#, "\u{1F600}"),
+            (r#""\u{e9}t\u{E9}""#, "été"),
+            (r#""a\tb\nc\r\0""#, "a\tb\nc\r\0"),
+            (r#""say \"hi\" \\ bye""#, "say \"hi\" \\ bye"),
+            (r#""\x7f\x00""#, "\x7f\0"),
+        ];
+        for (input, exp) in tests {
+            let mut l = Lexer::new(input);
+            assert_eq!(l.next_token(), Token::String(exp.into()), "{}", input);
+            assert_eq!(l.next_token(), Token::Eof, "{}", input);
+            assert!(l.take_errors().is_empty(), "{}", input);
+        }
+
+        let tests = [
+            (
+                r#""\u{110000}""#,
+                "invalid unicode escape \\u{110000}, 110000 is not a code point",
+            ),
+            (
+                r#""\u{D800}""#,
+                "invalid unicode escape \\u{D800}, D800 is not a code point",
+            ),
+            (
+                r#""\u{12x}""#,
+                "invalid unicode escape \\u{12, want 1 to 6 hex digits in braces",
+            ),
+            (
+                r#""\u{}""#,
+                "invalid unicode escape \\u{, want 1 to 6 hex digits in braces",
+            ),
+            (r#""\x4G""#, "invalid hex escape \\x4, want two hex digits"),
+            (r#""\x""#, "invalid hex escape \\x, want two hex digits"),
+            (
+                r#""\xFF""#,
+                "hex escape \\xFF is out of range, use \\u{...} for non-ASCII",
+            ),
+            (r#""\q""#, "unknown escape \\q"),
+            (r#""\u1234""#, "unknown escape \\u"),
+        ];
+        for (input, exp) in tests {
+            // the rest of the literal is skipped, and what follows lexes
+            let mut l = Lexer::new(&format!("{} x", input));
+            assert_eq!(l.next_token(), Token::Illegal, "{}", input);
+            assert_eq!(l.take_errors(), [exp], "{}", input);
+            assert_eq!(l.next_token(), Token::Ident("x".into()), "{}", input);
+        }
+
+        // only the first bad escape of a literal is reported
+        let mut l = Lexer::new(r#""\q \u{110000}""#);
+        assert_eq!(l.next_token(), Token::Illegal);
+        assert_eq!(l.take_errors(), ["unknown escape \\q"]);
+        assert!(is_complete(r#"let s = "a \" b";"#));
+        assert!(!is_complete(r#"let s = "a \";"#));
+    }
+
+    #[test]
+    fn test_number_spellings() {
+        let mut l = Lexer::new("0xFF 0X1a 1_000 0x 12.5 1_000.000_1 1.x");
+        let exps = [
+            Token::Int("0xFF".into()),
+            Token::Int("0X1a".into()),
+            Token::Int("1_000".into()),
+            Token::Int("0x".into()),
+            Token::Float("12.5".into()),
+            Token::Float("1_000.000_1".into()),
+            // without a digit after it the dot isn't part of the number
+            Token::Int("1".into()),
+            Token::Dot,
+            Token::Ident("x".into()),
+            Token::Eof,
+        ];
+        for exp in exps.iter() {
+            assert_eq!(l.next_token(), *exp);
+        }
+    }
+
+    #[test]
+    fn test_number_suffixes() {
+        let mut l = Lexer::new("5i 5f 5.0f 0xFFi 1_000i 2_f");
+        let exps = [
+            (Token::Int("5i".into()), 0..2),
+            (Token::Float("5f".into()), 3..5),
+            (Token::Float("5.0f".into()), 6..10),
+            (Token::Int("0xFFi".into()), 11..16),
+            (Token::Int("1_000i".into()), 17..23),
+            (Token::Float("2_f".into()), 24..27),
+            (Token::Eof, 27..27),
+        ];
+        for (exp, span) in exps {
+            assert_eq!(l.next_token(), exp);
+            assert_eq!(l.span(), span);
+        }
+        assert!(l.take_errors().is_empty());
+
+        let tests = [
+            ("5q", "unknown suffix q on number 5q, want i or f"),
+            ("12ab", "unknown suffix ab on number 12ab, want i or f"),
+            ("5if", "unknown suffix if on number 5if, want i or f"),
+            ("5.0i", "unknown suffix i on number 5.0i, want f"),
+            ("1.5e3", "unknown suffix e3 on number 1.5e3, want f"),
+            // an `f` is a hex digit
+            ("0xFFfz", "unknown suffix z on number 0xFFfz, want i"),
+            ("0x1g", "unknown suffix g on number 0x1g, want i"),
+        ];
+        for (input, exp) in tests {
+            // the whole literal is skipped, and what follows lexes
+            let mut l = Lexer::new(&format!("{} x", input));
+            assert_eq!(l.next_token(), Token::Illegal, "{}", input);
+            assert_eq!(l.span(), 0..input.len(), "{}", input);
+            assert_eq!(l.take_errors(), [exp], "{}", input);
+            assert_eq!(l.next_token(), Token::Ident("x".into()), "{}", input);
+        }
+    }
+
+    #[test]
+    fn test_comments() {
+        let input = "let x = 1; // one  \n// two\nx / 2 //\n";
+        let exps = [
+            (Token::Let, 0..3),
+            (Token::Ident("x".into()), 4..5),
+            (Token::Assign, 6..7),
+            (Token::Int("1".into()), 8..9),
+            (Token::Semicolon, 9..10),
+            (Token::Comment("// one".into()), 11..19),
+            (Token::Comment("// two".into()), 20..26),
+            (Token::Ident("x".into()), 27..28),
+            (Token::Slash, 29..30),
+            (Token::Int("2".into()), 31..32),
+            (Token::Comment("//".into()), 33..35),
+            (Token::Eof, 36..36),
+        ];
+        let mut l = Lexer::new(input).with_comments();
+        for (tok, span) in exps.iter() {
+            assert_eq!(l.next_token(), *tok);
+            assert_eq!(l.span(), *span, "span of {:?}", tok);
+        }
+
+        // without `with_comments` they are skipped
+        let mut l = Lexer::new(input);
+        let toks: Vec<_> = std::iter::repeat_with(|| l.next_token())
+            .take_while(|tok| *tok != Token::Eof)
+            .collect();
+        let exps: Vec<_> = exps
+            .into_iter()
+            .map(|(tok, _)| tok)
+            .filter(|tok| !matches!(tok, Token::Comment(_) | Token::Eof))
+            .collect();
+        assert_eq!(toks, exps);
+    }
+
+    #[test]
+    fn test_block_comments() {
+        let input = "a /* one */ b/*\ntwo*/c /* /* ** */ d /*/ e */ f";
+        let exps = [
+            (Token::Ident("a".into()), 0..1),
+            (Token::Comment("/* one */".into()), 2..11),
+            (Token::Ident("b".into()), 12..13),
+            (Token::Comment("/*\ntwo*/".into()), 13..21),
+            (Token::Ident("c".into()), 21..22),
+            // they don't nest
+            (Token::Comment("/* /* ** */".into()), 23..34),
+            (Token::Ident("d".into()), 35..36),
+            (Token::Comment("/*/ e */".into()), 37..45),
+            (Token::Ident("f".into()), 46..47),
+            (Token::Eof, 47..47),
+        ];
+        let mut l = Lexer::new(input).with_block_comments().with_comments();
+        for (tok, span) in exps.iter() {
+            assert_eq!(l.next_token(), *tok);
+            assert_eq!(l.span(), *span, "span of {:?}", tok);
+        }
+
+        let mut l = Lexer::new("x /* never closed").with_block_comments();
+        assert_eq!(l.next_token(), Token::Ident("x".into()));
+        assert_eq!(l.next_token(), Token::Illegal);
+        assert_eq!(l.take_errors(), ["unterminated block comment"]);
+        assert_eq!(l.next_token(), Token::Eof);
+    }
+
+    #[test]
+    fn test_nul() {
+        // a NUL is a char like any other, the input only ends at its end
+        let input = "a\0b // c\0d\n\"e\0f\"";
+        let exps = [
+            Token::Ident("a".into()),
+            Token::Illegal,
+            Token::Ident("b".into()),
+            Token::Comment("// c\0d".into()),
+            Token::String("e\0f".into()),
+            Token::Eof,
+            Token::Eof,
+        ];
+        let mut l = Lexer::new(input).with_comments();
+        for tok in exps {
+            assert_eq!(l.next_token(), tok);
+        }
+    }
+
+    #[test]
+    fn test_strip_comments() {
+        let tests = [
+            ("let x = 1; // one\nx", "let x = 1; \nx"),
+            ("a/* b */c", "a c"),
+            ("a /* b\n\n */ c", "a \n\n c"),
+            ("\"// not a comment\" // a comment", "\"// not a comment\" "),
+            ("\"/* kept */\" /* gone */", "\"/* kept */\"  "),
+            ("// only", ""),
+            ("no comments", "no comments"),
+        ];
+        for (input, exp) in tests {
+            assert_eq!(strip_comments(input), exp, "stripping {:?}", input);
+        }
+    }
+
+    #[test]
+    fn test_line_comment_prefix() {
+        let input = "x # y\nx // y";
+        let toks = |mut l: Lexer| -> Vec<Token> {
+            std::iter::repeat_with(|| l.next_token())
+                .take_while(|tok| *tok != Token::Eof)
+                .collect()
+        };
+        let x = || Token::Ident("x".into());
+        let y = || Token::Ident("y".into());
+        assert_eq!(toks(Lexer::new(input)), [x(), Token::Illegal, y(), x()]);
+        assert_eq!(
+            toks(Lexer::new(input).with_line_comment_prefix("#")),
+            [x(), x(), Token::Slash, Token::Slash, y()]
+        );
+        assert_eq!(
+            toks(
+                Lexer::new("a -- b\n--\n")
+                    .with_line_comment_prefix("%%")
+                    .with_comments()
+            ),
+            [
+                Token::Ident("a".into()),
+                Token::Minus,
+                Token::Minus,
+                Token::Ident("b".into()),
+                Token::Minus,
+                Token::Minus,
+            ]
+        );
+        let mut l = Lexer::new("1 %% c\n")
+            .with_line_comment_prefix("%%")
+            .with_comments();
+        assert_eq!(l.next_token(), Token::Int("1".into()));
+        assert_eq!(l.next_token(), Token::Comment("%% c".into()));
+
+        // the prefix survives a reset
+        let mut l = Lexer::new("").with_line_comment_prefix("#");
+        l.reset("# only a comment");
+        assert_eq!(l.next_token(), Token::Eof);
+    }
+
+    #[test]
+    fn test_line_comment_prefix_clashes() {
+        for prefix in ["", "-", "--", "x", "1", " #", "\""] {
+            let res = std::panic::catch_unwind(|| Lexer::new("").with_line_comment_prefix(prefix));
+            assert!(res.is_err(), "{:?} was accepted", prefix);
+        }
+    }
+
+    #[test]
+    fn test_token_histogram() {
+        let hist = token_histogram("let x = 5; let y = x + 10; y");
+        assert_eq!(hist[&TokenKind::Let], 2);
+        assert_eq!(hist[&TokenKind::Ident], 4);
+        assert_eq!(hist[&TokenKind::Int], 2);
+        assert_eq!(hist[&TokenKind::Assign], 2);
+        assert_eq!(hist[&TokenKind::Plus], 1);
+        assert_eq!(hist[&TokenKind::Semicolon], 2);
+        assert_eq!(hist.values().sum::<usize>(), 13);
+        assert!(!hist.contains_key(&TokenKind::Eof));
+
+        assert!(token_histogram("").is_empty());
+    }
+
+    #[test]
+    fn test_token_spans() {
+        let src = "let x = 1; // one\n  x";
+        assert_eq!(
+            token_spans(src),
+            [0..3, 4..5, 6..7, 8..9, 9..10, 20..21, 21..21]
+        );
+        let spans = token_spans("  ");
+        assert_eq!(spans.len(), 1);
+        assert_eq!(spans[0], 2..2);
+
+        let tests = [
+            ("let x", 4, (1, 5)),
+            ("a\n\nb", 3, (3, 1)),
+            ("é\n  é", 5, (2, 3)),
+            ("ééé", 4, (1, 3)),
+            ("", 0, (1, 1)),
+        ];
+        for (src, offset, exp) in tests {
+            assert_eq!(line_col(src, offset), exp, "{:?}", src);
+        }
+    }
+
+    #[test]
+    fn test_is_complete() {
+        let complete = [
+            "",
+            "let x = 5;",
+            "fn(x) { x + [1, 2][0] }(3)",
+            "\"has ( and { inside\"",
+            "let x = 1; }",
+            "let x = 1; // (",
+        ];
+        for input in complete {
+            assert!(is_complete(input), "{:?} should be complete", input);
+        }
+
+        let incomplete = [
+            "let add = fn(x, y) {",
+            "add(1,",
+            "[1, 2",
+            "if (x) { [1, (2 }",
+            "\"not closed",
+            "let s = \"a\" + \"b",
+            "f(1, // )\n",
+        ];
+        for input in incomplete {
+            assert!(!is_complete(input), "{:?} should be incomplete", input);
+        }
+    }
 }
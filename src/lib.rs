@@ -0,0 +1,78 @@
+//! An interpreter for the Monkey programming language from "Writing an
+//! Interpreter in Go". Source goes through the [`Lexer`] and the
+//! [`Parser`] into a [`Program`], which [`eval`] runs in an
+//! [`Environment`] to produce an [`Object`].
+//!
+//! The types most programs need are in the [`prelude`]:
+//!
+//! ```
+//! use interpreter::prelude::*;
+//!
+//! let mut p = Parser::new(Lexer::new("let x = 1 + 2 * 3; x * 2"));
+//! let program = p.parse();
+//! assert!(p.get_errors().is_empty());
+//! assert_eq!(program.statements.len(), 2);
+//! assert_eq!(program.statements[0].string(), "let x = (1 + (2 * 3));");
+//!
+//! let mut env = Environment::new();
+//! let value = eval(&program, &mut env);
+//! assert_eq!(value, Some(Object::Integer(14)));
+//! ```
+//!
+//! Enums that grow with the language, like [`Token`], the AST's
+//! [`Expression`](ast::Expression) and [`Object`], are `#[non_exhaustive]`,
+//! so matching on them needs a wildcard arm.
+
+pub mod analysis;
+pub mod ast;
+pub mod builtins;
+pub mod bytecode_file;
+pub mod cache;
+pub mod code;
+pub mod compiler;
+pub mod coverage;
+pub mod debugger;
+pub mod differential;
+pub mod environment;
+pub mod evaluator;
+#[cfg(feature = "capi")]
+pub mod ffi;
+pub mod format;
+pub mod incremental;
+pub mod iter;
+pub mod lexer;
+pub mod macros;
+pub mod modules;
+pub mod object;
+pub mod optimize;
+pub mod parser;
+pub mod peephole;
+pub mod project;
+pub mod repl;
+pub mod stdlib;
+pub mod symbol_table;
+pub mod token;
+mod util;
+pub mod vm;
+#[cfg(feature = "wasm")]
+pub mod wasm;
+
+pub use ast::Program;
+pub use environment::Environment;
+pub use evaluator::{eval, eval_each};
+pub use lexer::Lexer;
+pub use object::Object;
+pub use parser::Parser;
+pub use token::Token;
+
+/// `use interpreter::prelude::*;` brings in everything needed to parse
+/// and run a program and to look at the result.
+pub mod prelude {
+    pub use crate::ast::{Expression, Node, Program, Statement};
+    pub use crate::environment::Environment;
+    pub use crate::evaluator::{eval, eval_each};
+    pub use crate::lexer::Lexer;
+    pub use crate::object::{EvalError, Object, ObjectTrait};
+    pub use crate::parser::Parser;
+    pub use crate::token::Token;
+}
@@ -0,0 +1,215 @@
+//! Macros, which run before the program does and rewrite its code.
+//!
+//! `let unless = macro(cond, cons, alt) { ... };` defines a macro. A call
+//! to it gets its arguments unevaluated, as `quote`d code, and whatever
+//! code the body returns with `quote` takes the place of the call:
+//!
+//! ```
+//! use interpreter::macros::{define_macros, expand_macros};
+//! use interpreter::prelude::*;
+//!
+//! let input = "let twice = macro(x) { quote(unquote(x) + unquote(x)) }; twice(1 * 2)";
+//! let mut program = Parser::new(Lexer::new(input)).parse();
+//! let mut env = Environment::new();
+//! define_macros(&mut program, &mut env);
+//! let expanded = expand_macros(program, &env);
+//! assert_eq!(expanded.string(), "((1 * 2) + (1 * 2))");
+//! ```
+
+use std::rc::Rc;
+
+use crate::ast::{BlockStatement, CallExpression, Expression, Program, Statement};
+use crate::environment::Environment;
+use crate::evaluator::{eval_block_statments, unwrap_return_value};
+use crate::object::{Macro, Object};
+use crate::optimize::{map_children, map_statement};
+
+/// Moves the top-level `let` bindings of macro literals out of `program`
+/// and into `env`. Macros defined anywhere else stay where they are.
+pub fn define_macros(program: &mut Program, env: &mut Environment) {
+    program.statements.retain(|stmt| {
+        let Statement::LetStatement(ls) = stmt else {
+            return true;
+        };
+        let Expression::MacroLiteral(ml) = &ls.value else {
+            return true;
+        };
+        let mac = Macro {
+            parameters: ml.parameters.clone(),
+            body: ml.body.clone(),
+            env: env.clone(),
+        };
+        env.define(
+            ls.name.value.clone(),
+            Object::Macro(Rc::new(mac)),
+            ls.mutable,
+        );
+        false
+    });
+}
+
+/// Replaces every call to a macro of `env` with the code the macro
+/// returns. A call with the wrong number of arguments, or to a macro that
+/// fails or doesn't return quoted code, is left as it is and fails when
+/// the program runs.
+///
+/// The arguments are expanded after they are passed in, with the code the
+/// macro made of them.
+pub fn expand_macros(program: Program, env: &Environment) -> Program {
+    Program {
+        statements: program
+            .statements
+            .into_iter()
+            .map(|stmt| map_statement(stmt, |exp| expand(exp, env)))
+            .collect(),
+    }
+}
+
+fn expand(exp: Expression, env: &Environment) -> Expression {
+    let exp = match exp {
+        // what a macro returns may itself be a call to one
+        Expression::CallExpression(call) => match expand_call(&call, env) {
+            Some(expanded) => return expand(expanded, env),
+            None => Expression::CallExpression(call),
+        },
+        exp => exp,
+    };
+    map_children(
+        exp,
+        |exp| expand(exp, env),
+        |block| expand_block(block, env),
+    )
+}
+
+fn expand_block(block: BlockStatement, env: &Environment) -> BlockStatement {
+    BlockStatement {
+        tok: block.tok,
+        statements: block
+            .statements
+            .into_iter()
+            .map(|stmt| map_statement(stmt, |exp| expand(exp, env)))
+            .collect(),
+    }
+}
+
+/* the code the macro called by `call` returns, if it is one */
+fn expand_call(call: &CallExpression, env: &Environment) -> Option<Expression> {
+    let Expression::Identifier(ident) = &*call.function else {
+        return None;
+    };
+    let Some(Object::Macro(mac)) = env.get(&ident.value) else {
+        return None;
+    };
+    if mac.parameters.len() != call.arguments.len() {
+        return None;
+    }
+    let mut extended = Environment::new_enclosed_env(&mac.env);
+    for (param, arg) in mac.parameters.iter().zip(call.arguments.iter()) {
+        extended.set(param.value.clone(), Object::Quote(Rc::new(arg.clone())));
+    }
+    let evaluated = eval_block_statments(&mac.body.statements, &mut extended)?;
+    match unwrap_return_value(evaluated) {
+        Object::Quote(exp) => Some(Rc::unwrap_or_clone(exp)),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::ast::{Node, Program};
+    use crate::environment::Environment;
+    use crate::lexer::Lexer;
+    use crate::macros::{define_macros, expand_macros};
+    use crate::object::{Object, ObjectTrait};
+    use crate::parser::Parser;
+
+    fn parse(input: &str) -> Program {
+        let mut p = Parser::new(Lexer::new(input));
+        let program = p.parse();
+        assert!(p.get_errors().is_empty(), "{:?}", p.get_errors());
+        program
+    }
+
+    fn expand(input: &str) -> String {
+        let mut program = parse(input);
+        let mut env = Environment::new();
+        define_macros(&mut program, &mut env);
+        expand_macros(program, &env).string()
+    }
+
+    #[test]
+    fn test_define_macros() {
+        let input = "let number = 1;
+let function = fn(x, y) { x + y };
+let mymacro = macro(x, y) { x + y; };";
+        let mut program = parse(input);
+        let mut env = Environment::new();
+        define_macros(&mut program, &mut env);
+
+        assert_eq!(program.statements.len(), 2);
+        assert_eq!(env.get(&"number".into()), None);
+        assert_eq!(env.get(&"function".into()), None);
+        let Some(Object::Macro(mac)) = env.get(&"mymacro".into()) else {
+            panic!("mymacro isn't a macro: {:?}", env.get(&"mymacro".into()));
+        };
+        assert_eq!(mac.parameters.len(), 2);
+        assert_eq!(mac.body.string(), "(x + y)");
+        assert_eq!(env.get(&"mymacro".into()).unwrap().type_string(), "MACRO");
+    }
+
+    #[test]
+    fn test_expand_macros() {
+        let tests = [
+            (
+                "let infixExpression = macro() { quote(1 + 2); }; infixExpression();",
+                "(1 + 2)",
+            ),
+            (
+                "let reverse = macro(a, b) { quote(unquote(b) - unquote(a)); }; reverse(2 + 2, 10 - 5);",
+                "(10 - 5) - (2 + 2)",
+            ),
+            (
+                "let unless = macro(condition, consequence, alternative) {
+    quote(if (!(unquote(condition))) {
+        unquote(consequence);
+    } else {
+        unquote(alternative);
+    });
+};
+unless(10 > 5, print(\"not greater\"), print(\"greater\"));",
+                "if (!(10 > 5)) { print(\"not greater\") } else { print(\"greater\") }",
+            ),
+        ];
+        for (input, exp) in tests {
+            assert_eq!(expand(input), parse(exp).string(), "{}", input);
+        }
+    }
+
+    #[test]
+    fn test_non_macro_calls_untouched() {
+        let tests = [
+            "let add = fn(a, b) { a + b }; add(1, 2);",
+            "len(\"abc\"); f(g(1))[0]",
+            // a macro called with the wrong number of arguments
+            "let m = macro(a) { quote(unquote(a)) }; m(1, 2);",
+            // and one that doesn't return quoted code
+            "let m = macro(a) { 1 }; m(2);",
+        ];
+        for input in tests {
+            let mut exp = parse(input);
+            exp.statements
+                .retain(|stmt| !stmt.string().contains("macro("));
+            assert_eq!(expand(input), exp.string(), "{}", input);
+        }
+    }
+
+    #[test]
+    fn test_nested_expansion() {
+        let input = "let twice = macro(x) { quote(unquote(x) * 2) };
+let f = fn() { twice(twice(3)) };";
+        assert_eq!(
+            expand(input),
+            parse("let f = fn() { ((3 * 2) * 2) };").string()
+        );
+    }
+}
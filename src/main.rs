@@ -1,25 +1,700 @@
-// use environment::Environment;
-use object::ObjectTrait;
+use std::io::Write;
 
-pub mod ast;
-pub mod builtins;
-pub mod environment;
-pub mod evaluator;
-pub mod lexer;
-pub mod object;
-pub mod parser;
-pub mod token;
-pub mod util;
+use interpreter::analysis::{
+    dead_code, lint, typecheck, unused_results, Diagnostic, DiagnosticKind,
+};
+use interpreter::ast::Program;
+use interpreter::builtins::{with_host, Capabilities, Host, SeededRng};
+use interpreter::code::disassemble;
+use interpreter::compiler::{Bytecode, Compiler};
+use interpreter::coverage;
+use interpreter::debugger;
+use interpreter::environment::Environment;
+use interpreter::evaluator;
+use interpreter::format::format;
+use interpreter::lexer::{is_complete, line_col, token_spans, Lexer};
+use interpreter::macros::{define_macros, expand_macros};
+use interpreter::modules::FsResolver;
+use interpreter::object::{EvalError, Object, ObjectTrait};
+use interpreter::parser::{Parser, SourceMap};
+use interpreter::project;
+use interpreter::repl::{self, Command, History};
+use interpreter::vm::Vm;
 
-const PROMP: &'static str = ">> ";
+const PROMPT: &str = ">> ";
+const CONTINUATION_PROMPT: &str = ".. ";
+
+/* the stack everything runs on. A debug build takes up to 32 KiB of it
+ * per call the script nests, which the 8 MiB of the main thread doesn't
+ * have for `DEFAULT_MAX_CALL_DEPTH` calls */
+const STACK_SIZE: usize = 128 * 1024 * 1024;
+
+/* the unchanged lines `fmt --diff` shows around each change */
+const DIFF_CONTEXT: usize = 3;
 
 fn main() -> anyhow::Result<()> {
-    Ok(())
+    let cli = std::thread::Builder::new()
+        .stack_size(STACK_SIZE)
+        .spawn(cli)?;
+    cli.join()
+        .unwrap_or_else(|panic| std::panic::resume_unwind(panic))
+}
+
+fn cli() -> anyhow::Result<()> {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if args
+        .first()
+        .is_some_and(|arg| arg == "check" || arg == "--check")
+    {
+        let code = match args.get(1) {
+            Some(arg) if arg == "--strict" => check(&args[2..], true)?,
+            _ => check(&args[1..], false)?,
+        };
+        std::process::exit(code);
+    }
+    if args.first().is_some_and(|arg| arg == "--lint") {
+        let code = lint_files(&args[1..])?;
+        std::process::exit(code);
+    }
+    if args.first().is_some_and(|arg| arg == "--typecheck") {
+        let code = typecheck_files(&args[1..])?;
+        std::process::exit(code);
+    }
+    if args.first().is_some_and(|arg| arg == "--emit-bytecode") {
+        let code = emit_bytecode(&args[1..])?;
+        std::process::exit(code);
+    }
+    if args.first().is_some_and(|arg| arg == "build") {
+        let code = build(&args[1..])?;
+        std::process::exit(code);
+    }
+    if args.first().is_some_and(|arg| arg == "run") {
+        let code = run(&args[1..])?;
+        std::process::exit(code);
+    }
+    if args.first().is_some_and(|arg| arg == "fmt") {
+        let code = fmt(&args[1..])?;
+        std::process::exit(code);
+    }
+    if args.first().is_some_and(|arg| arg == "debug") {
+        let code = debug(&args[1..])?;
+        std::process::exit(code);
+    }
+    let mut host = command_line_host();
+    let mut args = &args[..];
+    if args.first().is_some_and(|arg| arg == "--seed") {
+        let Some(seed) = args.get(1).and_then(|seed| seed.parse().ok()) else {
+            anyhow::bail!("usage: --seed number [file [args]]");
+        };
+        host = host.with_rng(SeededRng::new(seed));
+        args = &args[2..];
+    }
+    if let Some(path) = args.first() {
+        let code = run_script(path, &args[1..], host)?;
+        std::process::exit(code);
+    }
+    with_host(host, repl)
+}
+
+fn repl() -> anyhow::Result<()> {
+    let mut env = Environment::new();
+    let mut macro_env = Environment::new();
+    let mut history = History::new();
+    loop {
+        let mut line = read_line(PROMPT)?;
+        if line.is_empty() {
+            return Ok(());
+        }
+        if let Some(command) = Command::parse(&line) {
+            match command {
+                Ok(Command::Help(name)) => println!("{}", repl::help(&name)),
+                Ok(Command::Env) => {
+                    for binding in repl::bindings(&env) {
+                        println!("{}", binding);
+                    }
+                }
+                // the builtins aren't in the environment, so they stay
+                Ok(Command::Reset) => {
+                    env = Environment::new();
+                    macro_env = Environment::new();
+                    history = History::new();
+                }
+                Ok(_) => {}
+                Err(msg) => println!("{}", msg),
+            }
+            continue;
+        }
+        while !is_complete(&line) {
+            let more = read_line(CONTINUATION_PROMPT)?;
+            if more.is_empty() {
+                break;
+            }
+            line.push_str(&more);
+        }
+        let l = Lexer::new(&line);
+        let mut p = Parser::new(l);
+        let mut program = p.parse();
+        if p.errors_len() != 0 {
+            print_errors(&p);
+            continue;
+        }
+        define_macros(&mut program, &mut macro_env);
+        let program = expand_macros(program, &macro_env);
+        match evaluator::eval(&program, &mut env) {
+            Some(Object::Error(EvalError::Exit(code))) => std::process::exit(code.into()),
+            Some(obj) => match history.record(&obj, &mut env) {
+                Some(n) => println!("[{}] => {}", n, obj.inspect()),
+                None => println!("{}", obj.inspect()),
+            },
+            None => {}
+        }
+    }
 }
 
-fn print_errors(p: &parser::Parser) {
+/// `[--seed number] file [args]` runs the file, which gets `args` from the
+/// `args()` builtin, and with `--seed` the same `rand()` numbers every
+/// run. Exits with the status the script passed `exit`, or nonzero if it
+/// didn't parse or failed. An error is printed with where in the script it
+/// was, like `file:3:7: division by zero`, and the line it is on.
+fn run_script(path: &str, args: &[String], host: Host) -> anyhow::Result<i32> {
+    let source = std::fs::read_to_string(path)?;
+    let Some((program, source_map)) = load_script(path, &source) else {
+        return Ok(1);
+    };
+    let host = with_script_modules(host.with_args(args.to_vec()), path);
+    let mut env = Environment::new();
+    match with_host(host, || evaluator::eval_located(&program, &mut env)) {
+        (Some(Object::Error(EvalError::Exit(code))), _) => Ok(code.into()),
+        (Some(Object::Error(err)), at) => {
+            let range = at
+                .zip(source_map)
+                .and_then(|(at, source_map)| at.token_range(&source_map));
+            let spans = token_spans(&source);
+            let span = range.map(|(start, end)| spans[start].start..spans[end - 1].end);
+            report(path, &source, span, &err);
+            Ok(1)
+        }
+        _ => Ok(0),
+    }
+}
+
+/* the script in `source`, read from `path`, with its macros expanded, or
+ * nothing if it doesn't parse, after reporting why. The source map is of
+ * the statements as parsed, so there is none if macros changed them. */
+fn load_script(path: &str, source: &str) -> Option<(Program, Option<Vec<SourceMap>>)> {
+    let mut p = Parser::new(Lexer::new(source).with_block_comments()).with_source_map();
+    let mut program = p.parse();
+    if p.errors_len() != 0 {
+        let spans = token_spans(source);
+        for (err, token) in p.get_errors().iter().zip(p.error_tokens()) {
+            let span = token.map(|i| {
+                spans
+                    .get(i)
+                    .map_or(source.len()..source.len(), Clone::clone)
+            });
+            report(path, source, span, err);
+        }
+        return None;
+    }
+    let parsed = program.statements.clone();
+    let mut macro_env = Environment::new();
+    define_macros(&mut program, &mut macro_env);
+    let program = expand_macros(program, &macro_env);
+    let source_map = (program.statements == parsed).then(|| p.source_map().to_vec());
+    Some((program, source_map))
+}
+
+/// `debug file [args]` runs the file like a script, under the debugger of
+/// `interpreter::debugger`, which takes its commands from stdin.
+fn debug(args: &[String]) -> anyhow::Result<i32> {
+    let Some(path) = args.first() else {
+        anyhow::bail!("usage: debug file [args]");
+    };
+    let source = std::fs::read_to_string(path)?;
+    let Some((program, source_map)) = load_script(path, &source) else {
+        return Ok(1);
+    };
+    // without one it can still step, just not say what line it is on
+    let source_map = source_map.unwrap_or_default();
+    let host = with_script_modules(command_line_host().with_args(args[1..].to_vec()), path);
+    let input = std::io::stdin().lock();
+    let res = with_host(host, || {
+        debugger::debug(&source, program, source_map, input, std::io::stdout())
+    });
+    match res {
+        Some(Object::Error(EvalError::Exit(code))) => Ok(code.into()),
+        Some(Object::Error(err)) => {
+            eprintln!("{}: {}", path, err);
+            Ok(1)
+        }
+        _ => Ok(0),
+    }
+}
+
+/* prints `msg` as `path:line:col: msg` followed by the line of `span` with
+ * a `^` under each char of it there, or as `path: msg` without a span */
+fn report(
+    path: &str,
+    source: &str,
+    span: Option<std::ops::Range<usize>>,
+    msg: &impl std::fmt::Display,
+) {
+    let Some(span) = span else {
+        eprintln!("{}: {}", path, msg);
+        return;
+    };
+    let (line, col) = line_col(source, span.start);
+    eprintln!("{}:{}:{}: {}", path, line, col, msg);
+    let line_start = source[..span.start].rfind('\n').map_or(0, |i| i + 1);
+    let line_end = source[span.start..]
+        .find('\n')
+        .map_or(source.len(), |i| span.start + i);
+    // tabs stay tabs so the carets line up under them
+    let indent: String = source[line_start..span.start]
+        .chars()
+        .map(|c| if c == '\t' { '\t' } else { ' ' })
+        .collect();
+    // at least one, for an error at the end of the source
+    let carets = source[span.start..span.end.min(line_end)]
+        .chars()
+        .count()
+        .max(1);
+    eprintln!("{}", &source[line_start..line_end]);
+    eprintln!("{}{}", indent, "^".repeat(carets));
+}
+
+/* whoever runs the command line trusts what they run with it, so it may
+ * use the files they can and take its time */
+fn command_line_host() -> Host {
+    Host::from_process().with_capabilities(Capabilities {
+        fs: true,
+        sleep: true,
+        eval: true,
+    })
+}
+
+/* a script imports the modules next to it, wherever it's run from, and
+ * an import cycle back to it starts at it */
+fn with_script_modules(host: Host, path: &str) -> Host {
+    let dir = std::path::Path::new(path)
+        .parent()
+        .unwrap_or(std::path::Path::new(""));
+    let host = host.with_module_resolver(FsResolver::new(dir));
+    match std::fs::canonicalize(path) {
+        Ok(main) => host.with_main_module(main.to_string_lossy()),
+        Err(_) => host,
+    }
+}
+
+fn print_errors(p: &Parser) {
     let errors = p.get_errors();
     for err in errors.iter() {
         println!("{}", err);
     }
 }
+
+/// `check [--strict] files` parses the files without evaluating them,
+/// printing every error with its line and column and exiting nonzero if
+/// there were any. `strict` also reports names that are never declared.
+/// `--check` is the same.
+fn check(paths: &[String], strict: bool) -> anyhow::Result<i32> {
+    if paths.is_empty() {
+        anyhow::bail!("usage: check [--strict] files");
+    }
+    let paths: Vec<std::path::PathBuf> = paths.iter().map(|p| p.into()).collect();
+    let report = if strict {
+        project::parse_files_strict(&paths)
+    } else {
+        project::parse_files(&paths)
+    };
+    print!("{}", report);
+    Ok(if report.is_ok() { 0 } else { 1 })
+}
+
+/// Lints the given files, printing the parse errors of the ones that don't
+/// parse and every lint and piece of dead code of the others, then the
+/// unused results as warnings, each after the `file:line:col` it is at
+/// when that is known. Exits nonzero if anything but a warning was
+/// reported.
+fn lint_files(paths: &[String]) -> anyhow::Result<i32> {
+    let mut code = 0;
+    for path in paths {
+        let source = std::fs::read_to_string(path)?;
+        let mut p = Parser::new(Lexer::new(&source)).with_source_map();
+        let program = p.parse();
+        if p.errors_len() != 0 {
+            for err in p.get_errors().iter() {
+                println!("{}: {}", path, err);
+            }
+            code = 1;
+            continue;
+        }
+        let spans = token_spans(&source);
+        let at = |diagnostic: &mut Diagnostic| {
+            diagnostic.locate(p.source_map(), &spans);
+            position(path, &source, diagnostic.span.as_ref())
+        };
+        for mut diagnostic in lint(&program).into_iter().chain(dead_code(&program)) {
+            println!("{}: {}", at(&mut diagnostic), diagnostic);
+            code = 1;
+        }
+        for mut diagnostic in unused_results(&program) {
+            println!("{}: warning: {}", at(&mut diagnostic), diagnostic);
+        }
+    }
+    Ok(code)
+}
+
+/* `path:line:col` of the start of `span`, or just `path` without one */
+fn position(path: &str, source: &str, span: Option<&std::ops::Range<usize>>) -> String {
+    match span {
+        Some(span) => {
+            let (line, col) = line_col(source, span.start);
+            format!("{}:{}:{}", path, line, col)
+        }
+        None => path.to_string(),
+    }
+}
+
+/// Typechecks the given files, printing the parse errors of the ones that
+/// don't parse and the type errors and warnings of the others. Exits
+/// nonzero if there was an error, warnings alone don't fail.
+fn typecheck_files(paths: &[String]) -> anyhow::Result<i32> {
+    let mut code = 0;
+    for path in paths {
+        let source = std::fs::read_to_string(path)?;
+        let mut p = Parser::new(Lexer::new(&source));
+        let program = p.parse();
+        if p.errors_len() != 0 {
+            for err in p.get_errors().iter() {
+                println!("{}: {}", path, err);
+            }
+            code = 1;
+            continue;
+        }
+        for diagnostic in typecheck(&program) {
+            if diagnostic.kind == DiagnosticKind::BranchTypes {
+                println!("{}: warning: {}", path, diagnostic);
+            } else {
+                println!("{}: {}", path, diagnostic);
+                code = 1;
+            }
+        }
+    }
+    Ok(code)
+}
+
+/* takes `-O` out of the arguments, with whether it was there */
+fn optimize_flag(args: &[String]) -> (bool, Vec<&String>) {
+    let (flags, rest): (Vec<&String>, Vec<&String>) = args.iter().partition(|arg| *arg == "-O");
+    (!flags.is_empty(), rest)
+}
+
+fn compiler(optimize: bool) -> Compiler {
+    if optimize {
+        Compiler::new().with_optimizations()
+    } else {
+        Compiler::new()
+    }
+}
+
+/// `--emit-bytecode [-O] files` compiles the files without running them
+/// and prints the listing of each, optimized with `-O`. Exits nonzero if
+/// any didn't parse or compile.
+fn emit_bytecode(args: &[String]) -> anyhow::Result<i32> {
+    let (optimize, paths) = optimize_flag(args);
+    let mut code = 0;
+    for path in paths.iter().copied() {
+        let source = std::fs::read_to_string(path)?;
+        let mut p = Parser::new(Lexer::new(&source));
+        let program = p.parse();
+        if p.errors_len() != 0 {
+            for err in p.get_errors().iter() {
+                eprintln!("{}: {}", path, err);
+            }
+            code = 1;
+            continue;
+        }
+        match compiler(optimize).compile(&program) {
+            Ok(bytecode) => {
+                if paths.len() > 1 {
+                    println!("{}:", path);
+                }
+                print!(
+                    "{}",
+                    disassemble(&bytecode.instructions, &bytecode.constants)
+                );
+            }
+            Err(err) => {
+                eprintln!("{}: {}", path, err);
+                code = 1;
+            }
+        }
+    }
+    Ok(code)
+}
+
+/// `build [-O] file [-o out]` compiles the file and saves the bytecode to
+/// `out`, by default the file with the extension `mkc`. `-O` optimizes it.
+fn build(args: &[String]) -> anyhow::Result<i32> {
+    let (optimize, args) = optimize_flag(args);
+    let (path, out) = match args.as_slice() {
+        [path] => (*path, std::path::Path::new(path).with_extension("mkc")),
+        [path, flag, out] if *flag == "-o" => (*path, out.into()),
+        _ => anyhow::bail!("usage: build [-O] file [-o out]"),
+    };
+    let source = std::fs::read_to_string(path)?;
+    let mut p = Parser::new(Lexer::new(&source));
+    let program = p.parse();
+    if p.errors_len() != 0 {
+        for err in p.get_errors().iter() {
+            eprintln!("{}: {}", path, err);
+        }
+        return Ok(1);
+    }
+    let bytecode = match compiler(optimize).compile(&program) {
+        Ok(bytecode) => bytecode,
+        Err(err) => {
+            eprintln!("{}: {}", path, err);
+            return Ok(1);
+        }
+    };
+    std::fs::write(out, bytecode.serialize()?)?;
+    Ok(0)
+}
+
+/// `run [--coverage] [--coverage-format text | json] file [args]` runs
+/// the bytecode `build` saved in `file` and prints the value of the last
+/// expression statement, or, if `file` isn't bytecode, runs the script in
+/// it like `file [args]` does. The flags go in any order before `file`.
+fn run(args: &[String]) -> anyhow::Result<i32> {
+    const USAGE: &str = "usage: run [--coverage] [--coverage-format text | json] file [args]";
+    let (mut coverage, mut json) = (false, None);
+    let mut args = args;
+    loop {
+        match args {
+            [flag, rest @ ..] if flag == "--coverage" => {
+                coverage = true;
+                args = rest;
+            }
+            [flag, format, rest @ ..] if flag == "--coverage-format" => {
+                json = match format.as_str() {
+                    "text" => Some(false),
+                    "json" => Some(true),
+                    _ => anyhow::bail!(USAGE),
+                };
+                args = rest;
+            }
+            _ => break,
+        }
+    }
+    let Some(path) = args.first() else {
+        anyhow::bail!(USAGE);
+    };
+    if json.is_some() && !coverage {
+        anyhow::bail!("--coverage-format needs --coverage");
+    }
+    let bytes = std::fs::read(path)?;
+    if !Bytecode::is_serialized(&bytes) {
+        return match coverage {
+            true => run_coverage(path, &args[1..], json == Some(true)),
+            false => run_script(path, &args[1..], command_line_host()),
+        };
+    }
+    if coverage {
+        anyhow::bail!("{}: coverage needs the source, not bytecode", path);
+    }
+    if args.len() > 1 {
+        anyhow::bail!("{}: bytecode takes no arguments", path);
+    }
+    let bytecode = match Bytecode::deserialize(&bytes) {
+        Ok(bytecode) => bytecode,
+        Err(err) => {
+            eprintln!("{}: {}", path, err);
+            return Ok(1);
+        }
+    };
+    let mut vm = Vm::new(bytecode);
+    if let Err(err) = vm.run() {
+        eprintln!("{}: {}", path, err);
+        return Ok(1);
+    }
+    if let Some(obj) = vm.last_popped() {
+        println!("{}", obj.inspect());
+    }
+    Ok(0)
+}
+
+/// `run --coverage file [args]` runs the script in `file` like
+/// `file [args]` does, then reports which lines of it ran on stderr, as
+/// the source with each line marked or, with `--coverage-format json`, as
+/// JSON.
+fn run_coverage(path: &str, args: &[String], json: bool) -> anyhow::Result<i32> {
+    let source = std::fs::read_to_string(path)?;
+    let Some((program, source_map)) = load_script(path, &source) else {
+        return Ok(1);
+    };
+    let Some(source_map) = source_map else {
+        anyhow::bail!("{}: coverage doesn't work with macros", path);
+    };
+    let host = with_script_modules(command_line_host().with_args(args.to_vec()), path);
+    let (res, coverage) = with_host(host, || coverage::run(&source, program, source_map));
+    let code = match res {
+        Some(Object::Error(EvalError::Exit(code))) => code.into(),
+        Some(Object::Error(err)) => {
+            eprintln!("{}: {}", path, err);
+            1
+        }
+        _ => 0,
+    };
+    match json {
+        true => eprintln!("{}", coverage.to_json()),
+        false => eprint!("{}", coverage.annotate(&source)),
+    }
+    Ok(code)
+}
+
+/// `fmt [--write | --check | --diff] [files]` formats the files, or stdin
+/// if there are none. The formatted source goes to stdout unless `--write`
+/// puts it back in the files. `--check` lists the files that aren't
+/// formatted and `--diff` shows what would change as a unified diff; both
+/// exit nonzero if anything would. Files that don't parse are reported and left alone.
+fn fmt(args: &[String]) -> anyhow::Result<i32> {
+    let (flags, paths): (Vec<&String>, Vec<&String>) =
+        args.iter().partition(|arg| arg.starts_with("--"));
+    let mode = match flags.as_slice() {
+        [] => None,
+        [flag] if ["--write", "--check", "--diff"].contains(&flag.as_str()) => Some(flag.as_str()),
+        _ => anyhow::bail!("usage: fmt [--write | --check | --diff] [files]"),
+    };
+    if paths.is_empty() {
+        if mode == Some("--write") {
+            anyhow::bail!("--write needs files to write to");
+        }
+        let source = std::io::read_to_string(std::io::stdin())?;
+        return fmt_source("<stdin>", &source, mode);
+    }
+    let mut code = 0;
+    for path in paths {
+        let source = std::fs::read_to_string(path)?;
+        code = code.max(fmt_source(path, &source, mode)?);
+    }
+    Ok(code)
+}
+
+/* does what `mode` asks for with one file and returns the exit code */
+fn fmt_source(path: &str, source: &str, mode: Option<&str>) -> anyhow::Result<i32> {
+    let formatted = match format(source) {
+        Ok(formatted) => formatted,
+        Err(errors) => {
+            for err in errors {
+                eprintln!("{}: {}", path, err);
+            }
+            return Ok(1);
+        }
+    };
+    match mode {
+        None => print!("{}", formatted),
+        Some(_) if formatted == source => {}
+        Some("--write") => std::fs::write(path, formatted)?,
+        Some("--check") => {
+            println!("{}", path);
+            return Ok(1);
+        }
+        Some(_) => {
+            println!("--- {}\n+++ {}", path, path);
+            print!("{}", diff(source, &formatted));
+            return Ok(1);
+        }
+    }
+    Ok(0)
+}
+
+/* the unified diff from `old` to `new`, the hunks of changed lines with up
+ * to `DIFF_CONTEXT` lines around them */
+fn diff(old: &str, new: &str) -> String {
+    let old: Vec<&str> = old.split_inclusive('\n').collect();
+    let new: Vec<&str> = new.split_inclusive('\n').collect();
+    // common[i][j] is the length of the longest common subsequence of
+    // old[i..] and new[j..]
+    let mut common = vec![vec![0; new.len() + 1]; old.len() + 1];
+    for i in (0..old.len()).rev() {
+        for j in (0..new.len()).rev() {
+            common[i][j] = if old[i] == new[j] {
+                common[i + 1][j + 1] + 1
+            } else {
+                common[i + 1][j].max(common[i][j + 1])
+            };
+        }
+    }
+    // each line prefixed with `-`, `+` or ` `, with how many lines of old
+    // and new came before it
+    let mut lines = Vec::new();
+    let (mut i, mut j) = (0, 0);
+    while i < old.len() || j < new.len() {
+        if i < old.len() && j < new.len() && old[i] == new[j] {
+            lines.push((' ', old[i], i, j));
+            i += 1;
+            j += 1;
+        } else if j == new.len() || (i < old.len() && common[i + 1][j] >= common[i][j + 1]) {
+            lines.push(('-', old[i], i, j));
+            i += 1;
+        } else {
+            lines.push(('+', new[j], i, j));
+            j += 1;
+        }
+    }
+    let changed = |k: &usize| lines[*k].0 != ' ';
+    let mut res = String::new();
+    let mut done = 0;
+    while let Some(first) = (done..lines.len()).find(changed) {
+        // changes closer than twice the context share a hunk
+        let mut end = first;
+        loop {
+            while end < lines.len() && changed(&end) {
+                end += 1;
+            }
+            match (end..lines.len()).find(changed) {
+                Some(next) if next - end <= 2 * DIFF_CONTEXT => end = next,
+                _ => break,
+            }
+        }
+        let hunk =
+            &lines[first.saturating_sub(DIFF_CONTEXT)..(end + DIFF_CONTEXT).min(lines.len())];
+        let (_, _, old_start, new_start) = hunk[0];
+        let old_len = hunk.iter().filter(|line| line.0 != '+').count();
+        let new_len = hunk.iter().filter(|line| line.0 != '-').count();
+        res.push_str(&format!(
+            "@@ -{} +{} @@\n",
+            hunk_range(old_start, old_len),
+            hunk_range(new_start, new_len)
+        ));
+        for (prefix, line, _, _) in hunk {
+            res.push(*prefix);
+            match line.strip_suffix('\n') {
+                Some(line) => res.push_str(&format!("{}\n", line)),
+                None => res.push_str(&format!("{}\n\\ No newline at end of file\n", line)),
+            }
+        }
+        done = (end + DIFF_CONTEXT).min(lines.len());
+    }
+    res
+}
+
+/* `start,len` of a hunk counting lines from 1, where an empty one starts
+ * at the line before it and `,1` is left out */
+fn hunk_range(start: usize, len: usize) -> String {
+    match len {
+        0 => format!("{},0", start),
+        1 => format!("{}", start + 1),
+        _ => format!("{},{}", start + 1, len),
+    }
+}
+
+fn read_line(prompt: &str) -> anyhow::Result<String> {
+    let mut input = String::new();
+    print!("{}", prompt);
+    std::io::stdout().flush()?;
+    std::io::stdin().read_line(&mut input)?;
+    Ok(input)
+}
@@ -0,0 +1,342 @@
+//! The modules `import` loads, and where the [`Host`](crate::builtins::Host)
+//! finds them.
+//!
+//! `import "path";` binds the top-level names of the module at `path` in the
+//! scope it's in, and `import "path" as name;` binds `name` to a hash of
+//! them by name, so `name["f"]` is the module's `f`. A module runs in an
+//! environment of its own the first time it's imported, and after that
+//! every import of it gets the same bindings without running it again:
+//!
+//! ```
+//! use interpreter::builtins::{capture_output, with_host, Host};
+//! use interpreter::modules::MapResolver;
+//! use interpreter::prelude::*;
+//!
+//! let resolver = MapResolver::new()
+//!     .with_module("math", "print(\"loading\"); let add = fn(a, b) { a + b };");
+//! let host = Host::new().with_module_resolver(resolver);
+//! let program = Parser::new(Lexer::new(
+//!     "import \"math\" as m; import \"math\"; m[\"add\"](1, add(2, 3))",
+//! ))
+//! .parse();
+//! let (res, printed) = capture_output(|| {
+//!     with_host(host, || eval(&program, &mut Environment::new()))
+//! });
+//! assert_eq!(res, Some(Object::Integer(6)));
+//! assert_eq!(printed, "loading\n");
+//! ```
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+use crate::builtins::{fs_granted, module_host};
+use crate::environment::Environment;
+use crate::evaluator::eval;
+use crate::lexer::Lexer;
+use crate::object::{EvalError, Object};
+use crate::parser::Parser;
+
+/// Finds the modules `import` names and reads their source.
+pub trait ModuleResolver {
+    /// The path of the module `import name` means in the module at path
+    /// `importer`, or in the program that is run without one. Modules are
+    /// cached by path, so every name of a module should resolve to the
+    /// same one.
+    fn resolve(&self, name: &str, importer: Option<&str>) -> Result<String, EvalError>;
+
+    /// The source of the module at `path`, which `resolve` gave.
+    fn load(&self, path: &str) -> Result<String, EvalError>;
+}
+
+/// Modules in files. A name is relative to the directory of the module
+/// that imports it, and to the root in the program that is run. Needs the
+/// `fs` capability.
+#[derive(Debug, Clone)]
+pub struct FsResolver {
+    root: PathBuf,
+}
+
+impl FsResolver {
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        FsResolver { root: root.into() }
+    }
+}
+
+impl ModuleResolver for FsResolver {
+    fn resolve(&self, name: &str, importer: Option<&str>) -> Result<String, EvalError> {
+        if !fs_granted() {
+            return Err(EvalError::CapabilityNotGranted("fs"));
+        }
+        let dir = match importer {
+            Some(importer) => Path::new(importer).parent().unwrap_or(Path::new("")),
+            None => &self.root,
+        };
+        // the canonical path, so `a/../m` and `m` are one module
+        match std::fs::canonicalize(dir.join(name)) {
+            Ok(path) => Ok(path.to_string_lossy().into_owned()),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => {
+                Err(EvalError::ModuleNotFound(name.to_owned()))
+            }
+            Err(err) => Err(io_error(name, err)),
+        }
+    }
+
+    fn load(&self, path: &str) -> Result<String, EvalError> {
+        if !fs_granted() {
+            return Err(EvalError::CapabilityNotGranted("fs"));
+        }
+        std::fs::read_to_string(path).map_err(|err| io_error(path, err))
+    }
+}
+
+fn io_error(path: &str, err: std::io::Error) -> EvalError {
+    EvalError::Io {
+        builtin: "import",
+        path: path.to_owned(),
+        reason: err.to_string(),
+    }
+}
+
+/// Modules by name, for tests and for hosts without files, like the
+/// browser. A name is its own path wherever it's imported.
+#[derive(Debug, Clone, Default)]
+pub struct MapResolver {
+    modules: HashMap<String, String>,
+}
+
+impl MapResolver {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds the module `name` with `source`.
+    pub fn with_module(mut self, name: impl Into<String>, source: impl Into<String>) -> Self {
+        self.modules.insert(name.into(), source.into());
+        self
+    }
+}
+
+impl ModuleResolver for MapResolver {
+    fn resolve(&self, name: &str, _importer: Option<&str>) -> Result<String, EvalError> {
+        match self.modules.contains_key(name) {
+            true => Ok(name.to_owned()),
+            false => Err(EvalError::ModuleNotFound(name.to_owned())),
+        }
+    }
+
+    fn load(&self, path: &str) -> Result<String, EvalError> {
+        self.modules
+            .get(path)
+            .cloned()
+            .ok_or_else(|| EvalError::ModuleNotFound(path.to_owned()))
+    }
+}
+
+/* the top-level bindings of a module by name */
+pub(crate) type Exports = Rc<[(Rc<str>, Object)]>;
+
+/* the modules a host has imported by path, and the ones being imported,
+ * innermost last, below the program that is run if the host knows its
+ * path */
+#[derive(Default)]
+pub(crate) struct Modules {
+    loaded: HashMap<String, Exports>,
+    importing: Vec<String>,
+    pub(crate) main: Option<String>,
+}
+
+/* what the module `name` exports, running it the first time */
+pub(crate) fn import(name: &str) -> Result<Exports, EvalError> {
+    let (resolver, modules) = module_host();
+    let path = {
+        let modules = modules.borrow();
+        let importing: Vec<&String> = modules.main.iter().chain(&modules.importing).collect();
+        let importer = importing.last().map(|p| p.as_str());
+        let path = resolver.resolve(name, importer)?;
+        if let Some(exports) = modules.loaded.get(&path) {
+            return Ok(exports.clone());
+        }
+        if let Some(start) = importing.iter().position(|p| **p == path) {
+            let mut cycle: Vec<String> = importing[start..].iter().map(|p| p.to_string()).collect();
+            cycle.push(path);
+            return Err(EvalError::ImportCycle(cycle));
+        }
+        path
+    };
+    let source = resolver.load(&path)?;
+    let mut p = Parser::new(Lexer::new(&source));
+    let program = p.parse();
+    if p.errors_len() != 0 {
+        return Err(EvalError::ModuleParseFailed {
+            path,
+            errors: p.get_errors().clone(),
+        });
+    }
+    modules.borrow_mut().importing.push(path.clone());
+    let mut env = Environment::new();
+    let res = eval(&program, &mut env);
+    modules.borrow_mut().importing.pop();
+    if let Some(Object::Error(err)) = res {
+        return Err(err);
+    }
+    let mut exports = env.locals();
+    exports.sort_by(|(a, _), (b, _)| a.cmp(b));
+    let exports: Exports = exports.into();
+    modules.borrow_mut().loaded.insert(path, exports.clone());
+    Ok(exports)
+}
+
+#[cfg(test)]
+mod test {
+    use crate::builtins::{capture_output, with_host, Capabilities, Host};
+    use crate::environment::Environment;
+    use crate::evaluator::eval;
+    use crate::lexer::Lexer;
+    use crate::modules::{FsResolver, MapResolver};
+    use crate::object::{EvalError, Object};
+    use crate::parser::Parser;
+
+    fn run(host: Host, input: &str) -> (Option<Object>, String) {
+        let mut p = Parser::new(Lexer::new(input));
+        let program = p.parse();
+        assert!(p.get_errors().is_empty(), "{:?}", p.get_errors());
+        capture_output(|| with_host(host, || eval(&program, &mut Environment::new())))
+    }
+
+    fn map_host() -> Host {
+        let resolver = MapResolver::new()
+            .with_module(
+                "math",
+                "print(\"math\"); let add = fn(a, b) { a + b }; const two = 2;",
+            )
+            .with_module("twice", "import \"math\"; let twice = fn(x) { add(x, x) };")
+            .with_module("a", "import \"b\"; let a = 1;")
+            .with_module("b", "import \"a\"; let b = 2;")
+            .with_module("broken", "let x 1;")
+            .with_module("fails", "1 / 0;");
+        Host::new().with_module_resolver(resolver)
+    }
+
+    #[test]
+    fn test_import() {
+        let tests = [
+            ("import \"math\"; add(1, two)", Object::Integer(3)),
+            (
+                "import \"math\" as m; m[\"add\"](m[\"two\"], 3)",
+                Object::Integer(5),
+            ),
+            ("import \"math\" as m; keys(m)", {
+                let (res, _) = run(Host::new(), "[\"add\", \"two\"]");
+                res.unwrap()
+            }),
+            ("import \"twice\"; twice(4)", Object::Integer(8)),
+            // what a module imports is bound at its top level too
+            ("import \"twice\"; add(1, 2)", Object::Integer(3)),
+            (
+                "const add = 1; import \"math\";",
+                Object::Error(EvalError::ConstReassignment("add".to_owned())),
+            ),
+            (
+                "import \"nowhere\" as n;",
+                Object::Error(EvalError::ModuleNotFound("nowhere".to_owned())),
+            ),
+            (
+                "import \"broken\";",
+                Object::Error(EvalError::ModuleParseFailed {
+                    path: "broken".to_owned(),
+                    errors: vec![
+                        "expected next token to be Assign, got Int(\n    \"1\",\n) instead"
+                            .to_owned(),
+                    ],
+                }),
+            ),
+            (
+                "import \"fails\";",
+                Object::Error(EvalError::DivisionByZero),
+            ),
+        ];
+        for (input, exp) in tests {
+            let (res, _) = run(map_host(), input);
+            assert_eq!(res, Some(exp), "{}", input);
+        }
+    }
+
+    #[test]
+    fn test_modules_run_once() {
+        let input = "import \"math\"; import \"twice\"; import \"math\" as m; twice(1)";
+        let (res, printed) = run(map_host(), input);
+        assert_eq!(res, Some(Object::Integer(2)));
+        assert_eq!(printed, "math\n");
+    }
+
+    #[test]
+    fn test_import_cycle() {
+        let (res, _) = run(map_host(), "import \"a\";");
+        let cycle = ["a", "b", "a"].map(str::to_owned).to_vec();
+        assert_eq!(res, Some(Object::Error(EvalError::ImportCycle(cycle))));
+        assert_eq!(
+            EvalError::ImportCycle(["a", "b", "a"].map(str::to_owned).to_vec()).to_string(),
+            "import cycle: a -> b -> a"
+        );
+
+        // from wherever it was entered
+        let (res, _) = run(map_host(), "import \"b\"; b");
+        let cycle = ["b", "a", "b"].map(str::to_owned).to_vec();
+        assert_eq!(res, Some(Object::Error(EvalError::ImportCycle(cycle))));
+
+        // the program that is run is where it starts, if it's a module
+        let (res, _) = run(map_host().with_main_module("a"), "import \"b\"; b");
+        let cycle = ["a", "b", "a"].map(str::to_owned).to_vec();
+        assert_eq!(res, Some(Object::Error(EvalError::ImportCycle(cycle))));
+    }
+
+    #[test]
+    fn test_fs_resolver() {
+        let dir = std::env::temp_dir().join(format!("monkey-modules-{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("lib")).unwrap();
+        // next to the module that imports it, not in the root
+        std::fs::write(dir.join("lib/one.monkey"), "let one = 1;").unwrap();
+        std::fs::write(
+            dir.join("lib/inc.monkey"),
+            "import \"one.monkey\"; let inc = fn(x) { x + one };",
+        )
+        .unwrap();
+
+        let host = |fs| {
+            Host::new()
+                .with_module_resolver(FsResolver::new(&dir))
+                .with_capabilities(Capabilities {
+                    fs,
+                    ..Capabilities::default()
+                })
+        };
+        let tests = [
+            (
+                true,
+                "import \"lib/inc.monkey\"; inc(1)",
+                Object::Integer(2),
+            ),
+            (
+                true,
+                "import \"lib/../lib/inc.monkey\" as a; import \"lib/inc.monkey\" as b; a == b",
+                Object::Boolean(true),
+            ),
+            (
+                true,
+                "import \"one.monkey\";",
+                Object::Error(EvalError::ModuleNotFound("one.monkey".to_owned())),
+            ),
+            (
+                false,
+                "import \"lib/inc.monkey\";",
+                Object::Error(EvalError::CapabilityNotGranted("fs")),
+            ),
+        ];
+        for (fs, input, exp) in tests {
+            let (res, _) = run(host(fs), input);
+            assert_eq!(res, Some(exp), "{}", input);
+        }
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
@@ -1,5 +1,6 @@
 use crate::{
-    ast::{BlockStatement, Identifier, Node},
+    ast::{BlockStatement, Expression, Identifier, InfixOperator, Node, PrefixOperator},
+    builtins::BuiltinDef,
     environment::Environment,
 };
 
@@ -9,57 +10,917 @@ pub trait ObjectTrait {
     fn inspect(&self) -> String;
 }
 
-type BuiltinFunction = fn(args: &Vec<Object>) -> Object;
-
-#[derive(Debug, PartialEq, Eq, Clone)]
+#[derive(Debug, PartialEq, Clone)]
 pub struct Array {
     pub elements: Vec<Object>,
+    /// Whether `freeze` made it, so builtins that would give a changed
+    /// copy of it, like `push`, fail instead.
+    pub frozen: bool,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct Tuple {
+    pub elements: Vec<Object>,
 }
 
-#[derive(Debug, PartialEq, Eq, Clone)]
+/// The pairs of a hash in the order their keys were first inserted, which
+/// is the order `inspect` and `keys` go through them in. A key is found
+/// through an index of the keys that can be hashed. The others, a float or
+/// anything with a float, function or hash in it, are compared one pair at
+/// a time.
+#[derive(Debug, Default, Clone)]
 pub struct Hash {
-    pub pairs: Vec<(Object, Object)>,
+    pairs: Vec<(Object, Object)>,
+    /* where in `pairs` the pair of each key that can be hashed is */
+    index: std::collections::HashMap<HashKey, usize>,
+}
+
+/* a key as `Hash` indexes it, equal to another exactly when the objects
+ * are */
+#[derive(Debug, PartialEq, Eq, Hash, Clone)]
+enum HashKey {
+    Null,
+    Integer(i64),
+    Boolean(bool),
+    String(std::rc::Rc<str>),
+    Range(i64, i64),
+    Array(Vec<HashKey>, bool),
+    Tuple(Vec<HashKey>),
+}
+
+impl HashKey {
+    fn new(obj: &Object) -> Option<Self> {
+        let elements =
+            |elements: &[Object]| elements.iter().map(HashKey::new).collect::<Option<_>>();
+        match obj {
+            Object::Null => Some(HashKey::Null),
+            Object::Integer(v) => Some(HashKey::Integer(*v)),
+            Object::Boolean(v) => Some(HashKey::Boolean(*v)),
+            Object::String(s) => Some(HashKey::String(s.clone())),
+            Object::Range(r) => Some(HashKey::Range(r.start, r.end)),
+            Object::Array(arr) => Some(HashKey::Array(elements(&arr.elements)?, arr.frozen)),
+            Object::Tuple(tuple) => Some(HashKey::Tuple(elements(&tuple.elements)?)),
+            _ => None,
+        }
+    }
 }
 
-#[derive(PartialEq, Eq)]
+/* the index follows from the pairs */
+impl PartialEq for Hash {
+    fn eq(&self, other: &Self) -> bool {
+        self.pairs == other.pairs
+    }
+}
+
+impl Hash {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The pairs in the order their keys were first inserted.
+    pub fn pairs(&self) -> &[(Object, Object)] {
+        &self.pairs
+    }
+
+    pub fn len(&self) -> usize {
+        self.pairs.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pairs.is_empty()
+    }
+
+    /* where the pair of `key` is in `pairs` */
+    fn position(&self, key: &Object) -> Option<usize> {
+        match HashKey::new(key) {
+            Some(hashed) => self.index.get(&hashed).copied(),
+            None => self.pairs.iter().position(|(k, _)| k == key),
+        }
+    }
+
+    pub fn get(&self, key: &Object) -> Option<&Object> {
+        self.position(key).map(|i| &self.pairs[i].1)
+    }
+
+    /// Sets the value of `key`. A key that is already there keeps its
+    /// place and gets the new value.
+    pub fn insert(&mut self, key: Object, value: Object) {
+        match self.position(&key) {
+            Some(i) => self.pairs[i].1 = value,
+            None => {
+                if let Some(hashed) = HashKey::new(&key) {
+                    self.index.insert(hashed, self.pairs.len());
+                }
+                self.pairs.push((key, value));
+            }
+        }
+    }
+}
+
+/// A hash of `pairs`, where a later pair with the same key as an earlier
+/// one sets its value.
+impl FromIterator<(Object, Object)> for Hash {
+    fn from_iter<I: IntoIterator<Item = (Object, Object)>>(pairs: I) -> Self {
+        let mut hash = Hash::new();
+        for (key, value) in pairs {
+            hash.insert(key, value);
+        }
+        hash
+    }
+}
+
+/// The integers from `start` up to but not including `end`, which a `for`
+/// loop goes through without making an array of them.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct Range {
+    pub start: i64,
+    pub end: i64,
+}
+
+#[non_exhaustive]
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Hash)]
 pub enum ObjectType {
     Null,
     Integer,
+    Float,
     Boolean,
     Return,
+    Break,
+    Continue,
     Error,
     Function,
     String,
     Builtin,
     Array,
+    Tuple,
     Hash,
+    Range,
+    Iterator,
     CompiledFunction,
+    Quote,
+    Macro,
+    Caught,
 }
 
-#[derive(Debug, PartialEq, Eq, Clone)]
+/// Numbers, booleans, ranges and null are stored inline and never allocate.
+/// Everything bigger sits behind an `Rc`, so cloning an object is cheap and
+/// values are shared instead of copied. Nothing hands out `&mut` to a shared
+/// value; builtins like `push` build a new one. The exception is an
+/// iterator, which every copy of moves on as elements are taken from it.
+#[non_exhaustive]
+#[derive(Debug, PartialEq, Clone)]
 pub enum Object {
     Null,
     Integer(i64),
+    Float(f64),
     Boolean(bool),
     Return(std::boxed::Box<Object>),
-    Error(String),
-    Function(Function),
+    /// What a `break` or `continue` evaluates to on its way out to its loop,
+    /// with the label of the loop a `break label` ends.
+    Break(Option<std::rc::Rc<str>>),
+    Continue,
+    Error(EvalError),
+    Function(std::rc::Rc<Function>),
     String(std::rc::Rc<str>),
     Builtin(Builtin),
-    Array(Array),
-    Hash(Hash),
+    Array(std::rc::Rc<Array>),
+    Tuple(std::rc::Rc<Tuple>),
+    Hash(std::rc::Rc<Hash>),
+    Range(Range),
+    /// See [`iter`](crate::iter).
+    Iterator(std::rc::Rc<crate::iter::Iter>),
+    /// The unevaluated code passed to `quote`.
+    Quote(std::rc::Rc<Expression>),
+    Macro(std::rc::Rc<Macro>),
+    /// An error `try` caught, which is a value like any other rather than
+    /// one that ends the program. `kind(err)` tells what sort it is.
+    Caught(std::rc::Rc<EvalError>),
 }
 
-#[derive(Debug, PartialEq, Eq, Clone)]
+#[non_exhaustive]
+#[derive(Debug, PartialEq, Clone)]
+pub enum EvalError {
+    /// An infix operator with no meaning for its operand types.
+    UnsupportedOperands {
+        op: InfixOperator,
+        left: ObjectType,
+        right: ObjectType,
+    },
+    /// A prefix operator with no meaning for its operand's type.
+    UnsupportedOperand {
+        op: PrefixOperator,
+        right: ObjectType,
+    },
+    DivisionByZero,
+    /// Integer arithmetic that overflowed under [`IntOverflow::Checked`].
+    ///
+    /// [`IntOverflow::Checked`]: crate::builtins::IntOverflow::Checked
+    IntegerOverflow,
+    IdentifierNotFound(String),
+    NotCallable(ObjectType),
+    IndexNotSupported(ObjectType),
+    /// A builtin called with fewer than `min` or more than `max`
+    /// arguments. Without a `max` it takes any number from `min` on.
+    WrongArgumentCount {
+        builtin: &'static str,
+        got: usize,
+        min: usize,
+        max: Option<usize>,
+    },
+    /// A builtin's argument at `position`, counted from 1, of a type it
+    /// doesn't take there.
+    WrongArgumentType {
+        builtin: &'static str,
+        position: usize,
+        want: TypeSet,
+        got: ObjectType,
+    },
+    /// Arguments of types a builtin takes one by one, but not together.
+    UnsupportedArgument {
+        builtin: &'static str,
+        got: ObjectType,
+    },
+    InvalidArgument {
+        builtin: &'static str,
+        reason: &'static str,
+    },
+    /// A builtin's `index` into something `len` long that isn't in it,
+    /// like the end of a `slice` past the end of the array.
+    IndexOutOfRange {
+        builtin: &'static str,
+        index: i64,
+        len: usize,
+    },
+    NotDestructurable {
+        got: ObjectType,
+        names: usize,
+    },
+    DestructureCountMismatch {
+        got: usize,
+        want: usize,
+    },
+    ConstReassignment(String),
+    /// `push` on an array `freeze` gave.
+    FrozenArray,
+    /// `typeassert(x, want)` got an `x` whose type isn't named `want`.
+    TypeAssertion {
+        want: String,
+        got: ObjectType,
+    },
+    /// `assert_eq(expected, got)` got values that differ, with the
+    /// differences [`Object::diff`] found.
+    AssertionFailed(Vec<String>),
+    /// A string repeated a negative or impossibly large number of times.
+    InvalidRepeatCount(i64),
+    /// `exit(code)` was called. It isn't a failure, but it stops the
+    /// program the way an error does, so whoever runs it decides what
+    /// exiting means.
+    Exit(u8),
+    /// A builtin needs a capability the host didn't grant, see
+    /// [`Capabilities`](crate::builtins::Capabilities).
+    CapabilityNotGranted(&'static str),
+    /// The code given to `eval` or `parse` didn't parse, with the parser's
+    /// errors.
+    ParseFailed(Vec<String>),
+    /// `eval` was called from code run by `eval` more often than the
+    /// limit.
+    EvalTooDeep(usize),
+    /// Calls of functions nested more deeply than the host allows, see
+    /// [`Host::with_max_call_depth`](crate::builtins::Host::with_max_call_depth).
+    CallTooDeep(usize),
+    /// The program took all the steps the host let it, see
+    /// [`Host::with_fuel`](crate::builtins::Host::with_fuel).
+    OutOfFuel(u64),
+    /// A `for` loop over something that has no elements.
+    NotIterable(ObjectType),
+    /// A file builtin failed, with the reason the OS gave.
+    Io {
+        builtin: &'static str,
+        path: String,
+        reason: String,
+    },
+    /// `import` named a module the resolver doesn't know.
+    ModuleNotFound(String),
+    /// The module at `path` didn't parse, with the parser's errors.
+    ModuleParseFailed {
+        path: String,
+        errors: Vec<String>,
+    },
+    /// A module imported itself, directly or through others. The paths
+    /// are in the order they imported each other, and start and end with
+    /// the same one.
+    ImportCycle(Vec<String>),
+    /// `throw(value)` was called.
+    Thrown(std::boxed::Box<Object>),
+}
+
+#[derive(Debug, PartialEq, Clone)]
 pub struct Function {
     pub parameters: Vec<Identifier>,
     pub body: BlockStatement,
     pub env: Environment,
+    /* the name the function was `let` bound to, visible in its own body */
+    pub name: Option<std::rc::Rc<str>>,
+    /* the results so far of a function `memoize` made */
+    pub memo: Option<Memo>,
+}
+
+/// The results a function `memoize` made has given, by the arguments it
+/// gave them for. Only arguments made of null, integers, booleans, strings,
+/// arrays and tuples are remembered; a call with any other argument, like a
+/// float or a function, runs the body every time.
+#[derive(Debug, Default, PartialEq, Clone)]
+pub struct Memo {
+    results: std::cell::RefCell<std::collections::HashMap<Vec<MemoKey>, Object>>,
+}
+
+/* an argument as `Memo` compares it, equal to another exactly when the
+ * objects are */
+#[derive(Debug, PartialEq, Eq, Hash, Clone)]
+enum MemoKey {
+    Null,
+    Integer(i64),
+    Boolean(bool),
+    String(std::rc::Rc<str>),
+    Array(Vec<MemoKey>),
+    Tuple(Vec<MemoKey>),
+}
+
+impl MemoKey {
+    fn new(obj: &Object) -> Option<Self> {
+        let elements =
+            |elements: &[Object]| elements.iter().map(MemoKey::new).collect::<Option<_>>();
+        match obj {
+            Object::Null => Some(MemoKey::Null),
+            Object::Integer(v) => Some(MemoKey::Integer(*v)),
+            Object::Boolean(v) => Some(MemoKey::Boolean(*v)),
+            Object::String(s) => Some(MemoKey::String(s.clone())),
+            Object::Array(arr) => Some(MemoKey::Array(elements(&arr.elements)?)),
+            Object::Tuple(tuple) => Some(MemoKey::Tuple(elements(&tuple.elements)?)),
+            _ => None,
+        }
+    }
+}
+
+impl Memo {
+    pub fn get(&self, args: &[Object]) -> Option<Object> {
+        let key: Vec<MemoKey> = args.iter().map(MemoKey::new).collect::<Option<_>>()?;
+        self.results.borrow().get(&key).cloned()
+    }
+
+    /// Remembers `result` for `args`, unless one of them can't be.
+    pub fn insert(&self, args: &[Object], result: Object) {
+        if let Some(key) = args.iter().map(MemoKey::new).collect::<Option<_>>() {
+            self.results.borrow_mut().insert(key, result);
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.results.borrow().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct Macro {
+    pub parameters: Vec<Identifier>,
+    pub body: BlockStatement,
+    pub env: Environment,
 }
 
-#[derive(Debug, PartialEq, Eq, Clone)]
+#[derive(Debug, Clone)]
 pub struct Builtin {
-    pub func: BuiltinFunction,
+    pub def: &'static BuiltinDef,
+}
+
+/* there is one definition for each name */
+impl PartialEq for Builtin {
+    fn eq(&self, other: &Self) -> bool {
+        self.def.name == other.def.name
+    }
+}
+
+impl Eq for Builtin {}
+
+impl ObjectType {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Null => "NULL",
+            Self::Integer => "INTEGER",
+            Self::Float => "FLOAT",
+            Self::Boolean => "BOOLEAN",
+            Self::String => "STRING",
+            Self::Return => "RETURN",
+            Self::Break => "BREAK",
+            Self::Continue => "CONTINUE",
+            Self::Error => "ERROR",
+            Self::Function => "FUNCTION",
+            Self::Builtin => "BUILTIN",
+            Self::Array => "ARRAY",
+            Self::Tuple => "TUPLE",
+            Self::Hash => "HASH",
+            Self::Range => "RANGE",
+            Self::Iterator => "ITERATOR",
+            Self::CompiledFunction => "COMPILED_FUNCTION",
+            Self::Quote => "QUOTE",
+            Self::Macro => "MACRO",
+            Self::Caught => "CAUGHT",
+        }
+    }
+}
+
+impl std::fmt::Display for ObjectType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// The types an argument of a builtin may have, shown like
+/// `STRING|ARRAY`.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct TypeSet {
+    /* empty for any type */
+    types: &'static [ObjectType],
+}
+
+impl TypeSet {
+    pub const ANY: TypeSet = TypeSet { types: &[] };
+
+    /// The set of `types`, which it shows in this order.
+    pub const fn of(types: &'static [ObjectType]) -> Self {
+        TypeSet { types }
+    }
+
+    pub fn contains(&self, t: ObjectType) -> bool {
+        self.types.is_empty() || self.types.contains(&t)
+    }
+}
+
+impl std::fmt::Display for TypeSet {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.types.is_empty() {
+            return f.write_str("ANY");
+        }
+        for (i, t) in self.types.iter().enumerate() {
+            if i > 0 {
+                f.write_str("|")?;
+            }
+            f.write_str(t.as_str())?;
+        }
+        Ok(())
+    }
+}
+
+impl std::fmt::Display for EvalError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::UnsupportedOperands { op, left, right } => {
+                write!(
+                    f,
+                    "unsupported operand types for {}: {} and {}",
+                    op, left, right
+                )
+            }
+            Self::UnsupportedOperand { op, right } => {
+                write!(f, "unsupported operand type for {}: {}", op, right)
+            }
+            Self::DivisionByZero => f.write_str("division by zero"),
+            Self::IntegerOverflow => f.write_str("integer overflow"),
+            Self::IdentifierNotFound(name) => write!(f, "identifier not found: {}", name),
+            Self::NotCallable(got) => write!(f, "not a function: {}", got),
+            Self::IndexNotSupported(got) => write!(f, "index operator not supported: {}", got),
+            Self::WrongArgumentCount {
+                builtin,
+                got,
+                min,
+                max,
+            } => {
+                let plural = |n: usize| if n == 1 { "argument" } else { "arguments" };
+                match max {
+                    Some(max) if max == min => {
+                        write!(f, "`{}` expects {} {}", builtin, min, plural(*min))?
+                    }
+                    Some(max) => write!(f, "`{}` expects {} to {} arguments", builtin, min, max)?,
+                    None => write!(f, "`{}` expects at least {} {}", builtin, min, plural(*min))?,
+                }
+                write!(f, ", got {}", got)
+            }
+            Self::WrongArgumentType {
+                builtin,
+                position,
+                want,
+                got,
+            } => write!(
+                f,
+                "`{}` expects {} for argument {}, got {}",
+                builtin, want, position, got
+            ),
+            Self::UnsupportedArgument { builtin, got } => {
+                write!(f, "argument to `{}` not supported, got {}", builtin, got)
+            }
+            Self::InvalidArgument { builtin, reason } => {
+                write!(f, "invalid argument to `{}`: {}", builtin, reason)
+            }
+            Self::IndexOutOfRange {
+                builtin,
+                index,
+                len,
+            } => write!(
+                f,
+                "index {} out of range for `{}`, the length is {}",
+                index, builtin, len
+            ),
+            Self::NotDestructurable { got, names } => {
+                write!(f, "cannot destructure {} into {} names", got, names)
+            }
+            Self::DestructureCountMismatch { got, want } => write!(
+                f,
+                "wrong number of values to destructure. got={}, want={}",
+                got, want
+            ),
+            Self::ConstReassignment(name) => write!(f, "cannot reassign const '{}'", name),
+            Self::FrozenArray => write!(f, "cannot modify frozen array"),
+            Self::TypeAssertion { want, got } => write!(f, "expected {}, got {}", want, got),
+            Self::AssertionFailed(diffs) => write!(f, "assertion failed: {}", diffs.join("; ")),
+            Self::InvalidRepeatCount(count) => write!(f, "invalid repeat count: {}", count),
+            Self::Exit(code) => write!(f, "exit with status {}", code),
+            Self::CapabilityNotGranted(name) => write!(f, "capability '{}' not granted", name),
+            Self::ParseFailed(errors) => write!(f, "parse errors: {}", errors.join("; ")),
+            Self::EvalTooDeep(limit) => write!(f, "eval nested more than {} deep", limit),
+            Self::CallTooDeep(limit) => write!(f, "calls nested more than {} deep", limit),
+            Self::OutOfFuel(limit) => write!(f, "out of fuel after {} steps", limit),
+            Self::NotIterable(got) => write!(f, "cannot iterate over {}", got),
+            Self::Io {
+                builtin,
+                path,
+                reason,
+            } => write!(f, "`{}` failed on {}: {}", builtin, path, reason),
+            Self::ModuleNotFound(name) => write!(f, "module not found: {}", name),
+            Self::ModuleParseFailed { path, errors } => {
+                write!(f, "module {} doesn't parse: {}", path, errors.join("; "))
+            }
+            Self::ImportCycle(paths) => write!(f, "import cycle: {}", paths.join(" -> ")),
+            Self::Thrown(value) => f.write_str(&value.inspect()),
+        }
+    }
+}
+
+impl std::error::Error for EvalError {}
+
+/// The broad sort of an [`EvalError`], for a host that handles errors by
+/// what went wrong rather than by their exact message. Where the error
+/// happened comes from [`eval_source`](crate::evaluator::eval_source), or
+/// [`eval_located`](crate::evaluator::eval_located) for a parsed program.
+///
+/// A script that caught an error with `try` gets the name of its kind from
+/// `kind(err)`, like `"DivisionByZero"`.
+#[non_exhaustive]
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Hash)]
+pub enum ErrorKind {
+    /// A value of the wrong type: an operand, an argument, something
+    /// called, indexed, iterated over or destructured.
+    TypeMismatch,
+    UnknownIdentifier,
+    DivisionByZero,
+    IntegerOverflow,
+    /// The wrong number of arguments, or of values to destructure.
+    ArityMismatch,
+    /// An argument of the right type with a value that is out of bounds.
+    InvalidArgument,
+    /// An index past either end of what it indexes.
+    IndexOutOfRange,
+    /// Rebinding a `const`.
+    ConstReassignment,
+    /// Changing an array `freeze` made.
+    Frozen,
+    /// The host didn't allow it.
+    Denied,
+    /// The OS failed a file builtin.
+    Io,
+    /// Code given to `eval` or `parse` that doesn't parse or nests `eval`
+    /// too deeply.
+    Eval,
+    /// `exit(code)`, which isn't a failure.
+    Exit,
+    /// A module that can't be found, doesn't parse or imports itself.
+    Import,
+    /// Calls nested more deeply than the host allows.
+    CallTooDeep,
+    /// More steps than the host allows.
+    OutOfFuel,
+    /// `assert_eq` of values that differ.
+    AssertionFailed,
+    /// A value `throw` threw.
+    Custom,
+}
+
+impl ErrorKind {
+    /// The name of the kind, which `kind(err)` gives.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::TypeMismatch => "TypeMismatch",
+            Self::UnknownIdentifier => "UnknownIdentifier",
+            Self::DivisionByZero => "DivisionByZero",
+            Self::IntegerOverflow => "IntegerOverflow",
+            Self::ArityMismatch => "ArityMismatch",
+            Self::InvalidArgument => "InvalidArgument",
+            Self::IndexOutOfRange => "IndexOutOfRange",
+            Self::ConstReassignment => "ConstReassignment",
+            Self::Frozen => "Frozen",
+            Self::Denied => "Denied",
+            Self::Io => "Io",
+            Self::Eval => "Eval",
+            Self::Exit => "Exit",
+            Self::Import => "Import",
+            Self::CallTooDeep => "CallTooDeep",
+            Self::OutOfFuel => "OutOfFuel",
+            Self::AssertionFailed => "AssertionFailed",
+            Self::Custom => "Custom",
+        }
+    }
+}
+
+impl std::fmt::Display for ErrorKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl EvalError {
+    pub fn kind(&self) -> ErrorKind {
+        match self {
+            Self::UnsupportedOperands { .. }
+            | Self::UnsupportedOperand { .. }
+            | Self::NotCallable(_)
+            | Self::IndexNotSupported(_)
+            | Self::WrongArgumentType { .. }
+            | Self::UnsupportedArgument { .. }
+            | Self::NotDestructurable { .. }
+            | Self::NotIterable(_)
+            | Self::TypeAssertion { .. } => ErrorKind::TypeMismatch,
+            Self::IdentifierNotFound(_) => ErrorKind::UnknownIdentifier,
+            Self::DivisionByZero => ErrorKind::DivisionByZero,
+            Self::IntegerOverflow => ErrorKind::IntegerOverflow,
+            Self::WrongArgumentCount { .. } | Self::DestructureCountMismatch { .. } => {
+                ErrorKind::ArityMismatch
+            }
+            Self::InvalidArgument { .. } | Self::InvalidRepeatCount(_) => {
+                ErrorKind::InvalidArgument
+            }
+            Self::ConstReassignment(_) => ErrorKind::ConstReassignment,
+            Self::FrozenArray => ErrorKind::Frozen,
+            Self::IndexOutOfRange { .. } => ErrorKind::IndexOutOfRange,
+            Self::CapabilityNotGranted(_) => ErrorKind::Denied,
+            Self::Io { .. } => ErrorKind::Io,
+            Self::ParseFailed(_) | Self::EvalTooDeep(_) => ErrorKind::Eval,
+            Self::Exit(_) => ErrorKind::Exit,
+            Self::CallTooDeep(_) => ErrorKind::CallTooDeep,
+            Self::OutOfFuel(_) => ErrorKind::OutOfFuel,
+            Self::AssertionFailed(_) => ErrorKind::AssertionFailed,
+            Self::ModuleNotFound(_) | Self::ModuleParseFailed { .. } | Self::ImportCycle(_) => {
+                ErrorKind::Import
+            }
+            Self::Thrown(_) => ErrorKind::Custom,
+        }
+    }
+}
+
+impl Object {
+    /// What `==` means in Monkey. Values of the same type compare by
+    /// value, the elements of arrays and tuples in order and the pairs of
+    /// hashes in any order. Functions, builtins and macros are only equal
+    /// to themselves. An integer equals a float of the same value, so
+    /// `[2] == [sqrt(4)]`. Values of other different types are never
+    /// equal, so `1 == "1"` is false rather than an error.
+    pub fn monkey_eq(&self, other: &Object) -> bool {
+        match (self, other) {
+            (Self::Integer(i), Self::Float(f)) | (Self::Float(f), Self::Integer(i)) => {
+                *i as f64 == *f
+            }
+            (Self::Array(l), Self::Array(r)) => elements_eq(&l.elements, &r.elements),
+            (Self::Tuple(l), Self::Tuple(r)) => elements_eq(&l.elements, &r.elements),
+            (Self::Hash(l), Self::Hash(r)) => {
+                l.pairs.len() == r.pairs.len()
+                    && l.pairs.iter().all(|(key, value)| {
+                        r.pairs
+                            .iter()
+                            .any(|(k, v)| key.monkey_eq(k) && value.monkey_eq(v))
+                    })
+            }
+            (Self::Function(l), Self::Function(r)) => std::rc::Rc::ptr_eq(l, r),
+            (Self::Macro(l), Self::Macro(r)) => std::rc::Rc::ptr_eq(l, r),
+            _ => self == other,
+        }
+    }
+}
+
+/* `left operator right` as the evaluator does it, with its error */
+fn binary(left: &Object, right: &Object, operator: InfixOperator) -> Result<Object, EvalError> {
+    match crate::evaluator::eval_infix_expression(left, right, &operator) {
+        Object::Error(e) => Err(e),
+        obj => Ok(obj),
+    }
+}
+
+/// `+` on two values the way Monkey adds them: integers, numbers with a
+/// float in them as floats, and strings end to end. Anything else is the
+/// error the evaluator reports. `-`, `*` and `/` are the same.
+impl std::ops::Add for &Object {
+    type Output = Result<Object, EvalError>;
+
+    fn add(self, rhs: &Object) -> Self::Output {
+        binary(self, rhs, InfixOperator::Plus)
+    }
+}
+
+impl std::ops::Sub for &Object {
+    type Output = Result<Object, EvalError>;
+
+    fn sub(self, rhs: &Object) -> Self::Output {
+        binary(self, rhs, InfixOperator::Minus)
+    }
+}
+
+impl std::ops::Mul for &Object {
+    type Output = Result<Object, EvalError>;
+
+    fn mul(self, rhs: &Object) -> Self::Output {
+        binary(self, rhs, InfixOperator::Asterisk)
+    }
+}
+
+impl std::ops::Div for &Object {
+    type Output = Result<Object, EvalError>;
+
+    fn div(self, rhs: &Object) -> Self::Output {
+        binary(self, rhs, InfixOperator::Slash)
+    }
+}
+
+fn elements_eq(left: &[Object], right: &[Object]) -> bool {
+    left.len() == right.len() && left.iter().zip(right).all(|(l, r)| l.monkey_eq(r))
+}
+
+impl Object {
+    /// What `deepEqual(a, b)` means: like [`monkey_eq`](Object::monkey_eq)
+    /// all the way through arrays, tuples and hashes, except that two
+    /// functions or macros are equal when their parameters and bodies are,
+    /// whatever they captured, so `fn(x) { x }` equals another `fn(x) { x }`.
+    pub fn deep_eq(&self, other: &Object) -> bool {
+        match (self, other) {
+            (Self::Array(l), Self::Array(r)) => elements_deep_eq(&l.elements, &r.elements),
+            (Self::Tuple(l), Self::Tuple(r)) => elements_deep_eq(&l.elements, &r.elements),
+            (Self::Hash(l), Self::Hash(r)) => {
+                l.pairs.len() == r.pairs.len()
+                    && l.pairs.iter().all(|(key, value)| {
+                        r.pairs
+                            .iter()
+                            .any(|(k, v)| key.deep_eq(k) && value.deep_eq(v))
+                    })
+            }
+            (Self::Function(l), Self::Function(r)) => {
+                l.parameters == r.parameters && l.body == r.body
+            }
+            (Self::Macro(l), Self::Macro(r)) => l.parameters == r.parameters && l.body == r.body,
+            _ => self.monkey_eq(other),
+        }
+    }
+}
+
+fn elements_deep_eq(left: &[Object], right: &[Object]) -> bool {
+    left.len() == right.len() && left.iter().zip(right).all(|(l, r)| l.deep_eq(r))
+}
+
+/// How deeply [`Object::diff`] goes into arrays, tuples and hashes. Two
+/// values that differ further down are one difference.
+pub const MAX_DIFF_DEPTH: usize = 32;
+
+/// How many differences [`Object::diff`] gives at most, not counting the
+/// one that says there are more.
+pub const MAX_DIFFS: usize = 50;
+
+impl Object {
+    /// Where `other` differs from `self`, the value it was expected to be,
+    /// each difference with the path to it: `[2].name: expected "bob", got
+    /// "alice"`, `[0]: missing key "id"` or `length 3 vs 4`. Nothing when
+    /// they are [`monkey_eq`](Object::monkey_eq) all the way through.
+    pub fn diff(&self, other: &Object) -> Vec<String> {
+        let mut diff = Diff {
+            path: String::new(),
+            found: Vec::new(),
+        };
+        diff.values(self, other, 0);
+        diff.found
+    }
+}
+
+/* the differences found so far, and the path to the values being
+ * compared */
+struct Diff {
+    path: String,
+    found: Vec<String>,
+}
+
+impl Diff {
+    fn full(&self) -> bool {
+        self.found.len() > MAX_DIFFS
+    }
+
+    fn report(&mut self, what: String) {
+        if self.found.len() == MAX_DIFFS {
+            self.found.push("and more differences".to_owned());
+        }
+        if self.full() {
+            return;
+        }
+        match self.path.is_empty() {
+            true => self.found.push(what),
+            false => self.found.push(format!("{}: {}", self.path, what)),
+        }
+    }
+
+    fn values(&mut self, expected: &Object, got: &Object, depth: usize) {
+        if self.full() {
+            return;
+        }
+        let nested = matches!(
+            (expected, got),
+            (Object::Array(_), Object::Array(_))
+                | (Object::Tuple(_), Object::Tuple(_))
+                | (Object::Hash(_), Object::Hash(_))
+        );
+        if nested && depth == MAX_DIFF_DEPTH {
+            if !expected.monkey_eq(got) {
+                self.report("differs further down".to_owned());
+            }
+            return;
+        }
+        match (expected, got) {
+            (Object::Array(e), Object::Array(g)) => self.elements(&e.elements, &g.elements, depth),
+            (Object::Tuple(e), Object::Tuple(g)) => self.elements(&e.elements, &g.elements, depth),
+            (Object::Hash(e), Object::Hash(g)) => self.pairs(e, g, depth),
+            _ if expected.monkey_eq(got) => {}
+            _ if expected.type_val() == got.type_val() => {
+                self.report(format!("expected {}, got {}", shown(expected), shown(got)))
+            }
+            _ => self.report(format!(
+                "expected {} {}, got {} {}",
+                expected.type_val(),
+                shown(expected),
+                got.type_val(),
+                shown(got)
+            )),
+        }
+    }
+
+    fn elements(&mut self, expected: &[Object], got: &[Object], depth: usize) {
+        if expected.len() != got.len() {
+            self.report(format!("length {} vs {}", expected.len(), got.len()));
+        }
+        for (i, (e, g)) in expected.iter().zip(got).enumerate() {
+            let len = self.path.len();
+            self.path.push_str(&format!("[{}]", i));
+            self.values(e, g, depth + 1);
+            self.path.truncate(len);
+        }
+    }
+
+    fn pairs(&mut self, expected: &Hash, got: &Hash, depth: usize) {
+        for (key, e) in expected.pairs.iter() {
+            let Some(g) = got.get(key) else {
+                self.report(format!("missing key {}", shown(key)));
+                continue;
+            };
+            let len = self.path.len();
+            match key {
+                Object::String(s) if is_name(s) => self.path.push_str(&format!(".{}", s)),
+                _ => self.path.push_str(&format!("[{}]", shown(key))),
+            }
+            self.values(e, g, depth + 1);
+            self.path.truncate(len);
+        }
+        for (key, _) in got.pairs.iter() {
+            if expected.get(key).is_none() {
+                self.report(format!("unexpected key {}", shown(key)));
+            }
+        }
+    }
+}
+
+/* a value in a difference, a string in quotes so `"1"` isn't `1` */
+fn shown(obj: &Object) -> String {
+    match obj {
+        Object::String(s) => format!("{:?}", s),
+        obj => obj.inspect(),
+    }
+}
+
+/* whether `s` could be an identifier, and so a key `.s` in a path */
+fn is_name(s: &str) -> bool {
+    !s.is_empty() && s.chars().all(|ch| ch.is_alphabetic() || ch == '_')
 }
 
 impl ObjectTrait for Object {
@@ -67,39 +928,41 @@ impl ObjectTrait for Object {
         match self {
             Self::Null => ObjectType::Null,
             Self::Integer(_) => ObjectType::Integer,
+            Self::Float(_) => ObjectType::Float,
             Self::Boolean(_) => ObjectType::Boolean,
             Self::String(_) => ObjectType::String,
             Self::Return(_) => ObjectType::Return,
+            Self::Break(_) => ObjectType::Break,
+            Self::Continue => ObjectType::Continue,
             Self::Error(_) => ObjectType::Error,
             Self::Function(_) => ObjectType::Function,
             Self::Builtin(_) => ObjectType::Builtin,
             Self::Array(_) => ObjectType::Array,
+            Self::Tuple(_) => ObjectType::Tuple,
             Self::Hash(_) => ObjectType::Hash,
+            Self::Range(_) => ObjectType::Range,
+            Self::Iterator(_) => ObjectType::Iterator,
+            Self::Quote(_) => ObjectType::Quote,
+            Self::Macro(_) => ObjectType::Macro,
+            Self::Caught(_) => ObjectType::Caught,
         }
     }
     fn type_string(&self) -> &'static str {
-        match self {
-            Self::Null => "NULL",
-            Self::Integer(_) => "INTEGER",
-            Self::Boolean(_) => "BOOLEAN",
-            Self::String(_) => "STRING",
-            Self::Return(_) => "RETURN",
-            Self::Error(_) => "ERROR",
-            Self::Function(_) => "FUNCTION",
-            Self::Builtin(_) => "BUILTIN",
-            Self::Array(_) => "ARRAY",
-            Self::Hash(_) => "HASH",
-        }
+        self.type_val().as_str()
     }
 
     fn inspect(&self) -> String {
         match self {
             Self::Null => "null".to_owned(),
             Self::Integer(val) => val.to_string(),
+            Self::Float(val) => format!("{:?}", val),
             Self::Boolean(val) => val.to_string(),
             Self::String(val) => val.to_string(),
             Self::Return(val) => val.inspect(),
-            Self::Error(val) => "ERROR: ".to_owned() + &val,
+            Self::Break(_) => "break".to_owned(),
+            Self::Continue => "continue".to_owned(),
+            Self::Error(err) => format!("ERROR: {}", err),
+            Self::Caught(err) => format!("CAUGHT: {}", err),
             Self::Function(val) => {
                 let mut res = String::new();
                 res.push_str("fn(");
@@ -116,6 +979,23 @@ impl ObjectTrait for Object {
                 res
             }
             Self::Builtin(_) => "builtin function".to_owned(),
+            Self::Range(range) => format!("range({}, {})", range.start, range.end),
+            Self::Iterator(_) => "iterator".to_owned(),
+            Self::Quote(exp) => format!("QUOTE({})", exp.string()),
+            Self::Macro(val) => {
+                let mut res = String::new();
+                res.push_str("macro(");
+                for (i, param) in val.parameters.iter().enumerate() {
+                    res.push_str(&param.string());
+                    if i != val.parameters.len() - 1 {
+                        res.push_str(", ");
+                    }
+                }
+                res.push_str(") {\n");
+                res.push_str(&val.body.string());
+                res.push_str("\n}");
+                res
+            }
             Self::Array(val) => {
                 let mut res = String::new();
                 res.push('[');
@@ -128,6 +1008,18 @@ impl ObjectTrait for Object {
                 res.push(']');
                 res
             }
+            Self::Tuple(val) => {
+                let mut res = String::new();
+                res.push('(');
+                for (i, el) in val.elements.iter().enumerate() {
+                    res.push_str(&el.inspect());
+                    if i != val.elements.len() - 1 {
+                        res.push_str(", ");
+                    }
+                }
+                res.push(')');
+                res
+            }
             Self::Hash(hash) => {
                 let mut res = String::new();
                 res.push('{');
@@ -146,3 +1038,169 @@ impl ObjectTrait for Object {
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use crate::ast::InfixOperator;
+    use crate::environment::Environment;
+    use crate::evaluator::eval;
+    use crate::lexer::Lexer;
+    use crate::object::{
+        Array, EvalError, Hash, Object, ObjectTrait, ObjectType, Tuple, MAX_DIFFS, MAX_DIFF_DEPTH,
+    };
+    use crate::parser::Parser;
+
+    #[test]
+    fn test_operators() {
+        let int = Object::Integer;
+        let float = Object::Float;
+        let string = |s: &str| Object::String(s.into());
+        let tests = [
+            (&int(2) + &int(3), Ok(int(5))),
+            (&int(2) - &int(3), Ok(int(-1))),
+            (&int(2) * &int(3), Ok(int(6))),
+            (&int(7) / &int(2), Ok(int(3))),
+            (&int(7) / &int(0), Err(EvalError::DivisionByZero)),
+            // a float makes the other number a float
+            (&int(1) + &float(0.5), Ok(float(1.5))),
+            (&float(0.5) - &int(1), Ok(float(-0.5))),
+            (&float(1.5) * &float(2.0), Ok(float(3.0))),
+            (&int(3) / &float(2.0), Ok(float(1.5))),
+            (&float(1.0) / &int(0), Err(EvalError::DivisionByZero)),
+            (&string("ab") + &string("c"), Ok(string("abc"))),
+            (&string("ab") * &int(2), Ok(string("abab"))),
+            (
+                &string("ab") - &string("c"),
+                Err(EvalError::UnsupportedOperands {
+                    op: InfixOperator::Minus,
+                    left: ObjectType::String,
+                    right: ObjectType::String,
+                }),
+            ),
+            (
+                &int(1) + &Object::Boolean(true),
+                Err(EvalError::UnsupportedOperands {
+                    op: InfixOperator::Plus,
+                    left: ObjectType::Integer,
+                    right: ObjectType::Boolean,
+                }),
+            ),
+            (
+                &Object::Array(std::rc::Rc::new(Array {
+                    elements: vec![],
+                    frozen: false,
+                })) * &int(2),
+                Err(EvalError::UnsupportedOperands {
+                    op: InfixOperator::Asterisk,
+                    left: ObjectType::Array,
+                    right: ObjectType::Integer,
+                }),
+            ),
+        ];
+        for (i, (got, exp)) in tests.into_iter().enumerate() {
+            assert_eq!(got, exp, "case {}", i);
+        }
+    }
+
+    #[test]
+    fn test_hash() {
+        let int = Object::Integer;
+        let string = |s: &str| Object::String(s.into());
+        let tuple = |elements: Vec<Object>| Object::Tuple(std::rc::Rc::new(Tuple { elements }));
+        let mut hash = Hash::new();
+        hash.insert(string("a"), int(1));
+        hash.insert(int(2), int(2));
+        hash.insert(Object::Float(0.5), int(3));
+        hash.insert(tuple(vec![int(1), string("b")]), int(4));
+        hash.insert(string("a"), int(5));
+        hash.insert(Object::Float(0.5), int(6));
+        let keys: Vec<String> = hash.pairs().iter().map(|(k, _)| k.inspect()).collect();
+        assert_eq!(keys, ["a", "2", "0.5", "(1, b)"]);
+        let tests = [
+            (string("a"), Some(int(5))),
+            (int(2), Some(int(2))),
+            (Object::Float(0.5), Some(int(6))),
+            (tuple(vec![int(1), string("b")]), Some(int(4))),
+            (Object::Float(2.0), None),
+            (string("b"), None),
+            (Object::Boolean(true), None),
+        ];
+        for (key, exp) in tests {
+            assert_eq!(hash.get(&key), exp.as_ref(), "{}", key.inspect());
+        }
+        let collected: Hash = hash.pairs().iter().cloned().collect();
+        assert_eq!(collected, hash);
+    }
+
+    fn value(input: &str) -> Object {
+        let program = Parser::new(Lexer::new(input)).parse();
+        eval(&program, &mut Environment::new()).unwrap()
+    }
+
+    #[test]
+    fn test_diff() {
+        let tests: [(&str, &str, &[&str]); 11] = [
+            ("[1, {\"a\": [true]}]", "[1, {\"a\": [true]}]", &[]),
+            ("[1, 2]", "[1.0, 2]", &[]),
+            ("1", "2", &["expected 1, got 2"]),
+            ("\"1\"", "1", &["expected STRING \"1\", got INTEGER 1"]),
+            (
+                "[{}, {}, {\"name\": \"bob\"}]",
+                "[{}, {}, {\"name\": \"alice\"}]",
+                &["[2].name: expected \"bob\", got \"alice\""],
+            ),
+            (
+                "{\"id\": 1, \"tags\": [1, 2, 3]}",
+                "{\"tags\": [1, 5, 3, 4], \"extra\": true}",
+                &[
+                    "missing key \"id\"",
+                    ".tags: length 3 vs 4",
+                    ".tags[1]: expected 2, got 5",
+                    "unexpected key \"extra\"",
+                ],
+            ),
+            (
+                "{1: [\"x\"], \"a b\": 2}",
+                "{1: [if (false) { 1 }], \"a b\": 3}",
+                &[
+                    "[1][0]: expected STRING \"x\", got NULL null",
+                    "[\"a b\"]: expected 2, got 3",
+                ],
+            ),
+            (
+                "(1, [2])",
+                "(1, (2))",
+                &["[1]: expected ARRAY [2], got INTEGER 2"],
+            ),
+            ("[1]", "(1)", &["expected ARRAY [1], got INTEGER 1"]),
+            ("[]", "[[]]", &["length 0 vs 1"]),
+            ("len", "len", &[]),
+        ];
+        for (expected, got, exp) in tests {
+            let res = value(expected).diff(&value(got));
+            assert_eq!(res, exp, "{} vs {}", expected, got);
+        }
+    }
+
+    #[test]
+    fn test_diff_limits() {
+        let res = value("collect(range(0, 100))").diff(&value("collect(range(1, 101))"));
+        assert_eq!(res.len(), MAX_DIFFS + 1);
+        assert_eq!(res[0], "[0]: expected 0, got 1");
+        assert_eq!(res[MAX_DIFFS], "and more differences");
+
+        let nested = |leaf: &str| {
+            let mut res = leaf.to_owned();
+            for _ in 0..MAX_DIFF_DEPTH + 5 {
+                res = format!("[{}]", res);
+            }
+            value(&res)
+        };
+        let path = "[0]".repeat(MAX_DIFF_DEPTH);
+        assert_eq!(
+            nested("1").diff(&nested("2")),
+            [format!("{}: differs further down", path)]
+        );
+        assert!(nested("1").diff(&nested("1")).is_empty());
+    }
+}
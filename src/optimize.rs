@@ -0,0 +1,509 @@
+//! Rewrites of the AST that leave the meaning of a program unchanged.
+
+use std::rc::Rc;
+
+use crate::analysis::{always_returns, literal_truthiness};
+use crate::ast::{
+    ArrayLiteral, AssignExpression, BlockStatement, BooleanLiteral, CallExpression, Constant,
+    Expression, ExpressionStatement, ForExpression, FunctionLiteral, HashLiteral, IfExpression,
+    IndexExpression, InfixExpression, InfixOperator, IntegerLiteral, MacroLiteral,
+    PrefixExpression, PrefixOperator, Program, Statement, TupleLiteral,
+};
+use crate::token::Token;
+
+/// Evaluates what can be evaluated without running the program: integer
+/// and boolean operators and comparisons on literals, and `if` on a literal
+/// condition, which becomes the branch it takes.
+///
+/// Nothing that fails at runtime is folded. Division by zero and integer
+/// overflow stay in the tree, and so do operators on mismatched types, so
+/// the program still reports them when it runs. Chains like `x + 1 + 2`
+/// are left alone, `+` isn't reassociated.
+///
+/// A taken branch replaces the whole `if` when it is a single expression.
+/// One with more statements replaces an `if` statement inside a block,
+/// where its statements run in the same scope anyway. At the top level
+/// it's kept so every statement still has its own value for `eval_each`.
+pub fn fold_constants(program: Program) -> Program {
+    Program {
+        statements: program.statements.into_iter().map(statement).collect(),
+    }
+}
+
+fn statement(stmt: Statement) -> Statement {
+    map_statement(stmt, expression)
+}
+
+fn block(block: BlockStatement) -> BlockStatement {
+    let mut statements = Vec::with_capacity(block.statements.len());
+    for stmt in block.statements {
+        match statement(stmt) {
+            Statement::ExpressionStatement(ExpressionStatement {
+                expression: Expression::IfExpression(ife),
+                tok,
+            }) => match taken_branch(&ife) {
+                // an empty block has no value, unlike its last statement
+                Some(Some(branch)) if !branch.statements.is_empty() => {
+                    statements.extend(branch.statements.iter().cloned())
+                }
+                _ => statements.push(Statement::ExpressionStatement(ExpressionStatement {
+                    expression: Expression::IfExpression(ife),
+                    tok,
+                })),
+            },
+            stmt => statements.push(stmt),
+        }
+    }
+    BlockStatement {
+        tok: block.tok,
+        statements,
+    }
+}
+
+/* `Some` if the condition is a literal, with the branch it takes if any */
+fn taken_branch(ife: &IfExpression) -> Option<Option<&BlockStatement>> {
+    if literal_truthiness(&ife.condition)? {
+        Some(Some(&ife.consequence))
+    } else {
+        Some(ife.alternative.as_ref())
+    }
+}
+
+fn expression(exp: Expression) -> Expression {
+    let ie = match exp {
+        Expression::InfixExpression(ie) => ie,
+        exp => {
+            return match map_children(exp, expression, block) {
+                Expression::PrefixExpression(pe) => match prefix(&pe.operator, &pe.right) {
+                    Some(folded) => folded,
+                    None => Expression::PrefixExpression(pe),
+                },
+                Expression::IfExpression(ife) => {
+                    if let Some(Some(branch)) = taken_branch(&ife) {
+                        if let [Statement::ExpressionStatement(es)] = branch.statements.as_slice() {
+                            return es.expression.clone();
+                        }
+                    }
+                    Expression::IfExpression(ife)
+                }
+                exp => exp,
+            }
+        }
+    };
+    // a long `1 + 1 + ...` nests down the left, so take that spine apart in
+    // a loop and fold it back together from the bottom
+    let mut spine = Vec::new();
+    let mut exp = Expression::InfixExpression(ie);
+    let leftmost = loop {
+        match exp {
+            Expression::InfixExpression(ie) => {
+                spine.push((ie.tok, ie.operator, ie.right));
+                exp = Rc::unwrap_or_clone(ie.left);
+            }
+            exp => break exp,
+        }
+    };
+    let mut left = expression(leftmost);
+    for (tok, operator, right) in spine.into_iter().rev() {
+        let right = expression(Rc::unwrap_or_clone(right));
+        left = match infix(&left, &operator, &right) {
+            Some(folded) => folded,
+            None => Expression::InfixExpression(InfixExpression {
+                tok,
+                left: Rc::new(left),
+                operator,
+                right: Rc::new(right),
+            }),
+        };
+    }
+    left
+}
+
+/// Removes the code that never runs, the code `analysis::dead_code`
+/// reports: the statements after one that always returns, and the branch
+/// an `if` with a literal condition doesn't take. The `if` itself stays,
+/// with an empty consequence or without its `else`, so it still has the
+/// value it had.
+pub fn strip_dead_code(program: Program) -> Program {
+    Program {
+        statements: live_statements(program.statements),
+    }
+}
+
+fn live_statements(statements: Vec<Statement>) -> Vec<Statement> {
+    let mut res = Vec::with_capacity(statements.len());
+    for stmt in statements {
+        let stmt = map_statement(stmt, live_expression);
+        let returns = always_returns(&stmt);
+        res.push(stmt);
+        if returns {
+            break;
+        }
+    }
+    res
+}
+
+fn live_block(block: BlockStatement) -> BlockStatement {
+    BlockStatement {
+        tok: block.tok,
+        statements: live_statements(block.statements),
+    }
+}
+
+fn live_expression(exp: Expression) -> Expression {
+    let exp = match exp {
+        Expression::IfExpression(mut ife) => {
+            match literal_truthiness(&ife.condition) {
+                Some(true) => ife.alternative = None,
+                Some(false) => ife.consequence.statements.clear(),
+                None => {}
+            }
+            Expression::IfExpression(ife)
+        }
+        exp => exp,
+    };
+    map_children(exp, live_expression, live_block)
+}
+
+/* applies `f` to the expression of a statement */
+pub(crate) fn map_statement(stmt: Statement, f: impl Fn(Expression) -> Expression) -> Statement {
+    match stmt {
+        Statement::LetStatement(mut ls) => {
+            ls.value = f(ls.value);
+            Statement::LetStatement(ls)
+        }
+        Statement::LetTupleStatement(mut lts) => {
+            lts.value = f(lts.value);
+            Statement::LetTupleStatement(lts)
+        }
+        Statement::ReturnStatement(mut rs) => {
+            rs.value = f(rs.value);
+            Statement::ReturnStatement(rs)
+        }
+        Statement::ExpressionStatement(mut es) => {
+            es.expression = f(es.expression);
+            Statement::ExpressionStatement(es)
+        }
+        // the path of an import is a string literal already
+        stmt @ (Statement::BreakStatement(_)
+        | Statement::ContinueStatement(_)
+        | Statement::ImportStatement(_)) => stmt,
+    }
+}
+
+/* rebuilds `exp` with `f` applied to the expressions right below it and
+ * `g` to its blocks */
+pub(crate) fn map_children(
+    exp: Expression,
+    f: impl Fn(Expression) -> Expression + Copy,
+    g: impl Fn(BlockStatement) -> BlockStatement + Copy,
+) -> Expression {
+    let each = |exps: Vec<Expression>| exps.into_iter().map(f).collect();
+    let inner = |exp: Rc<Expression>| Rc::new(f(Rc::unwrap_or_clone(exp)));
+    match exp {
+        Expression::Identifier(_)
+        | Expression::Integer(_)
+        | Expression::Float(_)
+        | Expression::String(_)
+        | Expression::Boolean(_) => exp,
+        Expression::Array(al) => {
+            let elements: Vec<Expression> = each(al.elements);
+            Expression::Array(ArrayLiteral {
+                tok: al.tok,
+                constant: Constant::of(&elements),
+                elements,
+            })
+        }
+        Expression::Tuple(tl) => {
+            let elements: Vec<Expression> = each(tl.elements);
+            Expression::Tuple(TupleLiteral {
+                tok: tl.tok,
+                constant: Constant::of(&elements),
+                elements,
+            })
+        }
+        Expression::Hash(hl) => {
+            let pairs: Vec<(Expression, Expression)> = hl
+                .pairs
+                .into_iter()
+                .map(|(key, value)| (f(key), f(value)))
+                .collect();
+            Expression::Hash(HashLiteral {
+                tok: hl.tok,
+                constant: Constant::of(pairs.iter().flat_map(|(key, value)| [key, value])),
+                pairs,
+            })
+        }
+        Expression::PrefixExpression(pe) => Expression::PrefixExpression(PrefixExpression {
+            tok: pe.tok,
+            operator: pe.operator,
+            right: inner(pe.right),
+        }),
+        Expression::InfixExpression(ie) => {
+            // `f` only sees the operands at the ends of the left spine, so
+            // a long chain doesn't recurse through it
+            let mut spine = Vec::new();
+            let mut exp = Expression::InfixExpression(ie);
+            let leftmost = loop {
+                match exp {
+                    Expression::InfixExpression(ie) => {
+                        spine.push((ie.tok, ie.operator, ie.right));
+                        exp = Rc::unwrap_or_clone(ie.left);
+                    }
+                    exp => break exp,
+                }
+            };
+            let mut left = f(leftmost);
+            for (tok, operator, right) in spine.into_iter().rev() {
+                left = Expression::InfixExpression(InfixExpression {
+                    tok,
+                    left: Rc::new(left),
+                    operator,
+                    right: inner(right),
+                });
+            }
+            left
+        }
+        Expression::Assign(ae) => Expression::Assign(AssignExpression {
+            tok: ae.tok,
+            name: ae.name,
+            value: inner(ae.value),
+        }),
+        Expression::IfExpression(ife) => Expression::IfExpression(IfExpression {
+            tok: ife.tok,
+            condition: inner(ife.condition),
+            consequence: g(ife.consequence),
+            alternative: ife.alternative.map(g),
+        }),
+        Expression::ForExpression(fe) => Expression::ForExpression(ForExpression {
+            tok: fe.tok,
+            label: fe.label,
+            names: fe.names,
+            iterable: inner(fe.iterable),
+            body: g(fe.body),
+        }),
+        Expression::FunctionLiteral(fl) => Expression::FunctionLiteral(FunctionLiteral {
+            tok: fl.tok,
+            parameters: fl.parameters,
+            body: g(fl.body),
+        }),
+        Expression::MacroLiteral(ml) => Expression::MacroLiteral(MacroLiteral {
+            tok: ml.tok,
+            parameters: ml.parameters,
+            body: g(ml.body),
+        }),
+        Expression::CallExpression(ce) => Expression::CallExpression(CallExpression {
+            tok: ce.tok,
+            function: inner(ce.function),
+            arguments: each(ce.arguments),
+        }),
+        Expression::IndexExpression(ie) => Expression::IndexExpression(IndexExpression {
+            tok: ie.tok,
+            left: inner(ie.left),
+            index: inner(ie.index),
+        }),
+    }
+}
+
+/* the same results as the evaluator's operators, `None` where it errors */
+fn prefix(operator: &PrefixOperator, right: &Expression) -> Option<Expression> {
+    match (operator, right) {
+        (PrefixOperator::Bang, Expression::Boolean(b)) => Some(boolean(!b.value)),
+        (PrefixOperator::Bang, Expression::Integer(_)) => Some(boolean(false)),
+        (PrefixOperator::Minus, Expression::Integer(i)) => Some(integer(i.value.checked_neg()?)),
+        _ => None,
+    }
+}
+
+fn infix(left: &Expression, operator: &InfixOperator, right: &Expression) -> Option<Expression> {
+    match (left, right) {
+        (Expression::Integer(l), Expression::Integer(r)) => {
+            let (l, r) = (l.value, r.value);
+            let res = match operator {
+                InfixOperator::Plus => integer(l.checked_add(r)?),
+                InfixOperator::Minus => integer(l.checked_sub(r)?),
+                InfixOperator::Asterisk => integer(l.checked_mul(r)?),
+                InfixOperator::Slash => integer(l.checked_div(r)?),
+                InfixOperator::Eq => boolean(l == r),
+                InfixOperator::NotEq => boolean(l != r),
+                InfixOperator::Lt => boolean(l < r),
+                InfixOperator::Gt => boolean(l > r),
+            };
+            Some(res)
+        }
+        (Expression::Boolean(l), Expression::Boolean(r)) => match operator {
+            InfixOperator::Eq => Some(boolean(l.value == r.value)),
+            InfixOperator::NotEq => Some(boolean(l.value != r.value)),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+pub(crate) fn integer(value: i64) -> Expression {
+    Expression::Integer(IntegerLiteral {
+        tok: Token::Int(value.to_string().into()),
+        value,
+    })
+}
+
+pub(crate) fn boolean(value: bool) -> Expression {
+    Expression::Boolean(BooleanLiteral {
+        tok: if value { Token::True } else { Token::False },
+        value,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use crate::ast::{Node, Program};
+    use crate::environment::Environment;
+    use crate::evaluator::eval_each;
+    use crate::lexer::Lexer;
+    use crate::optimize::{fold_constants, strip_dead_code};
+    use crate::parser::Parser;
+
+    fn parse(input: &str) -> Program {
+        let mut p = Parser::new(Lexer::new(input));
+        let program = p.parse();
+        assert!(p.get_errors().is_empty(), "{:?}", p.get_errors());
+        program
+    }
+
+    #[test]
+    fn test_fold_constants() {
+        let tests = [
+            ("1 + 2 * 3", "7"),
+            ("!(true == true)", "false"),
+            ("x + 1 + 2", "((x + 1) + 2)"),
+            ("x + (1 + 2)", "(x + 3)"),
+            ("-5 - -5", "0"),
+            ("!5", "false"),
+            ("!!true", "true"),
+            ("10 / 3 > 3", "false"),
+            ("1 != 2 == true", "true"),
+            (
+                "let a = [1 + 1, {2 * 2: f(3 - 1)}][0 + 0];",
+                "let a = ([2, {4:f(2)}][0]);",
+            ),
+            ("fn(x) { return x * (2 * 2); }", "fn(x) { return (x * 4); }"),
+            ("if (1 < 2) { 10 } else { 20 }", "10"),
+            ("if (false) { 10 } else { 20 + 1 }", "21"),
+            ("if (0) { 10 }", "10"),
+            ("if (x) { 1 + 1 }", "if (x) { 2 }"),
+            // no branch to take, the value is null
+            ("if (false) { 10 }", "if (false) { 10 }"),
+            // at the top level every statement keeps its own value
+            ("if (true) { let a = 1; a }", "if (true) { let a = 1; a }"),
+            (
+                "fn() { if (true) { let a = 1; a } }",
+                "fn() { let a = 1; a }",
+            ),
+            ("fn() { 5; if (true) {} }", "fn() { 5; if (true) {} }"),
+            (
+                "let y = if (true) { let a = 1; a };",
+                "let y = if (true) { let a = 1; a };",
+            ),
+        ];
+        for (input, exp) in tests {
+            let folded = fold_constants(parse(input));
+            assert_eq!(folded.string(), exp, "folding {:?}", input);
+        }
+    }
+
+    #[test]
+    fn test_fold_constants_keeps_runtime_errors() {
+        let tests = [
+            ("1 / 0", "(1 / 0)"),
+            ("x / (2 - 2)", "(x / 0)"),
+            ("9223372036854775807 + 1", "(9223372036854775807 + 1)"),
+            ("-9223372036854775807 - 2", "(-9223372036854775807 - 2)"),
+            ("4611686018427387904 * 2", "(4611686018427387904 * 2)"),
+            (
+                "(0 - 9223372036854775807 - 1) / -1",
+                "(-9223372036854775808 / -1)",
+            ),
+            ("-true", "(-true)"),
+            ("1 == true", "(1 == true)"),
+            ("true < false", "(true < false)"),
+            ("true + 1 + 2", "((true + 1) + 2)"),
+        ];
+        for (input, exp) in tests {
+            let folded = fold_constants(parse(input));
+            assert_eq!(folded.string(), exp, "folding {:?}", input);
+        }
+    }
+
+    #[test]
+    fn test_fold_constants_preserves_values() {
+        let inputs = [
+            "let x = 2; x * (3 + 4); -(1 - 10)",
+            "1 / 0; 2",
+            "let f = fn(n) { if (1 > 0) { let m = n * 2; m + 1 } else { 0 } }; f(4)",
+            "let g = fn() { if (true) { return 1 + 1; } 3 }; g()",
+            "if (true) { let a = 1; a } if (false) { 1 }",
+            "[!5, !!0, 1 == 1 != false, \"a\" + \"b\"]",
+            "true < false",
+        ];
+        for input in inputs {
+            let program = parse(input);
+            let exp = eval_each(&program, &mut Environment::new());
+            let got = eval_each(&fold_constants(program), &mut Environment::new());
+            assert_eq!(got, exp, "evaluating {:?}", input);
+        }
+    }
+
+    #[test]
+    fn test_strip_dead_code() {
+        let tests = [
+            ("return 1; 2; let x = 3;", "return 1;"),
+            ("fn() { return 1; 2 }", "fn() { return 1; }"),
+            // the code after an if is only dead if both branches return
+            (
+                "fn(x) { if (x) { return 1; } 2 }",
+                "fn(x) { if (x) { return 1; } 2 }",
+            ),
+            (
+                "fn(x) { if (x) { return 1; } else { return 2; } 3 }",
+                "fn(x) { if (x) { return 1; } else { return 2; } }",
+            ),
+            (
+                "fn(x) { if (x) { if (x) { return 1; 2 } 3 } 4 }",
+                "fn(x) { if (x) { if (x) { return 1; } 3 } 4 }",
+            ),
+            ("if (false) { 1 } else { 2 }", "if (false) {} else { 2 }"),
+            ("if (true) { 1 } else { 2 }", "if (true) { 1 }"),
+            (
+                "fn() { if (true) { return 1; } else { 2 } 3 }",
+                "fn() { if (true) { return 1; } }",
+            ),
+            ("let a = 1 + 2 + 3; a", "let a = 1 + 2 + 3; a"),
+        ];
+        for (input, exp) in tests {
+            let stripped = strip_dead_code(parse(input));
+            assert_eq!(
+                stripped.string(),
+                parse(exp).string(),
+                "stripping {:?}",
+                input
+            );
+        }
+    }
+
+    #[test]
+    fn test_strip_dead_code_preserves_values() {
+        let inputs = [
+            "return 1; 2",
+            "5; if (false) { 1 }; if (true) { 2 } else { 3 }",
+            "let f = fn(x) { if (x > 0) { return x; } -x }; [f(2), f(-3)]",
+            "let g = fn(x) { if (x) { return 1; } else { return 2; } 3 }; [g(true), g(false)]",
+            "let h = fn() { if (true) { return 1; } 2 }; h()",
+        ];
+        for input in inputs {
+            let program = parse(input);
+            let exp = eval_each(&program, &mut Environment::new());
+            let got = eval_each(&strip_dead_code(program), &mut Environment::new());
+            assert_eq!(got, exp, "evaluating {:?}", input);
+        }
+    }
+}
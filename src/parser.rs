@@ -1,57 +1,316 @@
-use crate::ast::{
-    ArrayLiteral, BlockStatement, BooleanLiteral, CallExpression, Expression, ExpressionStatement,
-    FunctionLiteral, HashLiteral, Identifier, IfExpression, IndexExpression, InfixExpression,
-    InfixOperator, IntegerLiteral, LetStatement, PrefixExpression, PrefixOperator, Program,
-    ReturnStatement, Statement, StringLiteral,
-};
+use std::collections::VecDeque;
+
+use crate::ast::{InfixOperator, PrefixOperator, Program, Statement};
 use crate::lexer::Lexer;
 use crate::token::Token;
+use crate::util::{parse_float_literal, parse_int_literal};
+
+#[cfg(feature = "bump")]
+mod arena;
+mod build;
+mod shadowing;
+
+use build::{List, NodeBuilder, Owned};
+
+/// How deeply expressions may nest before the parser gives up, so a
+/// pathological input like `((((...` reports an error instead of
+/// overflowing the stack.
+pub const MAX_NESTING_DEPTH: usize = 128;
+
+/// How many parameters a function literal and arguments a call may have
+/// unless [`with_max_arguments`](Parser::with_max_arguments) says
+/// otherwise.
+pub const MAX_ARGUMENTS: usize = 255;
+
+/// Lexes and parses arbitrary bytes, which is all a fuzzer needs to call.
+/// Bytes that aren't UTF-8 are replaced, as a file read lossily would be.
+/// Bad input only ever produces errors, so this panicking is a bug.
+pub fn parse_dont_panic(data: &[u8]) {
+    let src = String::from_utf8_lossy(data);
+    let mut p = Parser::new(Lexer::new(&src)).with_source_map();
+    p.parse();
+    // parsing again after a reset goes through the same paths from a
+    // different starting state
+    p.reset(&src);
+    p.parse();
+}
 
 pub struct Parser {
-    l: Lexer,
+    tokens: TokenSource,
     cur: Token,
     peek: Token,
+    /* the tokens after `peek` that `peek_n` has already read */
+    ahead: VecDeque<Token>,
+    /* how many tokens have been taken from `tokens`, for `position` */
+    read: usize,
     errors: Vec<String>,
+    /* in step with `errors`, the `position` of the token each one is at */
+    error_tokens: Vec<Option<usize>>,
+    depth: usize,
+    /* the labels of the loops the current function body is inside,
+     * innermost last */
+    loops: Vec<Option<std::rc::Rc<str>>>,
+    /* the label before the `for` about to be parsed */
+    label: Option<std::rc::Rc<str>>,
+    no_shadowing: bool,
+    max_arguments: usize,
+    /* `read` panics past this, see `with_max_reads` */
+    max_reads: usize,
+    /* `with_source_map`'s ranges: one for each statement parsed so far,
+     * and while a statement is being parsed, one for each expression of
+     * it that isn't below another one yet */
+    source_map: Option<Vec<SourceMap>>,
+}
+
+/// The tokens an expression was parsed from, as the range of their
+/// indices into the tokens of the source without comments, and the same
+/// for the expressions right below it, in the order of the paths of
+/// [`Program::replace_expr_at`]. The tokens of a parenthesized
+/// expression include the parentheses.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SourceMap {
+    pub token_range: (usize, usize),
+    pub children: Vec<SourceMap>,
+    /// For the value of a `let`, a `const` or a function declaration, the
+    /// range of the tokens of the names it binds, from the first to the
+    /// last.
+    pub names: Option<(usize, usize)>,
 }
 
-#[derive(Eq, PartialEq, PartialOrd, Ord)]
-enum Precedence {
+impl SourceMap {
+    /// The token range of the expression at `path` below this one, so an
+    /// empty `path` is this expression's.
+    pub fn token_range(&self, path: &[usize]) -> Option<(usize, usize)> {
+        Some(self.at(path)?.token_range)
+    }
+
+    /// The [`names`](SourceMap::names) of the expression at `path` below
+    /// this one.
+    pub fn names(&self, path: &[usize]) -> Option<(usize, usize)> {
+        self.at(path)?.names
+    }
+
+    fn at(&self, path: &[usize]) -> Option<&SourceMap> {
+        let mut target = self;
+        for i in path {
+            target = target.children.get(*i)?;
+        }
+        Some(target)
+    }
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq, PartialOrd, Ord)]
+pub enum Precedence {
     Lowest = 0,
-    Equals = 1,
-    LessGreater = 2,
-    Sum = 3,
-    Product = 4,
-    Prefix = 5,
-    Call = 6,
-    Index = 7,
+    Assign = 1,
+    Pipe = 2,
+    Equals = 3,
+    LessGreater = 4,
+    Sum = 5,
+    Product = 6,
+    Prefix = 7,
+    Call = 8,
+    Index = 9,
+}
+
+/* where the parser's tokens come from */
+enum TokenSource {
+    Lexer(Lexer),
+    Tokens(std::vec::IntoIter<Token>),
+}
+
+impl TokenSource {
+    /* the parser has no use for comments, even from a lexer that keeps them */
+    fn next_token(&mut self) -> Token {
+        loop {
+            let tok = match self {
+                TokenSource::Lexer(l) => l.next_token(),
+                TokenSource::Tokens(tokens) => tokens.next().unwrap_or(Token::Eof),
+            };
+            if !matches!(tok, Token::Comment(_)) {
+                return tok;
+            }
+        }
+    }
+
+    fn take_errors(&mut self) -> Vec<String> {
+        match self {
+            TokenSource::Lexer(l) => l.take_errors(),
+            TokenSource::Tokens(_) => Vec::new(),
+        }
+    }
 }
 
 impl Parser {
-    pub fn new(mut l: Lexer) -> Self {
-        let cur = l.next_token();
-        let peek = l.next_token();
-        let errors = Vec::new();
-        Parser {
-            l,
+    pub fn new(l: Lexer) -> Self {
+        Self::with_source(TokenSource::Lexer(l))
+    }
+
+    /// Parses tokens that were lexed or made up beforehand, instead of
+    /// lexing source. The tokens don't need to end with a `Token::Eof`,
+    /// running out of them is the end of input.
+    pub fn from_tokens(tokens: Vec<Token>) -> Self {
+        Self::with_source(TokenSource::Tokens(tokens.into_iter()))
+    }
+
+    fn with_source(mut tokens: TokenSource) -> Self {
+        let cur = tokens.next_token();
+        let peek = tokens.next_token();
+        let mut p = Parser {
+            tokens,
             cur,
             peek,
-            errors,
+            ahead: VecDeque::new(),
+            read: 2,
+            errors: Vec::new(),
+            error_tokens: Vec::new(),
+            depth: 0,
+            loops: Vec::new(),
+            label: None,
+            no_shadowing: false,
+            max_arguments: MAX_ARGUMENTS,
+            max_reads: usize::MAX,
+            source_map: None,
+        };
+        p.take_lexer_errors();
+        p
+    }
+
+    /// Reports a `let` that re-declares a name already bound in the same
+    /// scope as `redeclaration of 'x'`. Monkey allows shadowing, so this is
+    /// off by default. Shadowing a name inside a function body is still
+    /// fine. Only `parse` runs the check.
+    pub fn with_no_shadowing(mut self) -> Self {
+        self.no_shadowing = true;
+        self
+    }
+
+    /// Reports a function literal with more than `max` parameters as `too
+    /// many parameters, the limit is max`, and a call with more than `max`
+    /// arguments as `too many arguments, ...`. The parser stops at the
+    /// first one over, so it never collects them all. It's
+    /// [`MAX_ARGUMENTS`] by default.
+    pub fn with_max_arguments(mut self, max: usize) -> Self {
+        self.max_arguments = max;
+        self
+    }
+
+    /// Panics once a parse has read more than `max` tokens, counting each
+    /// time the parser reads the `Token::Eof` at the end again. A parse
+    /// reads every other token once and the `Token::Eof` only a few times,
+    /// so a fuzzer can catch input the parser gets stuck on with a `max` a
+    /// little over the token count, long before it would time out.
+    pub fn with_max_reads(mut self, max: usize) -> Self {
+        self.max_reads = max;
+        self
+    }
+
+    /// Records the tokens each expression is parsed from, for
+    /// [`source_map`](Parser::source_map). It's off by default, since it
+    /// costs an allocation for every expression.
+    pub fn with_source_map(mut self) -> Self {
+        self.source_map = Some(Vec::new());
+        self
+    }
+
+    /// The [`SourceMap`] of the expression of each statement parsed since
+    /// the parser was made or reset, in order, or nothing without
+    /// `with_source_map`. The ranges are only meaningful for a parse
+    /// without errors.
+    pub fn source_map(&self) -> &[SourceMap] {
+        self.source_map.as_deref().unwrap_or_default()
+    }
+
+    /// The token range of the expression at `path`, a path of
+    /// [`Program::replace_expr_at`] into the statements parsed since the
+    /// parser was made or reset.
+    pub fn token_range(&self, path: &[usize]) -> Option<(usize, usize)> {
+        let (first, rest) = path.split_first()?;
+        self.source_map().get(*first)?.token_range(rest)
+    }
+
+    /// Points the parser at a new `source`, reusing the lexer and the error
+    /// buffer. Errors from the previous parse are dropped, options are kept.
+    /// A parser made `from_tokens` gets a lexer.
+    pub fn reset(&mut self, source: &str) {
+        match &mut self.tokens {
+            TokenSource::Lexer(l) => l.reset(source),
+            TokenSource::Tokens(_) => self.tokens = TokenSource::Lexer(Lexer::new(source)),
+        }
+        self.errors.clear();
+        self.error_tokens.clear();
+        if let Some(ranges) = &mut self.source_map {
+            ranges.clear();
         }
+        self.depth = 0;
+        self.loops.clear();
+        self.label = None;
+        self.ahead.clear();
+        self.read = 2;
+        self.cur = self.tokens.next_token();
+        self.peek = self.tokens.next_token();
+        self.take_lexer_errors();
     }
 
     pub fn parse(&mut self) -> Program {
-        let mut res: Vec<Statement> = Vec::new();
-        while self.cur != Token::Eof {
-            let stmt = self.parse_statement();
-            match stmt {
-                Some(s) => res.push(s),
-                None => {}
-            }
-            self.next_token();
+        let mut res: Vec<Statement> = Vec::with_capacity(self.statements_hint());
+        self.parse_into(&mut res, usize::MAX);
+        if self.no_shadowing {
+            shadowing::check_redeclarations(&res, &mut self.errors);
+            self.error_tokens.resize(self.errors.len(), None);
         }
         Program { statements: res }
     }
 
+    /// Parses up to `limit` statements and appends them to `buf`, so a
+    /// caller that parses one input after another can keep them all in one
+    /// buffer. Returns how many were appended, which is less than `limit`
+    /// only once the input runs out. Errors go to the parser's errors as
+    /// with `parse`, but the `with_no_shadowing` check doesn't run.
+    pub fn parse_into(&mut self, buf: &mut Vec<Statement>, limit: usize) -> usize {
+        let start = buf.len();
+        while self.cur != Token::Eof && buf.len() - start < limit {
+            if let Some(s) = self.parse_top_level() {
+                buf.push(s)
+            }
+        }
+        buf.len() - start
+    }
+
+    /* one turn of `parse_into`'s loop, which leaves the parser on the token
+     * after the statement. The incremental reparser drives the parser
+     * through these, since between them it holds no state but its
+     * position and errors. */
+    pub(crate) fn parse_top_level(&mut self) -> Option<Statement> {
+        let stmt = self.parse_statement(Owned);
+        self.next_token();
+        stmt
+    }
+
+    /* the index of `cur` among the tokens read, comments not counted */
+    pub(crate) fn position(&self) -> usize {
+        self.read - 2 - self.ahead.len()
+    }
+
+    /* how many tokens have been read, which includes every token that
+     * `cur`, `peek` and the lookahead have looked at */
+    pub(crate) fn tokens_read(&self) -> usize {
+        self.read
+    }
+
+    pub(crate) fn at_eof(&self) -> bool {
+        self.cur == Token::Eof
+    }
+
+    /* a guess at the statement count, about one per short line */
+    fn statements_hint(&self) -> usize {
+        let hint = match &self.tokens {
+            TokenSource::Lexer(l) => l.input_len() / 32,
+            TokenSource::Tokens(tokens) => tokens.len() / 8,
+        };
+        hint.min(1024)
+    }
+
     pub fn errors_len(&self) -> usize {
         self.errors.len()
     }
@@ -60,135 +319,428 @@ impl Parser {
         &self.errors
     }
 
-    fn parse_statement(&mut self) -> Option<Statement> {
+    /// The token each of [`get_errors`](Parser::get_errors) was reported
+    /// at, as an index into the tokens of the source without comments, the
+    /// final `Token::Eof` included. The redeclarations `with_no_shadowing`
+    /// reports are found after parsing and have none.
+    pub fn error_tokens(&self) -> &[Option<usize>] {
+        &self.error_tokens
+    }
+
+    fn parse_statement<B: NodeBuilder>(&mut self, b: B) -> Option<B::Stmt> {
+        let recorded = self.source_map.as_ref().map_or(0, Vec::len);
+        let stmt = self.parse_statement_kind(b);
+        if stmt.is_none() {
+            // the ranges of what it parsed before it failed
+            if let Some(ranges) = &mut self.source_map {
+                ranges.truncate(recorded);
+            }
+        }
+        stmt
+    }
+
+    fn parse_statement_kind<B: NodeBuilder>(&mut self, b: B) -> Option<B::Stmt> {
+        if self.cur == Token::LSquirly && self.starts_block() {
+            self.block_error();
+            return None;
+        }
+        if matches!(self.cur, Token::Ident(_)) && self.peek_token_is(&Token::Colon) {
+            self.label = Some(self.loop_label()?);
+        }
         match &self.cur {
-            Token::Let => self.parse_let_statement(),
-            Token::Return => self.parse_return_statement(),
-            _ => self.parse_expression_statement(),
+            Token::Let | Token::Const => self.parse_let_statement(b),
+            Token::Return => self.parse_return_statement(b),
+            Token::Break | Token::Continue => self.parse_loop_control(b),
+            Token::Import => self.parse_import_statement(b),
+            Token::Function if matches!(self.peek, Token::Ident(_)) => {
+                self.parse_function_declaration(b)
+            }
+            _ => self.parse_expression_statement(b),
+        }
+    }
+
+    /* `fn name(x) { ... }`, which is `let name = fn(x) { ... };` */
+    fn parse_function_declaration<B: NodeBuilder>(&mut self, b: B) -> Option<B::Stmt> {
+        let start = self.position();
+        let tok = std::mem::take(&mut self.cur);
+        self.next_token();
+        let Token::Ident(value) = &self.cur else {
+            unreachable!("parse_function_declaration without a name");
+        };
+        let name = b.name(self.cur.clone(), value);
+        let function = self.parse_function(b, tok.clone())?;
+        let value = self.record_range(b, Some(start), b.function_literal(function));
+        self.record_names(start + 1, start + 1);
+        if self.peek_token_is(&Token::Semicolon) {
+            self.next_token();
         }
+        Some(b.function_declaration(tok, name, value))
     }
 
-    fn parse_let_statement(&mut self) -> Option<Statement> {
+    fn parse_let_statement<B: NodeBuilder>(&mut self, b: B) -> Option<B::Stmt> {
         let tok = std::mem::take(&mut self.cur);
-        let name: Identifier;
+        let mutable = tok == Token::Let;
+        let first = self.position() + 1;
+        let mut names = b.names();
         if let Token::Ident(v) = self.peek.clone() {
             self.next_token();
-            name = Identifier {
-                tok: std::mem::take(&mut self.cur),
-                value: v.clone(),
-            }
+            names.push(b.name(std::mem::take(&mut self.cur), &v));
         } else {
             let e = format!(
                 "expected next token to be Token::Ident, got {:#?} instead",
                 self.peek
             );
-            self.errors.push(e);
+            self.error_at(e, self.position() + 1);
             return None;
         }
+        while self.peek_token_is(&Token::Comma) {
+            self.next_token();
+            self.next_token();
+            match &self.cur {
+                Token::Ident(v) => names.push(b.name(self.cur.clone(), v)),
+                _ => {
+                    self.ident_error();
+                    return None;
+                }
+            }
+        }
+        let last = self.position();
         if !self.expect_peek(Token::Assign) {
             return None;
         }
         self.next_token();
-        let value_opt = self.parse_expression(Precedence::Lowest);
-        let res = match value_opt {
-            Some(value) => Some(Statement::LetStatement(LetStatement { tok, name, value })),
-            None => None,
-        };
+        let value_opt = self.parse_expression(b, Precedence::Lowest);
+        if value_opt.is_some() {
+            self.record_names(first, last);
+        }
+        let res = value_opt.map(|value| b.let_statement(tok, names, value, mutable));
         if self.peek_token_is(&Token::Semicolon) {
             self.next_token();
         }
         res
     }
 
-    fn parse_return_statement(&mut self) -> Option<Statement> {
+    fn parse_return_statement<B: NodeBuilder>(&mut self, b: B) -> Option<B::Stmt> {
         let tok = std::mem::take(&mut self.cur);
         self.next_token();
-        let value_opt = self.parse_expression(Precedence::Lowest);
-        let res = match value_opt {
-            Some(value) => Some(Statement::ReturnStatement(ReturnStatement { tok, value })),
-            None => None,
-        };
+        let mut value_opt = self.parse_expression(b, Precedence::Lowest);
+        if self.peek_token_is(&Token::Comma) {
+            // `return a, b;` is the same as `return (a, b);`
+            let mut elements = b.exprs();
+            elements.push(value_opt?);
+            let tuple_tok = self.peek.clone();
+            while self.peek_token_is(&Token::Comma) {
+                self.next_token();
+                self.next_token();
+                elements.push(self.parse_expression(b, Precedence::Lowest)?);
+            }
+            value_opt = Some(self.record_range(b, None, b.tuple(tuple_tok, elements)));
+        }
+        let res = value_opt.map(|value| b.return_statement(tok, value));
         if self.peek_token_is(&Token::Semicolon) {
             self.next_token();
         }
         res
     }
 
-    fn parse_expression_statement(&mut self) -> Option<Statement> {
+    fn parse_loop_control<B: NodeBuilder>(&mut self, b: B) -> Option<B::Stmt> {
         let tok = self.cur.clone();
-        match self.parse_expression(Precedence::Lowest) {
-            Some(e) => {
-                let res = Some(Statement::ExpressionStatement(ExpressionStatement {
-                    tok,
-                    expression: e,
-                }));
-                if self.peek_token_is(&Token::Semicolon) {
-                    self.next_token();
-                }
-                res
+        let label = self.loop_control()?;
+        match tok {
+            Token::Break => {
+                let label = label.map(|label| b.name(Token::Ident(label.clone()), &label));
+                Some(b.break_statement(tok, label))
+            }
+            _ => Some(b.continue_statement(tok)),
+        }
+    }
+
+    /* `import "path";` or `import "path" as name;`. The path is parsed as
+     * an expression so it gets a range like any statement's expression. */
+    fn parse_import_statement<B: NodeBuilder>(&mut self, b: B) -> Option<B::Stmt> {
+        let tok = std::mem::take(&mut self.cur);
+        if !matches!(self.peek, Token::String(_)) {
+            let e = format!(
+                "expected next token to be Token::String, got {:#?} instead",
+                self.peek
+            );
+            self.error_at(e, self.position() + 1);
+            return None;
+        }
+        self.next_token();
+        let path = self.parse_expression(b, Precedence::Lowest)?;
+        if !B::is_string(&path) {
+            self.error(format!(
+                "import needs a string literal, got {}",
+                B::string(&path)
+            ));
+            return None;
+        }
+        let mut alias = None;
+        if matches!(&self.peek, Token::Ident(v) if &**v == "as") {
+            self.next_token();
+            let Token::Ident(value) = self.peek.clone() else {
+                let e = format!(
+                    "expected next token to be Token::Ident, got {:#?} instead",
+                    self.peek
+                );
+                self.error_at(e, self.position() + 1);
+                return None;
+            };
+            self.next_token();
+            alias = Some(b.name(std::mem::take(&mut self.cur), &value));
+        }
+        if self.peek_token_is(&Token::Semicolon) {
+            self.next_token();
+        }
+        Some(b.import(tok, path, alias))
+    }
+
+    fn parse_expression_statement<B: NodeBuilder>(&mut self, b: B) -> Option<B::Stmt> {
+        let tok = self.cur.clone();
+        let expression = self.parse_expression(b, Precedence::Lowest)?;
+        if self.peek_token_is(&Token::Semicolon) {
+            self.next_token();
+        }
+        Some(b.expression_statement(tok, expression))
+    }
+
+    /// Parses infix chains with an explicit operand/operator stack instead of
+    /// recursing for every right operand, so `a + b + c + ...` uses a constant
+    /// amount of Rust stack no matter how long it is. Only genuinely nested
+    /// constructs (prefix operators, groups, literals with bodies) recurse.
+    fn parse_expression<B: NodeBuilder>(
+        &mut self,
+        b: B,
+        precedence: Precedence,
+    ) -> Option<B::Expr> {
+        if !self.enter_nesting() {
+            return None;
+        }
+        let res = self.parse_infix_chain(b, precedence);
+        self.depth -= 1;
+        res
+    }
+
+    fn parse_infix_chain<B: NodeBuilder>(
+        &mut self,
+        b: B,
+        precedence: Precedence,
+    ) -> Option<B::Expr> {
+        let mut operands = vec![self.parse_operand(b, &precedence)?];
+        let mut operators: Vec<(Token, InfixOperator, Precedence)> = Vec::new();
+
+        while !self.peek_token_is(&Token::Semicolon) && precedence < self.peek_precedence() {
+            if self.peek_token_is(&Token::Assign) {
+                break;
+            }
+            let operator = match self.peek.infix_operator() {
+                Some(op) => op,
+                None => break,
+            };
+            self.next_token();
+            let op_precedence = self.cur_precedence();
+            // everything is left associative, so equal precedence reduces too
+            while operators.last().is_some_and(|op| op.2 >= op_precedence) {
+                self.reduce_infix(b, &mut operands, &mut operators);
+            }
+            let tok = std::mem::take(&mut self.cur);
+            self.next_token();
+            let operand = self.parse_operand(b, &op_precedence)?;
+            operators.push((tok, operator, op_precedence));
+            operands.push(operand);
+        }
+
+        while !operators.is_empty() {
+            self.reduce_infix(b, &mut operands, &mut operators);
+        }
+        let mut left = operands.pop()?;
+        while precedence < Precedence::Pipe && self.peek_token_is(&Token::Pipe) {
+            left = self.parse_pipe(b, left)?;
+        }
+        if precedence < Precedence::Assign && self.peek_token_is(&Token::Assign) {
+            return self.parse_assign_expression(b, left);
+        }
+        Some(left)
+    }
+
+    /* `x |> f` is `f(x)` and `x |> f(y)` is `f(x, y)`, nothing in the AST
+     * tells them apart. The pipe binds looser than every operator but `=`,
+     * and to the left, so a chain of them reads left to right. */
+    fn parse_pipe<B: NodeBuilder>(&mut self, b: B, left: B::Expr) -> Option<B::Expr> {
+        self.next_token();
+        self.next_token();
+        let right = self.parse_expression(b, Precedence::Pipe)?;
+        let piped_into_call = B::is_call(&right);
+        let end = self.position() + 1;
+        if let Some(ranges) = &mut self.source_map {
+            // the piped value is the first argument, so its range goes
+            // right after the function's
+            if let (Some(right), Some(left)) = (ranges.pop(), ranges.pop()) {
+                let start = left.token_range.0;
+                let mut children = if piped_into_call {
+                    right.children
+                } else {
+                    vec![right]
+                };
+                children.insert(children.len().min(1), left);
+                ranges.push(SourceMap {
+                    token_range: (start, end),
+                    children,
+                    names: None,
+                });
+            }
+        }
+        Some(b.pipe(left, right))
+    }
+
+    /* `=` binds loosest of all and to the right, so its target is
+     * everything before it and its value everything after it */
+    fn parse_assign_expression<B: NodeBuilder>(
+        &mut self,
+        b: B,
+        target: B::Expr,
+    ) -> Option<B::Expr> {
+        let name = match b.into_name(target) {
+            Ok(name) => name,
+            Err(target) => {
+                self.error(format!("cannot assign to {}", B::string(&target)));
+                return None;
             }
+        };
+        // the target is a name rather than an expression below the assignment
+        let start = match &mut self.source_map {
+            Some(ranges) => ranges.pop().map(|target| target.token_range.0),
             None => None,
+        };
+        self.next_token();
+        let tok = std::mem::take(&mut self.cur);
+        self.next_token();
+        let value = self.parse_expression(b, Precedence::Lowest)?;
+        Some(self.record_range(b, start, b.assign(tok, name, value)))
+    }
+
+    fn reduce_infix<B: NodeBuilder>(
+        &mut self,
+        b: B,
+        operands: &mut Vec<B::Expr>,
+        operators: &mut Vec<(Token, InfixOperator, Precedence)>,
+    ) {
+        let (tok, operator, _) = operators.pop().expect("reduce with no operator");
+        let right = operands.pop().expect("reduce with no right operand");
+        let left = operands.pop().expect("reduce with no left operand");
+        let infix = b.infix(tok, left, operator, right);
+        operands.push(self.record_range(b, None, infix));
+    }
+
+    /* notes the tokens from `start` to `cur` as the range of `exp`, or
+     * from the start of its first child without a `start`. The ranges of
+     * its children are the last ones noted. */
+    fn record_range<B: NodeBuilder>(
+        &mut self,
+        _: B,
+        start: Option<usize>,
+        exp: B::Expr,
+    ) -> B::Expr {
+        if self.source_map.is_some() {
+            self.note_range(start, B::child_count(&exp));
         }
+        exp
     }
 
-    fn parse_expression(&mut self, precedence: Precedence) -> Option<Expression> {
+    /* `record_range` for an expression with `count` children, which the
+     * builder hasn't made yet */
+    fn note_range(&mut self, start: Option<usize>, count: usize) {
+        let end = self.position() + 1;
+        let Some(ranges) = &mut self.source_map else {
+            return;
+        };
+        let children = ranges.split_off(ranges.len().saturating_sub(count));
+        let start = start
+            .or_else(|| children.first().map(|child| child.token_range.0))
+            .unwrap_or(end - 1);
+        ranges.push(SourceMap {
+            token_range: (start, end),
+            children,
+            names: None,
+        });
+    }
+
+    /* notes the tokens from `first` to `last` as the names the value
+     * parsed last is bound to */
+    fn record_names(&mut self, first: usize, last: usize) {
+        if let Some(value) = self
+            .source_map
+            .as_mut()
+            .and_then(|ranges| ranges.last_mut())
+        {
+            value.names = Some((first, last + 1));
+        }
+    }
+
+    /// A single operand of an infix chain: a prefix expression followed by
+    /// any calls or index operations, which bind tighter than every infix
+    /// operator.
+    fn parse_operand<B: NodeBuilder>(&mut self, b: B, precedence: &Precedence) -> Option<B::Expr> {
+        let start = self.position();
+        let grouped = self.cur == Token::LParen;
         let mut left = match &self.cur {
-            Token::Ident(_) => Some(self.parse_identifier()),
-            Token::Int(_) => self.parse_integer_literal(),
-            Token::String(_) => Some(self.parse_string_literal()),
-            Token::Bang | Token::Minus => self.parse_prefix_expression(),
-            Token::True | Token::False => Some(self.parse_boolean_literal()),
-            Token::LParen => self.parse_grouped_expression(),
-            Token::If => self.parse_if_expression(),
-            Token::Function => self.parse_function_literal(),
+            Token::Ident(_) => self.parse_identifier(b),
+            Token::Int(_) => self.parse_integer_literal(b),
+            Token::Float(_) => self.parse_float_literal(b),
+            Token::String(s) => Some(b.string_literal(self.cur.clone(), s)),
+            Token::Bang | Token::Minus => self.parse_prefix_expression(b),
+            Token::True | Token::False => {
+                let value = self.cur == Token::True;
+                Some(b.boolean(std::mem::take(&mut self.cur), value))
+            }
+            Token::LParen => self.parse_grouped_expression(b),
+            Token::If => self.parse_if_expression(b),
+            Token::For => self.parse_for_expression(b),
+            Token::Function => {
+                let tok = std::mem::take(&mut self.cur);
+                self.parse_function(b, tok).map(|f| b.function_literal(f))
+            }
+            Token::Macro => self.parse_macro_literal(b),
             Token::LBracket => {
                 let tok = self.cur.clone();
-                let elements_opt = self.parse_expression_list(Token::RBracket);
-                match elements_opt {
-                    Some(elements) => Some(Expression::Array(ArrayLiteral { tok, elements })),
-                    None => return None,
-                }
+                let elements = self.parse_expression_list(b, Token::RBracket)?;
+                Some(b.array(tok, elements))
             }
-            Token::LSquirly => self.parse_hash_literal(),
+            Token::LSquirly => self.parse_hash_literal(b),
             _ => {
                 let e = format!("no prefix parse fn for {:#?}", self.cur);
-                self.errors.push(e);
+                self.error(e);
                 None
             }
         };
+        // a group notes its own range, since it may not be a new expression
+        if !grouped {
+            left = left.map(|exp| self.record_range(b, Some(start), exp));
+        }
 
-        while !self.peek_token_is(&Token::Semicolon) && precedence < self.peek_precedence() {
+        while !self.peek_token_is(&Token::Semicolon) && *precedence < self.peek_precedence() {
             match &self.peek {
-                Token::Plus
-                | Token::Minus
-                | Token::Slash
-                | Token::Asterisk
-                | Token::Eq
-                | Token::NotEq
-                | Token::Lt
-                | Token::Gt => {
-                    self.next_token();
-                    let l = match left {
-                        Some(exp) => exp,
-                        None => return None,
-                    };
-                    left = self.parse_infix_expression(l);
-                }
                 Token::LParen => {
                     self.next_token();
-                    let l = match left {
-                        Some(exp) => exp,
-                        None => return None,
-                    };
-                    left = self.parse_call_expression(l);
+                    let l = left?;
+                    left = self
+                        .parse_call_expression(b, l)
+                        .map(|exp| self.record_range(b, Some(start), exp));
                 }
                 Token::LBracket => {
                     self.next_token();
-                    let l = match left {
-                        Some(exp) => exp,
-                        None => return None,
-                    };
-                    left = self.parse_index_expression(l);
+                    let l = left?;
+                    left = self
+                        .parse_index_expression(b, l)
+                        .map(|exp| self.record_range(b, Some(start), exp));
+                }
+                Token::Dot => {
+                    self.next_token();
+                    let l = left?;
+                    left = self
+                        .parse_member_expression(b, l)
+                        .map(|exp| self.record_range(b, Some(start), exp));
                 }
                 _ => return left,
             }
@@ -196,48 +748,46 @@ impl Parser {
         left
     }
 
-    fn parse_identifier(&mut self) -> Expression {
+    fn parse_identifier<B: NodeBuilder>(&mut self, b: B) -> Option<B::Expr> {
         if let Token::Ident(v) = &self.cur {
-            let tok = self.cur.clone();
-            Expression::Identifier(Identifier {
-                tok,
-                value: v.clone(),
-            })
+            Some(b.identifier(b.name(self.cur.clone(), v)))
         } else {
-            panic!("unreachable");
+            self.internal_error("expected identifier token");
+            None
         }
     }
 
-    fn parse_integer_literal(&mut self) -> Option<Expression> {
+    fn parse_integer_literal<B: NodeBuilder>(&mut self, b: B) -> Option<B::Expr> {
         if let Token::Int(v) = &self.cur {
-            let tok = self.cur.clone();
-            match v.parse::<i64>() {
-                Ok(i) => Some(Expression::Integer(IntegerLiteral { tok, value: i })),
-                Err(_) => None,
+            match parse_int_literal(v) {
+                Some(i) => Some(b.integer(self.cur.clone(), v, i)),
+                None => {
+                    self.int_error(v.clone().as_ref());
+                    None
+                }
             }
         } else {
-            panic!("unreachable");
+            self.internal_error("expected integer token");
+            None
         }
     }
 
-    fn parse_boolean_literal(&mut self) -> Expression {
-        let value = self.cur == Token::True;
-        let tok = std::mem::take(&mut self.cur);
-        Expression::Boolean(BooleanLiteral { tok, value })
-    }
-
-    fn parse_string_literal(&mut self) -> Expression {
-        if let Token::String(s) = &self.cur {
-            Expression::String(StringLiteral {
-                tok: self.cur.clone(),
-                value: s.clone(),
-            })
+    fn parse_float_literal<B: NodeBuilder>(&mut self, b: B) -> Option<B::Expr> {
+        if let Token::Float(v) = &self.cur {
+            match parse_float_literal(v) {
+                Some(f) => Some(b.float(self.cur.clone(), v, f)),
+                None => {
+                    self.float_error(v.clone().as_ref());
+                    None
+                }
+            }
         } else {
-            panic!("unreachable");
+            self.internal_error("expected float token");
+            None
         }
     }
 
-    fn parse_prefix_expression(&mut self) -> Option<Expression> {
+    fn parse_prefix_expression<B: NodeBuilder>(&mut self, b: B) -> Option<B::Expr> {
         let operator = match self.cur {
             Token::Minus => PrefixOperator::Minus,
             Token::Bang => PrefixOperator::Bang,
@@ -245,154 +795,178 @@ impl Parser {
         };
         let tok = std::mem::take(&mut self.cur);
         self.next_token();
-        let right = self.parse_expression(Precedence::Prefix);
-        match right {
-            Some(exp) => Some(Expression::PrefixExpression(PrefixExpression {
-                tok,
-                operator,
-                right: std::rc::Rc::new(exp),
-            })),
-            None => None,
-        }
+        let right = self.parse_expression(b, Precedence::Prefix)?;
+        Some(b.prefix(tok, operator, right))
     }
 
-    fn parse_infix_expression(&mut self, left: Expression) -> Option<Expression> {
-        let operator = match self.cur {
-            Token::Plus => InfixOperator::Plus,
-            Token::Minus => InfixOperator::Minus,
-            Token::Asterisk => InfixOperator::Asterisk,
-            Token::Slash => InfixOperator::Slash,
-            Token::Eq => InfixOperator::Eq,
-            Token::NotEq => InfixOperator::NotEq,
-            Token::Lt => InfixOperator::Lt,
-            Token::Gt => InfixOperator::Gt,
-            _ => return None,
-        };
-        let precedence = self.cur_precedence();
-        let tok = std::mem::take(&mut self.cur);
+    fn parse_grouped_expression<B: NodeBuilder>(&mut self, b: B) -> Option<B::Expr> {
+        let start = self.position();
+        let tok = self.cur.clone();
         self.next_token();
-        let right = self.parse_expression(precedence);
-        match right {
-            Some(exp) => Some(Expression::InfixExpression(InfixExpression {
-                tok,
-                left: std::rc::Rc::new(left),
-                operator,
-                right: std::rc::Rc::new(exp),
-            })),
-            None => None,
+        let exp = self.parse_expression(b, Precedence::Lowest);
+        if self.peek_token_is(&Token::Comma) {
+            let mut elements = b.exprs();
+            elements.push(exp?);
+            while self.peek_token_is(&Token::Comma) {
+                self.next_token();
+                self.next_token();
+                elements.push(self.parse_expression(b, Precedence::Lowest)?);
+            }
+            if !self.expect_peek(Token::RParen) {
+                return None;
+            }
+            let tuple = b.tuple(tok, elements);
+            return Some(self.record_range(b, Some(start), tuple));
         }
-    }
-
-    fn parse_grouped_expression(&mut self) -> Option<Expression> {
-        self.next_token();
-        let exp = self.parse_expression(Precedence::Lowest);
         if !self.expect_peek(Token::RParen) {
             return None;
         }
+        let end = self.position() + 1;
+        if let Some(inner) = self
+            .source_map
+            .as_mut()
+            .and_then(|ranges| ranges.last_mut())
+        {
+            inner.token_range = (start, end);
+        }
         exp
     }
 
-    fn parse_if_expression(&mut self) -> Option<Expression> {
+    fn parse_if_expression<B: NodeBuilder>(&mut self, b: B) -> Option<B::Expr> {
         let tok = std::mem::take(&mut self.cur);
         if !self.expect_peek(Token::LParen) {
             return None;
         }
         self.next_token();
-        let cond_opt = self.parse_expression(Precedence::Lowest);
-        let condition = match cond_opt {
-            Some(c) => std::rc::Rc::new(c),
-            None => return None,
-        };
+        let condition = self.parse_expression(b, Precedence::Lowest)?;
         if !self.expect_peek(Token::RParen) {
             return None;
         }
         if !self.expect_peek(Token::LSquirly) {
             return None;
         }
-        let consequence = self.parse_block_statement();
+        let consequence = self.parse_block_statement(b);
+        let mut alternative = None;
         if self.peek_token_is(&Token::Else) {
             self.next_token();
             if !self.expect_peek(Token::LSquirly) {
                 return None;
             }
-            let alternative = self.parse_block_statement();
-            return Some(Expression::IfExpression(IfExpression {
-                tok,
-                condition,
-                consequence,
-                alternative: Some(alternative),
-            }));
-        }
-        Some(Expression::IfExpression(IfExpression {
-            tok,
-            condition,
-            consequence,
-            alternative: None,
-        }))
-    }
-
-    fn parse_block_statement(&mut self) -> BlockStatement {
-        let mut statements = Vec::new();
+            alternative = Some(self.parse_block_statement(b));
+        }
+        Some(b.if_expression(tok, condition, consequence, alternative))
+    }
+
+    fn parse_for_expression<B: NodeBuilder>(&mut self, b: B) -> Option<B::Expr> {
+        let tok = std::mem::take(&mut self.cur);
+        let label = self.label.take();
+        if !self.expect_peek(Token::LParen) {
+            return None;
+        }
+        let mut names = b.names();
+        loop {
+            self.next_token();
+            match &self.cur {
+                Token::Ident(v) => names.push(b.name(self.cur.clone(), v)),
+                _ => {
+                    self.ident_error();
+                    return None;
+                }
+            }
+            if names.len() == 2 || !self.peek_token_is(&Token::Comma) {
+                break;
+            }
+            self.next_token();
+        }
+        if !self.expect_peek(Token::In) {
+            return None;
+        }
+        self.next_token();
+        let iterable = self.parse_expression(b, Precedence::Lowest)?;
+        if !self.expect_peek(Token::RParen) {
+            return None;
+        }
+        if !self.expect_peek(Token::LSquirly) {
+            return None;
+        }
+        self.loops.push(label.clone());
+        let body = self.parse_block_statement(b);
+        self.loops.pop();
+        let label = label.map(|label| b.name(Token::Ident(label.clone()), &label));
+        Some(b.for_expression(tok, label, names, iterable, body))
+    }
+
+    /* a function's body is outside the loops around the function */
+    fn parse_function_body<B: NodeBuilder>(&mut self, b: B) -> B::Block {
+        let loops = std::mem::take(&mut self.loops);
+        let body = self.parse_block_statement(b);
+        self.loops = loops;
+        body
+    }
+
+    fn parse_block_statement<B: NodeBuilder>(&mut self, b: B) -> B::Block {
+        let mut statements = b.stmts();
         let tok = std::mem::take(&mut self.cur);
         self.next_token();
         while !self.cur_token_is(Token::RSquirly) && !self.cur_token_is(Token::Eof) {
-            let stmt = self.parse_statement();
-            match stmt {
-                Some(s) => statements.push(s),
-                None => {}
+            if let Some(s) = self.parse_statement(b) {
+                statements.push(s)
             };
             self.next_token();
         }
-        BlockStatement { tok, statements }
+        self.check_block_closed();
+        b.block(tok, statements)
     }
 
-    fn parse_function_literal(&mut self) -> Option<Expression> {
+    /* the parameters and body after `tok`, `fn` or a declaration's name */
+    fn parse_function<B: NodeBuilder>(&mut self, b: B, tok: Token) -> Option<B::Function> {
+        if !self.expect_peek(Token::LParen) {
+            return None;
+        }
+        let parameters = self.parse_function_parameters(b)?;
+        if !self.expect_peek(Token::LSquirly) {
+            return None;
+        }
+        let body = self.parse_function_body(b);
+        Some(b.function(tok, parameters, body))
+    }
+
+    fn parse_macro_literal<B: NodeBuilder>(&mut self, b: B) -> Option<B::Expr> {
         let tok = std::mem::take(&mut self.cur);
         if !self.expect_peek(Token::LParen) {
             return None;
         }
-        let parameters_opt = self.parse_function_parameters();
-        let parameters = match parameters_opt {
-            Some(p) => p,
-            None => return None,
-        };
+        let parameters = self.parse_function_parameters(b)?;
         if !self.expect_peek(Token::LSquirly) {
             return None;
         }
-        let body = self.parse_block_statement();
-        Some(Expression::FunctionLiteral(FunctionLiteral {
-            tok,
-            parameters,
-            body,
-        }))
+        let body = self.parse_function_body(b);
+        Some(b.macro_literal(tok, parameters, body))
     }
 
-    fn parse_function_parameters(&mut self) -> Option<Vec<Identifier>> {
-        let mut res = Vec::new();
+    fn parse_function_parameters<B: NodeBuilder>(&mut self, b: B) -> Option<B::Names> {
+        let mut res = b.names();
         if self.peek_token_is(&Token::RParen) {
             self.next_token();
             return Some(res);
         }
         self.next_token();
-        let mut ident = match &self.cur {
-            Token::Ident(v) => Identifier {
-                tok: self.cur.clone(),
-                value: v.clone(),
-            },
-            _ => return None,
-        };
-        res.push(ident);
-        while self.peek_token_is(&Token::Comma) {
+        loop {
+            if !self.within_arguments(res.len(), "parameters") {
+                return None;
+            }
+            match &self.cur {
+                Token::Ident(v) => res.push(b.name(self.cur.clone(), v)),
+                _ => {
+                    self.ident_error();
+                    return None;
+                }
+            }
+            if !self.peek_token_is(&Token::Comma) {
+                break;
+            }
             self.next_token();
             self.next_token();
-            ident = match &self.cur {
-                Token::Ident(v) => Identifier {
-                    tok: self.cur.clone(),
-                    value: v.clone(),
-                },
-                _ => return None,
-            };
-            res.push(ident);
         }
         if !self.expect_peek(Token::RParen) {
             return None;
@@ -400,37 +974,33 @@ impl Parser {
         Some(res)
     }
 
-    fn parse_call_expression(&mut self, func: Expression) -> Option<Expression> {
+    fn parse_call_expression<B: NodeBuilder>(
+        &mut self,
+        b: B,
+        function: B::Expr,
+    ) -> Option<B::Expr> {
         let tok = std::mem::take(&mut self.cur);
-        let function = std::rc::Rc::new(func);
-        match self.parse_expression_list(Token::RParen) {
-            Some(arguments) => Some(Expression::CallExpression(CallExpression {
-                tok,
-                function,
-                arguments,
-            })),
-            None => None,
-        }
+        let arguments = self.parse_expression_list(b, Token::RParen)?;
+        Some(b.call(tok, function, arguments))
     }
 
-    fn parse_expression_list(&mut self, end: Token) -> Option<Vec<Expression>> {
-        let mut res = Vec::new();
+    fn parse_expression_list<B: NodeBuilder>(&mut self, b: B, end: Token) -> Option<B::Exprs> {
+        // an array can be as long as it likes, only a call's list has a limit
+        let mut res = b.exprs();
         if self.peek_token_is(&end) {
             self.next_token();
             return Some(res);
         }
-        self.next_token();
-        match self.parse_expression(Precedence::Lowest) {
-            Some(e) => res.push(e),
-            None => return None,
-        };
-        while self.peek_token_is(&Token::Comma) {
+        loop {
             self.next_token();
+            if end == Token::RParen && !self.within_arguments(res.len(), "arguments") {
+                return None;
+            }
+            res.push(self.parse_expression(b, Precedence::Lowest)?);
+            if !self.peek_token_is(&Token::Comma) {
+                break;
+            }
             self.next_token();
-            match self.parse_expression(Precedence::Lowest) {
-                Some(e) => res.push(e),
-                None => return None,
-            };
         }
         if !self.expect_peek(end) {
             return None;
@@ -438,43 +1008,41 @@ impl Parser {
         Some(res)
     }
 
-    fn parse_index_expression(&mut self, left_exp: Expression) -> Option<Expression> {
+    fn parse_index_expression<B: NodeBuilder>(&mut self, b: B, left: B::Expr) -> Option<B::Expr> {
         let tok = std::mem::take(&mut self.cur);
-        let left = std::rc::Rc::new(left_exp);
         self.next_token();
-        match self.parse_expression(Precedence::Lowest) {
-            Some(e) => {
-                let index = std::rc::Rc::new(e);
-                if !self.expect_peek(Token::RBracket) {
-                    return None;
-                }
-                Some(Expression::IndexExpression(IndexExpression {
-                    tok,
-                    left,
-                    index,
-                }))
-            }
-            None => None,
+        let index = self.parse_expression(b, Precedence::Lowest)?;
+        if !self.expect_peek(Token::RBracket) {
+            return None;
         }
+        Some(b.index(tok, left, index))
+    }
+
+    /* `left.name`, which indexes `left` with the string `"name"` */
+    fn parse_member_expression<B: NodeBuilder>(&mut self, b: B, left: B::Expr) -> Option<B::Expr> {
+        let tok = std::mem::take(&mut self.cur);
+        self.next_token();
+        let Token::Ident(name) = &self.cur else {
+            self.ident_error();
+            return None;
+        };
+        let name = name.clone();
+        // the string the name stands for is below the index
+        self.note_range(None, 0);
+        Some(b.member(tok, left, &name))
     }
 
-    fn parse_hash_literal(&mut self) -> Option<Expression> {
+    fn parse_hash_literal<B: NodeBuilder>(&mut self, b: B) -> Option<B::Expr> {
         let tok = std::mem::take(&mut self.cur);
-        let mut pairs = Vec::new();
+        let mut pairs = b.pairs();
         while !self.peek_token_is(&Token::RSquirly) {
             self.next_token();
-            let key = match self.parse_expression(Precedence::Lowest) {
-                Some(e) => e,
-                None => return None,
-            };
+            let key = self.parse_expression(b, Precedence::Lowest)?;
             if !self.expect_peek(Token::Colon) {
                 return None;
             }
             self.next_token();
-            let value = match self.parse_expression(Precedence::Lowest) {
-                Some(e) => e,
-                None => return None,
-            };
+            let value = self.parse_expression(b, Precedence::Lowest)?;
             pairs.push((key, value));
             if !self.peek_token_is(&Token::RSquirly) && !self.expect_peek(Token::Comma) {
                 return None;
@@ -483,12 +1051,18 @@ impl Parser {
         if !self.expect_peek(Token::RSquirly) {
             return None;
         }
-        Some(Expression::Hash(HashLiteral { tok, pairs }))
+        Some(b.hash(tok, pairs))
     }
 
     fn next_token(&mut self) {
         std::mem::swap(&mut self.cur, &mut self.peek);
-        self.peek = self.l.next_token();
+        self.peek = match self.ahead.pop_front() {
+            Some(tok) => tok,
+            None => self.read_token(),
+        };
+        if self.peek == Token::Illegal {
+            self.take_lexer_errors();
+        }
     }
 
     fn cur_token_is(&self, tok: Token) -> bool {
@@ -496,7 +1070,34 @@ impl Parser {
     }
 
     fn peek_token_is(&self, tok: &Token) -> bool {
-        self.peek == *tok
+        *self.peek_token() == *tok
+    }
+
+    fn peek_token(&self) -> &Token {
+        &self.peek
+    }
+
+    /* the token `n` places after `peek`, without moving past anything, so
+     * `peek_n(0)` is `peek` */
+    fn read_token(&mut self) -> Token {
+        self.read += 1;
+        assert!(
+            self.read <= self.max_reads,
+            "the parser read more than {} tokens",
+            self.max_reads
+        );
+        self.tokens.next_token()
+    }
+
+    fn peek_n(&mut self, n: usize) -> &Token {
+        if n == 0 {
+            return self.peek_token();
+        }
+        while self.ahead.len() < n {
+            let tok = self.read_token();
+            self.ahead.push_back(tok);
+        }
+        &self.ahead[n - 1]
     }
 
     fn expect_peek(&mut self, tok: Token) -> bool {
@@ -509,52 +1110,189 @@ impl Parser {
         }
     }
 
+    /* the label of `label: for`, from the label on to the `for` */
+    fn loop_label(&mut self) -> Option<std::rc::Rc<str>> {
+        let Token::Ident(label) = std::mem::take(&mut self.cur) else {
+            unreachable!("checked to be an identifier")
+        };
+        self.next_token();
+        if !self.expect_peek(Token::For) {
+            return None;
+        }
+        Some(label)
+    }
+
+    /* from a `break` or `continue` to its end, with the label after a
+     * `break`, if there is one. None if it isn't in a loop, or in none
+     * with the label. */
+    fn loop_control(&mut self) -> Option<Option<std::rc::Rc<str>>> {
+        let brk = self.cur == Token::Break;
+        // the errors are at the keyword, or at the label that isn't there
+        let keyword_at = self.position();
+        let label = match &self.peek {
+            Token::Ident(label) if brk => Some(label.clone()),
+            _ => None,
+        };
+        if label.is_some() {
+            self.next_token();
+        }
+        let label_at = self.position();
+        if self.peek_token_is(&Token::Semicolon) {
+            self.next_token();
+        }
+        if self.loops.is_empty() {
+            let keyword = if brk { "break" } else { "continue" };
+            self.error_at(format!("{} outside of a loop", keyword), keyword_at);
+            return None;
+        }
+        if let Some(label) = &label {
+            if !self.loops.iter().any(|l| l.as_ref() == Some(label)) {
+                let e = format!("break to an undefined label `{}`", label);
+                self.error_at(e, label_at);
+                return None;
+            }
+        }
+        Some(label)
+    }
+
+    fn enter_nesting(&mut self) -> bool {
+        if self.depth >= MAX_NESTING_DEPTH {
+            let e = format!(
+                "expression nested too deeply, the limit is {}",
+                MAX_NESTING_DEPTH
+            );
+            self.error(e);
+            return false;
+        }
+        self.depth += 1;
+        true
+    }
+
+    /* whether one more parameter or argument after `count` of them is
+     * still allowed */
+    fn within_arguments(&mut self, count: usize, what: &str) -> bool {
+        if count >= self.max_arguments {
+            let e = format!("too many {}, the limit is {}", what, self.max_arguments);
+            self.error(e);
+            return false;
+        }
+        true
+    }
+
+    /* whether the `{` at `cur` opens what can only be a block, which isn't
+     * a statement, rather than a hash. A key is followed by `:`, so a name
+     * or literal followed by `}` or `;` is in a block, and so is a statement
+     * keyword. Anything else is left to parse as a hash. */
+    fn starts_block(&mut self) -> bool {
+        match self.peek_token() {
+            Token::Let
+            | Token::Const
+            | Token::Return
+            | Token::Break
+            | Token::Continue
+            | Token::Import => true,
+            Token::Ident(_)
+            | Token::Int(_)
+            | Token::Float(_)
+            | Token::String(_)
+            | Token::True
+            | Token::False => {
+                matches!(self.peek_n(1), Token::Semicolon | Token::RSquirly)
+            }
+            _ => false,
+        }
+    }
+
+    fn block_error(&mut self) {
+        self.error("unexpected block, a block only follows if, else, for or fn".to_owned());
+    }
+
+    fn check_block_closed(&mut self) {
+        if self.cur_token_is(Token::Eof) {
+            let e = format!(
+                "expected next token to be {:#?}, got {:#?} instead",
+                Token::RSquirly,
+                self.cur
+            );
+            self.error(e);
+        }
+    }
+
+    /* a parse function was called on a token it doesn't parse */
+    fn internal_error(&mut self, msg: &str) {
+        self.error(format!("internal parser error: {}", msg));
+    }
+
+    fn ident_error(&mut self) {
+        let e = format!(
+            "expected token to be Token::Ident, got {:#?} instead",
+            self.cur
+        );
+        self.error(e);
+    }
+
+    fn int_error(&mut self, literal: &str) {
+        self.error(format!("could not parse {} as integer", literal));
+    }
+
+    fn float_error(&mut self, literal: &str) {
+        self.error(format!("could not parse {} as float", literal));
+    }
+
     fn peek_error(&mut self, tok: &Token) {
         let str = format!(
             "expected next token to be {:#?}, got {:#?} instead",
             tok, self.peek
         );
-        self.errors.push(str);
+        self.error_at(str, self.position() + 1);
     }
 
-    fn peek_precedence(&self) -> Precedence {
-        match &self.peek {
-            Token::Eq => Precedence::Equals,
-            Token::NotEq => Precedence::Equals,
-            Token::Lt => Precedence::LessGreater,
-            Token::Gt => Precedence::LessGreater,
-            Token::Plus => Precedence::Sum,
-            Token::Minus => Precedence::Sum,
-            Token::Asterisk => Precedence::Product,
-            Token::Slash => Precedence::Product,
-            Token::LParen => Precedence::Call,
-            Token::LBracket => Precedence::Index,
-            _ => Precedence::Lowest,
+    /* reports `e` at `cur` */
+    fn error(&mut self, e: String) {
+        self.error_at(e, self.position());
+    }
+
+    fn error_at(&mut self, e: String, token: usize) {
+        self.errors.push(e);
+        self.error_tokens.push(Some(token));
+    }
+
+    /* the lexer reports an error with the `Token::Illegal` it returns,
+     * which is `peek` by the time the parser looks, or `cur` at the start */
+    fn take_lexer_errors(&mut self) {
+        let token = if self.cur == Token::Illegal {
+            self.position()
+        } else {
+            self.position() + 1
+        };
+        for e in self.tokens.take_errors() {
+            self.error_at(e, token);
         }
     }
 
+    fn peek_precedence(&self) -> Precedence {
+        self.peek.precedence()
+    }
+
     fn cur_precedence(&self) -> Precedence {
-        match &self.cur {
-            Token::Eq => Precedence::Equals,
-            Token::NotEq => Precedence::Equals,
-            Token::Lt => Precedence::LessGreater,
-            Token::Gt => Precedence::LessGreater,
-            Token::Plus => Precedence::Sum,
-            Token::Minus => Precedence::Sum,
-            Token::Asterisk => Precedence::Product,
-            Token::Slash => Precedence::Product,
-            Token::LParen => Precedence::Call,
-            Token::LBracket => Precedence::Index,
-            _ => Precedence::Lowest,
-        }
+        self.cur.precedence()
     }
 }
 
 #[cfg(test)]
 mod test {
-    use crate::ast::{Expression, InfixOperator, Node, PrefixOperator, Statement};
-    use crate::lexer::Lexer;
-    use crate::parser::Parser;
+    use std::rc::Rc;
+
+    use crate::ast::{
+        Expression, ExpressionStatement, Identifier, IndexExpression, InfixExpression,
+        InfixOperator, IntegerLiteral, LetStatement, Node, PrefixOperator, Statement,
+        StringLiteral,
+    };
+    use crate::lexer::{line_col, token_spans, Lexer};
+    use crate::parser::{
+        parse_dont_panic, Owned, Parser, Precedence, SourceMap, MAX_ARGUMENTS, MAX_NESTING_DEPTH,
+    };
+    use crate::token::Token;
 
     struct BoolTest {
         input: &'static str,
@@ -599,8 +1337,7 @@ mod test {
             assert_eq!(ls.name.value.to_string(), name.to_owned());
             assert_eq!(ls.name.token_literal(), name.to_owned());
         } else {
-            eprintln!("{:#?} is not a let statement", stmt);
-            assert!(false);
+            panic!("{:#?} is not a let statement", stmt);
         }
     }
 
@@ -608,8 +1345,7 @@ mod test {
         if let Expression::Integer(il) = exp {
             assert_eq!(il.value, exp_int);
         } else {
-            eprintln!("{:#?} is not an integer literal", exp);
-            assert!(false);
+            panic!("{:#?} is not an integer literal", exp);
         }
     }
 
@@ -617,8 +1353,7 @@ mod test {
         if let Expression::Boolean(bl) = exp {
             assert_eq!(bl.value, exp_bool);
         } else {
-            eprintln!("{:#?} is not a boolean literal", exp);
-            assert!(false);
+            panic!("{:#?} is not a boolean literal", exp);
         }
     }
 
@@ -664,16 +1399,16 @@ mod test {
         let input = "let x = 5;
         let y = 10;
         let foobar = 838383;";
-        let l = Lexer::new(&input);
+        let l = Lexer::new(input);
         let mut p = Parser::new(l);
         let program = p.parse();
         check_errors(&p);
-        let exps = vec!["x", "y", "foobar"];
+        let exps = ["x", "y", "foobar"];
         let exp_int: Vec<i64> = vec![5, 10, 838383];
         assert_eq!(program.statements.len(), 3);
         for (i, exp) in exps.iter().enumerate() {
             let stmt = &program.statements[i];
-            test_let_statement(&stmt, exp);
+            test_let_statement(stmt, exp);
             if let Statement::LetStatement(ls) = stmt {
                 test_integer_exp(&ls.value, exp_int[i]);
             } else {
@@ -682,17 +1417,79 @@ mod test {
         }
     }
 
+    #[test]
+    fn test_let_tuple_statement() {
+        let input = "let a, b = f();";
+        let l = Lexer::new(input);
+        let mut p = Parser::new(l);
+        let program = p.parse();
+        check_errors(&p);
+        assert_eq!(program.statements.len(), 1);
+        if let Statement::LetTupleStatement(lts) = &program.statements[0] {
+            let names: Vec<String> = lts.names.iter().map(|n| n.value.to_string()).collect();
+            assert_eq!(names, vec!["a", "b"]);
+            assert_eq!(lts.value.string(), "f()");
+        } else {
+            panic!("{:#?} is not a let tuple statement", program.statements[0]);
+        }
+    }
+
+    #[test]
+    fn test_const_statement() {
+        let input = "const PI = 3; let x = PI; const a, b = (1, 2);";
+        let mut p = Parser::new(Lexer::new(input));
+        let program = p.parse();
+        check_errors(&p);
+        let mutable: Vec<bool> = program
+            .statements
+            .iter()
+            .map(|stmt| match stmt {
+                Statement::LetStatement(ls) => ls.mutable,
+                Statement::LetTupleStatement(lts) => lts.mutable,
+                stmt => panic!("{:#?} is not a let statement", stmt),
+            })
+            .collect();
+        assert_eq!(mutable, [false, true, false]);
+        assert_eq!(
+            program.string(),
+            "const PI = 3;\nlet x = PI;\nconst a, b = (1, 2);"
+        );
+    }
+
+    #[test]
+    fn test_return_tuple() {
+        let inputs = ["return (a, b + 1);", "return a, b + 1;"];
+        for input in inputs.iter() {
+            let l = Lexer::new(input);
+            let mut p = Parser::new(l);
+            let program = p.parse();
+            check_errors(&p);
+            assert_eq!(program.statements.len(), 1);
+            if let Statement::ReturnStatement(rs) = &program.statements[0] {
+                if let Expression::Tuple(t) = &rs.value {
+                    assert_eq!(t.elements.len(), 2);
+                    test_ident(&t.elements[0], "a");
+                    assert_eq!(t.elements[1].string(), "(b + 1)");
+                } else {
+                    panic!("{:#?} is not a tuple", rs.value);
+                }
+            } else {
+                panic!("{:#?} is not a return statement", program.statements[0]);
+            }
+        }
+    }
+
     #[test]
     fn test_return_statements() {
         let input = "return 5;
         return 10;
         return 993322;";
-        let l = Lexer::new(&input);
+        let l = Lexer::new(input);
         let mut p = Parser::new(l);
         let program = p.parse();
         check_errors(&p);
         assert_eq!(program.statements.len(), 3);
-        let exp_ints = vec![5, 10, 993322];
+        let exp_ints = [5, 10, 993322];
         for (i, stmt) in program.statements.iter().enumerate() {
             if let Statement::ReturnStatement(rs) = stmt {
                 assert_eq!(rs.token_literal(), "return".to_string());
@@ -707,7 +1504,7 @@ mod test {
     #[test]
     fn test_identifier_expression() {
         let input = "foobar";
-        let l = Lexer::new(&input);
+        let l = Lexer::new(input);
         let mut p = Parser::new(l);
         let program = p.parse();
         check_errors(&p);
@@ -729,7 +1526,7 @@ mod test {
     #[test]
     fn test_integer_literal_expression() {
         let input = "5;";
-        let l = Lexer::new(&input);
+        let l = Lexer::new(input);
         let mut p = Parser::new(l);
         let program = p.parse();
         check_errors(&p);
@@ -742,15 +1539,88 @@ mod test {
                 let s = format!("{:#?} is not an integer literal expression", es.expression);
                 panic!("{}", s);
             }
-        } else {
-            let s = format!("{:#?} is not an expression statement", stmt);
-            panic!("{}", s);
+        } else {
+            let s = format!("{:#?} is not an expression statement", stmt);
+            panic!("{}", s);
+        }
+    }
+
+    #[test]
+    fn test_integer_literal_spellings() {
+        let tests = [
+            ("0xFF", 255),
+            ("0x1f", 31),
+            ("1_000", 1000),
+            ("0x7fff_ffff_ffff_ffff", i64::MAX),
+            ("007", 7),
+            ("5i", 5),
+            ("0xFFi", 255),
+        ];
+        for (input, exp) in tests {
+            let mut p = Parser::new(Lexer::new(input));
+            let program = p.parse();
+            check_errors(&p);
+            match &program.statements[0] {
+                Statement::ExpressionStatement(es) => test_integer_exp(&es.expression, exp),
+                stmt => panic!("{:#?} is not an expression statement", stmt),
+            }
+            assert_eq!(program.string(), input);
+        }
+
+        // the printed program keeps the literals as written
+        let mut p = Parser::new(Lexer::new("0xFF + 1_000"));
+        let program = p.parse();
+        check_errors(&p);
+        assert_eq!(program.string(), "(0xFF + 1_000)");
+
+        let mut p = Parser::new(Lexer::new("0x\n0x8000_0000_0000_0000"));
+        p.parse();
+        assert_eq!(
+            p.get_errors(),
+            &vec![
+                "could not parse 0x as integer",
+                "could not parse 0x8000_0000_0000_0000 as integer",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_float_literal_spellings() {
+        let tests = [
+            ("5f", 5.0),
+            ("5.0", 5.0),
+            ("0.25", 0.25),
+            ("2.5f", 2.5),
+            ("1_000.5", 1000.5),
+        ];
+        for (input, exp) in tests {
+            let mut p = Parser::new(Lexer::new(input));
+            let program = p.parse();
+            check_errors(&p);
+            match &program.statements[0] {
+                Statement::ExpressionStatement(es) => match &es.expression {
+                    Expression::Float(fl) => assert_eq!(fl.value, exp, "{}", input),
+                    exp => panic!("{:#?} is not a float literal", exp),
+                },
+                stmt => panic!("{:#?} is not an expression statement", stmt),
+            }
+            assert_eq!(program.string(), input);
         }
+
+        let mut p = Parser::new(Lexer::new("let x = 5q;"));
+        p.parse();
+        assert_eq!(
+            p.get_errors(),
+            &vec![
+                "unknown suffix q on number 5q, want i or f",
+                "no prefix parse fn for Illegal",
+            ]
+        );
     }
 
     #[test]
     fn test_prefix_expressoins() {
-        let prefix_int_tests = vec![
+        let prefix_int_tests = [
             PrefixIntTest {
                 input: "!5;",
                 oper: PrefixOperator::Bang,
@@ -762,7 +1632,7 @@ mod test {
                 int_val: 15,
             },
         ];
-        let prefix_bool_tests = vec![
+        let prefix_bool_tests = [
             PrefixBoolTest {
                 input: "!true;",
                 oper: PrefixOperator::Bang,
@@ -819,7 +1689,7 @@ mod test {
 
     #[test]
     fn test_infix_expressions() {
-        let int_tests = vec![
+        let int_tests = [
             InfixIntTest {
                 input: "5 + 5",
                 lval: 5,
@@ -870,7 +1740,7 @@ mod test {
             },
         ];
 
-        let bool_tests = vec![
+        let bool_tests = [
             InfixBoolTest {
                 input: "true == true",
                 lval: true,
@@ -971,7 +1841,7 @@ mod test {
             },
             PrecedenceTest {
                 input: "3 + 4; -5 * 5",
-                exp: "(3 + 4)((-5) * 5)",
+                exp: "(3 + 4);\n((-5) * 5)",
             },
             PrecedenceTest {
                 input: "5 > 4 == 3 < 4",
@@ -1045,6 +1915,18 @@ mod test {
                 input: "add(a * b[2], b[1], 2 * [1, 2][1])",
                 exp: "add((a * (b[2])), (b[1]), (2 * ([1, 2][1])))",
             },
+            PrecedenceTest {
+                input: "-string.split(a, b)[0].x",
+                exp: "(-(((string.split)(a, b)[0]).x))",
+            },
+            PrecedenceTest {
+                input: "a == b < c + d * e - f / g",
+                exp: "(a == (b < ((c + (d * e)) - (f / g))))",
+            },
+            PrecedenceTest {
+                input: "a * b - c * d == e",
+                exp: "(((a * b) - (c * d)) == e)",
+            },
         ];
 
         for t in tests.iter() {
@@ -1058,7 +1940,7 @@ mod test {
 
     #[test]
     fn test_boolean_literal() {
-        let tests = vec![
+        let tests = [
             BoolTest {
                 input: "true",
                 exp: true,
@@ -1087,7 +1969,7 @@ mod test {
     #[test]
     fn test_if_expression() {
         let input = "if (x < y) { x }";
-        let l = Lexer::new(&input);
+        let l = Lexer::new(input);
         let mut p = Parser::new(l);
         check_errors(&p);
         let program = p.parse();
@@ -1119,7 +2001,7 @@ mod test {
     #[test]
     fn test_if_else_expression() {
         let input = "if (x < y) { x } else { y }";
-        let l = Lexer::new(&input);
+        let l = Lexer::new(input);
         let mut p = Parser::new(l);
         check_errors(&p);
         let program = p.parse();
@@ -1162,7 +2044,7 @@ mod test {
     #[test]
     fn test_function_literal() {
         let input = "fn(x, y) { x + y; }";
-        let l = Lexer::new(&input);
+        let l = Lexer::new(input);
         let mut p = Parser::new(l);
         let program = p.parse();
         assert_eq!(program.statements.len(), 1);
@@ -1191,10 +2073,272 @@ mod test {
         }
     }
 
+    #[test]
+    fn test_macro_literal() {
+        let input = "macro(x, y) { x + y; }";
+        let mut p = Parser::new(Lexer::new(input));
+        let program = p.parse();
+        check_errors(&p);
+        assert_eq!(program.statements.len(), 1);
+        let Statement::ExpressionStatement(es) = &program.statements[0] else {
+            panic!(
+                "{:#?} is not an expression statement",
+                program.statements[0]
+            );
+        };
+        let Expression::MacroLiteral(ml) = &es.expression else {
+            panic!("{:#?} is not a macro literal", es.expression);
+        };
+        assert_eq!(ml.parameters.len(), 2);
+        assert_eq!(&*ml.parameters[0].value, "x");
+        assert_eq!(&*ml.parameters[1].value, "y");
+        assert_eq!(ml.body.statements.len(), 1);
+        let Statement::ExpressionStatement(body) = &ml.body.statements[0] else {
+            panic!(
+                "{:#?} is not an expression statement",
+                ml.body.statements[0]
+            );
+        };
+        test_ident_infix_exp(&body.expression, "x", "y", InfixOperator::Plus);
+        assert_eq!(program.string(), "macro(x, y) { (x + y) }");
+    }
+
+    #[test]
+    fn test_function_declaration() {
+        let input = "fn add(x, y) { x + y; } fn one() { 1 }; add(one(), 2)";
+        let mut p = Parser::new(Lexer::new(input));
+        let program = p.parse();
+        check_errors(&p);
+        assert_eq!(program.statements.len(), 3);
+        let Statement::LetStatement(ls) = &program.statements[0] else {
+            panic!("{:#?} is not a let statement", program.statements[0]);
+        };
+        assert_eq!(ls.tok, Token::Function);
+        assert_eq!(&*ls.name.value, "add");
+        assert!(ls.mutable);
+        let Expression::FunctionLiteral(fl) = &ls.value else {
+            panic!("{:#?} is not a function literal", ls.value);
+        };
+        assert_eq!(fl.parameters.len(), 2);
+        assert_eq!(fl.body.statements.len(), 1);
+        assert_eq!(
+            program.string(),
+            "fn add(x, y) { (x + y) }\nfn one() { 1 }\nadd(one(), 2)"
+        );
+
+        // a function literal still needs its parens
+        let mut p = Parser::new(Lexer::new("fn add { 1 }"));
+        p.parse();
+        assert_eq!(
+            p.get_errors()[0],
+            "expected next token to be LParen, got LSquirly instead"
+        );
+    }
+
+    #[test]
+    fn test_assign_expression() {
+        let tests = [
+            ("let x = (y = 5);", "let x = (y = 5);"),
+            ("let x = y = 5;", "let x = (y = 5);"),
+            ("a = b = 1 + 2", "(a = (b = (1 + 2)))"),
+            ("f(a = 1)", "f((a = 1))"),
+            ("if (a = b) { a }", "if ((a = b)) { a }"),
+        ];
+        for (input, exp) in tests {
+            let mut p = Parser::new(Lexer::new(input));
+            let program = p.parse();
+            check_errors(&p);
+            assert_eq!(program.string(), exp, "{}", input);
+        }
+
+        let mut p = Parser::new(Lexer::new("let x = (y = 5);"));
+        let program = p.parse();
+        let Statement::LetStatement(ls) = &program.statements[0] else {
+            panic!("{:#?} is not a let statement", program.statements[0]);
+        };
+        let Expression::Assign(ae) = &ls.value else {
+            panic!("{:#?} is not an assignment", ls.value);
+        };
+        assert_eq!(ae.tok, Token::Assign);
+        assert_eq!(&*ae.name.value, "y");
+        assert_eq!(ae.value.string(), "5");
+
+        let tests = [
+            ("a + b = 1", "cannot assign to (a + b)"),
+            ("f(x) = 1", "cannot assign to f(x)"),
+        ];
+        for (input, exp) in tests {
+            let mut p = Parser::new(Lexer::new(input));
+            p.parse();
+            assert_eq!(p.get_errors()[0], exp, "{}", input);
+        }
+    }
+
+    #[test]
+    fn test_for_expression() {
+        let tests = [
+            ("for (x in xs) { x }", "for (x in xs) { x }"),
+            (
+                "for (k, v in {1: 2}) { if (k) { continue; } break; }",
+                "for (k, v in {1:2}) { if (k) { continue; }; break; }",
+            ),
+            (
+                "for (c in \"ab\") { for (d in c) { break } }",
+                "for (c in \"ab\") { for (d in c) { break; } }",
+            ),
+            (
+                "for (x in range(0, 3)) { fn() { x } }",
+                "for (x in range(0, 3)) { fn() { x } }",
+            ),
+            (
+                "outer: for (x in xs) { for (y in x) { break outer; } }",
+                "outer: for (x in xs) { for (y in x) { break outer; } }",
+            ),
+            // the innermost loop with the label is the one that ends
+            (
+                "a: for (x in xs) { a: for (y in x) { break a } }",
+                "a: for (x in xs) { a: for (y in x) { break a; } }",
+            ),
+        ];
+        for (input, exp) in tests {
+            let mut p = Parser::new(Lexer::new(input));
+            let program = p.parse();
+            check_errors(&p);
+            assert_eq!(program.string(), exp, "{}", input);
+        }
+
+        let mut p = Parser::new(Lexer::new("for (k, v in h) { k }"));
+        let program = p.parse();
+        let Statement::ExpressionStatement(es) = &program.statements[0] else {
+            panic!(
+                "{:#?} is not an expression statement",
+                program.statements[0]
+            );
+        };
+        let Expression::ForExpression(fe) = &es.expression else {
+            panic!("{:#?} is not a for expression", es.expression);
+        };
+        assert_eq!(fe.tok, Token::For);
+        assert_eq!(fe.names.len(), 2);
+        test_ident(&fe.iterable, "h");
+        assert_eq!(fe.body.statements.len(), 1);
+
+        let tests = [
+            ("break;", "break outside of a loop"),
+            ("if (x) { continue }", "continue outside of a loop"),
+            // a function body is outside of the loop it is written in
+            (
+                "for (x in xs) { fn() { break; } }",
+                "break outside of a loop",
+            ),
+            (
+                "for (a, b, c in d) {}",
+                "expected next token to be In, got Comma instead",
+            ),
+            (
+                "for (x in xs) { break outer; }",
+                "break to an undefined label `outer`",
+            ),
+            (
+                "outer: for (x in xs) {}; for (y in ys) { break outer; }",
+                "break to an undefined label `outer`",
+            ),
+            (
+                "outer: for (x in xs) { fn() { for (y in ys) { break outer; } } }",
+                "break to an undefined label `outer`",
+            ),
+            (
+                "outer: let x = 1;",
+                "expected next token to be For, got Let instead",
+            ),
+            // `for` is the only loop there is to label
+            (
+                "outer: while (x) { break outer; }",
+                "expected next token to be For, got Ident(\n    \"while\",\n) instead",
+            ),
+            (
+                "for x in xs {}",
+                "expected next token to be LParen, got Ident(\n    \"x\",\n) instead",
+            ),
+        ];
+        for (input, exp) in tests {
+            let mut p = Parser::new(Lexer::new(input));
+            p.parse();
+            assert_eq!(p.get_errors()[0], exp, "{}", input);
+        }
+
+        // at the keyword, or at the label that isn't there
+        let tests = [
+            ("break;", (1, 1)),
+            ("if (x) {\n  continue;\n}", (2, 3)),
+            ("for (x in xs) { fn() { break; } }", (1, 24)),
+            ("for (x in xs) { break nope; }", (1, 23)),
+            (
+                "outer: for (x in xs) {}; for (y in ys) { break outer }",
+                (1, 48),
+            ),
+        ];
+        for (input, at) in tests {
+            let mut p = Parser::new(Lexer::new(input));
+            p.parse();
+            let spans = token_spans(input);
+            let token = p.error_tokens()[0].unwrap();
+            assert_eq!(line_col(input, spans[token].start), at, "{}", input);
+        }
+    }
+
+    #[test]
+    fn test_import_statement() {
+        let tests = [
+            ("import \"math.monkey\";", "import \"math.monkey\";"),
+            ("import \"a b\" as m", "import \"a b\" as m;"),
+            // `as` is only special after the path
+            (
+                "let as = 1; import \"m\" as as",
+                "let as = 1;\nimport \"m\" as as;",
+            ),
+            ("fn f() { import \"m\"; m }", "fn f() { import \"m\"; m }"),
+        ];
+        for (input, exp) in tests {
+            let mut p = Parser::new(Lexer::new(input));
+            let program = p.parse();
+            check_errors(&p);
+            assert_eq!(program.string(), exp, "{}", input);
+        }
+
+        let mut p = Parser::new(Lexer::new("import \"m\" as m;"));
+        let program = p.parse();
+        let Statement::ImportStatement(is) = &program.statements[0] else {
+            panic!("{:#?} is not an import statement", program.statements[0]);
+        };
+        assert_eq!(is.tok, Token::Import);
+        assert_eq!(is.alias.as_ref().map(|alias| &*alias.value), Some("m"));
+
+        let tests = [
+            (
+                "import m;",
+                "expected next token to be Token::String, got Ident(\n    \"m\",\n) instead",
+            ),
+            (
+                "import \"a\" + \"b\";",
+                "import needs a string literal, got (\"a\" + \"b\")",
+            ),
+            (
+                "import \"m\" as 1;",
+                "expected next token to be Token::Ident, got Int(\n    \"1\",\n) instead",
+            ),
+        ];
+        for (input, exp) in tests {
+            let mut p = Parser::new(Lexer::new(input));
+            p.parse();
+            assert_eq!(p.get_errors()[0], exp, "{}", input);
+        }
+    }
+
     #[test]
     fn test_call_expression() {
         let input = "add(1, 2 * 3, 4 + 5);";
-        let l = Lexer::new(&input);
+        let l = Lexer::new(input);
         let mut p = Parser::new(l);
         let program = p.parse();
         check_errors(&p);
@@ -1207,9 +2351,9 @@ mod test {
                 let a1 = &call.arguments[0];
                 let a2 = &call.arguments[1];
                 let a3 = &call.arguments[2];
-                test_integer_exp(&a1, 1);
-                test_int_infix_exp(&a2, 2, 3, InfixOperator::Asterisk);
-                test_int_infix_exp(&a3, 4, 5, InfixOperator::Plus);
+                test_integer_exp(a1, 1);
+                test_int_infix_exp(a2, 2, 3, InfixOperator::Asterisk);
+                test_int_infix_exp(a3, 4, 5, InfixOperator::Plus);
             } else {
                 let s = format!("{:#?} is not a call expressin", es.expression);
                 panic!("{}", s);
@@ -1220,6 +2364,42 @@ mod test {
         }
     }
 
+    #[test]
+    fn test_pipe_expression() {
+        let tests = [
+            // (pipe, the call it is)
+            ("[1, 2, 3] |> len", "len([1, 2, 3])"),
+            ("x |> f |> g", "g(f(x))"),
+            ("x |> f(y)", "f(x, y)"),
+            ("x |> f(y) |> g(z)", "g(f(x, y), z)"),
+            ("a + b |> f", "f((a + b))"),
+            ("a |> f == b |> g", "g((f == b)(a))"),
+            ("x |> fn(a) { a * 2 }", "fn(a) { a * 2 }(x)"),
+            ("x |> fs[0]", "(fs[0])(x)"),
+            ("y = x |> f", "y = f(x)"),
+        ];
+        for (input, exp) in tests {
+            let mut p = Parser::new(Lexer::new(input));
+            let program = p.parse();
+            check_errors(&p);
+            let mut p = Parser::new(Lexer::new(exp));
+            let expected = p.parse();
+            check_errors(&p);
+            // the statement's token is the one it starts with
+            let [Statement::ExpressionStatement(piped)] = &program.statements[..] else {
+                panic!("{:?} is not one expression statement", program.statements);
+            };
+            let [Statement::ExpressionStatement(call)] = &expected.statements[..] else {
+                panic!("{:?} is not one expression statement", expected.statements);
+            };
+            assert_eq!(piped.expression, call.expression, "{}", input);
+        }
+
+        let mut p = Parser::new(Lexer::new("x |> "));
+        p.parse();
+        assert_eq!(p.get_errors()[0], "no prefix parse fn for Eof");
+    }
+
     #[test]
     fn test_string_literal_expression() {
         let input = "\"hello world\"";
@@ -1284,6 +2464,36 @@ mod test {
         }
     }
 
+    #[test]
+    fn test_parsing_member_expression() {
+        let mut p = Parser::new(Lexer::new("string.split"));
+        let program = p.parse();
+        check_errors(&p);
+        let exp = Expression::IndexExpression(IndexExpression {
+            tok: Token::Dot,
+            left: Rc::new(Expression::Identifier(Identifier {
+                tok: Token::Ident("string".into()),
+                value: "string".into(),
+            })),
+            index: Rc::new(Expression::String(StringLiteral {
+                tok: Token::String("split".into()),
+                value: "split".into(),
+            })),
+        });
+        assert_eq!(program.statements[0].expression(), Some(&exp));
+
+        // a float is lexed before the dot is
+        let program = Parser::new(Lexer::new("a[1.5]")).parse();
+        assert_eq!(program.string(), "(a[1.5])");
+
+        let mut p = Parser::new(Lexer::new("a.1"));
+        p.parse();
+        assert_eq!(
+            p.get_errors()[0],
+            "expected token to be Token::Ident, got Int(\n    \"1\",\n) instead"
+        );
+    }
+
     #[test]
     fn test_parsing_hash_literal_string_keys() {
         let input = "{\"one\": 1, \"two\": 2, \"three\": 3}";
@@ -1296,7 +2506,7 @@ mod test {
         if let Statement::ExpressionStatement(es) = stmt {
             if let Expression::Hash(hash) = &es.expression {
                 assert_eq!(hash.pairs.len(), 3);
-                let exp_keys = vec!["one", "two", "three"];
+                let exp_keys = ["one", "two", "three"];
                 let exp_ints: Vec<i64> = vec![1, 2, 3];
                 for (i, pair) in hash.pairs.iter().enumerate() {
                     let exp_key = &exp_keys[i];
@@ -1352,8 +2562,8 @@ mod test {
         if let Statement::ExpressionStatement(es) = stmt {
             if let Expression::Hash(hash) = &es.expression {
                 assert_eq!(hash.pairs.len(), 3);
-                let exp_keys = vec!["one", "two", "three"];
-                let exp_values = vec![
+                let exp_keys = ["one", "two", "three"];
+                let exp_values = [
                     InfixIntTest {
                         input: "",
                         lval: 0,
@@ -1384,7 +2594,7 @@ mod test {
                     } else {
                         panic!("{:#?} is not a string literal", key);
                     }
-                    test_int_infix_exp(&val, exp_val.lval, exp_val.rval, exp_val.oper.clone());
+                    test_int_infix_exp(val, exp_val.lval, exp_val.rval, exp_val.oper.clone());
                 }
             } else {
                 panic!("{:#?} is not an index expression", es.expression);
@@ -1394,4 +2604,752 @@ mod test {
             panic!("{}", s);
         }
     }
+
+    #[test]
+    fn test_long_sum_small_stack() {
+        const TERMS: usize = 100_000;
+        let handle = std::thread::Builder::new()
+            .stack_size(256 * 1024)
+            .spawn(|| {
+                let input = vec!["1"; TERMS].join(" + ");
+                let l = Lexer::new(&input);
+                let mut p = Parser::new(l);
+                let mut program = p.parse();
+                check_errors(&p);
+                assert_eq!(program.statements.len(), 1);
+                // walk (and take apart) the left spine iteratively, dropping
+                // the tree recursively would overflow this stack
+                let mut exp = match program.statements.pop() {
+                    Some(Statement::ExpressionStatement(es)) => es.expression,
+                    stmt => panic!("{:#?} is not an expression statement", stmt),
+                };
+                let mut depth = 0;
+                while let Expression::InfixExpression(ie) = exp {
+                    assert_eq!(ie.operator, InfixOperator::Plus);
+                    test_integer_exp(&ie.right, 1);
+                    depth += 1;
+                    exp = std::rc::Rc::try_unwrap(ie.left).expect("left operand is shared");
+                }
+                test_integer_exp(&exp, 1);
+                depth
+            })
+            .expect("failed to spawn parser thread");
+        assert_eq!(handle.join().expect("parser thread panicked"), TERMS - 1);
+    }
+
+    #[test]
+    fn test_lexer_errors_are_reported() {
+        let l = Lexer::new("let x = \"way too long\"; let y = 1;").with_max_literal_len(8);
+        let mut p = Parser::new(l);
+        let program = p.parse();
+        assert_eq!(p.get_errors()[0], "string literal exceeds max length of 8");
+        test_let_statement(&program.statements[program.statements.len() - 1], "y");
+    }
+
+    #[test]
+    fn test_reset_matches_fresh_parser() {
+        let inputs = [
+            "let x = 5 * (2 + y);",
+            "fn(a, b) { return a, b; }(1, 2)[0]",
+            "{\"one\": 1, \"two\": [2, 2]}",
+            "if (x < y) { x } else { y }",
+        ];
+        let mut reused = Parser::new(Lexer::new(""));
+        for input in inputs {
+            reused.reset(input);
+            let got = reused.parse();
+            check_errors(&reused);
+
+            let mut fresh = Parser::new(Lexer::new(input));
+            assert_eq!(got.statements, fresh.parse().statements);
+        }
+    }
+
+    #[test]
+    fn test_parse_into() {
+        let mut buf = Vec::new();
+        let mut p = Parser::new(Lexer::new("let a = 1; a + 1;"));
+        assert_eq!(p.parse_into(&mut buf, usize::MAX), 2);
+        p.reset("let b = a; fn(x) { x }(b)");
+        assert_eq!(p.parse_into(&mut buf, usize::MAX), 2);
+        check_errors(&p);
+        let all = Parser::new(Lexer::new("let a = 1; a + 1; let b = a; fn(x) { x }(b)")).parse();
+        assert_eq!(buf, all.statements);
+
+        // a limit stops it between statements, and it carries on from there
+        let mut buf = Vec::new();
+        let mut p = Parser::new(Lexer::new("1; 2; 3;"));
+        assert_eq!(p.parse_into(&mut buf, 2), 2);
+        assert_eq!(p.parse_into(&mut buf, 2), 1);
+        assert_eq!(p.parse_into(&mut buf, 2), 0);
+        assert_eq!(buf.len(), 3);
+
+        p.reset("let = 2; 4;");
+        p.parse_into(&mut buf, usize::MAX);
+        assert!(p.errors_len() > 0);
+        assert_eq!(buf[buf.len() - 1].string(), "4");
+    }
+
+    #[test]
+    fn test_from_tokens() {
+        // let x = 1 + y; x
+        let tokens = vec![
+            Token::Let,
+            Token::Ident("x".into()),
+            Token::Assign,
+            Token::Int("1".into()),
+            Token::Plus,
+            Token::Ident("y".into()),
+            Token::Semicolon,
+            Token::Comment("// skipped".into()),
+            Token::Ident("x".into()),
+        ];
+        let mut p = Parser::from_tokens(tokens);
+        let program = p.parse();
+        check_errors(&p);
+
+        let ident = |name: &str| Identifier {
+            tok: Token::Ident(name.into()),
+            value: name.into(),
+        };
+        let exp = vec![
+            Statement::LetStatement(LetStatement {
+                tok: Token::Let,
+                name: ident("x"),
+                value: Expression::InfixExpression(InfixExpression {
+                    tok: Token::Plus,
+                    left: Rc::new(Expression::Integer(IntegerLiteral {
+                        tok: Token::Int("1".into()),
+                        value: 1,
+                    })),
+                    operator: InfixOperator::Plus,
+                    right: Rc::new(Expression::Identifier(ident("y"))),
+                }),
+                mutable: true,
+            }),
+            Statement::ExpressionStatement(ExpressionStatement {
+                tok: Token::Ident("x".into()),
+                expression: Expression::Identifier(ident("x")),
+            }),
+        ];
+        assert_eq!(program.statements, exp);
+
+        let mut p = Parser::from_tokens(vec![Token::Let, Token::Assign, Token::Eof]);
+        p.parse();
+        assert_eq!(
+            p.get_errors()[0],
+            "expected next token to be Token::Ident, got Assign instead"
+        );
+
+        // a reset parser lexes its new source
+        p.reset("let y = 2;");
+        let program = p.parse();
+        check_errors(&p);
+        test_let_statement(&program.statements[0], "y");
+    }
+
+    #[test]
+    fn test_nesting_limit() {
+        let n = 100_000;
+        let inputs = [
+            "(".repeat(n) + "1",
+            "-".repeat(n) + "1",
+            "[".repeat(n) + "1",
+            "if (1) { ".repeat(n) + "1",
+            "fn() { ".repeat(n) + "1",
+            "{1: ".repeat(n) + "1",
+        ];
+        for input in inputs.iter() {
+            let mut p = Parser::new(Lexer::new(input));
+            p.parse();
+            let errors = p.get_errors();
+            assert!(
+                errors.contains(&"expression nested too deeply, the limit is 128".to_owned()),
+                "{:?}...",
+                &input[..20]
+            );
+        }
+
+        let input = "(".repeat(MAX_NESTING_DEPTH - 1) + "1" + &")".repeat(MAX_NESTING_DEPTH - 1);
+        let mut p = Parser::new(Lexer::new(&input));
+        p.parse();
+        check_errors(&p);
+    }
+
+    #[test]
+    fn test_argument_limit() {
+        let args: Vec<String> = (0..300).map(|i| i.to_string()).collect();
+        let params: Vec<String> = (0..300).map(|i| format!("p{}", i)).collect();
+        let tests = [
+            (
+                format!("f({})", args.join(", ")),
+                "too many arguments, the limit is 255",
+            ),
+            (
+                format!("fn({}) {{}}", params.join(", ")),
+                "too many parameters, the limit is 255",
+            ),
+        ];
+        for (input, exp) in tests.iter() {
+            let mut p = Parser::new(Lexer::new(input));
+            p.parse();
+            assert_eq!(p.get_errors().first().map(String::as_str), Some(*exp));
+
+            let mut p = Parser::new(Lexer::new(input)).with_max_arguments(300);
+            p.parse();
+            check_errors(&p);
+        }
+
+        let tests = [
+            ("f(1, 2, 3)", Some("too many arguments, the limit is 2")),
+            ("f(1, 2)", None),
+            (
+                "fn(a, b, c) { a }",
+                Some("too many parameters, the limit is 2"),
+            ),
+            (
+                "macro(a, b, c) { a }",
+                Some("too many parameters, the limit is 2"),
+            ),
+            ("fn(a, b) { a }", None),
+            // only calls have a limit
+            ("[1, 2, 3]", None),
+        ];
+        for (input, exp) in tests {
+            let mut p = Parser::new(Lexer::new(input)).with_max_arguments(2);
+            p.parse();
+            assert_eq!(p.get_errors().first().map(String::as_str), exp, "{}", input);
+        }
+
+        let mut p = Parser::new(Lexer::new("f(); f(1)")).with_max_arguments(0);
+        p.parse();
+        assert_eq!(p.get_errors()[0], "too many arguments, the limit is 0");
+
+        let args = vec!["1"; MAX_ARGUMENTS].join(", ");
+        let mut p = Parser::new(Lexer::new(&format!("f({})", args)));
+        p.parse();
+        check_errors(&p);
+    }
+
+    #[test]
+    fn test_max_reads() {
+        // the fuzz target's budget, which unclosed nesting at the limit
+        // comes closest to
+        let tests = [
+            "let x = 5; let y = fn(a) { a + x }; y(1)",
+            &"(".repeat(MAX_NESTING_DEPTH + 1),
+            &"if (x) {".repeat(MAX_NESTING_DEPTH + 1),
+            &"fn() {".repeat(MAX_NESTING_DEPTH + 1),
+        ];
+        for input in tests {
+            let tokens = crate::lexer::token_spans(input).len();
+            let mut p =
+                Parser::new(Lexer::new(input)).with_max_reads(tokens + 2 * MAX_NESTING_DEPTH);
+            p.parse();
+        }
+
+        let parse = std::panic::catch_unwind(|| {
+            Parser::new(Lexer::new("let x = 5;"))
+                .with_max_reads(4)
+                .parse()
+        });
+        assert!(parse.is_err());
+    }
+
+    #[test]
+    fn test_block_or_hash() {
+        // the token after a hash key is `:`, which takes two tokens of
+        // lookahead past the `{` to see
+        let hashes = [
+            "{}",
+            "{ x: 1 }",
+            "{ \"a\": 1, \"b\": 2 }",
+            "{ -x: 1 }",
+            "{ f(x): 1 }",
+        ];
+        for input in hashes {
+            let mut p = Parser::new(Lexer::new(input));
+            let program = p.parse();
+            check_errors(&p);
+            assert_eq!(program.statements.len(), 1, "{}", input);
+            match &program.statements[0] {
+                Statement::ExpressionStatement(ExpressionStatement {
+                    expression: Expression::Hash(_),
+                    ..
+                }) => {}
+                stmt => panic!("{} parsed to {:?}", input, stmt),
+            }
+        }
+        let blocks = [
+            "{ x }",
+            "{ x; y }",
+            "{ let x = 1; }",
+            "{ return 1; }",
+            "{ 1 }",
+        ];
+        for input in blocks {
+            let mut p = Parser::new(Lexer::new(input));
+            p.parse();
+            assert_eq!(
+                p.get_errors().first().map(String::as_str),
+                Some("unexpected block, a block only follows if, else, for or fn"),
+                "{}",
+                input
+            );
+        }
+    }
+
+    #[test]
+    fn test_peek_n() {
+        let mut p = Parser::new(Lexer::new("a b c d"));
+        assert_eq!(*p.peek_n(2), Token::Ident("d".into()));
+        assert_eq!(*p.peek_n(0), Token::Ident("b".into()));
+        assert_eq!(*p.peek_n(1), Token::Ident("c".into()));
+        assert_eq!(*p.peek_n(3), Token::Eof);
+        // nothing was consumed by looking ahead
+        let mut seen = vec![p.cur.clone()];
+        while p.cur != Token::Eof {
+            p.next_token();
+            seen.push(p.cur.clone());
+        }
+        let idents = ["a", "b", "c", "d"].map(|s| Token::Ident(s.into()));
+        assert_eq!(seen[..4], idents);
+        assert_eq!(seen[4], Token::Eof);
+    }
+
+    #[test]
+    fn test_statements_ending_at_eof() {
+        // the semicolon after a statement is optional
+        for input in ["let x = 5", "const x = 5", "let a, b = (1, 2)", "return 5"] {
+            let mut p = Parser::new(Lexer::new(input));
+            let program = p.parse();
+            assert!(p.get_errors().is_empty(), "{}: {:?}", input, p.get_errors());
+            assert_eq!(program.statements.len(), 1, "{}", input);
+        }
+        for input in ["let", "let x", "let x =", "let a,", "return", "return 1,"] {
+            let mut p = Parser::new(Lexer::new(input));
+            p.parse();
+            assert!(!p.get_errors().is_empty(), "{}", input);
+        }
+    }
+
+    #[test]
+    fn test_literal_on_wrong_token() {
+        let mut p = Parser::new(Lexer::new("1 a"));
+        assert_eq!(p.parse_identifier(Owned), None);
+        p.next_token();
+        assert_eq!(p.parse_integer_literal(Owned), None);
+        assert_eq!(
+            p.get_errors(),
+            &[
+                "internal parser error: expected identifier token",
+                "internal parser error: expected integer token",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_dont_panic() {
+        let inputs: [&[u8]; 10] = [
+            b"",
+            b"let",
+            b"let a, = 1",
+            b"let a, b",
+            b"return 1, , 2",
+            b"return ,",
+            b"{ ,: }",
+            b"fn(a, a, ) {",
+            b"\xff\xfe let x = \"\xc3",
+            b"if (()) { [ } else {",
+        ];
+        for input in inputs {
+            parse_dont_panic(input);
+        }
+        let corpus = concat!(env!("CARGO_MANIFEST_DIR"), "/fuzz/corpus/parse");
+        for entry in std::fs::read_dir(corpus).expect("the corpus is checked in") {
+            parse_dont_panic(&std::fs::read(entry.unwrap().path()).unwrap());
+        }
+    }
+
+    #[test]
+    fn test_malformed_input_reports_errors() {
+        let tests = [
+            ("fn(", "expected token to be Token::Ident, got Eof instead"),
+            (
+                "fn(x,) {}",
+                "expected token to be Token::Ident, got RParen instead",
+            ),
+            (
+                "fn(1) {}",
+                "expected token to be Token::Ident, got Int(\n    \"1\",\n) instead",
+            ),
+            (
+                "99999999999999999999",
+                "could not parse 99999999999999999999 as integer",
+            ),
+            (
+                "if (x) { y",
+                "expected next token to be RSquirly, got Eof instead",
+            ),
+            (
+                "fn() { 1",
+                "expected next token to be RSquirly, got Eof instead",
+            ),
+            (
+                "{ x }",
+                "unexpected block, a block only follows if, else, for or fn",
+            ),
+        ];
+        for (input, exp) in tests {
+            let mut p = Parser::new(Lexer::new(input));
+            p.parse();
+            assert_eq!(
+                p.get_errors().first().map(String::as_str),
+                Some(exp),
+                "{}",
+                input
+            );
+        }
+    }
+
+    #[test]
+    fn test_error_tokens() {
+        let input = "let = 5;\nlet x 5;\nfn(x) { y\n";
+        let mut p = Parser::new(Lexer::new(input));
+        p.parse();
+        let spans = token_spans(input);
+        let at: Vec<(usize, usize)> = p
+            .error_tokens()
+            .iter()
+            .map(|token| line_col(input, spans[token.unwrap()].start))
+            .collect();
+        assert_eq!(p.get_errors().len(), at.len());
+        assert_eq!(
+            p.get_errors()[0],
+            "expected next token to be Token::Ident, got Assign instead"
+        );
+        assert_eq!(at, [(1, 5), (1, 5), (2, 7), (4, 1)], "{:?}", p.get_errors());
+
+        // lexer errors are at the token they made
+        let input = "1;\n  \"\\q\"";
+        let mut p = Parser::new(Lexer::new(input));
+        p.parse();
+        assert_eq!(p.get_errors()[0], "unknown escape \\q");
+        let token = p.error_tokens()[0].unwrap();
+        assert_eq!(line_col(input, token_spans(input)[token].start), (2, 3));
+
+        let mut p = Parser::new(Lexer::new("let a = 1; let a = 2;")).with_no_shadowing();
+        p.parse();
+        assert_eq!(p.error_tokens(), [None]);
+        p.reset("");
+        assert!(p.error_tokens().is_empty());
+    }
+
+    #[test]
+    fn test_source_map() {
+        // 1 + 2 * 3
+        let tokens = vec![
+            Token::Int("1".into()),
+            Token::Plus,
+            Token::Int("2".into()),
+            Token::Asterisk,
+            Token::Int("3".into()),
+        ];
+        let mut p = Parser::from_tokens(tokens).with_source_map();
+        p.parse();
+        check_errors(&p);
+        assert_eq!(p.token_range(&[0]), Some((0, 5)));
+        assert_eq!(p.token_range(&[0, 0]), Some((0, 1)));
+        assert_eq!(p.token_range(&[0, 1]), Some((2, 5)));
+        assert_eq!(p.token_range(&[0, 1, 1]), Some((4, 5)));
+        assert_eq!(p.token_range(&[0, 2]), None);
+
+        assert!(Parser::from_tokens(vec![Token::Int("1".into())])
+            .source_map()
+            .is_empty());
+    }
+
+    /* the source of the tokens of every expression parses back to it */
+    fn check_source_map(source: &str, map: &SourceMap, exp: &Expression) {
+        let spans = token_spans(source);
+        let (start, end) = map.token_range;
+        let text = &source[spans[start].start..spans[end - 1].end];
+        let mut p = Parser::new(Lexer::new(text));
+        let program = p.parse();
+        check_errors(&p);
+        assert_eq!(program.string(), exp.string(), "{:?} in {:?}", text, source);
+
+        let mut exp = exp.clone();
+        let children = exp.children_mut();
+        assert_eq!(children.len(), map.children.len(), "{:?}", text);
+        for (child, map) in children.into_iter().zip(map.children.iter()) {
+            check_source_map(source, map, child);
+        }
+    }
+
+    #[test]
+    fn test_source_map_covers_expressions() {
+        let inputs = [
+            "let x = (1 + 2) * -f(a, b)[0];",
+            "// comment\nx = y = [1, {\"a\": 2}, (3, 4)];",
+            "if (x) { let y = x; y } else { z };",
+            "for (x in xs) { break; x + 1 }",
+            "let f = fn(a) { return (a, a); };\nmacro(q) { q };",
+            "(-a)(b)",
+            "xs |> map(f) |> len == 2 |> g;",
+        ];
+        for input in inputs {
+            let mut p = Parser::new(Lexer::new(input)).with_source_map();
+            let mut program = p.parse();
+            check_errors(&p);
+            assert_eq!(p.source_map().len(), program.statements.len());
+            for (stmt, map) in program.statements.iter_mut().zip(p.source_map()) {
+                check_source_map(input, map, stmt.expression_mut().unwrap());
+            }
+        }
+
+        // a failed statement leaves nothing behind
+        let mut p = Parser::new(Lexer::new("1 + (2; 3 * 4")).with_source_map();
+        p.parse();
+        assert_eq!(p.source_map().len(), 1);
+        assert_eq!(p.token_range(&[0]), Some((5, 8)));
+        p.reset("a");
+        p.parse();
+        assert_eq!(p.token_range(&[0]), Some((0, 1)));
+
+        // the names a statement binds are noted with its value
+        let mut p = Parser::new(Lexer::new(
+            "let a, b = (1, 2);\nfn f() { const c = 3; }\na + 1;",
+        ))
+        .with_source_map();
+        p.parse();
+        check_errors(&p);
+        let names: Vec<_> = p.source_map().iter().map(|map| map.names).collect();
+        assert_eq!(names, [Some((1, 4)), Some((12, 13)), None]);
+        // the `const` in the body of `f`
+        assert_eq!(p.source_map()[1].names(&[0]), Some((17, 18)));
+    }
+
+    #[test]
+    fn test_reset_clears_errors() {
+        let mut p = Parser::new(Lexer::new("let = 5;").with_max_literal_len(4));
+        p.parse();
+        assert!(p.errors_len() > 0);
+
+        p.reset("let x = 5;");
+        let program = p.parse();
+        check_errors(&p);
+        test_let_statement(&program.statements[0], "x");
+
+        // the literal cap survives a reset
+        p.reset("let abcde = 1;");
+        p.parse();
+        assert_eq!(p.get_errors()[0], "identifier exceeds max length of 4");
+    }
+
+    #[test]
+    fn test_no_shadowing() {
+        let tests = [
+            ("let x = 1; let x = 2;", vec!["redeclaration of 'x'"]),
+            (
+                "let x = 1; if (x) { let x = 2; }",
+                vec!["redeclaration of 'x'"],
+            ),
+            (
+                "let a, b = (1, 2); let b = 3;",
+                vec!["redeclaration of 'b'"],
+            ),
+            ("fn(x) { let x = 2; }", vec!["redeclaration of 'x'"]),
+            ("fn(x, x) { x }", vec!["redeclaration of 'x'"]),
+            (
+                "let x = 1; let y = 2; let x = 3; let y = 4;",
+                vec!["redeclaration of 'x'", "redeclaration of 'y'"],
+            ),
+            ("let x = 1; let f = fn() { let x = 2; x };", vec![]),
+            ("let f = fn(x) { fn() { let x = 1; } };", vec![]),
+            (
+                "let f = fn() { let y = 1; }; let g = fn() { let y = 2; };",
+                vec![],
+            ),
+            ("let x = 1; x + fn(y) { let x = y; x }(2);", vec![]),
+        ];
+        for (input, exp) in tests {
+            let mut p = Parser::new(Lexer::new(input)).with_no_shadowing();
+            p.parse();
+            assert_eq!(p.get_errors(), &exp, "{}", input);
+
+            // the check survives a reset, and is off by default
+            p.reset(input);
+            p.parse();
+            assert_eq!(p.get_errors(), &exp, "{}", input);
+            let mut p = Parser::new(Lexer::new(input));
+            p.parse();
+            check_errors(&p);
+        }
+    }
+
+    fn all_tokens() -> Vec<Token> {
+        // adding a variant to Token breaks this match until it's listed below
+        match Token::Illegal {
+            Token::Illegal
+            | Token::Eof
+            | Token::Ident(_)
+            | Token::Int(_)
+            | Token::Float(_)
+            | Token::String(_)
+            | Token::Assign
+            | Token::Plus
+            | Token::Minus
+            | Token::Slash
+            | Token::Asterisk
+            | Token::Bang
+            | Token::Lt
+            | Token::Gt
+            | Token::Eq
+            | Token::NotEq
+            | Token::Comma
+            | Token::Colon
+            | Token::Semicolon
+            | Token::Pipe
+            | Token::Dot
+            | Token::LParen
+            | Token::RParen
+            | Token::LSquirly
+            | Token::RSquirly
+            | Token::LBracket
+            | Token::RBracket
+            | Token::Function
+            | Token::Macro
+            | Token::Let
+            | Token::Const
+            | Token::If
+            | Token::Else
+            | Token::Return
+            | Token::For
+            | Token::In
+            | Token::Break
+            | Token::Continue
+            | Token::Import
+            | Token::True
+            | Token::False
+            | Token::Comment(_) => {}
+        }
+        vec![
+            Token::Illegal,
+            Token::Eof,
+            Token::Ident("x".into()),
+            Token::Int("1".into()),
+            Token::Float("1.5".into()),
+            Token::String("s".into()),
+            Token::Assign,
+            Token::Plus,
+            Token::Minus,
+            Token::Slash,
+            Token::Asterisk,
+            Token::Bang,
+            Token::Lt,
+            Token::Gt,
+            Token::Eq,
+            Token::NotEq,
+            Token::Comma,
+            Token::Colon,
+            Token::Semicolon,
+            Token::Pipe,
+            Token::Dot,
+            Token::LParen,
+            Token::RParen,
+            Token::LSquirly,
+            Token::RSquirly,
+            Token::LBracket,
+            Token::RBracket,
+            Token::Function,
+            Token::Macro,
+            Token::Let,
+            Token::Const,
+            Token::If,
+            Token::Else,
+            Token::Return,
+            Token::For,
+            Token::In,
+            Token::Break,
+            Token::Continue,
+            Token::Import,
+            Token::True,
+            Token::False,
+            Token::Comment("// c".into()),
+        ]
+    }
+
+    #[test]
+    fn test_precedence_values() {
+        for tok in all_tokens() {
+            let exp = match tok {
+                Token::Assign => Precedence::Assign,
+                Token::Pipe => Precedence::Pipe,
+                Token::Eq | Token::NotEq => Precedence::Equals,
+                Token::Lt | Token::Gt => Precedence::LessGreater,
+                Token::Plus | Token::Minus => Precedence::Sum,
+                Token::Asterisk | Token::Slash => Precedence::Product,
+                Token::LParen => Precedence::Call,
+                Token::LBracket | Token::Dot => Precedence::Index,
+                _ => Precedence::Lowest,
+            };
+            assert_eq!(tok.precedence(), exp, "{:?}", tok);
+        }
+        // the parser looks both up in the same table
+        let mut p = Parser::new(Lexer::new("a * b"));
+        assert_eq!(p.peek_precedence(), Precedence::Product);
+        p.next_token();
+        assert_eq!(p.cur_precedence(), Precedence::Product);
+    }
+
+    #[test]
+    fn test_operator_tables_agree() {
+        for tok in all_tokens() {
+            let precedence = tok.precedence();
+            match tok.infix_operator() {
+                Some(op) => {
+                    assert!(
+                        precedence > Precedence::Lowest && precedence < Precedence::Prefix,
+                        "{:?} is an infix operator with precedence {:?}",
+                        tok,
+                        precedence
+                    );
+                    let mut l = Lexer::new(&op.to_string());
+                    assert_eq!(l.next_token(), tok, "{} doesn't lex back to {:?}", op, tok);
+
+                    let input = format!("a {} b", op);
+                    let l = Lexer::new(&input);
+                    let mut p = Parser::new(l);
+                    let program = p.parse();
+                    check_errors(&p);
+                    assert_eq!(program.statements.len(), 1);
+                    if let Statement::ExpressionStatement(es) = &program.statements[0] {
+                        test_ident_infix_exp(&es.expression, "a", "b", op.clone());
+                    } else {
+                        panic!(
+                            "{:#?} is not an expression statement",
+                            program.statements[0]
+                        );
+                    }
+                    assert_eq!(program.string(), format!("(a {} b)", op));
+                }
+                None => match tok {
+                    Token::LParen | Token::LBracket | Token::Dot => {
+                        assert!(precedence > Precedence::Prefix)
+                    }
+                    // `=` is parsed on its own, see `parse_assign_expression`
+                    Token::Assign => assert_eq!(precedence, Precedence::Assign),
+                    // and `|>` in `parse_pipe`
+                    Token::Pipe => assert_eq!(precedence, Precedence::Pipe),
+                    _ => assert_eq!(
+                        precedence,
+                        Precedence::Lowest,
+                        "{:?} has a precedence but no infix operator",
+                        tok
+                    ),
+                },
+            }
+        }
+    }
 }
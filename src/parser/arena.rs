@@ -0,0 +1,350 @@
+//! [`Parser::parse_in`], the bump allocated counterpart of [`Parser::parse`].
+//!
+//! The parser is the same one, with a [`NodeBuilder`] that makes the nodes
+//! of [`crate::ast::arena`] in the bump instead of those of the owned AST.
+
+use std::rc::Rc;
+
+use bumpalo::collections::Vec as BumpVec;
+use bumpalo::Bump;
+
+use super::build::{List, NodeBuilder};
+use super::Parser;
+use crate::ast::arena::{Expression, Program, Statement};
+use crate::ast::{InfixOperator, Node, PrefixOperator};
+use crate::token::Token;
+
+impl Parser {
+    /// Parses the program into `bump` instead of allocating every node on
+    /// the heap. See [`crate::ast::arena`] for how to get an owned tree out.
+    /// It keeps no [`source_map`](Parser::source_map).
+    pub fn parse_in<'a>(&mut self, bump: &'a Bump) -> Program<'a> {
+        let source_map = self.source_map.take();
+        let mut res = BumpVec::new_in(bump);
+        while self.cur != Token::Eof {
+            if let Some(s) = self.parse_statement(bump) {
+                res.push(s);
+            }
+            self.next_token();
+        }
+        self.source_map = source_map;
+        Program {
+            statements: res.into_bump_slice(),
+        }
+    }
+}
+
+impl<'a, T> List<T> for BumpVec<'a, T> {
+    fn push(&mut self, item: T) {
+        BumpVec::push(self, item)
+    }
+
+    fn len(&self) -> usize {
+        BumpVec::len(self)
+    }
+}
+
+impl<'a> NodeBuilder for &'a Bump {
+    type Expr = Expression<'a>;
+    type Stmt = Statement<'a>;
+    type Name = &'a str;
+    type Block = &'a [Statement<'a>];
+    type Function = (&'a [&'a str], &'a [Statement<'a>]);
+    type Exprs = BumpVec<'a, Expression<'a>>;
+    type Names = BumpVec<'a, &'a str>;
+    type Stmts = BumpVec<'a, Statement<'a>>;
+    type Pairs = BumpVec<'a, (Expression<'a>, Expression<'a>)>;
+
+    fn exprs(self) -> Self::Exprs {
+        BumpVec::new_in(self)
+    }
+
+    fn names(self) -> Self::Names {
+        BumpVec::new_in(self)
+    }
+
+    fn stmts(self) -> Self::Stmts {
+        BumpVec::new_in(self)
+    }
+
+    fn pairs(self) -> Self::Pairs {
+        BumpVec::new_in(self)
+    }
+
+    fn child_count(_: &Expression<'a>) -> usize {
+        unreachable!("parse_in keeps no source map")
+    }
+
+    fn string(exp: &Expression<'a>) -> String {
+        exp.to_owned_expression().string()
+    }
+
+    fn is_call(exp: &Expression<'a>) -> bool {
+        matches!(exp, Expression::Call(..))
+    }
+
+    fn is_string(exp: &Expression<'a>) -> bool {
+        matches!(exp, Expression::String(_))
+    }
+
+    fn name(self, _: Token, value: &Rc<str>) -> &'a str {
+        self.alloc_str(value)
+    }
+
+    fn identifier(self, name: &'a str) -> Expression<'a> {
+        Expression::Identifier(name)
+    }
+
+    fn into_name(self, exp: Expression<'a>) -> Result<&'a str, Expression<'a>> {
+        match exp {
+            Expression::Identifier(name) => Ok(name),
+            exp => Err(exp),
+        }
+    }
+
+    fn integer(self, _: Token, literal: &str, value: i64) -> Expression<'a> {
+        Expression::Integer(self.alloc_str(literal), value)
+    }
+
+    fn float(self, _: Token, literal: &str, value: f64) -> Expression<'a> {
+        Expression::Float(self.alloc_str(literal), value)
+    }
+
+    fn string_literal(self, _: Token, value: &Rc<str>) -> Expression<'a> {
+        Expression::String(self.alloc_str(value))
+    }
+
+    fn boolean(self, _: Token, value: bool) -> Expression<'a> {
+        Expression::Boolean(value)
+    }
+
+    fn array(self, _: Token, elements: Self::Exprs) -> Expression<'a> {
+        Expression::Array(elements.into_bump_slice())
+    }
+
+    fn tuple(self, _: Token, elements: Self::Exprs) -> Expression<'a> {
+        Expression::Tuple(elements.into_bump_slice())
+    }
+
+    fn hash(self, _: Token, pairs: Self::Pairs) -> Expression<'a> {
+        Expression::Hash(pairs.into_bump_slice())
+    }
+
+    fn prefix(self, _: Token, operator: PrefixOperator, right: Expression<'a>) -> Expression<'a> {
+        Expression::Prefix(operator, self.alloc(right))
+    }
+
+    fn infix(
+        self,
+        _: Token,
+        left: Expression<'a>,
+        operator: InfixOperator,
+        right: Expression<'a>,
+    ) -> Expression<'a> {
+        Expression::Infix(self.alloc(left), operator, self.alloc(right))
+    }
+
+    fn assign(self, _: Token, name: &'a str, value: Expression<'a>) -> Expression<'a> {
+        Expression::Assign(name, self.alloc(value))
+    }
+
+    fn pipe(self, left: Expression<'a>, right: Expression<'a>) -> Expression<'a> {
+        let (function, arguments) = match right {
+            Expression::Call(function, arguments) => (function, arguments),
+            function => (&*self.alloc(function), &[][..]),
+        };
+        let mut piped = BumpVec::with_capacity_in(arguments.len() + 1, self);
+        piped.push(left);
+        piped.extend(arguments.iter().cloned());
+        Expression::Call(function, piped.into_bump_slice())
+    }
+
+    fn if_expression(
+        self,
+        _: Token,
+        condition: Expression<'a>,
+        consequence: Self::Block,
+        alternative: Option<Self::Block>,
+    ) -> Expression<'a> {
+        Expression::If(self.alloc(condition), consequence, alternative)
+    }
+
+    fn for_expression(
+        self,
+        _: Token,
+        label: Option<&'a str>,
+        names: Self::Names,
+        iterable: Expression<'a>,
+        body: Self::Block,
+    ) -> Expression<'a> {
+        Expression::For(label, names.into_bump_slice(), self.alloc(iterable), body)
+    }
+
+    fn function(self, _: Token, parameters: Self::Names, body: Self::Block) -> Self::Function {
+        (parameters.into_bump_slice(), body)
+    }
+
+    fn function_literal(self, (parameters, body): Self::Function) -> Expression<'a> {
+        Expression::Function(parameters, body)
+    }
+
+    fn macro_literal(self, _: Token, parameters: Self::Names, body: Self::Block) -> Expression<'a> {
+        Expression::Macro(parameters.into_bump_slice(), body)
+    }
+
+    fn call(self, _: Token, function: Expression<'a>, arguments: Self::Exprs) -> Expression<'a> {
+        Expression::Call(self.alloc(function), arguments.into_bump_slice())
+    }
+
+    fn index(self, _: Token, left: Expression<'a>, index: Expression<'a>) -> Expression<'a> {
+        Expression::Index(self.alloc(left), self.alloc(index))
+    }
+
+    fn member(self, _: Token, left: Expression<'a>, name: &Rc<str>) -> Expression<'a> {
+        Expression::Member(self.alloc(left), self.alloc_str(name))
+    }
+
+    fn block(self, _: Token, statements: Self::Stmts) -> Self::Block {
+        statements.into_bump_slice()
+    }
+
+    fn let_statement(
+        self,
+        _: Token,
+        names: Self::Names,
+        value: Expression<'a>,
+        mutable: bool,
+    ) -> Statement<'a> {
+        if names.len() == 1 {
+            Statement::Let(names[0], value, mutable)
+        } else {
+            Statement::LetTuple(names.into_bump_slice(), value, mutable)
+        }
+    }
+
+    fn function_declaration(self, _: Token, name: &'a str, value: Expression<'a>) -> Statement<'a> {
+        let Expression::Function(parameters, body) = value else {
+            unreachable!("a declaration of something other than a function");
+        };
+        Statement::Function(name, parameters, body)
+    }
+
+    fn return_statement(self, _: Token, value: Expression<'a>) -> Statement<'a> {
+        Statement::Return(value)
+    }
+
+    fn break_statement(self, _: Token, label: Option<&'a str>) -> Statement<'a> {
+        Statement::Break(label)
+    }
+
+    fn continue_statement(self, _: Token) -> Statement<'a> {
+        Statement::Continue
+    }
+
+    fn import(self, _: Token, path: Expression<'a>, alias: Option<&'a str>) -> Statement<'a> {
+        let Expression::String(path) = path else {
+            unreachable!("an import of something other than a string");
+        };
+        Statement::Import(path, alias)
+    }
+
+    fn expression_statement(self, _: Token, expression: Expression<'a>) -> Statement<'a> {
+        Statement::Expression(expression)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use bumpalo::Bump;
+
+    use crate::ast::Node;
+    use crate::lexer::Lexer;
+    use crate::parser::Parser;
+
+    const INPUT: &str = "let x = 5 * -y + add(1, 2)[0];
+let a, b = (1, true);
+const PI = 3;
+let f = fn(p, q) { if (p < q) { return p, q; } else { q } };
+f(\"one\", {\"k\": [1, 2], 3: !false});
+let m = macro(x) { quote(unquote(x) + 1) };
+fn g(n) { g(n - 1) }
+let y = (x = x + 1);
+1 + 2 + 3 * 4 == 15 != false;
+0xFF + 1_000;
+for (k, v in {1: 2}) { if (k) { continue; } break; };
+for (c in \"ab\") { c };
+[1, 2] |> len |> f(1) == 2 |> g;
+string.split(\"a b\", \" \")[0].x;
+outer: for (x in [1]) { for (y in [2]) { break outer; } };";
+
+    #[test]
+    fn test_parse_in_matches_parse() {
+        let mut p = Parser::new(Lexer::new(INPUT));
+        let expected = p.parse();
+        assert!(p.get_errors().is_empty());
+
+        let bump = Bump::new();
+        let mut p = Parser::new(Lexer::new(INPUT)).with_source_map();
+        let program = p.parse_in(&bump);
+        assert!(p.get_errors().is_empty());
+        assert!(p.source_map().is_empty());
+        let owned = program.to_owned_program();
+
+        assert_eq!(owned.string(), expected.string());
+        // the bare `return p, q` and the statement that starts with a pipe
+        // are the only places the tokens differ
+        let differing = owned
+            .statements
+            .iter()
+            .zip(expected.statements.iter())
+            .filter(|(a, b)| a != b)
+            .count();
+        assert_eq!(differing, 2);
+    }
+
+    #[test]
+    fn test_owned_program_outlives_arena() {
+        let owned = {
+            let bump = Bump::new();
+            let mut p = Parser::new(Lexer::new("let s = \"some string\"; s + \"!\";"));
+            let program = p.parse_in(&bump);
+            program.to_owned_program()
+        };
+        assert_eq!(owned.string(), "let s = \"some string\";\n(s + \"!\")");
+    }
+
+    #[test]
+    fn test_parse_in_reports_errors() {
+        let nested = "(".repeat(1000) + "1";
+        let nested_index = "[x[".repeat(1000);
+        let arguments = format!("f({})", vec!["1"; 300].join(", "));
+        let parameters = format!("fn({}) {{}}", vec!["x"; 300].join(", "));
+        let inputs = [
+            "let = 5; let x 5;",
+            "fn(x,) {}",
+            "fn(1, y) {}",
+            "99999999999999999999",
+            "if (x) { y",
+            "a + b = 1",
+            "break; for (x in y) { fn() { continue } }",
+            "for (a, b, c in d) {}",
+            "a.1",
+            "a: for (x in y) { break b; }",
+            "a: if (x) {}",
+            "a.",
+            &nested,
+            &nested_index,
+            &arguments,
+            &parameters,
+        ];
+        for input in inputs {
+            let bump = Bump::new();
+            let mut p = Parser::new(Lexer::new(input));
+            p.parse_in(&bump);
+            let mut expected = Parser::new(Lexer::new(input));
+            expected.parse();
+            assert!(!expected.get_errors().is_empty());
+            assert_eq!(p.get_errors(), expected.get_errors());
+        }
+    }
+}
@@ -0,0 +1,425 @@
+//! What the parser makes its nodes with, so one parser builds both the
+//! owned AST of [`Parser::parse`](super::Parser::parse) and the arena AST of
+//! `Parser::parse_in`.
+//!
+//! The parser checks the syntax, reports the errors and keeps the source
+//! map; a [`NodeBuilder`] only puts together what it is handed. Every method
+//! gets the token the owned node keeps, which the arena drops.
+
+use std::rc::Rc;
+
+use crate::ast::{
+    ArrayLiteral, AssignExpression, BlockStatement, BooleanLiteral, BreakStatement, CallExpression,
+    Constant, ContinueStatement, Expression, ExpressionStatement, FloatLiteral, ForExpression,
+    FunctionLiteral, HashLiteral, Identifier, IfExpression, ImportStatement, IndexExpression,
+    InfixExpression, InfixOperator, IntegerLiteral, LetStatement, LetTupleStatement, MacroLiteral,
+    Node, PrefixExpression, PrefixOperator, ReturnStatement, Statement, StringLiteral,
+    TupleLiteral,
+};
+use crate::token::Token;
+
+/* a list the parser pushes nodes onto one at a time */
+pub(super) trait List<T> {
+    fn push(&mut self, item: T);
+    fn len(&self) -> usize;
+}
+
+impl<T> List<T> for Vec<T> {
+    fn push(&mut self, item: T) {
+        Vec::push(self, item)
+    }
+
+    fn len(&self) -> usize {
+        Vec::len(self)
+    }
+}
+
+pub(super) trait NodeBuilder: Copy {
+    type Expr;
+    type Stmt;
+    type Name;
+    type Block;
+    /* the parameters and body of a function literal */
+    type Function;
+    type Exprs: List<Self::Expr>;
+    type Names: List<Self::Name>;
+    type Stmts: List<Self::Stmt>;
+    type Pairs: List<(Self::Expr, Self::Expr)>;
+
+    fn exprs(self) -> Self::Exprs;
+    fn names(self) -> Self::Names;
+    fn stmts(self) -> Self::Stmts;
+    fn pairs(self) -> Self::Pairs;
+
+    /* how many of the expressions below `exp` the source map has a range
+     * for, which only a builder that keeps one needs */
+    fn child_count(exp: &Self::Expr) -> usize;
+    /* for an error that shows the expression */
+    fn string(exp: &Self::Expr) -> String;
+    fn is_call(exp: &Self::Expr) -> bool;
+    fn is_string(exp: &Self::Expr) -> bool;
+
+    fn name(self, tok: Token, value: &Rc<str>) -> Self::Name;
+    fn identifier(self, name: Self::Name) -> Self::Expr;
+    /* the name `exp` is, or `exp` back if it isn't one */
+    fn into_name(self, exp: Self::Expr) -> Result<Self::Name, Self::Expr>;
+    fn integer(self, tok: Token, literal: &str, value: i64) -> Self::Expr;
+    fn float(self, tok: Token, literal: &str, value: f64) -> Self::Expr;
+    fn string_literal(self, tok: Token, value: &Rc<str>) -> Self::Expr;
+    fn boolean(self, tok: Token, value: bool) -> Self::Expr;
+    fn array(self, tok: Token, elements: Self::Exprs) -> Self::Expr;
+    fn tuple(self, tok: Token, elements: Self::Exprs) -> Self::Expr;
+    fn hash(self, tok: Token, pairs: Self::Pairs) -> Self::Expr;
+    fn prefix(self, tok: Token, operator: PrefixOperator, right: Self::Expr) -> Self::Expr;
+    fn infix(
+        self,
+        tok: Token,
+        left: Self::Expr,
+        operator: InfixOperator,
+        right: Self::Expr,
+    ) -> Self::Expr;
+    fn assign(self, tok: Token, name: Self::Name, value: Self::Expr) -> Self::Expr;
+    /* `left |> right`, `right` with `left` as its first argument if it is
+     * a call, or called with `left` if it isn't */
+    fn pipe(self, left: Self::Expr, right: Self::Expr) -> Self::Expr;
+    fn if_expression(
+        self,
+        tok: Token,
+        condition: Self::Expr,
+        consequence: Self::Block,
+        alternative: Option<Self::Block>,
+    ) -> Self::Expr;
+    fn for_expression(
+        self,
+        tok: Token,
+        label: Option<Self::Name>,
+        names: Self::Names,
+        iterable: Self::Expr,
+        body: Self::Block,
+    ) -> Self::Expr;
+    fn function(self, tok: Token, parameters: Self::Names, body: Self::Block) -> Self::Function;
+    fn function_literal(self, function: Self::Function) -> Self::Expr;
+    fn macro_literal(self, tok: Token, parameters: Self::Names, body: Self::Block) -> Self::Expr;
+    fn call(self, tok: Token, function: Self::Expr, arguments: Self::Exprs) -> Self::Expr;
+    fn index(self, tok: Token, left: Self::Expr, index: Self::Expr) -> Self::Expr;
+    /* `left.name` */
+    fn member(self, tok: Token, left: Self::Expr, name: &Rc<str>) -> Self::Expr;
+    fn block(self, tok: Token, statements: Self::Stmts) -> Self::Block;
+
+    /* a `let` of one name, or of a tuple of them */
+    fn let_statement(
+        self,
+        tok: Token,
+        names: Self::Names,
+        value: Self::Expr,
+        mutable: bool,
+    ) -> Self::Stmt;
+    /* `fn name(...) { ... }`, its `value` being the function literal */
+    fn function_declaration(self, tok: Token, name: Self::Name, value: Self::Expr) -> Self::Stmt;
+    fn return_statement(self, tok: Token, value: Self::Expr) -> Self::Stmt;
+    fn break_statement(self, tok: Token, label: Option<Self::Name>) -> Self::Stmt;
+    fn continue_statement(self, tok: Token) -> Self::Stmt;
+    /* `path` is a string literal */
+    fn import(self, tok: Token, path: Self::Expr, alias: Option<Self::Name>) -> Self::Stmt;
+    fn expression_statement(self, tok: Token, expression: Self::Expr) -> Self::Stmt;
+}
+
+/* the owned AST, every node on the heap */
+#[derive(Clone, Copy)]
+pub(super) struct Owned;
+
+impl NodeBuilder for Owned {
+    type Expr = Expression;
+    type Stmt = Statement;
+    type Name = Identifier;
+    type Block = BlockStatement;
+    type Function = FunctionLiteral;
+    type Exprs = Vec<Expression>;
+    type Names = Vec<Identifier>;
+    type Stmts = Vec<Statement>;
+    type Pairs = Vec<(Expression, Expression)>;
+
+    fn exprs(self) -> Self::Exprs {
+        Vec::new()
+    }
+
+    fn names(self) -> Self::Names {
+        Vec::new()
+    }
+
+    fn stmts(self) -> Self::Stmts {
+        Vec::new()
+    }
+
+    fn pairs(self) -> Self::Pairs {
+        Vec::new()
+    }
+
+    fn child_count(exp: &Expression) -> usize {
+        exp.children().len()
+    }
+
+    fn string(exp: &Expression) -> String {
+        exp.string()
+    }
+
+    fn is_call(exp: &Expression) -> bool {
+        matches!(exp, Expression::CallExpression(_))
+    }
+
+    fn is_string(exp: &Expression) -> bool {
+        matches!(exp, Expression::String(_))
+    }
+
+    fn name(self, tok: Token, value: &Rc<str>) -> Identifier {
+        Identifier {
+            tok,
+            value: value.clone(),
+        }
+    }
+
+    fn identifier(self, name: Identifier) -> Expression {
+        Expression::Identifier(name)
+    }
+
+    fn into_name(self, exp: Expression) -> Result<Identifier, Expression> {
+        match exp {
+            Expression::Identifier(name) => Ok(name),
+            exp => Err(exp),
+        }
+    }
+
+    fn integer(self, tok: Token, _: &str, value: i64) -> Expression {
+        Expression::Integer(IntegerLiteral { tok, value })
+    }
+
+    fn float(self, tok: Token, _: &str, value: f64) -> Expression {
+        Expression::Float(FloatLiteral { tok, value })
+    }
+
+    fn string_literal(self, tok: Token, value: &Rc<str>) -> Expression {
+        Expression::String(StringLiteral {
+            tok,
+            value: value.clone(),
+        })
+    }
+
+    fn boolean(self, tok: Token, value: bool) -> Expression {
+        Expression::Boolean(BooleanLiteral { tok, value })
+    }
+
+    fn array(self, tok: Token, elements: Vec<Expression>) -> Expression {
+        Expression::Array(ArrayLiteral {
+            tok,
+            constant: Constant::of(&elements),
+            elements,
+        })
+    }
+
+    fn tuple(self, tok: Token, elements: Vec<Expression>) -> Expression {
+        Expression::Tuple(TupleLiteral {
+            tok,
+            constant: Constant::of(&elements),
+            elements,
+        })
+    }
+
+    fn hash(self, tok: Token, pairs: Vec<(Expression, Expression)>) -> Expression {
+        let constant = Constant::of(pairs.iter().flat_map(|(key, value)| [key, value]));
+        Expression::Hash(HashLiteral {
+            tok,
+            pairs,
+            constant,
+        })
+    }
+
+    fn prefix(self, tok: Token, operator: PrefixOperator, right: Expression) -> Expression {
+        Expression::PrefixExpression(PrefixExpression {
+            tok,
+            operator,
+            right: Rc::new(right),
+        })
+    }
+
+    fn infix(
+        self,
+        tok: Token,
+        left: Expression,
+        operator: InfixOperator,
+        right: Expression,
+    ) -> Expression {
+        Expression::InfixExpression(InfixExpression {
+            tok,
+            left: Rc::new(left),
+            operator,
+            right: Rc::new(right),
+        })
+    }
+
+    fn assign(self, tok: Token, name: Identifier, value: Expression) -> Expression {
+        Expression::Assign(AssignExpression {
+            tok,
+            name,
+            value: Rc::new(value),
+        })
+    }
+
+    fn pipe(self, left: Expression, right: Expression) -> Expression {
+        let call = match right {
+            Expression::CallExpression(mut call) => {
+                call.arguments.insert(0, left);
+                call
+            }
+            // the same as the call written out, so it's the call's token
+            function => CallExpression {
+                tok: Token::LParen,
+                function: Rc::new(function),
+                arguments: vec![left],
+            },
+        };
+        Expression::CallExpression(call)
+    }
+
+    fn if_expression(
+        self,
+        tok: Token,
+        condition: Expression,
+        consequence: BlockStatement,
+        alternative: Option<BlockStatement>,
+    ) -> Expression {
+        Expression::IfExpression(IfExpression {
+            tok,
+            condition: Rc::new(condition),
+            consequence,
+            alternative,
+        })
+    }
+
+    fn for_expression(
+        self,
+        tok: Token,
+        label: Option<Identifier>,
+        names: Vec<Identifier>,
+        iterable: Expression,
+        body: BlockStatement,
+    ) -> Expression {
+        Expression::ForExpression(ForExpression {
+            tok,
+            label,
+            names,
+            iterable: Rc::new(iterable),
+            body,
+        })
+    }
+
+    fn function(
+        self,
+        tok: Token,
+        parameters: Vec<Identifier>,
+        body: BlockStatement,
+    ) -> FunctionLiteral {
+        FunctionLiteral {
+            tok,
+            parameters,
+            body,
+        }
+    }
+
+    fn function_literal(self, function: FunctionLiteral) -> Expression {
+        Expression::FunctionLiteral(function)
+    }
+
+    fn macro_literal(
+        self,
+        tok: Token,
+        parameters: Vec<Identifier>,
+        body: BlockStatement,
+    ) -> Expression {
+        Expression::MacroLiteral(MacroLiteral {
+            tok,
+            parameters,
+            body,
+        })
+    }
+
+    fn call(self, tok: Token, function: Expression, arguments: Vec<Expression>) -> Expression {
+        Expression::CallExpression(CallExpression {
+            tok,
+            function: Rc::new(function),
+            arguments,
+        })
+    }
+
+    fn index(self, tok: Token, left: Expression, index: Expression) -> Expression {
+        Expression::IndexExpression(IndexExpression {
+            tok,
+            left: Rc::new(left),
+            index: Rc::new(index),
+        })
+    }
+
+    fn member(self, tok: Token, left: Expression, name: &Rc<str>) -> Expression {
+        // indexing with the string `"name"`
+        let index = self.string_literal(Token::String(name.clone()), name);
+        self.index(tok, left, index)
+    }
+
+    fn block(self, tok: Token, statements: Vec<Statement>) -> BlockStatement {
+        BlockStatement { tok, statements }
+    }
+
+    fn let_statement(
+        self,
+        tok: Token,
+        mut names: Vec<Identifier>,
+        value: Expression,
+        mutable: bool,
+    ) -> Statement {
+        if names.len() == 1 {
+            Statement::LetStatement(LetStatement {
+                tok,
+                name: names.pop().expect("a let binds a name"),
+                value,
+                mutable,
+            })
+        } else {
+            Statement::LetTupleStatement(LetTupleStatement {
+                tok,
+                names,
+                value,
+                mutable,
+            })
+        }
+    }
+
+    fn function_declaration(self, tok: Token, name: Identifier, value: Expression) -> Statement {
+        // `let name = fn(x) { ... };` with the `fn` as its token, so the
+        // evaluator can hoist it
+        Statement::LetStatement(LetStatement {
+            tok,
+            name,
+            value,
+            mutable: true,
+        })
+    }
+
+    fn return_statement(self, tok: Token, value: Expression) -> Statement {
+        Statement::ReturnStatement(ReturnStatement { tok, value })
+    }
+
+    fn break_statement(self, tok: Token, label: Option<Identifier>) -> Statement {
+        Statement::BreakStatement(BreakStatement { tok, label })
+    }
+
+    fn continue_statement(self, tok: Token) -> Statement {
+        Statement::ContinueStatement(ContinueStatement { tok })
+    }
+
+    fn import(self, tok: Token, path: Expression, alias: Option<Identifier>) -> Statement {
+        Statement::ImportStatement(ImportStatement { tok, path, alias })
+    }
+
+    fn expression_statement(self, tok: Token, expression: Expression) -> Statement {
+        Statement::ExpressionStatement(ExpressionStatement { tok, expression })
+    }
+}
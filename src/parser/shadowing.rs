@@ -0,0 +1,135 @@
+use std::collections::HashSet;
+use std::rc::Rc;
+
+use crate::ast::{Expression, Identifier, Statement};
+
+/// Reports every `let` that binds a name already bound in the same scope.
+/// Only function and loop bodies open a new scope: an `if` block binds
+/// into the scope around it, the same as the evaluator, and a function's
+/// parameters and a loop's names live in the scope of its body.
+pub(super) fn check_redeclarations(statements: &[Statement], errors: &mut Vec<String>) {
+    let mut checker = Checker {
+        scopes: vec![HashSet::new()],
+        errors,
+    };
+    checker.statements(statements);
+}
+
+struct Checker<'e> {
+    scopes: Vec<HashSet<Rc<str>>>,
+    errors: &'e mut Vec<String>,
+}
+
+impl Checker<'_> {
+    fn declare(&mut self, name: &Identifier) {
+        let scope = self.scopes.last_mut().expect("there is always a scope");
+        if !scope.insert(name.value.clone()) {
+            self.errors
+                .push(format!("redeclaration of '{}'", name.value));
+        }
+    }
+
+    fn statements(&mut self, statements: &[Statement]) {
+        for stmt in statements {
+            match stmt {
+                Statement::LetStatement(ls) => {
+                    // the value is checked first, `let x = x + 1` reads the old `x`
+                    self.expression(&ls.value);
+                    self.declare(&ls.name);
+                }
+                Statement::LetTupleStatement(lts) => {
+                    self.expression(&lts.value);
+                    for name in lts.names.iter() {
+                        self.declare(name);
+                    }
+                }
+                Statement::ReturnStatement(rs) => self.expression(&rs.value),
+                Statement::ExpressionStatement(es) => self.expression(&es.expression),
+                Statement::ImportStatement(is) => {
+                    if let Some(alias) = &is.alias {
+                        self.declare(alias);
+                    }
+                }
+                Statement::BreakStatement(_) | Statement::ContinueStatement(_) => {}
+            }
+        }
+    }
+
+    fn expression(&mut self, exp: &Expression) {
+        match exp {
+            Expression::Identifier(_)
+            | Expression::Integer(_)
+            | Expression::Float(_)
+            | Expression::String(_)
+            | Expression::Boolean(_) => {}
+            Expression::Array(al) => self.expressions(&al.elements),
+            Expression::Tuple(tl) => self.expressions(&tl.elements),
+            Expression::Hash(hl) => {
+                for (key, value) in hl.pairs.iter() {
+                    self.expression(key);
+                    self.expression(value);
+                }
+            }
+            Expression::PrefixExpression(pe) => self.expression(&pe.right),
+            Expression::Assign(ae) => self.expression(&ae.value),
+            Expression::InfixExpression(ie) => {
+                // a long `1 + 1 + ...` nests down the left, so walk that spine
+                // in a loop rather than recursing
+                let mut ie = ie;
+                loop {
+                    self.expression(&ie.right);
+                    match &*ie.left {
+                        Expression::InfixExpression(left) => ie = left,
+                        left => break self.expression(left),
+                    }
+                }
+            }
+            Expression::IfExpression(ie) => {
+                self.expression(&ie.condition);
+                self.statements(&ie.consequence.statements);
+                if let Some(alt) = &ie.alternative {
+                    self.statements(&alt.statements);
+                }
+            }
+            Expression::ForExpression(fe) => {
+                self.expression(&fe.iterable);
+                self.scopes.push(HashSet::new());
+                for name in fe.names.iter() {
+                    self.declare(name);
+                }
+                self.statements(&fe.body.statements);
+                self.scopes.pop();
+            }
+            Expression::FunctionLiteral(fl) => {
+                self.scopes.push(HashSet::new());
+                for param in fl.parameters.iter() {
+                    self.declare(param);
+                }
+                self.statements(&fl.body.statements);
+                self.scopes.pop();
+            }
+            Expression::MacroLiteral(ml) => {
+                self.scopes.push(HashSet::new());
+                for param in ml.parameters.iter() {
+                    self.declare(param);
+                }
+                self.statements(&ml.body.statements);
+                self.scopes.pop();
+            }
+            Expression::CallExpression(ce) => {
+                self.expression(&ce.function);
+                self.expressions(&ce.arguments);
+            }
+            Expression::IndexExpression(ie) => {
+                self.expression(&ie.left);
+                self.expression(&ie.index);
+            }
+        }
+    }
+
+    fn expressions(&mut self, exps: &[Expression]) {
+        for exp in exps {
+            self.expression(exp);
+        }
+    }
+}
@@ -0,0 +1,382 @@
+//! Rewrites short runs of compiled instructions into shorter ones that
+//! leave the same values on the stack and in the globals, and pop the same
+//! last value. [`Compiler::with_optimizations`](crate::compiler::Compiler::with_optimizations)
+//! runs it after compiling.
+//!
+//! The rewrites are:
+//! - a push of what the statement before just popped, `x; x`, drops the
+//!   second statement
+//! - `!!` after an instruction that pushes a boolean, and `--` after an
+//!   integer constant, are dropped
+//! - two integer constants and `OpAdd` become one constant, which is added
+//!   to the pool
+//! - a jump to the instruction after it is dropped
+//!
+//! Jumps are kept as instruction indices while instructions are removed,
+//! and turned back into offsets once nothing more changes. Nothing that a
+//! jump lands on is removed, except for a jump to the next instruction,
+//! which then lands on that one.
+
+use crate::code::{make, read_operands, Instructions, Opcode};
+use crate::compiler::{Bytecode, MAX_CONSTANTS};
+use crate::object::Object;
+
+/* an instruction with its operand, the index of the instruction it goes to
+ * for a jump */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Ins {
+    op: Opcode,
+    operand: usize,
+}
+
+pub fn optimize(bytecode: Bytecode) -> Bytecode {
+    let Some(mut code) = decode(&bytecode.instructions) else {
+        // not what the compiler produces, leave it to the VM to report
+        return bytecode;
+    };
+    let mut constants = bytecode.constants;
+    while let Some(shorter) = pass(&code, &mut constants) {
+        code = shorter;
+    }
+    Bytecode {
+        instructions: encode(&code),
+        constants,
+    }
+}
+
+fn is_jump(op: Opcode) -> bool {
+    matches!(op, Opcode::Jump | Opcode::JumpNotTruthy)
+}
+
+fn decode(ins: &[u8]) -> Option<Vec<Ins>> {
+    let mut code = Vec::new();
+    let mut offsets = Vec::new();
+    let mut offset = 0;
+    while offset < ins.len() {
+        let op = Opcode::lookup(ins[offset])?;
+        let def = op.definition();
+        if ins.len() - offset - 1 < def.operand_widths.iter().sum() {
+            return None;
+        }
+        let (operands, read) = read_operands(&def, &ins[offset + 1..]);
+        code.push(Ins {
+            op,
+            operand: operands.first().copied().unwrap_or(0),
+        });
+        offsets.push(offset);
+        offset += 1 + read;
+    }
+    offsets.push(offset);
+    for ins in code.iter_mut().filter(|ins| is_jump(ins.op)) {
+        ins.operand = offsets.binary_search(&ins.operand).ok()?;
+    }
+    Some(code)
+}
+
+fn encode(code: &[Ins]) -> Instructions {
+    let mut offsets = Vec::with_capacity(code.len() + 1);
+    let mut offset = 0;
+    for ins in code {
+        offsets.push(offset);
+        offset += 1 + ins.op.definition().operand_widths.iter().sum::<usize>();
+    }
+    offsets.push(offset);
+    let mut res = Vec::with_capacity(offset);
+    for ins in code {
+        let operand = if is_jump(ins.op) {
+            offsets[ins.operand]
+        } else {
+            ins.operand
+        };
+        res.extend(make(ins.op, &[operand]));
+    }
+    res
+}
+
+/* rewrites everything it can once, or nothing if nothing can be */
+fn pass(code: &[Ins], constants: &mut Vec<Object>) -> Option<Vec<Ins>> {
+    let mut targeted = vec![false; code.len() + 1];
+    for ins in code.iter().filter(|ins| is_jump(ins.op)) {
+        targeted[ins.operand] = true;
+    }
+    let mut res = Vec::with_capacity(code.len());
+    // where each instruction went, a removed one goes where the next one
+    // that's kept does
+    let mut moved = vec![0; code.len() + 1];
+    let mut changed = false;
+    let mut i = 0;
+    while i < code.len() {
+        moved[i] = res.len();
+        match rewrite(code, i, &targeted, constants) {
+            Some((replacement, consumed)) => {
+                moved[i + 1..i + consumed].fill(res.len());
+                res.extend(replacement);
+                i += consumed;
+                changed = true;
+            }
+            None => {
+                res.push(code[i]);
+                i += 1;
+            }
+        }
+    }
+    if !changed {
+        return None;
+    }
+    moved[code.len()] = res.len();
+    for ins in res.iter_mut().filter(|ins| is_jump(ins.op)) {
+        ins.operand = moved[ins.operand];
+    }
+    Some(res)
+}
+
+/* what the instructions starting at `i` can be replaced with, and how many
+ * of them that replaces */
+fn rewrite(
+    code: &[Ins],
+    i: usize,
+    targeted: &[bool],
+    constants: &mut Vec<Object>,
+) -> Option<(Vec<Ins>, usize)> {
+    let ins = code[i];
+    if ins.op == Opcode::Jump && ins.operand == i + 1 {
+        return Some((vec![], 1));
+    }
+    let window = code.get(i..i + 3)?;
+    // only the first of the instructions replaced may be jumped to
+    if targeted[i + 1..i + 3].iter().any(|&t| t) {
+        return None;
+    }
+    match (window[0].op, window[1].op, window[2].op) {
+        (_, Opcode::Bang, Opcode::Bang) if pushes_boolean(window[0].op) => {
+            Some((vec![window[0]], 3))
+        }
+        (Opcode::Constant, Opcode::Minus, Opcode::Minus)
+            if matches!(constants[window[0].operand], Object::Integer(_)) =>
+        {
+            Some((vec![window[0]], 3))
+        }
+        (Opcode::Constant, Opcode::Constant, Opcode::Add) => {
+            let (Object::Integer(a), Object::Integer(b)) =
+                (&constants[window[0].operand], &constants[window[1].operand])
+            else {
+                return None;
+            };
+            // an overflow is left for the VM to run into
+            let sum = a.checked_add(*b)?;
+            if constants.len() == MAX_CONSTANTS {
+                return None;
+            }
+            constants.push(Object::Integer(sum));
+            let folded = Ins {
+                op: Opcode::Constant,
+                operand: constants.len() - 1,
+            };
+            Some((vec![folded], 3))
+        }
+        (_, Opcode::Pop, _) if is_pure_push(window[0].op) => {
+            let repeat = code.get(i + 3)?;
+            if window[2] != window[0] || repeat.op != Opcode::Pop || targeted[i + 3] {
+                return None;
+            }
+            Some((vec![window[0], window[1]], 4))
+        }
+        _ => None,
+    }
+}
+
+/* pushes a value without popping anything or failing */
+fn is_pure_push(op: Opcode) -> bool {
+    matches!(
+        op,
+        Opcode::Constant | Opcode::True | Opcode::False | Opcode::Null | Opcode::GetGlobal
+    )
+}
+
+fn pushes_boolean(op: Opcode) -> bool {
+    matches!(
+        op,
+        Opcode::True
+            | Opcode::False
+            | Opcode::Bang
+            | Opcode::Equal
+            | Opcode::NotEqual
+            | Opcode::GreaterThan
+            | Opcode::LessThan
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use crate::code::disassemble;
+    use crate::compiler::{Bytecode, Compiler};
+    use crate::environment::Environment;
+    use crate::evaluator::eval;
+    use crate::lexer::Lexer;
+    use crate::object::Object;
+    use crate::parser::Parser;
+    use crate::vm::Vm;
+
+    fn compile(input: &str, optimize: bool) -> Bytecode {
+        let mut p = Parser::new(Lexer::new(input));
+        let program = p.parse();
+        assert!(p.get_errors().is_empty(), "{:?}", p.get_errors());
+        let compiler = if optimize {
+            Compiler::new().with_optimizations()
+        } else {
+            Compiler::new()
+        };
+        compiler.compile(&program).unwrap()
+    }
+
+    fn listing(bytecode: &Bytecode) -> String {
+        disassemble(&bytecode.instructions, &bytecode.constants)
+    }
+
+    fn run(bytecode: Bytecode) -> Result<Option<Object>, String> {
+        let mut vm = Vm::new(bytecode);
+        vm.run().map_err(|e| e.to_string())?;
+        Ok(vm.last_popped().cloned())
+    }
+
+    #[test]
+    fn test_rewrites() {
+        let tests = [
+            (
+                "1 + 2 + 3",
+                "0000 OpConstant 4 (= 6)\n\
+                 0003 OpPop\n",
+            ),
+            (
+                "1 + 2 * 3",
+                "0000 OpConstant 0 (= 1)\n\
+                 0003 OpConstant 1 (= 2)\n\
+                 0006 OpConstant 2 (= 3)\n\
+                 0009 OpMul\n\
+                 0010 OpAdd\n\
+                 0011 OpPop\n",
+            ),
+            ("!!true", "0000 OpTrue\n0001 OpPop\n"),
+            (
+                "!!!(1 > 2)",
+                "0000 OpConstant 0 (= 1)\n\
+                 0003 OpConstant 1 (= 2)\n\
+                 0006 OpGreaterThan\n\
+                 0007 OpBang\n\
+                 0008 OpPop\n",
+            ),
+            // the operand might not be a boolean
+            (
+                "let a = 1; !!a",
+                "0000 OpConstant 0 (= 1)\n\
+                 0003 OpSetGlobal 0\n\
+                 0006 OpGetGlobal 0\n\
+                 0009 OpBang\n\
+                 0010 OpBang\n\
+                 0011 OpPop\n",
+            ),
+            ("--5", "0000 OpConstant 0 (= 5)\n0003 OpPop\n"),
+            ("----5", "0000 OpConstant 0 (= 5)\n0003 OpPop\n"),
+            (
+                "--true",
+                "0000 OpTrue\n0001 OpMinus\n0002 OpMinus\n0003 OpPop\n",
+            ),
+            (
+                "let a = 1; a; a; a",
+                "0000 OpConstant 0 (= 1)\n\
+                 0003 OpSetGlobal 0\n\
+                 0006 OpGetGlobal 0\n\
+                 0009 OpPop\n",
+            ),
+            // the alternative starts at the second push
+            (
+                "if (true) { 1 } else { 1 }",
+                "0000 OpTrue\n\
+                 0001 OpJumpNotTruthy 10\n\
+                 0004 OpConstant 0 (= 1)\n\
+                 0007 OpJump 13\n\
+                 0010 OpConstant 1 (= 1)\n\
+                 0013 OpPop\n",
+            ),
+            // the jumps land where they did before the constants folded
+            (
+                "if (true) { 1 + 2 }; 3",
+                "0000 OpTrue\n\
+                 0001 OpJumpNotTruthy 10\n\
+                 0004 OpConstant 3 (= 3)\n\
+                 0007 OpJump 11\n\
+                 0010 OpNull\n\
+                 0011 OpPop\n\
+                 0012 OpConstant 2 (= 3)\n\
+                 0015 OpPop\n",
+            ),
+        ];
+        for (input, exp) in tests {
+            let optimized = compile(input, true);
+            assert_eq!(listing(&optimized), exp, "optimizing {:?}", input);
+            assert_eq!(
+                run(optimized),
+                run(compile(input, false)),
+                "running {:?}",
+                input
+            );
+        }
+    }
+
+    #[test]
+    fn test_jump_to_next_instruction() {
+        use crate::code::{make, Opcode};
+        use crate::peephole::optimize;
+
+        let bytecode = Bytecode {
+            instructions: [
+                make(Opcode::True, &[]),
+                make(Opcode::JumpNotTruthy, &[8]),
+                make(Opcode::Jump, &[7]),
+                make(Opcode::Null, &[]),
+                make(Opcode::Pop, &[]),
+            ]
+            .concat(),
+            constants: vec![],
+        };
+        let optimized = optimize(bytecode);
+        assert_eq!(
+            listing(&optimized),
+            "0000 OpTrue\n0001 OpJumpNotTruthy 5\n0004 OpNull\n0005 OpPop\n"
+        );
+    }
+
+    /// Optimized bytecode gives what unoptimized bytecode and the evaluator
+    /// give.
+    #[test]
+    fn test_differential() {
+        let tests = [
+            "1 + 2",
+            "1 + 2 + 3 + 4 - 5",
+            "9223372036854775807 + 0",
+            "let a = 1; a + 2 + 3",
+            "!!true; !!false; !!(1 == 2)",
+            "--5 * --2",
+            "let a = true; a; a",
+            "let a = 5; a; a; let b = a + 1 + 1; b",
+            "if (1 + 1 > 1) { 2 + 2 } else { 3 + 3 }",
+            "if (false) { 1 } else { 1 }",
+            "if (true) { if (false) { 1 } } else { 2 }",
+            "if (!!(1 > 2)) { 1 + 2 }",
+            "let x = if (true) { 1 + 2 } else { --4 }; x + x",
+            "true + 1 + 2",
+            "1 + 2 + true",
+        ];
+        for input in tests {
+            let optimized = run(compile(input, true));
+            assert_eq!(optimized, run(compile(input, false)), "{}", input);
+            let mut p = Parser::new(Lexer::new(input));
+            let evaluated = match eval(&p.parse(), &mut Environment::new()) {
+                Some(Object::Error(err)) => Err(err.to_string()),
+                res => Ok(res.or(Some(Object::Null))),
+            };
+            let optimized = optimized.map(|res| res.or(Some(Object::Null)));
+            assert_eq!(optimized, evaluated, "{}", input);
+        }
+    }
+}
@@ -0,0 +1,253 @@
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+
+use crate::analysis::resolve;
+use crate::lexer::{line_col, token_spans, Lexer};
+use crate::parser::Parser;
+use crate::stdlib::StdlibConfig;
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct FileReport {
+    pub path: PathBuf,
+    pub statements: usize,
+    pub diagnostics: Vec<String>,
+    /// The line and column of each of `diagnostics`, counted from 1, for
+    /// the parse errors and the undefined names. A file that can't be read
+    /// doesn't have one.
+    pub positions: Vec<Option<(usize, usize)>>,
+}
+
+/// The outcome of parsing a set of files, sorted by path so the report
+/// reads the same no matter which file finished first.
+#[derive(Debug, PartialEq, Eq, Clone, Default)]
+pub struct ProjectReport {
+    pub files: Vec<FileReport>,
+}
+
+impl ProjectReport {
+    pub fn error_count(&self) -> usize {
+        self.files.iter().map(|f| f.diagnostics.len()).sum()
+    }
+
+    pub fn is_ok(&self) -> bool {
+        self.error_count() == 0
+    }
+}
+
+impl std::fmt::Display for ProjectReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for file in self.files.iter() {
+            for (diag, at) in file.diagnostics.iter().zip(file.positions.iter()) {
+                match at {
+                    Some((line, col)) => {
+                        writeln!(f, "{}:{}:{}: {}", file.path.display(), line, col, diag)?
+                    }
+                    None => writeln!(f, "{}: {}", file.path.display(), diag)?,
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Parses every file in `paths` on a small pool of scoped threads. Each
+/// file gets its own `Parser`, so nothing is shared between workers except
+/// the queue of paths. A file that can't be read is reported like a parse
+/// error instead of aborting the whole run. Targets without threads, like
+/// wasm32, report no parallelism and parse on the calling thread.
+pub fn parse_files(paths: &[PathBuf]) -> ProjectReport {
+    let workers = std::thread::available_parallelism()
+        .map(|n| n.get())
+        .unwrap_or(1);
+    parse_files_on(paths, workers, parse_file)
+}
+
+/// Like `parse_files`, and also reports the undefined names in every file
+/// that parses, see `analysis::resolve`.
+pub fn parse_files_strict(paths: &[PathBuf]) -> ProjectReport {
+    let workers = std::thread::available_parallelism()
+        .map(|n| n.get())
+        .unwrap_or(1);
+    parse_files_on(paths, workers, parse_file_strict)
+}
+
+fn parse_files_on(
+    paths: &[PathBuf],
+    workers: usize,
+    check: fn(&Path) -> FileReport,
+) -> ProjectReport {
+    let workers = workers.min(paths.len());
+    if workers <= 1 {
+        let mut files: Vec<_> = paths.iter().map(|p| check(p)).collect();
+        files.sort_by(|a, b| a.path.cmp(&b.path));
+        return ProjectReport { files };
+    }
+    let next = AtomicUsize::new(0);
+    let files = Mutex::new(Vec::with_capacity(paths.len()));
+
+    std::thread::scope(|s| {
+        for _ in 0..workers {
+            s.spawn(|| loop {
+                let i = next.fetch_add(1, Ordering::Relaxed);
+                let Some(path) = paths.get(i) else {
+                    break;
+                };
+                let report = check(path);
+                files.lock().unwrap().push(report);
+            });
+        }
+    });
+
+    let mut files = files.into_inner().unwrap();
+    files.sort_by(|a, b| a.path.cmp(&b.path));
+    ProjectReport { files }
+}
+
+pub fn parse_file(path: &Path) -> FileReport {
+    check_file(path, false)
+}
+
+pub fn parse_file_strict(path: &Path) -> FileReport {
+    check_file(path, true)
+}
+
+fn check_file(path: &Path, strict: bool) -> FileReport {
+    let source = match std::fs::read_to_string(path) {
+        Ok(source) => source,
+        Err(e) => {
+            return FileReport {
+                path: path.to_owned(),
+                statements: 0,
+                diagnostics: vec![format!("could not read file: {}", e)],
+                positions: vec![None],
+            }
+        }
+    };
+    let mut p = Parser::new(Lexer::new(&source)).with_source_map();
+    let program = p.parse();
+    let mut diagnostics = p.get_errors().clone();
+    let spans = token_spans(&source);
+    let mut positions: Vec<_> = p
+        .error_tokens()
+        .iter()
+        .map(|token| {
+            // anything past the last token is at the end
+            token.map(|i| line_col(&source, spans.get(i).map_or(source.len(), |s| s.start)))
+        })
+        .collect();
+    if strict && diagnostics.is_empty() {
+        for mut undefined in resolve(&program, &StdlibConfig::new().global_names()) {
+            undefined.locate(p.source_map(), &spans);
+            diagnostics.push(undefined.to_string());
+            positions.push(undefined.span.map(|span| line_col(&source, span.start)));
+        }
+    }
+    FileReport {
+        path: path.to_owned(),
+        statements: program.statements.len(),
+        diagnostics,
+        positions,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::path::PathBuf;
+
+    use crate::project::{parse_file, parse_files, parse_files_on, parse_files_strict};
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("monkey-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn test_parse_files() {
+        let dir = temp_dir("parse-files");
+        let files = [
+            ("d.mk", "let x = 5; x + 1;"),
+            ("a.mk", "let = 5;"),
+            ("c.mk", "fn(a, b) { a + b }(1, 2);"),
+            ("b.mk", "let y 10; let z = 1;"),
+        ];
+        let mut paths = Vec::new();
+        for (name, source) in files {
+            let path = dir.join(name);
+            std::fs::write(&path, source).unwrap();
+            paths.push(path);
+        }
+        paths.push(dir.join("missing.mk"));
+
+        // force the threaded path even on a single core machine
+        let report = parse_files_on(&paths, 4, parse_file);
+        assert_eq!(report, parse_files(&paths));
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        let names: Vec<_> = report
+            .files
+            .iter()
+            .map(|f| f.path.file_name().unwrap().to_str().unwrap())
+            .collect();
+        assert_eq!(names, ["a.mk", "b.mk", "c.mk", "d.mk", "missing.mk"]);
+
+        let files = &report.files;
+        assert!(!files[0].diagnostics.is_empty());
+        assert_eq!(files[1].diagnostics.len(), 1);
+        assert!(files[1].diagnostics[0].starts_with("expected next token to be Assign"));
+        assert_eq!(files[1].positions, [Some((1, 7))]);
+        assert!(files[2].diagnostics.is_empty());
+        assert_eq!(files[3].statements, 2);
+        assert!(files[3].diagnostics.is_empty());
+        assert_eq!(files[4].diagnostics.len(), 1);
+        assert!(files[4].diagnostics[0].starts_with("could not read file: "));
+        assert_eq!(files[4].positions, [None]);
+        let printed = report.to_string();
+        let b = files[1].path.display();
+        assert!(printed.contains(&format!("\n{}:1:7: expected next token", b)));
+        let missing = files[4].path.display();
+        assert!(printed.contains(&format!("\n{}: could not read file: ", missing)));
+
+        assert_eq!(
+            report.error_count(),
+            files[0].diagnostics.len() + files[1].diagnostics.len() + 1
+        );
+        assert!(!report.is_ok());
+    }
+
+    #[test]
+    fn test_parse_files_strict() {
+        let dir = temp_dir("parse-files-strict");
+        let files = [
+            ("a.mk", "let x = 1;\nlen(x) + y;"),
+            ("b.mk", "let = 1; z"),
+            ("c.mk", "let f = fn() { g() }; let g = fn() { 1 };"),
+        ];
+        let mut paths = Vec::new();
+        for (name, source) in files {
+            let path = dir.join(name);
+            std::fs::write(&path, source).unwrap();
+            paths.push(path);
+        }
+        let report = parse_files_strict(&paths);
+        let loose = parse_files(&paths);
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        let files = &report.files;
+        assert_eq!(files[0].diagnostics, ["identifier not found: y"]);
+        assert_eq!(files[0].positions, [Some((2, 10))]);
+        // a file that doesn't parse only gets its parse errors
+        assert_eq!(files[1].diagnostics, loose.files[1].diagnostics);
+        assert!(files[2].diagnostics.is_empty());
+        assert!(loose.files[0].diagnostics.is_empty());
+    }
+
+    #[test]
+    fn test_parse_no_files() {
+        let report = parse_files(&[]);
+        assert!(report.files.is_empty());
+        assert!(report.is_ok());
+    }
+}
@@ -0,0 +1,303 @@
+//! What the REPL keeps between one input and the next, apart from the
+//! environment the inputs run in.
+//!
+//! A [`History`] binds every value the REPL prints to `_`, and to `_1`,
+//! `_2` and so on in the order they came:
+//!
+//! ```
+//! use interpreter::repl::History;
+//! use interpreter::prelude::*;
+//!
+//! let mut env = Environment::new();
+//! let mut history = History::new();
+//! assert_eq!(history.record(&Object::Integer(42), &mut env), Some(1));
+//! let program = Parser::new(Lexer::new("_1 + _")).parse();
+//! assert_eq!(eval(&program, &mut env), Some(Object::Integer(84)));
+//! ```
+//!
+//! A line that starts with `:` is a [`Command`] to the REPL rather than
+//! code:
+//!
+//! - `:help name` shows how the builtin `name` is called, see [`help`],
+//!   and `:help` alone all of them
+//! - `:env` lists what is bound, see [`bindings`]
+//! - `:reset` starts over in an empty environment, where only the
+//!   builtins are bound
+
+use std::rc::Rc;
+
+use crate::builtins::{lookup, BUILTINS};
+use crate::environment::Environment;
+use crate::object::{Object, ObjectTrait};
+
+const USAGE: &str = "commands: :help [name], :env, :reset";
+
+/// A line of input to the REPL that isn't code.
+#[non_exhaustive]
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum Command {
+    /// The signature of a builtin, or of all of them for an empty name.
+    Help(String),
+    Env,
+    Reset,
+}
+
+impl Command {
+    /// The command `line` is, or what is wrong with it, or `None` if it is
+    /// code.
+    pub fn parse(line: &str) -> Option<Result<Self, String>> {
+        let line = line.trim().strip_prefix(':')?;
+        let (name, rest) = line
+            .split_once(char::is_whitespace)
+            .map_or((line, ""), |(name, rest)| (name, rest.trim()));
+        let command = match name {
+            "help" => return Some(Ok(Command::Help(rest.to_owned()))),
+            "env" => Command::Env,
+            "reset" => Command::Reset,
+            _ => return Some(Err(format!("unknown command `:{}`, {}", name, USAGE))),
+        };
+        if !rest.is_empty() {
+            return Some(Err(format!("`:{}` takes no arguments", name)));
+        }
+        Some(Ok(command))
+    }
+}
+
+/// How many numbered values a [`History`] keeps by default.
+pub const DEFAULT_HISTORY_LIMIT: usize = 1000;
+
+/// The numbered values of a REPL session. Only the last `limit` of them
+/// stay bound, so a long session doesn't hold on to everything it ever
+/// printed.
+#[derive(Debug)]
+pub struct History {
+    /* how many values have been numbered */
+    count: usize,
+    limit: usize,
+}
+
+impl Default for History {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl History {
+    pub fn new() -> Self {
+        History {
+            count: 0,
+            limit: DEFAULT_HISTORY_LIMIT,
+        }
+    }
+
+    /// Keeps `_n` bound only for the last `limit` values. `_` is always
+    /// the last one.
+    pub fn with_limit(mut self, limit: usize) -> Self {
+        self.limit = limit;
+        self
+    }
+
+    /// Binds `value` to `_` and the next number in `env`, and unbinds the
+    /// number that falls out of the limit. Returns the number, or `None`
+    /// for null and errors, which don't get one.
+    pub fn record(&mut self, value: &Object, env: &mut Environment) -> Option<usize> {
+        if matches!(value, Object::Null | Object::Error(_)) {
+            return None;
+        }
+        self.count += 1;
+        env.set("_".into(), value.clone());
+        env.set(numbered(self.count), value.clone());
+        if self.count > self.limit {
+            env.remove(&numbered(self.count - self.limit));
+        }
+        Some(self.count)
+    }
+
+    /// The names the history has bound, for a pass like
+    /// [`analysis::resolve`](crate::analysis::resolve) that would
+    /// otherwise report them as undefined.
+    pub fn names(&self) -> Vec<Rc<str>> {
+        if self.count == 0 {
+            return Vec::new();
+        }
+        let first = self.count.saturating_sub(self.limit) + 1;
+        let mut res: Vec<Rc<str>> = vec!["_".into()];
+        res.extend((first..=self.count).map(numbered));
+        res
+    }
+}
+
+fn numbered(n: usize) -> Rc<str> {
+    format!("_{}", n).into()
+}
+
+/// The bindings of `env`'s scope as `name = value`, in order of their
+/// names. A function shows only its parameters, since its whole body
+/// would be in the way.
+pub fn bindings(env: &Environment) -> Vec<String> {
+    let mut locals = env.locals();
+    locals.sort_by(|(a, _), (b, _)| a.cmp(b));
+    locals
+        .into_iter()
+        .map(|(name, value)| match value {
+            Object::Function(func) => {
+                let params: Vec<&str> = func.parameters.iter().map(|p| &*p.value).collect();
+                format!("{} = fn({})", name, params.join(", "))
+            }
+            value => format!("{} = {}", name, value.inspect()),
+        })
+        .collect()
+}
+
+/// How the builtin `name` is called, like `push(ARRAY, ANY)`, or every
+/// builtin one per line if `name` is empty.
+pub fn help(name: &str) -> String {
+    if name.is_empty() {
+        let lines: Vec<String> = BUILTINS.iter().map(|def| def.signature()).collect();
+        return lines.join("\n");
+    }
+    match lookup(name) {
+        Some(def) => def.signature(),
+        None => format!("no builtin named `{}`", name),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::analysis::resolve;
+    use crate::builtins::BUILTIN_NAMES;
+    use crate::environment::Environment;
+    use crate::evaluator::eval;
+    use crate::lexer::Lexer;
+    use crate::object::{Object, ObjectTrait};
+    use crate::parser::Parser;
+    use crate::repl::{bindings, help, Command, History};
+
+    /* runs `input` like the REPL does, then what it prints */
+    fn enter(input: &str, history: &mut History, env: &mut Environment) -> String {
+        let program = Parser::new(Lexer::new(input)).parse();
+        let obj = eval(&program, env).unwrap_or(Object::Null);
+        match history.record(&obj, env) {
+            Some(n) => format!("[{}] => {}", n, obj.inspect()),
+            None => obj.inspect(),
+        }
+    }
+
+    #[test]
+    fn test_history() {
+        let tests = [
+            ("40 + 2", "[1] => 42"),
+            ("_ * 2", "[2] => 84"),
+            ("_1 + _2", "[3] => 126"),
+            // neither a failure nor null takes a number
+            (
+                "1 + true",
+                "ERROR: unsupported operand types for +: INTEGER and BOOLEAN",
+            ),
+            ("let x = 1;", "null"),
+            ("_", "[4] => 126"),
+            ("\"s\"", "[5] => s"),
+            ("[_4, _5]", "[6] => [126, s]"),
+        ];
+        let mut history = History::new();
+        let mut env = Environment::new();
+        for (input, exp) in tests {
+            assert_eq!(
+                enter(input, &mut history, &mut env),
+                exp,
+                "entering {:?}",
+                input
+            );
+        }
+    }
+
+    #[test]
+    fn test_history_limit() {
+        let mut history = History::new().with_limit(2);
+        let mut env = Environment::new();
+        for i in 1..=4 {
+            history.record(&Object::Integer(i), &mut env);
+        }
+        let get = |name: &str| env.get(&name.into());
+        assert_eq!(get("_"), Some(Object::Integer(4)));
+        assert_eq!(get("_4"), Some(Object::Integer(4)));
+        assert_eq!(get("_3"), Some(Object::Integer(3)));
+        assert_eq!(get("_2"), None);
+        assert_eq!(get("_1"), None);
+        let names: Vec<String> = history.names().iter().map(|n| n.to_string()).collect();
+        assert_eq!(names, ["_", "_3", "_4"]);
+
+        // without numbers, only `_` is kept
+        let mut history = History::new().with_limit(0);
+        let mut env = Environment::new();
+        assert_eq!(history.record(&Object::Integer(1), &mut env), Some(1));
+        assert_eq!(env.get(&"_1".into()), None);
+        assert_eq!(env.get(&"_".into()), Some(Object::Integer(1)));
+    }
+
+    #[test]
+    fn test_parse_commands() {
+        let tests = [
+            ("1 + 1", None),
+            (":help", Some(Ok(Command::Help(String::new())))),
+            (" :help  len ", Some(Ok(Command::Help("len".to_owned())))),
+            (":env\n", Some(Ok(Command::Env))),
+            (":reset", Some(Ok(Command::Reset))),
+            (
+                ":reset all",
+                Some(Err("`:reset` takes no arguments".to_owned())),
+            ),
+            (
+                ":quit",
+                Some(Err(
+                    "unknown command `:quit`, commands: :help [name], :env, :reset".to_owned(),
+                )),
+            ),
+        ];
+        for (input, exp) in tests {
+            assert_eq!(Command::parse(input), exp, "{:?}", input);
+        }
+    }
+
+    #[test]
+    fn test_bindings() {
+        let mut env = Environment::new();
+        assert!(bindings(&env).is_empty());
+        let program = Parser::new(Lexer::new("let b = [1, 2]; let a = fn(x, y) { x };")).parse();
+        eval(&program, &mut env);
+        assert_eq!(bindings(&env), ["a = fn(x, y)", "b = [1, 2]"]);
+    }
+
+    #[test]
+    fn test_help() {
+        let tests = [
+            ("len", "len(STRING|ARRAY|HASH|ITERATOR)"),
+            ("push", "push(ARRAY, ANY)"),
+            ("print", "print(...)"),
+            ("clock", "clock()"),
+            ("nope", "no builtin named `nope`"),
+        ];
+        for (name, exp) in tests {
+            assert_eq!(help(name), exp, "{}", name);
+        }
+        let all = help("");
+        assert_eq!(all.lines().count(), BUILTIN_NAMES.len());
+        assert!(all.starts_with("len(STRING|ARRAY|HASH|ITERATOR)\nfirst(ARRAY)\n"));
+    }
+
+    #[test]
+    fn test_history_names_resolve() {
+        let mut history = History::new();
+        assert!(history.names().is_empty());
+        history.record(&Object::Integer(1), &mut Environment::new());
+        let program = Parser::new(Lexer::new("_ + _1 + _2")).parse();
+        let names = history.names();
+        let mut declared: Vec<&str> = BUILTIN_NAMES.to_vec();
+        declared.extend(names.iter().map(|name| &**name));
+        let undefined: Vec<String> = resolve(&program, &declared)
+            .iter()
+            .map(|d| d.to_string())
+            .collect();
+        assert_eq!(undefined, ["identifier not found: _2"]);
+    }
+}
@@ -0,0 +1,301 @@
+//! The builtins by group, each of which is also bound to a namespace: a
+//! hash of the group's builtins by name, so `string.split` and
+//! `string["split"]` are `split`. The [`CORE`] builtins are in every
+//! program, and a [`StdlibConfig`] set with
+//! [`Host::with_stdlib`](crate::builtins::Host::with_stdlib) leaves out the
+//! groups an embedder doesn't want a script to see:
+//!
+//! ```
+//! use interpreter::builtins::{with_host, Host};
+//! use interpreter::prelude::*;
+//! use interpreter::stdlib::{Group, StdlibConfig};
+//!
+//! let program = Parser::new(Lexer::new("[math.abs(-2), os[\"args\"], len(keys(os))]")).parse();
+//! let run = |config| {
+//!     let host = Host::new().with_stdlib(config);
+//!     with_host(host, || eval(&program, &mut Environment::new()))
+//! };
+//! assert_eq!(run(StdlibConfig::new()).unwrap().inspect(), "[2, builtin function, 3]");
+//! assert_eq!(
+//!     run(StdlibConfig::new().without(Group::Os)),
+//!     Some(Object::Error(EvalError::IdentifierNotFound("os".to_owned())))
+//! );
+//! ```
+
+use std::rc::Rc;
+
+use crate::builtins;
+use crate::environment::Environment;
+use crate::evaluator::eval;
+use crate::lexer::Lexer;
+use crate::object::{Builtin, Hash, Object};
+use crate::parser::Parser;
+
+/// Functions written in Monkey that a host
+/// [`with_prelude`](crate::builtins::Host::with_prelude) binds for every
+/// program: `reduce(xs, initial, f)`, and `map(xs, f)` and
+/// `filter(xs, f)` in terms of it.
+pub const PRELUDE: &str = "fn reduce(xs, initial, f) {
+    if (len(xs) == 0) { initial } else { reduce(rest(xs), f(initial, first(xs)), f) }
+}
+fn map(xs, f) {
+    reduce(xs, [], fn(acc, x) { push(acc, f(x)) })
+}
+fn filter(xs, f) {
+    reduce(xs, [], fn(acc, x) { if (f(x)) { push(acc, x) } else { acc } })
+}
+";
+
+/* the bindings the prelude makes */
+pub(crate) fn prelude() -> Environment {
+    let program = Parser::new(Lexer::new(PRELUDE)).parse();
+    let mut env = Environment::new();
+    eval(&program, &mut env);
+    env
+}
+
+/// The builtins every program has, whatever groups are left out.
+pub const CORE: [&str; 3] = ["len", "print", "type"];
+
+/// A group of builtins, bound to a namespace of the same name.
+#[non_exhaustive]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Group {
+    Array,
+    Hash,
+    String,
+    Math,
+    /// The builtins that read and write files.
+    Io,
+    /// The arguments and environment of the script, and `exit`.
+    Os,
+    Time,
+    Random,
+    /// The builtins about functions and code, like `memoize` and `parse`.
+    Lang,
+}
+
+impl Group {
+    pub const ALL: [Group; 9] = [
+        Group::Array,
+        Group::Hash,
+        Group::String,
+        Group::Math,
+        Group::Io,
+        Group::Os,
+        Group::Time,
+        Group::Random,
+        Group::Lang,
+    ];
+
+    /// The name of the group's namespace.
+    pub fn name(self) -> &'static str {
+        match self {
+            Group::Array => "array",
+            Group::Hash => "hash",
+            Group::String => "string",
+            Group::Math => "math",
+            Group::Io => "io",
+            Group::Os => "os",
+            Group::Time => "time",
+            Group::Random => "random",
+            Group::Lang => "lang",
+        }
+    }
+
+    /// The names of the builtins in the group. A builtin can be in more
+    /// than one, like `len`.
+    pub fn members(self) -> &'static [&'static str] {
+        match self {
+            Group::Array => &[
+                "len",
+                "first",
+                "last",
+                "rest",
+                "push",
+                "slice",
+                "map",
+                "range",
+                "zip",
+                "enumerate",
+                "freeze",
+                "iter",
+                "take",
+                "collect",
+                "map_iter",
+                "filter_iter",
+            ],
+            Group::Hash => &["len", "keys"],
+            Group::String => &[
+                "len", "chars", "bytes", "slice", "split", "upper", "contains",
+            ],
+            Group::Math => &["abs", "min", "max", "pow", "sqrt", "mod"],
+            Group::Io => &["read_file", "read_lines", "write_file"],
+            Group::Os => &["args", "env", "exit"],
+            Group::Time => &["clock", "sleep"],
+            Group::Random => &["rand", "rand_int"],
+            Group::Lang => &[
+                "memoize",
+                "typeassert",
+                "deepEqual",
+                "diff",
+                "assert_eq",
+                "throw",
+                "try",
+                "kind",
+                "thrown",
+                "parse",
+            ],
+        }
+    }
+
+    /// The namespace of the group.
+    pub fn namespace(self) -> Object {
+        let hash: Hash = self
+            .members()
+            .iter()
+            .map(|&name| {
+                let key = Object::String(name.into());
+                (key, builtin(name))
+            })
+            .collect();
+        Object::Hash(Rc::new(hash))
+    }
+
+    fn bit(self) -> u16 {
+        1 << self as u16
+    }
+}
+
+fn builtin(name: &str) -> Object {
+    let def = builtins::lookup(name).expect("every member is a builtin");
+    Object::Builtin(Builtin { def })
+}
+
+/// Which groups of builtins a program can see. Every group by default,
+/// each also by the names of its builtins on their own, like `split`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StdlibConfig {
+    /* a bit for each left out group */
+    excluded: u16,
+    flat_names: bool,
+}
+
+impl Default for StdlibConfig {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl StdlibConfig {
+    pub fn new() -> Self {
+        StdlibConfig {
+            excluded: 0,
+            flat_names: true,
+        }
+    }
+
+    /// Leaves out `group`: neither its namespace nor its builtins are
+    /// bound, unless a builtin is in [`CORE`] or in a group that's still
+    /// in.
+    pub fn without(mut self, group: Group) -> Self {
+        self.excluded |= group.bit();
+        self
+    }
+
+    /// Whether the builtins of the groups are bound by their own names
+    /// too, and not only as members of their namespace. On by default.
+    pub fn with_flat_names(mut self, flat_names: bool) -> Self {
+        self.flat_names = flat_names;
+        self
+    }
+
+    pub fn includes(&self, group: Group) -> bool {
+        self.excluded & group.bit() == 0
+    }
+
+    /// What `name` is when the program doesn't bind it: a core builtin, a
+    /// namespace, or a builtin of a group that's in.
+    pub fn global(&self, name: &str) -> Option<Object> {
+        if CORE.contains(&name) {
+            return Some(builtin(name));
+        }
+        let mut groups = Group::ALL.into_iter().filter(|&g| self.includes(g));
+        if let Some(group) = groups.clone().find(|g| g.name() == name) {
+            return Some(group.namespace());
+        }
+        if self.flat_names && groups.any(|g| g.members().contains(&name)) {
+            return Some(builtin(name));
+        }
+        None
+    }
+
+    /// Every name [`global`](Self::global) gives something for, for
+    /// passes like `analysis::resolve` that need to know.
+    pub fn global_names(&self) -> Vec<&'static str> {
+        let mut names = CORE.to_vec();
+        for group in Group::ALL.into_iter().filter(|&g| self.includes(g)) {
+            names.push(group.name());
+            if self.flat_names {
+                for member in group.members() {
+                    if !names.contains(member) {
+                        names.push(member);
+                    }
+                }
+            }
+        }
+        names
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::builtins::{lookup, BUILTIN_NAMES};
+    use crate::stdlib::{Group, StdlibConfig, CORE};
+
+    #[test]
+    fn test_every_builtin_has_a_place() {
+        for name in BUILTIN_NAMES {
+            let placed =
+                CORE.contains(&name) || Group::ALL.iter().any(|g| g.members().contains(&name));
+            assert!(placed, "{} is in no group", name);
+        }
+        for group in Group::ALL {
+            for name in group.members() {
+                assert!(lookup(name).is_some(), "{} is not a builtin", name);
+            }
+        }
+    }
+
+    #[test]
+    fn test_global_names() {
+        let all = StdlibConfig::new().global_names();
+        assert_eq!(all.len(), BUILTIN_NAMES.len() + Group::ALL.len());
+        for name in all.iter() {
+            assert!(StdlibConfig::new().global(name).is_some(), "{}", name);
+        }
+
+        let tests = [
+            (StdlibConfig::new().without(Group::Io), "read_file", false),
+            (StdlibConfig::new().without(Group::Io), "io", false),
+            (StdlibConfig::new().without(Group::Io), "env", true),
+            // `len` is core and `slice` still in `string`
+            (StdlibConfig::new().without(Group::Array), "len", true),
+            (StdlibConfig::new().without(Group::Array), "slice", true),
+            (StdlibConfig::new().without(Group::Array), "first", false),
+            (StdlibConfig::new().with_flat_names(false), "split", false),
+            (StdlibConfig::new().with_flat_names(false), "string", true),
+            (StdlibConfig::new().with_flat_names(false), "type", true),
+        ];
+        for (config, name, exp) in tests {
+            assert_eq!(
+                config.global(name).is_some(),
+                exp,
+                "{} in {:?}",
+                name,
+                config
+            );
+            assert_eq!(config.global_names().contains(&name), exp, "{}", name);
+        }
+    }
+}
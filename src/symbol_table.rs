@@ -0,0 +1,166 @@
+//! The names the [`compiler`](crate::compiler) has seen and where it put
+//! their values. Scopes nest like the blocks that open them, and a name
+//! resolves to the innermost scope that defines it.
+
+use std::collections::HashMap;
+use std::rc::Rc;
+
+#[non_exhaustive]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum SymbolScope {
+    /// Defined at the top level, the VM keeps its value in its globals.
+    Global,
+    /// Defined in a scope that was pushed on top of the global one.
+    Local,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Symbol {
+    pub name: Rc<str>,
+    pub scope: SymbolScope,
+    /// Where the value is kept among the others of its scope.
+    pub index: usize,
+    /// False for `const`.
+    pub mutable: bool,
+}
+
+#[derive(Debug, Default)]
+struct Scope {
+    store: HashMap<Rc<str>, Symbol>,
+}
+
+#[derive(Debug)]
+pub struct SymbolTable {
+    /* the global scope first, the innermost last */
+    scopes: Vec<Scope>,
+}
+
+impl Default for SymbolTable {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SymbolTable {
+    /// A table with only the global scope.
+    pub fn new() -> Self {
+        Self {
+            scopes: vec![Scope::default()],
+        }
+    }
+
+    pub fn push_scope(&mut self) {
+        self.scopes.push(Scope::default());
+    }
+
+    /// Drops the innermost scope and its names. The global scope stays.
+    pub fn pop_scope(&mut self) {
+        if self.scopes.len() > 1 {
+            self.scopes.pop();
+        }
+    }
+
+    /// Defines `name` in the innermost scope. Defining a name again keeps
+    /// its index, so the new value takes the place of the old one.
+    pub fn define(&mut self, name: Rc<str>, mutable: bool) -> Symbol {
+        let scope = if self.scopes.len() == 1 {
+            SymbolScope::Global
+        } else {
+            SymbolScope::Local
+        };
+        let store = &mut self.scopes.last_mut().unwrap().store;
+        let index = store.get(&name).map_or(store.len(), |sym| sym.index);
+        let sym = Symbol {
+            name: name.clone(),
+            scope,
+            index,
+            mutable,
+        };
+        store.insert(name, sym.clone());
+        sym
+    }
+
+    pub fn resolve(&self, name: &str) -> Option<&Symbol> {
+        self.scopes
+            .iter()
+            .rev()
+            .find_map(|scope| scope.store.get(name))
+    }
+
+    /// How many names the innermost scope defines.
+    pub fn len(&self) -> usize {
+        self.scopes.last().unwrap().store.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::symbol_table::{Symbol, SymbolScope, SymbolTable};
+
+    fn symbol(name: &str, scope: SymbolScope, index: usize) -> Symbol {
+        Symbol {
+            name: name.into(),
+            scope,
+            index,
+            mutable: true,
+        }
+    }
+
+    #[test]
+    fn test_define_and_resolve() {
+        let mut table = SymbolTable::new();
+        assert_eq!(
+            table.define("a".into(), true),
+            symbol("a", SymbolScope::Global, 0)
+        );
+        assert_eq!(
+            table.define("b".into(), true),
+            symbol("b", SymbolScope::Global, 1)
+        );
+        // defining again keeps the index
+        assert_eq!(
+            table.define("a".into(), true),
+            symbol("a", SymbolScope::Global, 0)
+        );
+        assert_eq!(table.len(), 2);
+        assert_eq!(
+            table.resolve("b"),
+            Some(&symbol("b", SymbolScope::Global, 1))
+        );
+        assert_eq!(table.resolve("c"), None);
+    }
+
+    #[test]
+    fn test_nested_scopes() {
+        let mut table = SymbolTable::new();
+        table.define("a".into(), true);
+        table.define("b".into(), false);
+        table.push_scope();
+        assert!(table.is_empty());
+        assert_eq!(
+            table.define("b".into(), true),
+            symbol("b", SymbolScope::Local, 0)
+        );
+        assert_eq!(
+            table.resolve("a"),
+            Some(&symbol("a", SymbolScope::Global, 0))
+        );
+        assert_eq!(
+            table.resolve("b"),
+            Some(&symbol("b", SymbolScope::Local, 0))
+        );
+        table.pop_scope();
+        let b = table.resolve("b").unwrap();
+        assert_eq!(
+            (b.scope, b.index, b.mutable),
+            (SymbolScope::Global, 1, false)
+        );
+        // the global scope can't be popped
+        table.pop_scope();
+        assert_eq!(table.len(), 2);
+    }
+}
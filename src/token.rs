@@ -1,9 +1,18 @@
-#[derive(PartialEq, Eq, Debug, Clone, Hash)]
+use crate::ast::InfixOperator;
+use crate::parser::Precedence;
+
+#[non_exhaustive]
+#[derive(PartialEq, Eq, Debug, Clone, Hash, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub enum Token {
+    #[default]
     Illegal,
     Eof,
     Ident(std::rc::Rc<str>),
+    /// An integer literal as written, e.g. `0xFF` or `5i`.
     Int(std::rc::Rc<str>),
+    /// A float literal as written, e.g. `5.0` or `5f`.
+    Float(std::rc::Rc<str>),
     String(std::rc::Rc<str>),
     Assign,
     Plus,
@@ -18,6 +27,10 @@ pub enum Token {
     Comma,
     Colon,
     Semicolon,
+    /// `|>`
+    Pipe,
+    /// `.`, for a member like `string.split`
+    Dot,
     LParen,
     RParen,
     LSquirly,
@@ -25,16 +38,149 @@ pub enum Token {
     LBracket,
     RBracket,
     Function,
+    Macro,
     Let,
+    Const,
     If,
     Else,
     Return,
+    For,
+    In,
+    Break,
+    Continue,
+    Import,
     True,
     False,
+    /// Only produced by a lexer built `with_comments`.
+    Comment(std::rc::Rc<str>),
 }
 
-impl Default for Token {
-    fn default() -> Self {
-        Token::Illegal
+/// The kind of a token without its payload, e.g. every `Token::Ident` is a
+/// `TokenKind::Ident` no matter its name.
+#[non_exhaustive]
+#[derive(PartialEq, Eq, Debug, Clone, Copy, Hash)]
+pub enum TokenKind {
+    Illegal,
+    Eof,
+    Ident,
+    Int,
+    Float,
+    String,
+    Assign,
+    Plus,
+    Minus,
+    Slash,
+    Asterisk,
+    Bang,
+    Lt,
+    Gt,
+    Eq,
+    NotEq,
+    Comma,
+    Colon,
+    Semicolon,
+    Pipe,
+    Dot,
+    LParen,
+    RParen,
+    LSquirly,
+    RSquirly,
+    LBracket,
+    RBracket,
+    Function,
+    Macro,
+    Let,
+    Const,
+    If,
+    Else,
+    Return,
+    For,
+    In,
+    Break,
+    Continue,
+    Import,
+    True,
+    False,
+    Comment,
+}
+
+impl Token {
+    /// How tightly the token binds when it follows an operand. `Lowest` for
+    /// every token that can't continue an expression.
+    pub fn precedence(&self) -> Precedence {
+        match self {
+            Token::Assign => Precedence::Assign,
+            Token::Pipe => Precedence::Pipe,
+            Token::Eq | Token::NotEq => Precedence::Equals,
+            Token::Lt | Token::Gt => Precedence::LessGreater,
+            Token::Plus | Token::Minus => Precedence::Sum,
+            Token::Asterisk | Token::Slash => Precedence::Product,
+            Token::LParen => Precedence::Call,
+            Token::LBracket | Token::Dot => Precedence::Index,
+            _ => Precedence::Lowest,
+        }
+    }
+
+    /// The binary operator the token stands for, if any. Calls and index
+    /// expressions have a precedence but are not infix operators.
+    pub fn infix_operator(&self) -> Option<InfixOperator> {
+        match self {
+            Token::Plus => Some(InfixOperator::Plus),
+            Token::Minus => Some(InfixOperator::Minus),
+            Token::Asterisk => Some(InfixOperator::Asterisk),
+            Token::Slash => Some(InfixOperator::Slash),
+            Token::Eq => Some(InfixOperator::Eq),
+            Token::NotEq => Some(InfixOperator::NotEq),
+            Token::Lt => Some(InfixOperator::Lt),
+            Token::Gt => Some(InfixOperator::Gt),
+            _ => None,
+        }
+    }
+
+    pub fn kind(&self) -> TokenKind {
+        match self {
+            Token::Illegal => TokenKind::Illegal,
+            Token::Eof => TokenKind::Eof,
+            Token::Ident(_) => TokenKind::Ident,
+            Token::Int(_) => TokenKind::Int,
+            Token::Float(_) => TokenKind::Float,
+            Token::String(_) => TokenKind::String,
+            Token::Assign => TokenKind::Assign,
+            Token::Plus => TokenKind::Plus,
+            Token::Minus => TokenKind::Minus,
+            Token::Slash => TokenKind::Slash,
+            Token::Asterisk => TokenKind::Asterisk,
+            Token::Bang => TokenKind::Bang,
+            Token::Lt => TokenKind::Lt,
+            Token::Gt => TokenKind::Gt,
+            Token::Eq => TokenKind::Eq,
+            Token::NotEq => TokenKind::NotEq,
+            Token::Comma => TokenKind::Comma,
+            Token::Colon => TokenKind::Colon,
+            Token::Semicolon => TokenKind::Semicolon,
+            Token::Pipe => TokenKind::Pipe,
+            Token::Dot => TokenKind::Dot,
+            Token::LParen => TokenKind::LParen,
+            Token::RParen => TokenKind::RParen,
+            Token::LSquirly => TokenKind::LSquirly,
+            Token::RSquirly => TokenKind::RSquirly,
+            Token::LBracket => TokenKind::LBracket,
+            Token::RBracket => TokenKind::RBracket,
+            Token::Function => TokenKind::Function,
+            Token::Macro => TokenKind::Macro,
+            Token::Let => TokenKind::Let,
+            Token::Const => TokenKind::Const,
+            Token::If => TokenKind::If,
+            Token::Else => TokenKind::Else,
+            Token::Return => TokenKind::Return,
+            Token::For => TokenKind::For,
+            Token::In => TokenKind::In,
+            Token::Break => TokenKind::Break,
+            Token::Continue => TokenKind::Continue,
+            Token::Import => TokenKind::Import,
+            Token::True => TokenKind::True,
+            Token::False => TokenKind::False,
+            Token::Comment(_) => TokenKind::Comment,
+        }
     }
 }
@@ -1,32 +1,70 @@
-use std::io::Write;
-
 use crate::token::Token;
 
-pub fn is_letter(ch: char) -> bool {
-    'a' <= ch && ch <= 'z' || 'A' <= ch && ch <= 'Z' || ch == '_'
+pub(crate) fn is_letter(ch: char) -> bool {
+    ch.is_ascii_lowercase() || ch.is_ascii_uppercase() || ch == '_'
+}
+
+pub(crate) fn is_digit(ch: char) -> bool {
+    ch.is_ascii_digit()
+}
+
+/// Parses the text of a `Token::Int`: decimal or `0x` hex digits, with `_`
+/// allowed anywhere after the first digit as a separator, and an optional
+/// `i` suffix.
+pub(crate) fn parse_int_literal(literal: &str) -> Option<i64> {
+    let literal = literal.strip_suffix('i').unwrap_or(literal);
+    let digits = literal.replace('_', "");
+    match digits.strip_prefix("0x").or(digits.strip_prefix("0X")) {
+        Some(hex) => i64::from_str_radix(hex, 16).ok(),
+        None => digits.parse::<i64>().ok(),
+    }
 }
 
-pub fn is_digit(ch: char) -> bool {
-    '0' <= ch && ch <= '9'
+/// Parses the text of a `Token::Float`: decimal digits with a fraction, an
+/// `f` suffix or both, with `_` separators like an integer literal.
+pub(crate) fn parse_float_literal(literal: &str) -> Option<f64> {
+    let literal = literal.strip_suffix('f').unwrap_or(literal);
+    literal.replace('_', "").parse::<f64>().ok()
 }
 
-pub fn lookup_ident(ident: &str) -> Token {
+/// `s` as a Monkey string literal, quoted and with the characters that
+/// need it escaped, so lexing it gives `s` back.
+pub(crate) fn quote_string(s: &str) -> String {
+    let mut res = String::with_capacity(s.len() + 2);
+    res.push('"');
+    for ch in s.chars() {
+        match ch {
+            '"' => res.push_str("\\\""),
+            '\\' => res.push_str("\\\\"),
+            '\n' => res.push_str("\\n"),
+            '\t' => res.push_str("\\t"),
+            '\r' => res.push_str("\\r"),
+            '\0' => res.push_str("\\0"),
+            ch if ch.is_ascii_control() => res.push_str(&format!("\\x{:02X}", ch as u32)),
+            ch if ch.is_control() => res.push_str(&format!("\\u{{{:X}}}", ch as u32)),
+            ch => res.push(ch),
+        }
+    }
+    res.push('"');
+    res
+}
+
+pub(crate) fn lookup_ident(ident: &str) -> Token {
     match ident {
         "fn" => Token::Function,
+        "macro" => Token::Macro,
         "let" => Token::Let,
+        "const" => Token::Const,
         "if" => Token::If,
         "return" => Token::Return,
+        "for" => Token::For,
+        "in" => Token::In,
+        "break" => Token::Break,
+        "continue" => Token::Continue,
+        "import" => Token::Import,
         "true" => Token::True,
         "false" => Token::False,
         "else" => Token::Else,
         _ => Token::Ident(ident.into()),
     }
 }
-
-pub fn read_line(prompt: &str) -> anyhow::Result<String> {
-    let mut input = String::new();
-    print!("{}", prompt);
-    std::io::stdout().flush()?;
-    std::io::stdin().read_line(&mut input)?;
-    Ok(input)
-}
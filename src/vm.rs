@@ -0,0 +1,411 @@
+//! Runs the [`Bytecode`] the [`compiler`](crate::compiler) produces on a
+//! stack. The operators are the evaluator's, so a program gives the same
+//! value either way.
+
+use crate::ast::InfixOperator;
+use crate::code::{Instructions, Opcode};
+use crate::compiler::{Bytecode, MAX_GLOBALS};
+use crate::evaluator::{
+    eval_bang_operator, eval_infix_expression, eval_minus_operator, is_truthy, FALSE, NULL, TRUE,
+};
+use crate::object::{EvalError, Object};
+
+const STACK_SIZE: usize = 2048;
+
+#[non_exhaustive]
+#[derive(Debug, Clone, PartialEq)]
+pub enum VmError {
+    /// What the evaluator would have returned as an error object.
+    Runtime(EvalError),
+    /// More than `STACK_SIZE` values on the stack at once.
+    StackOverflow,
+    /// A global set at an index past the limit
+    /// [`with_max_globals`](Vm::with_max_globals) gave.
+    TooManyGlobals { max: usize },
+    /// Bytecode the compiler wouldn't produce: an unknown opcode, an
+    /// operand cut short, a jump out of the instructions, a pop from an
+    /// empty stack or a global read before it's set.
+    InvalidInstruction { offset: usize },
+}
+
+impl std::fmt::Display for VmError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Runtime(err) => write!(f, "{}", err),
+            Self::StackOverflow => write!(f, "stack overflow, the limit is {}", STACK_SIZE),
+            Self::TooManyGlobals { max } => write!(f, "too many globals, the limit is {}", max),
+            Self::InvalidInstruction { offset } => {
+                write!(f, "invalid instruction at offset {}", offset)
+            }
+        }
+    }
+}
+
+impl std::error::Error for VmError {}
+
+pub struct Vm {
+    instructions: Instructions,
+    constants: Vec<Object>,
+    stack: Vec<Object>,
+    /* grows as globals are set, up to max_globals */
+    globals: Vec<Option<Object>>,
+    max_globals: usize,
+    last_popped: Option<Object>,
+}
+
+impl Vm {
+    pub fn new(bytecode: Bytecode) -> Self {
+        Self {
+            instructions: bytecode.instructions,
+            constants: bytecode.constants,
+            stack: Vec::new(),
+            globals: Vec::new(),
+            max_globals: MAX_GLOBALS,
+            last_popped: None,
+        }
+    }
+
+    /// Limits how many globals the program may set, by default as many as
+    /// the compiler can give indices to.
+    pub fn with_max_globals(mut self, max: usize) -> Self {
+        self.max_globals = max;
+        self
+    }
+
+    /// Runs the instructions to the end, or up to the first error.
+    pub fn run(&mut self) -> Result<(), VmError> {
+        let mut ip = 0;
+        while ip < self.instructions.len() {
+            let offset = ip;
+            let invalid = VmError::InvalidInstruction { offset };
+            let op = Opcode::lookup(self.instructions[ip]).ok_or(invalid.clone())?;
+            ip += 1;
+            match op {
+                Opcode::Constant => {
+                    let index = self.read_u16(ip).ok_or(invalid.clone())?;
+                    ip += 2;
+                    let constant = self.constants.get(index).ok_or(invalid)?.clone();
+                    self.push(constant)?;
+                }
+                Opcode::Add
+                | Opcode::Sub
+                | Opcode::Mul
+                | Opcode::Div
+                | Opcode::Equal
+                | Opcode::NotEqual
+                | Opcode::GreaterThan
+                | Opcode::LessThan => {
+                    let right = self.pop().ok_or(invalid.clone())?;
+                    let left = self.pop().ok_or(invalid)?;
+                    let res = eval_infix_expression(&left, &right, &infix_operator(op));
+                    self.push_result(res)?;
+                }
+                Opcode::True => self.push(TRUE)?,
+                Opcode::False => self.push(FALSE)?,
+                Opcode::Null => self.push(NULL)?,
+                Opcode::Minus => {
+                    let right = self.pop().ok_or(invalid)?;
+                    self.push_result(eval_minus_operator(&right))?;
+                }
+                Opcode::Bang => {
+                    let right = self.pop().ok_or(invalid)?;
+                    self.push_result(eval_bang_operator(&right))?;
+                }
+                Opcode::Pop => {
+                    self.last_popped = Some(self.pop().ok_or(invalid)?);
+                }
+                Opcode::SetGlobal => {
+                    let index = self.read_u16(ip).ok_or(invalid.clone())?;
+                    ip += 2;
+                    if index >= self.max_globals {
+                        return Err(VmError::TooManyGlobals {
+                            max: self.max_globals,
+                        });
+                    }
+                    let value = self.pop().ok_or(invalid)?;
+                    if index >= self.globals.len() {
+                        self.globals.resize(index + 1, None);
+                    }
+                    self.globals[index] = Some(value);
+                }
+                Opcode::GetGlobal => {
+                    let index = self.read_u16(ip).ok_or(invalid.clone())?;
+                    ip += 2;
+                    let value = self.globals.get(index).cloned().flatten();
+                    self.push(value.ok_or(invalid)?)?;
+                }
+                Opcode::Jump => {
+                    ip = self.jump_target(ip).ok_or(invalid)?;
+                }
+                Opcode::JumpNotTruthy => {
+                    let target = self.jump_target(ip).ok_or(invalid.clone())?;
+                    ip += 2;
+                    if !is_truthy(&self.pop().ok_or(invalid)?) {
+                        ip = target;
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// The value of the last expression statement that ran.
+    pub fn last_popped(&self) -> Option<&Object> {
+        self.last_popped.as_ref()
+    }
+
+    fn push(&mut self, obj: Object) -> Result<(), VmError> {
+        if self.stack.len() == STACK_SIZE {
+            return Err(VmError::StackOverflow);
+        }
+        self.stack.push(obj);
+        Ok(())
+    }
+
+    /* pushes what an operator gave, unless it was an error */
+    fn push_result(&mut self, obj: Object) -> Result<(), VmError> {
+        match obj {
+            Object::Error(err) => Err(VmError::Runtime(err)),
+            obj => self.push(obj),
+        }
+    }
+
+    fn pop(&mut self) -> Option<Object> {
+        self.stack.pop()
+    }
+
+    fn read_u16(&self, ip: usize) -> Option<usize> {
+        let bytes = self.instructions.get(ip..ip + 2)?;
+        Some(u16::from_be_bytes([bytes[0], bytes[1]]) as usize)
+    }
+
+    /* the operand of the jump at `ip`, if it's inside the instructions */
+    fn jump_target(&self, ip: usize) -> Option<usize> {
+        self.read_u16(ip)
+            .filter(|&target| target <= self.instructions.len())
+    }
+}
+
+fn infix_operator(op: Opcode) -> InfixOperator {
+    match op {
+        Opcode::Add => InfixOperator::Plus,
+        Opcode::Sub => InfixOperator::Minus,
+        Opcode::Mul => InfixOperator::Asterisk,
+        Opcode::Div => InfixOperator::Slash,
+        Opcode::Equal => InfixOperator::Eq,
+        Opcode::NotEqual => InfixOperator::NotEq,
+        Opcode::GreaterThan => InfixOperator::Gt,
+        Opcode::LessThan => InfixOperator::Lt,
+        _ => unreachable!("{:?} isn't an infix operator", op),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::ast::Statement;
+    use crate::code::{make, Opcode};
+    use crate::compiler::{Bytecode, Compiler};
+    use crate::environment::Environment;
+    use crate::evaluator::eval;
+    use crate::lexer::Lexer;
+    use crate::object::{EvalError, Object};
+    use crate::parser::Parser;
+    use crate::vm::{Vm, VmError};
+
+    fn run(input: &str) -> Result<Option<Object>, VmError> {
+        let mut p = Parser::new(Lexer::new(input));
+        let program = p.parse();
+        assert!(p.get_errors().is_empty(), "{:?}", p.get_errors());
+        let bytecode = Compiler::new()
+            .compile(&program)
+            .unwrap_or_else(|e| panic!("compiling {:?}: {}", input, e));
+        let mut vm = Vm::new(bytecode);
+        vm.run()?;
+        Ok(vm.last_popped().cloned())
+    }
+
+    /* what the evaluator gives for `input`, in the VM's terms */
+    fn eval_input(input: &str) -> Result<Option<Object>, VmError> {
+        let mut p = Parser::new(Lexer::new(input));
+        let program = p.parse();
+        assert!(p.get_errors().is_empty(), "{:?}", p.get_errors());
+        match eval(&program, &mut Environment::new()) {
+            Some(Object::Error(err)) => Err(VmError::Runtime(err)),
+            // a block without statements has no value, which the VM pushes
+            // as null
+            None if matches!(
+                program.statements.last(),
+                Some(Statement::ExpressionStatement(_))
+            ) =>
+            {
+                Ok(Some(Object::Null))
+            }
+            // the VM remembers the last expression statement, which the
+            // evaluator forgets after a `let`
+            None => Ok(None),
+            res => Ok(res),
+        }
+    }
+
+    #[test]
+    fn test_integer_arithmetic() {
+        let tests = [
+            ("1", 1),
+            ("1 + 2", 3),
+            ("1 - 2", -1),
+            ("2 * 3 - 4 / 2", 4),
+            ("5 * (2 + 10)", 60),
+            ("-5 + 10 * -2", -25),
+            ("1; 2; 3", 3),
+        ];
+        for (input, exp) in tests {
+            assert_eq!(run(input), Ok(Some(Object::Integer(exp))), "{}", input);
+        }
+    }
+
+    #[test]
+    fn test_conditionals() {
+        let tests = [
+            ("if (true) { 10 }", Object::Integer(10)),
+            ("if (true) { 10 } else { 20 }", Object::Integer(10)),
+            ("if (false) { 10 } else { 20 } ", Object::Integer(20)),
+            ("if (1) { 10 }", Object::Integer(10)),
+            ("if (1 < 2) { 10 } else { 20 }", Object::Integer(10)),
+            ("if (1 > 2) { 10 } else { 20 }", Object::Integer(20)),
+            ("if (1 > 2) { 10 }", Object::Null),
+            ("if (false) { 10 }", Object::Null),
+            ("!(if (false) { 5; })", Object::Boolean(true)),
+            (
+                "if ((if (false) { 10 })) { 10 } else { 20 }",
+                Object::Integer(20),
+            ),
+            ("if (true) {} else { 1 }", Object::Null),
+            ("if (true) { 1; 2 } + 3", Object::Integer(5)),
+        ];
+        for (input, exp) in tests {
+            assert_eq!(run(input), Ok(Some(exp)), "{}", input);
+        }
+    }
+
+    #[test]
+    fn test_runtime_errors() {
+        assert_eq!(
+            run("1 / 0"),
+            Err(VmError::Runtime(EvalError::DivisionByZero))
+        );
+        assert_eq!(
+            run("if (true) { -true }").unwrap_err().to_string(),
+            "unsupported operand type for -: BOOLEAN"
+        );
+    }
+
+    #[test]
+    fn test_invalid_instructions() {
+        let tests = [
+            (vec![vec![255]], 0),
+            (vec![make(Opcode::Pop, &[])], 0),
+            (vec![make(Opcode::True, &[]), make(Opcode::Add, &[])], 1),
+            (vec![make(Opcode::Constant, &[0])], 0),
+            (vec![make(Opcode::Jump, &[4])], 0),
+            (vec![vec![Opcode::JumpNotTruthy as u8, 0]], 0),
+            (vec![make(Opcode::GetGlobal, &[0])], 0),
+            (vec![make(Opcode::SetGlobal, &[0])], 0),
+        ];
+        for (instructions, offset) in tests {
+            let mut vm = Vm::new(Bytecode {
+                instructions: instructions.concat(),
+                constants: vec![],
+            });
+            assert_eq!(
+                vm.run(),
+                Err(VmError::InvalidInstruction { offset }),
+                "running {:?}",
+                instructions
+            );
+        }
+    }
+
+    #[test]
+    fn test_globals() {
+        let tests = [
+            ("let one = 1; one", 1),
+            ("let one = 1; let two = 2; one + two", 3),
+            ("let one = 1; let two = one + one; one + two", 3),
+            ("let x = 1; let x = x + 1; x", 2),
+            ("let x = 5; if (x > 2) { let y = x * 2; }; y", 10),
+        ];
+        for (input, exp) in tests {
+            assert_eq!(run(input), Ok(Some(Object::Integer(exp))), "{}", input);
+        }
+    }
+
+    #[test]
+    fn test_too_many_globals() {
+        let bytecode = |input| {
+            let mut p = Parser::new(Lexer::new(input));
+            Compiler::new().compile(&p.parse()).unwrap()
+        };
+        let mut vm = Vm::new(bytecode("let a = 1; let b = 2;")).with_max_globals(2);
+        assert_eq!(vm.run(), Ok(()));
+        let mut vm = Vm::new(bytecode("let a = 1; let b = 2; let c = 3;")).with_max_globals(2);
+        let err = vm.run().unwrap_err();
+        assert_eq!(err, VmError::TooManyGlobals { max: 2 });
+        assert_eq!(err.to_string(), "too many globals, the limit is 2");
+    }
+
+    #[test]
+    fn test_stack_overflow() {
+        let mut vm = Vm::new(Bytecode {
+            instructions: make(Opcode::True, &[]).repeat(3000),
+            constants: vec![],
+        });
+        assert_eq!(vm.run(), Err(VmError::StackOverflow));
+    }
+
+    /// The evaluator and the VM agree on every input.
+    #[test]
+    fn test_differential() {
+        let tests = [
+            "1 + 2 * 3 - 4",
+            "10 / 3; -7 / 2",
+            "1 == 1; 1 != 1",
+            "true == false; true != false",
+            "1 < 2 == true",
+            "!5; !!false; !-1",
+            "5 / 0",
+            "true + 1",
+            "true + false",
+            "-false",
+            "1 > true",
+            "true < 1",
+            "(1 / 0) < (true - 1)",
+            "if (true) { 10 }",
+            "if (false) { 10 }",
+            "if (false) { 10 } else { 20 }",
+            "if (0) { 1 } else { 2 }",
+            "if (1 == 2) { 1 } else { if (2 == 2) { 2 } else { 3 } }",
+            "if (true) { 1 / 0 } else { 2 }",
+            "if (false) { 1 / 0 } else { 2 }",
+            "if (if (true) { false }) { 1 } else { 2 }",
+            "if (true) { }",
+            "if (false) { 1 } else { }",
+            "if (true) { 1; 2; 3 }",
+            "!if (false) { 1 }",
+            "if (true) { 10 } else { 20 }; 3333;",
+            "(if (true) { 2 } else { 3 }) * (if (false) { 4 } else { 5 })",
+            "-if (true) { 5 }",
+            "let one = 1; let two = one + one; two;",
+            "let a = 1; let b = a * 2; let c = b - a; a + b + c",
+            "let a = 1;",
+            "if (true) { let b = 2; }",
+            "let a = 1 / 0; a",
+            "let x = true; if (x) { let x = 5; x } else { 0 }",
+            "let x = 0; if (x) { 1 } else { 2 }",
+            "let x = false; let y = !x; if (y == x) { 1 }",
+            "const a = 1; let b = a + 1; b",
+        ];
+        for input in tests {
+            assert_eq!(run(input), eval_input(input), "{}", input);
+        }
+    }
+}
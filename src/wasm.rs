@@ -0,0 +1,128 @@
+//! Bindings for embedding the interpreter in a web page. Build with
+//! `wasm-pack build --features wasm`; every function takes and returns
+//! plain strings, and the results are JSON.
+
+use wasm_bindgen::prelude::*;
+
+use crate::builtins::capture_output;
+use crate::environment::Environment;
+use crate::evaluator;
+use crate::lexer::Lexer;
+use crate::object::ObjectTrait;
+use crate::parser::Parser;
+
+/// Parses `source` into `{"program": ...}`, or `{"errors": [...]}` if it
+/// doesn't parse.
+#[wasm_bindgen]
+pub fn parse_to_json(source: &str) -> String {
+    let mut p = Parser::new(Lexer::new(source));
+    let program = p.parse();
+    let res = if p.errors_len() != 0 {
+        serde_json::json!({ "errors": p.get_errors() })
+    } else {
+        serde_json::json!({ "program": program })
+    };
+    res.to_string()
+}
+
+/// Runs `source` in a fresh environment. See [`Session::run`] for the
+/// result.
+#[wasm_bindgen]
+pub fn run(source: &str) -> String {
+    Session::new().run(source)
+}
+
+/// An environment that lives across calls, so a binding made in one `run`
+/// can be used in the next, like in the REPL.
+#[wasm_bindgen]
+pub struct Session {
+    env: Environment,
+}
+
+impl Default for Session {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[wasm_bindgen]
+impl Session {
+    #[wasm_bindgen(constructor)]
+    pub fn new() -> Session {
+        Session {
+            env: Environment::new(),
+        }
+    }
+
+    /// Runs `source` into `{"output": ..., "result": ...}`, where `output`
+    /// is everything `print` printed and `result` is the inspected value of
+    /// the program, or null if it has none. A program that doesn't parse
+    /// gives `{"errors": [...]}` and isn't run.
+    pub fn run(&mut self, source: &str) -> String {
+        let mut p = Parser::new(Lexer::new(source));
+        let program = p.parse();
+        if p.errors_len() != 0 {
+            return serde_json::json!({ "errors": p.get_errors() }).to_string();
+        }
+        let (obj, output) = capture_output(|| evaluator::eval(&program, &mut self.env));
+        let result = obj.map(|obj| obj.inspect());
+        serde_json::json!({ "output": output, "result": result }).to_string()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use serde_json::{json, Value};
+
+    use crate::wasm::{parse_to_json, run, Session};
+
+    fn parse_json(s: &str) -> Value {
+        serde_json::from_str(s).expect("output is not json")
+    }
+
+    #[test]
+    fn test_parse_to_json() {
+        let res = parse_json(&parse_to_json("let x = 5;"));
+        let stmt = &res["program"]["statements"][0]["LetStatement"];
+        assert_eq!(stmt["name"]["value"], "x");
+        assert_eq!(stmt["value"]["Integer"]["value"], 5);
+
+        let res = parse_json(&parse_to_json("let = 5;"));
+        assert!(res.get("program").is_none());
+        assert_eq!(
+            res["errors"][0],
+            "expected next token to be Token::Ident, got Assign instead"
+        );
+    }
+
+    #[test]
+    fn test_run() {
+        let res = parse_json(&run("print(\"hi\"); let x = 2; x * 3"));
+        assert_eq!(res, json!({ "output": "hi\n", "result": "6" }));
+
+        let res = parse_json(&run("let x = 1;"));
+        assert_eq!(res, json!({ "output": "", "result": null }));
+
+        let res = parse_json(&run("1 + true"));
+        assert_eq!(
+            res["result"],
+            "ERROR: unsupported operand types for +: INTEGER and BOOLEAN"
+        );
+
+        let res = parse_json(&run("let = 1;"));
+        assert!(res.get("output").is_none());
+        assert!(!res["errors"].as_array().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_session_keeps_bindings() {
+        let mut session = Session::new();
+        parse_json(&session.run("let add = fn(a, b) { a + b };"));
+        let res = parse_json(&session.run("add(1, 2)"));
+        assert_eq!(res["result"], "3");
+
+        // a fresh run doesn't see the session's bindings
+        let res = parse_json(&run("add(1, 2)"));
+        assert_eq!(res["result"], "ERROR: identifier not found: add");
+    }
+}
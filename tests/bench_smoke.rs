@@ -0,0 +1,90 @@
+//! Runs every benchmark input through the pipeline once, so a broken
+//! generator or a regression in the benchmarked code paths is caught by
+//! `cargo test --features bench-smoke` without running the full benchmarks.
+//! `make bench-smoke` runs these after `cargo bench --no-run`, which builds
+//! the benchmarks themselves.
+#![cfg(feature = "bench-smoke")]
+
+use interpreter::environment::Environment;
+use interpreter::evaluator::eval;
+use interpreter::lexer::Lexer;
+use interpreter::object::Object;
+use interpreter::parser::Parser;
+
+#[path = "../benches/generator/mod.rs"]
+mod generator;
+
+fn parse_ok(input: &str) -> interpreter::ast::Program {
+    let l = Lexer::new(input);
+    let mut p = Parser::new(l);
+    let program = p.parse();
+    assert_eq!(p.get_errors(), &Vec::<String>::new());
+    program
+}
+
+#[test]
+fn test_generators_are_deterministic() {
+    assert_eq!(
+        generator::many_statements(50),
+        generator::many_statements(50)
+    );
+    assert_eq!(
+        generator::many_identifiers(50),
+        generator::many_identifiers(50)
+    );
+    assert_eq!(
+        generator::deep_expression(50),
+        generator::deep_expression(50)
+    );
+}
+
+#[test]
+fn test_parse_inputs() {
+    parse_ok(&generator::deep_expression(50));
+    parse_ok(&generator::many_identifiers(100));
+    parse_ok(&generator::many_statements(100));
+}
+
+#[test]
+fn test_parse_snippets_with_reset() {
+    let mut p = Parser::new(Lexer::new(""));
+    for snippet in generator::snippets(100) {
+        p.reset(&snippet);
+        assert_eq!(p.parse().statements.len(), 1);
+        assert_eq!(p.get_errors(), &Vec::<String>::new());
+    }
+}
+
+#[test]
+fn test_eval_inputs() {
+    let program = parse_ok(&generator::fibonacci(10));
+    let mut env = Environment::new();
+    assert_eq!(eval(&program, &mut env), Some(Object::Integer(55)));
+
+    let program = parse_ok(&generator::arithmetic_loop(100));
+    let mut env = Environment::new();
+    assert!(matches!(eval(&program, &mut env), Some(Object::Integer(_))));
+
+    let program = parse_ok(&generator::hash_literal(100));
+    let mut env = Environment::new();
+    assert_eq!(eval(&program, &mut env), Some(Object::Integer(100)));
+
+    let program = parse_ok(&generator::literal_array(100));
+    for _ in 0..100 {
+        let mut env = Environment::new();
+        assert_eq!(eval(&program, &mut env), Some(Object::Integer(100)));
+    }
+}
+
+#[test]
+fn test_incremental_edit() {
+    let source = generator::many_statements(100);
+    let at = source[source.len() / 2..].find('\n').unwrap() + source.len() / 2 + 1;
+    let mut doc = interpreter::incremental::Document::new(&source);
+    doc.apply_edit(at..at, "1");
+    let edited = Parser::new(Lexer::new(doc.source())).parse();
+    assert_eq!(doc.program().statements, edited.statements);
+    doc.apply_edit(at..at + 1, "");
+    assert_eq!(doc.source(), source);
+    assert_eq!(doc.program().statements, parse_ok(&source).statements);
+}
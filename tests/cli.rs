@@ -0,0 +1,515 @@
+//! Runs the `interpreter` binary on scripts, to check what it passes them
+//! and how their exit reaches the shell.
+
+use std::io::Write;
+use std::path::PathBuf;
+use std::process::{Command, Output, Stdio};
+
+/* writes `source` to a file of its own and runs it with `args` */
+fn run_script(name: &str, source: &str, args: &[&str]) -> Output {
+    let path: PathBuf =
+        std::env::temp_dir().join(format!("monkey-cli-{}-{}.mk", std::process::id(), name));
+    std::fs::write(&path, source).unwrap();
+    let output = Command::new(env!("CARGO_BIN_EXE_interpreter"))
+        .arg(&path)
+        .args(args)
+        .env("MONKEY_CLI_TEST", "from the shell")
+        .output()
+        .expect("the binary runs");
+    std::fs::remove_file(&path).unwrap();
+    output
+}
+
+/* runs `source` under `debug` with `input` piped in as the commands */
+fn debug_script(name: &str, source: &str, input: &str) -> Output {
+    let path: PathBuf =
+        std::env::temp_dir().join(format!("monkey-cli-{}-{}.mk", std::process::id(), name));
+    std::fs::write(&path, source).unwrap();
+    let mut child = Command::new(env!("CARGO_BIN_EXE_interpreter"))
+        .arg("debug")
+        .arg(&path)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .expect("the binary runs");
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(input.as_bytes())
+        .unwrap();
+    let output = child.wait_with_output().unwrap();
+    std::fs::remove_file(&path).unwrap();
+    output
+}
+
+/* runs the REPL with `input` piped in as the lines typed */
+fn repl(input: &str) -> Output {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_interpreter"))
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .expect("the binary runs");
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(input.as_bytes())
+        .unwrap();
+    child.wait_with_output().unwrap()
+}
+
+fn stdout(output: &Output) -> String {
+    String::from_utf8(output.stdout.clone()).unwrap()
+}
+
+#[test]
+fn test_script_args() {
+    let output = run_script(
+        "args",
+        "print(len(args())); map(args(), print);",
+        &["one", "two words", "--check"],
+    );
+    assert_eq!(stdout(&output), "3\none\ntwo words\n--check\n");
+    assert_eq!(output.status.code(), Some(0));
+}
+
+#[test]
+fn test_script_env() {
+    let output = run_script(
+        "env",
+        "print(env(\"MONKEY_CLI_TEST\")); print(env(\"MONKEY_CLI_UNSET\"));",
+        &[],
+    );
+    assert_eq!(stdout(&output), "from the shell\nnull\n");
+}
+
+#[test]
+fn test_script_exit_status() {
+    let output = run_script(
+        "exit",
+        "let check = fn(n) { if (n > 2) { exit(n) } }; check(1); print(\"ran\"); check(7); print(\"skipped\")",
+        &[],
+    );
+    assert_eq!(stdout(&output), "ran\n");
+    assert_eq!(output.status.code(), Some(7));
+
+    let output = run_script("failure", "1 + true", &[]);
+    assert_eq!(output.status.code(), Some(1));
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(
+        stderr.ends_with(
+            ":1:1: unsupported operand types for +: INTEGER and BOOLEAN\n1 + true\n^^^^^^^^\n"
+        ),
+        "{}",
+        stderr
+    );
+}
+
+#[test]
+fn test_script_call_depth() {
+    // deeper than the default limit, and not a tail call
+    let output = run_script(
+        "depth",
+        "let sum = fn(n) { if (n == 0) { 0 } else { n + sum(n - 1) } };\nprint(sum(100));\nsum(5000);\n",
+        &[],
+    );
+    assert_eq!(stdout(&output), "5050\n");
+    let err = String::from_utf8(output.stderr).unwrap();
+    assert!(err.contains("calls nested more than 1000 deep"), "{}", err);
+    assert_eq!(output.status.code(), Some(1));
+}
+
+#[test]
+fn test_script_error_locations() {
+    let tests = [
+        // (script, the error's place and what is printed after it)
+        (
+            "let a = 1;\nlet b = a * (2 + (3 / (a - 1)));\n",
+            ":2:18: division by zero\nlet b = a * (2 + (3 / (a - 1)));\n                 ^^^^^^^^^^^^^\n",
+        ),
+        (
+            "fn half(n) {\n\tif (n > 0) { n / 2 } else { n + \"!\" }\n}\nhalf(4);\nhalf(-1);\n",
+            ":2:30: unsupported operand types for +: INTEGER and STRING\n\tif (n > 0) { n / 2 } else { n + \"!\" }\n\t                            ^^^^^^^\n",
+        ),
+        // one that spans lines is underlined to the end of its first
+        (
+            "let f = fn(x) { x };\nf(1) + fn() {\n  2\n};\n",
+            ":2:1: unsupported operand types for +: INTEGER and FUNCTION\nf(1) + fn() {\n^^^^^^^^^^^^^\n",
+        ),
+        (
+            "let x = (1;\n",
+            ":1:11: expected next token to be RParen, got Semicolon instead\nlet x = (1;\n          ^\n",
+        ),
+        // block comments are skipped
+        (
+            "/* a\n b */ let a = /* c */ 1;\na / (a - 1);\n",
+            ":3:1: division by zero\na / (a - 1);\n^^^^^^^^^^^\n",
+        ),
+        // an error binding names is at the names
+        (
+            "const PI = 3;\nlet PI = 4;\n",
+            ":2:5: cannot reassign const 'PI'\nlet PI = 4;\n    ^^\n",
+        ),
+        (
+            "let a, b = (1, 2, 3);\n",
+            ":1:5: wrong number of values to destructure. got=3, want=2\nlet a, b = (1, 2, 3);\n    ^^^^\n",
+        ),
+    ];
+    for (i, (source, exp)) in tests.iter().enumerate() {
+        let output = run_script(&format!("location-{}", i), source, &[]);
+        assert_eq!(output.status.code(), Some(1));
+        let stderr = String::from_utf8(output.stderr).unwrap();
+        assert!(stderr.ends_with(exp), "{:?}\n{}", source, stderr);
+        assert_eq!(stderr.lines().count(), 3, "{}", stderr);
+    }
+}
+
+#[test]
+fn test_script_reads_files() {
+    let data = std::env::temp_dir().join(format!("monkey-cli-{}-data.txt", std::process::id()));
+    std::fs::write(&data, "3\n4\n").unwrap();
+    let source = format!("map(read_lines({:?}), print);", data.to_str().unwrap());
+    let output = run_script("fs", &source, &[]);
+    std::fs::remove_file(&data).unwrap();
+    assert_eq!(stdout(&output), "3\n4\n");
+    assert_eq!(output.status.code(), Some(0));
+}
+
+#[test]
+fn test_script_imports_modules_next_to_it() {
+    let name = format!("monkey-cli-{}-greet.mk", std::process::id());
+    let module = std::env::temp_dir().join(&name);
+    std::fs::write(&module, "let greet = fn(who) { \"hi \" + who };").unwrap();
+    let source = format!("import {:?} as g; print(g[\"greet\"](\"there\"));", name);
+    let output = run_script("import", &source, &[]);
+    std::fs::remove_file(&module).unwrap();
+    assert_eq!(stdout(&output), "hi there\n");
+    assert_eq!(output.status.code(), Some(0));
+}
+
+#[test]
+fn test_script_import_errors() {
+    let dir = std::env::temp_dir().join(format!("monkey-cli-{}-cycle", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(dir.join("a.mk"), "let x = 1;\nimport \"b.mk\";\n").unwrap();
+    std::fs::write(dir.join("b.mk"), "import \"a.mk\";\n").unwrap();
+    std::fs::write(
+        dir.join("c.mk"),
+        "print(1);\n  import \"nowhere.mk\" as n;\n",
+    )
+    .unwrap();
+    let run = |name: &str| {
+        Command::new(env!("CARGO_BIN_EXE_interpreter"))
+            .arg(dir.join(name))
+            .output()
+            .expect("the binary runs")
+    };
+    let (cycle, missing) = (run("a.mk"), run("c.mk"));
+    let canonical = std::fs::canonicalize(&dir).unwrap();
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    // from the script that was run, at its import
+    let err = String::from_utf8(cycle.stderr).unwrap();
+    let module = |name: &str| canonical.join(name).display().to_string();
+    let exp = format!(
+        "{}:2:8: import cycle: {} -> {} -> {}\n",
+        dir.join("a.mk").display(),
+        module("a.mk"),
+        module("b.mk"),
+        module("a.mk")
+    );
+    assert!(err.starts_with(&exp), "{}", err);
+    assert_eq!(cycle.status.code(), Some(1));
+
+    assert_eq!(stdout(&missing), "1\n");
+    let err = String::from_utf8(missing.stderr).unwrap();
+    let exp = format!(
+        "{}:2:10: module not found: nowhere.mk\n  import \"nowhere.mk\" as n;\n",
+        dir.join("c.mk").display()
+    );
+    assert!(err.starts_with(&exp), "{}", err);
+}
+
+#[test]
+fn test_script_seed() {
+    let path = std::env::temp_dir().join(format!("monkey-cli-{}-seed.mk", std::process::id()));
+    std::fs::write(&path, "print(rand_int(1, 1000000)); print(rand());").unwrap();
+    let seeded = |seed: &str| {
+        Command::new(env!("CARGO_BIN_EXE_interpreter"))
+            .args(["--seed", seed])
+            .arg(&path)
+            .output()
+            .expect("the binary runs")
+    };
+    let (first, again, other) = (seeded("7"), seeded("7"), seeded("8"));
+    let bad = seeded("seven");
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(first.status.code(), Some(0));
+    assert_eq!(stdout(&first), stdout(&again));
+    assert_ne!(stdout(&first), stdout(&other));
+    assert_ne!(bad.status.code(), Some(0));
+}
+
+#[test]
+fn test_check() {
+    let dir = std::env::temp_dir();
+    let bad = dir.join(format!("monkey-cli-{}-check-bad.mk", std::process::id()));
+    let good = dir.join(format!("monkey-cli-{}-check-good.mk", std::process::id()));
+    std::fs::write(&bad, "let x = 1;\nlet y 3;\nprint(x);\n  if (x { 2 }\n").unwrap();
+    std::fs::write(&good, "let x = 1;\nprint(x);\n").unwrap();
+    let check = |path: &PathBuf| {
+        Command::new(env!("CARGO_BIN_EXE_interpreter"))
+            .arg("check")
+            .arg(path)
+            .output()
+            .expect("the binary runs")
+    };
+    let (bad_output, good_output) = (check(&bad), check(&good));
+    std::fs::remove_file(&bad).unwrap();
+    std::fs::remove_file(&good).unwrap();
+
+    let out = stdout(&bad_output);
+    let at = |pos: &str| format!("{}:{}: ", bad.display(), pos);
+    assert!(
+        out.starts_with(&(at("2:7") + "expected next token to be Assign")),
+        "{}",
+        out
+    );
+    assert!(
+        out.contains(&(at("4:9") + "expected next token to be RParen")),
+        "{}",
+        out
+    );
+    assert_eq!(bad_output.status.code(), Some(1));
+    // nothing ran
+    assert!(!out.contains("\n1\n"));
+
+    assert_eq!(stdout(&good_output), "");
+    assert_eq!(good_output.status.code(), Some(0));
+
+    for args in [&["check"][..], &["check", "--strict"], &["--check"]] {
+        let output = Command::new(env!("CARGO_BIN_EXE_interpreter"))
+            .args(args)
+            .output()
+            .expect("the binary runs");
+        let err = String::from_utf8(output.stderr).unwrap();
+        assert!(err.contains("usage: check [--strict] files"), "{}", err);
+        assert_ne!(output.status.code(), Some(0));
+    }
+}
+
+#[test]
+fn test_lint() {
+    let path = std::env::temp_dir().join(format!("monkey-cli-{}-lint.mk", std::process::id()));
+    std::fs::write(&path, "let x = 1;\n  x + 2;\nif (false) { x }\n").unwrap();
+    let output = Command::new(env!("CARGO_BIN_EXE_interpreter"))
+        .arg("--lint")
+        .arg(&path)
+        .output()
+        .expect("the binary runs");
+    std::fs::remove_file(&path).unwrap();
+    let expected = format!(
+        "{0}:3:14: branch never taken: {{ x }}\n\
+         {0}:2:3: warning: expression result unused: (x + 2)\n",
+        path.display()
+    );
+    assert_eq!(stdout(&output), expected);
+    assert_eq!(output.status.code(), Some(1));
+}
+
+#[test]
+fn test_fmt_diff() {
+    let path = std::env::temp_dir().join(format!("monkey-cli-{}-fmt.mk", std::process::id()));
+    let lines: Vec<String> = (1..=11).map(|i| format!("let x{} = {};", i, i)).collect();
+    let source = lines
+        .join("\n")
+        .replace("= 1;", "=1;")
+        .replace("x10 = ", "x10=");
+    std::fs::write(&path, &source).unwrap();
+    let output = Command::new(env!("CARGO_BIN_EXE_interpreter"))
+        .args(["fmt", "--diff"])
+        .arg(&path)
+        .output()
+        .expect("the binary runs");
+    std::fs::remove_file(&path).unwrap();
+    let expected = format!(
+        "--- {0}
++++ {0}
+@@ -1,4 +1,4 @@
+-let x1 =1;
++let x1 = 1;
+ let x2 = 2;
+ let x3 = 3;
+ let x4 = 4;
+@@ -7,5 +7,5 @@
+ let x7 = 7;
+ let x8 = 8;
+ let x9 = 9;
+-let x10=10;
+-let x11 = 11;
+\\ No newline at end of file
++let x10 = 10;
++let x11 = 11;
+",
+        path.display()
+    );
+    assert_eq!(stdout(&output), expected);
+    assert_eq!(output.status.code(), Some(1));
+}
+
+#[test]
+fn test_debug_session() {
+    let source = "let add = fn(a, b) {
+  let sum = a + b;
+  sum
+};
+let x = 1;
+let y = add(x, 2);
+print(y);
+";
+    let input = "next\nbreak 2\ncontinue\nlocals\nprint a * 10\nstep\nstep\nbogus\ncontinue\n";
+    let output = debug_script("debug", source, input);
+    assert_eq!(output.status.code(), Some(0));
+    let exp = "line 1: let add = fn(a, b) {
+(debug) line 5: let x = 1;
+(debug) breakpoint at line 2
+(debug) line 2: let sum = a + b;
+(debug) a = 1
+add = fn(a, b)
+b = 2
+(debug) 10
+(debug) line 3: sum
+(debug) line 7: print(y);
+(debug) unknown command `bogus`, commands: step, next, continue, break <line>, print <expr>, locals
+(debug) 3
+";
+    assert_eq!(stdout(&output), exp);
+
+    // once the commands run out the program runs to the end
+    let output = debug_script("debug-eof", "let a = 1;\nexit(a + 4);\n", "step\n");
+    assert_eq!(
+        stdout(&output),
+        "line 1: let a = 1;\n(debug) line 2: exit(a + 4);\n(debug) "
+    );
+    assert_eq!(output.status.code(), Some(5));
+}
+
+#[test]
+fn test_coverage() {
+    let path = std::env::temp_dir().join(format!("monkey-cli-{}-coverage.mk", std::process::id()));
+    let source = "let abs = fn(x) {\n  if (x < 0) {\n    -x\n  } else {\n    x\n  }\n};\n\n// only ever positive\nprint(abs(3));\n";
+    std::fs::write(&path, source).unwrap();
+    let covered = |format: &[&str]| {
+        Command::new(env!("CARGO_BIN_EXE_interpreter"))
+            .args(["run", "--coverage"])
+            .args(format)
+            .arg(&path)
+            .output()
+            .expect("the binary runs")
+    };
+    let (text, json, bad) = (
+        covered(&[]),
+        covered(&["--coverage-format", "json"]),
+        covered(&["--coverage-format", "xml"]),
+    );
+    // the flags go in any order
+    let json_first = Command::new(env!("CARGO_BIN_EXE_interpreter"))
+        .args(["run", "--coverage-format", "json", "--coverage"])
+        .arg(&path)
+        .output()
+        .expect("the binary runs");
+    std::fs::remove_file(&path).unwrap();
+
+    // the script's output is left alone
+    assert_eq!(stdout(&text), "3\n");
+    assert_eq!(text.status.code(), Some(0));
+    let exp = "✓ let abs = fn(x) {
+✓   if (x < 0) {
+✗     -x
+    } else {
+✓     x
+    }
+  };
+
+  // only ever positive
+✓ print(abs(3));
+4 of 5 lines ran (80.0%)
+";
+    assert_eq!(String::from_utf8(text.stderr).unwrap(), exp);
+
+    assert_eq!(stdout(&json), "3\n");
+    let exp = "{\"lines\":[{\"line\":1,\"executed\":true},{\"line\":2,\"executed\":true},{\"line\":3,\"executed\":false},{\"line\":5,\"executed\":true},{\"line\":10,\"executed\":true}],\"executed\":4,\"total\":5,\"percent\":80.0}\n";
+    assert_eq!(String::from_utf8(json.stderr).unwrap(), exp);
+    assert_eq!(stdout(&json_first), "3\n");
+    assert_eq!(String::from_utf8(json_first.stderr).unwrap(), exp);
+
+    assert_ne!(bad.status.code(), Some(0));
+    assert_eq!(stdout(&bad), "");
+}
+
+#[test]
+fn test_run() {
+    let dir = std::env::temp_dir();
+    let source = dir.join(format!("monkey-cli-{}-run.mk", std::process::id()));
+    let bytecode = dir.join(format!("monkey-cli-{}-run.mkc", std::process::id()));
+    std::fs::write(&source, "1 + 2").unwrap();
+    let interpreter = |args: &[&std::ffi::OsStr]| {
+        Command::new(env!("CARGO_BIN_EXE_interpreter"))
+            .args(args)
+            .output()
+            .expect("the binary runs")
+    };
+    let built = interpreter(&[
+        "build".as_ref(),
+        source.as_ref(),
+        "-o".as_ref(),
+        bytecode.as_ref(),
+    ]);
+    let saved = interpreter(&["run".as_ref(), bytecode.as_ref()]);
+    let covered = interpreter(&["run".as_ref(), "--coverage".as_ref(), bytecode.as_ref()]);
+    std::fs::write(&source, "print(args()[0]);").unwrap();
+    let script = interpreter(&["run".as_ref(), source.as_ref(), "an arg".as_ref()]);
+    std::fs::remove_file(&source).unwrap();
+    std::fs::remove_file(&bytecode).unwrap();
+    assert_eq!(built.status.code(), Some(0));
+
+    // a script is run as one, with the arguments after it
+    assert_eq!(stdout(&script), "an arg\n");
+    assert_eq!(script.status.code(), Some(0));
+    assert!(script.stderr.is_empty());
+
+    // bytecode is told apart by how it starts, whatever its name
+    assert_eq!(stdout(&saved), "3\n");
+    assert_eq!(saved.status.code(), Some(0));
+
+    let err = String::from_utf8(covered.stderr).unwrap();
+    assert!(
+        err.contains("coverage needs the source, not bytecode"),
+        "{}",
+        err
+    );
+    assert_ne!(covered.status.code(), Some(0));
+}
+
+#[test]
+fn test_repl_reset() {
+    let output =
+        repl("let x = 5;\nlet f = fn(a) { a * x };\nf(2)\n:env\n:reset\n:env\nlen(\"ok\")\nx\n");
+    let exp = [
+        ">> >> >> [1] => 10",
+        ">> _ = 10",
+        "_1 = 10",
+        "f = fn(a)",
+        "x = 5",
+        // nothing is bound after the reset, not even the numbered values,
+        // but the builtins still are
+        ">> >> >> [1] => 2",
+        ">> ERROR: identifier not found: x",
+        ">> ",
+    ];
+    assert_eq!(stdout(&output), exp.join("\n"));
+    assert_eq!(output.status.code(), Some(0));
+}
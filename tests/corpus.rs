@@ -0,0 +1,112 @@
+//! Snapshots every program in `tests/corpus`: how it formats, what it
+//! parses to as `string()` prints it, and the diagnostics of every check.
+//! Each `name.monkey` has its expected output next to it in `name.snap`.
+//!
+//! After a change that is meant to alter the output, run with
+//! `UPDATE_SNAPSHOTS=1` to rewrite the snapshots, then review the diff.
+
+use std::path::{Path, PathBuf};
+
+use interpreter::analysis::{dead_code, lint, resolve, typecheck, unused_results, Diagnostic};
+use interpreter::ast::Node;
+use interpreter::builtins::BUILTIN_NAMES;
+use interpreter::format::format;
+use interpreter::lexer::Lexer;
+use interpreter::parser::Parser;
+
+fn fixtures() -> Vec<PathBuf> {
+    let dir = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/corpus");
+    let mut paths: Vec<PathBuf> = std::fs::read_dir(dir)
+        .expect("the corpus is checked in")
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "monkey"))
+        .collect();
+    // read_dir's order depends on the file system
+    paths.sort();
+    paths
+}
+
+fn section(res: &mut String, name: &str, lines: impl IntoIterator<Item = String>) {
+    res.push_str("-- ");
+    res.push_str(name);
+    res.push_str(" --\n");
+    for line in lines {
+        res.push_str(&line);
+        res.push('\n');
+    }
+}
+
+fn snapshot(source: &str) -> String {
+    let mut res = String::new();
+    let mut p = Parser::new(Lexer::new(source));
+    let program = p.parse();
+    if p.errors_len() != 0 {
+        section(&mut res, "parse errors", p.get_errors().iter().cloned());
+        return res;
+    }
+    let formatted = format(source).expect("the program parses");
+    section(&mut res, "format", formatted.lines().map(str::to_owned));
+    section(
+        &mut res,
+        "string",
+        program.string().lines().map(str::to_owned),
+    );
+
+    let passes: [(&str, Vec<Diagnostic>); 5] = [
+        ("lint", lint(&program)),
+        ("dead code", dead_code(&program)),
+        ("resolve", resolve(&program, &BUILTIN_NAMES)),
+        ("typecheck", typecheck(&program)),
+        ("unused results", unused_results(&program)),
+    ];
+    let diagnostics = passes.iter().flat_map(|(pass, diagnostics)| {
+        diagnostics.iter().map(move |d| format!("{}: {}", pass, d))
+    });
+    section(&mut res, "diagnostics", diagnostics);
+    res
+}
+
+fn check(path: &Path, update: bool) -> Result<(), String> {
+    let source = std::fs::read_to_string(path).unwrap();
+    let actual = snapshot(&source);
+    let snap = path.with_extension("snap");
+    if update {
+        std::fs::write(&snap, &actual).unwrap();
+        return Ok(());
+    }
+    let Ok(expected) = std::fs::read_to_string(&snap) else {
+        return Err(format!(
+            "{}: no snapshot, run with UPDATE_SNAPSHOTS=1 to write {}",
+            path.display(),
+            snap.display()
+        ));
+    };
+    if actual == expected {
+        return Ok(());
+    }
+    Err(format!(
+        "{}: output doesn't match {}\n--- expected\n{}--- actual\n{}",
+        path.display(),
+        snap.display(),
+        expected,
+        actual
+    ))
+}
+
+#[test]
+fn test_corpus_snapshots() {
+    let update = std::env::var_os("UPDATE_SNAPSHOTS").is_some_and(|v| v != "0");
+    let fixtures = fixtures();
+    assert!(!fixtures.is_empty());
+    let failures: Vec<String> = fixtures
+        .iter()
+        .filter_map(|path| check(path, update).err())
+        .collect();
+    assert!(
+        failures.is_empty(),
+        "{} of {} snapshots failed, run with UPDATE_SNAPSHOTS=1 to accept the new output\n\n{}",
+        failures.len(),
+        fixtures.len(),
+        failures.join("\n")
+    );
+}
@@ -0,0 +1,42 @@
+//! Runs programs on both the evaluator and the VM and checks they agree:
+//! the ones in `tests/differential/found.monkey`, which once diverged, and
+//! a few hundred generated ones. `cargo fuzz run differential` goes on
+//! generating where this stops.
+
+use interpreter::builtins::SeededRng;
+use interpreter::differential::{check, generate};
+
+#[test]
+fn test_found_divergences() {
+    let found = include_str!("differential/found.monkey");
+    let programs: Vec<String> = found
+        .split("\n\n")
+        .map(|paragraph| {
+            paragraph
+                .lines()
+                .filter(|line| !line.starts_with("//"))
+                .collect::<Vec<_>>()
+                .join("\n")
+        })
+        .filter(|program| !program.trim().is_empty())
+        .collect();
+    assert!(programs.len() >= 3, "{:?}", programs);
+    for program in programs {
+        if let Err(divergence) = check(&program) {
+            panic!("{}", divergence);
+        }
+    }
+}
+
+#[test]
+fn test_generated_programs() {
+    for seed in 0..100 {
+        let mut rng = SeededRng::new(seed);
+        for size in [2, 4, 6] {
+            let program = generate(&mut rng, size);
+            if let Err(divergence) = check(&program) {
+                panic!("seed {}, size {}: {}", seed, size, divergence);
+            }
+        }
+    }
+}
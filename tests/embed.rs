@@ -0,0 +1,42 @@
+//! Embedding the evaluator: a script's `exit` comes back to the host as a
+//! value it can act on, the host process keeps running.
+
+use interpreter::builtins::{with_host, Host};
+use interpreter::object::EvalError;
+use interpreter::prelude::*;
+
+fn run(input: &str, env: &mut Environment) -> Option<Object> {
+    let mut p = Parser::new(Lexer::new(input));
+    let program = p.parse();
+    assert!(p.get_errors().is_empty(), "{:?}", p.get_errors());
+    eval(&program, env)
+}
+
+#[test]
+fn test_exit_returns_to_the_embedder() {
+    let mut env = Environment::new();
+    let res = run("let x = 1; exit(5); let y = 2;", &mut env);
+    assert_eq!(res, Some(Object::Error(EvalError::Exit(5))));
+    // the statements before it ran, the ones after didn't
+    assert_eq!(env.get(&"x".into()), Some(Object::Integer(1)));
+    assert_eq!(env.get(&"y".into()), None);
+
+    // and the environment is still good for more code
+    assert_eq!(run("x + 1", &mut env), Some(Object::Integer(2)));
+}
+
+#[test]
+fn test_embedder_decides_what_exit_means() {
+    let host = Host::new().with_args(vec!["3".to_owned()]);
+    let status = with_host(host, || {
+        let res = run(
+            "let n = len(args()[0]); if (n > 0) { exit(n + 40) }",
+            &mut Environment::new(),
+        );
+        match res {
+            Some(Object::Error(EvalError::Exit(code))) => i32::from(code),
+            _ => 0,
+        }
+    });
+    assert_eq!(status, 41);
+}
@@ -0,0 +1,44 @@
+//! Formats every input in the fuzz corpus and checks that formatting is
+//! idempotent and doesn't change what the program parses to.
+
+use interpreter::ast::Node;
+use interpreter::format::format;
+use interpreter::lexer::Lexer;
+use interpreter::parser::Parser;
+
+fn parse(source: &str) -> String {
+    let mut p = Parser::new(Lexer::new(source));
+    let program = p.parse();
+    assert!(
+        p.get_errors().is_empty(),
+        "{}: {:?}",
+        source,
+        p.get_errors()
+    );
+    program.string()
+}
+
+#[test]
+fn test_corpus_formats_stably() {
+    let dir = concat!(env!("CARGO_MANIFEST_DIR"), "/fuzz/corpus/parse");
+    let mut formatted_any = false;
+    for entry in std::fs::read_dir(dir).expect("the corpus is checked in") {
+        let path = entry.unwrap().path();
+        let Ok(source) = std::fs::read_to_string(&path) else {
+            continue;
+        };
+        // most of the corpus is there because it doesn't parse
+        let Ok(formatted) = format(&source) else {
+            continue;
+        };
+        formatted_any = true;
+        assert_eq!(
+            format(&formatted).as_ref(),
+            Ok(&formatted),
+            "{}",
+            path.display()
+        );
+        assert_eq!(parse(&formatted), parse(&source), "{}", path.display());
+    }
+    assert!(formatted_any);
+}
@@ -0,0 +1,89 @@
+//! Property tests for `Document::apply_edit`: after any edits, the tokens,
+//! the program and the errors are what lexing and parsing the edited
+//! source from scratch gives.
+
+use interpreter::incremental::Document;
+use interpreter::lexer::Lexer;
+use interpreter::parser::Parser;
+use proptest::prelude::*;
+
+/* pieces of code that are often valid on their own and often not once
+ * cut at random, and some characters that change how the rest lexes */
+fn text() -> impl Strategy<Value = String> {
+    prop_oneof![
+        Just("let x = 1;\n".to_owned()),
+        Just("fn(a, b) { a + b }".to_owned()),
+        Just("if (x < y) { x } else { y };\n".to_owned()),
+        Just("[1, 2][0];".to_owned()),
+        Just("{\"k\": true}".to_owned()),
+        Just("return f(x);".to_owned()),
+        Just("// comment\n".to_owned()),
+        Just("\"".to_owned()),
+        "[a-z0-9 =!<>+*/;,(){}\\[\\]\n-]{0,6}",
+    ]
+}
+
+fn source() -> impl Strategy<Value = String> {
+    prop::collection::vec(text(), 0..12).prop_map(|pieces| pieces.concat())
+}
+
+/* an edit as fractions of the length, so it fits whatever the source is
+ * by the time it is applied */
+fn edits() -> impl Strategy<Value = Vec<(f64, f64, String)>> {
+    prop::collection::vec((0.0..=1.0, 0.0..0.3, text()), 1..8)
+}
+
+fn assert_fresh(doc: &Document) {
+    let fresh = Document::new(doc.source());
+    assert_eq!(doc.tokens(), fresh.tokens(), "{:?}", doc.source());
+    assert_eq!(doc.spans(), fresh.spans(), "{:?}", doc.source());
+    let mut p = Parser::new(Lexer::new(doc.source()));
+    let program = p.parse();
+    assert_eq!(
+        doc.program().statements,
+        program.statements,
+        "{:?}",
+        doc.source()
+    );
+    assert_eq!(
+        doc.errors().collect::<Vec<_>>(),
+        *p.get_errors(),
+        "{:?}",
+        doc.source()
+    );
+}
+
+proptest! {
+    #[test]
+    fn edits_match_a_fresh_parse(source in source(), edits in edits()) {
+        let mut doc = Document::new(&source);
+        for (at, len, new_text) in edits {
+            // the generated text is all ASCII, so any offset is a char boundary
+            let n = doc.source().len();
+            let start = (at * n as f64) as usize;
+            let end = (start + (len * n as f64) as usize).min(n);
+            doc.apply_edit(start..end, &new_text);
+            assert_fresh(&doc);
+        }
+    }
+}
+
+#[test]
+fn test_typing_a_program() {
+    let program =
+        "let fib = fn(n) {\n  if (n < 2) { return n; }\n  fib(n - 1) + fib(n - 2)\n};\nfib(10);\n";
+    let mut doc = Document::new("");
+    for (at, ch) in program.char_indices() {
+        doc.apply_edit(at..at, ch.encode_utf8(&mut [0; 4]));
+        assert_fresh(&doc);
+    }
+    assert_eq!(doc.program().statements.len(), 2);
+    assert_eq!(doc.errors().count(), 0);
+
+    // and deleting it from the front
+    while !doc.source().is_empty() {
+        doc.apply_edit(0..1, "");
+        assert_fresh(&doc);
+    }
+    assert!(doc.program().statements.is_empty());
+}
@@ -0,0 +1,352 @@
+//! Property tests for the printer and the parser: printing a program with
+//! `string()` and parsing the output gives back the same AST, and no input
+//! makes the parser panic. The formatter's output parses back the same way,
+//! keeps every comment and doesn't change when formatted again.
+
+use std::rc::Rc;
+
+use interpreter::ast::{
+    ArrayLiteral, BlockStatement, BooleanLiteral, CallExpression, Constant, Expression,
+    ExpressionStatement, FloatLiteral, FunctionLiteral, HashLiteral, Identifier, IfExpression,
+    IndexExpression, InfixExpression, InfixOperator, IntegerLiteral, LetStatement,
+    LetTupleStatement, Node, PrefixExpression, PrefixOperator, ReturnStatement, Statement,
+    StringLiteral, TupleLiteral,
+};
+use interpreter::format::format;
+use interpreter::lexer::Lexer;
+use interpreter::parser::Parser;
+use interpreter::token::Token;
+use proptest::prelude::*;
+
+const KEYWORDS: [&str; 12] = [
+    "fn", "let", "const", "if", "else", "return", "true", "false", "for", "in", "break", "continue",
+];
+
+fn ident() -> impl Strategy<Value = Identifier> {
+    "[a-z][a-z_]{0,4}"
+        .prop_filter("keywords are not identifiers", |s| {
+            !KEYWORDS.contains(&s.as_str())
+        })
+        .prop_map(|s| {
+            let value: Rc<str> = s.into();
+            Identifier {
+                tok: Token::Ident(value.clone()),
+                value,
+            }
+        })
+}
+
+fn leaf() -> impl Strategy<Value = Expression> {
+    prop_oneof![
+        ident().prop_map(Expression::Identifier),
+        (0..=i64::MAX).prop_map(|value| Expression::Integer(IntegerLiteral {
+            tok: Token::Int(value.to_string().into()),
+            value,
+        })),
+        (0..1_000_000u32, 0..100u32, any::<bool>()).prop_map(|(int, frac, suffix)| {
+            let lit = format!("{}.{}{}", int, frac, if suffix { "f" } else { "" });
+            Expression::Float(FloatLiteral {
+                value: lit.trim_end_matches('f').parse().unwrap(),
+                tok: Token::Float(lit.into()),
+            })
+        }),
+        any::<bool>().prop_map(|value| Expression::Boolean(BooleanLiteral {
+            tok: if value { Token::True } else { Token::False },
+            value,
+        })),
+        "[a-zA-Z0-9 ]{0,8}".prop_map(|s| {
+            let value: Rc<str> = s.into();
+            Expression::String(StringLiteral {
+                tok: Token::String(value.clone()),
+                value,
+            })
+        }),
+    ]
+}
+
+fn prefix_operator() -> impl Strategy<Value = (Token, PrefixOperator)> {
+    prop_oneof![
+        Just((Token::Bang, PrefixOperator::Bang)),
+        Just((Token::Minus, PrefixOperator::Minus)),
+    ]
+}
+
+fn infix_operator() -> impl Strategy<Value = (Token, InfixOperator)> {
+    prop_oneof![
+        Just((Token::Plus, InfixOperator::Plus)),
+        Just((Token::Minus, InfixOperator::Minus)),
+        Just((Token::Asterisk, InfixOperator::Asterisk)),
+        Just((Token::Slash, InfixOperator::Slash)),
+        Just((Token::Lt, InfixOperator::Lt)),
+        Just((Token::Gt, InfixOperator::Gt)),
+        Just((Token::Eq, InfixOperator::Eq)),
+        Just((Token::NotEq, InfixOperator::NotEq)),
+    ]
+}
+
+/// The token the parser records for an expression statement, which is the
+/// first token of the printed expression.
+fn first_token(exp: &Expression) -> Token {
+    match exp {
+        Expression::Identifier(i) => i.tok.clone(),
+        Expression::Integer(i) => i.tok.clone(),
+        Expression::Float(f) => f.tok.clone(),
+        Expression::String(s) => s.tok.clone(),
+        Expression::Boolean(b) => b.tok.clone(),
+        Expression::Array(_) => Token::LBracket,
+        Expression::Hash(_) => Token::LSquirly,
+        Expression::IfExpression(_) => Token::If,
+        Expression::FunctionLiteral(_) => Token::Function,
+        Expression::CallExpression(ce) => first_token(&ce.function),
+        // printed inside parens
+        Expression::Tuple(_)
+        | Expression::PrefixExpression(_)
+        | Expression::InfixExpression(_)
+        | Expression::IndexExpression(_) => Token::LParen,
+        // a new kind of expression needs a strategy in `expression` first
+        exp => unimplemented!("{:?}", exp),
+    }
+}
+
+fn let_token(mutable: bool) -> Token {
+    if mutable {
+        Token::Let
+    } else {
+        Token::Const
+    }
+}
+
+fn statement(exp: BoxedStrategy<Expression>) -> impl Strategy<Value = Statement> {
+    prop_oneof![
+        (ident(), exp.clone(), any::<bool>()).prop_map(|(name, value, mutable)| {
+            Statement::LetStatement(LetStatement {
+                tok: let_token(mutable),
+                name,
+                value,
+                mutable,
+            })
+        }),
+        (
+            prop::collection::vec(ident(), 2..4),
+            exp.clone(),
+            any::<bool>()
+        )
+            .prop_map(|(names, value, mutable)| {
+                Statement::LetTupleStatement(LetTupleStatement {
+                    tok: let_token(mutable),
+                    names,
+                    value,
+                    mutable,
+                })
+            }),
+        exp.clone()
+            .prop_map(|value| Statement::ReturnStatement(ReturnStatement {
+                tok: Token::Return,
+                value,
+            })),
+        exp.prop_map(
+            |expression| Statement::ExpressionStatement(ExpressionStatement {
+                tok: first_token(&expression),
+                expression,
+            })
+        ),
+    ]
+}
+
+fn block(exp: BoxedStrategy<Expression>) -> impl Strategy<Value = BlockStatement> {
+    prop::collection::vec(statement(exp), 0..3).prop_map(|statements| BlockStatement {
+        tok: Token::LSquirly,
+        statements,
+    })
+}
+
+fn expression() -> BoxedStrategy<Expression> {
+    leaf()
+        .prop_recursive(4, 48, 4, |inner| {
+            let elements = prop::collection::vec(inner.clone(), 0..4);
+            prop_oneof![
+                elements
+                    .clone()
+                    .prop_map(|elements| Expression::Array(ArrayLiteral {
+                        tok: Token::LBracket,
+                        elements,
+                        constant: Constant::default(),
+                    })),
+                // a single element in parens is a group, not a tuple
+                prop::collection::vec(inner.clone(), 2..4).prop_map(|elements| {
+                    Expression::Tuple(TupleLiteral {
+                        tok: Token::LParen,
+                        elements,
+                        constant: Constant::default(),
+                    })
+                }),
+                prop::collection::vec((inner.clone(), inner.clone()), 0..3).prop_map(|pairs| {
+                    Expression::Hash(HashLiteral {
+                        tok: Token::LSquirly,
+                        pairs,
+                        constant: Constant::default(),
+                    })
+                }),
+                (prefix_operator(), inner.clone()).prop_map(|((tok, operator), right)| {
+                    Expression::PrefixExpression(PrefixExpression {
+                        tok,
+                        operator,
+                        right: Rc::new(right),
+                    })
+                }),
+                (inner.clone(), infix_operator(), inner.clone()).prop_map(
+                    |(left, (tok, operator), right)| {
+                        Expression::InfixExpression(InfixExpression {
+                            tok,
+                            left: Rc::new(left),
+                            operator,
+                            right: Rc::new(right),
+                        })
+                    }
+                ),
+                (
+                    inner.clone(),
+                    block(inner.clone()),
+                    prop::option::of(block(inner.clone()))
+                )
+                    .prop_map(|(condition, consequence, alternative)| {
+                        Expression::IfExpression(IfExpression {
+                            tok: Token::If,
+                            condition: Rc::new(condition),
+                            consequence,
+                            alternative,
+                        })
+                    }),
+                (prop::collection::vec(ident(), 0..3), block(inner.clone())).prop_map(
+                    |(parameters, body)| {
+                        Expression::FunctionLiteral(FunctionLiteral {
+                            tok: Token::Function,
+                            parameters,
+                            body,
+                        })
+                    }
+                ),
+                (inner.clone(), elements).prop_map(|(function, arguments)| {
+                    Expression::CallExpression(CallExpression {
+                        tok: Token::LParen,
+                        function: Rc::new(function),
+                        arguments,
+                    })
+                }),
+                (inner.clone(), inner).prop_map(|(left, index)| {
+                    Expression::IndexExpression(IndexExpression {
+                        tok: Token::LBracket,
+                        left: Rc::new(left),
+                        index: Rc::new(index),
+                    })
+                }),
+            ]
+        })
+        .boxed()
+}
+
+fn program() -> impl Strategy<Value = Vec<Statement>> {
+    prop::collection::vec(statement(expression()), 1..4)
+}
+
+/* one statement per line, with the `;` an expression statement needs to
+ * keep the next line from continuing it */
+fn print(statements: &[Statement]) -> String {
+    let mut res = String::new();
+    for stmt in statements {
+        res.push_str(&stmt.string());
+        if let Statement::ExpressionStatement(_) = stmt {
+            res.push(';');
+        }
+        res.push('\n');
+    }
+    res
+}
+
+/* puts a comment after some of the lines, or on a line of its own */
+fn with_comments(source: &str, comments: &[Option<bool>]) -> String {
+    let mut res = String::new();
+    for (i, (line, comment)) in source.lines().zip(comments.iter().cycle()).enumerate() {
+        res.push_str(line);
+        match comment {
+            Some(true) => res.push_str(&format!(" // trailing {}\n", i)),
+            Some(false) => res.push_str(&format!("\n// own {}\n", i)),
+            None => res.push('\n'),
+        }
+    }
+    res
+}
+
+fn comment_count(source: &str) -> usize {
+    let mut l = Lexer::new(source).with_comments();
+    std::iter::repeat_with(|| l.next_token())
+        .take_while(|tok| *tok != Token::Eof)
+        .filter(|tok| matches!(tok, Token::Comment(_)))
+        .count()
+}
+
+proptest! {
+    #[test]
+    fn formatted_program_is_stable(
+        statements in program(),
+        comments in prop::collection::vec(prop::option::of(any::<bool>()), 1..4),
+    ) {
+        // formatted first so there are comments inside blocks too
+        let plain = format(&print(&statements));
+        prop_assert!(plain.is_ok(), "{:?}", plain);
+        let source = with_comments(&plain.unwrap(), &comments);
+        let formatted = format(&source);
+        prop_assert!(formatted.is_ok(), "{}: {:?}", source, formatted);
+        let formatted = formatted.unwrap();
+        let reformatted = format(&formatted);
+        prop_assert_eq!(reformatted.as_ref(), Ok(&formatted), "{}", source);
+        prop_assert_eq!(comment_count(&formatted), comment_count(&source), "{}", formatted);
+
+        let mut p = Parser::new(Lexer::new(&formatted));
+        let program = p.parse();
+        prop_assert!(p.get_errors().is_empty(), "{}: {:?}", formatted, p.get_errors());
+        // the first token of a statement may be a parenthesis that is gone,
+        // which `print` puts back
+        prop_assert_eq!(print(&program.statements), print(&statements), "{}", formatted);
+    }
+
+    #[test]
+    fn printed_program_parses_back(statements in program()) {
+        let source = print(&statements);
+        let mut p = Parser::new(Lexer::new(&source));
+        let program = p.parse();
+        prop_assert!(p.get_errors().is_empty(), "{}: {:?}", source, p.get_errors());
+        prop_assert_eq!(&program.statements, &statements, "{}", source);
+
+        // the program prints its statements apart the same way
+        let source = program.to_string();
+        let mut p = Parser::new(Lexer::new(&source));
+        let reparsed = p.parse();
+        prop_assert!(p.get_errors().is_empty(), "{}: {:?}", source, p.get_errors());
+        prop_assert_eq!(reparsed.statements, statements, "{}", source);
+    }
+
+    #[test]
+    fn parser_never_panics(source in "\\PC{0,64}") {
+        let mut p = Parser::new(Lexer::new(&source));
+        p.parse();
+        // every error is reported at a token, and there is at most one of
+        // those per char plus the Eof
+        prop_assert!(p.errors_len() <= source.chars().count() + 1);
+    }
+
+    #[test]
+    fn parser_never_panics_on_tokens(
+        source in prop::collection::vec(
+            prop::sample::select(vec![
+                "let", "const", "fn", "if", "else", "return", "true", "for", "in", "break", "x", "1", "\"s\"", "=", "+",
+                "-", "!", "*", "<", "==", ",", ":", ";", "(", ")", "{", "}", "[", "]",
+            ]),
+            0..48,
+        ).prop_map(|toks| toks.join(" "))
+    ) {
+        let mut p = Parser::new(Lexer::new(&source));
+        p.parse();
+        prop_assert!(p.errors_len() <= source.split(' ').count() + 1);
+    }
+}